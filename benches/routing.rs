@@ -0,0 +1,66 @@
+//! Messages/sec through `TransferService` and agent lookup latency in `AgentRegistry`,
+//! with `EchoAgent`s so only routing and locking are measured.
+
+use std::sync::Arc;
+use criterion::{BenchmarkId, Criterion, Throughput, criterion_group, criterion_main};
+use futures::future::join_all;
+use tokio::runtime::Runtime;
+use tokio::sync::RwLock;
+use swarmonomicon::agents::{AgentRegistry, TransferService};
+use swarmonomicon::testing::EchoAgent;
+use swarmonomicon::types::Message;
+
+const AGENTS: usize = 100;
+
+fn registry(rt: &Runtime) -> Arc<RwLock<AgentRegistry>> {
+    rt.block_on(async {
+        let mut registry = AgentRegistry::new();
+        for i in 0..AGENTS {
+            let name = format!("agent-{}", i);
+            registry.register(name.clone(), Box::new(EchoAgent::new(&name))).await.unwrap();
+        }
+        registry.set_current_agent("agent-0".to_string());
+        Arc::new(RwLock::new(registry))
+    })
+}
+
+fn transfer_service(c: &mut Criterion) {
+    let rt = Runtime::new().unwrap();
+    let service = Arc::new(TransferService::new(registry(&rt)));
+
+    let mut group = c.benchmark_group("transfer_service");
+    for concurrency in [1, 16, 64] {
+        group.throughput(Throughput::Elements(concurrency as u64));
+        group.bench_with_input(BenchmarkId::new("process_message", concurrency), &concurrency, |b, &concurrency| {
+            b.to_async(&rt).iter(|| {
+                let service = service.clone();
+                async move {
+                    let messages = (0..concurrency).map(|i| service.process_message(Message::new(format!("message {}", i))));
+                    for response in join_all(messages).await {
+                        response.unwrap();
+                    }
+                }
+            });
+        });
+    }
+    group.finish();
+}
+
+fn registry_lookup(c: &mut Criterion) {
+    let rt = Runtime::new().unwrap();
+    let registry = registry(&rt);
+
+    c.bench_function("registry/get", |b| {
+        b.to_async(&rt).iter(|| async {
+            assert!(registry.read().await.get("agent-42").is_some());
+        });
+    });
+    c.bench_function("registry/get_missing", |b| {
+        b.to_async(&rt).iter(|| async {
+            assert!(registry.read().await.get("nobody").is_none());
+        });
+    });
+}
+
+criterion_group!(benches, transfer_service, registry_lookup);
+criterion_main!(benches);
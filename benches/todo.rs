@@ -0,0 +1,92 @@
+//! Tasks/sec through the todo pipeline with `MockAiProvider` standing in for the model:
+//! adding and completing tasks through `TodoTool`, and processing them the way the todo
+//! worker does, through an agent's `process_task`.
+
+use std::time::{Duration, Instant};
+use criterion::{Criterion, Throughput, criterion_group, criterion_main};
+use tokio::runtime::Runtime;
+use swarmonomicon::agents::AgentWrapper;
+use swarmonomicon::ai::MockAiProvider;
+use swarmonomicon::testing::{EchoAgent, TodoHarness};
+use swarmonomicon::tools::CancellationToken;
+use swarmonomicon::types::{TaskPriority, TaskStatus, TodoList, TodoProcessor, TodoTask};
+
+fn mock_ai() -> MockAiProvider {
+    MockAiProvider::new()
+        .with_rule("Enhance this task", "1. Reproduce the failure\n2. Fix it")
+        .with_rule("priority classifier", "medium")
+        .with_rule("project classifier", "Swarmonomicon")
+}
+
+fn task(i: u64) -> TodoTask {
+    TodoTask {
+        id: format!("bench-{}", i),
+        description: format!("Benchmark task {}", i),
+        enhanced_description: None,
+        priority: TaskPriority::Medium,
+        project: None,
+        source_agent: None,
+        target_agent: "echo".to_string(),
+        status: TaskStatus::InProgress,
+        created_at: 0,
+        completed_at: None,
+        due_date: None,
+        duration_minutes: None,
+        notes: None,
+        ticket: None,
+        last_modified: None,
+        failure_reason: None,
+        embedding: None,
+        claim: None,
+        tags: Vec::new(),
+        failures: Vec::new(),
+        confidence: None,
+        reviewed_at: None,
+        tenant_id: None,
+        idempotency_key: None,
+        content_hash: None,
+        history: Vec::new(),
+    }
+}
+
+fn todo_tool(c: &mut Criterion) {
+    let rt = Runtime::new().unwrap();
+    let mut group = c.benchmark_group("todo_tool");
+    group.throughput(Throughput::Elements(1));
+    group.bench_function("add_and_complete", |b| {
+        // A fresh server per sample, so lookups don't slow down as tasks pile up
+        b.to_async(&rt).iter_custom(|iters| async move {
+            let harness = TodoHarness::start(mock_ai()).await.unwrap();
+            let start = Instant::now();
+            for i in 0..iters {
+                let description = format!("Benchmark task {}", i);
+                harness.run("add", &[("description", description.as_str())]).await.unwrap();
+                harness.run("complete", &[("description", description.as_str())]).await.unwrap();
+            }
+            start.elapsed()
+        });
+    });
+    group.finish();
+}
+
+fn process_task(c: &mut Criterion) {
+    let rt = Runtime::new().unwrap();
+    // Processing doesn't touch the task list, but the wrapper needs one
+    let todos = rt.block_on(TodoList::shared()).expect("RTK_MONGO_URI must point at MongoDB");
+    let agent = AgentWrapper::new(Box::new(EchoAgent::new("echo")), todos.for_agent("echo"));
+
+    let mut group = c.benchmark_group("todo_worker");
+    group.throughput(Throughput::Elements(1));
+    group.measurement_time(Duration::from_secs(10));
+    group.bench_function("process_task", |b| {
+        let mut i = 0;
+        b.to_async(&rt).iter(|| {
+            i += 1;
+            agent.process_task(task(i), CancellationToken::new())
+        });
+    });
+    group.finish();
+}
+
+criterion_group!(benches, todo_tool, process_task);
+criterion_main!(benches);
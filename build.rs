@@ -0,0 +1,45 @@
+fn main() {
+    #[cfg(feature = "grpc")]
+    grpc::generate();
+}
+
+/// Server and client stubs for proto/swarmonomicon.proto. The message types are written
+/// by hand in src/grpc/proto.rs, so only the service plumbing is generated here and
+/// building doesn't need protoc.
+#[cfg(feature = "grpc")]
+mod grpc {
+    use tonic_build::manual::{Builder, Method, Service};
+
+    const PACKAGE: &str = "swarmonomicon.v1";
+    const CODEC: &str = "tonic::codec::ProstCodec";
+
+    fn method(name: &str, route: &str, input: &str, output: &str) -> tonic_build::manual::MethodBuilder {
+        Method::builder()
+            .name(name)
+            .route_name(route)
+            .input_type(format!("crate::grpc::proto::{}", input))
+            .output_type(format!("crate::grpc::proto::{}", output))
+            .codec_path(CODEC)
+    }
+
+    pub fn generate() {
+        println!("cargo:rerun-if-changed=proto/swarmonomicon.proto");
+        println!("cargo:rerun-if-changed=build.rs");
+
+        let agents = Service::builder()
+            .name("AgentService")
+            .package(PACKAGE)
+            .method(method("send_message", "SendMessage", "SendMessageRequest", "AgentReply").build())
+            .method(method("transfer", "Transfer", "TransferRequest", "AgentReply").build())
+            .method(method("list_agents", "ListAgents", "ListAgentsRequest", "ListAgentsResponse").build())
+            .build();
+        let tasks = Service::builder()
+            .name("TaskService")
+            .package(PACKAGE)
+            .method(method("create", "Create", "CreateTaskRequest", "Task").build())
+            .method(method("list", "List", "ListTasksRequest", "ListTasksResponse").build())
+            .method(method("watch", "Watch", "WatchTasksRequest", "TaskEvent").server_streaming().build())
+            .build();
+        Builder::new().compile(&[agents, tasks]);
+    }
+}
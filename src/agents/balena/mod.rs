@@ -24,6 +24,11 @@ impl BalenaWrapperAgent {
                         transitions
                     },
                     validation: None,
+                    guarded_transitions: None,
+                    parent: None,
+                    initial_child: None,
+                    on_enter: None,
+                    on_exit: None,
                 });
                 states.insert("executing".to_string(), crate::types::State {
                     prompt: "⚡ Executing fleet command... Stand by for quantum entanglement.".to_string(),
@@ -33,6 +38,11 @@ impl BalenaWrapperAgent {
                         transitions
                     },
                     validation: None,
+                    guarded_transitions: None,
+                    parent: None,
+                    initial_child: None,
+                    on_enter: None,
+                    on_exit: None,
                 });
                 states
             },
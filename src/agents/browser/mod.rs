@@ -28,8 +28,7 @@ impl BrowserAgentWrapper {
     }
 
     pub async fn shutdown(&self) -> Result<()> {
-        // TODO: Implement shutdown logic
-        Ok(())
+        self.inner.shutdown().await
     }
 }
 
@@ -63,6 +62,10 @@ impl Agent for DummyAgent {
             downstream_agents: vec![],
             personality: None,
             state_machine: None,
+            script: None,
+            max_concurrency: None,
+            middleware: None,
+            remote: None,
         })
     }
 }
@@ -88,4 +91,8 @@ impl Agent for BrowserAgentWrapper {
     async fn get_config(&self) -> Result<AgentConfig> {
         Ok(self.agent_config.clone())
     }
+
+    async fn shutdown(&self) -> Result<()> {
+        BrowserAgentWrapper::shutdown(self).await
+    }
 }
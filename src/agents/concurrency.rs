@@ -0,0 +1,151 @@
+//! Caps how many messages an agent handles at once. Callers over the cap wait their
+//! turn in FIFO order instead of piling onto slow agents like `browser` or `git`.
+
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use serde::{Deserialize, Serialize};
+use tokio::sync::{Semaphore, SemaphorePermit};
+use utoipa::ToSchema;
+
+/// A snapshot of an agent's work queue
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub struct ConcurrencyStats {
+    /// Maximum simultaneous messages; `None` when unlimited
+    pub limit: Option<usize>,
+    /// Messages being processed now
+    pub active: usize,
+    /// Messages waiting for a slot
+    pub queued: usize,
+    /// The longest the queue has been
+    pub peak_queued: usize,
+    /// Messages that have finished processing, successfully or not
+    pub completed: u64,
+}
+
+pub struct ConcurrencyLimit {
+    semaphore: Option<Semaphore>,
+    limit: Option<usize>,
+    active: AtomicUsize,
+    queued: AtomicUsize,
+    peak_queued: AtomicUsize,
+    completed: AtomicU64,
+}
+
+impl ConcurrencyLimit {
+    /// At most `limit` concurrent holders; `None` or zero means unlimited
+    pub fn new(limit: Option<usize>) -> Self {
+        let limit = limit.filter(|&n| n > 0);
+        Self {
+            semaphore: limit.map(Semaphore::new),
+            limit,
+            active: AtomicUsize::new(0),
+            queued: AtomicUsize::new(0),
+            peak_queued: AtomicUsize::new(0),
+            completed: AtomicU64::new(0),
+        }
+    }
+
+    pub fn unlimited() -> Self {
+        Self::new(None)
+    }
+
+    /// Wait for a slot. The slot is released when the returned guard is dropped.
+    pub async fn acquire(&self) -> ConcurrencyPermit<'_> {
+        let permit = match &self.semaphore {
+            Some(semaphore) => {
+                let _waiting = Waiting::enter(self);
+                // The semaphore is never closed
+                semaphore.acquire().await.ok()
+            }
+            None => None,
+        };
+        self.active.fetch_add(1, Ordering::Relaxed);
+        ConcurrencyPermit { limit: self, _permit: permit }
+    }
+
+    pub fn stats(&self) -> ConcurrencyStats {
+        ConcurrencyStats {
+            limit: self.limit,
+            active: self.active.load(Ordering::Relaxed),
+            queued: self.queued.load(Ordering::Relaxed),
+            peak_queued: self.peak_queued.load(Ordering::Relaxed),
+            completed: self.completed.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// Counts a caller as queued until dropped, including when its wait is cancelled
+struct Waiting<'a>(&'a ConcurrencyLimit);
+
+impl<'a> Waiting<'a> {
+    fn enter(limit: &'a ConcurrencyLimit) -> Self {
+        let depth = limit.queued.fetch_add(1, Ordering::Relaxed) + 1;
+        limit.peak_queued.fetch_max(depth, Ordering::Relaxed);
+        Self(limit)
+    }
+}
+
+impl Drop for Waiting<'_> {
+    fn drop(&mut self) {
+        self.0.queued.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+pub struct ConcurrencyPermit<'a> {
+    limit: &'a ConcurrencyLimit,
+    _permit: Option<SemaphorePermit<'a>>,
+}
+
+impl Drop for ConcurrencyPermit<'_> {
+    fn drop(&mut self) {
+        self.limit.active.fetch_sub(1, Ordering::Relaxed);
+        self.limit.completed.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    #[tokio::test]
+    async fn test_waiters_queue_until_a_slot_frees() {
+        let limit = Arc::new(ConcurrencyLimit::new(Some(1)));
+        let first = limit.acquire().await;
+
+        let waiter = tokio::spawn({
+            let limit = limit.clone();
+            async move {
+                let _permit = limit.acquire().await;
+            }
+        });
+        while limit.stats().queued == 0 {
+            tokio::task::yield_now().await;
+        }
+        assert_eq!(limit.stats(), ConcurrencyStats { limit: Some(1), active: 1, queued: 1, peak_queued: 1, completed: 0 });
+
+        drop(first);
+        waiter.await.unwrap();
+        assert_eq!(limit.stats(), ConcurrencyStats { limit: Some(1), active: 0, queued: 0, peak_queued: 1, completed: 2 });
+    }
+
+    #[tokio::test]
+    async fn test_cancelled_waiters_leave_the_queue() {
+        let limit = ConcurrencyLimit::new(Some(1));
+        let _held = limit.acquire().await;
+
+        let timed_out = tokio::time::timeout(std::time::Duration::from_millis(10), limit.acquire()).await;
+        assert!(timed_out.is_err());
+        assert_eq!(limit.stats().queued, 0);
+        assert_eq!(limit.stats().peak_queued, 1);
+    }
+
+    #[tokio::test]
+    async fn test_unlimited_never_queues() {
+        let limit = ConcurrencyLimit::new(Some(0));
+        let permits: Vec<_> = futures::future::join_all((0..50).map(|_| limit.acquire())).await;
+        let stats = limit.stats();
+        assert_eq!((stats.limit, stats.active, stats.peak_queued), (None, 50, 0));
+        drop(permits);
+        assert_eq!(limit.stats().completed, 50);
+    }
+}
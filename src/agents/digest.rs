@@ -0,0 +1,134 @@
+//! Summarizes the last day's task activity per project and posts it through the notification
+//! tool. Send it a number of hours to cover another window; schedule it with a cron job whose
+//! action is `{ message: { agent: digest, content: "24" } }`.
+
+use std::collections::HashMap;
+use async_trait::async_trait;
+use anyhow::{Result, anyhow};
+use chrono::Utc;
+use crate::ai::{AiProvider, BudgetedAiClient, DefaultAiClient, cassette_from_env};
+use crate::tools::{NotificationKind, NotificationTool};
+use crate::types::{Agent, AgentConfig, Message, MessageHandler, TodoList};
+use crate::types::digest::{Digest, summarize};
+
+const DEFAULT_HOURS: i64 = 24;
+
+#[derive(Agent)]
+pub struct DigestAgent {
+    config: AgentConfig,
+    ai_client: Box<dyn AiProvider + Send + Sync>,
+    todos: Option<TodoList>,
+    notifier: Option<NotificationTool>,
+    /// Notification channel; the default routing when unset
+    channel: Option<String>,
+}
+
+impl DigestAgent {
+    pub fn new(config: AgentConfig) -> Self {
+        Self {
+            config,
+            ai_client: cassette_from_env(BudgetedAiClient::new("digest", DefaultAiClient::new())),
+            todos: None,
+            notifier: None,
+            channel: std::env::var("DIGEST_CHANNEL").ok(),
+        }
+    }
+
+    /// Connect to task storage and the configured notification channels
+    pub async fn from_env(config: AgentConfig) -> Result<Self> {
+        let mut agent = Self::new(config);
+        if crate::secrets::get(crate::secrets::MONGO_URI).is_some() {
+            agent = agent.with_todos(TodoList::shared().await?);
+        }
+        if let Some(notifier) = NotificationTool::from_env()? {
+            agent = agent.with_notifier(notifier);
+        }
+        Ok(agent)
+    }
+
+    pub fn with_ai_client<T: AiProvider + Send + Sync + 'static>(mut self, client: T) -> Self {
+        self.ai_client = Box::new(client);
+        self
+    }
+
+    pub fn with_todos(mut self, todos: TodoList) -> Self {
+        self.todos = Some(todos);
+        self
+    }
+
+    pub fn with_notifier(mut self, notifier: NotificationTool) -> Self {
+        self.notifier = Some(notifier);
+        self
+    }
+
+    pub fn with_channel(mut self, channel: impl Into<String>) -> Self {
+        self.channel = Some(channel.into());
+        self
+    }
+
+    /// The digest of the last `hours`, without posting it
+    pub async fn digest(&self, hours: i64) -> Result<Digest> {
+        let todos = self.todos.as_ref().ok_or_else(|| anyhow!("Digests need MongoDB (RTK_MONGO_URI)"))?;
+        let until = Utc::now().timestamp();
+        let activity = todos.activity(until - hours * 3600, until).await?;
+        Ok(summarize(&activity, self.ai_client.as_ref()).await)
+    }
+}
+
+/// Hours covered by a request: a leading number, or a day by default
+fn parse_hours(content: &str) -> Result<i64> {
+    match content.split_whitespace().next() {
+        None => Ok(DEFAULT_HOURS),
+        Some(word) => match word.parse::<i64>() {
+            Ok(hours) if hours > 0 => Ok(hours),
+            _ => Err(anyhow!("Expected a number of hours, got '{}'", word)),
+        },
+    }
+}
+
+#[async_trait]
+impl MessageHandler for DigestAgent {
+    /// Build the digest for the hours in the message and post it, replying with its text
+    async fn handle_message(&self, message: Message) -> Result<Message> {
+        let digest = self.digest(parse_hours(&message.content)?).await?;
+        if let Some(notifier) = &self.notifier {
+            let vars = HashMap::from([("text".to_string(), digest.summary.clone()), ("job".to_string(), "digest".to_string())]);
+            if let Err(e) = notifier.notify(NotificationKind::Message, &vars, self.channel.as_deref()).await {
+                tracing::warn!("Failed to post the digest: {}", e);
+            }
+        }
+        Ok(Message::new(digest.summary))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_hours() {
+        assert_eq!(parse_hours("").unwrap(), 24);
+        assert_eq!(parse_hours(" 168 hours").unwrap(), 168);
+        assert!(parse_hours("yesterday").is_err());
+        assert!(parse_hours("0").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_digest_needs_task_storage() {
+        let config = AgentConfig {
+            name: "digest".to_string(),
+            public_description: String::new(),
+            instructions: String::new(),
+            tools: Vec::new(),
+            downstream_agents: Vec::new(),
+            personality: None,
+            state_machine: None,
+            script: None,
+            max_concurrency: None,
+            middleware: None,
+            remote: None,
+        };
+        let error = DigestAgent::new(config).process_message(Message::new("24".to_string())).await.err().unwrap();
+        assert!(error.to_string().contains("MongoDB"), "{}", error);
+    }
+}
@@ -3,13 +3,15 @@ use std::process::Command;
 use std::collections::HashMap;
 use std::path::{Path, PathBuf};
 use std::sync::{Arc, Mutex};
-use crate::types::{Agent, AgentConfig, Message, MessageMetadata, Tool, ToolCall, State, StateMachine, AgentStateManager};
-use crate::tools::ToolRegistry;
+use crate::types::{Agent, AgentConfig, Capability, CostHint, Message, MessageMetadata, Tool, ToolCall, State, StateMachine, AgentStateManager};
+use crate::tools::AgentToolset;
 use anyhow::{Result, anyhow};
+use crate::error::SwarmError;
 #[cfg(feature = "git-agent")]
 use rand::Rng;
 use chrono;
-use crate::ai::{AiProvider, DefaultAiClient};
+use crate::ai::{AiProvider, BudgetedAiClient, FallbackAiClient};
+use crate::knowledge::{self, KnowledgeBase};
 use tokio::process::Command as TokioCommand;
 use tokio::io::{AsyncBufReadExt, BufReader};
 use futures::executor::block_on;
@@ -19,18 +21,28 @@ pub struct GitAssistantAgent {
     working_dir: Arc<Mutex<Option<PathBuf>>>,
     current_state: Option<State>,
     ai_client: Box<dyn AiProvider + Send + Sync>,
+    /// Documentation of the repositories it works in, for their commit conventions
+    knowledge: Option<KnowledgeBase>,
+    tools: AgentToolset,
 }
 
 impl GitAssistantAgent {
     pub fn new(config: AgentConfig) -> Self {
         Self {
+            tools: AgentToolset::for_agent(&config),
             config,
             working_dir: Arc::new(Mutex::new(None)),
             current_state: None,
-            ai_client: Box::new(DefaultAiClient::new()),
+            ai_client: Box::new(BudgetedAiClient::new("git", FallbackAiClient::default_chain())),
+            knowledge: None,
         }
     }
 
+    pub fn with_knowledge(mut self, knowledge: KnowledgeBase) -> Self {
+        self.knowledge = Some(knowledge);
+        self
+    }
+
     pub fn with_ai_client<T: AiProvider + Send + Sync + 'static>(mut self, client: T) -> Self {
         self.ai_client = Box::new(client);
         self
@@ -41,7 +53,7 @@ impl GitAssistantAgent {
             .lock()
             .unwrap()
             .clone()
-            .ok_or_else(|| anyhow!("Working directory not set"))
+            .ok_or_else(|| SwarmError::Validation("Working directory not set".to_string()).into())
     }
 
     pub fn update_working_dir(&self, path: PathBuf) -> Result<()> {
@@ -55,13 +67,13 @@ impl GitAssistantAgent {
             .current_dir(&self.get_working_dir()?)
             .output()
             .await
-            .map_err(|e| anyhow!("Failed to execute git command: {}", e))?;
+            .map_err(|e| SwarmError::tool_failure("git", format!("Failed to execute git command: {}", e)))?;
 
         let stdout = String::from_utf8(output.stdout)?;
         let stderr = String::from_utf8(output.stderr)?;
 
         if !output.status.success() {
-            return Err(anyhow!("Git command failed: {}", stderr));
+            return Err(SwarmError::tool_failure("git", format!("Git command failed: {}", stderr)).into());
         }
 
         Ok(stdout)
@@ -127,13 +139,29 @@ impl GitAssistantAgent {
         Ok(())
     }
 
+    /// What the working directory's ingested documentation says about commits, as a prompt
+    /// section; empty without any
+    async fn commit_conventions(&self) -> String {
+        let (Some(knowledge), Ok(dir)) = (&self.knowledge, self.get_working_dir()) else { return String::new() };
+        match knowledge.retrieve(&knowledge::project_name(&dir), "Commit message conventions", knowledge::DEFAULT_PASSAGES).await {
+            Ok(passages) => knowledge::context_prompt(&passages),
+            Err(e) => {
+                tracing::warn!("Generating a commit message without the project's documentation: {}", e);
+                String::new()
+            }
+        }
+    }
+
     async fn generate_commit_message(&self, diff: &str) -> Result<String> {
-        let system_prompt = "You are a helpful assistant that generates clear and concise git commit messages. \
+        let system_prompt = format!(
+            "You are a helpful assistant that generates clear and concise git commit messages. \
             You analyze git diffs and create conventional commit messages that follow best practices. \
             Focus on describing WHAT changed and WHY, being specific but concise. \
             Use the conventional commits format: type(scope): Detailed description\n\n\
             Types: feat, fix, docs, style, refactor, test, chore\n\
-            Example: feat(auth): add password reset functionality";
+            Example: feat(auth): add password reset functionality{}",
+            self.commit_conventions().await
+        );
 
         let messages = vec![HashMap::from([
             ("role".to_string(), "user".to_string()),
@@ -143,7 +171,7 @@ impl GitAssistantAgent {
             )),
         ])];
 
-        let message = self.ai_client.chat(system_prompt, messages).await?;
+        let message = self.ai_client.chat(&system_prompt, messages).await?;
 
         if message == "NEED_MORE_CONTEXT" {
             Ok("Please provide a commit message. The changes are too complex for automatic generation.".to_string())
@@ -202,6 +230,7 @@ impl GitAssistantAgent {
             metadata: Some(metadata),
             role: Some("assistant".to_string()),
             timestamp: Some(chrono::Utc::now().timestamp()),
+            attachments: Vec::new(),
         }
     }
 
@@ -323,7 +352,7 @@ impl Agent for GitAssistantAgent {
     }
 
     async fn call_tool(&self, tool: &Tool, params: HashMap<String, String>) -> Result<String> {
-        Err(anyhow!("GitAssistantAgent does not support tool calls"))
+        self.tools.execute(tool, params).await
     }
 
     async fn get_current_state(&self) -> Result<Option<State>> {
@@ -333,6 +362,13 @@ impl Agent for GitAssistantAgent {
     async fn get_config(&self) -> Result<AgentConfig> {
         Ok(self.config.clone())
     }
+
+    async fn capabilities(&self) -> Result<Vec<Capability>> {
+        Ok(vec![Capability::new("git", self.config.public_description.clone())
+            .with_commands(["status", "add", "commit", "branch", "checkout", "merge", "push", "pull"])
+            .with_keywords(["git", "repository", "version"])
+            .with_cost(CostHint::Free)])
+    }
 }
 
 #[cfg(test)]
@@ -354,6 +390,10 @@ mod tests {
             downstream_agents: Vec::new(),
             personality: None,
             state_machine: None,
+            script: None,
+            max_concurrency: None,
+            middleware: None,
+            remote: None,
         }
     }
 
@@ -412,6 +452,10 @@ mod tests {
             downstream_agents: vec![],
             personality: None,
             state_machine: None,
+            script: None,
+            max_concurrency: None,
+            middleware: None,
+            remote: None,
         }))
     }
 
@@ -552,4 +596,20 @@ mod tests {
         assert!(response.content.contains("Shifting to timeline"),
             "Should indicate timeline shift");
     }
+
+    #[tokio::test]
+    async fn test_commit_messages_follow_the_documented_conventions() -> Result<()> {
+        let mongo = crate::testsupport::MongoFixture::start().await?;
+        let ai = crate::ai::MockAiProvider::new().with_default("PROJ-1 Add parse_config");
+        let knowledge = KnowledgeBase::from_database(&mongo.db).with_embedder("mock", ai.clone());
+        let (agent, temp_dir) = setup_test_repo().await;
+        fs::write(temp_dir.path().join("CONTRIBUTING.md"), "## Commit messages\nStart every commit message with the ticket number.\n")?;
+        knowledge.ingest(&knowledge::project_name(temp_dir.path()), temp_dir.path()).await?;
+
+        let agent = agent.with_ai_client(ai.clone()).with_knowledge(knowledge);
+        let message = agent.generate_commit_message("diff --git a/src/config.rs b/src/config.rs\n+pub fn parse_config() {}").await?;
+        assert_eq!(message, "PROJ-1 Add parse_config");
+        assert!(ai.requests()[0].system_prompt.contains("[CONTRIBUTING.md § Commit messages]\nStart every commit message"));
+        Ok(())
+    }
 }
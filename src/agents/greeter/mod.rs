@@ -1,41 +1,66 @@
 use async_trait::async_trait;
 use std::collections::HashMap;
-use std::time::Duration;
 use serde_json::Value;
-use crate::types::{Agent, AgentConfig, Message, MessageMetadata, State, AgentStateManager, StateMachine, Tool};
-use crate::types::{TodoProcessor, TodoList, TodoTask};
-use crate::ai::{AiProvider, DefaultAiClient};
+use crate::types::{Agent, AgentCapabilities, AgentConfig, Attachment, Capability, Message, MessageMetadata, State, AgentStateManager, StateMachine, Tool};
+use crate::tools::AgentToolset;
+use crate::ai::{AiProvider, BudgetedAiClient, ConversationMemory, DefaultAiClient, memory_session, router};
 use anyhow::{Result, anyhow};
+use crate::error::SwarmError;
 use std::error::Error as StdError;
 use uuid::Uuid;
-use futures::executor::block_on;
 
 pub struct GreeterAgent {
     config: AgentConfig,
     state_manager: AgentStateManager,
     ai_client: Box<dyn AiProvider + Send + Sync>,
-    conversation_history: Vec<Message>,
-    todo_list: TodoList,
+    memory: ConversationMemory,
+    /// Agents conversations can be handed to; keyword matching on agent names when empty
+    catalog: Vec<AgentCapabilities>,
+    tools: AgentToolset,
 }
 
 impl GreeterAgent {
     pub fn new(config: AgentConfig) -> Self {
         Self {
+            tools: AgentToolset::for_agent(&config),
             config,
             state_manager: AgentStateManager::new(None),
-            ai_client: Box::new(DefaultAiClient::new()),
-            conversation_history: Vec::new(),
-            todo_list: block_on(TodoList::new()).expect("Failed to create TodoList"),
+            ai_client: Box::new(BudgetedAiClient::new("greeter", DefaultAiClient::new())),
+            memory: ConversationMemory::default(),
+            catalog: Vec::new(),
         }
     }
 
+    /// Route conversations by these agents' capabilities. Only the configured downstream
+    /// agents are kept, when there are any.
+    pub fn with_catalog(mut self, catalog: Vec<AgentCapabilities>) -> Self {
+        let downstream = &self.config.downstream_agents;
+        self.catalog = catalog.into_iter()
+            .filter(|entry| entry.agent != self.config.name)
+            .filter(|entry| downstream.is_empty() || downstream.contains(&entry.agent))
+            .collect();
+        self
+    }
+
+    async fn transfer_target(&self, message: &str) -> Result<Option<String>> {
+        if self.catalog.is_empty() {
+            return Ok(keyword_target(message).map(str::to_string));
+        }
+        router::route(message, &self.catalog, Some(self.ai_client.as_ref())).await
+    }
+
     pub fn with_ai_client<T: AiProvider + Send + Sync + 'static>(mut self, client: T) -> Self {
         self.ai_client = Box::new(client);
         self
     }
 
-    async fn get_ai_response(&self, prompt: &str) -> Result<String> {
-        let messages = self.build_conversation_messages(prompt);
+    pub fn with_memory(mut self, memory: ConversationMemory) -> Self {
+        self.memory = memory;
+        self
+    }
+
+    async fn get_ai_response(&self, prompt: &str, session: &str, images: &[Attachment]) -> Result<String> {
+        let messages = self.build_conversation_messages(prompt, session).await;
         let system_prompt = format!(
             "You are a friendly AI greeter assistant named {}. Your role is to: \
             1. Welcome users and understand their needs \
@@ -48,19 +73,13 @@ impl GreeterAgent {
             self.config.name
         );
 
-        self.ai_client.chat(&system_prompt, messages).await
+        let system_prompt = self.memory.system_prompt(session, &system_prompt).await;
+        self.ai_client.chat_with_images(&system_prompt, messages, images).await
     }
 
-    fn build_conversation_messages(&self, current_prompt: &str) -> Vec<HashMap<String, String>> {
-        let mut messages = Vec::new();
-
-        // Add conversation history
-        for message in &self.conversation_history {
-            messages.push(HashMap::from([
-                ("role".to_string(), "user".to_string()),
-                ("content".to_string(), message.content.clone()),
-            ]));
-        }
+    async fn build_conversation_messages(&self, current_prompt: &str, session: &str) -> Vec<HashMap<String, String>> {
+        // Recent history; anything older is summarized in the system prompt
+        let mut messages = self.memory.recent_messages(session).await;
 
         // Add current prompt
         messages.push(HashMap::from([
@@ -71,24 +90,17 @@ impl GreeterAgent {
         messages
     }
 
-    async fn handle_greeting(&self, message: &str) -> Result<Message> {
+    async fn handle_greeting(&self, message: &str, session: &str, images: &[Attachment]) -> Result<Message> {
         // Check for direct transfer requests first
-        let transfer_agent = match message.to_lowercase().as_str() {
-            msg if msg.contains("haiku") || msg.contains("poetry") || msg.contains("nature") => Some("haiku"),
-            msg if msg.contains("git") || msg.contains("version") || msg.contains("repository") => Some("git"),
-            msg if msg.contains("project") || msg.contains("init") || msg.contains("create") => Some("project-init"),
-            _ => None,
-        };
-
-        if let Some(agent) = transfer_agent {
+        if let Some(agent) = self.transfer_target(message).await? {
             let mut response = Message::new(format!("Let me transfer you to our {} specialist...", agent));
             response.metadata = Some(MessageMetadata::new("greeter".to_string())
-                .with_transfer_target(agent.to_string()));
+                .with_transfer_target(agent));
             return Ok(response);
         }
 
         // Get AI response for conversation
-        let ai_response = self.get_ai_response(message).await?;
+        let ai_response = self.get_ai_response(message, session, images).await?;
 
         let mut response = Message::new(ai_response);
         response.metadata = Some(MessageMetadata::new("greeter".to_string())
@@ -97,45 +109,49 @@ impl GreeterAgent {
     }
 }
 
+/// Transfers named outright, for greeters without a capability catalog
+fn keyword_target(message: &str) -> Option<&'static str> {
+    match message.to_lowercase().as_str() {
+        msg if msg.contains("haiku") || msg.contains("poetry") || msg.contains("nature") => Some("haiku"),
+        msg if msg.contains("git") || msg.contains("version") || msg.contains("repository") => Some("git"),
+        msg if msg.contains("project") || msg.contains("init") || msg.contains("create") => Some("project-init"),
+        _ => None,
+    }
+}
+
 #[async_trait]
 impl Agent for GreeterAgent {
     async fn process_message(&self, message: Message) -> Result<Message> {
-        self.handle_greeting(&message.content).await
+        let session = memory_session(&message);
+        let images: Vec<Attachment> = message.images().cloned().collect();
+        let response = self.handle_greeting(&message.content, &session, &images).await?;
+        self.memory.remember_exchange(&session, &message.content, &response.content, self.ai_client.as_ref()).await;
+        Ok(response)
     }
 
     async fn transfer_to(&self, target_agent: String, message: Message) -> Result<Message> {
         // Check if target agent is in downstream_agents
         if !self.config.downstream_agents.contains(&target_agent) {
-            return Err(anyhow!("Cannot transfer to unknown agent: {}", target_agent));
+            return Err(SwarmError::Transfer(format!("Cannot transfer to unknown agent: {}", target_agent)).into());
         }
         Ok(Message::new(format!("Transferring to {} agent...", target_agent)))
     }
 
     async fn call_tool(&self, tool: &Tool, params: HashMap<String, String>) -> Result<String> {
-        Err(anyhow!("GreeterAgent does not support tool calls"))
+        self.tools.execute(tool, params).await
     }
 
     async fn get_current_state(&self) -> Result<Option<State>> {
         Ok(self.state_manager.get_current_state().cloned())
     }
 
-    async fn get_config(&self) -> Result<AgentConfig> {
-        Ok(self.config.clone())
+    async fn capabilities(&self) -> Result<Vec<Capability>> {
+        Ok(vec![Capability::new(self.config.name.clone(), self.config.public_description.clone())
+            .with_keywords(["hello", "hi", "help"])])
     }
-}
 
-#[async_trait]
-impl TodoProcessor for GreeterAgent {
-    async fn process_task(&self, task: TodoTask) -> Result<Message> {
-        self.process_message(Message::new(task.description)).await
-    }
-
-    fn get_check_interval(&self) -> Duration {
-        Duration::from_secs(5)
-    }
-
-    fn get_todo_list(&self) -> &TodoList {
-        &self.todo_list
+    async fn get_config(&self) -> Result<AgentConfig> {
+        Ok(self.config.clone())
     }
 }
 
@@ -164,6 +180,10 @@ mod tests {
                 }
             }).to_string()),
             state_machine: None,
+            script: None,
+            max_concurrency: None,
+            middleware: None,
+            remote: None,
         }
     }
 
@@ -178,6 +198,18 @@ mod tests {
         }
     }
 
+    #[tokio::test]
+    async fn test_message_images_reach_the_model() {
+        let mock = crate::ai::MockAiProvider::new().with_default("A login form with an error banner");
+        let agent = GreeterAgent::new(create_test_config()).with_ai_client(mock.clone());
+        let screenshot = Attachment::image(b"\x89PNG\r\n\x1a\n".to_vec()).unwrap();
+        let message = Message::new("What is on this screen?".to_string()).with_attachments(vec![screenshot.clone()]);
+
+        let response = agent.process_message(message).await.unwrap();
+        assert_eq!(response.content, "A login form with an error banner");
+        assert_eq!(mock.requests()[0].images, vec![screenshot]);
+    }
+
     #[tokio::test]
     async fn test_project_transfer() {
         let agent = GreeterAgent::new(create_test_config());
@@ -216,7 +248,13 @@ mod tests {
 
     #[tokio::test]
     async fn test_todo_processing() {
-        let agent = GreeterAgent::new(create_test_config());
+        use crate::agents::AgentWrapper;
+        use crate::tools::CancellationToken;
+        use crate::types::{TodoProcessor, TodoTask};
+
+        // The registry's wrapper owns the agent's view of the task list
+        let mongo = crate::testsupport::MongoFixture::start().await.unwrap();
+        let agent = AgentWrapper::new(Box::new(GreeterAgent::new(create_test_config())), mongo.todo_list().await.for_agent("greeter"));
 
         // Create a test task
         let task = TodoTask {
@@ -235,13 +273,24 @@ mod tests {
             notes: None,
             ticket: None,
             last_modified: Some(chrono::Utc::now().timestamp()),
+            failure_reason: None,
+            embedding: None,
+            claim: None,
+            tags: Vec::new(),
+            failures: Vec::new(),
+            confidence: None,
+            reviewed_at: None,
+            tenant_id: None,
+            idempotency_key: None,
+            content_hash: None,
+            history: Vec::new(),
         };
 
         // Add task to todo list
-        <GreeterAgent as TodoProcessor>::get_todo_list(&agent).add_task(task.clone()).await;
+        TodoProcessor::get_todo_list(&agent).add_task(task.clone()).await.unwrap();
 
         // Process the task
-        let response = agent.process_task(task).await.unwrap();
+        let response = agent.process_task(task, CancellationToken::new()).await.unwrap();
 
         // Since the message mentions git, it should suggest transferring to the git agent
         assert!(response.metadata.is_some());
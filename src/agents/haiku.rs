@@ -1,10 +1,13 @@
 use async_trait::async_trait;
 use std::collections::HashMap;
 use std::sync::Arc;
-use tokio::sync::RwLock;
-use crate::types::{Agent, AgentConfig, Message, MessageMetadata, State, AgentStateManager, StateMachine, ValidationRule, Tool};
-use crate::ai::{AiProvider, DefaultAiClient};
+use tokio::sync::{Mutex, RwLock};
+use crate::types::{Agent, AgentConfig, Capability, Message, MessageMetadata, State, AgentStateManager, StateMachine, ValidationRule, Tool};
+use crate::types::{sessions::message_session_id, state_store::StateStore};
+use crate::ai::{AiProvider, BudgetedAiClient, ConversationMemory, DefaultAiClient, cassette_from_env, memory_session};
+use crate::tools::AgentToolset;
 use anyhow::{Result, anyhow};
+use crate::error::SwarmError;
 use std::error::Error as StdError;
 use serde_json;
 
@@ -12,6 +15,11 @@ pub struct HaikuAgent {
     config: AgentConfig,
     state_manager: Arc<RwLock<AgentStateManager>>,
     ai_client: Box<dyn AiProvider + Send + Sync>,
+    session_store: Option<Arc<dyn StateStore>>,
+    /// Serializes resume/process/persist so sessions don't see each other's state
+    session_lock: Mutex<()>,
+    memory: ConversationMemory,
+    tools: AgentToolset,
 }
 
 impl HaikuAgent {
@@ -28,7 +36,12 @@ impl HaikuAgent {
                         transitions.insert("topic_received".to_string(), "generating".to_string());
                         transitions
                     }),
-                    validation: None,
+                    validation: Some(vec![r"\S".to_string(), "🌸 Every verse needs a seed; what topic shall I write about?".to_string()]),
+                    guarded_transitions: None,
+                    parent: None,
+                    initial_child: None,
+                    on_enter: None,
+                    on_exit: None,
                 });
                 states.insert("generating".to_string(), State {
                     name: "generating".to_string(),
@@ -40,6 +53,11 @@ impl HaikuAgent {
                         transitions
                     }),
                     validation: None,
+                    guarded_transitions: None,
+                    parent: None,
+                    initial_child: None,
+                    on_enter: None,
+                    on_exit: None,
                 });
                 states.insert("complete".to_string(), State {
                     name: "complete".to_string(),
@@ -55,6 +73,11 @@ impl HaikuAgent {
                         "^(yes|no)$".to_string(),
                         "Please respond with 'yes' to continue our poetic computations, or 'no' to conclude.".to_string(),
                     ]),
+                    guarded_transitions: None,
+                    parent: None,
+                    initial_child: None,
+                    on_enter: None,
+                    on_exit: None,
                 });
                 states.insert("goodbye".to_string(), State {
                     name: "goodbye".to_string(),
@@ -62,6 +85,11 @@ impl HaikuAgent {
                     prompt: Some("🌟 May your algorithms flow like cherry blossoms in the digital wind...".to_string()),
                     transitions: None,
                     validation: None,
+                    guarded_transitions: None,
+                    parent: None,
+                    initial_child: None,
+                    on_enter: None,
+                    on_exit: None,
                 });
                 states
             },
@@ -69,9 +97,13 @@ impl HaikuAgent {
         });
 
         Self {
+            tools: AgentToolset::for_agent(&config),
             config,
             state_manager: Arc::new(RwLock::new(AgentStateManager::new(state_machine))),
-            ai_client: Box::new(DefaultAiClient::new()),
+            ai_client: cassette_from_env(BudgetedAiClient::new("haiku", DefaultAiClient::new())),
+            session_store: None,
+            session_lock: Mutex::new(()),
+            memory: ConversationMemory::default(),
         }
     }
 
@@ -80,8 +112,20 @@ impl HaikuAgent {
         self
     }
 
-    async fn generate_haiku(&self, topic: String) -> Result<String> {
-        let system_prompt = "You are a poetic AI that creates haikus. A haiku is a three-line poem with 5 syllables in the first line, 7 in the second, and 5 in the third. Create a haiku that blends nature imagery with technical concepts.";
+    /// Persist conversation state per session id so it survives restarts
+    pub fn with_session_store(mut self, store: Arc<dyn StateStore>) -> Self {
+        self.session_store = Some(store);
+        self
+    }
+
+    pub fn with_memory(mut self, memory: ConversationMemory) -> Self {
+        self.memory = memory;
+        self
+    }
+
+    async fn generate_haiku(&self, topic: String, session: &str) -> Result<String> {
+        let system_prompt = self.memory.system_prompt(session, "You are a poetic AI that creates haikus. A haiku is a three-line poem with 5 syllables in the first line, 7 in the second, and 5 in the third. Create a haiku that blends nature imagery with technical concepts.").await;
+        let system_prompt = system_prompt.as_str();
 
         let messages = vec![HashMap::from([
             ("role".to_string(), "user".to_string()),
@@ -93,7 +137,7 @@ impl HaikuAgent {
         // Validate haiku format
         let lines: Vec<&str> = haiku.trim().split('\n').collect();
         if lines.len() != 3 {
-            return Err(anyhow!("Generated haiku does not have 3 lines"));
+            return Err(SwarmError::Validation("Generated haiku does not have 3 lines".to_string()).into());
         }
 
         let syllables: Vec<usize> = lines.iter().map(|line| count_syllables(line)).collect();
@@ -147,6 +191,7 @@ impl HaikuAgent {
             metadata: Some(metadata),
             role: Some("assistant".to_string()),
             timestamp: Some(chrono::Utc::now().timestamp()),
+            attachments: Vec::new(),
         }
     }
 }
@@ -160,9 +205,8 @@ fn count_syllables(line: &str) -> usize {
         .sum()
 }
 
-#[async_trait]
-impl Agent for HaikuAgent {
-    async fn process_message(&self, message: Message) -> Result<Message> {
+impl HaikuAgent {
+    async fn handle_message(&self, message: Message) -> Result<Message> {
         let guard = self.state_manager.read().await;
         let state = guard.get_current_state_name().map(|s| s.to_string());
         drop(guard); // Drop the read guard before acquiring write guards
@@ -170,18 +214,26 @@ impl Agent for HaikuAgent {
         let response = match state {
             Some(state) => match state.as_str() {
                 "awaiting_topic" => {
-                    // Store the topic in the state data
                     let mut state_manager = self.state_manager.write().await;
+                    if let Err(reason) = state_manager.validate_input(&message.content) {
+                        drop(state_manager);
+                        return Ok(self.create_response(reason).await);
+                    }
+
+                    // Store the topic in the state data
                     let mut current_state = state_manager.get_current_state()
-                        .ok_or_else(|| anyhow!("Failed to get current state"))?
+                        .ok_or_else(|| SwarmError::State("Failed to get current state".to_string()))?
                         .clone();
 
                     // Create state data with the topic
                     current_state.data = Some(message.content.clone());
+                    state_manager.set_context("topic".to_string(), message.content.clone());
 
                     // Transition to generating state
                     state_manager.transition("topic_received")
-                        .ok_or_else(|| anyhow!("Failed to transition to generating state"))?;
+                        .ok_or_else(|| SwarmError::State("Failed to transition to generating state".to_string()))?;
+                    // create_response reads the state
+                    drop(state_manager);
 
                     self.create_response("🎋 Weaving your thoughts into digital poetry...".to_string()).await
                 }
@@ -189,19 +241,19 @@ impl Agent for HaikuAgent {
                     // Get the stored topic
                     let state_manager = self.state_manager.read().await;
                     let current_state = state_manager.get_current_state()
-                        .ok_or_else(|| anyhow!("Failed to get current state"))?;
-                    let topic = match &current_state.data {
+                        .ok_or_else(|| SwarmError::State("Failed to get current state".to_string()))?;
+                    let topic = match state_manager.context().get("topic").or(current_state.data.as_ref()) {
                         Some(data) => data.clone(),
                         None => message.content.clone()
                     };
                     drop(state_manager);
 
                     // Generate the haiku
-                    let haiku = self.generate_haiku(topic).await?;
+                    let haiku = self.generate_haiku(topic, &memory_session(&message)).await?;
 
                     // Transition to complete state
                     self.state_manager.write().await.transition("haiku_generated")
-                        .ok_or_else(|| anyhow!("Failed to transition to complete state"))?;
+                        .ok_or_else(|| SwarmError::State("Failed to transition to complete state".to_string()))?;
 
                     self.create_response(haiku).await
                 }
@@ -209,19 +261,19 @@ impl Agent for HaikuAgent {
                     match message.content.to_lowercase().as_str() {
                         "yes" => {
                             self.state_manager.write().await.transition("yes")
-                                .ok_or_else(|| anyhow!("Failed to transition to awaiting_topic state"))?;
+                                .ok_or_else(|| SwarmError::State("Failed to transition to awaiting_topic state".to_string()))?;
                             self.create_response("🌸 What new topic shall inspire our next algorithmic verse?".to_string()).await
                         }
                         "no" => {
                             self.state_manager.write().await.transition("no")
-                                .ok_or_else(|| anyhow!("Failed to transition to goodbye state"))?;
+                                .ok_or_else(|| SwarmError::State("Failed to transition to goodbye state".to_string()))?;
                             self.create_response("🌟 May your path be illuminated by the glow of poetic algorithms...".to_string()).await
                         }
                         _ => self.create_response("Please respond with 'yes' to continue or 'no' to conclude.".to_string()).await,
                     }
                 }
                 "goodbye" => self.create_response("Farewell, seeker of digital poetry.".to_string()).await,
-                _ => return Err(anyhow!("Invalid state: {}", state)),
+                _ => return Err(SwarmError::State(format!("Invalid state: {}", state)).into()),
             },
             None => self.create_response("🌸 What shall we crystallize into algorithmic verse today?".to_string()).await,
         };
@@ -229,21 +281,54 @@ impl Agent for HaikuAgent {
         Ok(response)
     }
 
+    async fn process_in_session(&self, message: Message) -> Result<Message> {
+        let session = self.session_store.clone().zip(message_session_id(&message));
+        let Some((store, session_id)) = session else {
+            return self.handle_message(message).await;
+        };
+
+        let _session = self.session_lock.lock().await;
+        self.state_manager.write().await.resume(store.as_ref(), &self.config.name, &session_id).await?;
+        let response = self.handle_message(message).await?;
+        self.state_manager.read().await.persist(store.as_ref(), &self.config.name, &session_id).await?;
+        Ok(response)
+    }
+}
+
+#[async_trait]
+impl Agent for HaikuAgent {
+    async fn process_message(&self, message: Message) -> Result<Message> {
+        let session = memory_session(&message);
+        let content = message.content.clone();
+        let response = self.process_in_session(message).await?;
+        self.memory.remember_exchange(&session, &content, &response.content, self.ai_client.as_ref()).await;
+        Ok(response)
+    }
+
     async fn transfer_to(&self, target_agent: String, message: Message) -> Result<Message> {
         Ok(Message::new(format!("Transferring to {} agent...", target_agent)))
     }
 
     async fn call_tool(&self, tool: &Tool, params: HashMap<String, String>) -> Result<String> {
-        Err(anyhow!("HaikuAgent does not support tool calls").into())
+        self.tools.execute(tool, params).await
     }
 
     async fn get_current_state(&self) -> Result<Option<State>> {
         Ok(self.state_manager.read().await.get_current_state().cloned())
     }
 
+    async fn get_state_machine(&self) -> Result<Option<StateMachine>> {
+        Ok(self.state_manager.read().await.state_machine().cloned())
+    }
+
     async fn get_config(&self) -> Result<AgentConfig> {
         Ok(self.config.clone())
     }
+
+    async fn capabilities(&self) -> Result<Vec<Capability>> {
+        Ok(vec![Capability::new("haiku", self.config.public_description.clone())
+            .with_keywords(["haiku", "poem", "poetry", "nature"])])
+    }
 }
 
 #[cfg(test)]
@@ -274,6 +359,11 @@ mod tests {
                         transitions
                     }),
                     validation: None,
+                    guarded_transitions: None,
+                    parent: None,
+                    initial_child: None,
+                    on_enter: None,
+                    on_exit: None,
                 });
                 states.insert("generating".to_string(), State {
                     name: "generating".to_string(),
@@ -285,6 +375,11 @@ mod tests {
                         transitions
                     }),
                     validation: None,
+                    guarded_transitions: None,
+                    parent: None,
+                    initial_child: None,
+                    on_enter: None,
+                    on_exit: None,
                 });
                 states.insert("complete".to_string(), State {
                     name: "complete".to_string(),
@@ -297,6 +392,11 @@ mod tests {
                         transitions
                     }),
                     validation: None,
+                    guarded_transitions: None,
+                    parent: None,
+                    initial_child: None,
+                    on_enter: None,
+                    on_exit: None,
                 });
                 states.insert("goodbye".to_string(), State {
                     name: "goodbye".to_string(),
@@ -304,6 +404,11 @@ mod tests {
                     prompt: Some("Farewell!".to_string()),
                     transitions: None,
                     validation: None,
+                    guarded_transitions: None,
+                    parent: None,
+                    initial_child: None,
+                    on_enter: None,
+                    on_exit: None,
                 });
                 states
             },
@@ -321,6 +426,10 @@ mod tests {
             downstream_agents: vec![],
             personality: None,
             state_machine: Some(create_test_state_machine()),
+            script: None,
+            max_concurrency: None,
+            middleware: None,
+            remote: None,
         });
 
         // Replace the default AI client with our mock
@@ -339,6 +448,27 @@ mod tests {
         assert_eq!(state.unwrap().name, "complete");
     }
 
+    #[tokio::test]
+    async fn test_a_blank_topic_is_refused() {
+        let agent = HaikuAgent::new(AgentConfig {
+            name: "haiku".to_string(),
+            public_description: "Test haiku agent".to_string(),
+            instructions: "Test haiku generation".to_string(),
+            tools: vec![],
+            downstream_agents: vec![],
+            personality: None,
+            state_machine: None,
+            script: None,
+            max_concurrency: None,
+            middleware: None,
+            remote: None,
+        }).with_ai_client(MockAiClient);
+
+        let response = agent.process_message(Message::new("   ".to_string())).await.unwrap();
+        assert!(response.content.contains("what topic"), "got {}", response.content);
+        assert_eq!(agent.get_current_state().await.unwrap().unwrap().name, "awaiting_topic");
+    }
+
     #[tokio::test]
     async fn test_state_transitions() -> Result<(), anyhow::Error> {
         let agent = HaikuAgent::new(AgentConfig {
@@ -361,6 +491,11 @@ mod tests {
                             transitions
                         }),
                         validation: None,
+                        guarded_transitions: None,
+                        parent: None,
+                        initial_child: None,
+                        on_enter: None,
+                        on_exit: None,
                     });
                     states.insert("generating".to_string(), State {
                         name: "generating".to_string(),
@@ -373,6 +508,11 @@ mod tests {
                             transitions
                         }),
                         validation: None,
+                        guarded_transitions: None,
+                        parent: None,
+                        initial_child: None,
+                        on_enter: None,
+                        on_exit: None,
                     });
                     states.insert("complete".to_string(), State {
                         name: "complete".to_string(),
@@ -385,6 +525,11 @@ mod tests {
                             transitions
                         }),
                         validation: None,
+                        guarded_transitions: None,
+                        parent: None,
+                        initial_child: None,
+                        on_enter: None,
+                        on_exit: None,
                     });
                     states.insert("error".to_string(), State {
                         name: "error".to_string(),
@@ -397,6 +542,11 @@ mod tests {
                             transitions
                         }),
                         validation: None,
+                        guarded_transitions: None,
+                        parent: None,
+                        initial_child: None,
+                        on_enter: None,
+                        on_exit: None,
                     });
                     states.insert("goodbye".to_string(), State {
                         name: "goodbye".to_string(),
@@ -404,11 +554,20 @@ mod tests {
                         prompt: Some("Farewell, seeker of digital poetry.".to_string()),
                         transitions: None,
                         validation: None,
+                        guarded_transitions: None,
+                        parent: None,
+                        initial_child: None,
+                        on_enter: None,
+                        on_exit: None,
                     });
                     states
                 },
                 initial_state: "awaiting_topic".to_string(),
             }),
+            script: None,
+            max_concurrency: None,
+            middleware: None,
+            remote: None,
         });
 
         // Test 1: Initial state
@@ -464,6 +623,11 @@ mod tests {
                             "^[a-zA-Z]+$".to_string(),
                             "Only letters are allowed".to_string(),
                         ]),
+                        guarded_transitions: None,
+                        parent: None,
+                        initial_child: None,
+                        on_enter: None,
+                        on_exit: None,
                     });
                     states.insert("error".to_string(), State {
                         name: "error".to_string(),
@@ -475,11 +639,20 @@ mod tests {
                             transitions
                         }),
                         validation: None,
+                        guarded_transitions: None,
+                        parent: None,
+                        initial_child: None,
+                        on_enter: None,
+                        on_exit: None,
                     });
                     states
                 },
                 initial_state: "awaiting_topic".to_string(),
             }),
+            script: None,
+            max_concurrency: None,
+            middleware: None,
+            remote: None,
         });
 
         // Test invalid input handling
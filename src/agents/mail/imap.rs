@@ -0,0 +1,186 @@
+//! Just enough IMAP4rev1 (RFC 3501) to find, read and flag messages: tagged commands,
+//! untagged responses and `{n}` literals.
+
+use anyhow::{Result, anyhow, bail};
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader};
+use tokio::net::TcpStream;
+use tokio_native_tls::{TlsConnector, TlsStream, native_tls};
+
+/// An untagged response line, with the literals it carried in order
+#[derive(Debug, Default)]
+struct Untagged {
+    text: String,
+    literals: Vec<Vec<u8>>,
+}
+
+pub struct ImapSession<S> {
+    stream: BufReader<S>,
+    next_tag: u32,
+}
+
+impl ImapSession<TlsStream<TcpStream>> {
+    /// An implicit-TLS connection, normally on port 993
+    pub async fn connect(host: &str, port: u16) -> Result<Self> {
+        let tcp = TcpStream::connect((host, port)).await
+            .map_err(|e| anyhow!("Failed to connect to {}:{}: {}", host, port, e))?;
+        let connector = TlsConnector::from(native_tls::TlsConnector::new()?);
+        let tls = connector.connect(host, tcp).await
+            .map_err(|e| anyhow!("TLS handshake with {} failed: {}", host, e))?;
+        Self::start(tls).await
+    }
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin> ImapSession<S> {
+    /// Read the server greeting on an open stream
+    pub async fn start(stream: S) -> Result<Self> {
+        let mut session = Self { stream: BufReader::new(stream), next_tag: 1 };
+        let greeting = session.read_line().await?;
+        if !greeting.starts_with("* OK") && !greeting.starts_with("* PREAUTH") {
+            bail!("Unexpected IMAP greeting: {}", greeting.trim_end());
+        }
+        Ok(session)
+    }
+
+    async fn read_line(&mut self) -> Result<String> {
+        let mut line = String::new();
+        if self.stream.read_line(&mut line).await? == 0 {
+            bail!("IMAP server closed the connection");
+        }
+        Ok(line)
+    }
+
+    /// Send `command` and collect the untagged responses, failing unless it ends `OK`
+    async fn command(&mut self, command: &str) -> Result<Vec<Untagged>> {
+        let tag = format!("A{:04}", self.next_tag);
+        self.next_tag += 1;
+        self.stream.get_mut().write_all(format!("{} {}\r\n", tag, command).as_bytes()).await?;
+        self.stream.get_mut().flush().await?;
+
+        let mut responses = Vec::new();
+        let mut current: Option<Untagged> = None;
+        loop {
+            let line = self.read_line().await?;
+            if let Some(status) = line.strip_prefix(&tag).map(str::trim) {
+                if let Some(untagged) = current.take() {
+                    responses.push(untagged);
+                }
+                if status.starts_with("OK") {
+                    return Ok(responses);
+                }
+                let verb = command.split_whitespace().take(2).collect::<Vec<_>>().join(" ");
+                bail!("IMAP {} failed: {}", verb, status);
+            }
+            let untagged = match current.as_mut() {
+                // The rest of a line that a literal interrupted
+                Some(untagged) => untagged,
+                None => current.insert(Untagged::default()),
+            };
+            untagged.text.push_str(line.trim_end());
+            match literal_len(&line) {
+                Some(len) => {
+                    let mut literal = vec![0; len];
+                    self.stream.read_exact(&mut literal).await?;
+                    untagged.literals.push(literal);
+                }
+                None => responses.extend(current.take()),
+            }
+        }
+    }
+
+    pub async fn login(&mut self, username: &str, password: &str) -> Result<()> {
+        self.command(&format!("LOGIN {} {}", quote(username), quote(password))).await?;
+        Ok(())
+    }
+
+    pub async fn select(&mut self, mailbox: &str) -> Result<()> {
+        self.command(&format!("SELECT {}", quote(mailbox))).await?;
+        Ok(())
+    }
+
+    /// UIDs of the messages matching `criteria`, e.g. `FLAGGED`
+    pub async fn uid_search(&mut self, criteria: &str) -> Result<Vec<u32>> {
+        let responses = self.command(&format!("UID SEARCH {}", criteria)).await?;
+        Ok(responses.iter()
+            .filter_map(|response| response.text.strip_prefix("* SEARCH"))
+            .flat_map(|uids| uids.split_whitespace().filter_map(|uid| uid.parse().ok()))
+            .collect())
+    }
+
+    /// The raw message, without marking it seen
+    pub async fn fetch(&mut self, uid: u32) -> Result<Vec<u8>> {
+        let responses = self.command(&format!("UID FETCH {} BODY.PEEK[]", uid)).await?;
+        responses.into_iter()
+            .find(|response| response.text.contains("FETCH"))
+            .and_then(|response| response.literals.into_iter().next())
+            .ok_or_else(|| anyhow!("Message {} has no body", uid))
+    }
+
+    /// Change flags, e.g. `+FLAGS (\Seen)`
+    pub async fn uid_store(&mut self, uid: u32, change: &str) -> Result<()> {
+        self.command(&format!("UID STORE {} {}", uid, change)).await?;
+        Ok(())
+    }
+
+    pub async fn logout(mut self) -> Result<()> {
+        self.command("LOGOUT").await?;
+        Ok(())
+    }
+}
+
+/// The length of the literal announced at the end of `line`, as in `... {123}`
+fn literal_len(line: &str) -> Option<usize> {
+    let line = line.trim_end();
+    line.strip_suffix('}')?.rsplit_once('{')?.1.parse().ok()
+}
+
+fn quote(value: &str) -> String {
+    format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_search_fetch_and_flag() {
+        let (client, mut server) = tokio::io::duplex(4096);
+        let script = tokio::spawn(async move {
+            let message = "Subject: Hi\r\n\r\nBody\r\n";
+            let mut lines = BufReader::new(&mut server);
+            let mut sent = Vec::new();
+            let mut replies = vec![
+                "A0001 OK LOGIN done\r\n".to_string(),
+                "* 2 EXISTS\r\nA0002 OK SELECT done\r\n".to_string(),
+                "* SEARCH 4 9\r\nA0003 OK SEARCH done\r\n".to_string(),
+                format!("* 1 FETCH (UID 4 BODY[] {{{}}}\r\n{})\r\nA0004 OK FETCH done\r\n", message.len(), message),
+                "A0005 NO [READ-ONLY] mailbox\r\n".to_string(),
+            ].into_iter();
+            lines.get_mut().write_all(b"* OK IMAP ready\r\n").await.unwrap();
+            loop {
+                let mut line = String::new();
+                if lines.read_line(&mut line).await.unwrap() == 0 {
+                    break;
+                }
+                sent.push(line.trim_end().to_string());
+                match replies.next() {
+                    Some(reply) => lines.get_mut().write_all(reply.as_bytes()).await.unwrap(),
+                    None => break,
+                }
+            }
+            sent
+        });
+
+        let mut session = ImapSession::start(client).await.unwrap();
+        session.login("me@example.com", "p\"w").await.unwrap();
+        session.select("INBOX").await.unwrap();
+        assert_eq!(session.uid_search("FLAGGED").await.unwrap(), [4, 9]);
+        assert_eq!(session.fetch(4).await.unwrap(), b"Subject: Hi\r\n\r\nBody\r\n");
+        let error = session.uid_store(4, "+FLAGS (\\Seen)").await.unwrap_err();
+        assert_eq!(error.to_string(), "IMAP UID STORE failed: NO [READ-ONLY] mailbox");
+        drop(session);
+
+        let sent = script.await.unwrap();
+        assert_eq!(sent[0], r#"A0001 LOGIN "me@example.com" "p\"w""#);
+        assert_eq!(sent[3], "A0004 UID FETCH 4 BODY.PEEK[]");
+    }
+}
@@ -0,0 +1,307 @@
+//! Files tasks by email. The mail agent polls an IMAP inbox for flagged messages, adds
+//! each one as a todo through `TodoTool` (so it gets the usual AI enhancement), replies
+//! with the task id, then unflags the message and marks it read.
+
+mod imap;
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+use async_trait::async_trait;
+use anyhow::{Result, anyhow};
+use lettre::message::Mailbox;
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::{AsyncSmtpTransport, AsyncTransport, Tokio1Executor};
+use mail_parser::MessageParser;
+use serde::Serialize;
+use serde_json::Value;
+use tokio::task::JoinHandle;
+use crate::tools::{ToolExecutor, TodoTool};
+use crate::types::{Agent, AgentConfig, Message, State, Tool};
+
+pub use imap::ImapSession;
+
+/// Longest email body kept as task context
+const MAX_CONTEXT_CHARS: usize = 4000;
+const REPLY_PREFIXES: [&str; 5] = ["re:", "fwd:", "fw:", "tr:", "aw:"];
+
+#[derive(Debug, Clone)]
+pub struct MailSettings {
+    pub imap_host: String,
+    pub imap_port: u16,
+    /// Replies are skipped without an SMTP server
+    pub smtp_host: Option<String>,
+    pub smtp_port: u16,
+    pub username: String,
+    pub password: String,
+    pub mailbox: String,
+    /// Address replies are sent from
+    pub from_address: String,
+    /// Agent the filed tasks are for
+    pub target_agent: String,
+    pub poll_interval: Duration,
+}
+
+impl MailSettings {
+    /// From `MAIL_IMAP_HOST`, `MAIL_USERNAME` and the `MAIL_PASSWORD` secret, plus optional
+    /// `MAIL_IMAP_PORT` (993), `MAIL_SMTP_HOST`, `MAIL_SMTP_PORT` (587), `MAIL_MAILBOX` (INBOX),
+    /// `MAIL_FROM`, `MAIL_TARGET_AGENT` (user) and `MAIL_POLL_SECS` (60)
+    pub fn from_env() -> Result<Self> {
+        let var = |name: &str| std::env::var(name).ok().filter(|value| !value.is_empty());
+        let required = |name: &str| var(name).ok_or_else(|| anyhow!("{} is not set", name));
+        let port = |name: &str, default: u16| -> Result<u16> {
+            var(name).map(|port| port.parse().map_err(|_| anyhow!("Invalid {}: {}", name, port))).unwrap_or(Ok(default))
+        };
+        let username = required("MAIL_USERNAME")?;
+        Ok(Self {
+            imap_host: required("MAIL_IMAP_HOST")?,
+            imap_port: port("MAIL_IMAP_PORT", 993)?,
+            smtp_host: var("MAIL_SMTP_HOST"),
+            smtp_port: port("MAIL_SMTP_PORT", 587)?,
+            password: crate::secrets::require(crate::secrets::MAIL_PASSWORD)?,
+            mailbox: var("MAIL_MAILBOX").unwrap_or_else(|| "INBOX".to_string()),
+            from_address: var("MAIL_FROM").unwrap_or_else(|| username.clone()),
+            target_agent: var("MAIL_TARGET_AGENT").unwrap_or_else(|| "user".to_string()),
+            poll_interval: Duration::from_secs(var("MAIL_POLL_SECS").and_then(|s| s.parse().ok()).unwrap_or(60)),
+            username,
+        })
+    }
+}
+
+/// The parts of an email a task is filed from
+#[derive(Debug, Clone, PartialEq)]
+pub struct IncomingEmail {
+    pub from: Option<String>,
+    pub subject: String,
+    pub body: String,
+    pub message_id: Option<String>,
+}
+
+impl IncomingEmail {
+    pub fn parse(raw: &[u8]) -> Option<Self> {
+        let message = MessageParser::default().parse(raw)?;
+        Some(Self {
+            from: message.from().and_then(|from| from.first()).and_then(|addr| addr.address()).map(str::to_string),
+            subject: message.subject().unwrap_or_default().to_string(),
+            body: message.body_text(0).map(|body| body.into_owned()).unwrap_or_default(),
+            message_id: message.message_id().map(str::to_string),
+        })
+    }
+
+    /// The subject without reply and forward prefixes, else the first line of the body
+    pub fn task_description(&self) -> Option<String> {
+        let mut subject = self.subject.trim();
+        while let Some(prefix) = REPLY_PREFIXES.iter().find(|p| subject.get(..p.len()).is_some_and(|s| s.eq_ignore_ascii_case(p))) {
+            subject = subject[prefix.len()..].trim_start();
+        }
+        let description = if subject.is_empty() {
+            self.body.lines().map(str::trim).find(|line| !line.is_empty()).unwrap_or_default()
+        } else {
+            subject
+        };
+        (!description.is_empty()).then(|| description.to_string())
+    }
+
+    /// The body, for the task's context, cut at the signature and `MAX_CONTEXT_CHARS`
+    pub fn task_context(&self) -> Option<String> {
+        let body = self.body.replace("\r\n", "\n");
+        let body = body.split("\n-- \n").next().unwrap_or_default().trim();
+        (!body.is_empty()).then(|| body.chars().take(MAX_CONTEXT_CHARS).collect())
+    }
+}
+
+/// The id of the task TodoTool's `add` reports creating, if its response names one
+pub fn task_id_from_response(response: &str) -> Option<String> {
+    let value: Value = serde_json::from_str(response).ok()?;
+    let candidates = [&value, &value["todo"], &value["task"], &value["data"], &value["result"]];
+    candidates.iter()
+        .flat_map(|v| ["id", "todo_id", "task_id", "_id"].into_iter().map(move |key| &v[key]))
+        .find_map(|id| id.as_str().map(str::to_string))
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct FiledEmail {
+    pub uid: u32,
+    pub subject: String,
+    pub task_id: Option<String>,
+}
+
+/// The inbox side of the agent, shared with its poller
+pub struct MailInbox {
+    settings: MailSettings,
+    todo_tool: Arc<dyn ToolExecutor>,
+}
+
+impl MailInbox {
+    pub fn new(settings: MailSettings, todo_tool: Arc<dyn ToolExecutor>) -> Self {
+        Self { settings, todo_tool }
+    }
+
+    /// File every flagged message in the mailbox, returning what was filed
+    pub async fn poll_once(&self) -> Result<Vec<FiledEmail>> {
+        let settings = &self.settings;
+        let mut session = ImapSession::connect(&settings.imap_host, settings.imap_port).await?;
+        session.login(&settings.username, &settings.password).await?;
+        session.select(&settings.mailbox).await?;
+        let mut filed = Vec::new();
+        for uid in session.uid_search("FLAGGED").await? {
+            let raw = session.fetch(uid).await?;
+            let Some(email) = IncomingEmail::parse(&raw) else {
+                tracing::warn!("Skipping message {} that could not be parsed", uid);
+                continue;
+            };
+            match self.file(&email).await {
+                Ok(task_id) => {
+                    if let Err(e) = self.reply(&email, task_id.as_deref()).await {
+                        tracing::warn!("Filed message {} but could not reply: {}", uid, e);
+                    }
+                    session.uid_store(uid, "-FLAGS (\\Flagged)").await?;
+                    session.uid_store(uid, "+FLAGS (\\Seen)").await?;
+                    filed.push(FiledEmail { uid, subject: email.subject, task_id });
+                }
+                // Left flagged, so the next poll tries again
+                Err(e) => tracing::warn!("Failed to file message {} as a task: {}", uid, e),
+            }
+        }
+        session.logout().await?;
+        Ok(filed)
+    }
+
+    async fn file(&self, email: &IncomingEmail) -> Result<Option<String>> {
+        let description = email.task_description().ok_or_else(|| anyhow!("Email has no subject or body"))?;
+        let mut params = HashMap::from([
+            ("command".to_string(), "add".to_string()),
+            ("description".to_string(), description),
+            ("target_agent".to_string(), self.settings.target_agent.clone()),
+        ]);
+        if let Some(context) = email.task_context() {
+            params.insert("context".to_string(), context);
+        }
+        let response = self.todo_tool.execute(params).await?;
+        Ok(task_id_from_response(&response))
+    }
+
+    async fn reply(&self, email: &IncomingEmail, task_id: Option<&str>) -> Result<()> {
+        let (Some(smtp_host), Some(to)) = (&self.settings.smtp_host, &email.from) else { return Ok(()) };
+        let text = match task_id {
+            Some(id) => format!("Filed as task {} for {}.", id, self.settings.target_agent),
+            None => format!("Filed as a task for {}.", self.settings.target_agent),
+        };
+        let mut builder = lettre::Message::builder()
+            .from(self.settings.from_address.parse::<Mailbox>()?)
+            .to(to.parse::<Mailbox>()?)
+            .subject(format!("Re: {}", email.subject));
+        if let Some(message_id) = &email.message_id {
+            builder = builder.in_reply_to(format!("<{}>", message_id)).references(format!("<{}>", message_id));
+        }
+        let mailer = AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(smtp_host)?
+            .port(self.settings.smtp_port)
+            .credentials(Credentials::new(self.settings.username.clone(), self.settings.password.clone()))
+            .build();
+        mailer.send(builder.body(text)?).await?;
+        Ok(())
+    }
+}
+
+pub struct MailAgent {
+    config: AgentConfig,
+    inbox: Arc<MailInbox>,
+    poller: std::sync::Mutex<Option<JoinHandle<()>>>,
+}
+
+impl MailAgent {
+    pub fn new(config: AgentConfig, inbox: MailInbox) -> Self {
+        Self { config, inbox: Arc::new(inbox), poller: std::sync::Mutex::new(None) }
+    }
+
+    /// An agent for the inbox in `MailSettings::from_env`, filing through the default `TodoTool`
+    pub async fn from_env(config: AgentConfig) -> Result<Self> {
+        let todo_tool = TodoTool::new().await?;
+        Ok(Self::new(config, MailInbox::new(MailSettings::from_env()?, Arc::new(todo_tool))))
+    }
+
+    /// Poll every `poll_interval` in the background until shut down
+    pub fn start_polling(&self) {
+        let inbox = self.inbox.clone();
+        let handle = tokio::spawn(async move {
+            let mut interval = tokio::time::interval(inbox.settings.poll_interval);
+            loop {
+                interval.tick().await;
+                match inbox.poll_once().await {
+                    Ok(filed) if !filed.is_empty() => tracing::info!("Filed {} task(s) from email", filed.len()),
+                    Ok(_) => {}
+                    Err(e) => tracing::warn!("Failed to check {}: {}", inbox.settings.mailbox, e),
+                }
+            }
+        });
+        if let Some(previous) = self.poller.lock().unwrap().replace(handle) {
+            previous.abort();
+        }
+    }
+}
+
+#[async_trait]
+impl Agent for MailAgent {
+    /// Any message checks the inbox now
+    async fn process_message(&self, _message: Message) -> Result<Message> {
+        let filed = self.inbox.poll_once().await?;
+        let mut content = format!("Filed {} task(s) from email", filed.len());
+        for email in &filed {
+            content.push_str(&format!("\n- {} ({})", email.subject, email.task_id.as_deref().unwrap_or("id unknown")));
+        }
+        Ok(Message::new(content))
+    }
+
+    async fn transfer_to(&self, _target_agent: String, message: Message) -> Result<Message> {
+        Ok(message)
+    }
+
+    async fn call_tool(&self, tool: &Tool, _params: HashMap<String, String>) -> Result<String> {
+        Err(anyhow!("The mail agent has no tool {}", tool.name))
+    }
+
+    async fn get_current_state(&self) -> Result<Option<State>> {
+        Ok(None)
+    }
+
+    async fn get_config(&self) -> Result<AgentConfig> {
+        Ok(self.config.clone())
+    }
+
+    async fn shutdown(&self) -> Result<()> {
+        if let Some(poller) = self.poller.lock().unwrap().take() {
+            poller.abort();
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_email_to_task() {
+        let raw = b"From: Ada <ada@example.com>\r\n\
+            Subject: Fwd: RE: Renew the TLS certificate\r\n\
+            Message-ID: <abc@example.com>\r\n\
+            Content-Type: text/plain\r\n\r\n\
+            It expires Friday.\r\n-- \r\nAda\r\n";
+        let email = IncomingEmail::parse(raw).unwrap();
+        assert_eq!(email.from.as_deref(), Some("ada@example.com"));
+        assert_eq!(email.message_id.as_deref(), Some("abc@example.com"));
+        assert_eq!(email.task_description().as_deref(), Some("Renew the TLS certificate"));
+        assert_eq!(email.task_context().as_deref(), Some("It expires Friday."));
+
+        let untitled = IncomingEmail { subject: "Fw:".to_string(), body: "\n  Call the bank\nsoon".to_string(), ..email };
+        assert_eq!(untitled.task_description().as_deref(), Some("Call the bank"));
+    }
+
+    #[test]
+    fn test_task_id_from_response() {
+        assert_eq!(task_id_from_response(r#"{"success": true, "todo_id": "t-1"}"#).as_deref(), Some("t-1"));
+        assert_eq!(task_id_from_response(r#"{"todo": {"id": "t-2"}}"#).as_deref(), Some("t-2"));
+        assert_eq!(task_id_from_response(r#"{"success": true}"#), None);
+        assert_eq!(task_id_from_response("Added"), None);
+    }
+}
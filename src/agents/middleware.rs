@@ -0,0 +1,403 @@
+//! Cross-cutting behavior around `process_message`, so agents don't each re-implement it.
+//! `AgentWrapper` runs every message through the chain named in the agent's
+//! `middleware` config: `before` hooks in order on the way in, `after` hooks in reverse
+//! on the way out, like layers of an onion.
+//!
+//! Built in:
+//! - `guard`: blocks or sanitizes prompt injection and dangerous tool parameters; see `guard`
+//! - `logging`: logs messages and replies
+//! - `metrics`: counts messages and failures and times replies; see `AgentWrapper::message_stats`
+//! - `redact_pii`: masks emails, phone, card and social security numbers both ways
+//! - `profanity`: masks the words in `PROFANITY_WORDS` (comma-separated), or a short default list
+//! - `personality`: renders replies in the agent's personality; see `personality`
+
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Instant;
+use anyhow::Result;
+use async_trait::async_trait;
+use lazy_static::lazy_static;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use crate::types::{AgentConfig, Message, Tool};
+use super::guard::Guard;
+use super::personality::PersonalityRenderer;
+
+/// The chain agents get when their config doesn't name one
+pub const DEFAULT_MIDDLEWARE: &[&str] = &["logging", "metrics", "personality"];
+/// The default chain with the guard, for agents that run commands, commit or browse
+pub const GUARDED_MIDDLEWARE: &[&str] = &["logging", "guard", "metrics", "personality"];
+
+/// `names` as an agent's `middleware` setting
+pub fn middleware_config(names: &[&str]) -> Option<Vec<String>> {
+    Some(names.iter().map(|name| name.to_string()).collect())
+}
+
+const DEFAULT_PROFANITY: &[&str] = &["damn", "hell", "shit", "fuck", "crap", "bastard", "bitch", "ass", "asshole"];
+
+lazy_static! {
+    static ref PII: Vec<(Regex, &'static str)> = vec![
+        (Regex::new(r"[A-Za-z0-9._%+-]+@[A-Za-z0-9.-]+\.[A-Za-z]{2,}").unwrap(), "[email]"),
+        (Regex::new(r"\b\d{3}-\d{2}-\d{4}\b").unwrap(), "[ssn]"),
+        (Regex::new(r"\b(?:\d[ -]?){12,15}\d\b").unwrap(), "[card]"),
+        (Regex::new(r"(?:\+\d{1,2}[ .-]?)?(?:\(\d{3}\)|\b\d{3})[ .-]?\d{3}[ .-]\d{4}\b").unwrap(), "[phone]"),
+    ];
+}
+
+/// What one message's trip through the chain shares between hooks
+pub struct MiddlewareContext {
+    pub agent: String,
+    pub started: Instant,
+}
+
+#[async_trait]
+pub trait MessageMiddleware: Send + Sync {
+    fn name(&self) -> &str;
+
+    /// Runs before the agent sees the message; an error stops it reaching the agent
+    async fn before(&self, _ctx: &MiddlewareContext, message: Message) -> Result<Message> {
+        Ok(message)
+    }
+
+    /// Runs on the agent's reply
+    async fn after(&self, _ctx: &MiddlewareContext, response: Message) -> Result<Message> {
+        Ok(response)
+    }
+
+    /// Runs when the agent, or a `before` hook, failed
+    async fn on_error(&self, _ctx: &MiddlewareContext, _error: &anyhow::Error) {}
+
+    /// Runs before a tool the agent calls; an error stops the call
+    async fn before_tool(&self, _agent: &str, _tool: &Tool, params: HashMap<String, String>) -> Result<HashMap<String, String>> {
+        Ok(params)
+    }
+}
+
+#[derive(Clone, Default)]
+pub struct MiddlewareChain {
+    layers: Vec<Arc<dyn MessageMiddleware>>,
+    metrics: Option<Arc<MessageMetrics>>,
+}
+
+impl MiddlewareChain {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a layer inside the ones added so far
+    pub fn with(mut self, layer: impl MessageMiddleware + 'static) -> Self {
+        self.layers.push(Arc::new(layer));
+        self
+    }
+
+    /// Count messages, failures and latency, readable through `stats`
+    pub fn with_metrics(mut self) -> Self {
+        let metrics = Arc::new(MessageMetrics::default());
+        self.layers.push(metrics.clone());
+        self.metrics = Some(metrics);
+        self
+    }
+
+    /// The chain named in the agent's config, or `DEFAULT_MIDDLEWARE`. Unknown names are skipped.
+    pub fn from_config(config: &AgentConfig) -> Self {
+        let names: Vec<&str> = match &config.middleware {
+            Some(names) => names.iter().map(String::as_str).collect(),
+            None => DEFAULT_MIDDLEWARE.to_vec(),
+        };
+        let mut chain = Self::new();
+        for name in names {
+            chain = match name {
+                "logging" => chain.with(LoggingMiddleware),
+                "guard" => match Guard::from_env() {
+                    Ok(guard) => chain.with(guard),
+                    Err(e) => {
+                        // Fail safe: a broken GUARD_CONFIG still gets the built-in rules
+                        tracing::error!("Invalid guard config, using the built-in rules for agent {}: {}", config.name, e);
+                        chain.with(Guard::new(super::guard::default_rules()).expect("built-in guard rules compile"))
+                    }
+                },
+                "metrics" => chain.with_metrics(),
+                "redact_pii" => chain.with(RedactionMiddleware),
+                "profanity" => chain.with(ProfanityMiddleware::from_env()),
+                "personality" => match PersonalityRenderer::from_config(config) {
+                    Some(renderer) => chain.with(renderer),
+                    None => chain,
+                },
+                unknown => {
+                    tracing::warn!("Agent {} names unknown middleware '{}', skipping it", config.name, unknown);
+                    chain
+                }
+            };
+        }
+        chain
+    }
+
+    pub fn names(&self) -> Vec<&str> {
+        self.layers.iter().map(|layer| layer.name()).collect()
+    }
+
+    /// Message counts and latency, when the chain has `metrics`
+    pub fn stats(&self) -> Option<MessageStats> {
+        self.metrics.as_ref().map(|metrics| metrics.stats())
+    }
+
+    /// Pass `message` through the `before` hooks, `handler`, then the `after` hooks in reverse
+    pub async fn run<F, Fut>(&self, agent: &str, message: Message, handler: F) -> Result<Message>
+    where
+        F: FnOnce(Message) -> Fut + Send,
+        Fut: Future<Output = Result<Message>> + Send,
+    {
+        let ctx = MiddlewareContext { agent: agent.to_string(), started: Instant::now() };
+        let result = self.run_inner(&ctx, message, handler).await;
+        if let Err(e) = &result {
+            for layer in self.layers.iter().rev() {
+                layer.on_error(&ctx, e).await;
+            }
+        }
+        result
+    }
+
+    /// Pass a tool call's parameters through the `before_tool` hooks
+    pub async fn screen_tool(&self, agent: &str, tool: &Tool, mut params: HashMap<String, String>) -> Result<HashMap<String, String>> {
+        for layer in &self.layers {
+            params = layer.before_tool(agent, tool, params).await?;
+        }
+        Ok(params)
+    }
+
+    async fn run_inner<F, Fut>(&self, ctx: &MiddlewareContext, mut message: Message, handler: F) -> Result<Message>
+    where
+        F: FnOnce(Message) -> Fut + Send,
+        Fut: Future<Output = Result<Message>> + Send,
+    {
+        for layer in &self.layers {
+            message = layer.before(ctx, message).await?;
+        }
+        let mut response = handler(message).await?;
+        for layer in self.layers.iter().rev() {
+            response = layer.after(ctx, response).await?;
+        }
+        Ok(response)
+    }
+}
+
+pub struct LoggingMiddleware;
+
+#[async_trait]
+impl MessageMiddleware for LoggingMiddleware {
+    fn name(&self) -> &str {
+        "logging"
+    }
+
+    async fn before(&self, ctx: &MiddlewareContext, message: Message) -> Result<Message> {
+        tracing::info!("Agent {} received a message ({} chars)", ctx.agent, message.content.len());
+        tracing::debug!("Agent {} message: {}", ctx.agent, message.content);
+        Ok(message)
+    }
+
+    async fn after(&self, ctx: &MiddlewareContext, response: Message) -> Result<Message> {
+        tracing::info!("Agent {} replied in {:?} ({} chars)", ctx.agent, ctx.started.elapsed(), response.content.len());
+        tracing::debug!("Agent {} reply: {}", ctx.agent, response.content);
+        Ok(response)
+    }
+
+    async fn on_error(&self, ctx: &MiddlewareContext, error: &anyhow::Error) {
+        tracing::warn!("Agent {} failed after {:?}: {}", ctx.agent, ctx.started.elapsed(), error);
+    }
+}
+
+/// An agent's message counts and reply latency since it started
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct MessageStats {
+    pub messages: u64,
+    pub failures: u64,
+    pub average_latency_ms: u64,
+    pub max_latency_ms: u64,
+}
+
+#[derive(Default)]
+pub struct MessageMetrics {
+    messages: AtomicU64,
+    failures: AtomicU64,
+    total_latency_ms: AtomicU64,
+    max_latency_ms: AtomicU64,
+}
+
+impl MessageMetrics {
+    fn record(&self, ctx: &MiddlewareContext, failed: bool) {
+        let elapsed = ctx.started.elapsed().as_millis() as u64;
+        self.messages.fetch_add(1, Ordering::Relaxed);
+        if failed {
+            self.failures.fetch_add(1, Ordering::Relaxed);
+        }
+        self.total_latency_ms.fetch_add(elapsed, Ordering::Relaxed);
+        self.max_latency_ms.fetch_max(elapsed, Ordering::Relaxed);
+    }
+
+    pub fn stats(&self) -> MessageStats {
+        let messages = self.messages.load(Ordering::Relaxed);
+        MessageStats {
+            messages,
+            failures: self.failures.load(Ordering::Relaxed),
+            average_latency_ms: self.total_latency_ms.load(Ordering::Relaxed).checked_div(messages).unwrap_or(0),
+            max_latency_ms: self.max_latency_ms.load(Ordering::Relaxed),
+        }
+    }
+}
+
+#[async_trait]
+impl MessageMiddleware for MessageMetrics {
+    fn name(&self) -> &str {
+        "metrics"
+    }
+
+    async fn after(&self, ctx: &MiddlewareContext, response: Message) -> Result<Message> {
+        self.record(ctx, false);
+        Ok(response)
+    }
+
+    async fn on_error(&self, ctx: &MiddlewareContext, _error: &anyhow::Error) {
+        self.record(ctx, true);
+    }
+}
+
+/// `text` with emails, phone, card and social security numbers masked
+pub fn redact_pii(text: &str) -> String {
+    PII.iter().fold(text.to_string(), |text, (pattern, mask)| pattern.replace_all(&text, *mask).into_owned())
+}
+
+pub struct RedactionMiddleware;
+
+#[async_trait]
+impl MessageMiddleware for RedactionMiddleware {
+    fn name(&self) -> &str {
+        "redact_pii"
+    }
+
+    async fn before(&self, _ctx: &MiddlewareContext, mut message: Message) -> Result<Message> {
+        message.content = redact_pii(&message.content);
+        Ok(message)
+    }
+
+    async fn after(&self, _ctx: &MiddlewareContext, mut response: Message) -> Result<Message> {
+        response.content = redact_pii(&response.content);
+        Ok(response)
+    }
+}
+
+/// Masks whole words from a list, keeping their first letter: `d***`
+pub struct ProfanityMiddleware {
+    pattern: Option<Regex>,
+}
+
+impl ProfanityMiddleware {
+    pub fn new<S: AsRef<str>>(words: &[S]) -> Self {
+        let alternatives: Vec<String> = words.iter()
+            .map(|word| regex::escape(word.as_ref().trim()))
+            .filter(|word| !word.is_empty())
+            .collect();
+        let pattern = (!alternatives.is_empty())
+            .then(|| Regex::new(&format!(r"(?i)\b(?:{})\b", alternatives.join("|"))).ok())
+            .flatten();
+        Self { pattern }
+    }
+
+    pub fn from_env() -> Self {
+        match std::env::var("PROFANITY_WORDS") {
+            Ok(words) => Self::new(&words.split(',').collect::<Vec<_>>()),
+            Err(_) => Self::new(DEFAULT_PROFANITY),
+        }
+    }
+
+    pub fn filter(&self, text: &str) -> String {
+        let Some(pattern) = &self.pattern else { return text.to_string() };
+        pattern.replace_all(text, |caps: &regex::Captures| {
+            let word = &caps[0];
+            let mut chars = word.chars();
+            let first = chars.next().map(String::from).unwrap_or_default();
+            format!("{}{}", first, "*".repeat(chars.count()))
+        }).into_owned()
+    }
+}
+
+#[async_trait]
+impl MessageMiddleware for ProfanityMiddleware {
+    fn name(&self) -> &str {
+        "profanity"
+    }
+
+    async fn before(&self, _ctx: &MiddlewareContext, mut message: Message) -> Result<Message> {
+        message.content = self.filter(&message.content);
+        Ok(message)
+    }
+
+    async fn after(&self, _ctx: &MiddlewareContext, mut response: Message) -> Result<Message> {
+        response.content = self.filter(&response.content);
+        Ok(response)
+    }
+}
+
+#[async_trait]
+impl MessageMiddleware for PersonalityRenderer {
+    fn name(&self) -> &str {
+        "personality"
+    }
+
+    async fn after(&self, _ctx: &MiddlewareContext, response: Message) -> Result<Message> {
+        Ok(self.render(response).await)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::anyhow;
+
+    #[test]
+    fn test_redaction_and_profanity_filters() {
+        assert_eq!(
+            redact_pii("Mail jo.doe@example.com or call (555) 123-4567, SSN 123-45-6789, card 4111 1111 1111 1111"),
+            "Mail [email] or call [phone], SSN [ssn], card [card]"
+        );
+        assert_eq!(redact_pii("Build 1234 took 56 seconds"), "Build 1234 took 56 seconds");
+
+        let filter = ProfanityMiddleware::new(&["darn", "heck"]);
+        assert_eq!(filter.filter("Darn it, what the heck? Checked."), "D*** it, what the h***? Checked.");
+        assert_eq!(ProfanityMiddleware::new::<&str>(&[]).filter("darn"), "darn");
+    }
+
+    #[tokio::test]
+    async fn test_chain_order_and_metrics() {
+        let config = AgentConfig {
+            name: "echo".to_string(),
+            public_description: String::new(),
+            instructions: String::new(),
+            tools: vec![],
+            downstream_agents: vec![],
+            personality: Some(r#"{"template": "{{ content }}!"}"#.to_string()),
+            state_machine: None,
+            script: None,
+            max_concurrency: None,
+            middleware: Some(vec!["metrics".to_string(), "redact_pii".to_string(), "personality".to_string(), "bogus".to_string()]),
+            remote: None,
+        };
+        let chain = MiddlewareChain::from_config(&config);
+        assert_eq!(chain.names(), ["metrics", "redact_pii", "personality"]);
+
+        // The agent sees the redacted message; personality renders before redaction on the way out
+        let reply = chain.run("echo", Message::new("me@example.com".to_string()), |message| async move {
+            assert_eq!(message.content, "[email]");
+            Ok(Message::new(format!("{} you@example.com", message.content)))
+        }).await.unwrap();
+        assert_eq!(reply.content, "[email] [email]!");
+
+        let failed = chain.run("echo", Message::new("hi".to_string()), |_| async { Err(anyhow!("boom")) }).await;
+        assert!(failed.is_err());
+        let stats = chain.stats().unwrap();
+        assert_eq!((stats.messages, stats.failures), (2, 1));
+        assert_eq!(MiddlewareChain::new().stats(), None);
+    }
+}
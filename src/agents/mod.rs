@@ -2,10 +2,11 @@ use std::collections::HashMap;
 use std::sync::Arc;
 use std::any::Any;
 use tokio::sync::RwLock;
-use crate::types::{Agent, AgentConfig, Message, MessageMetadata, State, AgentStateManager, StateMachine, ValidationRule, ToolCall, Tool, TodoProcessor};
+use crate::types::{Agent, AgentConfig, AgentCapabilities, Message, MessageMetadata, State, AgentStateManager, StateMachine, ValidationRule, ToolCall, Tool, TodoList, TodoProcessor};
 use anyhow::Result;
 use lazy_static::lazy_static;
 use anyhow::anyhow;
+use crate::error::SwarmError;
 use async_trait::async_trait;
 use crate::ai::AiProvider;
 
@@ -34,19 +35,55 @@ pub mod project;
 #[cfg(feature = "project-agent")]
 pub use project::ProjectAgent;
 
+#[cfg(feature = "mail-agent")]
+pub mod mail;
+#[cfg(feature = "mail-agent")]
+pub use mail::MailAgent;
+
+#[cfg(feature = "digest-agent")]
+pub mod digest;
+#[cfg(feature = "digest-agent")]
+pub use digest::DigestAgent;
+
+#[cfg(feature = "plugins")]
+pub mod plugin;
+#[cfg(feature = "plugins")]
+pub use plugin::PluginAgent;
+
+#[cfg(feature = "wasm")]
+pub mod wasm;
+#[cfg(feature = "wasm")]
+pub use wasm::WasmAgent;
+
+#[cfg(feature = "scripting")]
+pub mod scripted;
+#[cfg(feature = "scripting")]
+pub use scripted::ScriptedAgent;
+
 pub mod user_agent;
 pub mod transfer;
+pub mod remote;
 pub mod wrapper;
+pub mod concurrency;
+pub mod personality;
+pub mod middleware;
+pub mod guard;
 #[cfg(feature = "rl")]
 pub mod rl;
 
 pub use user_agent::UserAgent;
 pub use transfer::TransferService;
+pub use remote::RemoteAgent;
 pub use wrapper::AgentWrapper;
+pub use personality::{Personality, PersonalityRenderer};
+pub use middleware::{MessageMiddleware, MessageStats, MiddlewareChain};
+pub use guard::{Guard, GuardConfig, GuardRule};
 
 pub struct AgentRegistry {
     pub agents: HashMap<String, AgentWrapper>,
     current_agent: Option<String>,
+    /// The task list agents get their views of; `TodoList::shared` when unset
+    todos: Option<TodoList>,
 }
 
 impl AgentRegistry {
@@ -54,14 +91,50 @@ impl AgentRegistry {
         Self {
             agents: HashMap::new(),
             current_agent: None,
+            todos: None,
         }
     }
 
+    /// Give registered agents views of `todos` rather than of the shared task list
+    pub fn with_todos(mut self, todos: TodoList) -> Self {
+        self.todos = Some(todos);
+        self
+    }
+
+    /// Register `agent` as `name`; it processes the tasks for `name` in the task list
     pub async fn register(&mut self, name: String, agent: Box<dyn Agent + Send + Sync>) -> Result<()> {
-        self.agents.insert(name, AgentWrapper::new(agent));
+        let todos = self.todo_list().await?;
+        self.insert(name.clone(), AgentWrapper::new(agent, todos.for_agent(&name)));
         Ok(())
     }
 
+    /// Register an already wrapped agent as `name`, returning the agent it replaces
+    pub fn insert(&mut self, name: String, agent: AgentWrapper) -> Option<AgentWrapper> {
+        let event = crate::events::DomainEvent::agent_registered(&name);
+        let previous = self.agents.insert(name, agent);
+        crate::events::global().publish(event);
+        previous
+    }
+
+    /// The task list registered agents get their views of
+    pub async fn todo_list(&self) -> Result<TodoList> {
+        match &self.todos {
+            Some(todos) => Ok(todos.clone()),
+            None => TodoList::shared().await,
+        }
+    }
+
+    /// Remove `name`, returning it so the caller can drain and shut it down
+    pub fn deregister(&mut self, name: &str) -> Option<AgentWrapper> {
+        self.agents.remove(name)
+    }
+
+    /// Create the agent `config` describes. Greeters route by the capabilities of the
+    /// agents registered so far.
+    pub async fn build_agent(&self, config: AgentConfig) -> Result<Box<dyn Agent + Send + Sync>> {
+        create_routed_agent(config, self.capability_catalog().await).await
+    }
+
     pub fn get(&self, name: &str) -> Option<&AgentWrapper> {
         self.agents.get(name)
     }
@@ -86,17 +159,143 @@ impl AgentRegistry {
         self.agents.iter()
     }
 
+    /// Every registered agent's advertised capabilities, sorted by agent name
+    pub async fn capability_catalog(&self) -> Vec<AgentCapabilities> {
+        capability_catalog(&self.agents).await
+    }
+
+    /// Shut down every agent, continuing past failures; errors name the agents that failed
+    pub async fn shutdown_all(&self) -> Result<()> {
+        let mut failed = Vec::new();
+        for (name, agent) in &self.agents {
+            if let Err(e) = agent.shutdown().await {
+                tracing::error!("Failed to shut down agent {}: {}", name, e);
+                failed.push(name.clone());
+            }
+        }
+        if failed.is_empty() {
+            Ok(())
+        } else {
+            failed.sort();
+            Err(anyhow!("Failed to shut down agents: {}", failed.join(", ")))
+        }
+    }
+
+    /// Create and register the agents in `configs`, then any plugin and wasm agents. Fails
+    /// with one validation error listing every problem found: agents this build can't
+    /// create, downstream agents that aren't registered, and downstream cycles unless
+    /// `ALLOW_AGENT_CYCLES` is set.
     pub async fn create_default_agents(configs: Vec<AgentConfig>) -> Result<Self> {
+        // Agents' tools come from the global registry; set it up before their first call
+        if let Err(e) = crate::tools::install_default_tools().await {
+            tracing::warn!("Default tools not installed yet, retrying on the first tool call: {}", e);
+        }
         let mut registry = Self::new();
-        for config in configs {
-            let agent = create_agent(config.clone()).await?;
+        let mut problems = Vec::new();
+        // The greeter routes conversations by the other agents' capabilities, so it comes last
+        let mut greeters = Vec::new();
+        for config in &configs {
+            if let Some(feature) = missing_feature(config) {
+                problems.push(format!("agent '{}' needs the {} feature", config.name, feature));
+                continue;
+            }
+            #[cfg(feature = "greeter-agent")]
+            if config.name == "greeter" && config.script.is_none() && config.remote.is_none() {
+                greeters.push(config.clone());
+                continue;
+            }
+            match create_agent(config.clone()).await {
+                Ok(agent) => registry.register(config.name.clone(), agent).await?,
+                Err(e) => problems.push(format!("agent '{}' could not be created: {}", config.name, e)),
+            }
+        }
+        for config in greeters {
+            let agent = registry.build_agent(config.clone()).await?;
             registry.register(config.name, agent).await?;
         }
+        #[cfg(feature = "plugins")]
+        if let Ok(dir) = std::env::var(plugin::PLUGIN_DIR_ENV) {
+            registry.load_plugins(dir).await?;
+        }
+        #[cfg(feature = "wasm")]
+        if let Ok(dir) = std::env::var(wasm::WASM_AGENT_DIR_ENV) {
+            registry.load_wasm_agents(dir, &wasm::WasmLimits::default()).await?;
+        }
+
+        // Plugin and wasm agents can be downstream agents too, and list their own
+        let mut graph = configs;
+        for (name, agent) in &registry.agents {
+            if !graph.iter().any(|config| &config.name == name) {
+                graph.push(agent.get_config().await?);
+            }
+        }
+        let registered = registry.agents.keys().map(String::as_str).collect();
+        problems.extend(crate::config::dependency_problems(&graph, &registered, crate::config::cycles_allowed()));
+        if !problems.is_empty() {
+            return Err(SwarmError::Validation(format!("Invalid agent configuration:\n  - {}", problems.join("\n  - "))).into());
+        }
         Ok(registry)
     }
+
+    /// Register every agent plugin in `dir`, returning their names.
+    /// A plugin named like an existing agent replaces it.
+    #[cfg(feature = "plugins")]
+    pub async fn load_plugins(&mut self, dir: impl AsRef<std::path::Path>) -> Result<Vec<String>> {
+        let mut names = Vec::new();
+        for plugin in plugin::load_plugins(dir)? {
+            let name = plugin.get_config().await?.name;
+            self.register(name.clone(), Box::new(plugin)).await?;
+            names.push(name);
+        }
+        Ok(names)
+    }
+
+    /// Register every `.wasm` agent in `dir`, returning their names
+    #[cfg(feature = "wasm")]
+    pub async fn load_wasm_agents(&mut self, dir: impl AsRef<std::path::Path>, limits: &wasm::WasmLimits) -> Result<Vec<String>> {
+        let mut names = Vec::new();
+        for agent in wasm::load_wasm_agents(dir, limits)? {
+            let name = agent.get_config().await?.name;
+            self.register(name.clone(), Box::new(agent)).await?;
+            names.push(name);
+        }
+        Ok(names)
+    }
+}
+
+/// The capabilities of `agents`, sorted by agent name. Agents that fail to describe
+/// themselves are left out.
+pub async fn capability_catalog<'a>(agents: impl IntoIterator<Item = (&'a String, &'a AgentWrapper)>) -> Vec<AgentCapabilities> {
+    let mut catalog = Vec::new();
+    for (name, agent) in agents {
+        match agent.capabilities().await {
+            Ok(capabilities) => catalog.push(AgentCapabilities { agent: name.clone(), capabilities }),
+            Err(e) => tracing::warn!("Agent {} did not report its capabilities: {}", name, e),
+        }
+    }
+    catalog.sort_by(|a, b| a.agent.cmp(&b.agent));
+    catalog
+}
+
+/// Create the agent `config` describes; greeters route by the agents in `catalog`
+pub async fn create_routed_agent(config: AgentConfig, catalog: Vec<AgentCapabilities>) -> Result<Box<dyn Agent + Send + Sync>> {
+    #[cfg(feature = "greeter-agent")]
+    if config.name == "greeter" && config.script.is_none() && config.remote.is_none() {
+        return Ok(Box::new(GreeterAgent::new(config).with_catalog(catalog)));
+    }
+    #[cfg(not(feature = "greeter-agent"))]
+    let _ = catalog;
+    create_agent(config).await
 }
 
 pub async fn create_agent(config: AgentConfig) -> Result<Box<dyn Agent + Send + Sync>> {
+    if config.remote.is_some() {
+        return Ok(Box::new(RemoteAgent::new(config)?));
+    }
+    #[cfg(feature = "scripting")]
+    if config.script.is_some() {
+        return Ok(Box::new(ScriptedAgent::new(config)?));
+    }
     match config.name.as_str() {
         #[cfg(feature = "project-agent")]
         "project" => {
@@ -105,7 +304,12 @@ pub async fn create_agent(config: AgentConfig) -> Result<Box<dyn Agent + Send +
         }
         #[cfg(feature = "git-agent")]
         "git" => {
-            let agent = GitAssistantAgent::new(config);
+            let mut agent = GitAssistantAgent::new(config);
+            match crate::knowledge::KnowledgeBase::from_env().await {
+                Ok(Some(knowledge)) => agent = agent.with_knowledge(knowledge),
+                Ok(None) => {}
+                Err(e) => tracing::warn!("Git agent running without the knowledge base: {}", e),
+            }
             Ok(Box::new(agent))
         }
         #[cfg(feature = "greeter-agent")]
@@ -113,9 +317,25 @@ pub async fn create_agent(config: AgentConfig) -> Result<Box<dyn Agent + Send +
             let agent = GreeterAgent::new(config);
             Ok(Box::new(agent))
         }
+        #[cfg(feature = "mail-agent")]
+        "mail" => {
+            let agent = MailAgent::from_env(config).await?;
+            agent.start_polling();
+            Ok(Box::new(agent))
+        }
+        #[cfg(feature = "digest-agent")]
+        "digest" => {
+            let agent = DigestAgent::from_env(config).await?;
+            Ok(Box::new(agent))
+        }
         #[cfg(feature = "haiku-agent")]
         "haiku" => {
-            let agent = HaikuAgent::new(config);
+            let mut agent = HaikuAgent::new(config);
+            match crate::types::state_store::from_env().await {
+                Ok(Some(store)) => agent = agent.with_session_store(store),
+                Ok(None) => {}
+                Err(e) => tracing::warn!("Haiku sessions will not persist: {}", e),
+            }
             Ok(Box::new(agent))
         }
         #[cfg(feature = "browser-agent")]
@@ -123,10 +343,31 @@ pub async fn create_agent(config: AgentConfig) -> Result<Box<dyn Agent + Send +
             let agent = browser::BrowserAgentWrapper::new(config)?;
             Ok(Box::new(agent))
         }
-        _ => Err(anyhow!("Unknown agent type: {}", config.name)),
+        _ => Err(SwarmError::AgentNotFound(config.name).into()),
     }
 }
 
+/// The cargo feature a built-in agent in `config` needs that this build was made without
+fn missing_feature(config: &AgentConfig) -> Option<&'static str> {
+    if config.remote.is_some() {
+        return None;
+    }
+    if config.script.is_some() {
+        return (!cfg!(feature = "scripting")).then_some("scripting");
+    }
+    let (feature, enabled) = match config.name.as_str() {
+        "project" => ("project-agent", cfg!(feature = "project-agent")),
+        "git" => ("git-agent", cfg!(feature = "git-agent")),
+        "greeter" => ("greeter-agent", cfg!(feature = "greeter-agent")),
+        "mail" => ("mail-agent", cfg!(feature = "mail-agent")),
+        "digest" => ("digest-agent", cfg!(feature = "digest-agent")),
+        "haiku" => ("haiku-agent", cfg!(feature = "haiku-agent")),
+        "browser" => ("browser-agent", cfg!(feature = "browser-agent")),
+        _ => return None,
+    };
+    (!enabled).then_some(feature)
+}
+
 lazy_static! {
     pub static ref GLOBAL_REGISTRY: Arc<RwLock<AgentRegistry>> = Arc::new(RwLock::new(AgentRegistry::new()));
 }
@@ -161,6 +402,10 @@ mod tests {
                 downstream_agents: vec![String::from("haiku")],
                 personality: None,
                 state_machine: None,
+                script: None,
+                max_concurrency: None,
+                middleware: None,
+                remote: None,
             },
             AgentConfig {
                 name: String::from("haiku"),
@@ -170,6 +415,10 @@ mod tests {
                 downstream_agents: vec![],
                 personality: None,
                 state_machine: None,
+                script: None,
+                max_concurrency: None,
+                middleware: None,
+                remote: None,
             },
         ]
     }
@@ -225,6 +474,10 @@ mod tests {
                 downstream_agents: vec!["haiku".to_string()],
                 personality: None,
                 state_machine: None,
+                script: None,
+                max_concurrency: None,
+                middleware: None,
+                remote: None,
             });
             registry.register("greeter".to_string(), Box::new(greeter)).await?;
 
@@ -248,11 +501,20 @@ mod tests {
                                 transitions
                             }),
                             validation: None,
+                            guarded_transitions: None,
+                            parent: None,
+                            initial_child: None,
+                            on_enter: None,
+                            on_exit: None,
                         });
                         states
                     },
                     initial_state: "awaiting_topic".to_string(),
                 }),
+                script: None,
+                max_concurrency: None,
+                middleware: None,
+                remote: None,
             });
             registry.register("haiku".to_string(), Box::new(haiku)).await?;
         }
@@ -291,6 +553,10 @@ mod tests {
                 downstream_agents: vec!["haiku".to_string()],
                 personality: None,
                 state_machine: None,
+                script: None,
+                max_concurrency: None,
+                middleware: None,
+                remote: None,
             });
 
             let haiku = HaikuAgent::new(AgentConfig {
@@ -301,6 +567,10 @@ mod tests {
                 downstream_agents: vec![],
                 personality: None,
                 state_machine: None,
+                script: None,
+                max_concurrency: None,
+                middleware: None,
+                remote: None,
             });
 
             reg.register("greeter".to_string(), Box::new(greeter)).await?;
@@ -314,65 +584,67 @@ mod tests {
         assert_eq!(service.get_current_agent_name().await?, "haiku");
         Ok(())
     }
+
+    #[tokio::test]
+    async fn test_invalid_registries_report_every_problem() {
+        let agent = |name: &str, downstream: &[&str]| AgentConfig {
+            downstream_agents: downstream.iter().map(|d| d.to_string()).collect(),
+            ..AgentConfig::builder(name).build().unwrap()
+        };
+        let configs = vec![agent("oracle", &["ghost"]), agent("browser", &["oracle"])];
+        let err = AgentRegistry::create_default_agents(configs).await.err().unwrap();
+        assert!(matches!(SwarmError::find(&err), Some(SwarmError::Validation(_))));
+        let message = err.to_string();
+        assert!(message.contains("agent 'oracle' could not be created"), "{}", message);
+        assert!(message.contains("agent 'oracle' lists undefined downstream agent 'ghost'"), "{}", message);
+        assert!(message.contains("agent 'browser' lists undefined downstream agent 'oracle'"), "{}", message);
+        assert_eq!(message.contains("needs the browser-agent feature"), !cfg!(feature = "browser-agent"));
+    }
 }
 
 pub fn default_agents() -> Vec<AgentConfig> {
     let mut agents = Vec::new();
 
     #[cfg(feature = "greeter-agent")]
-    agents.push(AgentConfig {
-        name: "greeter".to_string(),
-        public_description: "Agent that greets the user.".to_string(),
-        instructions: "Greet users and make them feel welcome.".to_string(),
-        tools: Vec::new(),
-        downstream_agents: Vec::new(),
-        personality: None,
-        state_machine: None,
-    });
+    agents.push(AgentConfig::builder("greeter")
+        .description("Agent that greets the user.")
+        .instructions("Greet users and make them feel welcome."));
 
     #[cfg(feature = "haiku-agent")]
-    agents.push(AgentConfig {
-        name: "haiku".to_string(),
-        public_description: "Agent that creates haikus.".to_string(),
-        instructions: "Create haikus based on user input.".to_string(),
-        tools: Vec::new(),
-        downstream_agents: Vec::new(),
-        personality: None,
-        state_machine: None,
-    });
+    agents.push(AgentConfig::builder("haiku")
+        .description("Agent that creates haikus.")
+        .instructions("Create haikus based on user input."));
 
     #[cfg(feature = "git-agent")]
-    agents.push(AgentConfig {
-        name: "git".to_string(),
-        public_description: "Agent that helps with git operations.".to_string(),
-        instructions: "Help users with git operations like commit, branch, merge etc.".to_string(),
-        tools: Vec::new(),
-        downstream_agents: Vec::new(),
-        personality: None,
-        state_machine: None,
-    });
+    agents.push(AgentConfig::builder("git")
+        .description("Agent that helps with git operations.")
+        .instructions("Help users with git operations like commit, branch, merge etc.")
+        // Concurrent git commands in the same working tree conflict
+        .with_max_concurrency(1)
+        .with_middleware(middleware::GUARDED_MIDDLEWARE));
 
     #[cfg(feature = "project-init-agent")]
-    agents.push(AgentConfig {
-        name: "project-init".to_string(),
-        public_description: "Agent that helps initialize new projects.".to_string(),
-        instructions: "Help users create new projects with proper structure and configuration.".to_string(),
-        tools: Vec::new(),
-        downstream_agents: Vec::new(),
-        personality: None,
-        state_machine: None,
-    });
+    agents.push(AgentConfig::builder("project-init")
+        .description("Agent that helps initialize new projects.")
+        .instructions("Help users create new projects with proper structure and configuration.")
+        .with_middleware(middleware::GUARDED_MIDDLEWARE));
 
     #[cfg(feature = "browser-agent")]
-    agents.push(AgentConfig {
-        name: "browser".to_string(),
-        public_description: "Agent that controls browser automation.".to_string(),
-        instructions: "Help users with browser automation tasks.".to_string(),
-        tools: Vec::new(),
-        downstream_agents: Vec::new(),
-        personality: None,
-        state_machine: None,
-    });
-
-    agents
+    agents.push(AgentConfig::builder("browser")
+        .description("Agent that controls browser automation.")
+        .instructions("Help users with browser automation tasks.")
+        // The agent drives a single browser session
+        .with_max_concurrency(1)
+        .with_middleware(middleware::GUARDED_MIDDLEWARE));
+
+    #[cfg(feature = "digest-agent")]
+    agents.push(AgentConfig::builder("digest")
+        .description("Agent that summarizes recent task activity per project.")
+        .instructions("Summarize the tasks created, completed and failed over the last day.")
+        // One digest at a time keeps AI spend predictable
+        .with_max_concurrency(1));
+
+    agents.into_iter()
+        .map(|builder| builder.build().expect("Built-in agent configs are valid"))
+        .collect()
 }
@@ -0,0 +1,214 @@
+//! Applies an agent's configured personality to its replies, so agents that ignore
+//! `AgentConfig.personality` still speak in their voice. The `personality` middleware,
+//! on by default, runs every response through a `PersonalityRenderer`.
+//!
+//! The personality is JSON, or plain text taken as the style:
+//!
+//! ```json
+//! {
+//!   "style": "friendly_receptionist",
+//!   "traits": ["friendly", "helpful"],
+//!   "voice": { "tone": "warm", "pacing": "measured", "quirks": ["uses_emojis"] },
+//!   "template": "{{ content }}\n\nAnything else I can help with?",
+//!   "rewrite": true
+//! }
+//! ```
+//!
+//! Traits fill in the reply's `personality_traits` when the agent set none. `template`
+//! wraps the content (`{{ content }}`, `{{ agent }}`), and `rewrite` has the model restate
+//! the reply in the voice, keeping the reply as it was if the model fails.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use anyhow::{Result, anyhow};
+use serde::Deserialize;
+use crate::ai::{AiProvider, BudgetedAiClient, FallbackAiClient};
+use crate::types::{AgentConfig, Message, MessageMetadata};
+
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+pub struct Personality {
+    #[serde(default)]
+    pub style: Option<String>,
+    #[serde(default)]
+    pub traits: Vec<String>,
+    #[serde(default)]
+    pub voice: Voice,
+    /// Wraps each reply; `{{ content }}` is the reply and `{{ agent }}` the agent's name
+    #[serde(default)]
+    pub template: Option<String>,
+    /// Have the model restate each reply in this voice
+    #[serde(default)]
+    pub rewrite: bool,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+pub struct Voice {
+    #[serde(default)]
+    pub tone: Option<String>,
+    #[serde(default)]
+    pub pacing: Option<String>,
+    #[serde(default)]
+    pub quirks: Vec<String>,
+}
+
+impl Personality {
+    /// A personality from JSON, or from plain text describing the style
+    pub fn parse(personality: &str) -> Result<Self> {
+        let personality = personality.trim();
+        if personality.starts_with('{') {
+            return serde_json::from_str(personality).map_err(|e| anyhow!("Invalid personality: {}", e));
+        }
+        Ok(Self { style: Some(personality.to_string()), ..Self::default() })
+    }
+
+    /// Instructions describing the voice, for system prompts; empty when there's nothing to say
+    pub fn prompt(&self) -> String {
+        let readable = |s: &str| s.replace('_', " ");
+        let mut lines = Vec::new();
+        if let Some(style) = &self.style {
+            lines.push(format!("Style: {}", readable(style)));
+        }
+        if !self.traits.is_empty() {
+            lines.push(format!("Traits: {}", self.traits.iter().map(|t| readable(t)).collect::<Vec<_>>().join(", ")));
+        }
+        if let Some(tone) = &self.voice.tone {
+            lines.push(format!("Tone: {}", readable(tone)));
+        }
+        if let Some(pacing) = &self.voice.pacing {
+            lines.push(format!("Pacing: {}", readable(pacing)));
+        }
+        if !self.voice.quirks.is_empty() {
+            lines.push(format!("Quirks: {}", self.voice.quirks.iter().map(|q| readable(q)).collect::<Vec<_>>().join(", ")));
+        }
+        if lines.is_empty() {
+            return String::new();
+        }
+        format!("Speak with this personality.\n{}", lines.join("\n"))
+    }
+}
+
+/// Post-processes an agent's replies according to its personality
+#[derive(Clone)]
+pub struct PersonalityRenderer {
+    agent: String,
+    personality: Personality,
+    ai_client: Option<Arc<dyn AiProvider>>,
+}
+
+impl PersonalityRenderer {
+    pub fn new(agent: impl Into<String>, personality: Personality) -> Self {
+        Self { agent: agent.into(), personality, ai_client: None }
+    }
+
+    /// The renderer for an agent's config; `None` without a usable personality
+    pub fn from_config(config: &AgentConfig) -> Option<Self> {
+        let personality = match Personality::parse(config.personality.as_deref()?) {
+            Ok(personality) => personality,
+            Err(e) => {
+                tracing::warn!("Ignoring personality of agent {}: {}", config.name, e);
+                return None;
+            }
+        };
+        let mut renderer = Self::new(config.name.clone(), personality);
+        if renderer.personality.rewrite {
+            renderer.ai_client = Some(Arc::new(BudgetedAiClient::new("personality", FallbackAiClient::default_chain())));
+        }
+        Some(renderer)
+    }
+
+    /// The model that rewrites replies when `rewrite` is set
+    pub fn with_ai_client<T: AiProvider + 'static>(mut self, client: T) -> Self {
+        self.ai_client = Some(Arc::new(client));
+        self
+    }
+
+    pub fn personality(&self) -> &Personality {
+        &self.personality
+    }
+
+    pub async fn render(&self, mut message: Message) -> Message {
+        if !self.personality.traits.is_empty() {
+            let metadata = message.metadata.get_or_insert_with(|| MessageMetadata::new(self.agent.clone()));
+            if metadata.personality_traits.is_none() {
+                metadata.personality_traits = Some(self.personality.traits.clone());
+            }
+        }
+        if message.content.trim().is_empty() {
+            return message;
+        }
+        if let (true, Some(ai_client)) = (self.personality.rewrite, &self.ai_client) {
+            match self.rewrite(&message.content, ai_client.as_ref()).await {
+                Ok(content) => message.content = content,
+                Err(e) => tracing::debug!("Keeping {}'s reply as written: {}", self.agent, e),
+            }
+        }
+        if let Some(template) = &self.personality.template {
+            let vars = HashMap::from([
+                ("content".to_string(), message.content.clone()),
+                ("agent".to_string(), self.agent.clone()),
+            ]);
+            match crate::workflow::render(template, &vars) {
+                Ok(content) => message.content = content,
+                Err(e) => tracing::warn!("Invalid personality template for agent {}: {}", self.agent, e),
+            }
+        }
+        message
+    }
+
+    async fn rewrite(&self, content: &str, ai_client: &dyn AiProvider) -> Result<String> {
+        let system_prompt = format!(
+            "{}\n\nRewrite the message in this voice. Keep its meaning, facts, names, numbers, commands and code blocks exactly. Output ONLY the rewritten message.",
+            self.personality.prompt()
+        );
+        let messages = vec![HashMap::from([
+            ("role".to_string(), "user".to_string()),
+            ("content".to_string(), content.to_string()),
+        ])];
+        let rewritten = ai_client.chat(&system_prompt, messages).await?;
+        let rewritten = rewritten.trim();
+        if rewritten.is_empty() {
+            return Err(anyhow!("Model returned an empty rewrite"));
+        }
+        Ok(rewritten.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ai::MockAiProvider;
+
+    #[test]
+    fn test_parse_json_and_plain_text() {
+        let personality = Personality::parse(r#"{"style": "friendly_receptionist", "traits": ["friendly"], "voice": {"quirks": ["uses_emojis"]}}"#).unwrap();
+        assert_eq!(personality.traits, ["friendly"]);
+        assert_eq!(personality.prompt(), "Speak with this personality.\nStyle: friendly receptionist\nTraits: friendly\nQuirks: uses emojis");
+        assert_eq!(Personality::parse("grumpy pirate").unwrap().style.as_deref(), Some("grumpy pirate"));
+        assert!(Personality::parse("{ not json").is_err());
+        assert_eq!(Personality::default().prompt(), "");
+    }
+
+    #[tokio::test]
+    async fn test_render_rewrites_then_applies_the_template() {
+        let personality = Personality {
+            traits: vec!["cheerful".to_string()],
+            template: Some("{{ content }} -- {{ agent }}".to_string()),
+            rewrite: true,
+            ..Personality::default()
+        };
+        let ai = MockAiProvider::new().with_response("Ahoy! The build passed.").with_error("model down");
+        let renderer = PersonalityRenderer::new("haiku", personality).with_ai_client(ai.clone());
+
+        let reply = renderer.render(Message::new("The build passed.".to_string())).await;
+        assert_eq!(reply.content, "Ahoy! The build passed. -- haiku");
+        assert_eq!(reply.metadata.unwrap().personality_traits.unwrap(), ["cheerful"]);
+        assert!(ai.requests()[0].system_prompt.contains("Traits: cheerful"));
+
+        // A failed rewrite keeps the reply, and traits the agent set are left alone
+        let message = Message::new("Done.".to_string())
+            .with_metadata(MessageMetadata::new("haiku".to_string()).with_personality(vec!["poetic".to_string()]));
+        let reply = renderer.render(message).await;
+        assert_eq!(reply.content, "Done. -- haiku");
+        assert_eq!(reply.metadata.unwrap().personality_traits.unwrap(), ["poetic"]);
+    }
+}
@@ -0,0 +1,409 @@
+//! Agents loaded at runtime from shared libraries.
+//!
+//! A plugin is a `cdylib` exporting a small C ABI. Values cross the boundary as JSON
+//! strings, so host and plugin don't need matching compilers or crate versions, only
+//! matching JSON shapes. Plugin authors implement `Agent` as usual and export it with
+//! `export_agent_plugin!`:
+//!
+//! ```ignore
+//! swarmonomicon::export_agent_plugin!(WeatherAgent::new());
+//! ```
+
+use std::collections::HashMap;
+use std::ffi::{CStr, CString, c_char, c_void};
+use std::path::Path;
+use std::sync::Arc;
+use anyhow::{Result, anyhow};
+use async_trait::async_trait;
+use libloading::Library;
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
+use crate::error::SwarmError;
+use crate::types::{Agent, AgentConfig, Message, State, Tool};
+
+/// Bumped whenever the exported functions or their JSON change incompatibly
+pub const PLUGIN_ABI_VERSION: u32 = 1;
+
+/// Directory scanned for plugins at startup
+pub const PLUGIN_DIR_ENV: &str = "SWARM_PLUGIN_DIR";
+
+type AbiVersionFn = unsafe extern "C" fn() -> u32;
+type CreateFn = unsafe extern "C" fn() -> *mut c_void;
+type QueryFn = unsafe extern "C" fn(*mut c_void) -> *mut c_char;
+type ProcessFn = unsafe extern "C" fn(*mut c_void, *const c_char) -> *mut c_char;
+type FreeStringFn = unsafe extern "C" fn(*mut c_char);
+type DestroyFn = unsafe extern "C" fn(*mut c_void);
+
+/// What every plugin call returns, as JSON
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PluginReply<T> {
+    Ok(T),
+    Error(String),
+}
+
+/// The functions a plugin exports
+#[derive(Clone, Copy)]
+struct PluginVTable {
+    abi_version: AbiVersionFn,
+    create: CreateFn,
+    config: QueryFn,
+    current_state: QueryFn,
+    process_message: ProcessFn,
+    free_string: FreeStringFn,
+    destroy: DestroyFn,
+}
+
+impl PluginVTable {
+    /// # Safety
+    /// The library must export the plugin ABI with the expected signatures
+    unsafe fn from_library(library: &Library) -> Result<Self> {
+        unsafe fn symbol<T: Copy>(library: &Library, name: &str) -> Result<T> {
+            Ok(*library.get::<T>(name.as_bytes())
+                .map_err(|e| anyhow!("Missing plugin symbol {}: {}", name, e))?)
+        }
+        Ok(Self {
+            abi_version: symbol(library, "swarm_plugin_abi_version")?,
+            create: symbol(library, "swarm_plugin_create")?,
+            config: symbol(library, "swarm_plugin_config")?,
+            current_state: symbol(library, "swarm_plugin_current_state")?,
+            process_message: symbol(library, "swarm_plugin_process_message")?,
+            free_string: symbol(library, "swarm_plugin_free_string")?,
+            destroy: symbol(library, "swarm_plugin_destroy")?,
+        })
+    }
+}
+
+/// A live plugin instance; destroyed before its library is unloaded
+struct PluginInstance {
+    vtable: PluginVTable,
+    handle: *mut c_void,
+    // Declared last so it is dropped after `drop` has destroyed the instance
+    _library: Option<Library>,
+}
+
+// Plugins are required to be thread safe; the exported guest functions make them so
+unsafe impl Send for PluginInstance {}
+unsafe impl Sync for PluginInstance {}
+
+impl Drop for PluginInstance {
+    fn drop(&mut self) {
+        unsafe { (self.vtable.destroy)(self.handle) }
+    }
+}
+
+impl PluginInstance {
+    /// Take ownership of a string returned by the plugin and decode its reply
+    fn reply<T: DeserializeOwned>(&self, raw: *mut c_char) -> Result<T> {
+        if raw.is_null() {
+            return Err(SwarmError::Agent("Plugin returned no reply".to_string()).into());
+        }
+        let json = unsafe { CStr::from_ptr(raw) }.to_string_lossy().into_owned();
+        unsafe { (self.vtable.free_string)(raw) };
+        match serde_json::from_str(&json)? {
+            PluginReply::Ok(value) => Ok(value),
+            PluginReply::Error(e) => Err(SwarmError::Agent(e).into()),
+        }
+    }
+
+    fn query<T: DeserializeOwned>(&self, function: QueryFn) -> Result<T> {
+        self.reply(unsafe { function(self.handle) })
+    }
+
+    fn process_message(&self, message: &Message) -> Result<Message> {
+        let request = CString::new(serde_json::to_string(message)?)?;
+        self.reply(unsafe { (self.vtable.process_message)(self.handle, request.as_ptr()) })
+    }
+}
+
+/// An agent backed by a plugin. Calls run on the blocking pool since plugins drive
+/// their own runtime.
+pub struct PluginAgent {
+    instance: Arc<PluginInstance>,
+    config: AgentConfig,
+}
+
+impl PluginAgent {
+    /// Load a plugin library and create its agent
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        // Loading runs the library's initializers; plugins are trusted code
+        let library = unsafe { Library::new(path) }
+            .map_err(|e| anyhow!("Failed to load plugin {}: {}", path.display(), e))?;
+        let vtable = unsafe { PluginVTable::from_library(&library)? };
+        Self::from_vtable(vtable, Some(library))
+    }
+
+    fn from_vtable(vtable: PluginVTable, library: Option<Library>) -> Result<Self> {
+        let version = unsafe { (vtable.abi_version)() };
+        if version != PLUGIN_ABI_VERSION {
+            return Err(SwarmError::Validation(format!(
+                "Plugin ABI version {} is not supported (expected {})", version, PLUGIN_ABI_VERSION
+            )).into());
+        }
+        let handle = unsafe { (vtable.create)() };
+        if handle.is_null() {
+            return Err(SwarmError::Agent("Plugin failed to create its agent".to_string()).into());
+        }
+        let instance = Arc::new(PluginInstance { vtable, handle, _library: library });
+        let config = instance.query(vtable.config)?;
+        Ok(Self { instance, config })
+    }
+
+    async fn blocking<T, F>(&self, call: F) -> Result<T>
+    where
+        T: Send + 'static,
+        F: FnOnce(&PluginInstance) -> Result<T> + Send + 'static,
+    {
+        let instance = self.instance.clone();
+        tokio::task::spawn_blocking(move || call(&instance)).await?
+    }
+}
+
+#[async_trait]
+impl Agent for PluginAgent {
+    async fn process_message(&self, message: Message) -> Result<Message> {
+        self.blocking(move |instance| instance.process_message(&message)).await
+    }
+
+    async fn transfer_to(&self, _target_agent: String, message: Message) -> Result<Message> {
+        Ok(message)
+    }
+
+    async fn call_tool(&self, tool: &Tool, _params: HashMap<String, String>) -> Result<String> {
+        Err(SwarmError::tool_failure(&tool.name, "Plugin agents do not support direct tool calls").into())
+    }
+
+    async fn get_current_state(&self) -> Result<Option<State>> {
+        self.blocking(|instance| instance.query(instance.vtable.current_state)).await
+    }
+
+    async fn get_config(&self) -> Result<AgentConfig> {
+        Ok(self.config.clone())
+    }
+}
+
+/// Load every plugin library in `dir`. Libraries that fail to load are logged and skipped.
+pub fn load_plugins(dir: impl AsRef<Path>) -> Result<Vec<PluginAgent>> {
+    let dir = dir.as_ref();
+    let mut plugins = Vec::new();
+    for entry in std::fs::read_dir(dir).map_err(|e| anyhow!("Failed to read plugin directory {}: {}", dir.display(), e))? {
+        let path = entry?.path();
+        if path.extension().and_then(|e| e.to_str()) != Some(std::env::consts::DLL_EXTENSION) {
+            continue;
+        }
+        match PluginAgent::load(&path) {
+            Ok(plugin) => {
+                tracing::info!("Loaded agent plugin '{}' from {}", plugin.config.name, path.display());
+                plugins.push(plugin);
+            }
+            Err(e) => tracing::warn!("Skipping plugin {}: {}", path.display(), e),
+        }
+    }
+    Ok(plugins)
+}
+
+/// The plugin side of the ABI, used by `export_agent_plugin!`
+#[doc(hidden)]
+pub mod guest {
+    use super::*;
+    use std::panic::{AssertUnwindSafe, catch_unwind};
+
+    pub struct GuestAgent {
+        agent: Box<dyn Agent>,
+        runtime: tokio::runtime::Runtime,
+    }
+
+    fn encode<T: Serialize>(reply: PluginReply<T>) -> *mut c_char {
+        let json = serde_json::to_string(&reply)
+            .unwrap_or_else(|e| format!(r#"{{"error":"Failed to encode plugin reply: {}"}}"#, e));
+        CString::new(json).map(CString::into_raw).unwrap_or(std::ptr::null_mut())
+    }
+
+    /// Run `call`, turning errors and panics into error replies
+    fn guarded<T: Serialize>(call: impl FnOnce() -> Result<T>) -> *mut c_char {
+        match catch_unwind(AssertUnwindSafe(call)) {
+            Ok(Ok(value)) => encode(PluginReply::Ok(value)),
+            Ok(Err(e)) => encode(PluginReply::<T>::Error(e.to_string())),
+            Err(_) => encode(PluginReply::<T>::Error("Plugin panicked".to_string())),
+        }
+    }
+
+    impl GuestAgent {
+        /// Drive `future` on the plugin's runtime. Hosts normally call in from a blocking
+        /// thread, but a caller inside a runtime (e.g. one that shares the plugin's copy
+        /// of tokio) can't block on another one, so run it from a fresh thread instead.
+        fn run<F: std::future::Future + Send>(&self, future: F) -> F::Output
+        where
+            F::Output: Send,
+        {
+            if tokio::runtime::Handle::try_current().is_err() {
+                return self.runtime.block_on(future);
+            }
+            std::thread::scope(|scope| {
+                scope.spawn(|| self.runtime.block_on(future)).join()
+                    .unwrap_or_else(|panic| std::panic::resume_unwind(panic))
+            })
+        }
+    }
+
+    pub fn create<A: Agent + 'static>(agent: A) -> *mut c_void {
+        let runtime = match tokio::runtime::Builder::new_multi_thread().worker_threads(1).enable_all().build() {
+            Ok(runtime) => runtime,
+            Err(_) => return std::ptr::null_mut(),
+        };
+        Box::into_raw(Box::new(GuestAgent { agent: Box::new(agent), runtime })) as *mut c_void
+    }
+
+    /// # Safety
+    /// `handle` must come from `create` and not have been destroyed
+    pub unsafe fn config(handle: *mut c_void) -> *mut c_char {
+        let guest = &*(handle as *const GuestAgent);
+        guarded(|| guest.run(guest.agent.get_config()))
+    }
+
+    /// # Safety
+    /// `handle` must come from `create` and not have been destroyed
+    pub unsafe fn current_state(handle: *mut c_void) -> *mut c_char {
+        let guest = &*(handle as *const GuestAgent);
+        guarded(|| guest.run(guest.agent.get_current_state()))
+    }
+
+    /// # Safety
+    /// `handle` must come from `create` and not have been destroyed, and `message`
+    /// must be a NUL-terminated string
+    pub unsafe fn process_message(handle: *mut c_void, message: *const c_char) -> *mut c_char {
+        let guest = &*(handle as *const GuestAgent);
+        let message = CStr::from_ptr(message).to_string_lossy().into_owned();
+        guarded(|| {
+            let message: Message = serde_json::from_str(&message)?;
+            guest.run(guest.agent.process_message(message))
+        })
+    }
+
+    /// # Safety
+    /// `raw` must be a string returned by this plugin and not already freed
+    pub unsafe fn free_string(raw: *mut c_char) {
+        if !raw.is_null() {
+            drop(CString::from_raw(raw));
+        }
+    }
+
+    /// # Safety
+    /// `handle` must come from `create` and not be used afterwards
+    pub unsafe fn destroy(handle: *mut c_void) {
+        if !handle.is_null() {
+            let guest = Box::from_raw(handle as *mut GuestAgent);
+            // Dropping a runtime inside another runtime's thread panics
+            let GuestAgent { agent, runtime } = *guest;
+            drop(agent);
+            runtime.shutdown_background();
+        }
+    }
+}
+
+/// Export an `Agent` from a `cdylib` crate as a Swarmonomicon plugin.
+/// The expression is evaluated once per load to construct the agent.
+#[macro_export]
+macro_rules! export_agent_plugin {
+    ($agent:expr) => {
+        #[no_mangle]
+        pub extern "C" fn swarm_plugin_abi_version() -> u32 {
+            $crate::agents::plugin::PLUGIN_ABI_VERSION
+        }
+
+        #[no_mangle]
+        pub extern "C" fn swarm_plugin_create() -> *mut ::std::ffi::c_void {
+            $crate::agents::plugin::guest::create($agent)
+        }
+
+        #[no_mangle]
+        pub unsafe extern "C" fn swarm_plugin_config(handle: *mut ::std::ffi::c_void) -> *mut ::std::ffi::c_char {
+            $crate::agents::plugin::guest::config(handle)
+        }
+
+        #[no_mangle]
+        pub unsafe extern "C" fn swarm_plugin_current_state(handle: *mut ::std::ffi::c_void) -> *mut ::std::ffi::c_char {
+            $crate::agents::plugin::guest::current_state(handle)
+        }
+
+        #[no_mangle]
+        pub unsafe extern "C" fn swarm_plugin_process_message(
+            handle: *mut ::std::ffi::c_void,
+            message: *const ::std::ffi::c_char,
+        ) -> *mut ::std::ffi::c_char {
+            $crate::agents::plugin::guest::process_message(handle, message)
+        }
+
+        #[no_mangle]
+        pub unsafe extern "C" fn swarm_plugin_free_string(raw: *mut ::std::ffi::c_char) {
+            $crate::agents::plugin::guest::free_string(raw)
+        }
+
+        #[no_mangle]
+        pub unsafe extern "C" fn swarm_plugin_destroy(handle: *mut ::std::ffi::c_void) {
+            $crate::agents::plugin::guest::destroy(handle)
+        }
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    mod echo_plugin {
+        use crate::agents::UserAgent;
+        use crate::types::AgentConfig;
+
+        crate::export_agent_plugin!(UserAgent::new(AgentConfig {
+            name: "echo".to_string(),
+            public_description: "Echoes messages".to_string(),
+            instructions: String::new(),
+            tools: Vec::new(),
+            downstream_agents: Vec::new(),
+            personality: None,
+            state_machine: None,
+            script: None,
+            max_concurrency: None,
+            middleware: None,
+            remote: None,
+        }));
+    }
+
+    fn echo_vtable() -> PluginVTable {
+        PluginVTable {
+            abi_version: echo_plugin::swarm_plugin_abi_version,
+            create: echo_plugin::swarm_plugin_create,
+            config: echo_plugin::swarm_plugin_config,
+            current_state: echo_plugin::swarm_plugin_current_state,
+            process_message: echo_plugin::swarm_plugin_process_message,
+            free_string: echo_plugin::swarm_plugin_free_string,
+            destroy: echo_plugin::swarm_plugin_destroy,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_plugin_round_trip() -> Result<()> {
+        let plugin = PluginAgent::from_vtable(echo_vtable(), None)?;
+        assert_eq!(plugin.get_config().await?.name, "echo");
+        assert!(plugin.get_current_state().await?.is_none());
+
+        let response = plugin.process_message(Message::new("hello".to_string())).await?;
+        assert_eq!(response.content, "User received: hello");
+        Ok(())
+    }
+
+    #[test]
+    fn test_rejects_non_plugins() {
+        unsafe extern "C" fn future_version() -> u32 {
+            PLUGIN_ABI_VERSION + 1
+        }
+        let error = PluginAgent::from_vtable(PluginVTable { abi_version: future_version, ..echo_vtable() }, None).err().unwrap();
+        assert!(matches!(SwarmError::find(&error), Some(SwarmError::Validation(_))), "{}", error);
+
+        let dir = tempfile::tempdir().unwrap();
+        let fake = dir.path().join(format!("fake.{}", std::env::consts::DLL_EXTENSION));
+        std::fs::write(&fake, b"not a library").unwrap();
+        assert!(PluginAgent::load(&fake).is_err());
+        assert!(load_plugins(dir.path()).unwrap().is_empty());
+    }
+}
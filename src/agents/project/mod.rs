@@ -3,8 +3,8 @@ use std::path::Path;
 use std::process::Command;
 use std::collections::HashMap;
 use async_trait::async_trait;
-use crate::types::{Agent, AgentConfig, Message, MessageMetadata, Tool, ToolCall, State, TaskPriority};
-use crate::tools::ToolRegistry;
+use crate::types::{Agent, AgentConfig, Capability, Message, MessageMetadata, Tool, ToolCall, State, TaskPriority};
+use crate::tools::AgentToolset;
 use crate::ai::{AiProvider, DefaultAiClient};
 use crate::Result;
 use serde::{Deserialize, Serialize};
@@ -62,7 +62,7 @@ enum TaskStatus {
 
 pub struct ProjectAgent {
     config: AgentConfig,
-    tools: ToolRegistry,
+    tools: AgentToolset,
     current_state: Option<String>,
     ai_client: Arc<dyn AiProvider + Send + Sync>,
     background_tasks: Arc<RwLock<Vec<BackgroundTask>>>,
@@ -92,8 +92,8 @@ impl ProjectAgent {
         ];
 
         let agent = Self {
+            tools: AgentToolset::for_agent(&config),
             config,
-            tools: ToolRegistry::create_default_tools().await?,
             current_state: None,
             ai_client: Arc::new(DefaultAiClient::new()),
             background_tasks: Arc::new(RwLock::new(Vec::new())),
@@ -443,8 +443,7 @@ setup(
             return Err("Spindlewrit CLI not available. Please install it first.".into());
         }
 
-        // Get the GEMMA_API_KEY from environment
-        let api_key = std::env::var("GEMMA_API_KEY").ok();
+        let api_key = crate::secrets::get(crate::secrets::GEMMA_API_KEY);
         let api_key_arg = api_key.map(|key| format!("--api-key={}", key)).unwrap_or_default();
 
         // Run the spindlewrit command
@@ -608,13 +607,24 @@ impl Agent for ProjectAgent {
     }
 
     async fn call_tool(&self, tool: &Tool, params: HashMap<String, String>) -> AnyhowResult<String> {
-        self.tools.execute(tool, params).await.map_err(|e| anyhow!("{}", e))
+        self.tools.execute(tool, params).await
     }
 
     async fn get_config(&self) -> AnyhowResult<AgentConfig> {
         Ok(self.config.clone())
     }
 
+    async fn capabilities(&self) -> AnyhowResult<Vec<Capability>> {
+        Ok(vec![
+            Capability::new("init", self.config.public_description.clone())
+                .with_commands(self.config.tools.iter().map(|tool| tool.name.clone()))
+                .with_keywords(["project", "init", "create"]),
+            Capability::new("classify", "Picks the project a task belongs to from a ProjectClassificationRequest")
+                .with_input_formats(["json"])
+                .with_keywords(["classify"]),
+        ])
+    }
+
     async fn get_current_state(&self) -> AnyhowResult<Option<State>> {
         Ok(self.current_state.clone().map(|s| State {
             name: s,
@@ -622,6 +632,11 @@ impl Agent for ProjectAgent {
             prompt: None,
             transitions: None,
             validation: None,
+            guarded_transitions: None,
+            parent: None,
+            initial_child: None,
+            on_enter: None,
+            on_exit: None,
         }))
     }
 }
@@ -640,6 +655,10 @@ mod tests {
             downstream_agents: vec![],
             personality: None,
             state_machine: None,
+            script: None,
+            max_concurrency: None,
+            middleware: None,
+            remote: None,
         };
 
         let agent = ProjectAgent::new(config).await?;
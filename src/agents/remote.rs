@@ -0,0 +1,202 @@
+//! Agents that run on another Swarmonomicon server. A `RemoteAgent` forwards messages and
+//! tool calls to that server's `/api/agents/{name}` routes, so heavy agents such as browser
+//! automation can live on a separate machine while routing treats them like local ones.
+//!
+//! ```toml
+//! [[agents]]
+//! name = "browser"
+//! remote = { url = "http://gpu-box:3000", api_key_secret = "BROWSER_HOST_API_KEY" }
+//! ```
+
+use std::collections::HashMap;
+use std::time::Duration;
+use anyhow::Result;
+use async_trait::async_trait;
+use reqwest::{RequestBuilder, StatusCode};
+use serde::de::DeserializeOwned;
+use serde_json::{Value, json};
+use crate::api::ToolCallResponse;
+use crate::error::SwarmError;
+use crate::types::{Agent, AgentConfig, Capability, Message, RemoteAgentConfig, State, Tool};
+
+pub const DEFAULT_TIMEOUT_SECS: u64 = 120;
+
+pub struct RemoteAgent {
+    config: AgentConfig,
+    remote: RemoteAgentConfig,
+    api_key: Option<String>,
+    client: reqwest::Client,
+}
+
+impl RemoteAgent {
+    /// A proxy for the agent `config.remote` points at. Fails if its API key secret isn't set.
+    pub fn new(config: AgentConfig) -> Result<Self> {
+        let remote = config.remote.clone()
+            .ok_or_else(|| SwarmError::Validation(format!("Agent '{}' has no remote server", config.name)))?;
+        let api_key = remote.api_key_secret.as_deref().map(crate::secrets::require).transpose()?;
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_secs(remote.timeout_secs.unwrap_or(DEFAULT_TIMEOUT_SECS)))
+            .build()?;
+        Ok(Self { config, remote, api_key, client })
+    }
+
+    /// The agent's name on the remote server
+    fn remote_name(&self) -> &str {
+        self.remote.agent.as_deref().unwrap_or(&self.config.name)
+    }
+
+    fn url(&self, path: &str) -> String {
+        format!("{}/api/agents/{}{}", self.remote.url.trim_end_matches('/'), self.remote_name(), path)
+    }
+
+    async fn send<T: DeserializeOwned>(&self, mut request: RequestBuilder) -> Result<T> {
+        if let Some(key) = &self.api_key {
+            request = request.bearer_auth(key);
+        }
+        let unreachable = |e: reqwest::Error| SwarmError::Transfer(
+            format!("Remote agent {} at {} is unreachable: {}", self.remote_name(), self.remote.url, e)
+        );
+        let response = request.send().await.map_err(unreachable)?;
+        match response.status() {
+            status if status.is_success() => Ok(response.json().await.map_err(unreachable)?),
+            StatusCode::NOT_FOUND => Err(SwarmError::AgentNotFound(self.remote_name().to_string()).into()),
+            StatusCode::BAD_REQUEST => Err(SwarmError::Validation(
+                format!("Remote agent {} rejected the request", self.remote_name())
+            ).into()),
+            status => Err(SwarmError::Transfer(
+                format!("Remote agent {} at {} answered {}", self.remote_name(), self.remote.url, status)
+            ).into()),
+        }
+    }
+}
+
+#[async_trait]
+impl Agent for RemoteAgent {
+    async fn process_message(&self, message: Message) -> Result<Message> {
+        // The remote server refuses local file paths
+        let attachments: Vec<_> = message.attachments.iter().filter(|a| a.is_inline()).collect();
+        let body = json!({ "content": message.content, "attachments": attachments });
+        self.send(self.client.post(self.url("/message")).json(&body)).await
+    }
+
+    async fn transfer_to(&self, _target_agent: String, message: Message) -> Result<Message> {
+        Ok(message)
+    }
+
+    async fn call_tool(&self, tool: &Tool, params: HashMap<String, String>) -> Result<String> {
+        let request = self.client.post(self.url(&format!("/tools/{}", tool.name))).json(&json!({ "params": params }));
+        let response: ToolCallResponse = self.send(request).await
+            .map_err(|e| SwarmError::tool_failure(&tool.name, e))?;
+        Ok(response.output)
+    }
+
+    /// The state lives on the remote server
+    async fn get_current_state(&self) -> Result<Option<State>> {
+        Ok(None)
+    }
+
+    async fn get_config(&self) -> Result<AgentConfig> {
+        Ok(self.config.clone())
+    }
+
+    /// What the remote server advertises, or the configured description while it can't be reached
+    async fn capabilities(&self) -> Result<Vec<Capability>> {
+        let info: Result<Value> = self.send(self.client.get(self.url(""))).await;
+        let advertised = info.and_then(|info| Ok(serde_json::from_value::<Vec<Capability>>(info["capabilities"].clone())?));
+        match advertised {
+            Ok(capabilities) => Ok(capabilities),
+            Err(e) => {
+                tracing::warn!("Using configured capabilities for remote agent {}: {}", self.config.name, e);
+                Ok(vec![Capability::from_config(&self.config)])
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use axum::{Router, routing::{get, post}};
+    use tokio::sync::RwLock;
+    use crate::agents::{AgentRegistry, TransferService};
+    use crate::api::{self, AppState};
+    use crate::types::{AgentStateManager, MessageHandler};
+
+    #[derive(Agent)]
+    #[agent(call_tool = "run_tool")]
+    struct ShoutAgent {
+        config: AgentConfig,
+        state_manager: Arc<RwLock<AgentStateManager>>,
+    }
+
+    impl ShoutAgent {
+        async fn run_tool(&self, tool: &Tool, params: HashMap<String, String>) -> Result<String> {
+            match params.get("url") {
+                Some(url) => Ok(format!("{} {}", tool.name, url)),
+                None => Err(SwarmError::Validation("url is required".to_string()).into()),
+            }
+        }
+    }
+
+    #[async_trait]
+    impl MessageHandler for ShoutAgent {
+        async fn handle_message(&self, message: Message) -> Result<Message> {
+            Ok(Message::new(message.content.to_uppercase()))
+        }
+    }
+
+    /// A server running `browser-host`, whose URL is returned
+    async fn serve_remote() -> Result<String> {
+        let mut registry = AgentRegistry::new();
+        let config = AgentConfig::builder("browser-host").description("Drives a browser").build()?;
+        let agent = ShoutAgent { config, state_manager: Arc::new(RwLock::new(AgentStateManager::new(None))) };
+        registry.register("browser-host".to_string(), Box::new(agent)).await?;
+        let registry = Arc::new(RwLock::new(registry));
+        let mut state = AppState::new(Arc::new(RwLock::new(TransferService::new(registry.clone()))));
+        state.agents = registry;
+
+        let app = Router::new()
+            .route("/api/agents/:name", get(api::get_agent))
+            .route("/api/agents/:name/message", post(api::process_message))
+            .route("/api/agents/:name/tools/:tool", post(api::call_agent_tool))
+            .with_state(Arc::new(state));
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+        let url = format!("http://{}", listener.local_addr()?);
+        tokio::spawn(async move { axum::serve(listener, app).await });
+        Ok(url)
+    }
+
+    #[tokio::test]
+    async fn test_messages_and_tool_calls_reach_the_remote_agent() -> Result<()> {
+        let url = serve_remote().await?;
+        let remote = RemoteAgentConfig { agent: Some("browser-host".to_string()), ..RemoteAgentConfig::new(url) };
+        let agent = RemoteAgent::new(AgentConfig::builder("browser").with_remote(remote).build()?)?;
+
+        assert_eq!(agent.process_message(Message::new("open the page".to_string())).await?.content, "OPEN THE PAGE");
+        let params = HashMap::from([("url".to_string(), "example.com".to_string())]);
+        assert_eq!(agent.call_tool(&Tool::new("navigate", "Open a URL"), params).await?, "navigate example.com");
+        let err = agent.call_tool(&Tool::new("navigate", "Open a URL"), HashMap::new()).await.unwrap_err();
+        assert!(matches!(SwarmError::find(&err), Some(SwarmError::ToolFailure { .. })), "{}", err);
+        assert_eq!(agent.capabilities().await?[0].description, "Drives a browser");
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_missing_and_unreachable_remotes_fail() -> Result<()> {
+        let url = serve_remote().await?;
+        let agent = RemoteAgent::new(AgentConfig::builder("ghost").with_remote(RemoteAgentConfig::new(url)).build()?)?;
+        let err = agent.process_message(Message::new("hi".to_string())).await.unwrap_err();
+        assert!(matches!(SwarmError::find(&err), Some(SwarmError::AgentNotFound(name)) if name == "ghost"));
+
+        let config = AgentConfig::builder("browser")
+            .description("Offline browser")
+            .with_remote(RemoteAgentConfig::new("http://127.0.0.1:9"))
+            .build()?;
+        let agent = RemoteAgent::new(config)?;
+        let err = agent.process_message(Message::new("hi".to_string())).await.unwrap_err();
+        assert!(matches!(SwarmError::find(&err), Some(SwarmError::Transfer(_))), "{}", err);
+        assert_eq!(agent.capabilities().await?[0].description, "Offline browser");
+        Ok(())
+    }
+}
@@ -0,0 +1,254 @@
+//! Agents whose message handling is a Rhai script, for routing and formatting agents
+//! that don't warrant Rust code.
+//!
+//! The script named by `AgentConfig.script` defines `fn process_message(message)`, where
+//! `message` is a map with `content`, `role` and `from` (the sending agent, if known).
+//! It returns either the reply text or a map with `content` and optionally `transfer_to`.
+//!
+//! Host functions available to scripts:
+//! - `call_tool(name, params)`: run one of the agent's configured tools with a map of parameters
+//! - `transfer(agent)`: hand the reply to another agent
+//! - `state()`: the current state name, or `()` without a state machine
+//! - `transition(event)`: fire a state machine event, returning whether a transition happened
+//! - `context(key)` / `set_context(key, value)`: values kept across messages
+//!
+//! `print` goes to the log. Each call is limited to `MAX_OPERATIONS` so a looping script
+//! fails instead of hanging a worker.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use anyhow::{Result, anyhow};
+use async_trait::async_trait;
+use rhai::{AST, Dynamic, Engine, EvalAltResult, Map, Scope};
+use crate::error::SwarmError;
+use crate::tools::AgentToolset;
+use crate::types::{Agent, AgentConfig, AgentStateManager, Message, MessageMetadata, State, Tool};
+
+/// Operations a script may perform per message
+pub const MAX_OPERATIONS: u64 = 1_000_000;
+
+/// An agent that delegates `process_message` to a Rhai script
+pub struct ScriptedAgent {
+    config: AgentConfig,
+    ast: Arc<AST>,
+    tools: AgentToolset,
+    state_manager: Arc<Mutex<AgentStateManager>>,
+}
+
+impl ScriptedAgent {
+    /// Load and compile the script at `config.script`
+    pub fn new(config: AgentConfig) -> Result<Self> {
+        let path = config.script.clone()
+            .ok_or_else(|| SwarmError::Validation(format!("Agent '{}' has no script", config.name)))?;
+        let source = std::fs::read_to_string(&path)
+            .map_err(|e| anyhow!("Failed to read script {} for agent '{}': {}", path, config.name, e))?;
+        Self::from_source(config, &source)
+    }
+
+    /// Compile `source` directly, ignoring `config.script`
+    pub fn from_source(config: AgentConfig, source: &str) -> Result<Self> {
+        let ast = Engine::new().compile(source)
+            .map_err(|e| SwarmError::Validation(format!("Invalid script for agent '{}': {}", config.name, e)))?;
+        if !ast.iter_functions().any(|f| f.name == "process_message" && f.params.len() == 1) {
+            return Err(SwarmError::Validation(
+                format!("Script for agent '{}' does not define process_message(message)", config.name)
+            ).into());
+        }
+        Ok(Self {
+            tools: AgentToolset::for_agent(&config),
+            state_manager: Arc::new(Mutex::new(AgentStateManager::new(config.state_machine.clone()))),
+            ast: Arc::new(ast),
+            config,
+        })
+    }
+
+    /// Run tool calls against `tools` instead of the global registry
+    pub fn with_toolset(mut self, tools: AgentToolset) -> Self {
+        self.tools = tools;
+        self
+    }
+
+    /// An engine whose host functions act on this agent; `transfer` writes to `transfer_target`
+    fn engine(&self, transfer_target: Arc<Mutex<Option<String>>>) -> Engine {
+        let mut engine = Engine::new();
+        engine.set_max_operations(MAX_OPERATIONS);
+        let name = self.config.name.clone();
+        engine.on_print(move |text| tracing::info!("script agent '{}': {}", name, text));
+
+        let tools = self.tools.clone();
+        let configured = self.config.tools.clone();
+        let runtime = tokio::runtime::Handle::current();
+        engine.register_fn("call_tool", move |tool: &str, params: Map| -> Result<String, Box<EvalAltResult>> {
+            let tool = configured.iter().find(|t| t.name == tool).cloned().unwrap_or_else(|| Tool {
+                name: tool.to_string(),
+                description: String::new(),
+                parameters: HashMap::new(),
+            });
+            let params = params.into_iter().map(|(k, v)| (k.to_string(), v.to_string())).collect();
+            runtime.block_on(tools.execute(&tool, params)).map_err(|e| e.to_string().into())
+        });
+
+        engine.register_fn("transfer", move |target: &str| {
+            *transfer_target.lock().unwrap() = Some(target.to_string());
+        });
+
+        let state_manager = self.state_manager.clone();
+        engine.register_fn("state", move || -> Dynamic {
+            state_manager.lock().unwrap().get_current_state_name().map_or(Dynamic::UNIT, |s| s.to_string().into())
+        });
+        let state_manager = self.state_manager.clone();
+        engine.register_fn("transition", move |event: &str| {
+            state_manager.lock().unwrap().transition(event).is_some()
+        });
+        let state_manager = self.state_manager.clone();
+        engine.register_fn("context", move |key: &str| -> Dynamic {
+            state_manager.lock().unwrap().context().get(key).cloned().map_or(Dynamic::UNIT, Dynamic::from)
+        });
+        let state_manager = self.state_manager.clone();
+        engine.register_fn("set_context", move |key: &str, value: Dynamic| {
+            state_manager.lock().unwrap().set_context(key.to_string(), value.to_string());
+        });
+        engine
+    }
+}
+
+fn to_script_message(message: &Message) -> Map {
+    let mut map = Map::new();
+    map.insert("content".into(), message.content.clone().into());
+    map.insert("role".into(), message.role.clone().map_or(Dynamic::UNIT, Dynamic::from));
+    map.insert("from".into(), message.metadata.as_ref().map_or(Dynamic::UNIT, |m| m.agent.clone().into()));
+    map
+}
+
+/// The reply text and any transfer target named in a returned map
+fn from_script_reply(reply: Dynamic) -> Result<(String, Option<String>)> {
+    if reply.is_string() {
+        return Ok((reply.into_string().unwrap_or_default(), None));
+    }
+    let Some(map) = reply.try_cast::<Map>() else {
+        return Err(anyhow!("process_message must return a string or a map"));
+    };
+    let field = |key: &str| map.get(key).filter(|v| !v.is_unit()).map(|v| v.to_string());
+    Ok((field("content").unwrap_or_default(), field("transfer_to")))
+}
+
+#[async_trait]
+impl Agent for ScriptedAgent {
+    async fn process_message(&self, message: Message) -> Result<Message> {
+        let transfer_target = Arc::new(Mutex::new(None));
+        let engine = self.engine(transfer_target.clone());
+        let ast = self.ast.clone();
+        let input = to_script_message(&message);
+        let (content, returned_target) = tokio::task::spawn_blocking(move || {
+            engine.call_fn::<Dynamic>(&mut Scope::new(), &ast, "process_message", (input,))
+        }).await?
+            .map_err(|e| anyhow!("{}", e))
+            .and_then(from_script_reply)
+            .map_err(|e| SwarmError::Agent(format!("Script agent '{}' failed: {}", self.config.name, e)))?;
+
+        let mut metadata = MessageMetadata::new(self.config.name.clone());
+        metadata.state = self.state_manager.lock().unwrap().get_current_state_name().map(str::to_string);
+        if let Some(target) = returned_target.or_else(|| transfer_target.lock().unwrap().take()) {
+            metadata = metadata.with_transfer_target(target);
+        }
+        Ok(Message::new(content).with_metadata(metadata))
+    }
+
+    async fn transfer_to(&self, _target_agent: String, message: Message) -> Result<Message> {
+        Ok(message)
+    }
+
+    async fn call_tool(&self, tool: &Tool, params: HashMap<String, String>) -> Result<String> {
+        self.tools.execute(tool, params).await
+    }
+
+    async fn get_current_state(&self) -> Result<Option<State>> {
+        Ok(self.state_manager.lock().unwrap().get_current_state().cloned())
+    }
+
+    async fn get_config(&self) -> Result<AgentConfig> {
+        Ok(self.config.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::sync::RwLock;
+    use crate::tools::{ToolExecutor, ToolRegistry};
+
+    struct Shout;
+
+    #[async_trait]
+    impl ToolExecutor for Shout {
+        async fn execute(&self, params: HashMap<String, String>) -> Result<String> {
+            Ok(params.get("text").cloned().unwrap_or_default().to_uppercase())
+        }
+    }
+
+    fn config(tools: Vec<Tool>) -> AgentConfig {
+        AgentConfig {
+            name: "router".to_string(),
+            public_description: "Routes by keyword".to_string(),
+            instructions: String::new(),
+            tools,
+            downstream_agents: vec!["haiku".to_string()],
+            personality: None,
+            state_machine: None,
+            script: None,
+            max_concurrency: None,
+            middleware: None,
+            remote: None,
+        }
+    }
+
+    const ROUTER: &str = r#"
+        fn process_message(message) {
+            if message.content.contains("poem") {
+                transfer("haiku");
+                return "Passing you to haiku";
+            }
+            let count = context("count") ?? "0";
+            set_context("count", parse_int(count) + 1);
+            #{ content: call_tool("shout", #{ text: message.content }) + " #" + context("count") }
+        }
+    "#;
+
+    #[tokio::test]
+    async fn test_script_routes_and_calls_tools() -> Result<()> {
+        let shout = Tool { name: "shout".to_string(), description: "Uppercase text".to_string(), parameters: HashMap::new() };
+        let config = config(vec![shout]);
+        let mut registry = ToolRegistry::new();
+        registry.register("shout".to_string(), Shout);
+        let agent = ScriptedAgent::from_source(config.clone(), ROUTER)?
+            .with_toolset(AgentToolset::new(&config, Arc::new(RwLock::new(registry))));
+
+        let reply = agent.process_message(Message::new("write a poem".to_string())).await?;
+        assert_eq!(reply.content, "Passing you to haiku");
+        assert_eq!(reply.metadata.unwrap().transfer_target.as_deref(), Some("haiku"));
+
+        let reply = agent.process_message(Message::new("hello".to_string())).await?;
+        assert_eq!(reply.content, "HELLO #1");
+        assert!(reply.metadata.unwrap().transfer_target.is_none());
+        assert_eq!(agent.process_message(Message::new("again".to_string())).await?.content, "AGAIN #2");
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_script_errors_are_reported() -> Result<()> {
+        let error = ScriptedAgent::from_source(config(vec![]), "fn respond(m) { m }").err().unwrap();
+        assert!(matches!(SwarmError::find(&error), Some(SwarmError::Validation(_))), "{}", error);
+
+        let agent = ScriptedAgent::from_source(config(vec![]), r#"
+            fn process_message(message) {
+                if message.content == "tool" { return call_tool("shout", #{}); }
+                loop {}
+            }
+        "#)?;
+        for content in ["tool", "spin"] {
+            let error = agent.process_message(Message::new(content.to_string())).await.unwrap_err();
+            assert!(matches!(SwarmError::find(&error), Some(SwarmError::Agent(_))), "{}", error);
+        }
+        Ok(())
+    }
+}
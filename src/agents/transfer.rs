@@ -1,26 +1,75 @@
 use std::sync::Arc;
 use tokio::sync::RwLock;
 use crate::{
-    types::{Message, Agent},
-    error::Error,
+    types::{Message, Agent, sessions, state_store::{InMemoryStateStore, StateStore}},
+    error::{Error, SwarmError},
     agents::AgentRegistry,
+    events::{self, DomainEvent},
+    mesh::AgentMesh,
 };
 use anyhow::{Result, anyhow};
+use tracing::Instrument;
 
+/// Routes messages to the agent handling their conversation. Messages with a session id
+/// (see `sessions::message_session_id`) follow that session's route, kept in the session
+/// store, so concurrent conversations don't move each other between agents. Messages
+/// without one, and sessions not yet routed, go to the registry's current agent. With a
+/// mesh, agents registered on other nodes can be routed to as well.
 pub struct TransferService {
     registry: Arc<RwLock<AgentRegistry>>,
+    sessions: Arc<dyn StateStore>,
+    mesh: Option<Arc<AgentMesh>>,
 }
 
 impl TransferService {
     pub fn new(registry: Arc<RwLock<AgentRegistry>>) -> Self {
-        Self { registry }
+        Self { registry, sessions: Arc::new(InMemoryStateStore::new()), mesh: None }
+    }
+
+    /// Keep session routes in `store`, e.g. `MongoStateStore` to share them between servers
+    pub fn with_session_store(mut self, store: Arc<dyn StateStore>) -> Self {
+        self.sessions = store;
+        self
+    }
+
+    /// Where session routes are kept
+    pub fn session_store(&self) -> Arc<dyn StateStore> {
+        self.sessions.clone()
+    }
+
+    /// Route to agents on other nodes of `mesh` when they aren't registered here
+    pub fn with_mesh(mut self, mesh: Arc<AgentMesh>) -> Self {
+        self.mesh = Some(mesh);
+        self
     }
 
     pub async fn process_message(&self, message: Message) -> Result<Message> {
-        let registry = self.registry.read().await;
-        let current_agent = self.get_current_agent_name().await?;
-        let agent = self.get_agent(&current_agent).await?;
-        agent.process_message(message).await
+        let session = sessions::message_session_id(&message);
+        let current_agent = self.current_agent_for(session.as_deref()).await?;
+        events::global().publish(DomainEvent::message_routed(None, &current_agent));
+        self.dispatch(&current_agent, message).await
+    }
+
+    /// Have `name` process `message`, locally if it is registered here and through the mesh otherwise
+    async fn dispatch(&self, name: &str, message: Message) -> Result<Message> {
+        let span = tracing::info_span!("agent", agent = name);
+        if !self.registry.read().await.exists(name) {
+            if let Some(mesh) = &self.mesh {
+                return mesh.request(name, message).instrument(span).await;
+            }
+        }
+        self.get_agent(name).await?.process_message(message).instrument(span).await
+    }
+
+    /// Whether `name` is registered here or on another node of the mesh
+    async fn is_routable(&self, name: &str) -> bool {
+        if self.registry.read().await.exists(name) {
+            return true;
+        }
+        match &self.mesh {
+            Some(mesh) => mesh.node_for(name).await.is_some(),
+            None => false,
+        }
     }
 
     pub async fn transfer(&self, from: &str, to: &str, message: Message) -> Result<Message> {
@@ -28,12 +77,12 @@ impl TransferService {
         {
             let registry = self.registry.read().await;
             if registry.get(from).is_none() {
-                return Err(anyhow!("Source agent '{}' not found", from));
-            }
-            if registry.get(to).is_none() {
-                return Err(anyhow!("Target agent '{}' not found", to));
+                return Err(SwarmError::AgentNotFound(from.to_string()).into());
             }
         } // registry read lock is dropped here
+        if !self.is_routable(to).await {
+            return Err(SwarmError::AgentNotFound(to.to_string()).into());
+        }
 
         // Get the source agent and perform the transfer
         let source_agent = {
@@ -42,10 +91,17 @@ impl TransferService {
         };
 
         // Perform the transfer
-        let result = source_agent.transfer_to(to.to_string(), message).await?;
+        let session = sessions::message_session_id(&message);
+        let result = if self.registry.read().await.exists(to) {
+            source_agent.transfer_to(to.to_string(), message).await?
+        } else {
+            // Agents on other nodes take the message itself
+            self.dispatch(to, message).await?
+        };
+        events::global().publish(DomainEvent::message_routed(Some(from), to));
 
-        // Update the current agent
-        self.set_current_agent_name(to).await?;
+        // Only the conversation that asked for the transfer moves
+        self.set_current_agent_for(session.as_deref(), to).await?;
 
         Ok(result)
     }
@@ -54,24 +110,53 @@ impl TransferService {
         let registry = self.registry.read().await;
         registry.get(name)
             .map(|wrapper| Arc::new(Box::new(wrapper.clone()) as Box<dyn Agent + Send + Sync>))
-            .ok_or_else(|| anyhow!("Agent '{}' not found", name))
+            .ok_or_else(|| SwarmError::AgentNotFound(name.to_string()).into())
+    }
+
+    /// The agent handling `session`, falling back to the current agent for unrouted
+    /// sessions and routes to agents no longer registered
+    pub async fn current_agent_for(&self, session: Option<&str>) -> Result<String> {
+        if let Some(session) = session {
+            if let Some(agent) = sessions::load_route(self.sessions.as_ref(), session).await? {
+                if self.is_routable(&agent).await {
+                    return Ok(agent);
+                }
+                tracing::warn!("Session {} was routed to unknown agent {}", session, agent);
+            }
+        }
+        self.get_current_agent_name().await
+    }
+
+    /// Route `session` to `target`; without a session, change the current agent
+    pub async fn set_current_agent_for(&self, session: Option<&str>, target: &str) -> Result<()> {
+        let Some(session) = session else {
+            return self.set_current_agent_name(target).await;
+        };
+        if !self.is_routable(target).await {
+            return Err(SwarmError::AgentNotFound(target.to_string()).into());
+        }
+        sessions::save_route(self.sessions.as_ref(), session, target).await
+    }
+
+    /// Forget the route of a finished conversation
+    pub async fn end_session(&self, session: &str) -> Result<()> {
+        self.sessions.remove(sessions::ROUTING_AGENT, session).await
     }
 
+    /// The agent new conversations start with
     pub async fn get_current_agent_name(&self) -> Result<String> {
         let registry = self.registry.read().await;
         registry.get_current_agent()
             .map(|s| s.to_string())
-            .ok_or_else(|| anyhow!("No current agent set"))
+            .ok_or_else(|| SwarmError::Transfer("No current agent set".to_string()).into())
     }
 
     pub async fn set_current_agent_name(&self, target: &str) -> Result<()> {
-        let mut registry = self.registry.write().await;
-        if registry.get(target).is_some() {
-            registry.set_current_agent(target.to_string());
-            Ok(())
-        } else {
-            Err(anyhow!("Target agent '{}' not found", target))
+        if !self.is_routable(target).await {
+            return Err(SwarmError::AgentNotFound(target.to_string()).into());
         }
+        self.registry.write().await.set_current_agent(target.to_string());
+        Ok(())
     }
 }
 
@@ -92,6 +177,10 @@ mod tests {
             downstream_agents: vec!["test_target".to_string()],
             personality: None,
             state_machine: None,
+            script: None,
+            max_concurrency: None,
+            middleware: None,
+            remote: None,
         });
 
         registry.register("test_greeter".to_string(), Box::new(agent)).await.unwrap();
@@ -104,6 +193,24 @@ mod tests {
 
         // Test manual transfer
         let result = service.transfer("test_greeter", "nonexistent", Message::new("transfer to nonexistent".to_string())).await;
-        assert!(result.is_err());
+        assert!(matches!(SwarmError::find(&result.unwrap_err()), Some(SwarmError::AgentNotFound(name)) if name == "nonexistent"));
+    }
+
+    #[tokio::test]
+    async fn test_sessions_fall_back_to_the_current_agent() -> Result<()> {
+        let store = Arc::new(InMemoryStateStore::new());
+        let service = TransferService::new(Arc::new(RwLock::new(AgentRegistry::new())))
+            .with_session_store(store.clone());
+
+        // Routes to agents that aren't registered are refused, or ignored if stored earlier
+        let result = service.set_current_agent_for(Some("alice"), "haiku").await;
+        assert!(matches!(SwarmError::find(&result.unwrap_err()), Some(SwarmError::AgentNotFound(_))));
+        sessions::save_route(store.as_ref(), "alice", "removed").await?;
+        let result = service.current_agent_for(Some("alice")).await;
+        assert!(matches!(SwarmError::find(&result.unwrap_err()), Some(SwarmError::Transfer(_))));
+
+        service.end_session("alice").await?;
+        assert_eq!(sessions::load_route(store.as_ref(), "alice").await?, None);
+        Ok(())
     }
 }
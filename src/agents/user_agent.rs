@@ -1,8 +1,7 @@
-use std::fs;
-use std::path::Path;
 use serde::{Serialize, Deserialize};
 use chrono::{DateTime, Utc};
 use crate::types::{Agent, AgentConfig, Message, Tool, State};
+use crate::types::state_store::{self, StateStore};
 use anyhow::Result;
 use crate::error::Error;
 use std::collections::HashMap;
@@ -39,30 +38,19 @@ pub struct UserAgent {
     pub config: AgentConfig,
 }
 
+/// Session the user agent's state is kept under in the state store
+pub const USER_STATE_SESSION: &str = "user";
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct UserAgentState {
     pub config: AgentConfig,
     pub state: String,
-    pub state_file: String,
     todos: Vec<TodoItem>,
     last_processed: Option<DateTime<Utc>>,
 }
 
 impl UserAgent {
     pub fn new(config: AgentConfig) -> Self {
-        let state_file = format!("{}.json", config.name);
-        let state = if Path::new(&state_file).exists() {
-            serde_json::from_str(&fs::read_to_string(&state_file).unwrap()).unwrap()
-        } else {
-            UserAgentState {
-                config: config.clone(),
-                state: String::new(),
-                state_file: state_file.clone(),
-                todos: Vec::new(),
-                last_processed: None,
-            }
-        };
-
         Self {
             id: Uuid::new_v4().to_string(),
             name: config.name.clone(),
@@ -125,20 +113,32 @@ impl UserAgent {
 }
 
 impl UserAgentState {
+    pub fn new(config: AgentConfig) -> Self {
+        Self {
+            config,
+            state: String::new(),
+            todos: Vec::new(),
+            last_processed: None,
+        }
+    }
+
+    /// The agent's saved state, or a fresh one if nothing was saved yet
+    pub async fn load(store: &dyn StateStore, config: AgentConfig) -> Result<Self> {
+        let saved = state_store::load(store, &config.name, USER_STATE_SESSION).await?;
+        Ok(saved.unwrap_or_else(|| Self::new(config)))
+    }
+
+    pub async fn save(&self, store: &dyn StateStore) -> Result<()> {
+        state_store::save(store, &self.config.name, USER_STATE_SESSION, self).await
+    }
+
     pub fn get_last_processed(&self) -> Option<DateTime<Utc>> {
         self.last_processed
     }
 
-    pub fn update_last_processed(&mut self) -> Result<()> {
+    pub async fn update_last_processed(&mut self, store: &dyn StateStore) -> Result<()> {
         self.last_processed = Some(Utc::now());
-        self.save_state()?;
-        Ok(())
-    }
-
-    fn save_state(&self) -> Result<()> {
-        let contents = serde_json::to_string_pretty(&self)?;
-        fs::write(&self.state_file, contents)?;
-        Ok(())
+        self.save(store).await
     }
 }
 
@@ -0,0 +1,260 @@
+//! Agents scripted as WebAssembly modules and run in a sandbox.
+//!
+//! A guest module exports:
+//! - `memory`
+//! - `alloc(len: i32) -> i32`: space for the host to write a request into
+//! - `get_config() -> i64`: the agent's config as JSON
+//! - `process_message(ptr: i32, len: i32) -> i64`: takes a `Message` as JSON, returns a reply
+//!
+//! Results are packed as `(ptr << 32) | len` pointing at UTF-8 JSON in guest memory. The
+//! config needs only `name`; a reply is `{"content": ..., "transfer_target": ..., "error": ...}`
+//! with every field optional. Guests may import `env.log(ptr, len)` to write to the host log.
+//! There is no WASI: guests can't touch files, the network or the clock, and each call is
+//! limited in fuel and memory.
+
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use anyhow::{Result, anyhow};
+use async_trait::async_trait;
+use serde::Deserialize;
+use wasmtime::{Caller, Config, Engine, Instance, Linker, Memory, Module, Store, StoreLimits, StoreLimitsBuilder, TypedFunc};
+use crate::error::SwarmError;
+use crate::types::{Agent, AgentConfig, Message, MessageMetadata, State, Tool};
+
+/// Directory scanned for `.wasm` agents at startup
+pub const WASM_AGENT_DIR_ENV: &str = "SWARM_WASM_AGENT_DIR";
+
+/// Resources a guest may use per call
+#[derive(Debug, Clone, PartialEq)]
+pub struct WasmLimits {
+    /// Roughly one unit per executed instruction
+    pub fuel: u64,
+    pub max_memory_bytes: usize,
+}
+
+impl Default for WasmLimits {
+    fn default() -> Self {
+        Self {
+            fuel: 100_000_000,
+            max_memory_bytes: 64 * 1024 * 1024,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct GuestConfig {
+    name: String,
+    #[serde(default)]
+    public_description: String,
+    #[serde(default)]
+    instructions: String,
+    #[serde(default)]
+    downstream_agents: Vec<String>,
+    #[serde(default)]
+    personality: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct GuestReply {
+    #[serde(default)]
+    content: String,
+    transfer_target: Option<String>,
+    error: Option<String>,
+}
+
+struct Guest {
+    store: Store<StoreLimits>,
+    memory: Memory,
+    alloc: TypedFunc<i32, i32>,
+    get_config: TypedFunc<(), i64>,
+    process_message: TypedFunc<(i32, i32), i64>,
+}
+
+impl Guest {
+    fn instantiate(engine: &Engine, module: &Module, limits: &WasmLimits) -> Result<Self> {
+        let mut linker = Linker::new(engine);
+        linker.func_wrap("env", "log", |mut caller: Caller<'_, StoreLimits>, ptr: i32, len: i32| {
+            if let Some(memory) = caller.get_export("memory").and_then(|e| e.into_memory()) {
+                if let Ok(text) = read_str(memory.data(&caller), ptr, len) {
+                    tracing::info!("wasm agent: {}", text);
+                }
+            }
+        })?;
+
+        let store_limits = StoreLimitsBuilder::new().memory_size(limits.max_memory_bytes).build();
+        let mut store = Store::new(engine, store_limits);
+        store.limiter(|limits| limits);
+        store.set_fuel(limits.fuel)?;
+        let instance: Instance = linker.instantiate(&mut store, module)?;
+        Ok(Self {
+            memory: instance.get_memory(&mut store, "memory").ok_or_else(|| anyhow!("Guest does not export memory"))?,
+            alloc: instance.get_typed_func(&mut store, "alloc")?,
+            get_config: instance.get_typed_func(&mut store, "get_config")?,
+            process_message: instance.get_typed_func(&mut store, "process_message")?,
+            store,
+        })
+    }
+
+    fn read(&self, packed: i64) -> Result<String> {
+        let (ptr, len) = ((packed >> 32) as i32, packed as i32);
+        read_str(self.memory.data(&self.store), ptr, len).map(str::to_string)
+    }
+
+    fn config(&mut self, fuel: u64) -> Result<GuestConfig> {
+        self.store.set_fuel(fuel)?;
+        let packed = self.get_config.call(&mut self.store, ())?;
+        Ok(serde_json::from_str(&self.read(packed)?)?)
+    }
+
+    fn process(&mut self, request: &str, fuel: u64) -> Result<GuestReply> {
+        self.store.set_fuel(fuel)?;
+        let len = i32::try_from(request.len()).map_err(|_| anyhow!("Message too large for wasm guest"))?;
+        let ptr = self.alloc.call(&mut self.store, len)?;
+        self.memory.write(&mut self.store, ptr as usize, request.as_bytes())?;
+        let packed = self.process_message.call(&mut self.store, (ptr, len))?;
+        Ok(serde_json::from_str(&self.read(packed)?)?)
+    }
+}
+
+fn read_str(memory: &[u8], ptr: i32, len: i32) -> Result<&str> {
+    let start = usize::try_from(ptr)?;
+    let bytes = memory.get(start..start + usize::try_from(len)?)
+        .ok_or_else(|| anyhow!("Guest pointer out of bounds"))?;
+    Ok(std::str::from_utf8(bytes)?)
+}
+
+/// An agent implemented by a sandboxed WebAssembly module
+pub struct WasmAgent {
+    guest: Arc<Mutex<Guest>>,
+    config: AgentConfig,
+    limits: WasmLimits,
+}
+
+impl WasmAgent {
+    /// Compile and instantiate a module from `.wasm` bytes or `.wat` text
+    pub fn from_bytes(bytes: &[u8], limits: WasmLimits) -> Result<Self> {
+        let engine = Engine::new(Config::new().consume_fuel(true))?;
+        let module = Module::new(&engine, bytes)?;
+        let mut guest = Guest::instantiate(&engine, &module, &limits)?;
+        let GuestConfig { name, public_description, instructions, downstream_agents, personality } = guest.config(limits.fuel)
+            .map_err(|e| SwarmError::Validation(format!("Invalid wasm agent config: {}", e)))?;
+        let config = AgentConfig {
+            name,
+            public_description,
+            instructions,
+            tools: Vec::new(),
+            downstream_agents,
+            personality,
+            state_machine: None,
+            script: None,
+            max_concurrency: None,
+            middleware: None,
+            remote: None,
+        };
+        Ok(Self { guest: Arc::new(Mutex::new(guest)), config, limits })
+    }
+
+    pub fn from_file(path: impl AsRef<Path>, limits: WasmLimits) -> Result<Self> {
+        let path = path.as_ref();
+        let bytes = std::fs::read(path).map_err(|e| anyhow!("Failed to read wasm agent {}: {}", path.display(), e))?;
+        Self::from_bytes(&bytes, limits)
+    }
+}
+
+#[async_trait]
+impl Agent for WasmAgent {
+    async fn process_message(&self, message: Message) -> Result<Message> {
+        let request = serde_json::to_string(&message)?;
+        let guest = self.guest.clone();
+        let fuel = self.limits.fuel;
+        let reply = tokio::task::spawn_blocking(move || guest.lock().unwrap().process(&request, fuel)).await?
+            .map_err(|e| SwarmError::Agent(format!("Wasm agent '{}' failed: {}", self.config.name, e)))?;
+        if let Some(error) = reply.error {
+            return Err(SwarmError::Agent(error).into());
+        }
+
+        let mut metadata = MessageMetadata::new(self.config.name.clone());
+        if let Some(target) = reply.transfer_target {
+            metadata = metadata.with_transfer_target(target);
+        }
+        Ok(Message::new(reply.content).with_metadata(metadata))
+    }
+
+    async fn transfer_to(&self, _target_agent: String, message: Message) -> Result<Message> {
+        Ok(message)
+    }
+
+    async fn call_tool(&self, tool: &Tool, _params: HashMap<String, String>) -> Result<String> {
+        Err(SwarmError::tool_failure(&tool.name, "Wasm agents do not support direct tool calls").into())
+    }
+
+    async fn get_current_state(&self) -> Result<Option<State>> {
+        Ok(None)
+    }
+
+    async fn get_config(&self) -> Result<AgentConfig> {
+        Ok(self.config.clone())
+    }
+}
+
+/// Load every `.wasm` agent in `dir`. Modules that fail to load are logged and skipped.
+pub fn load_wasm_agents(dir: impl AsRef<Path>, limits: &WasmLimits) -> Result<Vec<WasmAgent>> {
+    let dir = dir.as_ref();
+    let mut agents = Vec::new();
+    for entry in std::fs::read_dir(dir).map_err(|e| anyhow!("Failed to read wasm agent directory {}: {}", dir.display(), e))? {
+        let path = entry?.path();
+        if path.extension().and_then(|e| e.to_str()) != Some("wasm") {
+            continue;
+        }
+        match WasmAgent::from_file(&path, limits.clone()) {
+            Ok(agent) => {
+                tracing::info!("Loaded wasm agent '{}' from {}", agent.config.name, path.display());
+                agents.push(agent);
+            }
+            Err(e) => tracing::warn!("Skipping wasm agent {}: {}", path.display(), e),
+        }
+    }
+    Ok(agents)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Answers every message with a fixed reply that transfers to haiku
+    const DUCK: &str = r#"
+        (module
+          (memory (export "memory") 1)
+          (data (i32.const 0) "{\"name\":\"duck\",\"public_description\":\"Quacks\"}")
+          (data (i32.const 64) "{\"content\":\"quack\",\"transfer_target\":\"haiku\"}")
+          (func (export "alloc") (param i32) (result i32) (i32.const 1024))
+          (func (export "get_config") (result i64) (i64.const 45))
+          (func (export "process_message") (param i32 i32) (result i64)
+            (i64.or (i64.shl (i64.const 64) (i64.const 32)) (i64.const 45))))
+    "#;
+
+    #[tokio::test]
+    async fn test_wasm_agent_replies() -> Result<()> {
+        let agent = WasmAgent::from_bytes(DUCK.as_bytes(), WasmLimits::default())?;
+        let config = agent.get_config().await?;
+        assert_eq!((config.name.as_str(), config.public_description.as_str()), ("duck", "Quacks"));
+
+        let reply = agent.process_message(Message::new("hello".to_string())).await?;
+        assert_eq!(reply.content, "quack");
+        assert_eq!(reply.metadata.unwrap().transfer_target.as_deref(), Some("haiku"));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_runaway_guest_is_stopped() -> Result<()> {
+        let spinning = DUCK.replace(
+            r#"(func (export "process_message") (param i32 i32) (result i64)"#,
+            r#"(func (export "process_message") (param i32 i32) (result i64) (loop $spin (br $spin))"#,
+        );
+        let agent = WasmAgent::from_bytes(spinning.as_bytes(), WasmLimits { fuel: 10_000, ..WasmLimits::default() })?;
+        let error = agent.process_message(Message::new("hello".to_string())).await.unwrap_err();
+        assert!(matches!(SwarmError::find(&error), Some(SwarmError::Agent(_))), "{}", error);
+        Ok(())
+    }
+}
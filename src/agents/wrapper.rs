@@ -1,12 +1,15 @@
 use std::sync::Arc;
-use tokio::sync::RwLock;
+use tokio::sync::{OnceCell, RwLock};
 use async_trait::async_trait;
 use std::collections::HashMap;
 use std::time::Duration;
-use crate::types::{Agent, Message, Tool, State, AgentConfig};
+use crate::types::{Agent, Message, Tool, State, AgentConfig, StateMachine, Capability};
 use crate::types::{TodoProcessor, TodoList, TodoTask};
-use futures::executor::block_on;
+use crate::types::cancellation::{TaskCancelledError, is_cancelled};
+use crate::tools::CancellationToken;
 use anyhow::Result;
+use super::concurrency::{ConcurrencyLimit, ConcurrencyStats};
+use super::middleware::{MessageStats, MiddlewareChain};
 
 /// A wrapper type that handles the complexity of agent type management.
 /// This provides a consistent interface for working with agents while
@@ -14,22 +17,82 @@ use anyhow::Result;
 #[derive(Clone)]
 pub struct AgentWrapper {
     inner: Arc<Box<dyn Agent + Send + Sync>>,
+    /// The agent's view of the shared task list
     todo_list: TodoList,
+    /// Shared by clones; read from the agent's `max_concurrency` on first use
+    concurrency: Arc<OnceCell<ConcurrencyLimit>>,
+    /// Built from the agent's `middleware` config on first use
+    middleware: Arc<OnceCell<MiddlewareChain>>,
 }
 
 impl AgentWrapper {
-    /// Create a new AgentWrapper from any type that implements Agent
-    pub fn new(agent: Box<dyn Agent + Send + Sync>) -> Self {
+    /// Create a new AgentWrapper from any type that implements Agent, processing the
+    /// tasks in `todo_list`, usually `TodoList::for_agent` of the shared list
+    pub fn new(agent: Box<dyn Agent + Send + Sync>, todo_list: TodoList) -> Self {
         Self {
             inner: Arc::new(agent),
-            todo_list: block_on(TodoList::new()).expect("Failed to create TodoList"),
+            todo_list,
+            concurrency: Arc::new(OnceCell::new()),
+            middleware: Arc::new(OnceCell::new()),
+        }
+    }
+
+    /// Override the agent's configured `max_concurrency`
+    pub fn with_max_concurrency(self, limit: Option<usize>) -> Self {
+        Self { concurrency: Arc::new(OnceCell::new_with(Some(ConcurrencyLimit::new(limit)))), ..self }
+    }
+
+    /// Override the agent's configured `middleware`
+    pub fn with_middleware(self, chain: MiddlewareChain) -> Self {
+        Self { middleware: Arc::new(OnceCell::new_with(Some(chain))), ..self }
+    }
+
+    async fn middleware(&self) -> &MiddlewareChain {
+        self.middleware.get_or_init(|| async {
+            match self.inner.get_config().await {
+                Ok(config) => MiddlewareChain::from_config(&config),
+                Err(_) => MiddlewareChain::new(),
+            }
+        }).await
+    }
+
+    /// Message counts and latency, when the agent's middleware includes `metrics`
+    pub async fn message_stats(&self) -> Option<MessageStats> {
+        self.middleware().await.stats()
+    }
+
+    async fn concurrency_limit(&self) -> &ConcurrencyLimit {
+        self.concurrency.get_or_init(|| async {
+            let limit = self.inner.get_config().await.ok().and_then(|config| config.max_concurrency);
+            ConcurrencyLimit::new(limit)
+        }).await
+    }
+
+    /// Active and queued messages for this agent
+    pub async fn concurrency_stats(&self) -> ConcurrencyStats {
+        self.concurrency_limit().await.stats()
+    }
+
+    /// Wait up to `timeout` for the agent's active and queued messages to finish; false if
+    /// some were still running
+    pub async fn drain(&self, timeout: Duration) -> bool {
+        let deadline = tokio::time::Instant::now() + timeout;
+        loop {
+            let stats = self.concurrency_stats().await;
+            if stats.active + stats.queued == 0 {
+                return true;
+            }
+            if tokio::time::Instant::now() >= deadline {
+                return false;
+            }
+            tokio::time::sleep(Duration::from_millis(50)).await;
         }
     }
 }
 
 #[async_trait]
 impl TodoProcessor for AgentWrapper {
-    async fn process_task(&self, task: TodoTask) -> Result<Message> {
+    async fn process_task(&self, task: TodoTask, cancel: CancellationToken) -> Result<Message> {
         tracing::info!("Processing task: {}", task.id);
         
         // Use enhanced description if available, otherwise use the original description
@@ -47,11 +110,20 @@ impl TodoProcessor for AgentWrapper {
         // Convert the task to a message and process it
         let message = Message::new(description);
         
-        match self.process_message(message).await {
+        // Dropping the agent's future on cancellation stops it at its next await point
+        let result = tokio::select! {
+            result = self.process_message(message) => result,
+            _ = cancel.cancelled() => Err(TaskCancelledError { task_id: task.id.clone() }.into()),
+        };
+        match result {
             Ok(response) => {
                 tracing::info!("Successfully processed task {}", task.id);
                 Ok(response)
             },
+            Err(e) if is_cancelled(&e) => {
+                tracing::info!("Stopped processing task {}: cancelled", task.id);
+                Err(e)
+            },
             Err(e) => {
                 tracing::error!("Failed to process task {}: {}", task.id, e);
                 Err(e)
@@ -71,15 +143,22 @@ impl TodoProcessor for AgentWrapper {
 
 #[async_trait]
 impl Agent for AgentWrapper {
+    /// Waits for a free slot when the agent is already handling `max_concurrency` messages,
+    /// then runs the message through the agent's middleware
     async fn process_message(&self, message: Message) -> Result<Message> {
-        self.inner.process_message(message).await
+        let _permit = self.concurrency_limit().await.acquire().await;
+        let name = self.inner.get_config().await.map(|config| config.name).unwrap_or_default();
+        self.middleware().await.run(&name, message, |message| self.inner.process_message(message)).await
     }
 
     async fn transfer_to(&self, target_agent: String, message: Message) -> Result<Message> {
         self.inner.transfer_to(target_agent, message).await
     }
 
+    /// Tool parameters go through the agent's middleware first, e.g. the guard
     async fn call_tool(&self, tool: &Tool, params: HashMap<String, String>) -> Result<String> {
+        let name = self.inner.get_config().await.map(|config| config.name).unwrap_or_default();
+        let params = self.middleware().await.screen_tool(&name, tool, params).await?;
         self.inner.call_tool(tool, params).await
     }
 
@@ -87,6 +166,14 @@ impl Agent for AgentWrapper {
         self.inner.get_config().await
     }
 
+    async fn get_state_machine(&self) -> Result<Option<StateMachine>> {
+        self.inner.get_state_machine().await
+    }
+
+    async fn capabilities(&self) -> Result<Vec<Capability>> {
+        self.inner.capabilities().await
+    }
+
     async fn get_current_state(&self) -> Result<Option<State>> {
         self.inner.get_current_state().await
     }
@@ -94,27 +181,23 @@ impl Agent for AgentWrapper {
     fn get_todo_list(&self) -> Option<&TodoList> {
         Some(&self.todo_list)
     }
+
+    async fn shutdown(&self) -> Result<()> {
+        self.inner.shutdown().await
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::agents::GreeterAgent;
+    use crate::agents::AgentRegistry;
+    use crate::testing::EchoAgent;
+    use crate::testsupport::{self, MongoFixture};
 
     #[tokio::test]
     async fn test_agent_wrapper() {
-        let config = AgentConfig {
-            name: "test".to_string(),
-            public_description: "Test agent".to_string(),
-            instructions: "Test instructions".to_string(),
-            tools: vec![],
-            downstream_agents: vec![],
-            personality: None,
-            state_machine: None,
-        };
-
-        let agent = GreeterAgent::new(config);
-        let wrapper = AgentWrapper::new(Box::new(agent));
+        let todo_list = testsupport::unconnected_todo_list().for_agent("test");
+        let wrapper = AgentWrapper::new(Box::new(EchoAgent::new("test")), todo_list);
 
         // Test that we can process messages
         let response = wrapper.process_message(Message::new("test".to_string())).await;
@@ -124,4 +207,28 @@ mod tests {
         let state = wrapper.get_current_state().await;
         assert!(state.is_ok());
     }
+
+    #[tokio::test]
+    async fn test_agents_see_their_own_tasks_in_the_shared_list() -> Result<()> {
+        let mongo = MongoFixture::start().await?;
+        let git_task = testsupport::task("Commit the changelog", "git");
+        mongo.seeded([git_task.clone(), testsupport::task("Write a haiku", "haiku")]).await?;
+
+        let mut registry = AgentRegistry::new().with_todos(mongo.todo_list().await);
+        registry.register("git".to_string(), Box::new(EchoAgent::new("git"))).await?;
+        let git = TodoProcessor::get_todo_list(registry.get("git").unwrap());
+        assert_eq!(git.agent(), Some("git"));
+        let tasks = git.get_all_tasks().await?;
+        assert_eq!(tasks.iter().map(|task| task.id.as_str()).collect::<Vec<_>>(), [git_task.id.as_str()]);
+
+        // Added through one agent's view, for another agent: stored, and that agent's to claim
+        git.add_task(testsupport::task("Review the haiku", "haiku")).await?;
+        assert_eq!(git.get_next_task().await?.map(|task| task.id), Some(git_task.id));
+        assert!(git.get_next_task().await?.is_none());
+        // A restarted process sees the same tasks
+        let restarted = mongo.todo_list().await.for_agent("haiku");
+        assert_eq!(restarted.get_all_tasks().await?.len(), 2);
+        Ok(())
+    }
 }
+
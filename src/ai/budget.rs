@@ -0,0 +1,350 @@
+use std::collections::HashMap;
+use std::env;
+use std::sync::{Arc, Mutex};
+use anyhow::Result;
+use chrono::{NaiveDate, Utc};
+use serde::Serialize;
+use crate::error::SwarmError;
+use crate::types::{Attachment, Tool};
+use super::{AiProvider, ChatResponse, JsonSchema, LocalAiClient};
+
+/// Tokens added per chat message for role and framing
+const MESSAGE_OVERHEAD_TOKENS: usize = 4;
+/// Longest run of letters counted as a single token
+const CHARS_PER_WORD_PIECE: usize = 6;
+const BUDGET_SUFFIX: &str = "_DAILY_TOKEN_BUDGET";
+
+/// Estimate how many tokens `text` uses. Splits the text the way BPE tokenizers pre-tokenize
+/// it (words, numbers, punctuation, whitespace runs) and counts long words as several pieces,
+/// which lands close to the real count for English prose and code without shipping a vocabulary.
+pub fn count_tokens(text: &str) -> usize {
+    #[derive(PartialEq, Clone, Copy)]
+    enum Kind { Word, Digit, Space, Other }
+    fn kind(c: char) -> Kind {
+        if c.is_alphabetic() { Kind::Word }
+        else if c.is_ascii_digit() { Kind::Digit }
+        else if c.is_whitespace() { Kind::Space }
+        else { Kind::Other }
+    }
+
+    let mut tokens = 0;
+    let mut run: Option<(Kind, usize)> = None;
+    let mut flush = |run: Option<(Kind, usize)>| match run {
+        Some((Kind::Word, len)) => tokens += len.div_ceil(CHARS_PER_WORD_PIECE),
+        // Numbers split into groups of up to three digits
+        Some((Kind::Digit, len)) => tokens += len.div_ceil(3),
+        // A single space merges into the following word
+        Some((Kind::Space, len)) => tokens += usize::from(len > 1),
+        Some((Kind::Other, len)) => tokens += len,
+        None => {}
+    };
+    for c in text.chars() {
+        let k = kind(c);
+        run = match run {
+            Some((current, len)) if current == k && k != Kind::Other => Some((current, len + 1)),
+            previous => {
+                flush(previous);
+                Some((k, 1))
+            }
+        };
+    }
+    flush(run);
+    tokens
+}
+
+/// Estimated prompt tokens for a chat request
+pub fn count_prompt_tokens(system_prompt: &str, messages: &[HashMap<String, String>]) -> usize {
+    let content: usize = messages.iter()
+        .map(|m| m.get("content").map_or(0, |c| count_tokens(c)) + MESSAGE_OVERHEAD_TOKENS)
+        .sum();
+    count_tokens(system_prompt) + MESSAGE_OVERHEAD_TOKENS + content
+}
+
+/// One agent's AI usage for a day
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct TokenUsage {
+    pub prompt_tokens: u64,
+    pub completion_tokens: u64,
+    pub requests: u64,
+    /// Requests sent to the cheaper model because the budget was exhausted
+    pub downgraded: u64,
+    pub rejected: u64,
+}
+
+impl TokenUsage {
+    pub fn total(&self) -> u64 {
+        self.prompt_tokens + self.completion_tokens
+    }
+}
+
+lazy_static::lazy_static! {
+    static ref GLOBAL_BUDGETS: Arc<TokenBudgets> = Arc::new(TokenBudgets::from_env());
+}
+
+/// Daily token budgets and usage per agent. Usage resets at midnight UTC.
+#[derive(Debug, Default)]
+pub struct TokenBudgets {
+    default_limit: Option<u64>,
+    limits: HashMap<String, u64>,
+    usage: Mutex<HashMap<String, (NaiveDate, TokenUsage)>>,
+}
+
+impl TokenBudgets {
+    /// Budgets with no limits; every agent is allowed unlimited tokens until one is set
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// `AI_DAILY_TOKEN_BUDGET` for every agent, overridden per agent by e.g.
+    /// `HAIKU_DAILY_TOKEN_BUDGET`. Unset means unlimited.
+    pub fn from_env() -> Self {
+        let mut budgets = Self::new();
+        for (key, value) in env::vars() {
+            let (Some(name), Ok(limit)) = (key.strip_suffix(BUDGET_SUFFIX), value.parse()) else {
+                continue;
+            };
+            budgets = match name {
+                "AI" => budgets.with_default_limit(limit),
+                agent => budgets.with_limit(agent.to_lowercase(), limit),
+            };
+        }
+        budgets
+    }
+
+    /// Budgets shared by every agent in the process, from the environment
+    pub fn global() -> Arc<TokenBudgets> {
+        GLOBAL_BUDGETS.clone()
+    }
+
+    pub fn with_default_limit(mut self, tokens: u64) -> Self {
+        self.default_limit = Some(tokens);
+        self
+    }
+
+    pub fn with_limit(mut self, agent: impl Into<String>, tokens: u64) -> Self {
+        self.limits.insert(agent.into(), tokens);
+        self
+    }
+
+    pub fn limit(&self, agent: &str) -> Option<u64> {
+        self.limits.get(agent).copied().or(self.default_limit)
+    }
+
+    /// Run `f` on the agent's usage for today, starting fresh on a new day
+    fn with_usage<T>(&self, agent: &str, f: impl FnOnce(&mut TokenUsage) -> T) -> T {
+        let today = Utc::now().date_naive();
+        let mut usage = self.usage.lock().unwrap();
+        let entry = usage.entry(agent.to_string()).or_insert_with(|| (today, TokenUsage::default()));
+        if entry.0 != today {
+            *entry = (today, TokenUsage::default());
+        }
+        f(&mut entry.1)
+    }
+
+    /// Today's usage for an agent
+    pub fn usage(&self, agent: &str) -> TokenUsage {
+        self.with_usage(agent, |usage| usage.clone())
+    }
+
+    /// Tokens the agent may still use today, None if unlimited
+    pub fn remaining(&self, agent: &str) -> Option<u64> {
+        let limit = self.limit(agent)?;
+        Some(limit.saturating_sub(self.usage(agent).total()))
+    }
+
+    /// Today's usage for every agent that has made a request, for metrics reporting
+    pub fn snapshot(&self) -> HashMap<String, TokenUsage> {
+        let today = Utc::now().date_naive();
+        self.usage.lock().unwrap().iter()
+            .filter(|(_, (day, _))| *day == today)
+            .map(|(agent, (_, usage))| (agent.clone(), usage.clone()))
+            .collect()
+    }
+
+    fn record(&self, agent: &str, prompt_tokens: usize, completion_tokens: usize, downgraded: bool) {
+        self.with_usage(agent, |usage| {
+            usage.prompt_tokens += prompt_tokens as u64;
+            usage.completion_tokens += completion_tokens as u64;
+            usage.requests += 1;
+            usage.downgraded += u64::from(downgraded);
+        });
+    }
+
+    fn record_rejected(&self, agent: &str) {
+        self.with_usage(agent, |usage| usage.rejected += 1);
+    }
+}
+
+enum Route {
+    Primary,
+    Downgrade,
+}
+
+/// Counts an agent's tokens against its daily budget. Once a request would exceed the
+/// budget it goes to the downgrade model with only the latest message, or is rejected
+/// with an `AiProvider` error if there is no downgrade model.
+pub struct BudgetedAiClient<P> {
+    inner: P,
+    agent: String,
+    budgets: Arc<TokenBudgets>,
+    downgrade: Option<Box<dyn AiProvider>>,
+}
+
+impl<P: AiProvider> BudgetedAiClient<P> {
+    /// Uses the global budgets, downgrading to the local `AI_BUDGET_DOWNGRADE_MODEL` if set
+    pub fn new(agent: impl Into<String>, inner: P) -> Self {
+        let downgrade = env::var("AI_BUDGET_DOWNGRADE_MODEL").ok()
+            .map(|model| Box::new(LocalAiClient::new().with_model(model)) as Box<dyn AiProvider>);
+        Self { inner, agent: agent.into(), budgets: TokenBudgets::global(), downgrade }
+    }
+
+    pub fn with_budgets(mut self, budgets: Arc<TokenBudgets>) -> Self {
+        self.budgets = budgets;
+        self
+    }
+
+    pub fn with_downgrade<D: AiProvider + 'static>(mut self, client: D) -> Self {
+        self.downgrade = Some(Box::new(client));
+        self
+    }
+
+    fn route(&self, prompt_tokens: usize, can_downgrade: bool) -> Result<Route> {
+        match self.budgets.remaining(&self.agent) {
+            Some(remaining) if prompt_tokens as u64 > remaining => {
+                if can_downgrade && self.downgrade.is_some() {
+                    tracing::debug!("Agent '{}' is over its token budget, downgrading request", self.agent);
+                    return Ok(Route::Downgrade);
+                }
+                self.budgets.record_rejected(&self.agent);
+                Err(SwarmError::AiProvider(format!(
+                    "Daily token budget exhausted for agent '{}' ({} tokens left, request needs {})",
+                    self.agent, remaining, prompt_tokens
+                )).into())
+            }
+            _ => Ok(Route::Primary),
+        }
+    }
+
+    /// The client for a route, with history dropped for downgraded requests
+    fn target(&self, route: &Route, messages: Vec<HashMap<String, String>>) -> (&dyn AiProvider, Vec<HashMap<String, String>>) {
+        match (route, &self.downgrade) {
+            (Route::Downgrade, Some(downgrade)) => (downgrade.as_ref(), messages.into_iter().last().into_iter().collect()),
+            _ => (&self.inner, messages),
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl<P: AiProvider> AiProvider for BudgetedAiClient<P> {
+    async fn chat(&self, system_prompt: &str, messages: Vec<HashMap<String, String>>) -> Result<String> {
+        let route = self.route(count_prompt_tokens(system_prompt, &messages), true)?;
+        let (client, messages) = self.target(&route, messages);
+        let prompt_tokens = count_prompt_tokens(system_prompt, &messages);
+        let response = client.chat(system_prompt, messages).await?;
+        self.budgets.record(&self.agent, prompt_tokens, count_tokens(&response), matches!(route, Route::Downgrade));
+        Ok(response)
+    }
+
+    async fn chat_with_tools(&self, system_prompt: &str, messages: Vec<HashMap<String, String>>, tools: &[Tool]) -> Result<ChatResponse> {
+        let route = self.route(count_prompt_tokens(system_prompt, &messages), true)?;
+        let (client, messages) = self.target(&route, messages);
+        let prompt_tokens = count_prompt_tokens(system_prompt, &messages);
+        let response = client.chat_with_tools(system_prompt, messages, tools).await?;
+        let completion_tokens = match &response {
+            ChatResponse::Message(content) => count_tokens(content),
+            ChatResponse::ToolCalls(calls) => calls.iter()
+                .map(|request| count_tokens(&request.call.tool.name)
+                    + request.call.parameters.iter().map(|(k, v)| count_tokens(k) + count_tokens(v)).sum::<usize>())
+                .sum(),
+        };
+        self.budgets.record(&self.agent, prompt_tokens, completion_tokens, matches!(route, Route::Downgrade));
+        Ok(response)
+    }
+
+    async fn chat_structured(&self, system_prompt: &str, messages: Vec<HashMap<String, String>>, schema: &JsonSchema) -> Result<serde_json::Value> {
+        let route = self.route(count_prompt_tokens(system_prompt, &messages), true)?;
+        let (client, messages) = self.target(&route, messages);
+        let prompt_tokens = count_prompt_tokens(system_prompt, &messages);
+        let response = client.chat_structured(system_prompt, messages, schema).await?;
+        self.budgets.record(&self.agent, prompt_tokens, count_tokens(&response.to_string()), matches!(route, Route::Downgrade));
+        Ok(response)
+    }
+
+    /// Never downgraded: another model's vectors wouldn't be comparable with stored ones
+    async fn embed(&self, text: &str) -> Result<Vec<f32>> {
+        let prompt_tokens = count_tokens(text);
+        self.route(prompt_tokens, false)?;
+        let embedding = self.inner.embed(text).await?;
+        self.budgets.record(&self.agent, prompt_tokens, 0, false);
+        Ok(embedding)
+    }
+
+    /// Never downgraded, since the cheaper model may not take images. Only the text counts
+    /// towards the budget.
+    async fn chat_with_images(&self, system_prompt: &str, messages: Vec<HashMap<String, String>>, images: &[Attachment]) -> Result<String> {
+        let prompt_tokens = count_prompt_tokens(system_prompt, &messages);
+        self.route(prompt_tokens, false)?;
+        let response = self.inner.chat_with_images(system_prompt, messages, images).await?;
+        self.budgets.record(&self.agent, prompt_tokens, count_tokens(&response), false);
+        Ok(response)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct EchoClient(&'static str);
+
+    #[async_trait::async_trait]
+    impl AiProvider for EchoClient {
+        async fn chat(&self, _system_prompt: &str, messages: Vec<HashMap<String, String>>) -> Result<String> {
+            Ok(format!("{} saw {} message(s)", self.0, messages.len()))
+        }
+    }
+
+    fn history(len: usize) -> Vec<HashMap<String, String>> {
+        (0..len)
+            .map(|i| HashMap::from([
+                ("role".to_string(), "user".to_string()),
+                ("content".to_string(), format!("message number {}", i)),
+            ]))
+            .collect()
+    }
+
+    #[test]
+    fn test_count_tokens() {
+        assert_eq!(count_tokens(""), 0);
+        assert_eq!(count_tokens("Hello, world!"), 4);
+        // Long words and numbers split into pieces
+        assert_eq!(count_tokens("internationalization"), 4);
+        assert_eq!(count_tokens("1234567"), 3);
+        assert_eq!(count_tokens("fn main() {}"), 6);
+    }
+
+    #[tokio::test]
+    async fn test_budget_downgrades_then_rejects() {
+        let budgets = Arc::new(TokenBudgets::new().with_limit("haiku", 40));
+
+        let client = BudgetedAiClient::new("haiku", EchoClient("primary"))
+            .with_budgets(budgets.clone())
+            .with_downgrade(EchoClient("cheap"));
+        assert_eq!(client.chat("Be brief.", history(1)).await.unwrap(), "primary saw 1 message(s)");
+        let used = budgets.usage("haiku");
+        assert_eq!(used.requests, 1);
+        assert!(used.total() > 0 && used.total() < 40, "{:?}", used);
+
+        assert_eq!(client.chat("Be brief.", history(5)).await.unwrap(), "cheap saw 1 message(s)");
+        assert_eq!(budgets.usage("haiku").downgraded, 1);
+
+        let strict = BudgetedAiClient::new("haiku", EchoClient("primary")).with_budgets(budgets.clone());
+        let strict = BudgetedAiClient { downgrade: None, ..strict };
+        let error = strict.chat("Be brief.", history(5)).await.unwrap_err();
+        assert!(matches!(SwarmError::find(&error), Some(SwarmError::AiProvider(_))));
+        assert_eq!(budgets.usage("haiku").rejected, 1);
+
+        // Other agents are unaffected
+        assert_eq!(budgets.remaining("greeter"), None);
+        assert!(budgets.snapshot().contains_key("haiku"));
+    }
+}
@@ -0,0 +1,407 @@
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::env;
+use std::path::{Path, PathBuf};
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+use sha2::{Digest, Sha256};
+use tokio::io::AsyncWriteExt;
+use tokio::sync::Mutex;
+use crate::error::SwarmError;
+use crate::types::{Attachment, Tool};
+use super::{AiProvider, ChatResponse, JsonSchema, functions, structured};
+
+type ChatMessages = Vec<BTreeMap<String, String>>;
+
+/// One recorded exchange with a provider
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Interaction {
+    Chat {
+        system_prompt: String,
+        messages: ChatMessages,
+        /// Fingerprints of the attached images, if any (see `fingerprint`)
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        images: Vec<String>,
+        response: String,
+    },
+    /// A chat with tools on offer, by name
+    Tools {
+        system_prompt: String,
+        messages: ChatMessages,
+        tools: Vec<String>,
+        response: Value,
+    },
+    /// A chat for a reply matching `schema` (its name and schema)
+    Structured {
+        system_prompt: String,
+        messages: ChatMessages,
+        schema: Value,
+        response: Value,
+    },
+    Embed {
+        text: String,
+        embedding: Vec<f32>,
+    },
+}
+
+/// Recorded interactions, stored as JSON lines so recording only appends. Cassettes
+/// written as a single `{"interactions": [...]}` document still load.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Cassette {
+    pub interactions: Vec<Interaction>,
+}
+
+impl Cassette {
+    pub fn load(path: &Path) -> Result<Self> {
+        let content = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read cassette {}", path.display()))?;
+        if let Ok(cassette) = serde_json::from_str(&content) {
+            return Ok(cassette);
+        }
+        let interactions = content.lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(serde_json::from_str)
+            .collect::<Result<_, _>>()
+            .with_context(|| format!("Invalid cassette {}", path.display()))?;
+        Ok(Self { interactions })
+    }
+
+    pub async fn save(&self, path: &Path) -> Result<()> {
+        let mut content = String::new();
+        for interaction in &self.interactions {
+            content.push_str(&serde_json::to_string(interaction)?);
+            content.push('\n');
+        }
+        tokio::fs::write(path, content).await
+            .with_context(|| format!("Failed to write cassette {}", path.display()))
+    }
+}
+
+/// Messages with a stable field order, so requests compare and serialize consistently
+fn ordered(messages: &[HashMap<String, String>]) -> ChatMessages {
+    messages.iter().map(|m| m.iter().map(|(k, v)| (k.clone(), v.clone())).collect()).collect()
+}
+
+/// Identifies an attachment in a cassette without storing its contents
+fn fingerprint(attachment: &Attachment) -> String {
+    let bytes = match attachment {
+        Attachment::Image { data, .. } => data.clone(),
+        Attachment::File { path, .. } => path.to_string_lossy().into_owned().into_bytes(),
+        Attachment::Json { value } => value.to_string().into_bytes(),
+    };
+    hex::encode(Sha256::digest(bytes))
+}
+
+fn tool_names(tools: &[Tool]) -> Vec<String> {
+    tools.iter().map(|tool| tool.name.clone()).collect()
+}
+
+fn schema_value(schema: &JsonSchema) -> Value {
+    json!({ "name": schema.name, "schema": schema.schema })
+}
+
+/// Passes requests through to `inner` and appends each successful exchange to a cassette
+/// file
+pub struct RecordingAiClient<P> {
+    inner: P,
+    path: PathBuf,
+    /// Opened on the first exchange
+    file: Mutex<Option<tokio::fs::File>>,
+}
+
+impl<P: AiProvider> RecordingAiClient<P> {
+    /// Start a fresh cassette at `path`, replacing any existing one on the first exchange
+    pub fn new(inner: P, path: impl Into<PathBuf>) -> Self {
+        Self { inner, path: path.into(), file: Mutex::new(None) }
+    }
+
+    async fn record(&self, interaction: Interaction) -> Result<()> {
+        let mut file = self.file.lock().await;
+        if file.is_none() {
+            let created = tokio::fs::File::create(&self.path).await
+                .with_context(|| format!("Failed to create cassette {}", self.path.display()))?;
+            *file = Some(created);
+        }
+        let file = file.as_mut().expect("opened above");
+        let mut line = serde_json::to_string(&interaction)?;
+        line.push('\n');
+        file.write_all(line.as_bytes()).await?;
+        file.flush().await
+            .with_context(|| format!("Failed to write cassette {}", self.path.display()))
+    }
+}
+
+#[async_trait::async_trait]
+impl<P: AiProvider> AiProvider for RecordingAiClient<P> {
+    async fn chat(&self, system_prompt: &str, messages: Vec<HashMap<String, String>>) -> Result<String> {
+        let recorded = ordered(&messages);
+        let response = self.inner.chat(system_prompt, messages).await?;
+        self.record(Interaction::Chat {
+            system_prompt: system_prompt.to_string(),
+            messages: recorded,
+            images: Vec::new(),
+            response: response.clone(),
+        }).await?;
+        Ok(response)
+    }
+
+    async fn chat_with_tools(&self, system_prompt: &str, messages: Vec<HashMap<String, String>>, tools: &[Tool]) -> Result<ChatResponse> {
+        let recorded = ordered(&messages);
+        let response = self.inner.chat_with_tools(system_prompt, messages, tools).await?;
+        self.record(Interaction::Tools {
+            system_prompt: system_prompt.to_string(),
+            messages: recorded,
+            tools: tool_names(tools),
+            response: serde_json::to_value(&response)?,
+        }).await?;
+        Ok(response)
+    }
+
+    async fn chat_structured(&self, system_prompt: &str, messages: Vec<HashMap<String, String>>, schema: &JsonSchema) -> Result<Value> {
+        let recorded = ordered(&messages);
+        let response = self.inner.chat_structured(system_prompt, messages, schema).await?;
+        self.record(Interaction::Structured {
+            system_prompt: system_prompt.to_string(),
+            messages: recorded,
+            schema: schema_value(schema),
+            response: response.clone(),
+        }).await?;
+        Ok(response)
+    }
+
+    async fn embed(&self, text: &str) -> Result<Vec<f32>> {
+        let embedding = self.inner.embed(text).await?;
+        self.record(Interaction::Embed { text: text.to_string(), embedding: embedding.clone() }).await?;
+        Ok(embedding)
+    }
+
+    /// Recorded with fingerprints of the images rather than the images themselves
+    async fn chat_with_images(&self, system_prompt: &str, messages: Vec<HashMap<String, String>>, images: &[Attachment]) -> Result<String> {
+        let recorded = ordered(&messages);
+        let response = self.inner.chat_with_images(system_prompt, messages, images).await?;
+        self.record(Interaction::Chat {
+            system_prompt: system_prompt.to_string(),
+            messages: recorded,
+            images: images.iter().map(fingerprint).collect(),
+            response: response.clone(),
+        }).await?;
+        Ok(response)
+    }
+}
+
+/// A recorded request: the system prompt, the messages, and what else was sent with them
+/// (image fingerprints, tool names or the schema)
+type RequestKey = (String, ChatMessages, String);
+
+/// Recorded responses by request, served in order with the last one repeating
+struct Responses<T>(Mutex<HashMap<RequestKey, VecDeque<T>>>);
+
+impl<T: Clone> Responses<T> {
+    async fn next(&self, key: &RequestKey) -> Option<T> {
+        let mut responses = self.0.lock().await;
+        let queue = responses.get_mut(key)?;
+        Some(match queue.len() {
+            1 => queue[0].clone(),
+            _ => queue.pop_front().expect("recorded requests have at least one response"),
+        })
+    }
+}
+
+impl<T> From<HashMap<RequestKey, VecDeque<T>>> for Responses<T> {
+    fn from(responses: HashMap<RequestKey, VecDeque<T>>) -> Self {
+        Self(Mutex::new(responses))
+    }
+}
+
+fn key(system_prompt: &str, messages: &[HashMap<String, String>], extra: String) -> RequestKey {
+    (system_prompt.to_string(), ordered(messages), extra)
+}
+
+fn unrecorded(messages: &[HashMap<String, String>]) -> anyhow::Error {
+    let last = messages.last().and_then(|m| m.get("content")).cloned().unwrap_or_default();
+    SwarmError::AiProvider(format!("No recorded response for '{}'", last)).into()
+}
+
+/// Serves responses from a cassette without calling any model. Identical requests get
+/// their recorded responses in order, and the last one again once those run out;
+/// a request that was never recorded fails with an `AiProvider` error. Tool and
+/// structured chats recorded as plain chats (by older cassettes) are still served.
+pub struct ReplayAiClient {
+    chats: Responses<String>,
+    tool_chats: Responses<ChatResponse>,
+    structured: Responses<Value>,
+    embeddings: HashMap<String, Vec<f32>>,
+}
+
+impl ReplayAiClient {
+    pub fn new(cassette: Cassette) -> Self {
+        let mut chats: HashMap<RequestKey, VecDeque<String>> = HashMap::new();
+        let mut tool_chats: HashMap<RequestKey, VecDeque<ChatResponse>> = HashMap::new();
+        let mut structured: HashMap<RequestKey, VecDeque<Value>> = HashMap::new();
+        let mut embeddings = HashMap::new();
+        for interaction in cassette.interactions {
+            match interaction {
+                Interaction::Chat { system_prompt, messages, images, response } => {
+                    chats.entry((system_prompt, messages, images.join(","))).or_default().push_back(response);
+                }
+                Interaction::Tools { system_prompt, messages, tools, response } => match serde_json::from_value(response) {
+                    Ok(response) => tool_chats.entry((system_prompt, messages, tools.join(","))).or_default().push_back(response),
+                    Err(e) => tracing::warn!("Skipping unreadable tool chat in cassette: {}", e),
+                },
+                Interaction::Structured { system_prompt, messages, schema, response } => {
+                    structured.entry((system_prompt, messages, schema.to_string())).or_default().push_back(response);
+                }
+                Interaction::Embed { text, embedding } => {
+                    embeddings.insert(text, embedding);
+                }
+            }
+        }
+        Self { chats: chats.into(), tool_chats: tool_chats.into(), structured: structured.into(), embeddings }
+    }
+
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self> {
+        Ok(Self::new(Cassette::load(path.as_ref())?))
+    }
+}
+
+#[async_trait::async_trait]
+impl AiProvider for ReplayAiClient {
+    async fn chat(&self, system_prompt: &str, messages: Vec<HashMap<String, String>>) -> Result<String> {
+        self.chats.next(&key(system_prompt, &messages, String::new())).await
+            .ok_or_else(|| unrecorded(&messages))
+    }
+
+    async fn chat_with_tools(&self, system_prompt: &str, messages: Vec<HashMap<String, String>>, tools: &[Tool]) -> Result<ChatResponse> {
+        match self.tool_chats.next(&key(system_prompt, &messages, tool_names(tools).join(","))).await {
+            Some(response) => Ok(response),
+            None => functions::prompted(self, system_prompt, messages, tools).await,
+        }
+    }
+
+    async fn chat_structured(&self, system_prompt: &str, messages: Vec<HashMap<String, String>>, schema: &JsonSchema) -> Result<Value> {
+        match self.structured.next(&key(system_prompt, &messages, schema_value(schema).to_string())).await {
+            Some(response) => Ok(response),
+            None => structured::prompted(self, system_prompt, messages, schema).await,
+        }
+    }
+
+    async fn embed(&self, text: &str) -> Result<Vec<f32>> {
+        self.embeddings.get(text).cloned()
+            .ok_or_else(|| SwarmError::AiProvider(format!("No recorded embedding for '{}'", text)).into())
+    }
+
+    async fn chat_with_images(&self, system_prompt: &str, messages: Vec<HashMap<String, String>>, images: &[Attachment]) -> Result<String> {
+        let fingerprints: Vec<String> = images.iter().map(fingerprint).collect();
+        self.chats.next(&key(system_prompt, &messages, fingerprints.join(","))).await
+            .ok_or_else(|| unrecorded(&messages))
+    }
+}
+
+/// Wrap `inner` according to `AI_CASSETTE` (a cassette path) and `AI_CASSETTE_MODE`
+/// (`record` or `replay`, default `replay`). Returns `inner` unchanged when `AI_CASSETTE`
+/// is unset. A cassette that can't be loaded for replay is logged and `inner` used instead.
+pub fn cassette_from_env<P: AiProvider + 'static>(inner: P) -> Box<dyn AiProvider + Send + Sync> {
+    let Ok(path) = env::var("AI_CASSETTE") else {
+        return Box::new(inner);
+    };
+    match env::var("AI_CASSETTE_MODE").as_deref() {
+        Ok("record") => Box::new(RecordingAiClient::new(inner, path)),
+        _ => match ReplayAiClient::from_file(&path) {
+            Ok(replay) => Box::new(replay),
+            Err(e) => {
+                tracing::warn!("Not replaying AI interactions: {:#}", e);
+                Box::new(inner)
+            }
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ai::MockAiProvider;
+
+    fn user(content: &str) -> Vec<HashMap<String, String>> {
+        vec![HashMap::from([
+            ("role".to_string(), "user".to_string()),
+            ("content".to_string(), content.to_string()),
+        ])]
+    }
+
+    #[tokio::test]
+    async fn test_record_then_replay() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("haiku.json");
+        let mock = MockAiProvider::new().with_response("Silicon dreams flow").with_response("Rust never sleeps");
+
+        let recorder = RecordingAiClient::new(mock.clone(), &path);
+        recorder.chat("Write a haiku", user("rust")).await?;
+        recorder.chat("Write a haiku", user("rust")).await?;
+        let embedding = recorder.embed("rust").await?;
+
+        let replay = ReplayAiClient::from_file(&path)?;
+        assert_eq!(replay.chat("Write a haiku", user("rust")).await?, "Silicon dreams flow");
+        assert_eq!(replay.chat("Write a haiku", user("rust")).await?, "Rust never sleeps");
+        assert_eq!(replay.chat("Write a haiku", user("rust")).await?, "Rust never sleeps");
+        assert_eq!(replay.embed("rust").await?, embedding);
+        assert_eq!(mock.call_count(), 2);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_tools_structured_and_images_replay() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("agent.jsonl");
+        let mock = MockAiProvider::new()
+            .with_response(r#"{"name": "todo", "arguments": {"command": "list"}}"#)
+            .with_response(r#"{"syllables": 17}"#)
+            .with_response("A cat on a mat");
+        let todo = Tool { name: "todo".to_string(), description: "Todos".to_string(), parameters: HashMap::new() };
+        let schema = JsonSchema::new("haiku", json!({ "type": "object", "required": ["syllables"] }));
+        let image = Attachment::Image { data: vec![0x89, b'P', b'N', b'G'], mime_type: "image/png".to_string() };
+
+        let recorder = RecordingAiClient::new(mock.clone(), &path);
+        recorder.chat_with_tools("Help", user("what's next?"), std::slice::from_ref(&todo)).await?;
+        recorder.chat_structured("Count", user("rust"), &schema).await?;
+        recorder.chat_with_images("Describe", user("what is this?"), std::slice::from_ref(&image)).await?;
+        // Each exchange was appended as its own line
+        assert_eq!(std::fs::read_to_string(&path)?.lines().count(), 3);
+
+        let replay = ReplayAiClient::from_file(&path)?;
+        let response = replay.chat_with_tools("Help", user("what's next?"), std::slice::from_ref(&todo)).await?;
+        assert!(matches!(response, ChatResponse::ToolCalls(calls) if calls[0].call.tool.name == "todo"));
+        assert_eq!(replay.chat_structured("Count", user("rust"), &schema).await?, json!({ "syllables": 17 }));
+        assert_eq!(replay.chat_with_images("Describe", user("what is this?"), &[image]).await?, "A cat on a mat");
+        let other = Attachment::Image { data: vec![0xFF, 0xD8, 0xFF], mime_type: "image/jpeg".to_string() };
+        assert!(replay.chat_with_images("Describe", user("what is this?"), &[other]).await.is_err());
+        assert_eq!(mock.call_count(), 3);
+        Ok(())
+    }
+
+    #[test]
+    fn test_single_document_cassettes_still_load() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("old.json");
+        let old = r#"{"interactions": [{"kind": "chat", "system_prompt": "s", "messages": [], "response": "r"}]}"#;
+        std::fs::write(&path, old)?;
+        assert_eq!(Cassette::load(&path)?.interactions.len(), 1);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_unrecorded_request_fails() {
+        let replay = ReplayAiClient::new(Cassette {
+            interactions: vec![Interaction::Chat {
+                system_prompt: "Write a haiku".to_string(),
+                messages: ordered(&user("rust")),
+                images: Vec::new(),
+                response: "Silicon dreams flow".to_string(),
+            }],
+        });
+        let error = replay.chat("Write a haiku", user("go")).await.unwrap_err();
+        assert!(matches!(SwarmError::find(&error), Some(SwarmError::AiProvider(_))));
+        assert!(error.to_string().contains("'go'"), "{}", error);
+        assert!(replay.embed("rust").await.is_err());
+    }
+}
@@ -0,0 +1,54 @@
+/// Cosine similarity of two vectors, in the range -1.0 to 1.0.
+/// Returns 0.0 for mismatched lengths or zero vectors.
+pub fn cosine_similarity(a: &[f32], b: &[f32]) -> f32 {
+    if a.len() != b.len() || a.is_empty() {
+        return 0.0;
+    }
+    let dot: f32 = a.iter().zip(b).map(|(x, y)| x * y).sum();
+    let norm_a: f32 = a.iter().map(|x| x * x).sum::<f32>().sqrt();
+    let norm_b: f32 = b.iter().map(|x| x * x).sum::<f32>().sqrt();
+    if norm_a == 0.0 || norm_b == 0.0 {
+        return 0.0;
+    }
+    dot / (norm_a * norm_b)
+}
+
+/// Score candidates against a query embedding, keeping those at or above `min_similarity`,
+/// most similar first, at most `limit` results
+pub fn rank_by_similarity<T>(query: &[f32], candidates: Vec<(T, Vec<f32>)>, min_similarity: f32, limit: usize) -> Vec<(T, f32)> {
+    let mut scored: Vec<(T, f32)> = candidates.into_iter()
+        .map(|(item, embedding)| {
+            let score = cosine_similarity(query, &embedding);
+            (item, score)
+        })
+        .filter(|(_, score)| *score >= min_similarity)
+        .collect();
+    scored.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(std::cmp::Ordering::Equal));
+    scored.truncate(limit);
+    scored
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cosine_similarity() {
+        assert!((cosine_similarity(&[1.0, 0.0], &[2.0, 0.0]) - 1.0).abs() < 1e-6);
+        assert!(cosine_similarity(&[1.0, 0.0], &[0.0, 1.0]).abs() < 1e-6);
+        assert_eq!(cosine_similarity(&[1.0], &[1.0, 0.0]), 0.0);
+        assert_eq!(cosine_similarity(&[0.0, 0.0], &[1.0, 0.0]), 0.0);
+    }
+
+    #[test]
+    fn test_rank_by_similarity() {
+        let candidates = vec![
+            ("orthogonal", vec![0.0, 1.0]),
+            ("close", vec![0.9, 0.1]),
+            ("exact", vec![1.0, 0.0]),
+        ];
+        let ranked = rank_by_similarity(&[1.0, 0.0], candidates, 0.5, 5);
+        let names: Vec<&str> = ranked.iter().map(|(name, _)| *name).collect();
+        assert_eq!(names, vec!["exact", "close"]);
+    }
+}
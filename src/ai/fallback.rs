@@ -0,0 +1,343 @@
+use std::collections::{BTreeMap, HashMap};
+use std::env;
+use std::sync::Mutex;
+use std::time::Duration;
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tokio::time::Instant;
+use tracing::Instrument;
+use utoipa::ToSchema;
+use crate::error::SwarmError;
+use crate::events::{self, DomainEvent};
+use crate::types::{Attachment, Tool};
+use super::{AiProvider, ChatResponse, DefaultAiClient, HuggingFaceClient, JsonSchema, OpenAiClient};
+
+/// When to give up on a provider and for how long
+#[derive(Debug, Clone, PartialEq)]
+pub struct FallbackPolicy {
+    /// How long one call may take before the next provider is tried
+    pub timeout: Duration,
+    /// Consecutive failures before a provider's circuit opens
+    pub failure_threshold: u32,
+    /// How long an open circuit skips the provider before trying it again
+    pub cooldown: Duration,
+}
+
+impl Default for FallbackPolicy {
+    fn default() -> Self {
+        Self {
+            timeout: Duration::from_secs(120),
+            failure_threshold: 3,
+            cooldown: Duration::from_secs(60),
+        }
+    }
+}
+
+impl FallbackPolicy {
+    /// Read `AI_FALLBACK_TIMEOUT_SECS`, `AI_FALLBACK_FAILURE_THRESHOLD` and `AI_FALLBACK_COOLDOWN_SECS`
+    pub fn from_env() -> Self {
+        fn var<T: std::str::FromStr>(name: &str) -> Option<T> {
+            env::var(name).ok().and_then(|v| v.parse().ok())
+        }
+        let defaults = Self::default();
+        Self {
+            timeout: var("AI_FALLBACK_TIMEOUT_SECS").map(Duration::from_secs).unwrap_or(defaults.timeout),
+            failure_threshold: var("AI_FALLBACK_FAILURE_THRESHOLD").unwrap_or(defaults.failure_threshold),
+            cooldown: var("AI_FALLBACK_COOLDOWN_SECS").map(Duration::from_secs).unwrap_or(defaults.cooldown),
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone)]
+struct Health {
+    consecutive_failures: u32,
+    open_until: Option<Instant>,
+    last_latency: Option<Duration>,
+}
+
+impl Health {
+    fn report(&self, name: &str, now: Instant) -> ProviderHealth {
+        ProviderHealth {
+            name: name.to_string(),
+            consecutive_failures: self.consecutive_failures,
+            open: self.open_until.is_some_and(|until| now < until),
+            latency_ms: self.last_latency.map(|latency| latency.as_secs_f64() * 1000.0),
+        }
+    }
+}
+
+lazy_static::lazy_static! {
+    /// Each provider's health after its latest call through any chain in the process
+    static ref LATEST_HEALTH: Mutex<BTreeMap<String, Health>> = Mutex::new(BTreeMap::new());
+}
+
+/// The health of every provider called so far, as its latest call through any chain left
+/// it, e.g. for a dashboard. Chains keep their own circuits, so one may still be skipping
+/// a provider another has seen recover.
+pub fn provider_health() -> Vec<ProviderHealth> {
+    let now = Instant::now();
+    LATEST_HEALTH.lock().unwrap().iter().map(|(name, health)| health.report(name, now)).collect()
+}
+
+/// Health of one provider in a fallback chain
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct ProviderHealth {
+    pub name: String,
+    pub consecutive_failures: u32,
+    /// Whether the circuit is open, i.e. the provider is currently being skipped
+    pub open: bool,
+    /// How long its latest successful call took
+    pub latency_ms: Option<f64>,
+}
+
+struct Provider {
+    name: String,
+    client: Box<dyn AiProvider>,
+    health: Mutex<Health>,
+}
+
+/// Tries providers in order until one answers, moving on after an error or timeout.
+/// A provider that keeps failing has its circuit opened and is skipped until the cooldown
+/// passes. The last provider is the last resort and is never skipped.
+pub struct FallbackAiClient {
+    providers: Vec<Provider>,
+    policy: FallbackPolicy,
+}
+
+impl FallbackAiClient {
+    pub fn new(policy: FallbackPolicy) -> Self {
+        Self { providers: Vec::new(), policy }
+    }
+
+    /// Append a provider to the end of the chain
+    pub fn with_provider<P: AiProvider + 'static>(mut self, name: impl Into<String>, client: P) -> Self {
+        #[cfg(feature = "chaos")]
+        let client = crate::chaos::ai(client);
+        self.providers.push(Provider {
+            name: name.into(),
+            client: Box::new(client),
+            health: Mutex::new(Health::default()),
+        });
+        self
+    }
+
+    /// The default model, then OpenAI if `OPENAI_API_KEY` is set, then Hugging Face if
+    /// `HF_API_TOKEN` is set, then `HeuristicAiClient`
+    pub fn default_chain() -> Self {
+        let mut chain = Self::new(FallbackPolicy::from_env()).with_provider("default", DefaultAiClient::new());
+        if crate::secrets::get(crate::secrets::OPENAI_API_KEY).is_some() {
+            chain = chain.with_provider("openai", OpenAiClient::new());
+        }
+        if crate::secrets::get(crate::secrets::HF_API_TOKEN).is_some() {
+            chain = chain.with_provider("huggingface", HuggingFaceClient::new());
+        }
+        chain.with_provider("heuristic", HeuristicAiClient)
+    }
+
+    pub fn health(&self) -> Vec<ProviderHealth> {
+        let now = Instant::now();
+        self.providers.iter()
+            .map(|provider| provider.health.lock().unwrap().report(&provider.name, now))
+            .collect()
+    }
+
+    fn record(&self, provider: &Provider, result: &Result<impl Sized>, latency: Duration) {
+        // The circuit covers every method, so a provider that lacks one (say, embeddings)
+        // mustn't be counted as down for the others
+        if let Err(e) = result {
+            if matches!(SwarmError::find(e), Some(SwarmError::Unsupported(_))) {
+                return;
+            }
+        }
+        let mut health = provider.health.lock().unwrap();
+        match result {
+            Ok(_) => *health = Health { last_latency: Some(latency), ..Health::default() },
+            Err(e) => {
+                health.consecutive_failures += 1;
+                tracing::warn!("AI provider '{}' failed ({} in a row): {}", provider.name, health.consecutive_failures, e);
+                events::global().publish(DomainEvent::ai_provider_failed(&provider.name, &e.to_string()));
+                if health.consecutive_failures >= self.policy.failure_threshold {
+                    health.open_until = Some(Instant::now() + self.policy.cooldown);
+                }
+            }
+        }
+        LATEST_HEALTH.lock().unwrap().insert(provider.name.clone(), health.clone());
+    }
+
+    /// Run `call` against each available provider in turn, returning the first success
+    async fn first_success<'a, T, F>(&'a self, call: F) -> Result<T>
+    where
+        F: Fn(&'a dyn AiProvider) -> futures::future::BoxFuture<'a, Result<T>>,
+    {
+        let mut failures = Vec::new();
+        for (i, provider) in self.providers.iter().enumerate() {
+            let last_resort = i + 1 == self.providers.len();
+            let open = provider.health.lock().unwrap().open_until.is_some_and(|until| Instant::now() < until);
+            if open && !last_resort {
+                failures.push(format!("{}: circuit open", provider.name));
+                continue;
+            }
+
+            let attempt = call(provider.client.as_ref()).instrument(tracing::info_span!("ai", provider = %provider.name));
+            let started = Instant::now();
+            let result = match tokio::time::timeout(self.policy.timeout, attempt).await {
+                Ok(result) => result,
+                Err(_) => Err(SwarmError::AiProvider(format!("timed out after {:?}", self.policy.timeout)).into()),
+            };
+            self.record(provider, &result, started.elapsed());
+            match result {
+                Ok(value) => return Ok(value),
+                Err(e) => failures.push(format!("{}: {}", provider.name, e)),
+            }
+        }
+        Err(SwarmError::AiProvider(format!("All AI providers failed ({})", failures.join("; "))).into())
+    }
+}
+
+#[async_trait::async_trait]
+impl AiProvider for FallbackAiClient {
+    async fn chat(&self, system_prompt: &str, messages: Vec<HashMap<String, String>>) -> Result<String> {
+        self.first_success(|client| client.chat(system_prompt, messages.clone())).await
+    }
+
+    async fn chat_with_tools(&self, system_prompt: &str, messages: Vec<HashMap<String, String>>, tools: &[Tool]) -> Result<ChatResponse> {
+        self.first_success(|client| client.chat_with_tools(system_prompt, messages.clone(), tools)).await
+    }
+
+    async fn chat_structured(&self, system_prompt: &str, messages: Vec<HashMap<String, String>>, schema: &JsonSchema) -> Result<Value> {
+        self.first_success(|client| client.chat_structured(system_prompt, messages.clone(), schema)).await
+    }
+
+    async fn embed(&self, text: &str) -> Result<Vec<f32>> {
+        self.first_success(|client| client.embed(text)).await
+    }
+
+    async fn chat_with_images(&self, system_prompt: &str, messages: Vec<HashMap<String, String>>, images: &[Attachment]) -> Result<String> {
+        self.first_success(|client| client.chat_with_images(system_prompt, messages.clone(), images)).await
+    }
+}
+
+/// Answers without a model so callers keep working when every real provider is down:
+/// a commit summary for requests containing a diff, otherwise the request's subject
+/// echoed back (e.g. the task itself for "Enhance this task: ..."), which callers that
+/// expect a classification treat as unrecognised and default.
+pub struct HeuristicAiClient;
+
+/// How many changed files a heuristic commit message names before summarizing the rest
+const MAX_NAMED_FILES: usize = 3;
+
+impl HeuristicAiClient {
+    fn reply(request: &str) -> String {
+        let files: Vec<&str> = request.lines()
+            .filter_map(|line| line.strip_prefix("diff --git a/"))
+            .filter_map(|paths| paths.split(" b/").next())
+            .collect();
+        if !files.is_empty() {
+            let named = files.iter().take(MAX_NAMED_FILES).copied().collect::<Vec<_>>().join(", ");
+            return match files.len().saturating_sub(MAX_NAMED_FILES) {
+                0 => format!("chore: update {}", named),
+                rest => format!("chore: update {} and {} other file(s)", named, rest),
+            };
+        }
+
+        match request.split_once(": ") {
+            Some((instruction, subject)) if !instruction.contains('\n') => subject.trim().to_string(),
+            _ => request.trim().to_string(),
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl AiProvider for HeuristicAiClient {
+    async fn chat(&self, _system_prompt: &str, messages: Vec<HashMap<String, String>>) -> Result<String> {
+        let request = messages.iter().rev()
+            .find(|m| m.get("role").map(String::as_str) == Some("user"))
+            .and_then(|m| m.get("content"))
+            .map(String::as_str)
+            .unwrap_or_default();
+        Ok(Self::reply(request))
+    }
+
+    /// Never calls tools; echoing the request back could otherwise be parsed as a tool call
+    async fn chat_with_tools(&self, system_prompt: &str, messages: Vec<HashMap<String, String>>, _tools: &[Tool]) -> Result<ChatResponse> {
+        self.chat(system_prompt, messages).await.map(ChatResponse::Message)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::anyhow;
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[derive(Clone, Default)]
+    struct HangingClient {
+        calls: Arc<AtomicUsize>,
+    }
+
+    #[async_trait::async_trait]
+    impl AiProvider for HangingClient {
+        async fn chat(&self, _system_prompt: &str, _messages: Vec<HashMap<String, String>>) -> Result<String> {
+            self.calls.fetch_add(1, Ordering::SeqCst);
+            std::future::pending().await
+        }
+    }
+
+    struct FailingClient;
+
+    #[async_trait::async_trait]
+    impl AiProvider for FailingClient {
+        async fn chat(&self, _system_prompt: &str, _messages: Vec<HashMap<String, String>>) -> Result<String> {
+            Err(anyhow!("connection refused"))
+        }
+    }
+
+    fn user(content: &str) -> Vec<HashMap<String, String>> {
+        vec![HashMap::from([
+            ("role".to_string(), "user".to_string()),
+            ("content".to_string(), content.to_string()),
+        ])]
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_circuit_opens_and_recovers() {
+        let primary = HangingClient::default();
+        let policy = FallbackPolicy { timeout: Duration::from_secs(1), failure_threshold: 2, cooldown: Duration::from_secs(30) };
+        let client = FallbackAiClient::new(policy)
+            .with_provider("local", primary.clone())
+            .with_provider("heuristic", HeuristicAiClient);
+
+        for _ in 0..3 {
+            assert_eq!(client.chat("", user("Enhance this task: fix login")).await.unwrap(), "fix login");
+        }
+        // The third request skipped the open circuit instead of waiting out another timeout
+        assert_eq!(primary.calls.load(Ordering::SeqCst), 2);
+        assert_eq!(client.health()[0], ProviderHealth { name: "local".to_string(), consecutive_failures: 2, open: true, latency_ms: None });
+
+        tokio::time::advance(Duration::from_secs(31)).await;
+        assert!(!client.health()[0].open);
+        client.chat("", user("again")).await.unwrap();
+        assert_eq!(primary.calls.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn test_heuristic_last_resort() {
+        let diff = "Generate a commit message for these changes:\n\n\
+            diff --git a/src/main.rs b/src/main.rs\n+fn main() {}\n\
+            diff --git a/README.md b/README.md\n+docs";
+        let client = FallbackAiClient::new(FallbackPolicy::default())
+            .with_provider("local", FailingClient)
+            .with_provider("heuristic", HeuristicAiClient);
+        assert_eq!(client.chat("", user(diff)).await.unwrap(), "chore: update src/main.rs, README.md");
+        assert_eq!(client.health()[0].consecutive_failures, 1);
+
+        // Nothing in the chain can embed, which doesn't count against either circuit
+        let error = client.embed("text").await.unwrap_err();
+        assert!(matches!(SwarmError::find(&error), Some(SwarmError::AiProvider(_))));
+        assert!(error.to_string().contains("heuristic: "), "{}", error);
+        assert_eq!(client.health()[0].consecutive_failures, 1);
+        assert_eq!(client.health()[1].consecutive_failures, 0);
+    }
+}
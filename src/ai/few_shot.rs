@@ -0,0 +1,153 @@
+//! Tasks filed under the right project by a human, shown to the project classifier as
+//! examples. Examples come from review corrections (`TodoList::review`); the ones most
+//! similar to a task go into the classification prompts, and an example closer to the
+//! task than any project description decides the project outright.
+
+use anyhow::Result;
+use tokio::sync::RwLock;
+use crate::types::review::EnhancementCorrection;
+use super::{AiProvider, cosine_similarity};
+
+/// Examples shown per prompt
+pub const DEFAULT_PROMPT_EXAMPLES: usize = 3;
+/// How similar an example must be to decide a task's project without the descriptions
+const DECIDING_SIMILARITY: f32 = 0.85;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct FewShotExample {
+    pub description: String,
+    pub project: String,
+}
+
+impl FewShotExample {
+    /// The example a review correction makes, if it set a project
+    pub fn from_correction(correction: EnhancementCorrection) -> Option<Self> {
+        Some(Self { description: correction.description, project: correction.corrected_project? })
+    }
+}
+
+/// Examples with their embeddings, computed on first use
+#[derive(Debug, Default)]
+pub struct FewShotExamples {
+    examples: RwLock<Vec<(FewShotExample, Option<Vec<f32>>)>>,
+}
+
+impl FewShotExamples {
+    pub async fn add(&self, example: FewShotExample) {
+        let mut examples = self.examples.write().await;
+        // The latest correction of a description wins
+        examples.retain(|(existing, _)| existing.description != example.description);
+        examples.push((example, None));
+    }
+
+    /// Replace the examples, keeping embeddings of the ones already known
+    pub async fn replace(&self, examples: Vec<FewShotExample>) {
+        let mut current = self.examples.write().await;
+        let known = std::mem::take(&mut *current);
+        *current = examples.into_iter()
+            .map(|example| {
+                let embedding = known.iter().find(|(e, _)| *e == example).and_then(|(_, embedding)| embedding.clone());
+                (example, embedding)
+            })
+            .collect();
+    }
+
+    pub async fn len(&self) -> usize {
+        self.examples.read().await.len()
+    }
+
+    pub async fn is_empty(&self) -> bool {
+        self.examples.read().await.is_empty()
+    }
+
+    /// Every example with its similarity to `query`, best first
+    pub async fn rank(&self, query: &[f32], ai_client: &dyn AiProvider) -> Result<Vec<(FewShotExample, f32)>> {
+        let mut examples = self.examples.write().await;
+        let mut ranked = Vec::with_capacity(examples.len());
+        for (example, embedding) in examples.iter_mut() {
+            // A different embedding model invalidates the cached embedding
+            if embedding.as_ref().map(Vec::len) != Some(query.len()) {
+                *embedding = Some(ai_client.embed(&example.description).await?);
+            }
+            let similarity = embedding.as_deref().map_or(0.0, |embedding| cosine_similarity(query, embedding));
+            ranked.push((example.clone(), similarity));
+        }
+        ranked.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(std::cmp::Ordering::Equal));
+        Ok(ranked)
+    }
+
+    /// The project of an example close enough to `query` to decide it, if one beats `to_beat`
+    pub async fn deciding_project(&self, query: &[f32], to_beat: f32, ai_client: &dyn AiProvider) -> Option<String> {
+        let ranked = self.rank(query, ai_client).await.ok()?;
+        let (example, similarity) = ranked.into_iter().next()?;
+        (similarity >= DECIDING_SIMILARITY && similarity > to_beat).then_some(example.project)
+    }
+
+    /// The `limit` examples most similar to `description`, or the latest ones when the
+    /// provider can't embed
+    pub async fn most_similar(&self, description: &str, ai_client: &dyn AiProvider, limit: usize) -> Vec<FewShotExample> {
+        if self.is_empty().await {
+            return Vec::new();
+        }
+        let ranked = match ai_client.embed(description).await {
+            Ok(query) => self.rank(&query, ai_client).await.ok(),
+            Err(_) => None,
+        };
+        match ranked {
+            Some(ranked) => ranked.into_iter().take(limit).map(|(example, _)| example).collect(),
+            None => self.examples.read().await.iter().rev().take(limit).map(|(example, _)| example.clone()).collect(),
+        }
+    }
+
+    /// A prompt section listing the examples most similar to `description`; empty without any
+    pub async fn prompt(&self, description: &str, ai_client: &dyn AiProvider) -> String {
+        let examples = self.most_similar(description, ai_client, DEFAULT_PROMPT_EXAMPLES).await;
+        if examples.is_empty() {
+            return String::new();
+        }
+        let lines: Vec<String> = examples.iter()
+            .map(|example| format!("- \"{}\" -> {}", example.description, example.project))
+            .collect();
+        format!("\n\nTasks a human filed under the right project:\n{}", lines.join("\n"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ai::MockAiProvider;
+
+    fn example(description: &str, project: &str) -> FewShotExample {
+        FewShotExample { description: description.to_string(), project: project.to_string() }
+    }
+
+    #[tokio::test]
+    async fn test_most_similar_examples_go_in_the_prompt() {
+        let examples = FewShotExamples::default();
+        examples.add(example("Rotate the grafana dashboard credentials", "lab_management")).await;
+        examples.add(example("Hotkey to tile editor windows", "hammerspoon")).await;
+        examples.add(example("Hotkey to tile editor windows", "hammerghost")).await;
+        assert_eq!(examples.len().await, 2);
+
+        let ai = MockAiProvider::new();
+        let similar = examples.most_similar("New hotkey for tiling windows", &ai, 1).await;
+        assert_eq!(similar, [example("Hotkey to tile editor windows", "hammerghost")]);
+        let prompt = examples.prompt("Rotate credentials", &ai).await;
+        assert!(prompt.starts_with("\n\nTasks a human filed under the right project:\n- \"Rotate the grafana"));
+        assert_eq!(FewShotExamples::default().prompt("Rotate credentials", &ai).await, "");
+    }
+
+    #[tokio::test]
+    async fn test_close_examples_decide_the_project() {
+        let examples = FewShotExamples::default();
+        examples.replace(vec![example("Restart the lab grafana container", "lab_management")]).await;
+        let ai = MockAiProvider::new();
+
+        let close = ai.embed("restart lab grafana container").await.unwrap();
+        assert_eq!(examples.deciding_project(&close, 0.5, &ai).await.as_deref(), Some("lab_management"));
+        // Not when a project description matches better, or the example is only loosely related
+        assert_eq!(examples.deciding_project(&close, 0.95, &ai).await, None);
+        let loose = ai.embed("restart the build server").await.unwrap();
+        assert_eq!(examples.deciding_project(&loose, 0.0, &ai).await, None);
+    }
+}
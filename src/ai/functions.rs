@@ -0,0 +1,299 @@
+use std::collections::HashMap;
+use anyhow::{Result, anyhow};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use super::AiProvider;
+use crate::tools::AgentToolset;
+use crate::types::{Tool, ToolCall};
+
+/// Upper bound on model/tool round trips before a loop is abandoned
+pub const MAX_TOOL_STEPS: usize = 8;
+
+/// A tool call requested by the model, with the provider's id for feeding the result back
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ToolCallRequest {
+    pub id: String,
+    pub call: ToolCall,
+}
+
+/// What a provider returned for a tool-enabled chat: either a final answer or calls to run
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ChatResponse {
+    Message(String),
+    ToolCalls(Vec<ToolCallRequest>),
+}
+
+/// JSON schema for a tool's parameters. Tool parameters are documented as name -> description
+/// and are always passed as strings.
+pub fn tool_schema(tool: &Tool) -> Value {
+    let mut names: Vec<&String> = tool.parameters.keys().collect();
+    names.sort();
+    let properties: serde_json::Map<String, Value> = names.iter()
+        .map(|name| ((*name).clone(), json!({
+            "type": "string",
+            "description": tool.parameters[*name],
+        })))
+        .collect();
+    json!({
+        "type": "object",
+        "properties": properties,
+    })
+}
+
+/// Instructions appended to the system prompt for providers without native tool calling
+pub fn tools_prompt(tools: &[Tool]) -> String {
+    let mut prompt = String::from(
+        "You can call the following tools. To call tools, respond with ONLY a JSON object of the form \
+{\"tool_calls\": [{\"name\": \"<tool name>\", \"arguments\": {\"<parameter>\": \"<value>\"}}]}. \
+Tool results will be sent back to you. When you have the final answer, respond with plain text.\n\nTools:\n"
+    );
+    for tool in tools {
+        prompt.push_str(&format!("- {}: {}\n  parameters: {}\n", tool.name, tool.description, tool_schema(tool)["properties"]));
+    }
+    prompt
+}
+
+/// Describe the tools in the prompt and parse JSON calls from the reply; what providers
+/// without native tool calling do
+pub async fn prompted<P: AiProvider + ?Sized>(
+    provider: &P,
+    system_prompt: &str,
+    messages: Vec<HashMap<String, String>>,
+    tools: &[Tool],
+) -> Result<ChatResponse> {
+    if tools.is_empty() {
+        return provider.chat(system_prompt, messages).await.map(ChatResponse::Message);
+    }
+
+    let prompt = format!("{}\n\n{}", system_prompt, tools_prompt(tools));
+    let response = provider.chat(&prompt, messages).await?;
+    Ok(match parse_tool_calls(&response, tools) {
+        Some(calls) => ChatResponse::ToolCalls(calls),
+        None => ChatResponse::Message(response),
+    })
+}
+
+/// Convert model-supplied JSON arguments to tool parameters
+pub fn arguments_to_params(arguments: &Value) -> HashMap<String, String> {
+    match arguments {
+        Value::Object(map) => map.iter()
+            .map(|(key, value)| {
+                let value = match value {
+                    Value::String(s) => s.clone(),
+                    other => other.to_string(),
+                };
+                (key.clone(), value)
+            })
+            .collect(),
+        _ => HashMap::new(),
+    }
+}
+
+/// Build a call request for a named tool, returning None if the tool isn't on offer
+pub fn tool_call_request(id: String, name: &str, arguments: &Value, tools: &[Tool]) -> Option<ToolCallRequest> {
+    let tool = tools.iter().find(|t| t.name == name)?;
+    Some(ToolCallRequest {
+        id,
+        call: ToolCall {
+            tool: tool.clone(),
+            parameters: arguments_to_params(arguments),
+            result: None,
+        },
+    })
+}
+
+/// Parse tool calls out of a plain-text model response.
+///
+/// Accepts `{"tool_calls": [...]}`, a single `{"name", "arguments"}` object, or
+/// `{"tool", "parameters"}`, optionally wrapped in a markdown code fence.
+pub fn parse_tool_calls(response: &str, tools: &[Tool]) -> Option<Vec<ToolCallRequest>> {
+    let trimmed = response.trim();
+    let start = trimmed.find('{')?;
+    let end = trimmed.rfind('}')?;
+    if end < start {
+        return None;
+    }
+    let value: Value = serde_json::from_str(&trimmed[start..=end]).ok()?;
+
+    let entries: Vec<Value> = match value.get("tool_calls") {
+        Some(Value::Array(calls)) => calls.clone(),
+        Some(_) => return None,
+        None => vec![value],
+    };
+
+    let calls: Option<Vec<ToolCallRequest>> = entries.iter()
+        .enumerate()
+        .map(|(i, entry)| {
+            let name = entry.get("name").or_else(|| entry.get("tool"))?.as_str()?;
+            let arguments = entry.get("arguments").or_else(|| entry.get("parameters")).cloned().unwrap_or(Value::Null);
+            // Some models double-encode arguments as a JSON string
+            let arguments = match arguments {
+                Value::String(s) => serde_json::from_str(&s).unwrap_or(Value::Null),
+                other => other,
+            };
+            tool_call_request(format!("call_{}", i), name, &arguments, tools)
+        })
+        .collect();
+
+    calls.filter(|c| !c.is_empty())
+}
+
+/// Message recording the calls the assistant made, in the format providers expect in history
+pub fn assistant_tool_calls_message(calls: &[ToolCallRequest]) -> HashMap<String, String> {
+    let encoded = Value::Array(calls.iter()
+        .map(|c| json!({
+            "id": c.id,
+            "name": c.call.tool.name,
+            "arguments": c.call.parameters,
+        }))
+        .collect());
+    HashMap::from([
+        ("role".to_string(), "assistant".to_string()),
+        ("content".to_string(), json!({ "tool_calls": encoded }).to_string()),
+        ("tool_calls".to_string(), encoded.to_string()),
+    ])
+}
+
+/// Message carrying a tool's result back to the model
+pub fn tool_result_message(request: &ToolCallRequest, result: &str) -> HashMap<String, String> {
+    HashMap::from([
+        ("role".to_string(), "tool".to_string()),
+        ("tool_call_id".to_string(), request.id.clone()),
+        ("name".to_string(), request.call.tool.name.clone()),
+        ("content".to_string(), result.to_string()),
+    ])
+}
+
+/// Run a reasoning loop: ask the model, execute any tool calls it makes through the
+/// agent's toolset, feed the results back, and repeat until it answers in text.
+///
+/// Returns the final answer and the tool calls made along the way, with results filled in.
+pub async fn run_tool_loop(
+    provider: &dyn AiProvider,
+    system_prompt: &str,
+    mut messages: Vec<HashMap<String, String>>,
+    tools: &[Tool],
+    toolset: &AgentToolset,
+    max_steps: usize,
+) -> Result<(String, Vec<ToolCall>)> {
+    let mut history = Vec::new();
+
+    for _ in 0..max_steps {
+        let calls = match provider.chat_with_tools(system_prompt, messages.clone(), tools).await? {
+            ChatResponse::Message(answer) => return Ok((answer, history)),
+            ChatResponse::ToolCalls(calls) => calls,
+        };
+
+        messages.push(assistant_tool_calls_message(&calls));
+        for mut request in calls {
+            tracing::debug!("Model requested tool '{}' with {:?}", request.call.tool.name, request.call.parameters);
+            // Errors go back to the model so it can correct itself
+            let result = match toolset.execute(&request.call.tool, request.call.parameters.clone()).await {
+                Ok(result) => result,
+                Err(e) => format!("Error: {}", e),
+            };
+            messages.push(tool_result_message(&request, &result));
+            request.call.result = Some(result);
+            history.push(request.call);
+        }
+    }
+
+    Err(anyhow!("Model did not produce a final answer within {} tool steps", max_steps))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use async_trait::async_trait;
+    use tokio::sync::RwLock;
+    use crate::tools::{ToolExecutor, ToolRegistry};
+    use crate::types::AgentConfig;
+
+    fn weather_tool() -> Tool {
+        Tool {
+            name: "weather".to_string(),
+            description: "Current weather for a city".to_string(),
+            parameters: HashMap::from([("city".to_string(), "City name".to_string())]),
+        }
+    }
+
+    #[test]
+    fn test_parse_tool_calls() {
+        let tools = vec![weather_tool()];
+
+        let calls = parse_tool_calls(
+            "```json\n{\"tool_calls\": [{\"name\": \"weather\", \"arguments\": {\"city\": \"Oslo\"}}]}\n```",
+            &tools,
+        ).unwrap();
+        assert_eq!(calls.len(), 1);
+        assert_eq!(calls[0].call.parameters["city"], "Oslo");
+
+        let calls = parse_tool_calls(r#"{"tool": "weather", "parameters": "{\"city\": \"Rome\"}"}"#, &tools).unwrap();
+        assert_eq!(calls[0].call.parameters["city"], "Rome");
+
+        assert!(parse_tool_calls("It is sunny.", &tools).is_none());
+        assert!(parse_tool_calls(r#"{"name": "unknown", "arguments": {}}"#, &tools).is_none());
+    }
+
+    struct ScriptedProvider {
+        calls: AtomicUsize,
+    }
+
+    #[async_trait]
+    impl AiProvider for ScriptedProvider {
+        async fn chat(&self, _system_prompt: &str, messages: Vec<HashMap<String, String>>) -> Result<String> {
+            match self.calls.fetch_add(1, Ordering::SeqCst) {
+                0 => Ok(r#"{"name": "weather", "arguments": {"city": "Oslo"}}"#.to_string()),
+                _ => {
+                    let last = messages.last().unwrap();
+                    assert_eq!(last["role"], "tool");
+                    Ok(format!("The weather is {}", last["content"]))
+                }
+            }
+        }
+    }
+
+    struct WeatherTool;
+
+    #[async_trait]
+    impl ToolExecutor for WeatherTool {
+        async fn execute(&self, params: HashMap<String, String>) -> Result<String> {
+            Ok(format!("cold in {}", params["city"]))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_tool_loop_executes_and_feeds_back() -> Result<()> {
+        let mut registry = ToolRegistry::new();
+        registry.register("weather".to_string(), WeatherTool);
+        let config = AgentConfig {
+            name: "forecaster".to_string(),
+            public_description: "Weather agent".to_string(),
+            instructions: "Answer weather questions".to_string(),
+            tools: vec![weather_tool()],
+            downstream_agents: vec![],
+            personality: None,
+            state_machine: None,
+            script: None,
+            max_concurrency: None,
+            middleware: None,
+            remote: None,
+        };
+        let toolset = AgentToolset::new(&config, Arc::new(RwLock::new(registry)));
+        let provider = ScriptedProvider { calls: AtomicUsize::new(0) };
+
+        let messages = vec![HashMap::from([
+            ("role".to_string(), "user".to_string()),
+            ("content".to_string(), "How is Oslo?".to_string()),
+        ])];
+        let (answer, calls) = run_tool_loop(&provider, "You forecast", messages, &config.tools, &toolset, MAX_TOOL_STEPS).await?;
+
+        assert_eq!(answer, "The weather is cold in Oslo");
+        assert_eq!(calls.len(), 1);
+        assert_eq!(calls[0].result.as_deref(), Some("cold in Oslo"));
+        Ok(())
+    }
+}
@@ -0,0 +1,314 @@
+use std::collections::HashMap;
+use anyhow::Result;
+use serde_json::{Value, json};
+use tracing::{debug, error};
+use crate::error::SwarmError;
+use super::{AiProvider, JsonSchema, structured};
+
+const DEFAULT_MODEL: &str = "mistralai/Mistral-7B-Instruct-v0.3";
+const INFERENCE_API: &str = "https://api-inference.huggingface.co/models";
+const DEFAULT_MAX_TOKENS: u32 = 1024;
+
+/// Which Inference API route a model is called through
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HuggingFaceApi {
+    /// `/v1/chat/completions`; the server applies the model's own chat template
+    Chat,
+    /// Raw `text-generation`, with the conversation rendered through a `PromptTemplate`
+    TextGeneration(PromptTemplate),
+}
+
+/// How a conversation is flattened into one prompt for text-generation models
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PromptTemplate {
+    /// `### role:` sections, for base models
+    Plain,
+    /// `<|im_start|>role ... <|im_end|>`, used by Qwen, Hermes and others
+    ChatMl,
+    /// Llama 3 header tokens
+    Llama3,
+    /// `[INST] ... [/INST]`, used by Mistral and Mixtral
+    Mistral,
+}
+
+impl PromptTemplate {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "plain" => Some(Self::Plain),
+            "chatml" => Some(Self::ChatMl),
+            "llama3" => Some(Self::Llama3),
+            "mistral" => Some(Self::Mistral),
+            _ => None,
+        }
+    }
+
+    /// The prompt for `messages`, ending where the assistant's reply should start
+    pub fn render(&self, system_prompt: &str, messages: &[HashMap<String, String>]) -> String {
+        let turns = messages.iter().map(|message| (
+            message.get("role").map(String::as_str).unwrap_or("user"),
+            message.get("content").map(String::as_str).unwrap_or_default(),
+        ));
+        match self {
+            Self::Plain => {
+                let mut prompt = format!("### System:\n{}\n\n", system_prompt);
+                for (role, content) in turns {
+                    prompt.push_str(&format!("### {}:\n{}\n\n", role, content));
+                }
+                prompt.push_str("### assistant:\n");
+                prompt
+            }
+            Self::ChatMl => {
+                let mut prompt = format!("<|im_start|>system\n{}<|im_end|>\n", system_prompt);
+                for (role, content) in turns {
+                    prompt.push_str(&format!("<|im_start|>{}\n{}<|im_end|>\n", role, content));
+                }
+                prompt.push_str("<|im_start|>assistant\n");
+                prompt
+            }
+            Self::Llama3 => {
+                let header = |role: &str| format!("<|start_header_id|>{}<|end_header_id|>\n\n", role);
+                let mut prompt = format!("<|begin_of_text|>{}{}<|eot_id|>", header("system"), system_prompt);
+                for (role, content) in turns {
+                    prompt.push_str(&format!("{}{}<|eot_id|>", header(role), content));
+                }
+                prompt.push_str(&header("assistant"));
+                prompt
+            }
+            Self::Mistral => {
+                // No system role: it goes in front of the first instruction
+                let mut prompt = String::from("<s>");
+                let mut pending = vec![system_prompt.to_string()];
+                for (role, content) in turns {
+                    if role == "assistant" {
+                        prompt.push_str(&format!("[INST] {} [/INST] {}</s>", pending.join("\n\n"), content));
+                        pending.clear();
+                    } else {
+                        pending.push(content.to_string());
+                    }
+                }
+                prompt.push_str(&format!("[INST] {} [/INST]", pending.join("\n\n")));
+                prompt
+            }
+        }
+    }
+}
+
+/// Client for Hugging Face Inference API models and dedicated Inference Endpoints
+#[derive(Debug, Clone)]
+pub struct HuggingFaceClient {
+    model: String,
+    token: Option<String>,
+    /// A dedicated endpoint's URL; the serverless API for `model` when unset
+    endpoint: Option<String>,
+    api: HuggingFaceApi,
+    max_tokens: u32,
+}
+
+impl Default for HuggingFaceClient {
+    /// Read `HF_MODEL`, `HF_ENDPOINT`, `HF_API` (`chat` or `text-generation`),
+    /// `HF_PROMPT_TEMPLATE` and `HF_MAX_TOKENS`; the token is the `HF_API_TOKEN` secret
+    fn default() -> Self {
+        let api = match std::env::var("HF_API").as_deref() {
+            Ok("text-generation") => {
+                let template = std::env::var("HF_PROMPT_TEMPLATE").ok()
+                    .and_then(|name| PromptTemplate::parse(&name))
+                    .unwrap_or(PromptTemplate::Plain);
+                HuggingFaceApi::TextGeneration(template)
+            }
+            _ => HuggingFaceApi::Chat,
+        };
+        Self {
+            model: std::env::var("HF_MODEL").unwrap_or_else(|_| DEFAULT_MODEL.to_string()),
+            token: crate::secrets::get(crate::secrets::HF_API_TOKEN),
+            endpoint: std::env::var("HF_ENDPOINT").ok().filter(|url| !url.is_empty()),
+            api,
+            max_tokens: std::env::var("HF_MAX_TOKENS").ok().and_then(|v| v.parse().ok()).unwrap_or(DEFAULT_MAX_TOKENS),
+        }
+    }
+}
+
+impl HuggingFaceClient {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_model(mut self, model: String) -> Self {
+        self.model = model;
+        self
+    }
+
+    pub fn with_token(mut self, token: String) -> Self {
+        self.token = Some(token);
+        self
+    }
+
+    pub fn with_endpoint(mut self, endpoint: String) -> Self {
+        self.endpoint = Some(endpoint);
+        self
+    }
+
+    pub fn with_api(mut self, api: HuggingFaceApi) -> Self {
+        self.api = api;
+        self
+    }
+
+    pub fn with_max_tokens(mut self, max_tokens: u32) -> Self {
+        self.max_tokens = max_tokens;
+        self
+    }
+
+    fn base_url(&self) -> String {
+        match &self.endpoint {
+            Some(endpoint) => endpoint.trim_end_matches('/').to_string(),
+            None => format!("{}/{}", INFERENCE_API, self.model),
+        }
+    }
+
+    /// The URL and body of a request for the conversation, constrained by TGI's JSON
+    /// grammar to `schema` if given
+    fn request(&self, system_prompt: &str, messages: &[HashMap<String, String>], schema: Option<&JsonSchema>) -> (String, Value) {
+        match self.api {
+            HuggingFaceApi::Chat => {
+                let mut chat = vec![json!({ "role": "system", "content": system_prompt })];
+                chat.extend(messages.iter().map(|message| {
+                    let role = match message.get("role").map(String::as_str) {
+                        Some(role @ ("system" | "assistant")) => role,
+                        _ => "user",
+                    };
+                    json!({ "role": role, "content": message.get("content").cloned().unwrap_or_default() })
+                }));
+                let mut body = json!({ "model": self.model, "messages": chat, "max_tokens": self.max_tokens });
+                if let Some(schema) = schema {
+                    body["response_format"] = json!({ "type": "json_object", "value": schema.schema });
+                }
+                (format!("{}/v1/chat/completions", self.base_url()), body)
+            }
+            HuggingFaceApi::TextGeneration(template) => {
+                let mut body = json!({
+                    "inputs": template.render(system_prompt, messages),
+                    "parameters": { "max_new_tokens": self.max_tokens, "return_full_text": false },
+                });
+                if let Some(schema) = schema {
+                    body["parameters"]["grammar"] = json!({ "type": "json", "value": schema.schema });
+                }
+                (self.base_url(), body)
+            }
+        }
+    }
+
+    /// The generated text in a response body
+    fn parse_response(&self, body: &Value) -> Result<String> {
+        if let Some(message) = body["error"].as_str() {
+            return Err(SwarmError::AiProvider(format!("Hugging Face error: {}", message)).into());
+        }
+        let text = match self.api {
+            HuggingFaceApi::Chat => body["choices"][0]["message"]["content"].as_str(),
+            // The serverless API returns a list, dedicated endpoints a single object
+            HuggingFaceApi::TextGeneration(_) => body[0]["generated_text"].as_str().or(body["generated_text"].as_str()),
+        };
+        text.map(|text| text.trim().to_string())
+            .ok_or_else(|| SwarmError::AiProvider(format!("Unexpected Hugging Face response: {}", body)).into())
+    }
+
+    async fn send(&self, url: &str, body: &Value) -> Result<String> {
+        debug!("Sending prompt to Hugging Face model {} at {}", self.model, url);
+
+        // Wait for a cold model to load instead of failing with 503
+        let mut request = reqwest::Client::new().post(url).header("x-wait-for-model", "true").json(body);
+        if let Some(token) = &self.token {
+            request = request.bearer_auth(token);
+        }
+        let response = request.send().await
+            .map_err(|e| SwarmError::AiProvider(format!("Hugging Face request failed: {}", e)))?;
+
+        let status = response.status();
+        let body: Value = match response.text().await {
+            Ok(text) => serde_json::from_str(&text).unwrap_or(json!({ "error": text })),
+            Err(e) => return Err(SwarmError::AiProvider(format!("Failed to read Hugging Face response: {}", e)).into()),
+        };
+        if !status.is_success() {
+            error!("Hugging Face request failed: {} {}", status, body);
+            let message = body["error"].as_str().map(String::from).unwrap_or_else(|| body.to_string());
+            return Err(SwarmError::AiProvider(format!("Hugging Face request failed: {} {}", status, message)).into());
+        }
+        self.parse_response(&body)
+    }
+}
+
+#[async_trait::async_trait]
+impl AiProvider for HuggingFaceClient {
+    async fn chat(&self, system_prompt: &str, messages: Vec<HashMap<String, String>>) -> Result<String> {
+        let (url, body) = self.request(system_prompt, &messages, None);
+        self.send(&url, &body).await
+    }
+
+    async fn chat_structured(&self, system_prompt: &str, messages: Vec<HashMap<String, String>>, schema: &JsonSchema) -> Result<Value> {
+        let (url, body) = self.request(system_prompt, &messages, Some(schema));
+        match self.send(&url, &body).await {
+            Ok(reply) => structured::parse_or_clean(self, schema, &reply).await,
+            Err(e) => {
+                // Only TGI-backed models take grammars
+                debug!("Structured output unavailable ({}), asking for JSON in the prompt", e);
+                structured::prompted(self, system_prompt, messages, schema).await
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn conversation() -> Vec<HashMap<String, String>> {
+        [("user", "Hi"), ("assistant", "Hello!"), ("user", "Plan the release")].iter()
+            .map(|(role, content)| HashMap::from([
+                ("role".to_string(), role.to_string()),
+                ("content".to_string(), content.to_string()),
+            ]))
+            .collect()
+    }
+
+    #[test]
+    fn test_prompt_templates() {
+        let messages = conversation();
+        assert_eq!(
+            PromptTemplate::ChatMl.render("Be brief", &messages),
+            "<|im_start|>system\nBe brief<|im_end|>\n<|im_start|>user\nHi<|im_end|>\n\
+             <|im_start|>assistant\nHello!<|im_end|>\n<|im_start|>user\nPlan the release<|im_end|>\n<|im_start|>assistant\n"
+        );
+        assert_eq!(
+            PromptTemplate::Mistral.render("Be brief", &messages),
+            "<s>[INST] Be brief\n\nHi [/INST] Hello!</s>[INST] Plan the release [/INST]"
+        );
+        let llama = PromptTemplate::Llama3.render("Be brief", &messages);
+        assert!(llama.starts_with("<|begin_of_text|><|start_header_id|>system<|end_header_id|>\n\nBe brief<|eot_id|>"));
+        assert!(llama.ends_with("<|start_header_id|>assistant<|end_header_id|>\n\n"));
+        assert_eq!(PromptTemplate::parse("ChatML"), Some(PromptTemplate::ChatMl));
+        assert_eq!(PromptTemplate::parse("alpaca"), None);
+    }
+
+    #[test]
+    fn test_requests_and_responses() {
+        let chat = HuggingFaceClient::new().with_model("org/model".to_string()).with_api(HuggingFaceApi::Chat);
+        let (url, body) = chat.request("Be brief", &conversation(), None);
+        assert_eq!(url, "https://api-inference.huggingface.co/models/org/model/v1/chat/completions");
+        assert_eq!(body["messages"][0]["role"], "system");
+        assert_eq!(body["messages"][3]["content"], "Plan the release");
+        let reply = json!({ "choices": [{ "message": { "role": "assistant", "content": " Ship it " } }] });
+        assert_eq!(chat.parse_response(&reply).unwrap(), "Ship it");
+
+        let generation = chat.with_api(HuggingFaceApi::TextGeneration(PromptTemplate::Plain))
+            .with_endpoint("https://abc.endpoints.huggingface.cloud/".to_string())
+            .with_max_tokens(64);
+        let schema = JsonSchema::new("answer", json!({ "type": "object" }));
+        let (url, body) = generation.request("Be brief", &conversation(), Some(&schema));
+        assert_eq!(url, "https://abc.endpoints.huggingface.cloud");
+        assert_eq!(body["parameters"]["max_new_tokens"], 64);
+        assert_eq!(body["parameters"]["grammar"], json!({ "type": "json", "value": { "type": "object" } }));
+        assert!(body["inputs"].as_str().unwrap().ends_with("### assistant:\n"));
+        assert_eq!(generation.parse_response(&json!([{ "generated_text": "Done" }])).unwrap(), "Done");
+        assert_eq!(generation.parse_response(&json!({ "generated_text": "Done" })).unwrap(), "Done");
+        let loading = generation.parse_response(&json!({ "error": "Model is currently loading" })).unwrap_err();
+        assert!(loading.to_string().contains("Model is currently loading"));
+    }
+}
@@ -1,22 +1,38 @@
 use std::collections::HashMap;
-use serde_json::Value;
-use anyhow::{Result, anyhow};
-use super::AiProvider;
+use serde_json::{Value, json};
+use anyhow::Result;
+use crate::error::SwarmError;
+use super::{AiProvider, JsonSchema, structured, vision};
+use super::vision::EncodedImage;
+use crate::types::Attachment;
+use super::streaming::{self, Utf8Chunker};
+use std::process::Stdio;
+use tokio::io::AsyncReadExt;
 use tokio::process::Command as TokioCommand;
 use tracing::{debug, warn, error};
 
 const DEFAULT_MODEL: &str = "qwen2.5";
 const OLLAMA_CMD: &str = "ollama";
+const DEFAULT_EMBED_MODEL: &str = "nomic-embed-text";
+const DEFAULT_VISION_MODEL: &str = "llava";
+const DEFAULT_OLLAMA_HOST: &str = "http://localhost:11434";
 
 #[derive(Debug, Clone)]
 pub struct LocalAiClient {
     model: String,
+    embed_model: String,
+    /// Used for chats with images
+    vision_model: String,
 }
 
 impl Default for LocalAiClient {
     fn default() -> Self {
         Self {
             model: DEFAULT_MODEL.to_string(),
+            embed_model: std::env::var("OLLAMA_EMBED_MODEL")
+                .unwrap_or_else(|_| DEFAULT_EMBED_MODEL.to_string()),
+            vision_model: std::env::var("OLLAMA_VISION_MODEL")
+                .unwrap_or_else(|_| DEFAULT_VISION_MODEL.to_string()),
         }
     }
 }
@@ -31,18 +47,41 @@ impl LocalAiClient {
         self
     }
 
+    pub fn with_embed_model(mut self, model: String) -> Self {
+        self.embed_model = model;
+        self
+    }
+
+    pub fn embed_model(&self) -> &str {
+        &self.embed_model
+    }
+
+    pub fn with_vision_model(mut self, model: String) -> Self {
+        self.vision_model = model;
+        self
+    }
+
+    fn ollama_host() -> String {
+        let host = std::env::var("OLLAMA_HOST").unwrap_or_else(|_| DEFAULT_OLLAMA_HOST.to_string());
+        if host.starts_with("http://") || host.starts_with("https://") {
+            host.trim_end_matches('/').to_string()
+        } else {
+            format!("http://{}", host.trim_end_matches('/'))
+        }
+    }
+
     async fn check_model_availability(&self) -> Result<bool> {
         debug!("Checking availability of model: {}", self.model);
         let output = TokioCommand::new(OLLAMA_CMD)
             .args(["list"])
             .output()
             .await
-            .map_err(|e| anyhow!("Failed to execute ollama list command: {}", e))?;
+            .map_err(|e| SwarmError::AiProvider(format!("Failed to execute ollama list command: {}", e)))?;
 
         if !output.status.success() {
             let err = String::from_utf8_lossy(&output.stderr);
             error!("Failed to list models: {}", err);
-            return Err(anyhow!("Failed to list models: {}", err));
+            return Err(SwarmError::AiProvider(format!("Failed to list models: {}", err)).into());
         }
 
         let output_str = String::from_utf8_lossy(&output.stdout);
@@ -56,12 +95,12 @@ impl LocalAiClient {
                 .args(["pull", &self.model])
                 .output()
                 .await
-                .map_err(|e| anyhow!("Failed to pull model: {}", e))?;
+                .map_err(|e| SwarmError::AiProvider(format!("Failed to pull model: {}", e)))?;
 
             if !output.status.success() {
                 let err = String::from_utf8_lossy(&output.stderr);
                 error!("Failed to pull model {}: {}", self.model, err);
-                return Err(anyhow!("Failed to pull model {}: {}", self.model, err));
+                return Err(SwarmError::AiProvider(format!("Failed to pull model {}: {}", self.model, err)).into());
             }
         }
         Ok(())
@@ -82,6 +121,83 @@ impl LocalAiClient {
 
         formatted
     }
+
+    /// Chat through the server's HTTP API, which takes images on the last user message
+    /// and, when `format` is a schema, enforces it with a llama.cpp grammar
+    async fn chat_api(&self, system_prompt: &str, messages: &[HashMap<String, String>], images: &[EncodedImage], format: Option<&Value>) -> Result<String> {
+        self.ensure_model().await?;
+        let mut chat = vec![json!({ "role": "system", "content": system_prompt })];
+        chat.extend(messages.iter().map(|message| json!({
+            "role": message.get("role").map(String::as_str).unwrap_or("user"),
+            "content": message.get("content").cloned().unwrap_or_default(),
+        })));
+        if !images.is_empty() {
+            let index = vision::image_message_index(&chat)
+                .ok_or_else(|| SwarmError::Validation("Images need a user message to go with".to_string()))?;
+            chat[index]["images"] = images.iter().map(|image| image.data.clone()).collect();
+        }
+        let mut body = json!({ "model": self.model, "messages": chat, "stream": false });
+        if let Some(format) = format {
+            body["format"] = format.clone();
+        }
+        let url = format!("{}/api/chat", Self::ollama_host());
+        debug!("Requesting chat from {} with model {}", url, self.model);
+
+        let response = reqwest::Client::new()
+            .post(&url)
+            .json(&body)
+            .send()
+            .await
+            .map_err(|e| SwarmError::AiProvider(format!("Failed to call ollama chat API: {}", e)))?;
+        if !response.status().is_success() {
+            let status = response.status();
+            let err = response.text().await.unwrap_or_default();
+            return Err(SwarmError::AiProvider(format!("Ollama chat request failed: {} {}", status, err)).into());
+        }
+        let body: Value = response.json().await
+            .map_err(|e| SwarmError::AiProvider(format!("Failed to parse ollama chat response: {}", e)))?;
+        body["message"]["content"].as_str()
+            .map(String::from)
+            .ok_or_else(|| SwarmError::AiProvider("Ollama chat response missing content".to_string()).into())
+    }
+
+    /// Run the prompt, forwarding output to the task's chunk sink as ollama prints it
+    async fn run_streaming(&self, prompt: &str) -> Result<String> {
+        let mut child = TokioCommand::new(OLLAMA_CMD)
+            .args(["run", &self.model, prompt])
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true)
+            .spawn()
+            .map_err(|e| SwarmError::AiProvider(format!("Failed to execute ollama command: {}", e)))?;
+
+        let mut stdout = child.stdout.take().expect("stdout is piped");
+        let mut chunker = Utf8Chunker::default();
+        let mut output = String::new();
+        let mut buffer = [0u8; 512];
+        loop {
+            let read = stdout.read(&mut buffer).await
+                .map_err(|e| SwarmError::AiProvider(format!("Failed to read ollama output: {}", e)))?;
+            let chunk = if read == 0 { chunker.finish() } else { chunker.push(&buffer[..read]) };
+            if !chunk.is_empty() {
+                streaming::emit_chunk(&chunk);
+                output.push_str(&chunk);
+            }
+            if read == 0 {
+                break;
+            }
+        }
+
+        let finished = child.wait_with_output().await
+            .map_err(|e| SwarmError::AiProvider(format!("Failed to wait for ollama: {}", e)))?;
+        if finished.status.success() {
+            Ok(output)
+        } else {
+            let err = String::from_utf8_lossy(&finished.stderr);
+            error!("Ollama command failed: {}", err);
+            Err(SwarmError::AiProvider(format!("Ollama command failed: {}", err)).into())
+        }
+    }
 }
 
 #[async_trait::async_trait]
@@ -93,6 +209,9 @@ impl AiProvider for LocalAiClient {
         // Format the messages into a structured prompt
         let prompt = self.format_prompt(system_prompt, &messages);
         debug!("Sending prompt to Ollama model {}", self.model);
+        if streaming::is_streaming() {
+            return self.run_streaming(&prompt).await;
+        }
 
         // Execute ollama CLI command with timeout
         let output = TokioCommand::new(OLLAMA_CMD)
@@ -105,20 +224,64 @@ impl AiProvider for LocalAiClient {
             .await
             .map_err(|e| {
                 error!("Failed to execute ollama command: {}", e);
-                anyhow!("Failed to execute ollama command: {}", e)
+                SwarmError::AiProvider(format!("Failed to execute ollama command: {}", e))
             })?;
 
         if output.status.success() {
             String::from_utf8(output.stdout)
                 .map_err(|e| {
                     error!("Failed to parse ollama output: {}", e);
-                    anyhow!("Failed to parse ollama output: {}", e)
+                    SwarmError::AiProvider(format!("Failed to parse ollama output: {}", e)).into()
                 })
         } else {
             let err = String::from_utf8_lossy(&output.stderr);
             error!("Ollama command failed: {}", err);
-            Err(anyhow!("Ollama command failed: {}", err))
+            Err(SwarmError::AiProvider(format!("Ollama command failed: {}", err)).into())
+        }
+    }
+
+    async fn chat_structured(&self, system_prompt: &str, messages: Vec<HashMap<String, String>>, schema: &JsonSchema) -> Result<Value> {
+        match self.chat_api(system_prompt, &messages, &[], Some(&schema.schema)).await {
+            Ok(reply) => structured::parse_or_clean(self, schema, &reply).await,
+            Err(e) => {
+                warn!("Structured output unavailable ({}), asking for JSON in the prompt", e);
+                structured::prompted(self, system_prompt, messages, schema).await
+            }
+        }
+    }
+
+    async fn embed(&self, text: &str) -> Result<Vec<f32>> {
+        // The ollama CLI has no embedding command, so use the server's HTTP API
+        let url = format!("{}/api/embeddings", Self::ollama_host());
+        debug!("Requesting embedding from {} with model {}", url, self.embed_model);
+
+        let response = reqwest::Client::new()
+            .post(&url)
+            .json(&serde_json::json!({ "model": self.embed_model, "prompt": text }))
+            .send()
+            .await
+            .map_err(|e| SwarmError::AiProvider(format!("Failed to call ollama embeddings API: {}", e)))?;
+
+        if !response.status().is_success() {
+            let status = response.status();
+            let err = response.text().await.unwrap_or_default();
+            error!("Ollama embeddings request failed: {} {}", status, err);
+            return Err(SwarmError::AiProvider(format!("Ollama embeddings request failed: {} {}", status, err)).into());
+        }
+
+        let body: Value = response.json().await
+            .map_err(|e| SwarmError::AiProvider(format!("Failed to parse ollama embeddings response: {}", e)))?;
+        serde_json::from_value(body["embedding"].clone())
+            .map_err(|e| SwarmError::AiProvider(format!("Ollama embeddings response missing embedding: {}", e)).into())
+    }
+
+    async fn chat_with_images(&self, system_prompt: &str, messages: Vec<HashMap<String, String>>, images: &[Attachment]) -> Result<String> {
+        let images = vision::encode_images(images)?;
+        if images.is_empty() {
+            return self.chat(system_prompt, messages).await;
         }
+        let vision_client = self.clone().with_model(self.vision_model.clone());
+        vision_client.chat_api(system_prompt, &messages, &images, None).await
     }
 }
 
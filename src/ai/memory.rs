@@ -0,0 +1,210 @@
+use std::collections::{HashMap, VecDeque};
+use std::env;
+use std::sync::Arc;
+use tokio::sync::{Mutex, RwLock};
+use crate::types::Message;
+use crate::types::sessions::message_session_id;
+use super::{AiProvider, streaming};
+
+/// Session used for messages that don't carry a session id
+pub const DEFAULT_SESSION: &str = "default";
+const DEFAULT_WINDOW: usize = 12;
+/// Cap on the summary kept when the AI provider can't summarize for us
+const MAX_FALLBACK_SUMMARY_CHARS: usize = 2000;
+
+const SUMMARY_PROMPT: &str = "You maintain a running summary of a conversation between a user and an assistant. \
+Merge the previous summary with the new messages into one concise summary of at most 150 words. \
+Keep names, topics, decisions and anything the user is still waiting on. Reply with the summary only.";
+
+#[derive(Debug, Default)]
+struct SessionMemory {
+    summary: Option<String>,
+    recent: VecDeque<(String, String)>,
+}
+
+/// Rolling per-session conversation history. The most recent messages are kept verbatim;
+/// once the window overflows, the oldest half is folded into a summary by the AI provider.
+pub struct ConversationMemory {
+    window: usize,
+    sessions: RwLock<HashMap<String, Arc<Mutex<SessionMemory>>>>,
+}
+
+impl Default for ConversationMemory {
+    /// Window size from `MEMORY_WINDOW`, defaulting to 12 messages
+    fn default() -> Self {
+        let window = env::var("MEMORY_WINDOW")
+            .ok()
+            .and_then(|w| w.parse().ok())
+            .unwrap_or(DEFAULT_WINDOW);
+        Self::new(window)
+    }
+}
+
+impl ConversationMemory {
+    pub fn new(window: usize) -> Self {
+        Self {
+            window: window.max(2),
+            sessions: RwLock::new(HashMap::new()),
+        }
+    }
+
+    async fn session(&self, session_id: &str) -> Arc<Mutex<SessionMemory>> {
+        if let Some(session) = self.sessions.read().await.get(session_id) {
+            return session.clone();
+        }
+        self.sessions.write().await.entry(session_id.to_string()).or_default().clone()
+    }
+
+    /// Record a message, summarizing older history if the window is full
+    pub async fn remember(&self, session_id: &str, role: &str, content: &str, ai_client: &dyn AiProvider) {
+        let session = self.session(session_id).await;
+        let mut memory = session.lock().await;
+        memory.recent.push_back((role.to_string(), content.to_string()));
+        if memory.recent.len() <= self.window {
+            return;
+        }
+
+        let excess = memory.recent.len() - self.window / 2;
+        let overflow: Vec<(String, String)> = memory.recent.drain(..excess).collect();
+        let transcript: Vec<String> = overflow.iter().map(|(role, content)| format!("{}: {}", role, content)).collect();
+        let request = format!(
+            "Previous summary: {}\n\nNew messages:\n{}",
+            memory.summary.as_deref().unwrap_or("(none)"),
+            transcript.join("\n")
+        );
+        let messages = vec![HashMap::from([
+            ("role".to_string(), "user".to_string()),
+            ("content".to_string(), request),
+        ])];
+
+        memory.summary = match streaming::silenced(ai_client.chat(SUMMARY_PROMPT, messages)).await {
+            Ok(summary) => Some(summary.trim().to_string()),
+            Err(e) => {
+                tracing::warn!("Failed to summarize conversation {}: {}", session_id, e);
+                let mut summary = memory.summary.take().unwrap_or_default();
+                for line in transcript {
+                    summary.push('\n');
+                    summary.push_str(&line);
+                }
+                Some(keep_tail(summary.trim(), MAX_FALLBACK_SUMMARY_CHARS))
+            }
+        };
+    }
+
+    /// Record a user message and the reply to it
+    pub async fn remember_exchange(&self, session_id: &str, user: &str, assistant: &str, ai_client: &dyn AiProvider) {
+        self.remember(session_id, "user", user, ai_client).await;
+        self.remember(session_id, "assistant", assistant, ai_client).await;
+    }
+
+    pub async fn summary(&self, session_id: &str) -> Option<String> {
+        let session = self.sessions.read().await.get(session_id).cloned()?;
+        let memory = session.lock().await;
+        memory.summary.clone()
+    }
+
+    /// Recent messages, oldest first, in the format `AiProvider::chat` expects
+    pub async fn recent_messages(&self, session_id: &str) -> Vec<HashMap<String, String>> {
+        let Some(session) = self.sessions.read().await.get(session_id).cloned() else {
+            return Vec::new();
+        };
+        let memory = session.lock().await;
+        memory.recent.iter()
+            .map(|(role, content)| HashMap::from([
+                ("role".to_string(), role.clone()),
+                ("content".to_string(), content.clone()),
+            ]))
+            .collect()
+    }
+
+    /// The system prompt with the session's summary appended, if there is one
+    pub async fn system_prompt(&self, session_id: &str, base: &str) -> String {
+        match self.summary(session_id).await {
+            Some(summary) => format!("{}\n\nSummary of the earlier conversation:\n{}", base, summary),
+            None => base.to_string(),
+        }
+    }
+
+    pub async fn forget(&self, session_id: &str) {
+        self.sessions.write().await.remove(session_id);
+    }
+}
+
+/// Memory session for a message: its session id, or the default session
+pub fn memory_session(message: &Message) -> String {
+    message_session_id(message).unwrap_or_else(|| DEFAULT_SESSION.to_string())
+}
+
+/// The last `max_chars` characters of `text`
+fn keep_tail(text: &str, max_chars: usize) -> String {
+    let count = text.chars().count();
+    text.chars().skip(count.saturating_sub(max_chars)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::{Result, anyhow};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[derive(Default)]
+    struct SummarizingClient {
+        calls: AtomicUsize,
+    }
+
+    #[async_trait::async_trait]
+    impl AiProvider for SummarizingClient {
+        async fn chat(&self, system_prompt: &str, messages: Vec<HashMap<String, String>>) -> Result<String> {
+            assert_eq!(system_prompt, SUMMARY_PROMPT);
+            self.calls.fetch_add(1, Ordering::SeqCst);
+            let request = &messages[0]["content"];
+            Ok(format!("summary of {} lines", request.lines().filter(|l| l.starts_with("user:") || l.starts_with("assistant:")).count()))
+        }
+    }
+
+    struct FailingClient;
+
+    #[async_trait::async_trait]
+    impl AiProvider for FailingClient {
+        async fn chat(&self, _system_prompt: &str, _messages: Vec<HashMap<String, String>>) -> Result<String> {
+            Err(anyhow!("model unavailable"))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_overflow_is_summarized() {
+        let memory = ConversationMemory::new(4);
+        let client = SummarizingClient::default();
+        for i in 0..4 {
+            memory.remember("abc", "user", &format!("message {}", i), &client).await;
+        }
+        assert_eq!(client.calls.load(Ordering::SeqCst), 0);
+        assert!(memory.summary("abc").await.is_none());
+
+        memory.remember("abc", "assistant", "message 4", &client).await;
+        assert_eq!(client.calls.load(Ordering::SeqCst), 1);
+        assert_eq!(memory.summary("abc").await.as_deref(), Some("summary of 3 lines"));
+
+        let recent = memory.recent_messages("abc").await;
+        assert_eq!(recent.len(), 2);
+        assert_eq!(recent[1]["role"], "assistant");
+        assert_eq!(recent[1]["content"], "message 4");
+
+        let prompt = memory.system_prompt("abc", "Be kind.").await;
+        assert!(prompt.starts_with("Be kind.\n\nSummary of the earlier conversation:\nsummary of 3 lines"));
+        assert_eq!(memory.system_prompt("other", "Be kind.").await, "Be kind.");
+    }
+
+    #[tokio::test]
+    async fn test_summary_falls_back_to_transcript() {
+        let memory = ConversationMemory::new(2);
+        memory.remember_exchange("abc", "write about rust", "Weaving...", &FailingClient).await;
+        memory.remember("abc", "user", "again", &FailingClient).await;
+
+        assert_eq!(memory.summary("abc").await.as_deref(), Some("user: write about rust\nassistant: Weaving..."));
+        assert_eq!(memory.recent_messages("abc").await.len(), 1);
+
+        memory.forget("abc").await;
+        assert!(memory.summary("abc").await.is_none());
+    }
+}
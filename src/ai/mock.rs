@@ -0,0 +1,187 @@
+use std::collections::{HashMap, VecDeque};
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::sync::{Arc, Mutex};
+use anyhow::Result;
+use crate::error::SwarmError;
+use crate::types::Attachment;
+use super::AiProvider;
+
+/// Dimensions of the bag-of-words embeddings the mock returns
+pub const MOCK_EMBEDDING_DIMENSIONS: usize = 64;
+
+#[derive(Debug, Clone)]
+enum Reply {
+    Text(String),
+    Error(String),
+}
+
+impl Reply {
+    fn into_result(self) -> Result<String> {
+        match self {
+            Reply::Text(text) => Ok(text),
+            Reply::Error(message) => Err(SwarmError::AiProvider(message).into()),
+        }
+    }
+}
+
+/// A chat request the mock received
+#[derive(Debug, Clone, PartialEq)]
+pub struct MockRequest {
+    pub system_prompt: String,
+    pub messages: Vec<HashMap<String, String>>,
+    /// Sent through `chat_with_images`
+    pub images: Vec<Attachment>,
+}
+
+impl MockRequest {
+    /// Content of the last message
+    pub fn last_message(&self) -> &str {
+        self.messages.last().and_then(|m| m.get("content")).map(String::as_str).unwrap_or_default()
+    }
+}
+
+#[derive(Debug, Default)]
+struct Script {
+    queued: VecDeque<Reply>,
+    rules: Vec<(String, Reply)>,
+    fallback: Option<Reply>,
+    requests: Vec<MockRequest>,
+}
+
+/// Scripted `AiProvider` for tests. Each chat is answered by the next queued reply if
+/// there is one, else by the first rule whose pattern appears in the system prompt or
+/// last message, else by the default reply; with none of those it fails. Embeddings are
+/// deterministic bag-of-words vectors, so texts sharing words come out similar.
+/// Clones share the script, so a test can keep one to inspect requests.
+#[derive(Debug, Clone, Default)]
+pub struct MockAiProvider {
+    script: Arc<Mutex<Script>>,
+}
+
+impl MockAiProvider {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Queue a reply for the next unanswered chat
+    pub fn with_response(self, text: impl Into<String>) -> Self {
+        self.script.lock().unwrap().queued.push_back(Reply::Text(text.into()));
+        self
+    }
+
+    /// Queue a failure for the next unanswered chat
+    pub fn with_error(self, message: impl Into<String>) -> Self {
+        self.script.lock().unwrap().queued.push_back(Reply::Error(message.into()));
+        self
+    }
+
+    /// Answer chats mentioning `pattern` with `text`
+    pub fn with_rule(self, pattern: impl Into<String>, text: impl Into<String>) -> Self {
+        self.script.lock().unwrap().rules.push((pattern.into(), Reply::Text(text.into())));
+        self
+    }
+
+    /// Answer anything the queue and rules don't cover with `text`
+    pub fn with_default(self, text: impl Into<String>) -> Self {
+        self.script.lock().unwrap().fallback = Some(Reply::Text(text.into()));
+        self
+    }
+
+    /// Every chat request received so far, oldest first
+    pub fn requests(&self) -> Vec<MockRequest> {
+        self.script.lock().unwrap().requests.clone()
+    }
+
+    pub fn call_count(&self) -> usize {
+        self.script.lock().unwrap().requests.len()
+    }
+}
+
+/// Hash each lowercased word into a bucket and normalize
+fn bag_of_words(text: &str) -> Vec<f32> {
+    let mut vector = vec![0.0f32; MOCK_EMBEDDING_DIMENSIONS];
+    for word in text.split(|c: char| !c.is_alphanumeric()).filter(|w| !w.is_empty()) {
+        let mut hasher = DefaultHasher::new();
+        word.to_lowercase().hash(&mut hasher);
+        vector[(hasher.finish() % MOCK_EMBEDDING_DIMENSIONS as u64) as usize] += 1.0;
+    }
+    let norm = vector.iter().map(|v| v * v).sum::<f32>().sqrt();
+    if norm > 0.0 {
+        vector.iter_mut().for_each(|v| *v /= norm);
+    }
+    vector
+}
+
+impl MockAiProvider {
+    fn answer(&self, request: MockRequest) -> Result<String> {
+        let mut script = self.script.lock().unwrap();
+        let reply = script.queued.pop_front()
+            .or_else(|| script.rules.iter()
+                .find(|(pattern, _)| request.system_prompt.contains(pattern.as_str()) || request.last_message().contains(pattern.as_str()))
+                .map(|(_, reply)| reply.clone()))
+            .or_else(|| script.fallback.clone());
+        let last_message = request.last_message().to_string();
+        script.requests.push(request);
+        match reply {
+            Some(reply) => reply.into_result(),
+            None => Err(SwarmError::AiProvider(format!("MockAiProvider has no reply scripted for '{}'", last_message)).into()),
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl AiProvider for MockAiProvider {
+    async fn chat(&self, system_prompt: &str, messages: Vec<HashMap<String, String>>) -> Result<String> {
+        self.answer(MockRequest { system_prompt: system_prompt.to_string(), messages, images: Vec::new() })
+    }
+
+    async fn chat_with_images(&self, system_prompt: &str, messages: Vec<HashMap<String, String>>, images: &[Attachment]) -> Result<String> {
+        self.answer(MockRequest { system_prompt: system_prompt.to_string(), messages, images: images.to_vec() })
+    }
+
+    async fn embed(&self, text: &str) -> Result<Vec<f32>> {
+        Ok(bag_of_words(text))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ai::cosine_similarity;
+
+    fn user(content: &str) -> Vec<HashMap<String, String>> {
+        vec![HashMap::from([
+            ("role".to_string(), "user".to_string()),
+            ("content".to_string(), content.to_string()),
+        ])]
+    }
+
+    #[tokio::test]
+    async fn test_script_order() {
+        let mock = MockAiProvider::new()
+            .with_response("first")
+            .with_error("model crashed")
+            .with_rule("priority", "high")
+            .with_default("whatever");
+        let observer = mock.clone();
+
+        assert_eq!(mock.chat("", user("Classify priority: fix login")).await.unwrap(), "first");
+        assert!(mock.chat("", user("anything")).await.is_err());
+        assert_eq!(mock.chat("", user("Classify priority: fix login")).await.unwrap(), "high");
+        assert_eq!(mock.chat("", user("Enhance this task")).await.unwrap(), "whatever");
+        assert_eq!(observer.call_count(), 4);
+        assert_eq!(observer.requests()[3].last_message(), "Enhance this task");
+
+        assert!(MockAiProvider::new().chat("", user("hi")).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_embeddings_are_deterministic() {
+        let mock = MockAiProvider::new();
+        let a = mock.embed("Fix the login page").await.unwrap();
+        assert_eq!(a, mock.embed("fix the LOGIN page").await.unwrap());
+        let related = cosine_similarity(&a, &mock.embed("login page broken").await.unwrap());
+        let unrelated = cosine_similarity(&a, &mock.embed("bake sourdough bread").await.unwrap());
+        assert!(related > unrelated, "{} <= {}", related, unrelated);
+    }
+}
@@ -1,16 +1,73 @@
 use std::collections::HashMap;
 use anyhow::Result;
-use crate::types::TaskPriority;
+use crate::types::{Attachment, TaskPriority, Tool};
 
 mod goose;
+mod huggingface;
 mod local;
+mod openai;
+pub mod functions;
+pub mod embeddings;
+pub mod project_classifier;
+pub mod memory;
+pub mod streaming;
+pub mod rate_limit;
+pub mod fallback;
+pub mod budget;
+pub mod mock;
+pub mod cassette;
+pub mod structured;
+pub mod offline;
+pub mod few_shot;
+pub mod router;
+pub mod vision;
 
 pub use goose::GooseClient;
+pub use huggingface::{HuggingFaceApi, HuggingFaceClient, PromptTemplate};
 pub use local::LocalAiClient;
+pub use openai::OpenAiClient;
+pub use functions::{ChatResponse, ToolCallRequest, run_tool_loop};
+pub use embeddings::{cosine_similarity, rank_by_similarity};
+pub use project_classifier::{ProjectClassifier, KNOWN_PROJECTS};
+pub use memory::{ConversationMemory, memory_session};
+pub use rate_limit::{RateLimit, RateLimitedAiClient};
+pub use budget::{BudgetedAiClient, TokenBudgets, TokenUsage, count_tokens};
+pub use cassette::{Cassette, RecordingAiClient, ReplayAiClient, cassette_from_env};
+pub use mock::{MockAiProvider, MockRequest};
+pub use fallback::{FallbackAiClient, FallbackPolicy, HeuristicAiClient, ProviderHealth, provider_health};
+pub use structured::JsonSchema;
+pub use few_shot::{FewShotExample, FewShotExamples};
 
 #[async_trait::async_trait]
 pub trait AiProvider: Send + Sync {
     async fn chat(&self, system_prompt: &str, messages: Vec<HashMap<String, String>>) -> Result<String>;
+
+    /// Chat with tools on offer. Providers with native function calling should override this;
+    /// the default describes the tools in the system prompt and parses JSON calls from the reply.
+    async fn chat_with_tools(&self, system_prompt: &str, messages: Vec<HashMap<String, String>>, tools: &[Tool]) -> Result<ChatResponse> {
+        functions::prompted(self, system_prompt, messages, tools).await
+    }
+
+    /// Chat for a JSON reply matching `schema`. Providers that can constrain their output
+    /// to a schema should override this; the default asks for JSON in the prompt. Either
+    /// way a reply that doesn't match gets one cleaning pass.
+    async fn chat_structured(&self, system_prompt: &str, messages: Vec<HashMap<String, String>>, schema: &JsonSchema) -> Result<serde_json::Value> {
+        structured::prompted(self, system_prompt, messages, schema).await
+    }
+
+    /// Embed text as a vector for similarity search
+    async fn embed(&self, _text: &str) -> Result<Vec<f32>> {
+        Err(crate::error::SwarmError::Unsupported("Embeddings are not supported by this provider".to_string()).into())
+    }
+
+    /// Chat with `images` attached to the last user message (see `vision`). Multimodal
+    /// providers override this; the default can only chat without images.
+    async fn chat_with_images(&self, system_prompt: &str, messages: Vec<HashMap<String, String>>, images: &[Attachment]) -> Result<String> {
+        if images.is_empty() {
+            return self.chat(system_prompt, messages).await;
+        }
+        Err(crate::error::SwarmError::Unsupported("Images are not supported by this provider".to_string()).into())
+    }
 }
 
 // Re-export the default client based on feature flags
@@ -31,6 +88,15 @@ pub fn new_ai_client() -> DefaultAiClient {
 pub async fn enhance_todo_description(
     description: &str,
     ai_client: &dyn AiProvider
+) -> Result<(String, TaskPriority, String)> {
+    enhance_todo_description_with(description, ai_client, &DEFAULT_PROJECT_CLASSIFIER).await
+}
+
+/// Like `enhance_todo_description`, classifying against the given classifier's projects
+pub async fn enhance_todo_description_with(
+    description: &str,
+    ai_client: &dyn AiProvider,
+    classifier: &ProjectClassifier,
 ) -> Result<(String, TaskPriority, String)> {
     // Enhance the description
     let system_prompt = r#"You are a task enhancement and planning system. Imagine you are creating a prompt for an ai agent to complete the Task given:
@@ -66,83 +132,185 @@ Use these guidelines:
     ])];
 
     let priority_response = ai_client.chat(priority_prompt, priority_messages).await?;
-    let priority = match priority_response.trim().to_lowercase().as_str() {
+    let priority = parse_priority(&priority_response, description);
+
+    let final_project = predict_project_with(description, ai_client, classifier).await?;
+
+    Ok((enhanced_description, priority, final_project))
+}
+
+/// The priority the model named, or the keyword rules' when it named none
+fn parse_priority(response: &str, description: &str) -> TaskPriority {
+    match response.trim().to_lowercase().as_str() {
         "inital" => TaskPriority::Inital,
         "low" => TaskPriority::Low,
         "medium" => TaskPriority::Medium,
         "high" => TaskPriority::High,
         "critical" => TaskPriority::Critical,
-        _ => TaskPriority::Medium, // Default to Medium for any unexpected response
-    };
-
-    // Predict project
-    let project_prompt = r#"You are a project classifier. Your task is to determine which project a given task belongs to. 
-Your output should be ONLY the project name, nothing else. Options are: 
-"madness_interactive - Parent Project of chaos", 
-"regressiontestkit - Parent repo for Work projects. Balena device testing in python", 
-"omnispindle - MCP server for Managing AI todo list in python", 
-"Todomill_projectorium - Todo list management Dashbaord on Node-red",
-"swarmonomicon - Todo worker and generation project in rust", 
-"hammerspoon - MacOS automation and workspace management", 
-"lab_management - Lab management general project", 
-"cogwyrm - Mobile app for Tasker infacing with madness network", 
-"docker_implementation - Tasks todo with docker and deployment", 
-"documentation - Documentation for all projects", 
-"eventghost - Event handling and monitoring automation. Being rewritten in Rust", 
-"hammerghost - MacOS automation menu in hammerspoon based on eventghost",  
-"quality_assurance - Quality assurance tasks",
-"spindlewrit - Writing and documentation project",
-"node_red_contrib_file_template - Node-red contrib for file manangement replacement of the HTML template node",
-"inventorium - Madnessinteractice.cc website and Todo Dashboard - React",
-
-If you're unsure, default to "madness_interactive"."#;
+        _ => {
+            log::debug!("Unexpected priority '{}', using keyword rules", response.trim());
+            offline::priority(description)
+        }
+    }
+}
+
+/// Everything AI enhancement adds to a task
+#[derive(Debug, Clone, PartialEq)]
+pub struct Enhancement {
+    pub enhanced_description: String,
+    pub priority: TaskPriority,
+    pub project: String,
+    pub tags: Vec<String>,
+    /// How sure the enhancement is of its priority and project, from 0 to 1
+    pub confidence: f32,
+}
+
+/// Confidence given to enhancements made field by field, which don't report one
+const FIELD_BY_FIELD_CONFIDENCE: f32 = 0.5;
+
+const ENHANCEMENT_PROMPT: &str = r#"You are a task enhancement and planning system. For the task given:
+- enhanced_description: a prompt for an ai agent to complete the task, in markdown. Add specific technical steps, explain impact and scope along with file locations and dependencies, name the components involved, and break it into smaller steps. Keep it concise.
+- priority: "inital" for tasks not yet comparable to others, "low" for nice to have features, documentation or cosmetic issues, "medium" for standard development work, "high" for tasks significantly impacting functionality or performance, "critical" for urgent tasks impacting system functionality or security.
+- project: the project the task belongs to, from the list below; if unsure, "madness_interactive".
+- tags: 1 to 4 short, general tags such as the kind of work (bug, feature, docs, refactor, security) and the area it touches (frontend, api, database, infra).
+- confidence: from 0 to 1, how sure you are of the priority and project."#;
+
+/// The schema of a structured enhancement, with the project one of `projects`
+fn enhancement_schema(projects: &[(String, String)]) -> JsonSchema {
+    let mut names: Vec<&str> = projects.iter().map(|(name, _)| name.as_str()).collect();
+    if !names.contains(&"madness_interactive") {
+        names.push("madness_interactive");
+    }
+    JsonSchema::new("task_enhancement", serde_json::json!({
+        "type": "object",
+        "properties": {
+            "enhanced_description": { "type": "string" },
+            "priority": { "type": "string", "enum": ["inital", "low", "medium", "high", "critical"] },
+            "project": { "type": "string", "enum": names },
+            "tags": { "type": "array", "items": { "type": "string" } },
+            "confidence": { "type": "number" },
+        },
+        "required": ["enhanced_description", "priority", "project", "tags", "confidence"],
+        "additionalProperties": false,
+    }))
+}
+
+/// Enhance a task in one structured call, falling back to asking for each field
+/// separately when the provider can't produce matching JSON. Fails when the provider
+/// does; callers then use `offline::enhance`.
+pub async fn enhance_task(description: &str, ai_client: &dyn AiProvider) -> Result<Enhancement> {
+    enhance_task_with(description, ai_client, &DEFAULT_PROJECT_CLASSIFIER).await
+}
+
+/// Like `enhance_task`, classifying against the given classifier's projects
+pub async fn enhance_task_with(description: &str, ai_client: &dyn AiProvider, classifier: &ProjectClassifier) -> Result<Enhancement> {
+    let projects = classifier.projects().await;
+    let examples = classifier.examples().prompt(description, ai_client).await;
+    match enhance_task_structured(description, ai_client, &projects, &examples).await {
+        Ok(enhancement) => return Ok(enhancement),
+        Err(e) => log::debug!("Structured enhancement failed ({}), enhancing field by field", e),
+    }
+
+    let (enhanced_description, priority, project) = enhance_todo_description_with(description, ai_client, classifier).await?;
+    // Tags are a nice-to-have; don't lose the enhancement over them
+    let tags = suggest_tags(description, ai_client).await.unwrap_or_else(|e| {
+        log::debug!("No tag suggestions ({}), using keywords", e);
+        offline::tags(description)
+    });
+    Ok(Enhancement { enhanced_description, priority, project, tags, confidence: FIELD_BY_FIELD_CONFIDENCE })
+}
+
+async fn enhance_task_structured(description: &str, ai_client: &dyn AiProvider, projects: &[(String, String)], examples: &str) -> Result<Enhancement> {
+    let options: Vec<String> = projects.iter().map(|(name, desc)| format!("- {}: {}", name, desc)).collect();
+    let system_prompt = format!("{}
+
+Projects:
+{}{}", ENHANCEMENT_PROMPT, options.join("\n"), examples);
+    let messages = vec![HashMap::from([
+        ("role".to_string(), "user".to_string()),
+        ("content".to_string(), format!("Task: {}", description)),
+    ])];
+
+    let reply = ai_client.chat_structured(&system_prompt, messages, &enhancement_schema(projects)).await?;
+    let text = |field: &str| reply[field].as_str().unwrap_or_default().to_string();
+    let tags = reply["tags"].as_array().into_iter().flatten().filter_map(serde_json::Value::as_str);
+    Ok(Enhancement {
+        enhanced_description: text("enhanced_description"),
+        priority: parse_priority(&text("priority"), description),
+        project: text("project"),
+        tags: crate::types::tags::normalize_tags(tags),
+        confidence: (reply["confidence"].as_f64().unwrap_or_default() as f32).clamp(0.0, 1.0),
+    })
+}
+
+/// Ask the model for a few short tags describing the task, e.g. `bug`, `frontend`
+pub async fn suggest_tags(description: &str, ai_client: &dyn AiProvider) -> Result<Vec<String>> {
+    let tag_prompt = r#"You are a task labeler. Suggest 1 to 4 short, general tags for the task, such as the kind of work (bug, feature, docs, refactor, security) and the area it touches (frontend, api, database, infra).
+Output ONLY the tags as a comma-separated list, with no other text."#;
+
+    let tag_messages = vec![HashMap::from([
+        ("role".to_string(), "user".to_string()),
+        ("content".to_string(), format!("Suggest tags: {}", description)),
+    ])];
+
+    let response = ai_client.chat(tag_prompt, tag_messages).await?;
+    Ok(crate::types::tags::parse_tags(&response))
+}
+
+/// Predict which known project a task belongs to, preferring embedding similarity
+/// and falling back to asking the model when the provider has no embeddings
+pub async fn predict_project(description: &str, ai_client: &dyn AiProvider) -> Result<String> {
+    predict_project_with(description, ai_client, &DEFAULT_PROJECT_CLASSIFIER).await
+}
+
+pub async fn predict_project_with(description: &str, ai_client: &dyn AiProvider, classifier: &ProjectClassifier) -> Result<String> {
+    match classifier.classify(description, ai_client).await {
+        Ok(project) => Ok(project),
+        Err(e) => {
+            log::debug!("Embedding project classification unavailable ({}), asking the model", e);
+            let examples = classifier.examples().prompt(description, ai_client).await;
+            predict_project_with_llm(description, ai_client, &classifier.projects().await, &examples).await
+        }
+    }
+}
+
+lazy_static::lazy_static! {
+    static ref DEFAULT_PROJECT_CLASSIFIER: ProjectClassifier = ProjectClassifier::default();
+}
+
+/// Ask the model for a project name, constrained afterwards to the known project list
+/// `examples` is appended to the prompt; see `FewShotExamples::prompt`
+async fn predict_project_with_llm(description: &str, ai_client: &dyn AiProvider, projects: &[(String, String)], examples: &str) -> Result<String> {
+    let options: Vec<String> = projects.iter()
+        .map(|(name, desc)| format!("\"{} - {}\"", name, desc))
+        .collect();
+    let project_prompt = format!(
+        "You are a project classifier. Your task is to determine which project a given task belongs to. \n\
+Your output should be ONLY the project name, nothing else. Options are: \n{},\n\n\
+If you're unsure, default to \"madness_interactive\".{}",
+        options.join(", \n"),
+        examples
+    );
 
     let project_messages = vec![HashMap::from([
         ("role".to_string(), "user".to_string()),
         ("content".to_string(), format!("Which project does this task belong to? {}", description)),
     ])];
 
-    let project_name = ai_client.chat(project_prompt, project_messages).await?;
-
-    // Verify project name against valid options
-    let valid_projects = [
-        "madness_interactive",
-        "regressiontestkit",
-        "omnispindle",
-        "todomill_projectorium",
-        "swarmonomicon",
-        "hammerspoon",
-        "lab_management",
-        "cogwyrm",
-        "docker_implementation",
-        "documentation",
-        "eventghost",
-        "hammerghost",
-        "quality_assurance",
-        "spindlewrit",
-        "inventorium"
-    ];
+    let project_name = ai_client.chat(&project_prompt, project_messages).await?;
 
     // Clean up project name
     let project = project_name.trim().trim_matches('"').trim_matches('\'').to_lowercase();
 
     // Verify project exists in valid list
-    let verified_project = if valid_projects.iter().any(|&p| p == project) {
-        project
+    if let Some((name, _)) = projects.iter().find(|(p, _)| p.to_lowercase() == project) {
+        Ok(name.clone())
     } else {
-        // If not a valid project, default to madness_interactive
-        log::warn!("Invalid project name detected: '{}'. Defaulting to madness_interactive", project);
-        "madness_interactive".to_string()
-    };
-
-    // If project is empty, use default
-    let final_project = if verified_project.is_empty() {
-        "madness_interactive".to_string()
-    } else {
-        verified_project.to_string()
-    };
-
-    Ok((enhanced_description, priority, final_project))
+        // If not a valid project, match by keywords, then default to madness_interactive
+        let matched = offline::project(description, projects).unwrap_or_else(|| "madness_interactive".to_string());
+        log::warn!("Invalid project name detected: '{}'. Using {}", project, matched);
+        Ok(matched)
+    }
 }
 
 // Deprecated: Use new_ai_client() instead
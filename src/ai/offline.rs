@@ -0,0 +1,157 @@
+//! Task enhancement without a model, for when every provider is down or answers nonsense.
+//! Priorities follow keyword rules mirroring the priority prompt, projects are matched by
+//! embedding similarity when embeddings still work and by shared words otherwise, and
+//! tags come from keyword groups.
+
+use crate::types::TaskPriority;
+use crate::types::projects::get_default_project;
+use super::{AiProvider, Enhancement, ProjectClassifier};
+
+/// Urgent tasks impacting system functionality or security
+const CRITICAL: &[&str] = &[
+    "urgent", "asap", "critical", "security", "vulnerab", "exploit", "breach", "outage", "down",
+    "crash", "data loss", "production", "leak", "cve", "emergency",
+];
+/// Tasks significantly impacting functionality or performance
+const HIGH: &[&str] = &[
+    "important", "blocker", "blocking", "broken", "fail", "error", "bug", "regression",
+    "performance", "slow", "timeout", "hang", "memory", "deadlock",
+];
+/// Nice to have features, documentation or cosmetic issues
+const LOW: &[&str] = &[
+    "docs", "document", "readme", "typo", "comment", "cosmetic", "style", "format",
+    "nice to have", "someday", "cleanup", "clean up", "rename", "polish", "tidy",
+];
+
+const TAGS: &[(&str, &[&str])] = &[
+    ("security", &["security", "vulnerab", "exploit", "breach", "auth", "cve", "leak", "permission"]),
+    ("bug", &["bug", "fix", "broken", "error", "crash", "fail", "regression", "wrong"]),
+    ("docs", &["docs", "document", "readme", "typo", "comment"]),
+    ("refactor", &["refactor", "cleanup", "clean up", "rename", "restructure", "simplify"]),
+    ("feature", &["add", "implement", "support", "feature", "new", "create"]),
+    ("frontend", &["ui", "frontend", "css", "page", "button", "dashboard", "layout"]),
+    ("api", &["api", "endpoint", "route", "rest", "http", "webhook"]),
+    ("database", &["database", "mongo", "db", "sql", "query", "migration", "index"]),
+    ("infra", &["deploy", "docker", "ci", "pipeline", "server", "infra", "kubernetes", "build"]),
+];
+const MAX_TAGS: usize = 4;
+
+const MATCHED_CONFIDENCE: f32 = 0.3;
+const DEFAULTED_CONFIDENCE: f32 = 0.1;
+
+fn words(text: &str) -> Vec<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(str::to_lowercase)
+        .collect()
+}
+
+/// Phrases match anywhere; single keywords match whole words, or word starts when they
+/// are long enough to be stems (`crash` matches `crashes`, `down` doesn't match `download`)
+fn mentions(text: &str, words: &[String], keyword: &str) -> bool {
+    if keyword.contains(' ') {
+        return text.contains(keyword);
+    }
+    words.iter().any(|word| word == keyword || (keyword.len() >= 5 && word.starts_with(keyword)))
+}
+
+fn mentions_any(description: &str, keywords: &[&str]) -> bool {
+    let text = description.to_lowercase();
+    let words = words(&text);
+    keywords.iter().any(|keyword| mentions(&text, &words, keyword))
+}
+
+/// The priority the keyword rules give a task; medium when none apply
+pub fn priority(description: &str) -> TaskPriority {
+    if mentions_any(description, CRITICAL) {
+        TaskPriority::Critical
+    } else if mentions_any(description, HIGH) {
+        TaskPriority::High
+    } else if mentions_any(description, LOW) {
+        TaskPriority::Low
+    } else {
+        TaskPriority::Medium
+    }
+}
+
+/// The project sharing the most words with the task, counting words of its name three
+/// times those of its description; `None` when none share any
+pub fn project(description: &str, projects: &[(String, String)]) -> Option<String> {
+    let task_words: Vec<String> = words(description).into_iter().filter(|word| word.len() >= 3).collect();
+    let score = |(name, about): &(String, String)| -> usize {
+        let named = words(name).iter().filter(|word| task_words.contains(word)).count()
+            // Names like `swarmonomicon` mentioned whole
+            + usize::from(task_words.contains(&name.to_lowercase()));
+        let described = words(about).iter().filter(|word| word.len() >= 4 && task_words.contains(word)).count();
+        3 * named + described
+    };
+    let mut best: Option<(&String, usize)> = None;
+    for project in projects {
+        let score = score(project);
+        if score > best.map_or(0, |(_, best)| best) {
+            best = Some((&project.0, score));
+        }
+    }
+    best.map(|(name, _)| name.clone())
+}
+
+/// Tags for the kinds of work and areas the task mentions
+pub fn tags(description: &str) -> Vec<String> {
+    TAGS.iter()
+        .filter(|(_, keywords)| mentions_any(description, keywords))
+        .map(|(tag, _)| tag.to_string())
+        .take(MAX_TAGS)
+        .collect()
+}
+
+/// An enhancement made without chatting: the description as given, keyword priority and
+/// tags, and the project by embeddings if the provider can still embed, else by keywords.
+/// Confidence is low, and lowest when no project matched and the default was used.
+pub async fn enhance(description: &str, ai_client: &dyn AiProvider, classifier: &ProjectClassifier) -> Enhancement {
+    let (project, confidence) = match classifier.rank(description, ai_client).await {
+        Ok(ranked) => (ranked[0].0.clone(), MATCHED_CONFIDENCE),
+        Err(_) => match project(description, &classifier.projects().await) {
+            Some(project) => (project, MATCHED_CONFIDENCE),
+            None => (get_default_project().to_string(), DEFAULTED_CONFIDENCE),
+        },
+    };
+    Enhancement {
+        enhanced_description: description.to_string(),
+        priority: priority(description),
+        project,
+        tags: tags(description),
+        confidence,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ai::{HeuristicAiClient, KNOWN_PROJECTS};
+
+    #[test]
+    fn test_priority_rules() {
+        assert_eq!(priority("Fix security vulnerability in login"), TaskPriority::Critical);
+        assert_eq!(priority("API crashes when the queue is empty"), TaskPriority::Critical);
+        assert_eq!(priority("Search is slow on large projects"), TaskPriority::High);
+        assert_eq!(priority("Fix typo in the README"), TaskPriority::Low);
+        assert_eq!(priority("Download the weekly report"), TaskPriority::Medium);
+        assert_eq!(tags("Add an API endpoint for archived tasks"), ["feature", "api"]);
+        assert_eq!(tags("Fix broken Mongo index migration"), ["bug", "database"]);
+    }
+
+    #[tokio::test]
+    async fn test_project_by_keywords_without_a_model() {
+        let projects: Vec<(String, String)> = KNOWN_PROJECTS.iter().map(|(n, d)| (n.to_string(), d.to_string())).collect();
+        assert_eq!(project("Add retries to the swarmonomicon todo worker", &projects).as_deref(), Some("swarmonomicon"));
+        assert_eq!(project("Hammerspoon hotkey for the workspace layout", &projects).as_deref(), Some("hammerspoon"));
+        assert_eq!(project("xyzzy", &projects), None);
+
+        // The heuristic client can't embed, so the project comes from keywords
+        let enhancement = enhance("Omnispindle MCP server drops todos, urgent", &HeuristicAiClient, &ProjectClassifier::default()).await;
+        assert_eq!(enhancement.project, "omnispindle");
+        assert_eq!(enhancement.priority, TaskPriority::Critical);
+        assert_eq!(enhancement.enhanced_description, "Omnispindle MCP server drops todos, urgent");
+        assert_eq!(enhancement.confidence, MATCHED_CONFIDENCE);
+    }
+}
@@ -0,0 +1,297 @@
+use std::collections::HashMap;
+use anyhow::{Result, anyhow};
+use crate::error::SwarmError;
+use async_openai::{
+    config::{Config, OpenAIConfig},
+    Client,
+    types::{
+        ChatCompletionMessageToolCall, ChatCompletionRequestAssistantMessageArgs,
+        ChatCompletionRequestMessage, ChatCompletionRequestSystemMessageArgs,
+        ChatCompletionRequestToolMessageArgs, ChatCompletionRequestUserMessageArgs,
+        ChatCompletionTool, ChatCompletionToolArgs, ChatCompletionToolType,
+        CreateChatCompletionRequestArgs, CreateEmbeddingRequestArgs, FunctionCall, FunctionObjectArgs,
+    },
+};
+use serde_json::{Value, json};
+use super::{AiProvider, JsonSchema, structured, vision};
+use super::vision::EncodedImage;
+use super::functions::{ChatResponse, tool_call_request, tool_schema};
+use crate::types::{Attachment, Tool};
+
+const DEFAULT_MODEL: &str = "gpt-4o-mini";
+const DEFAULT_EMBED_MODEL: &str = "text-embedding-3-small";
+
+/// OpenAI chat client with native tool calling
+#[derive(Clone)]
+pub struct OpenAiClient {
+    client: Client<OpenAIConfig>,
+    model: String,
+    embed_model: String,
+}
+
+impl Default for OpenAiClient {
+    fn default() -> Self {
+        let api_key = crate::secrets::get(crate::secrets::OPENAI_API_KEY).unwrap_or_default();
+        let model = std::env::var("OPENAI_MODEL").unwrap_or_else(|_| DEFAULT_MODEL.to_string());
+        let embed_model = std::env::var("OPENAI_EMBED_MODEL").unwrap_or_else(|_| DEFAULT_EMBED_MODEL.to_string());
+        Self {
+            client: Client::with_config(OpenAIConfig::new().with_api_key(api_key)),
+            model,
+            embed_model,
+        }
+    }
+}
+
+impl OpenAiClient {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_model(mut self, model: String) -> Self {
+        self.model = model;
+        self
+    }
+
+    pub fn with_embed_model(mut self, model: String) -> Self {
+        self.embed_model = model;
+        self
+    }
+
+    pub fn embed_model(&self) -> &str {
+        &self.embed_model
+    }
+
+    fn to_request_messages(system_prompt: &str, messages: &[HashMap<String, String>]) -> Result<Vec<ChatCompletionRequestMessage>> {
+        let mut request = vec![ChatCompletionRequestSystemMessageArgs::default()
+            .content(system_prompt)
+            .build()?
+            .into()];
+
+        for message in messages {
+            let content = message.get("content").cloned().unwrap_or_default();
+            let converted: ChatCompletionRequestMessage = match message.get("role").map(|s| s.as_str()) {
+                Some("system") => ChatCompletionRequestSystemMessageArgs::default()
+                    .content(content)
+                    .build()?
+                    .into(),
+                Some("assistant") => {
+                    let mut args = ChatCompletionRequestAssistantMessageArgs::default();
+                    match message.get("tool_calls") {
+                        Some(encoded) => { args.tool_calls(decode_tool_calls(encoded)?); }
+                        None => { args.content(content); }
+                    }
+                    args.build()?.into()
+                }
+                Some("tool") => ChatCompletionRequestToolMessageArgs::default()
+                    .content(content)
+                    .tool_call_id(message.get("tool_call_id").cloned().unwrap_or_default())
+                    .build()?
+                    .into(),
+                _ => ChatCompletionRequestUserMessageArgs::default()
+                    .content(content)
+                    .build()?
+                    .into(),
+            };
+            request.push(converted);
+        }
+
+        Ok(request)
+    }
+
+    fn to_request_tools(tools: &[Tool]) -> Result<Vec<ChatCompletionTool>> {
+        tools.iter()
+            .map(|tool| {
+                Ok(ChatCompletionToolArgs::default()
+                    .function(FunctionObjectArgs::default()
+                        .name(tool.name.clone())
+                        .description(tool.description.clone())
+                        .parameters(tool_schema(tool))
+                        .build()?)
+                    .build()?)
+            })
+            .collect()
+    }
+}
+
+impl OpenAiClient {
+    /// A chat completion constrained by `response_format: json_schema`. The typed request
+    /// in this async-openai version has no `json_schema` format, so the body is built here.
+    async fn chat_json_schema(&self, system_prompt: &str, messages: &[HashMap<String, String>], schema: &JsonSchema) -> Result<String> {
+        self.post_chat(json!({
+            "model": self.model,
+            "messages": Self::to_request_messages(system_prompt, messages)?,
+            "response_format": {
+                "type": "json_schema",
+                "json_schema": { "name": schema.name, "schema": schema.schema, "strict": true },
+            },
+        })).await
+    }
+
+    /// Send a chat completion request built as JSON, returning the reply's content
+    async fn post_chat(&self, body: Value) -> Result<String> {
+        let config = self.client.config();
+        let response = reqwest::Client::new()
+            .post(config.url("/chat/completions"))
+            .headers(config.headers())
+            .query(&config.query())
+            .json(&body)
+            .send()
+            .await
+            .map_err(|e| SwarmError::AiProvider(format!("OpenAI request failed: {}", e)))?;
+        let status = response.status();
+        let reply: Value = response.json().await
+            .map_err(|e| SwarmError::AiProvider(format!("Failed to parse OpenAI response: {}", e)))?;
+        if !status.is_success() {
+            return Err(SwarmError::AiProvider(format!("OpenAI request failed: {} {}", status, reply["error"]["message"])).into());
+        }
+        reply["choices"][0]["message"]["content"].as_str()
+            .map(String::from)
+            .ok_or_else(|| SwarmError::AiProvider("OpenAI returned no content".to_string()).into())
+    }
+
+    /// Request messages with `images` as parts of the last user message's content, which
+    /// this async-openai version can't express
+    fn image_request_messages(system_prompt: &str, messages: &[HashMap<String, String>], images: &[EncodedImage]) -> Result<Value> {
+        let mut request = serde_json::to_value(Self::to_request_messages(system_prompt, messages)?)?;
+        let chat = request.as_array_mut().expect("messages serialize to an array");
+        let index = vision::image_message_index(chat)
+            .ok_or_else(|| SwarmError::Validation("Images need a user message to go with".to_string()))?;
+        let text = chat[index]["content"].as_str().unwrap_or_default().to_string();
+        chat[index]["content"] = vision::content_parts(&text, images);
+        Ok(request)
+    }
+}
+
+/// Decode the `tool_calls` history entry written by `assistant_tool_calls_message`
+fn decode_tool_calls(encoded: &str) -> Result<Vec<ChatCompletionMessageToolCall>> {
+    let calls: Vec<Value> = serde_json::from_str(encoded)
+        .map_err(|e| anyhow!("Invalid tool_calls in message history: {}", e))?;
+    Ok(calls.iter()
+        .map(|call| ChatCompletionMessageToolCall {
+            id: call["id"].as_str().unwrap_or_default().to_string(),
+            r#type: ChatCompletionToolType::Function,
+            function: FunctionCall {
+                name: call["name"].as_str().unwrap_or_default().to_string(),
+                arguments: call["arguments"].to_string(),
+            },
+        })
+        .collect())
+}
+
+#[async_trait::async_trait]
+impl AiProvider for OpenAiClient {
+    async fn chat(&self, system_prompt: &str, messages: Vec<HashMap<String, String>>) -> Result<String> {
+        match self.chat_with_tools(system_prompt, messages, &[]).await? {
+            ChatResponse::Message(content) => Ok(content),
+            ChatResponse::ToolCalls(_) => Err(SwarmError::AiProvider("Model requested tool calls but no tools were offered".to_string()).into()),
+        }
+    }
+
+    async fn chat_with_tools(&self, system_prompt: &str, messages: Vec<HashMap<String, String>>, tools: &[Tool]) -> Result<ChatResponse> {
+        let mut request = CreateChatCompletionRequestArgs::default();
+        request
+            .model(self.model.clone())
+            .messages(Self::to_request_messages(system_prompt, &messages)?);
+        if !tools.is_empty() {
+            request.tools(Self::to_request_tools(tools)?);
+        }
+
+        let response = self.client.chat().create(request.build()?).await
+            .map_err(|e| SwarmError::AiProvider(format!("OpenAI request failed: {}", e)))?;
+        let message = response.choices.into_iter().next()
+            .ok_or_else(|| SwarmError::AiProvider("OpenAI returned no choices".to_string()))?
+            .message;
+
+        match message.tool_calls {
+            Some(calls) if !calls.is_empty() => {
+                let requests = calls.into_iter()
+                    .map(|call| {
+                        let arguments: Value = serde_json::from_str(&call.function.arguments).unwrap_or(Value::Null);
+                        tool_call_request(call.id, &call.function.name, &arguments, tools)
+                            .ok_or_else(|| SwarmError::AiProvider(format!("Model requested unknown tool '{}'", call.function.name)).into())
+                    })
+                    .collect::<Result<Vec<_>>>()?;
+                Ok(ChatResponse::ToolCalls(requests))
+            }
+            _ => Ok(ChatResponse::Message(message.content.unwrap_or_default())),
+        }
+    }
+
+    async fn chat_structured(&self, system_prompt: &str, messages: Vec<HashMap<String, String>>, schema: &JsonSchema) -> Result<Value> {
+        match self.chat_json_schema(system_prompt, &messages, schema).await {
+            Ok(reply) => structured::parse_or_clean(self, schema, &reply).await,
+            Err(e) => {
+                // Older models don't support json_schema
+                tracing::debug!("Structured output unavailable ({}), asking for JSON in the prompt", e);
+                structured::prompted(self, system_prompt, messages, schema).await
+            }
+        }
+    }
+
+    async fn embed(&self, text: &str) -> Result<Vec<f32>> {
+        let request = CreateEmbeddingRequestArgs::default()
+            .model(self.embed_model.clone())
+            .input(text)
+            .build()?;
+        let response = self.client.embeddings().create(request).await
+            .map_err(|e| SwarmError::AiProvider(format!("OpenAI embeddings request failed: {}", e)))?;
+        response.data.into_iter().next()
+            .map(|e| e.embedding)
+            .ok_or_else(|| SwarmError::AiProvider("OpenAI returned no embeddings".to_string()).into())
+    }
+
+    async fn chat_with_images(&self, system_prompt: &str, messages: Vec<HashMap<String, String>>, images: &[Attachment]) -> Result<String> {
+        let images = vision::encode_images(images)?;
+        if images.is_empty() {
+            return self.chat(system_prompt, messages).await;
+        }
+        let messages = Self::image_request_messages(system_prompt, &messages, &images)?;
+        self.post_chat(json!({ "model": self.model, "messages": messages })).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ai::functions::{assistant_tool_calls_message, parse_tool_calls};
+
+    #[test]
+    fn test_tool_call_history_round_trip() -> Result<()> {
+        let tool = Tool {
+            name: "weather".to_string(),
+            description: "Current weather".to_string(),
+            parameters: HashMap::from([("city".to_string(), "City name".to_string())]),
+        };
+        let calls = parse_tool_calls(r#"{"name": "weather", "arguments": {"city": "Oslo"}}"#, &[tool.clone()]).unwrap();
+        let history = vec![assistant_tool_calls_message(&calls)];
+
+        let messages = OpenAiClient::to_request_messages("system", &history)?;
+        match &messages[1] {
+            ChatCompletionRequestMessage::Assistant(message) => {
+                let tool_calls = message.tool_calls.as_ref().unwrap();
+                assert_eq!(tool_calls[0].function.name, "weather");
+                assert_eq!(tool_calls[0].function.arguments, r#"{"city":"Oslo"}"#);
+            }
+            other => panic!("unexpected message: {:?}", other),
+        }
+
+        let tools = OpenAiClient::to_request_tools(&[tool])?;
+        assert_eq!(tools[0].function.parameters.as_ref().unwrap()["properties"]["city"]["type"], "string");
+        Ok(())
+    }
+
+    #[test]
+    fn test_images_go_with_the_last_user_message() -> Result<()> {
+        let message = |role: &str, content: &str| HashMap::from([("role".to_string(), role.to_string()), ("content".to_string(), content.to_string())]);
+        let history = [message("user", "Open the dashboard"), message("assistant", "Done"), message("user", "What is on this screen?")];
+        let image = EncodedImage { mime_type: "image/png".to_string(), data: "aGk=".to_string() };
+
+        let messages = OpenAiClient::image_request_messages("system", &history, &[image])?;
+        assert_eq!(messages[1]["content"], "Open the dashboard");
+        assert_eq!(messages[3]["content"], json!([
+            { "type": "text", "text": "What is on this screen?" },
+            { "type": "image_url", "image_url": { "url": "data:image/png;base64,aGk=" } },
+        ]));
+        Ok(())
+    }
+}
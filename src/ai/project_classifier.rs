@@ -0,0 +1,246 @@
+use std::collections::HashMap;
+use anyhow::{Result, anyhow};
+use tokio::sync::RwLock;
+use super::{AiProvider, cosine_similarity};
+use super::few_shot::FewShotExamples;
+
+/// Scores closer than this are treated as a tie and settled by the LLM
+const DEFAULT_TIE_MARGIN: f32 = 0.02;
+
+/// Projects todos can be filed under, with the descriptions used for classification
+pub const KNOWN_PROJECTS: &[(&str, &str)] = &[
+    ("madness_interactive", "Parent Project of chaos"),
+    ("regressiontestkit", "Parent repo for Work projects. Balena device testing in python"),
+    ("omnispindle", "MCP server for Managing AI todo list in python"),
+    ("todomill_projectorium", "Todo list management Dashbaord on Node-red"),
+    ("swarmonomicon", "Todo worker and generation project in rust"),
+    ("hammerspoon", "MacOS automation and workspace management"),
+    ("lab_management", "Lab management general project"),
+    ("cogwyrm", "Mobile app for Tasker infacing with madness network"),
+    ("docker_implementation", "Tasks todo with docker and deployment"),
+    ("documentation", "Documentation for all projects"),
+    ("eventghost", "Event handling and monitoring automation. Being rewritten in Rust"),
+    ("hammerghost", "MacOS automation menu in hammerspoon based on eventghost"),
+    ("quality_assurance", "Quality assurance tasks"),
+    ("spindlewrit", "Writing and documentation project"),
+    ("node_red_contrib_file_template", "Node-red contrib for file manangement replacement of the HTML template node"),
+    ("inventorium", "Madnessinteractice.cc website and Todo Dashboard - React"),
+];
+
+/// Classifies task descriptions into a fixed set of projects by embedding similarity.
+///
+/// The result is always one of the configured projects. The LLM is only consulted
+/// when the top candidates score within the tie margin of each other. Human-filed
+/// examples closer to the task than any project description win outright.
+#[derive(Debug)]
+pub struct ProjectClassifier {
+    projects: RwLock<Vec<(String, String)>>,
+    tie_margin: f32,
+    /// Project embeddings, computed on first use
+    embeddings: RwLock<Option<Vec<Vec<f32>>>>,
+    examples: FewShotExamples,
+}
+
+impl ProjectClassifier {
+    pub fn new(projects: Vec<(String, String)>) -> Self {
+        Self {
+            projects: RwLock::new(projects),
+            tie_margin: DEFAULT_TIE_MARGIN,
+            embeddings: RwLock::new(None),
+            examples: FewShotExamples::default(),
+        }
+    }
+
+    pub fn with_tie_margin(mut self, tie_margin: f32) -> Self {
+        self.tie_margin = tie_margin;
+        self
+    }
+
+    /// The (name, description) pairs currently classified against
+    pub async fn projects(&self) -> Vec<(String, String)> {
+        self.projects.read().await.clone()
+    }
+
+    /// Corrected classifications to learn from
+    pub fn examples(&self) -> &FewShotExamples {
+        &self.examples
+    }
+
+    /// Replace the project list, dropping cached embeddings if it changed
+    pub async fn update_projects(&self, projects: Vec<(String, String)>) {
+        let mut current = self.projects.write().await;
+        if *current != projects {
+            *current = projects;
+            *self.embeddings.write().await = None;
+        }
+    }
+
+    async fn project_embeddings(&self, projects: &[(String, String)], ai_client: &dyn AiProvider, dimensions: usize) -> Result<Vec<Vec<f32>>> {
+        if let Some(cached) = self.embeddings.read().await.as_ref() {
+            // A different embedding model invalidates the cache
+            if cached.first().map(|e| e.len()) == Some(dimensions) {
+                return Ok(cached.clone());
+            }
+        }
+
+        let mut embeddings = Vec::with_capacity(projects.len());
+        for (name, description) in projects {
+            embeddings.push(ai_client.embed(&format!("{}: {}", name, description)).await?);
+        }
+        *self.embeddings.write().await = Some(embeddings.clone());
+        Ok(embeddings)
+    }
+
+    /// All projects with their similarity to the description, best first
+    pub async fn rank(&self, description: &str, ai_client: &dyn AiProvider) -> Result<Vec<(String, f32)>> {
+        let query = ai_client.embed(description).await?;
+        self.rank_embedding(&query, ai_client).await
+    }
+
+    async fn rank_embedding(&self, query: &[f32], ai_client: &dyn AiProvider) -> Result<Vec<(String, f32)>> {
+        let projects = self.projects().await;
+        if projects.is_empty() {
+            return Err(anyhow!("No projects to classify against"));
+        }
+        let embeddings = self.project_embeddings(&projects, ai_client, query.len()).await?;
+
+        let mut ranked: Vec<(String, f32)> = projects.iter()
+            .zip(embeddings.iter())
+            .map(|((name, _), embedding)| (name.clone(), cosine_similarity(query, embedding)))
+            .collect();
+        ranked.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(std::cmp::Ordering::Equal));
+        Ok(ranked)
+    }
+
+    pub async fn classify(&self, description: &str, ai_client: &dyn AiProvider) -> Result<String> {
+        let query = ai_client.embed(description).await?;
+        let ranked = self.rank_embedding(&query, ai_client).await?;
+        let best_score = ranked[0].1;
+        if let Some(project) = self.examples.deciding_project(&query, best_score, ai_client).await {
+            // Only while the project is still one of the options
+            if ranked.iter().any(|(name, _)| *name == project) {
+                return Ok(project);
+            }
+        }
+        let tied: Vec<&str> = ranked.iter()
+            .take_while(|(_, score)| best_score - score <= self.tie_margin)
+            .map(|(name, _)| name.as_str())
+            .collect();
+
+        if tied.len() == 1 {
+            return Ok(tied[0].to_string());
+        }
+
+        tracing::debug!("Project classification tied between {:?}, asking the model", tied);
+        match self.break_tie(description, &tied, ai_client).await {
+            Ok(choice) => Ok(choice),
+            Err(e) => {
+                tracing::warn!("Failed to break project tie, using best match: {}", e);
+                Ok(tied[0].to_string())
+            }
+        }
+    }
+
+    async fn break_tie(&self, description: &str, candidates: &[&str], ai_client: &dyn AiProvider) -> Result<String> {
+        let projects = self.projects().await;
+        let descriptions: HashMap<&str, &str> = projects.iter()
+            .map(|(name, description)| (name.as_str(), description.as_str()))
+            .collect();
+        let options: Vec<String> = candidates.iter()
+            .map(|name| format!("\"{} - {}\"", name, descriptions.get(name).unwrap_or(&"")))
+            .collect();
+        let prompt = format!(
+            "You are a project classifier. Your output should be ONLY the project name, nothing else. Options are:\n{}{}",
+            options.join(",\n"),
+            self.examples.prompt(description, ai_client).await
+        );
+        let messages = vec![HashMap::from([
+            ("role".to_string(), "user".to_string()),
+            ("content".to_string(), format!("Which project does this task belong to? {}", description)),
+        ])];
+
+        let answer = ai_client.chat(&prompt, messages).await?;
+        let answer = answer.trim().trim_matches('"').trim_matches('\'').to_lowercase();
+        candidates.iter()
+            .find(|name| name.to_lowercase() == answer)
+            .map(|name| name.to_string())
+            .ok_or_else(|| anyhow!("Model chose '{}', which is not one of the tied projects", answer))
+    }
+}
+
+impl Default for ProjectClassifier {
+    fn default() -> Self {
+        Self::new(KNOWN_PROJECTS.iter()
+            .map(|(name, description)| (name.to_string(), description.to_string()))
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use async_trait::async_trait;
+
+    /// Embeds text as keyword counts over a tiny vocabulary
+    struct KeywordProvider {
+        answer: String,
+    }
+
+    #[async_trait]
+    impl AiProvider for KeywordProvider {
+        async fn chat(&self, _system_prompt: &str, _messages: Vec<HashMap<String, String>>) -> Result<String> {
+            Ok(self.answer.clone())
+        }
+
+        async fn embed(&self, text: &str) -> Result<Vec<f32>> {
+            let text = text.to_lowercase();
+            Ok(["rust", "docker", "docs"].iter()
+                .map(|word| text.matches(word).count() as f32)
+                .collect())
+        }
+    }
+
+    fn classifier() -> ProjectClassifier {
+        ProjectClassifier::new(vec![
+            ("swarm".to_string(), "agents in rust".to_string()),
+            ("deploy".to_string(), "docker images".to_string()),
+            ("manual".to_string(), "docs site".to_string()),
+        ])
+    }
+
+    #[tokio::test]
+    async fn test_classify_by_embedding() -> Result<()> {
+        let provider = KeywordProvider { answer: "manual".to_string() };
+        let project = classifier().classify("Fix the docker compose file", &provider).await?;
+        assert_eq!(project, "deploy");
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_ties_are_settled_by_the_model() -> Result<()> {
+        let description = "Write docs for the docker setup";
+
+        let provider = KeywordProvider { answer: "\"Manual\"".to_string() };
+        assert_eq!(classifier().classify(description, &provider).await?, "manual");
+
+        // An answer outside the tied candidates falls back to the best embedding match
+        let provider = KeywordProvider { answer: "swarm".to_string() };
+        let project = classifier().classify(description, &provider).await?;
+        assert!(project == "deploy" || project == "manual");
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_update_projects_invalidates_embeddings() -> Result<()> {
+        let provider = KeywordProvider { answer: String::new() };
+        let classifier = classifier();
+        assert_eq!(classifier.classify("rust rewrite", &provider).await?, "swarm");
+
+        classifier.update_projects(vec![
+            ("engine".to_string(), "rust core".to_string()),
+            ("deploy".to_string(), "docker images".to_string()),
+        ]).await;
+        assert_eq!(classifier.classify("rust rewrite", &provider).await?, "engine");
+        Ok(())
+    }
+}
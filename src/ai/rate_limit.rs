@@ -0,0 +1,192 @@
+use std::collections::HashMap;
+use std::env;
+use std::time::Duration;
+use anyhow::Result;
+use tokio::sync::{Mutex, Semaphore};
+use tokio::time::Instant;
+use crate::error::SwarmError;
+use crate::types::{Attachment, Tool};
+use super::{AiProvider, ChatResponse, JsonSchema};
+
+/// Limits for one provider
+#[derive(Debug, Clone, PartialEq)]
+pub struct RateLimit {
+    /// Sustained requests per second
+    pub qps: f64,
+    /// Requests allowed back to back before the QPS limit kicks in
+    pub burst: u32,
+    pub max_concurrent: usize,
+    /// How long a request may queue before failing
+    pub max_wait: Duration,
+}
+
+impl Default for RateLimit {
+    fn default() -> Self {
+        Self {
+            qps: 2.0,
+            burst: 4,
+            max_concurrent: 2,
+            max_wait: Duration::from_secs(60),
+        }
+    }
+}
+
+impl RateLimit {
+    /// Read `{PREFIX}_RATE_LIMIT_QPS`, `{PREFIX}_RATE_LIMIT_BURST`, `{PREFIX}_MAX_CONCURRENT`
+    /// and `{PREFIX}_MAX_WAIT_SECS`, e.g. `AI` for the default client or `OPENAI`
+    pub fn from_env(prefix: &str) -> Self {
+        fn var<T: std::str::FromStr>(name: String) -> Option<T> {
+            env::var(name).ok().and_then(|v| v.parse().ok())
+        }
+        let defaults = Self::default();
+        Self {
+            qps: var(format!("{}_RATE_LIMIT_QPS", prefix)).unwrap_or(defaults.qps),
+            burst: var(format!("{}_RATE_LIMIT_BURST", prefix)).unwrap_or(defaults.burst),
+            max_concurrent: var(format!("{}_MAX_CONCURRENT", prefix)).unwrap_or(defaults.max_concurrent),
+            max_wait: var(format!("{}_MAX_WAIT_SECS", prefix)).map(Duration::from_secs).unwrap_or(defaults.max_wait),
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Bucket {
+    tokens: f64,
+    refilled_at: Instant,
+}
+
+/// Token-bucket QPS limit plus a cap on concurrent requests. Requests queue until both
+/// allow them, failing with an `AiProvider` error if that takes longer than `max_wait`.
+pub struct RateLimitedAiClient<P> {
+    inner: P,
+    limit: RateLimit,
+    bucket: Mutex<Bucket>,
+    concurrency: Semaphore,
+}
+
+impl<P: AiProvider> RateLimitedAiClient<P> {
+    pub fn new(inner: P, limit: RateLimit) -> Self {
+        let burst = limit.burst.max(1) as f64;
+        Self {
+            inner,
+            bucket: Mutex::new(Bucket { tokens: burst, refilled_at: Instant::now() }),
+            concurrency: Semaphore::new(limit.max_concurrent.max(1)),
+            limit,
+        }
+    }
+
+    pub fn limit(&self) -> &RateLimit {
+        &self.limit
+    }
+
+    fn waited_too_long(&self) -> anyhow::Error {
+        SwarmError::AiProvider(format!("Rate limited: no capacity within {:?}", self.limit.max_wait)).into()
+    }
+
+    /// Wait for a token, giving up at `deadline`
+    async fn take_token(&self, deadline: Instant) -> Result<()> {
+        loop {
+            let wait = {
+                let mut bucket = self.bucket.lock().await;
+                let now = Instant::now();
+                let burst = self.limit.burst.max(1) as f64;
+                let elapsed = now.duration_since(bucket.refilled_at).as_secs_f64();
+                bucket.tokens = (bucket.tokens + elapsed * self.limit.qps).min(burst);
+                bucket.refilled_at = now;
+                if bucket.tokens >= 1.0 {
+                    bucket.tokens -= 1.0;
+                    return Ok(());
+                }
+                if self.limit.qps <= 0.0 {
+                    return Err(self.waited_too_long());
+                }
+                Duration::from_secs_f64((1.0 - bucket.tokens) / self.limit.qps)
+            };
+            if Instant::now() + wait > deadline {
+                return Err(self.waited_too_long());
+            }
+            tokio::time::sleep(wait).await;
+        }
+    }
+
+    /// Queue for a concurrency slot and a token, then run `call`
+    async fn throttled<T, F>(&self, call: F) -> Result<T>
+    where
+        F: std::future::Future<Output = Result<T>>,
+    {
+        let deadline = Instant::now() + self.limit.max_wait;
+        let _permit = tokio::time::timeout_at(deadline, self.concurrency.acquire()).await
+            .map_err(|_| self.waited_too_long())?
+            .expect("rate limiter semaphore is never closed");
+        self.take_token(deadline).await?;
+        call.await
+    }
+}
+
+#[async_trait::async_trait]
+impl<P: AiProvider> AiProvider for RateLimitedAiClient<P> {
+    async fn chat(&self, system_prompt: &str, messages: Vec<HashMap<String, String>>) -> Result<String> {
+        self.throttled(self.inner.chat(system_prompt, messages)).await
+    }
+
+    async fn chat_with_tools(&self, system_prompt: &str, messages: Vec<HashMap<String, String>>, tools: &[Tool]) -> Result<ChatResponse> {
+        self.throttled(self.inner.chat_with_tools(system_prompt, messages, tools)).await
+    }
+
+    async fn chat_structured(&self, system_prompt: &str, messages: Vec<HashMap<String, String>>, schema: &JsonSchema) -> Result<serde_json::Value> {
+        self.throttled(self.inner.chat_structured(system_prompt, messages, schema)).await
+    }
+
+    async fn embed(&self, text: &str) -> Result<Vec<f32>> {
+        self.throttled(self.inner.embed(text)).await
+    }
+
+    async fn chat_with_images(&self, system_prompt: &str, messages: Vec<HashMap<String, String>>, images: &[Attachment]) -> Result<String> {
+        self.throttled(self.inner.chat_with_images(system_prompt, messages, images)).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    struct SlowClient(Duration);
+
+    #[async_trait::async_trait]
+    impl AiProvider for SlowClient {
+        async fn chat(&self, _system_prompt: &str, _messages: Vec<HashMap<String, String>>) -> Result<String> {
+            tokio::time::sleep(self.0).await;
+            Ok("ok".to_string())
+        }
+    }
+
+    fn limit(qps: f64, burst: u32, max_concurrent: usize, max_wait_ms: u64) -> RateLimit {
+        RateLimit { qps, burst, max_concurrent, max_wait: Duration::from_millis(max_wait_ms) }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_requests_queue_for_tokens() {
+        let client = RateLimitedAiClient::new(SlowClient(Duration::ZERO), limit(2.0, 1, 4, 1000));
+        let start = Instant::now();
+        client.chat("", vec![]).await.unwrap();
+        client.chat("", vec![]).await.unwrap();
+        assert_eq!(start.elapsed(), Duration::from_millis(500));
+
+        let impatient = RateLimitedAiClient::new(SlowClient(Duration::ZERO), limit(2.0, 1, 4, 100));
+        impatient.chat("", vec![]).await.unwrap();
+        let error = impatient.chat("", vec![]).await.unwrap_err();
+        assert!(matches!(SwarmError::find(&error), Some(SwarmError::AiProvider(_))), "{}", error);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_concurrency_cap() {
+        let client = Arc::new(RateLimitedAiClient::new(SlowClient(Duration::from_secs(1)), limit(100.0, 10, 1, 500)));
+        let first = {
+            let client = client.clone();
+            tokio::spawn(async move { client.chat("", vec![]).await })
+        };
+        tokio::task::yield_now().await;
+        assert!(client.chat("", vec![]).await.is_err(), "second request should time out waiting for the slot");
+        assert_eq!(first.await.unwrap().unwrap(), "ok");
+    }
+}
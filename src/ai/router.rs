@@ -0,0 +1,97 @@
+//! Chooses which agent a message should go to from the agents' advertised capabilities.
+//! Messages naming one agent's commands or keywords more than any other's go to it; ties
+//! and messages matching nothing are put to the model along with the catalog, and ties it
+//! can't settle go to the cheapest agent.
+
+use std::collections::HashMap;
+use anyhow::Result;
+use crate::types::capabilities::AgentCapabilities;
+use super::AiProvider;
+
+/// The agent best suited to `content`, if any
+pub async fn route(content: &str, catalog: &[AgentCapabilities], ai_client: Option<&dyn AiProvider>) -> Result<Option<String>> {
+    let best = catalog.iter().map(|entry| entry.matches(content)).max().unwrap_or(0);
+    let candidates: Vec<&AgentCapabilities> = if best > 0 {
+        catalog.iter().filter(|entry| entry.matches(content) == best).collect()
+    } else {
+        catalog.iter().collect()
+    };
+    if best > 0 && candidates.len() == 1 {
+        return Ok(Some(candidates[0].agent.clone()));
+    }
+
+    if let Some(ai_client) = ai_client {
+        let messages = vec![HashMap::from([
+            ("role".to_string(), "user".to_string()),
+            ("content".to_string(), content.to_string()),
+        ])];
+        let reply = ai_client.chat(&routing_prompt(&candidates), messages).await?;
+        let answer = reply.trim().trim_matches(|c: char| !c.is_alphanumeric()).to_lowercase();
+        if let Some(entry) = candidates.iter().find(|entry| entry.agent.to_lowercase() == answer) {
+            return Ok(Some(entry.agent.clone()));
+        }
+    }
+
+    if best == 0 {
+        return Ok(None);
+    }
+    Ok(candidates.iter()
+        .min_by_key(|entry| entry.capabilities.iter().map(|c| c.cost).min().unwrap_or_default())
+        .map(|entry| entry.agent.clone()))
+}
+
+fn routing_prompt(candidates: &[&AgentCapabilities]) -> String {
+    let mut prompt = String::from(
+        "Choose the agent best suited to handle the user's message. \
+         Reply with only the agent's name, or \"none\" if no agent fits.\n\nAgents:\n",
+    );
+    for entry in candidates {
+        for capability in &entry.capabilities {
+            prompt.push_str(&format!("- {}: {}", entry.agent, capability.description));
+            if !capability.commands.is_empty() {
+                prompt.push_str(&format!(" (commands: {})", capability.commands.join(", ")));
+            }
+            prompt.push_str(&format!(" [accepts: {}; cost: {:?}]\n", capability.input_formats.join(", "), capability.cost));
+        }
+    }
+    prompt
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ai::MockAiProvider;
+    use crate::types::capabilities::{Capability, CostHint};
+
+    fn catalog() -> Vec<AgentCapabilities> {
+        let entry = |agent: &str, capability: Capability| AgentCapabilities { agent: agent.to_string(), capabilities: vec![capability] };
+        vec![
+            entry("git", Capability::new("git", "Version control").with_commands(["commit", "push"]).with_cost(CostHint::Free)),
+            entry("haiku", Capability::new("haiku", "Writes haikus").with_keywords(["poem", "haiku"]).with_cost(CostHint::Medium)),
+            entry("docs", Capability::new("docs", "Writes documentation").with_keywords(["poem", "readme"]).with_cost(CostHint::Low)),
+        ]
+    }
+
+    #[tokio::test]
+    async fn test_routes_by_capabilities() -> Result<()> {
+        assert_eq!(route("commit and push please", &catalog(), None).await?.as_deref(), Some("git"));
+        assert_eq!(route("write me a haiku", &catalog(), None).await?.as_deref(), Some("haiku"));
+        // A tie without a model goes to the cheaper agent
+        assert_eq!(route("a poem", &catalog(), None).await?.as_deref(), Some("docs"));
+        assert_eq!(route("hello there", &catalog(), None).await?, None);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_the_model_settles_unclear_messages() -> Result<()> {
+        let ai = MockAiProvider::new().with_response("Haiku.").with_response("git").with_response("none");
+        assert_eq!(route("a poem", &catalog(), Some(&ai)).await?.as_deref(), Some("haiku"));
+        // Only tied agents are offered
+        assert_eq!(route("a poem", &catalog(), Some(&ai)).await?.as_deref(), Some("docs"));
+        assert_eq!(route("hello there", &catalog(), Some(&ai)).await?, None);
+        let prompt = &ai.requests()[0].system_prompt;
+        assert!(prompt.contains("- haiku: Writes haikus [accepts: text; cost: Medium]"));
+        assert!(!prompt.contains("- git:"));
+        Ok(())
+    }
+}
@@ -0,0 +1,96 @@
+use std::future::Future;
+use tokio::sync::mpsc::UnboundedSender;
+
+tokio::task_local! {
+    static CHUNK_SINK: Option<UnboundedSender<String>>;
+}
+
+/// Run `future` with `sink` receiving partial AI output as providers generate it.
+/// Providers that can't stream just return their full reply as usual.
+pub async fn with_chunk_sink<F: Future>(sink: UnboundedSender<String>, future: F) -> F::Output {
+    CHUNK_SINK.scope(Some(sink), future).await
+}
+
+/// Run `future` without streaming, e.g. for internal calls like summarization
+/// whose output shouldn't reach the user
+pub async fn silenced<F: Future>(future: F) -> F::Output {
+    CHUNK_SINK.scope(None, future).await
+}
+
+/// Whether the current task has a sink listening for chunks
+pub fn is_streaming() -> bool {
+    CHUNK_SINK.try_with(|sink| sink.as_ref().is_some_and(|s| !s.is_closed())).unwrap_or(false)
+}
+
+/// Send a chunk of output to the current task's sink, if any
+pub fn emit_chunk(chunk: &str) {
+    let _ = CHUNK_SINK.try_with(|sink| {
+        if let Some(sink) = sink {
+            let _ = sink.send(chunk.to_string());
+        }
+    });
+}
+
+/// Splits a byte stream into UTF-8 chunks without breaking multi-byte characters
+#[derive(Debug, Default)]
+pub struct Utf8Chunker {
+    pending: Vec<u8>,
+}
+
+impl Utf8Chunker {
+    /// Decode as much of `bytes` (plus anything held back) as forms whole characters
+    pub fn push(&mut self, bytes: &[u8]) -> String {
+        self.pending.extend_from_slice(bytes);
+        let valid = match std::str::from_utf8(&self.pending) {
+            Ok(_) => self.pending.len(),
+            Err(e) if e.error_len().is_none() => e.valid_up_to(),
+            // Genuinely invalid bytes: decode lossily rather than stalling
+            Err(_) => self.pending.len(),
+        };
+        let decoded = String::from_utf8_lossy(&self.pending[..valid]).into_owned();
+        self.pending.drain(..valid);
+        decoded
+    }
+
+    /// Whatever is left at the end of the stream
+    pub fn finish(&mut self) -> String {
+        let rest = String::from_utf8_lossy(&self.pending).into_owned();
+        self.pending.clear();
+        rest
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::sync::mpsc::unbounded_channel;
+
+    #[tokio::test]
+    async fn test_chunks_reach_sink_only_when_streaming() {
+        let (tx, mut rx) = unbounded_channel();
+        with_chunk_sink(tx, async {
+            assert!(is_streaming());
+            emit_chunk("Digital ");
+            silenced(async {
+                assert!(!is_streaming());
+                emit_chunk("hidden");
+            }).await;
+            emit_chunk("petals");
+        }).await;
+
+        assert_eq!(rx.recv().await.as_deref(), Some("Digital "));
+        assert_eq!(rx.recv().await.as_deref(), Some("petals"));
+        assert!(rx.recv().await.is_none());
+        assert!(!is_streaming());
+    }
+
+    #[test]
+    fn test_utf8_chunker_holds_back_partial_characters() {
+        let mut chunker = Utf8Chunker::default();
+        let bytes = "🌸 bloom".as_bytes();
+        assert_eq!(chunker.push(&bytes[..2]), "");
+        assert_eq!(chunker.push(&bytes[2..5]), "🌸 ");
+        assert_eq!(chunker.push(&bytes[5..]), "bloom");
+        assert_eq!(chunker.finish(), "");
+    }
+}
@@ -0,0 +1,185 @@
+//! JSON replies that match a schema. Providers that can constrain decoding (OpenAI's
+//! `response_format`, Ollama's and TGI's grammars) override `AiProvider::chat_structured`;
+//! the rest are asked for JSON in the prompt. Either way the reply is validated, and a
+//! reply that doesn't parse or match gets one cleaning pass before giving up.
+
+use std::collections::HashMap;
+use anyhow::Result;
+use serde_json::Value;
+use crate::error::SwarmError;
+use super::AiProvider;
+
+const CLEANING_PROMPT: &str = "You are a JSON formatter. Rewrite the text as a single JSON value matching the JSON schema, keeping its content. \
+Output ONLY the JSON, with no other text or code fences.";
+
+/// A named JSON schema for a reply; OpenAI requires the name to be `[a-zA-Z0-9_-]+`
+#[derive(Debug, Clone)]
+pub struct JsonSchema {
+    pub name: String,
+    pub schema: Value,
+}
+
+impl JsonSchema {
+    pub fn new(name: impl Into<String>, schema: Value) -> Self {
+        Self { name: name.into(), schema }
+    }
+
+    /// Check `value` against the schema's `type`, `enum`, `properties`, `required`,
+    /// `additionalProperties: false` and `items`; other keywords are ignored
+    pub fn validate(&self, value: &Value) -> Result<()> {
+        check(&self.schema, value, "$").map_err(|e| SwarmError::AiProvider(format!("Reply doesn't match schema {}: {}", self.name, e)).into())
+    }
+
+    /// `system_prompt` with instructions to answer in JSON matching the schema
+    pub fn prompt(&self, system_prompt: &str) -> String {
+        format!(
+            "{}\n\nRespond with ONLY a JSON value, with no other text, matching this JSON schema:\n{}",
+            system_prompt,
+            serde_json::to_string_pretty(&self.schema).unwrap_or_default()
+        )
+    }
+
+    /// The validated JSON in `reply`
+    pub fn parse(&self, reply: &str) -> Result<Value> {
+        let value = extract_json(reply)
+            .ok_or_else(|| SwarmError::AiProvider("Reply contains no JSON".to_string()))?;
+        self.validate(&value)?;
+        Ok(value)
+    }
+}
+
+fn check(schema: &Value, value: &Value, path: &str) -> std::result::Result<(), String> {
+    if let Some(expected) = schema["type"].as_str() {
+        let matches = match expected {
+            "object" => value.is_object(),
+            "array" => value.is_array(),
+            "string" => value.is_string(),
+            "number" => value.is_number(),
+            "integer" => value.is_i64() || value.is_u64(),
+            "boolean" => value.is_boolean(),
+            "null" => value.is_null(),
+            _ => true,
+        };
+        if !matches {
+            return Err(format!("{} should be {}", path, expected));
+        }
+    }
+    if let Some(allowed) = schema["enum"].as_array() {
+        if !allowed.contains(value) {
+            return Err(format!("{} should be one of {}", path, schema["enum"]));
+        }
+    }
+    if let Some(object) = value.as_object() {
+        for required in schema["required"].as_array().into_iter().flatten().filter_map(Value::as_str) {
+            if !object.contains_key(required) {
+                return Err(format!("{}.{} is missing", path, required));
+            }
+        }
+        for (key, item) in object {
+            match schema["properties"].get(key) {
+                Some(property) => check(property, item, &format!("{}.{}", path, key))?,
+                None if schema["additionalProperties"] == Value::Bool(false) => return Err(format!("{}.{} isn't allowed", path, key)),
+                None => {}
+            }
+        }
+    }
+    if let (Some(items), Some(array)) = (schema.get("items"), value.as_array()) {
+        for (i, item) in array.iter().enumerate() {
+            check(items, item, &format!("{}[{}]", path, i))?;
+        }
+    }
+    Ok(())
+}
+
+/// The JSON in a reply, allowing for code fences and chatter around it
+pub fn extract_json(reply: &str) -> Option<Value> {
+    let trimmed = reply.trim();
+    if let Ok(value) = serde_json::from_str(trimmed) {
+        return Some(value);
+    }
+    let start = trimmed.find(['{', '['])?;
+    let close = if trimmed[start..].starts_with('{') { '}' } else { ']' };
+    let end = trimmed.rfind(close)?;
+    (end > start).then(|| serde_json::from_str(&trimmed[start..=end]).ok()).flatten()
+}
+
+/// Ask for JSON in the prompt; what providers without constrained decoding do
+pub async fn prompted<P: AiProvider + ?Sized>(
+    provider: &P,
+    system_prompt: &str,
+    messages: Vec<HashMap<String, String>>,
+    schema: &JsonSchema,
+) -> Result<Value> {
+    let reply = provider.chat(&schema.prompt(system_prompt), messages).await?;
+    parse_or_clean(provider, schema, &reply).await
+}
+
+/// The validated JSON in `reply`, or failing that what one cleaning pass makes of it
+pub async fn parse_or_clean<P: AiProvider + ?Sized>(provider: &P, schema: &JsonSchema, reply: &str) -> Result<Value> {
+    let problem = match schema.parse(reply) {
+        Ok(value) => return Ok(value),
+        Err(e) => e,
+    };
+    tracing::debug!("Cleaning structured reply ({})", problem);
+    let request = format!(
+        "Schema:\n{}\n\nProblem: {}\n\nText:\n{}",
+        serde_json::to_string_pretty(&schema.schema).unwrap_or_default(),
+        problem,
+        reply
+    );
+    let messages = vec![HashMap::from([
+        ("role".to_string(), "user".to_string()),
+        ("content".to_string(), request),
+    ])];
+    let cleaned = provider.chat(CLEANING_PROMPT, messages).await?;
+    schema.parse(&cleaned)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use crate::ai::MockAiProvider;
+
+    fn schema() -> JsonSchema {
+        JsonSchema::new("verdict", json!({
+            "type": "object",
+            "properties": {
+                "label": { "type": "string", "enum": ["bug", "feature"] },
+                "scores": { "type": "array", "items": { "type": "integer" } },
+            },
+            "required": ["label"],
+            "additionalProperties": false,
+        }))
+    }
+
+    #[test]
+    fn test_parse_and_validate() {
+        let schema = schema();
+        let value = schema.parse("Sure!\n```json\n{\"label\": \"bug\", \"scores\": [1, 2]}\n```").unwrap();
+        assert_eq!(value["label"], "bug");
+        let error = |reply: &str| schema.parse(reply).unwrap_err().to_string();
+        assert!(error(r#"{"label": "chore"}"#).ends_with(r#"$.label should be one of ["bug","feature"]"#));
+        assert!(error(r#"{"scores": []}"#).ends_with("$.label is missing"));
+        assert!(error(r#"{"label": "bug", "scores": [1.5]}"#).ends_with("$.scores[0] should be integer"));
+        assert!(error(r#"{"label": "bug", "extra": 1}"#).ends_with("$.extra isn't allowed"));
+        assert!(error("no json here").contains("no JSON"));
+    }
+
+    #[tokio::test]
+    async fn test_cleaning_pass_only_when_needed() {
+        let ai = MockAiProvider::new().with_response(r#"{"label": "feature"}"#);
+        let value = ai.chat_structured("Classify", vec![], &schema()).await.unwrap();
+        assert_eq!(value["label"], "feature");
+        assert_eq!(ai.call_count(), 1);
+        assert!(ai.requests()[0].system_prompt.contains("\"enum\""));
+
+        let ai = MockAiProvider::new()
+            .with_response("It's a bug, with scores 3 and 4")
+            .with_response(r#"{"label": "bug", "scores": [3, 4]}"#);
+        let value = ai.chat_structured("Classify", vec![], &schema()).await.unwrap();
+        assert_eq!(value["scores"], json!([3, 4]));
+        assert_eq!(ai.requests()[1].system_prompt, CLEANING_PROMPT);
+        assert!(ai.requests()[1].last_message().contains("It's a bug"));
+    }
+}
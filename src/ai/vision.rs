@@ -0,0 +1,96 @@
+//! Images for multimodal models. Callers pass `Attachment`s, e.g. a message's `images()`:
+//! inline `Image`s, or `File`s of an image type, which are read when the request is made and
+//! refused unless they hold image bytes.
+
+use anyhow::Result;
+use base64::{engine::general_purpose::STANDARD, Engine};
+use serde_json::{Value, json};
+use crate::error::SwarmError;
+use crate::types::{attachments::sniff_image, Attachment};
+
+/// An image as providers send it
+#[derive(Debug, Clone, PartialEq)]
+pub struct EncodedImage {
+    pub mime_type: String,
+    /// Base64
+    pub data: String,
+}
+
+impl EncodedImage {
+    pub fn data_url(&self) -> String {
+        format!("data:{};base64,{}", self.mime_type, self.data)
+    }
+}
+
+/// The images among `attachments`, encoded; anything else is skipped.
+/// Files must hold image bytes whatever their name or claimed type says.
+pub fn encode_images(attachments: &[Attachment]) -> Result<Vec<EncodedImage>> {
+    let mut images = Vec::new();
+    for attachment in attachments {
+        if !attachment.mime_type().map_or(false, |mime| mime.starts_with("image/")) {
+            continue;
+        }
+        let (data, mime_type) = match attachment {
+            Attachment::Image { data, mime_type } => (STANDARD.encode(data), mime_type.clone()),
+            Attachment::File { path, .. } => {
+                // The same error whether the file is missing or not an image
+                let data = std::fs::read(path).ok().filter(|data| sniff_image(data).is_some())
+                    .ok_or_else(|| SwarmError::Validation("Attachment is not a readable image".to_string()))?;
+                let mime_type = sniff_image(&data).unwrap_or_default().to_string();
+                (STANDARD.encode(data), mime_type)
+            }
+            Attachment::Json { .. } => continue,
+        };
+        images.push(EncodedImage { mime_type, data });
+    }
+    Ok(images)
+}
+
+/// OpenAI-style message content: the text, then each image
+pub fn content_parts(text: &str, images: &[EncodedImage]) -> Value {
+    let mut parts = vec![json!({ "type": "text", "text": text })];
+    parts.extend(images.iter().map(|image| json!({ "type": "image_url", "image_url": { "url": image.data_url() } })));
+    Value::Array(parts)
+}
+
+/// Index of the message images are attached to: the last from the user
+pub fn image_message_index(messages: &[Value]) -> Option<usize> {
+    messages.iter().rposition(|message| message["role"] == "user")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PNG_HEADER: &[u8] = b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR";
+
+    #[test]
+    fn test_images_are_encoded_and_attached_as_parts() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let screenshot = dir.path().join("screen.png");
+        std::fs::write(&screenshot, PNG_HEADER)?;
+        let attachments = [
+            Attachment::image(PNG_HEADER.to_vec()).unwrap(),
+            Attachment::file(&screenshot),
+            Attachment::file(dir.path().join("notes.md")),
+            Attachment::Json { value: json!({}) },
+        ];
+
+        let images = encode_images(&attachments)?;
+        assert_eq!(images.len(), 2);
+        assert_eq!(images[1].data_url(), "data:image/png;base64,iVBORw0KGgoAAAANSUhEUg==");
+        assert!(encode_images(&[Attachment::file(dir.path().join("missing.png"))]).is_err());
+        let secrets = dir.path().join("secrets");
+        std::fs::write(&secrets, "root:x:0:0")?;
+        let disguised = Attachment::File { path: secrets, mime_type: Some("image/png".to_string()) };
+        assert!(encode_images(&[disguised]).is_err());
+
+        let parts = content_parts("What is on this screen?", &images[..1]);
+        assert_eq!(parts[0], json!({ "type": "text", "text": "What is on this screen?" }));
+        assert_eq!(parts[1]["image_url"]["url"], images[0].data_url());
+
+        let messages = [json!({ "role": "system" }), json!({ "role": "user" }), json!({ "role": "assistant" })];
+        assert_eq!(image_message_index(&messages), Some(1));
+        Ok(())
+    }
+}
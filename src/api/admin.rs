@@ -0,0 +1,157 @@
+use std::future::Future;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use axum::{extract::State, Json};
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use crate::agents::concurrency::ConcurrencyStats;
+use crate::ai::{self, ProviderHealth};
+use crate::api::AppState;
+use crate::events::ErrorRecord;
+use crate::types::Agent;
+use crate::types::reporting::{QueueDepth, WorkerStatus};
+
+/// How long a storage check may take before it's reported as failed
+const STORAGE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Everything a dashboard shows, in one document
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct Overview {
+    pub generated_at: i64,
+    pub agents: Vec<AgentOverview>,
+    /// Pending and in-progress tasks per agent, including agents not registered here
+    pub task_queues: Vec<QueueDepth>,
+    /// Workers with claimed tasks, across every process sharing the task collection
+    pub workers: Vec<WorkerStatus>,
+    pub server: ServerStatus,
+    /// Providers AI calls in this process have gone through
+    pub ai_providers: Vec<ProviderHealth>,
+    pub storage: Vec<StorageLatency>,
+    /// Newest first
+    pub recent_errors: Vec<ErrorRecord>,
+}
+
+/// A registered agent and the messages it is handling
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct AgentOverview {
+    pub name: String,
+    pub description: String,
+    pub messages: ConcurrencyStats,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct ServerStatus {
+    pub shutting_down: bool,
+    pub in_flight_requests: usize,
+}
+
+/// One round trip to a store, or why it failed
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct StorageLatency {
+    pub store: String,
+    pub latency_ms: Option<f64>,
+    pub error: Option<String>,
+}
+
+impl StorageLatency {
+    async fn measure<E: std::fmt::Display>(store: &str, check: impl Future<Output = Result<(), E>>) -> Self {
+        let started = Instant::now();
+        let (latency_ms, error) = match tokio::time::timeout(STORAGE_TIMEOUT, check).await {
+            Ok(Ok(())) => (Some(started.elapsed().as_secs_f64() * 1000.0), None),
+            Ok(Err(e)) => (None, Some(e.to_string())),
+            Err(_) => (None, Some(format!("timed out after {:?}", STORAGE_TIMEOUT))),
+        };
+        Self { store: store.to_string(), latency_ms, error }
+    }
+}
+
+#[utoipa::path(
+    get, path = "/admin/overview", tag = "admin",
+    responses((status = 200, description = "Agents, queues, workers, AI provider health, storage latency and recent errors", body = Overview))
+)]
+pub async fn overview(State(state): State<Arc<AppState>>) -> Json<Overview> {
+    let mut agents = Vec::new();
+    for (name, agent) in state.agents.read().await.agents.iter() {
+        agents.push(AgentOverview {
+            name: name.clone(),
+            description: agent.get_config().await.map(|config| config.public_description).unwrap_or_default(),
+            messages: agent.concurrency_stats().await,
+        });
+    }
+    agents.sort_by(|a, b| a.name.cmp(&b.name));
+
+    let mut storage = Vec::new();
+    let (mut task_queues, mut workers) = (Vec::new(), Vec::new());
+    if let Some(todos) = &state.todos {
+        storage.push(StorageLatency::measure("mongodb", todos.ping()).await);
+        // Skip the aggregations when the database is already known to be down
+        if storage[0].error.is_none() {
+            task_queues = todos.queue_depths().await.unwrap_or_else(|e| {
+                tracing::warn!("Queue depths unavailable: {}", e);
+                Vec::new()
+            });
+            workers = todos.worker_statuses().await.unwrap_or_else(|e| {
+                tracing::warn!("Worker statuses unavailable: {}", e);
+                Vec::new()
+            });
+        }
+    }
+    let sessions = state.transfer_service.read().await.session_store();
+    storage.push(StorageLatency::measure("state_store", async move {
+        sessions.get("admin", "overview").await.map(|_| ())
+    }).await);
+
+    Json(Overview {
+        generated_at: Utc::now().timestamp(),
+        agents,
+        task_queues,
+        workers,
+        server: ServerStatus {
+            shutting_down: state.shutdown.is_shutting_down(),
+            in_flight_requests: state.shutdown.in_flight(),
+        },
+        ai_providers: ai::provider_health(),
+        storage,
+        recent_errors: state.recent_errors.as_ref().map(|errors| errors.snapshot()).unwrap_or_default(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{Router, body::Body, extract::Request, routing::get};
+    use tokio::sync::RwLock;
+    use tower::ServiceExt;
+    use crate::agents::{AgentRegistry, TransferService};
+    use crate::events::{DomainEvent, RecentErrors};
+    use crate::testing::EchoAgent;
+    use crate::testsupport::{self, MongoFixture};
+
+    #[tokio::test]
+    async fn test_overview_reports_agents_queues_and_errors() -> anyhow::Result<()> {
+        let mongo = MongoFixture::start().await?;
+        let todos = mongo.seeded([testsupport::task("Say hello", "echo")]).await?;
+        let mut registry = AgentRegistry::new().with_todos(todos.clone());
+        registry.register("echo".to_string(), Box::new(EchoAgent::new("echo"))).await?;
+        let registry = Arc::new(RwLock::new(registry));
+        let errors = Arc::new(RecentErrors::new(10));
+        errors.record(&DomainEvent::ai_provider_failed("openai", "rate limited"));
+        let mut state = AppState::new(Arc::new(RwLock::new(TransferService::new(registry.clone())))).with_todos(todos);
+        state.agents = registry;
+        state.recent_errors = Some(errors);
+        let app = Router::new().route("/admin/overview", get(overview)).with_state(Arc::new(state));
+
+        let response = app.oneshot(Request::get("/admin/overview").body(Body::empty())?).await?;
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await?;
+        let overview: serde_json::Value = serde_json::from_slice(&body)?;
+        assert_eq!(overview["agents"][0]["name"], "echo");
+        assert_eq!(overview["agents"][0]["messages"]["active"], 0);
+        assert_eq!(overview["task_queues"], serde_json::json!([{ "agent": "echo", "pending": 1, "in_progress": 0 }]));
+        assert_eq!(overview["workers"], serde_json::json!([]));
+        assert_eq!(overview["storage"].as_array().map(Vec::len), Some(2));
+        assert!(overview["storage"].as_array().unwrap().iter().all(|store| store["error"].is_null()), "{}", overview["storage"]);
+        assert_eq!(overview["recent_errors"][0]["source"], "openai");
+        Ok(())
+    }
+}
@@ -0,0 +1,98 @@
+use std::net::SocketAddr;
+use std::sync::Arc;
+use axum::{
+    extract::{ConnectInfo, Query, Request, State},
+    http::{Method, StatusCode},
+    middleware::Next,
+    response::Response,
+    Json,
+};
+use serde_json::json;
+use crate::api::AppState;
+use crate::audit::{AuditEntry, AuditQuery};
+use crate::types::api_keys::ApiKey;
+
+fn actor(request: &Request) -> String {
+    if let Some(key) = request.extensions().get::<ApiKey>() {
+        return format!("key:{}", key.name);
+    }
+    match request.extensions().get::<ConnectInfo<SocketAddr>>() {
+        Some(ConnectInfo(addr)) => format!("ip:{}", addr.ip()),
+        None => "anonymous".to_string(),
+    }
+}
+
+/// Record every request that may change state, with its caller and response status.
+/// Runs after authentication so the key is known. Does nothing when unconfigured.
+pub async fn audit_requests(State(state): State<Arc<AppState>>, request: Request, next: Next) -> Response {
+    let Some(audit) = state.audit.clone() else {
+        return next.run(request).await;
+    };
+    let method = request.method().clone();
+    if method == Method::GET || method == Method::HEAD || method == Method::OPTIONS {
+        return next.run(request).await;
+    }
+    let actor = actor(&request);
+    let path = request.uri().path().to_string();
+    let response = next.run(request).await;
+    let mut details = json!({ "method": method.as_str(), "status": response.status().as_u16() });
+    if let Some(id) = crate::correlation::current() {
+        details["correlation_id"] = id.into();
+    }
+    audit.record(AuditEntry::new(actor, "http_request", path, details)).await;
+    response
+}
+
+#[utoipa::path(
+    get, path = "/api/audit", tag = "audit",
+    params(AuditQuery),
+    responses(
+        (status = 200, description = "Matching entries, newest first", body = [AuditEntry]),
+        (status = 503, description = "Auditing is not configured"),
+    )
+)]
+pub async fn query_audit_log(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<AuditQuery>,
+) -> Result<Json<Vec<AuditEntry>>, StatusCode> {
+    let audit = state.audit.as_ref().ok_or(StatusCode::SERVICE_UNAVAILABLE)?;
+    let entries = audit.query(&query).await.map_err(|e| {
+        tracing::error!("Audit query failed: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+    Ok(Json(entries))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{Router, body::Body, middleware, routing::{get, post}};
+    use tokio::sync::RwLock;
+    use tower::ServiceExt;
+    use crate::agents::{AgentRegistry, TransferService};
+    use crate::audit::{AuditLog, InMemoryAuditStore};
+
+    #[tokio::test]
+    async fn test_mutations_are_recorded_and_queryable() -> anyhow::Result<()> {
+        let registry = Arc::new(RwLock::new(AgentRegistry::new()));
+        let state = Arc::new(AppState::new(Arc::new(RwLock::new(TransferService::new(registry))))
+            .with_audit(AuditLog::new(Arc::new(InMemoryAuditStore::new()))));
+        let app = Router::new()
+            .route("/api/projects", post(|| async { StatusCode::CREATED }).get(|| async { "[]" }))
+            .route("/api/audit", get(query_audit_log))
+            .layer(middleware::from_fn_with_state(state.clone(), audit_requests))
+            .with_state(state);
+
+        app.clone().oneshot(Request::post("/api/projects").body(Body::empty())?).await?;
+        app.clone().oneshot(Request::get("/api/projects").body(Body::empty())?).await?;
+
+        let response = app.oneshot(Request::get("/api/audit?action=http_request").body(Body::empty())?).await?;
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await?;
+        let entries: Vec<AuditEntry> = serde_json::from_slice(&body)?;
+        assert_eq!(entries.len(), 1, "reads are not audited");
+        assert_eq!((entries[0].actor.as_str(), entries[0].target.as_str()), ("anonymous", "/api/projects"));
+        assert_eq!(entries[0].details, json!({ "method": "POST", "status": 201 }));
+        Ok(())
+    }
+}
@@ -0,0 +1,273 @@
+use std::sync::Arc;
+use axum::{
+    extract::{Path, Request, State},
+    http::{Method, StatusCode, header},
+    middleware::Next,
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use crate::api::AppState;
+use crate::types::api_keys::{ApiKey, ApiKeyStore, MongoApiKeyStore, Scope, hash_key};
+
+/// Header accepted alongside `Authorization: Bearer <key>`
+pub const API_KEY_HEADER: &str = "x-api-key";
+/// Query parameter for clients that can't set headers, such as browser `EventSource`
+const API_KEY_PARAM: &str = "api_key";
+
+/// Validates API keys against a store. An optional bootstrap key from the environment
+/// has admin scope without being stored, so the first real keys can be created.
+pub struct ApiAuth {
+    store: Arc<dyn ApiKeyStore>,
+    bootstrap_hash: Option<String>,
+}
+
+impl ApiAuth {
+    pub fn new(store: Arc<dyn ApiKeyStore>) -> Self {
+        Self { store, bootstrap_hash: None }
+    }
+
+    pub fn with_bootstrap_key(mut self, secret: &str) -> Self {
+        self.bootstrap_hash = Some(hash_key(secret));
+        self
+    }
+
+    /// Keys in MongoDB when `API_KEYS_REQUIRED=true`, with `SWARM_ADMIN_API_KEY` as the
+    /// bootstrap key. Returns `None` when authentication is not required.
+    pub async fn from_env() -> anyhow::Result<Option<Self>> {
+        if !std::env::var("API_KEYS_REQUIRED").is_ok_and(|v| v == "true" || v == "1") {
+            return Ok(None);
+        }
+        let mut auth = Self::new(Arc::new(MongoApiKeyStore::new().await?));
+        if let Some(secret) = crate::secrets::get(crate::secrets::ADMIN_API_KEY) {
+            auth = auth.with_bootstrap_key(&secret);
+        }
+        Ok(Some(auth))
+    }
+
+    pub fn store(&self) -> &Arc<dyn ApiKeyStore> {
+        &self.store
+    }
+
+    /// The key matching `secret`, if any
+    pub async fn authenticate(&self, secret: &str) -> anyhow::Result<Option<ApiKey>> {
+        let key_hash = hash_key(secret);
+        if self.bootstrap_hash.as_deref() == Some(key_hash.as_str()) {
+            return Ok(Some(ApiKey {
+                id: "bootstrap".to_string(),
+                name: "bootstrap".to_string(),
+                key_hash,
+                scopes: vec![Scope::Admin],
+                created_at: 0,
+                revoked: false,
+                tenant: None,
+            }));
+        }
+        self.store.find_by_hash(&key_hash).await
+    }
+}
+
+/// The scope a route needs, or `None` for public routes. Unknown routes need admin.
+pub fn required_scope(method: &Method, path: &str) -> Option<Scope> {
+    let segments: Vec<&str> = path.trim_matches('/').split('/').collect();
+    let tasks = if method == Method::GET || method == Method::HEAD { Scope::ReadTasks } else { Scope::WriteTasks };
+    match segments.as_slice() {
+        [""] | ["openapi.json"] | ["swagger-ui", ..] => None,
+        ["api", "keys", ..] => Some(Scope::Admin),
+        ["api", "agents", _, "tasks", ..] | ["api", "projects", ..] | ["api", "dead-letters", ..] | ["api", "review", ..] | ["api", "tags"] | ["api", "digest"] | ["api", "workflows", ..] | ["api", "workflow-runs", ..] | ["tasks", ..] | ["hooks", _] => Some(tasks),
+        ["api", "agents", ..] | ["agents", ..] | ["v1", ..] | ["ws"] => Some(Scope::Chat),
+        _ => Some(Scope::Admin),
+    }
+}
+
+fn presented_key(request: &Request) -> Option<String> {
+    let headers = request.headers();
+    let bearer = headers.get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "));
+    let header_key = headers.get(API_KEY_HEADER).and_then(|v| v.to_str().ok());
+    let query_key = request.uri().query().and_then(|query| {
+        query.split('&').find_map(|pair| pair.strip_prefix(API_KEY_PARAM)?.strip_prefix('='))
+    });
+    bearer.or(header_key).or(query_key).map(|key| key.trim().to_string())
+}
+
+/// Reject requests without a key holding the route's scope. The key is added to the
+/// request extensions for handlers. Does nothing when authentication isn't configured.
+pub async fn require_api_key(State(state): State<Arc<AppState>>, request: Request, next: Next) -> Response {
+    let Some(auth) = &state.auth else {
+        return next.run(request).await;
+    };
+    let Some(scope) = required_scope(request.method(), request.uri().path()) else {
+        return next.run(request).await;
+    };
+    authorize(auth, scope, request, next).await
+}
+
+/// Reject requests without a key holding `scope`, for routers outside the API such as
+/// the MCP transport
+pub async fn require_scope(State((auth, scope)): State<(Arc<ApiAuth>, Scope)>, request: Request, next: Next) -> Response {
+    authorize(&auth, scope, request, next).await
+}
+
+async fn authorize(auth: &ApiAuth, scope: Scope, mut request: Request, next: Next) -> Response {
+    let Some(secret) = presented_key(&request) else {
+        return (StatusCode::UNAUTHORIZED, "API key required").into_response();
+    };
+    let key = match auth.authenticate(&secret).await {
+        Ok(Some(key)) if !key.revoked => key,
+        Ok(_) => return (StatusCode::UNAUTHORIZED, "Invalid API key").into_response(),
+        Err(e) => {
+            tracing::error!("API key lookup failed: {}", e);
+            return StatusCode::SERVICE_UNAVAILABLE.into_response();
+        }
+    };
+    if !key.allows(scope) {
+        return (StatusCode::FORBIDDEN, format!("API key '{}' lacks the {:?} scope", key.name, scope)).into_response();
+    }
+    request.extensions_mut().insert(key);
+    next.run(request).await
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct CreateKeyRequest {
+    pub name: String,
+    pub scopes: Vec<Scope>,
+    /// Bind the key to one tenant
+    #[serde(default)]
+    pub tenant: Option<String>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct CreatedKey {
+    #[serde(flatten)]
+    pub key: ApiKey,
+    /// Shown only once
+    pub secret: String,
+}
+
+fn store(state: &AppState) -> Result<&Arc<dyn ApiKeyStore>, StatusCode> {
+    state.auth.as_ref().map(|auth| auth.store()).ok_or(StatusCode::SERVICE_UNAVAILABLE)
+}
+
+#[utoipa::path(
+    get, path = "/api/keys", tag = "keys",
+    responses((status = 200, description = "Keys, without their hashes", body = [ApiKey]))
+)]
+pub async fn list_keys(State(state): State<Arc<AppState>>) -> Result<Json<Vec<ApiKey>>, StatusCode> {
+    let keys = store(&state)?.list().await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    Ok(Json(keys.iter().map(ApiKey::redacted).collect()))
+}
+
+#[utoipa::path(
+    post, path = "/api/keys", tag = "keys",
+    request_body = CreateKeyRequest,
+    responses(
+        (status = 201, description = "The key and its secret, shown only once", body = CreatedKey),
+        (status = 400, description = "No scopes given, or an invalid tenant"),
+    )
+)]
+pub async fn create_key(
+    State(state): State<Arc<AppState>>,
+    Json(request): Json<CreateKeyRequest>,
+) -> Result<(StatusCode, Json<CreatedKey>), StatusCode> {
+    if request.scopes.is_empty() {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    let (mut key, secret) = ApiKey::generate(request.name, request.scopes);
+    if let Some(tenant) = request.tenant {
+        crate::types::tenants::validate_tenant_id(&tenant).map_err(|_| StatusCode::BAD_REQUEST)?;
+        key = key.with_tenant(tenant);
+    }
+    store(&state)?.insert(&key).await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    Ok((StatusCode::CREATED, Json(CreatedKey { key: key.redacted(), secret })))
+}
+
+#[utoipa::path(
+    delete, path = "/api/keys/{id}", tag = "keys",
+    params(("id" = String, Path, description = "Key id")),
+    responses(
+        (status = 204, description = "Revoked"),
+        (status = 404, description = "No such key"),
+    )
+)]
+pub async fn revoke_key(State(state): State<Arc<AppState>>, Path(id): Path<String>) -> Result<StatusCode, StatusCode> {
+    match store(&state)?.revoke(&id).await {
+        Ok(true) => Ok(StatusCode::NO_CONTENT),
+        Ok(false) => Err(StatusCode::NOT_FOUND),
+        Err(_) => Err(StatusCode::INTERNAL_SERVER_ERROR),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{Router, body::Body, middleware, routing::{delete, get}};
+    use tokio::sync::RwLock;
+    use tower::ServiceExt;
+    use crate::agents::{AgentRegistry, TransferService};
+    use crate::types::api_keys::InMemoryApiKeyStore;
+
+    #[test]
+    fn test_routes_map_to_scopes() {
+        assert_eq!(required_scope(&Method::GET, "/"), None);
+        assert_eq!(required_scope(&Method::GET, "/swagger-ui/index.html"), None);
+        assert_eq!(required_scope(&Method::GET, "/api/agents/git/tasks"), Some(Scope::ReadTasks));
+        assert_eq!(required_scope(&Method::POST, "/api/agents/git/tasks"), Some(Scope::WriteTasks));
+        assert_eq!(required_scope(&Method::POST, "/api/agents/git/message"), Some(Scope::Chat));
+        assert_eq!(required_scope(&Method::POST, "/v1/chat/completions"), Some(Scope::Chat));
+        assert_eq!(required_scope(&Method::GET, "/tasks/events"), Some(Scope::ReadTasks));
+        assert_eq!(required_scope(&Method::GET, "/tasks/search"), Some(Scope::ReadTasks));
+        assert_eq!(required_scope(&Method::GET, "/api/tags"), Some(Scope::ReadTasks));
+        assert_eq!(required_scope(&Method::GET, "/api/digest"), Some(Scope::ReadTasks));
+        assert_eq!(required_scope(&Method::POST, "/api/workflows/release/run"), Some(Scope::WriteTasks));
+        assert_eq!(required_scope(&Method::POST, "/hooks/ci"), Some(Scope::WriteTasks));
+        assert_eq!(required_scope(&Method::POST, "/api/dead-letters/t1/requeue"), Some(Scope::WriteTasks));
+        assert_eq!(required_scope(&Method::POST, "/api/review/t1/correct"), Some(Scope::WriteTasks));
+        assert_eq!(required_scope(&Method::DELETE, "/api/keys/abc"), Some(Scope::Admin));
+        assert_eq!(required_scope(&Method::GET, "/admin/overview"), Some(Scope::Admin));
+        assert_eq!(required_scope(&Method::GET, "/something/new"), Some(Scope::Admin));
+    }
+
+    #[tokio::test]
+    async fn test_keys_are_enforced_and_managed() -> anyhow::Result<()> {
+        let registry = Arc::new(RwLock::new(AgentRegistry::new()));
+        let auth = ApiAuth::new(Arc::new(InMemoryApiKeyStore::new())).with_bootstrap_key("root-secret");
+        let mut state = AppState::new(Arc::new(RwLock::new(TransferService::new(registry))));
+        state.auth = Some(Arc::new(auth));
+        let state = Arc::new(state);
+        let app = Router::new()
+            .route("/api/keys", get(list_keys).post(create_key))
+            .route("/api/keys/:id", delete(revoke_key))
+            .route("/tasks/events", get(|| async { "events" }))
+            .layer(middleware::from_fn_with_state(state.clone(), require_api_key))
+            .with_state(state);
+        let send = |method: &str, uri: &str, key: Option<&str>, body: Option<&str>| {
+            let mut request = Request::builder().method(method).uri(uri);
+            if let Some(key) = key {
+                request = request.header(header::AUTHORIZATION, format!("Bearer {}", key));
+            }
+            if body.is_some() {
+                request = request.header(header::CONTENT_TYPE, "application/json");
+            }
+            app.clone().oneshot(request.body(Body::from(body.unwrap_or("").to_string())).unwrap())
+        };
+
+        assert_eq!(send("GET", "/tasks/events", None, None).await?.status(), StatusCode::UNAUTHORIZED);
+        assert_eq!(send("GET", "/tasks/events", Some("wrong"), None).await?.status(), StatusCode::UNAUTHORIZED);
+
+        let created = send("POST", "/api/keys", Some("root-secret"), Some(r#"{"name":"dash","scopes":["read-tasks"]}"#)).await?;
+        assert_eq!(created.status(), StatusCode::CREATED);
+        let created: serde_json::Value = serde_json::from_slice(&axum::body::to_bytes(created.into_body(), usize::MAX).await?)?;
+        let (id, secret) = (created["id"].as_str().unwrap(), created["secret"].as_str().unwrap());
+        assert!(created.get("key_hash").is_none());
+
+        assert_eq!(send("GET", &format!("/tasks/events?api_key={}", secret), None, None).await?.status(), StatusCode::OK);
+        assert_eq!(send("GET", "/api/keys", Some(secret), None).await?.status(), StatusCode::FORBIDDEN);
+
+        assert_eq!(send("DELETE", &format!("/api/keys/{}", id), Some("root-secret"), None).await?.status(), StatusCode::NO_CONTENT);
+        assert_eq!(send("GET", "/tasks/events", Some(secret), None).await?.status(), StatusCode::UNAUTHORIZED);
+        Ok(())
+    }
+}
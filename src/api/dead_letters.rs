@@ -0,0 +1,126 @@
+use std::sync::Arc;
+use axum::{
+    extract::{Extension, Path, Query, State},
+    http::StatusCode,
+    Json,
+};
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+use crate::api::AppState;
+use super::tenants::Tenant;
+use crate::types::{TodoList, todo::TaskFailure, dead_letter::{DeadLetter, DeadLetterEdit}};
+use crate::types::api_keys::ApiKey;
+use super::models::TaskResponse;
+
+#[derive(Debug, Deserialize, IntoParams)]
+pub struct DeadLetterQuery {
+    /// Most entries to return; defaults to 50
+    pub limit: Option<usize>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct DeadLetterResponse {
+    pub task: TaskResponse,
+    pub notes: Option<String>,
+    /// Every failed attempt, oldest first
+    pub failures: Vec<TaskFailure>,
+    pub dead_lettered_at: i64,
+}
+
+impl From<DeadLetter> for DeadLetterResponse {
+    fn from(dead_letter: DeadLetter) -> Self {
+        let mut task = dead_letter.task;
+        Self {
+            notes: task.notes.take(),
+            failures: std::mem::take(&mut task.failures),
+            task: TaskResponse::from(task),
+            dead_lettered_at: dead_letter.dead_lettered_at,
+        }
+    }
+}
+
+fn todos(state: &AppState, tenant: &Tenant) -> Result<TodoList, StatusCode> {
+    state.todos.as_ref().map(|todos| tenant.todos(todos)).ok_or(StatusCode::SERVICE_UNAVAILABLE)
+}
+
+fn storage_error(e: mongodb::error::Error) -> StatusCode {
+    tracing::error!("Dead-letter storage error: {}", e);
+    StatusCode::INTERNAL_SERVER_ERROR
+}
+
+#[utoipa::path(
+    get, path = "/api/dead-letters", tag = "tasks",
+    params(DeadLetterQuery),
+    responses((status = 200, description = "Tasks that exhausted their retries, most recent first", body = [DeadLetterResponse]))
+)]
+pub async fn list_dead_letters(
+    State(state): State<Arc<AppState>>,
+    tenant: Tenant,
+    Query(query): Query<DeadLetterQuery>,
+) -> Result<Json<Vec<DeadLetterResponse>>, StatusCode> {
+    let dead_letters = todos(&state, &tenant)?.dead_letters(query.limit.unwrap_or(50)).await.map_err(storage_error)?;
+    Ok(Json(dead_letters.into_iter().map(DeadLetterResponse::from).collect()))
+}
+
+#[utoipa::path(
+    get, path = "/api/dead-letters/{id}", tag = "tasks",
+    params(("id" = String, Path, description = "Task id")),
+    responses(
+        (status = 200, description = "The dead-lettered task", body = DeadLetterResponse),
+        (status = 404, description = "No such dead-lettered task"),
+    )
+)]
+pub async fn get_dead_letter(
+    State(state): State<Arc<AppState>>,
+    tenant: Tenant,
+    Path(id): Path<String>,
+) -> Result<Json<DeadLetterResponse>, StatusCode> {
+    let dead_letter = todos(&state, &tenant)?.get_dead_letter(&id).await.map_err(storage_error)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+    Ok(Json(dead_letter.into()))
+}
+
+#[utoipa::path(
+    put, path = "/api/dead-letters/{id}", tag = "tasks",
+    params(("id" = String, Path, description = "Task id")),
+    request_body = DeadLetterEdit,
+    responses(
+        (status = 200, description = "The edited task, still dead-lettered", body = DeadLetterResponse),
+        (status = 404, description = "No such dead-lettered task"),
+    )
+)]
+pub async fn edit_dead_letter(
+    State(state): State<Arc<AppState>>,
+    tenant: Tenant,
+    Path(id): Path<String>,
+    Json(edit): Json<DeadLetterEdit>,
+) -> Result<Json<DeadLetterResponse>, StatusCode> {
+    let dead_letter = todos(&state, &tenant)?.edit_dead_letter(&id, edit).await.map_err(storage_error)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+    Ok(Json(dead_letter.into()))
+}
+
+/// Put the task back in the queue with fresh attempts. The body may edit it first,
+/// e.g. to send it to a different agent.
+#[utoipa::path(
+    post, path = "/api/dead-letters/{id}/requeue", tag = "tasks",
+    params(("id" = String, Path, description = "Task id")),
+    request_body = DeadLetterEdit,
+    responses(
+        (status = 200, description = "The requeued task", body = TaskResponse),
+        (status = 404, description = "No such dead-lettered task"),
+    )
+)]
+pub async fn requeue_dead_letter(
+    State(state): State<Arc<AppState>>,
+    tenant: Tenant,
+    api_key: Option<Extension<ApiKey>>,
+    Path(id): Path<String>,
+    edit: Option<Json<DeadLetterEdit>>,
+) -> Result<Json<TaskResponse>, StatusCode> {
+    let edit = edit.map(|Json(edit)| edit).unwrap_or_default();
+    let actor = super::routes::actor(api_key.as_ref().map(|Extension(key)| key));
+    let task = todos(&state, &tenant)?.requeue_dead_letter(&id, edit, &actor).await.map_err(storage_error)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+    Ok(Json(task.into()))
+}
@@ -0,0 +1,47 @@
+use std::sync::Arc;
+use axum::{
+    extract::{Query, State},
+    http::StatusCode,
+    Json,
+};
+use chrono::Utc;
+use serde::Deserialize;
+use utoipa::IntoParams;
+use crate::ai::{BudgetedAiClient, DefaultAiClient};
+use crate::api::AppState;
+use super::tenants::Tenant;
+use crate::types::digest::{Digest, summarize};
+
+#[derive(Debug, Deserialize, IntoParams)]
+pub struct DigestQuery {
+    /// Hours of activity to cover, 24 by default
+    pub hours: Option<u32>,
+}
+
+#[utoipa::path(
+    get, path = "/api/digest", tag = "tasks",
+    params(DigestQuery),
+    responses(
+        (status = 200, description = "Tasks created, completed and failed in the window, summarized per project", body = Digest),
+        (status = 400, description = "hours is 0"),
+        (status = 503, description = "Task storage is unavailable"),
+    )
+)]
+pub async fn get_digest(
+    State(state): State<Arc<AppState>>,
+    tenant: Tenant,
+    Query(query): Query<DigestQuery>,
+) -> Result<Json<Digest>, StatusCode> {
+    let hours = query.hours.unwrap_or(24);
+    if hours == 0 {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    let todos = state.todos.as_ref().map(|todos| tenant.todos(todos)).ok_or(StatusCode::SERVICE_UNAVAILABLE)?;
+    let until = Utc::now().timestamp();
+    let activity = todos.activity(until - i64::from(hours) * 3600, until).await.map_err(|e| {
+        tracing::error!("Digest failed: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+    let ai_client = BudgetedAiClient::new("digest", DefaultAiClient::new());
+    Ok(Json(summarize(&activity, &ai_client).await))
+}
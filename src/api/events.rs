@@ -0,0 +1,101 @@
+use std::convert::Infallible;
+use std::sync::Arc;
+use std::time::Duration;
+use axum::{
+    extract::State,
+    response::sse::{Event, KeepAlive, Sse},
+};
+use futures::{Stream, StreamExt, stream::BoxStream};
+use crate::api::AppState;
+use super::tenants::Tenant;
+use crate::events;
+use crate::types::TaskEvent;
+
+fn sse_event(event: TaskEvent) -> Result<Event, Infallible> {
+    let sse = Event::default().event(event.kind.as_str());
+    Ok(sse.json_data(&event).unwrap_or_else(|_| Event::default().comment("unserializable task event")))
+}
+
+/// Task lifecycle events as SSE, named `created`, `started`, `completed` or `failed`.
+/// Uses the MongoDB change stream when available, so changes made by other processes
+/// show up; otherwise only this server's own changes are seen. Callers acting for a tenant
+/// only get events known to be for that tenant.
+pub async fn task_events(State(state): State<Arc<AppState>>, tenant: Tenant) -> Sse<impl Stream<Item = Result<Event, Infallible>>> {
+    let events: BoxStream<'static, TaskEvent> = match &state.todos {
+        Some(todos) => match tenant.todos(todos).watch_changes().await {
+            Ok(changes) => changes.boxed(),
+            Err(e) => {
+                tracing::debug!("Task change stream unavailable, using in-process events: {}", e);
+                events::task_events(events::global()).boxed()
+            }
+        },
+        None => events::task_events(events::global()).boxed(),
+    };
+    let tenant = tenant.0;
+    let events = events.filter(move |event| std::future::ready(tenant.is_none() || event.tenant_id == tenant));
+    Sse::new(events.map(sse_event)).keep_alive(KeepAlive::new().interval(Duration::from_secs(15)))
+}
+
+/// Every domain event this server publishes (transfers, tool calls, task changes and the
+/// rest) as SSE named like `message_routed`, for monitors such as `swarm-top`
+pub async fn domain_events() -> Sse<impl Stream<Item = Result<Event, Infallible>>> {
+    let events = events::global().subscribe().map(|event| {
+        let sse = Event::default().event(event.name());
+        Ok(sse.json_data(&event).unwrap_or_else(|_| Event::default().comment("unserializable event")))
+    });
+    Sse::new(events).keep_alive(KeepAlive::new().interval(Duration::from_secs(15)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{Router, routing::get};
+    use tokio::sync::RwLock;
+    use crate::agents::{AgentRegistry, TransferService};
+    use crate::types::TaskEventKind;
+
+    #[tokio::test]
+    async fn test_streams_published_events() -> anyhow::Result<()> {
+        let registry = Arc::new(RwLock::new(AgentRegistry::new()));
+        let state = Arc::new(AppState::new(Arc::new(RwLock::new(TransferService::new(registry)))));
+        let app = Router::new().route("/tasks/events", get(task_events)).with_state(state);
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+        let url = format!("http://{}/tasks/events", listener.local_addr()?);
+        let server = tokio::spawn(async move { axum::serve(listener, app).await });
+
+        let mut response = reqwest::get(url).await?;
+        // The subscription exists once headers arrive, so this event can't be missed
+        events::global().publish(TaskEvent::new(TaskEventKind::Completed, "sse-test-task").into());
+
+        let mut received = String::new();
+        while !received.contains("sse-test-task") {
+            let chunk = response.chunk().await?.ok_or_else(|| anyhow::anyhow!("stream ended"))?;
+            received.push_str(std::str::from_utf8(&chunk)?);
+        }
+        let event = received.split("\n\n").find(|e| e.contains("sse-test-task")).unwrap();
+        assert!(event.lines().any(|l| l == "event: completed"), "{}", event);
+        server.abort();
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_streams_every_domain_event() -> anyhow::Result<()> {
+        let app: Router = Router::new().route("/admin/events", get(domain_events));
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+        let url = format!("http://{}/admin/events", listener.local_addr()?);
+        let server = tokio::spawn(async move { axum::serve(listener, app).await });
+
+        let mut response = reqwest::get(url).await?;
+        events::global().publish(events::DomainEvent::message_routed(Some("greeter"), "sse-test-agent"));
+
+        let mut received = String::new();
+        while !received.contains("sse-test-agent") {
+            let chunk = response.chunk().await?.ok_or_else(|| anyhow::anyhow!("stream ended"))?;
+            received.push_str(std::str::from_utf8(&chunk)?);
+        }
+        let event = received.split("\n\n").find(|e| e.contains("sse-test-agent")).unwrap();
+        assert!(event.lines().any(|l| l == "event: message_routed"), "{}", event);
+        server.abort();
+        Ok(())
+    }
+}
@@ -0,0 +1,37 @@
+use std::sync::Arc;
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    Json,
+};
+use crate::api::AppState;
+use super::tenants::Tenant;
+use crate::types::history::TaskChange;
+
+/// What happened to a task since it was added, including a dead-lettered one
+#[utoipa::path(
+    get, path = "/tasks/{id}/history", tag = "tasks",
+    params(("id" = String, Path, description = "Task id")),
+    responses(
+        (status = 200, description = "The task's changes, oldest first", body = [TaskChange]),
+        (status = 404, description = "No such task"),
+    )
+)]
+pub async fn task_history(
+    State(state): State<Arc<AppState>>,
+    tenant: Tenant,
+    Path(id): Path<String>,
+) -> Result<Json<Vec<TaskChange>>, StatusCode> {
+    let todos = state.todos.as_ref().map(|todos| tenant.todos(todos)).ok_or(StatusCode::SERVICE_UNAVAILABLE)?;
+    let storage_error = |e: mongodb::error::Error| {
+        tracing::error!("Task history lookup failed: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    };
+    let task = match todos.get_task(&id).await.map_err(storage_error)? {
+        Some(task) => task,
+        None => todos.get_dead_letter(&id).await.map_err(storage_error)?
+            .map(|dead_letter| dead_letter.task)
+            .ok_or(StatusCode::NOT_FOUND)?,
+    };
+    Ok(Json(task.history))
+}
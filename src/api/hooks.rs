@@ -0,0 +1,111 @@
+use std::sync::Arc;
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    Json,
+};
+use serde::Serialize;
+use serde_json::Value;
+use utoipa::ToSchema;
+use crate::api::AppState;
+use super::tenants::Tenant;
+use crate::types::{Agent, Message};
+use crate::types::webhooks::HookOutput;
+use crate::types::todo::NewTask;
+use super::models::TaskResponse;
+
+#[derive(Debug, Serialize, ToSchema)]
+#[serde(tag = "result", rename_all = "snake_case")]
+pub enum HookResponse {
+    /// The hook's conditions didn't match the payload
+    Ignored,
+    Task { task: TaskResponse },
+    Message { agent: String, reply: Message },
+}
+
+#[utoipa::path(
+    post, path = "/hooks/{name}", tag = "hooks",
+    params(("name" = String, Path, description = "Hook name from HOOKS_CONFIG")),
+    request_body(content = Object, description = "Any JSON payload"),
+    responses(
+        (status = 200, description = "The task created or the agent's reply", body = HookResponse),
+        (status = 202, description = "Ignored: the hook's conditions didn't match", body = HookResponse),
+        (status = 404, description = "No such hook, or the agent it names"),
+        (status = 422, description = "The payload didn't fill the hook's templates"),
+        (status = 503, description = "Hooks aren't configured, or task storage is unavailable"),
+    )
+)]
+pub async fn receive_hook(
+    State(state): State<Arc<AppState>>,
+    tenant: Tenant,
+    Path(name): Path<String>,
+    Json(payload): Json<Value>,
+) -> Result<(StatusCode, Json<HookResponse>), (StatusCode, String)> {
+    let hooks = state.hooks.as_ref().ok_or((StatusCode::SERVICE_UNAVAILABLE, "No hooks configured".to_string()))?;
+    let rule = hooks.hooks.get(&name).ok_or((StatusCode::NOT_FOUND, format!("No hook {}", name)))?;
+    let output = rule.apply(&payload).map_err(|e| (StatusCode::UNPROCESSABLE_ENTITY, e.to_string()))?;
+    let response = match output {
+        None => return Ok((StatusCode::ACCEPTED, Json(HookResponse::Ignored))),
+        Some(HookOutput::Task { description, target_agent, priority, project, tags }) => {
+            let todos = state.todos.as_ref()
+                .map(|todos| tenant.todos(todos))
+                .ok_or((StatusCode::SERVICE_UNAVAILABLE, "Task storage unavailable".to_string()))?;
+            let new = NewTask { description, priority, source_agent: Some(format!("hook:{}", name)), target_agent, project, tags, idempotency_key: None, allow_duplicate: false };
+            let task = super::routes::ingest_task(&state, todos, new).await.map_err(|e| {
+                tracing::error!("Hook {} failed to add a task: {}", name, e);
+                (super::routes::error_status(&e), "Failed to add task".to_string())
+            })?;
+            HookResponse::Task { task: TaskResponse::from(task.into_task()) }
+        }
+        Some(HookOutput::Message { agent, content }) => {
+            // Clone the agent out so the registry isn't locked while it works
+            let target = state.agents.read().await.get(&agent)
+                .filter(|_| tenant.sees_agent(&state, &agent))
+                .cloned()
+                .ok_or((StatusCode::NOT_FOUND, format!("No agent {}", agent)))?;
+            let reply = target.process_message(tenant.tag(&agent, Message::new(content))).await.map_err(|e| {
+                tracing::error!("Agent '{}' failed to process hook {}: {}", agent, name, e);
+                (super::routes::error_status(&e), "Agent failed to process the message".to_string())
+            })?;
+            HookResponse::Message { agent, reply }
+        }
+    };
+    Ok((StatusCode::OK, Json(response)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{Router, body::Body, http::Request, routing::post};
+    use tokio::sync::RwLock;
+    use tower::ServiceExt;
+    use crate::agents::{AgentRegistry, TransferService};
+    use crate::types::webhooks::HookConfig;
+
+    #[tokio::test]
+    async fn test_hook_statuses() -> anyhow::Result<()> {
+        let hooks: HookConfig = serde_yaml::from_str(r#"
+hooks:
+  ci:
+    when: [{ path: "$.status", equals: failed }]
+    task: { description: "{{ $.job }}", target_agent: git }
+"#)?;
+        let registry = Arc::new(RwLock::new(AgentRegistry::new()));
+        let state = AppState::new(Arc::new(RwLock::new(TransferService::new(registry)))).with_hooks(hooks);
+        let app = Router::new().route("/hooks/:name", post(receive_hook)).with_state(Arc::new(state));
+        let post_json = |uri: &str, body: &str| Request::post(uri)
+            .header("content-type", "application/json")
+            .body(Body::from(body.to_string()));
+
+        let passed = app.clone().oneshot(post_json("/hooks/ci", r#"{"status": "passed"}"#)?).await?;
+        assert_eq!(passed.status(), StatusCode::ACCEPTED);
+        let unknown = app.clone().oneshot(post_json("/hooks/deploy", "{}")?).await?;
+        assert_eq!(unknown.status(), StatusCode::NOT_FOUND);
+        let empty = app.clone().oneshot(post_json("/hooks/ci", r#"{"status": "failed"}"#)?).await?;
+        assert_eq!(empty.status(), StatusCode::UNPROCESSABLE_ENTITY);
+        // Matches, but there is no task storage in this state
+        let failed = app.oneshot(post_json("/hooks/ci", r#"{"status": "failed", "job": "lint"}"#)?).await?;
+        assert_eq!(failed.status(), StatusCode::SERVICE_UNAVAILABLE);
+        Ok(())
+    }
+}
@@ -1,19 +1,48 @@
 use std::sync::Arc;
 use std::net::SocketAddr;
 use axum::{
-    routing::{get, post},
+    extract::{Request, State},
+    http::StatusCode,
+    middleware::{self, Next},
+    response::{IntoResponse, Response},
+    routing::{delete, get, post},
     Router,
 };
+use crate::types::{TodoList, projects::ProjectRegistry, state_store::{self, StateStore}, tenants::TenantConfig, webhooks::HookConfig};
+use crate::workflow::WorkflowEngine;
+use crate::config::reload::{self, ConfigReloader, SwarmConfig};
 use tower_http::cors::CorsLayer;
 use tokio::sync::RwLock;
 use crate::{
     agents::{AgentRegistry, TransferService},
+    correlation,
+    events::RecentErrors,
+    ingest::{IngestConfig, IngestPipeline, TaskIntake},
+    mesh::{AgentMesh, MeshConfig},
+    shutdown::ShutdownCoordinator,
     types::Agent,
 };
 
 mod models;
 mod routes;
 mod websocket;
+mod projects;
+mod openai;
+mod events;
+mod dead_letters;
+mod review;
+mod search;
+mod tags;
+mod hooks;
+mod digest;
+mod workflows;
+mod history;
+pub mod admin;
+pub mod tenants;
+pub mod openapi;
+pub mod auth;
+pub mod rate_limit;
+pub mod audit;
 
 pub use models::*;
 pub use routes::*;
@@ -22,58 +51,385 @@ pub use websocket::*;
 pub struct AppState {
     pub transfer_service: Arc<RwLock<TransferService>>,
     pub agents: Arc<RwLock<AgentRegistry>>,
+    /// Project definitions, when MongoDB is configured
+    pub projects: Option<Arc<ProjectRegistry>>,
+    /// Shared task collection for reporting, when MongoDB is configured
+    pub todos: Option<TodoList>,
+    /// Refuses requests and tracks in-flight ones for graceful shutdown
+    pub shutdown: Arc<ShutdownCoordinator>,
+    /// API key checks; requests are unauthenticated when unset
+    pub auth: Option<Arc<auth::ApiAuth>>,
+    /// Per-client request limits; unlimited when unset
+    pub rate_limiter: Option<Arc<rate_limit::ClientRateLimiter>>,
+    /// Record of mutating requests; not audited when unset
+    pub audit: Option<Arc<crate::audit::AuditLog>>,
+    /// Rules for `/hooks/{name}`; hooks are disabled when unset
+    pub hooks: Option<Arc<HookConfig>>,
+    /// Workflows runnable through `/api/workflows`; disabled when unset
+    pub workflows: Option<Arc<WorkflowEngine>>,
+    /// Tenants callers may act for and the agents each sees; any tenant when unset
+    pub tenants: Option<Arc<TenantConfig>>,
+    /// Bounded queues new tasks pass through; tasks are stored as they arrive when unset
+    pub ingest: Option<Arc<IngestPipeline<TaskIntake>>>,
+    /// Failures shown by `/admin/overview`; none are kept when unset
+    pub recent_errors: Option<Arc<RecentErrors>>,
 }
 
 impl AppState {
     pub fn new(transfer_service: Arc<RwLock<TransferService>>) -> Self {
         Self {
             transfer_service,
-            agents: Arc::new(RwLock::new(AgentRegistry::new()))
+            agents: Arc::new(RwLock::new(AgentRegistry::new())),
+            projects: None,
+            todos: None,
+            shutdown: Arc::new(ShutdownCoordinator::default()),
+            auth: None,
+            rate_limiter: None,
+            audit: None,
+            hooks: None,
+            workflows: None,
+            tenants: None,
+            ingest: None,
+            recent_errors: None,
         }
     }
+
+    pub fn with_projects(mut self, projects: Arc<ProjectRegistry>) -> Self {
+        self.projects = Some(projects);
+        self
+    }
+
+    pub fn with_todos(mut self, todos: TodoList) -> Self {
+        self.todos = Some(todos);
+        self
+    }
+
+    pub fn with_shutdown(mut self, shutdown: Arc<ShutdownCoordinator>) -> Self {
+        self.shutdown = shutdown;
+        self
+    }
+
+    pub fn with_auth(mut self, auth: auth::ApiAuth) -> Self {
+        self.auth = Some(Arc::new(auth));
+        self
+    }
+
+    pub fn with_rate_limiter(mut self, limiter: rate_limit::ClientRateLimiter) -> Self {
+        self.rate_limiter = Some(Arc::new(limiter));
+        self
+    }
+
+    pub fn with_audit(mut self, audit: crate::audit::AuditLog) -> Self {
+        self.audit = Some(Arc::new(audit));
+        self
+    }
+
+    pub fn with_hooks(mut self, hooks: HookConfig) -> Self {
+        self.hooks = Some(Arc::new(hooks));
+        self
+    }
+
+    pub fn with_workflows(mut self, workflows: WorkflowEngine) -> Self {
+        self.workflows = Some(Arc::new(workflows));
+        self
+    }
+
+    pub fn with_tenants(mut self, tenants: TenantConfig) -> Self {
+        self.tenants = Some(Arc::new(tenants));
+        self
+    }
+
+    pub fn with_ingest(mut self, ingest: Arc<IngestPipeline<TaskIntake>>) -> Self {
+        self.ingest = Some(ingest);
+        self
+    }
+
+    pub fn with_recent_errors(mut self, errors: Arc<RecentErrors>) -> Self {
+        self.recent_errors = Some(errors);
+        self
+    }
 }
 
-pub async fn create_app_state() -> Arc<AppState> {
-    let registry = AgentRegistry::create_default_agents(routes::default_agents()).await.unwrap();
+/// Reject requests with 503 once shutdown has begun, and count the rest as in flight
+async fn track_in_flight(State(state): State<Arc<AppState>>, request: Request, next: Next) -> Response {
+    let Some(_guard) = state.shutdown.begin() else {
+        return (StatusCode::SERVICE_UNAVAILABLE, "Server is shutting down").into_response();
+    };
+    next.run(request).await
+}
+
+async fn connect_todo_list() -> Option<TodoList> {
+    if crate::secrets::get(crate::secrets::MONGO_URI).is_none() {
+        return None;
+    }
+    match TodoList::shared().await {
+        Ok(todos) => Some(todos),
+        Err(e) => {
+            tracing::warn!("Todo list unavailable: {}", e);
+            None
+        }
+    }
+}
+
+/// Misconfigured stages are logged and tasks stored as they arrive, as without the pipeline
+fn start_ingest() -> Option<Arc<IngestPipeline<TaskIntake>>> {
+    match IngestConfig::from_env() {
+        Ok(config) => Some(IngestPipeline::start(Arc::new(TaskIntake), config)),
+        Err(e) => {
+            tracing::warn!("Ingest pipeline disabled: {}", e);
+            None
+        }
+    }
+}
+
+async fn connect_project_registry() -> Option<Arc<ProjectRegistry>> {
+    match ProjectRegistry::new().await {
+        Ok(registry) => Some(Arc::new(registry)),
+        Err(e) => {
+            tracing::warn!("Project registry unavailable: {}", e);
+            None
+        }
+    }
+}
+
+/// Conversation routes in the configured state store, so they survive restarts; in memory otherwise
+async fn connect_session_store() -> Option<Arc<dyn StateStore>> {
+    match state_store::from_env().await {
+        Ok(store) => store,
+        Err(e) => {
+            tracing::warn!("Session routes will not persist: {}", e);
+            None
+        }
+    }
+}
+
+/// Join the agent mesh when `SWARM_MESH_NODE` is set, so agents on other nodes are routable
+async fn connect_mesh(registry: Arc<RwLock<AgentRegistry>>) -> Option<Arc<AgentMesh>> {
+    let config = match MeshConfig::from_env() {
+        Ok(config) => config?,
+        Err(e) => {
+            tracing::warn!("Agent mesh unavailable: {}", e);
+            return None;
+        }
+    };
+    match AgentMesh::start(config, registry).await {
+        Ok(mesh) => Some(mesh),
+        Err(e) => {
+            tracing::warn!("Agent mesh unavailable: {}", e);
+            None
+        }
+    }
+}
+
+/// Authentication fails closed: if keys are required but can't be checked, don't serve
+async fn connect_api_auth() -> Option<auth::ApiAuth> {
+    auth::ApiAuth::from_env().await
+        .expect("API_KEYS_REQUIRED is set but the API key store is unavailable")
+}
+
+/// The audit log, recording events from the global bus as well as API requests
+async fn connect_audit_log() -> Option<Arc<crate::audit::AuditLog>> {
+    match crate::audit::AuditLog::from_env().await {
+        Ok(audit) => {
+            let audit = Arc::new(audit);
+            audit.follow(crate::events::global());
+            Some(audit)
+        }
+        Err(e) => {
+            tracing::warn!("Audit log unavailable: {}", e);
+            None
+        }
+    }
+}
+
+/// How many failures `/admin/overview` shows
+const RECENT_ERRORS: usize = 50;
+
+/// Failures published from now on, for `/admin/overview`
+fn follow_recent_errors() -> Arc<RecentErrors> {
+    let errors = Arc::new(RecentErrors::new(RECENT_ERRORS));
+    errors.follow(crate::events::global());
+    errors
+}
+
+/// Hook rules fail closed too: a broken config is a deployment mistake, not a reason to
+/// silently drop payloads
+fn load_hooks() -> Option<Arc<HookConfig>> {
+    HookConfig::from_env().expect("HOOKS_CONFIG is set but could not be loaded").map(Arc::new)
+}
+
+/// A broken tenant list fails closed, rather than letting callers name any tenant
+fn load_tenants() -> Option<Arc<TenantConfig>> {
+    TenantConfig::from_env().expect("TENANTS_CONFIG is set but could not be loaded").map(Arc::new)
+}
+
+/// Like hooks, a broken workflow config fails closed
+async fn load_workflows(agents: Arc<RwLock<AgentRegistry>>) -> Option<Arc<WorkflowEngine>> {
+    let engine = WorkflowEngine::from_env().await.expect("WORKFLOWS_CONFIG is set but could not be loaded")?;
+    let tools = crate::tools::ToolRegistry::create_default_tools().await
+        .expect("Failed to create the tools workflows run");
+    Some(Arc::new(engine.with_agents(agents).with_tools(Arc::new(tools))))
+}
+
+/// Agents from `swarmonomicon.toml` (or `SWARM_CONFIG`), reloaded as it changes; the
+/// built-in agents otherwise
+async fn create_registry() -> Arc<RwLock<AgentRegistry>> {
+    let Some(path) = SwarmConfig::path_from_env() else {
+        let registry = AgentRegistry::create_default_agents(routes::default_agents()).await.unwrap();
+        return Arc::new(RwLock::new(registry));
+    };
+    let configs = SwarmConfig::load(&path).expect("The swarm config could not be loaded");
+    let registry = AgentRegistry::create_default_agents(configs.clone()).await.unwrap();
     let registry = Arc::new(RwLock::new(registry));
-    let transfer_service = Arc::new(RwLock::new(TransferService::new(registry.clone())));
+    ConfigReloader::new(path, registry.clone(), configs).spawn(reload::poll_interval());
+    registry
+}
 
-    Arc::new(AppState::new(transfer_service))
+pub async fn create_app_state() -> Arc<AppState> {
+    let registry = create_registry().await;
+    let mut transfer_service = TransferService::new(registry.clone());
+    if let Some(sessions) = connect_session_store().await {
+        transfer_service = transfer_service.with_session_store(sessions);
+    }
+    if let Some(mesh) = connect_mesh(registry.clone()).await {
+        transfer_service = transfer_service.with_mesh(mesh);
+    }
+    let transfer_service = Arc::new(RwLock::new(transfer_service));
+
+    let mut state = AppState::new(transfer_service);
+    if let Some(projects) = connect_project_registry().await {
+        state = state.with_projects(projects);
+    }
+    if let Some(todos) = connect_todo_list().await {
+        state = state.with_todos(todos);
+        state.ingest = start_ingest();
+    }
+    if let Some(auth) = connect_api_auth().await {
+        state = state.with_auth(auth);
+    }
+    state = state.with_rate_limiter(rate_limit::ClientRateLimiter::new(rate_limit::ClientLimits::from_env()));
+    state.audit = connect_audit_log().await;
+    state.hooks = load_hooks();
+    state.workflows = load_workflows(registry).await;
+    state.tenants = load_tenants();
+    Arc::new(state.with_recent_errors(follow_recent_errors()))
 }
 
 pub async fn serve(addr: SocketAddr, transfer_service: Arc<RwLock<TransferService>>) {
-    let registry = AgentRegistry::create_default_agents(routes::default_agents()).await.unwrap();
+    let agents = create_registry().await;
     let app_state = Arc::new(AppState {
         transfer_service,
-        agents: Arc::new(RwLock::new(registry)),
+        agents: agents.clone(),
+        projects: connect_project_registry().await,
+        todos: connect_todo_list().await,
+        shutdown: Arc::new(ShutdownCoordinator::default()),
+        auth: connect_api_auth().await.map(Arc::new),
+        rate_limiter: Some(Arc::new(rate_limit::ClientRateLimiter::new(rate_limit::ClientLimits::from_env()))),
+        audit: connect_audit_log().await,
+        hooks: load_hooks(),
+        workflows: load_workflows(agents).await,
+        tenants: load_tenants(),
+        ingest: start_ingest(),
+        recent_errors: Some(follow_recent_errors()),
     });
 
+    let shutdown = app_state.shutdown.clone();
+    let agents = app_state.agents.clone();
+    shutdown.on_shutdown("agents", move || async move {
+        agents.read().await.shutdown_all().await
+    }).await;
+    shutdown.listen_for_signals();
+
     let app = Router::new()
         .route("/", get(routes::index))
         .route("/api/agents", get(routes::list_agents))
         .route("/api/agents/:name", get(routes::get_agent))
         .route("/api/agents/:name/message", post(routes::process_message))
+        .route("/api/agents/:name/state-machine.dot", get(routes::get_state_machine_dot))
+        .route("/api/agents/:name/queue", get(routes::get_agent_queue))
+        .route("/api/agents/:name/metrics", get(routes::get_agent_metrics))
         .route("/api/agents/:name/send", post(routes::send_message))
+        .route("/api/agents/:name/tools/:tool", post(routes::call_agent_tool))
         .route("/api/agents/:name/tasks", get(routes::get_tasks))
         .route("/api/agents/:name/tasks", post(routes::add_task))
-        .route("/api/agents/:name/tasks/:task_id", get(routes::get_task))
+        .route("/api/agents/:name/tasks/queue", get(routes::get_task_queue))
+        .route("/api/agents/:name/tasks/:task_id", get(routes::get_task).patch(routes::edit_task))
+        .route("/api/agents/:name/tasks/:task_id/position", get(routes::get_task_position))
+        .route("/api/agents/:name/tasks/:task_id/cancel", post(routes::cancel_task))
+        .route("/api/agents/:name/tasks/:task_id/reassign", post(routes::reassign_task))
+        .route("/api/ingest", get(routes::get_ingest_metrics))
+        .route("/api/projects", get(projects::list_projects).post(projects::add_project))
+        .route("/api/projects/:name", get(projects::get_project).put(projects::update_project))
+        .route("/api/projects/:name/archive", post(projects::archive_project))
+        .route("/api/projects/:name/stats", get(projects::project_stats))
+        .route("/api/tags", get(tags::tag_stats))
+        .route("/api/digest", get(digest::get_digest))
+        .route("/api/dead-letters", get(dead_letters::list_dead_letters))
+        .route("/api/dead-letters/:id", get(dead_letters::get_dead_letter).put(dead_letters::edit_dead_letter))
+        .route("/api/dead-letters/:id/requeue", post(dead_letters::requeue_dead_letter))
+        .route("/api/review", get(review::review_queue))
+        .route("/api/review/:id/approve", post(review::approve))
+        .route("/api/review/:id/correct", post(review::correct))
+        .route("/tasks/events", get(events::task_events))
+        .route("/tasks/search", get(search::search_tasks))
+        .route("/tasks/:id/history", get(history::task_history))
+        .route("/hooks/:name", post(hooks::receive_hook))
+        .route("/api/workflows", get(workflows::list_workflows))
+        .route("/api/workflows/:name/run", post(workflows::run_workflow))
+        .route("/api/workflow-runs", get(workflows::list_workflow_runs))
+        .route("/api/workflow-runs/:id", get(workflows::get_workflow_run))
+        .route("/v1/chat/completions", post(openai::chat_completions))
+        .route("/v1/models", get(openai::list_models))
+        .route("/api/keys", get(auth::list_keys).post(auth::create_key))
+        .route("/api/keys/:id", delete(auth::revoke_key))
+        .route("/api/audit", get(audit::query_audit_log))
+        .route("/admin/overview", get(admin::overview))
+        .route("/admin/events", get(events::domain_events))
         .route("/ws", get(websocket::websocket_handler))
+        .merge(openapi::docs())
+        .layer(middleware::from_fn_with_state(app_state.clone(), audit::audit_requests))
+        .layer(middleware::from_fn_with_state(app_state.clone(), rate_limit::limit_requests))
+        .layer(middleware::from_fn_with_state(app_state.clone(), auth::require_api_key))
+        .layer(middleware::from_fn_with_state(app_state.clone(), rate_limit::limit_failed_auth))
+        .layer(middleware::from_fn_with_state(app_state.clone(), track_in_flight))
+        .layer(middleware::from_fn(correlation::correlate_requests))
         .layer(CorsLayer::permissive())
         .with_state(app_state);
 
     println!("Server running on {}", addr);
-    axum::serve(
+    let server = axum::serve(
         tokio::net::TcpListener::bind(addr).await.unwrap(),
-        app,
+        // Peer addresses identify clients without an API key for rate limiting
+        app.into_make_service_with_connect_info::<SocketAddr>(),
     )
-    .await
-    .unwrap();
+    .with_graceful_shutdown({
+        let shutdown = shutdown.clone();
+        async move { shutdown.signalled().await }
+    });
+
+    // Open connections (e.g. websockets) may outlive the drain timeout, so stop once
+    // the coordinator is done rather than waiting on them indefinitely
+    tokio::select! {
+        result = server => result.unwrap(),
+        _ = shutdown.finished() => {}
+    }
+    if shutdown.is_shutting_down() {
+        shutdown.finished().await;
+    }
 }
 
 pub fn create_router(state: Arc<AppState>) -> Router {
     Router::new()
         .route("/agents", get(routes::list_agents))
         .route("/agents/:agent_name/message", post(routes::send_message))
+        .route("/agents/:agent_name/state-machine.dot", get(routes::get_state_machine_dot))
+        .route("/tasks/events", get(events::task_events))
         .route("/ws", get(websocket::websocket_handler))
+        .layer(middleware::from_fn_with_state(state.clone(), audit::audit_requests))
+        .layer(middleware::from_fn_with_state(state.clone(), rate_limit::limit_requests))
+        .layer(middleware::from_fn_with_state(state.clone(), auth::require_api_key))
+        .layer(middleware::from_fn_with_state(state.clone(), rate_limit::limit_failed_auth))
+        .layer(middleware::from_fn_with_state(state.clone(), track_in_flight))
+        .layer(middleware::from_fn(correlation::correlate_requests))
         .with_state(state)
 }
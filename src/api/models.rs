@@ -1,7 +1,9 @@
 use crate::types::{TodoTask, TaskPriority, TaskStatus};
+use crate::types::history::TaskChange;
 use serde::Serialize;
+use utoipa::ToSchema;
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, ToSchema)]
 pub struct TaskResponse {
     pub id: String,
     pub description: String,
@@ -13,6 +15,16 @@ pub struct TaskResponse {
     pub status: TaskStatus,
     pub created_at: i64,
     pub completed_at: Option<i64>,
+    pub failure_reason: Option<String>,
+    pub tags: Vec<String>,
+    /// How sure AI enhancement was of the priority and project, from 0 to 1
+    pub confidence: Option<f32>,
+    pub reviewed_at: Option<i64>,
+    pub tenant_id: Option<String>,
+    /// The key the producer added the task with, if any
+    pub idempotency_key: Option<String>,
+    /// Status changes, retries, edits and reassignments, oldest first
+    pub history: Vec<TaskChange>,
 }
 
 impl From<TodoTask> for TaskResponse {
@@ -28,6 +40,13 @@ impl From<TodoTask> for TaskResponse {
             status: task.status,
             created_at: task.created_at,
             completed_at: task.completed_at,
+            failure_reason: task.failure_reason,
+            tags: task.tags,
+            confidence: task.confidence,
+            reviewed_at: task.reviewed_at,
+            tenant_id: task.tenant_id,
+            idempotency_key: task.idempotency_key,
+            history: task.history,
         }
     }
 } 
@@ -0,0 +1,339 @@
+//! The OpenAI chat completions wire format, so OpenAI client libraries and UIs can talk to
+//! agents unmodified. The requested model names the agent. Agents keep their own
+//! conversation state, so only the latest user message is forwarded; earlier turns and
+//! system prompts in the request are ignored.
+
+use std::convert::Infallible;
+use std::sync::Arc;
+use axum::{
+    extract::State,
+    http::StatusCode,
+    response::{IntoResponse, Response, sse::{Event, Sse}},
+    Json,
+};
+use base64::Engine as _;
+use futures::StreamExt;
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+use tokio::sync::mpsc::unbounded_channel;
+use uuid::Uuid;
+use crate::agents::AgentWrapper;
+use crate::ai::{count_tokens, streaming};
+use crate::api::AppState;
+use crate::types::{Agent, Attachment, Message};
+use super::routes::error_status;
+use super::tenants::Tenant;
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ChatCompletionRequest {
+    pub model: String,
+    pub messages: Vec<ChatMessage>,
+    #[serde(default)]
+    pub stream: bool,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ChatMessage {
+    pub role: String,
+    #[serde(default)]
+    pub content: Option<ChatContent>,
+}
+
+/// Plain text, or the array-of-parts form used for images
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged)]
+pub enum ChatContent {
+    Text(String),
+    Parts(Vec<ContentPart>),
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ContentPart {
+    Text { text: String },
+    ImageUrl { image_url: ImageUrl },
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ImageUrl {
+    pub url: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ChatCompletion {
+    pub id: String,
+    pub object: &'static str,
+    pub created: i64,
+    pub model: String,
+    pub choices: Vec<Value>,
+    pub usage: Usage,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct Usage {
+    pub prompt_tokens: usize,
+    pub completion_tokens: usize,
+    pub total_tokens: usize,
+}
+
+/// An error in OpenAI's `{"error": {...}}` shape
+fn openai_error(status: StatusCode, message: impl Into<String>, code: &str) -> Response {
+    let kind = if status.is_client_error() { "invalid_request_error" } else { "api_error" };
+    (status, Json(json!({ "error": { "message": message.into(), "type": kind, "code": code } }))).into_response()
+}
+
+/// Decode a `data:<mime>;base64,<data>` URL into an image attachment. Remote URLs aren't fetched.
+fn image_attachment(url: &str) -> Option<Attachment> {
+    let (header, data) = url.strip_prefix("data:")?.split_once(',')?;
+    let mime_type = header.strip_suffix(";base64")?.to_string();
+    let data = base64::engine::general_purpose::STANDARD.decode(data).ok()?;
+    Some(Attachment::Image { data, mime_type })
+}
+
+/// The message to hand the agent: the latest user turn with any inline images attached
+pub fn agent_message(request: &ChatCompletionRequest) -> Option<Message> {
+    let content = request.messages.iter().rev().find(|m| m.role == "user")?.content.as_ref()?;
+    let message = match content {
+        ChatContent::Text(text) => Message::new(text.clone()),
+        ChatContent::Parts(parts) => {
+            let mut texts = Vec::new();
+            let mut attachments = Vec::new();
+            for part in parts {
+                match part {
+                    ContentPart::Text { text } => texts.push(text.as_str()),
+                    ContentPart::ImageUrl { image_url } => match image_attachment(&image_url.url) {
+                        Some(attachment) => attachments.push(attachment),
+                        None => tracing::debug!("Ignoring image that isn't a base64 data URL"),
+                    },
+                }
+            }
+            Message::new(texts.join("\n")).with_attachments(attachments)
+        }
+    };
+    Some(message.with_role(Some("user".to_string())))
+}
+
+impl ChatCompletion {
+    pub fn new(model: &str, prompt: &str, reply: &str) -> Self {
+        let (prompt_tokens, completion_tokens) = (count_tokens(prompt), count_tokens(reply));
+        Self {
+            id: format!("chatcmpl-{}", Uuid::new_v4().simple()),
+            object: "chat.completion",
+            created: chrono::Utc::now().timestamp(),
+            model: model.to_string(),
+            choices: vec![json!({
+                "index": 0,
+                "message": { "role": "assistant", "content": reply },
+                "finish_reason": "stop",
+            })],
+            usage: Usage { prompt_tokens, completion_tokens, total_tokens: prompt_tokens + completion_tokens },
+        }
+    }
+}
+
+/// The `chat.completion.chunk` events of one streamed completion
+#[derive(Debug, Clone)]
+pub struct CompletionChunks {
+    id: String,
+    created: i64,
+    model: String,
+}
+
+impl CompletionChunks {
+    pub fn new(model: &str) -> Self {
+        Self {
+            id: format!("chatcmpl-{}", Uuid::new_v4().simple()),
+            created: chrono::Utc::now().timestamp(),
+            model: model.to_string(),
+        }
+    }
+
+    fn chunk(&self, delta: Value, finish_reason: Value) -> String {
+        json!({
+            "id": self.id,
+            "object": "chat.completion.chunk",
+            "created": self.created,
+            "model": self.model,
+            "choices": [{ "index": 0, "delta": delta, "finish_reason": finish_reason }],
+        }).to_string()
+    }
+
+    /// The opening chunk, naming the assistant role
+    pub fn role(&self) -> String {
+        self.chunk(json!({ "role": "assistant" }), Value::Null)
+    }
+
+    pub fn content(&self, text: &str) -> String {
+        self.chunk(json!({ "content": text }), Value::Null)
+    }
+
+    pub fn stop(&self) -> String {
+        self.chunk(json!({}), json!("stop"))
+    }
+}
+
+/// What the client hasn't seen of the agent's reply once `streamed` model output has been
+/// sent. Agents may post-process or replace what the model produced; a replacement
+/// follows the streamed text, as in the REPL.
+fn unsent(streamed: &str, reply: &str) -> Option<String> {
+    if streamed.trim() == reply.trim() {
+        return None;
+    }
+    match reply.strip_prefix(streamed) {
+        Some(rest) => Some(rest.to_string()),
+        None if streamed.is_empty() => Some(reply.to_string()),
+        None => Some(format!("\n\n{}", reply)),
+    }
+}
+
+/// Run the agent in the background, sending model output to the client as it's generated,
+/// then the rest of the reply, the finish reason and `[DONE]`. The agent finishes its
+/// turn even if the client goes away.
+fn stream_completion(agent: AgentWrapper, model: String, message: Message) -> Response {
+    let chunks = CompletionChunks::new(&model);
+    let (events, data) = futures::channel::mpsc::unbounded::<String>();
+    tokio::spawn(async move {
+        let _ = events.unbounded_send(chunks.role());
+        let (sink, mut tokens) = unbounded_channel();
+        let mut streamed = String::new();
+        let result = {
+            let response = streaming::with_chunk_sink(sink, agent.process_message(message));
+            tokio::pin!(response);
+            loop {
+                tokio::select! {
+                    Some(token) = tokens.recv() => {
+                        let _ = events.unbounded_send(chunks.content(&token));
+                        streamed.push_str(&token);
+                    }
+                    result = &mut response => break result,
+                }
+            }
+        };
+        while let Ok(token) = tokens.try_recv() {
+            let _ = events.unbounded_send(chunks.content(&token));
+            streamed.push_str(&token);
+        }
+
+        match result {
+            Ok(reply) => {
+                if let Some(rest) = unsent(&streamed, &reply.content) {
+                    let _ = events.unbounded_send(chunks.content(&rest));
+                }
+                let _ = events.unbounded_send(chunks.stop());
+            }
+            Err(e) => {
+                tracing::error!("Agent '{}' failed to process message: {}", model, e);
+                let error = json!({ "error": { "message": format!("{:#}", e), "type": "api_error", "code": "agent_error" } });
+                let _ = events.unbounded_send(error.to_string());
+            }
+        }
+        let _ = events.unbounded_send("[DONE]".to_string());
+    });
+    Sse::new(data.map(|data| Ok::<_, Infallible>(Event::default().data(data)))).into_response()
+}
+
+pub async fn chat_completions(
+    State(state): State<Arc<AppState>>,
+    tenant: Tenant,
+    Json(request): Json<ChatCompletionRequest>,
+) -> Response {
+    let agent = state.agents.read().await.get(&request.model).filter(|_| tenant.sees_agent(&state, &request.model)).cloned();
+    let Some(agent) = agent else {
+        return openai_error(StatusCode::NOT_FOUND, format!("The model '{}' does not exist", request.model), "model_not_found");
+    };
+    let Some(message) = agent_message(&request) else {
+        return openai_error(StatusCode::BAD_REQUEST, "messages must include a user message", "invalid_messages");
+    };
+
+    let message = tenant.tag(&request.model, message);
+    if request.stream {
+        return stream_completion(agent, request.model, message);
+    }
+    let prompt = message.content.clone();
+    let reply = match agent.process_message(message).await {
+        Ok(reply) => reply,
+        Err(e) => {
+            tracing::error!("Agent '{}' failed to process message: {}", request.model, e);
+            return openai_error(error_status(&e), format!("{:#}", e), "agent_error");
+        }
+    };
+
+    Json(ChatCompletion::new(&request.model, &prompt, &reply.content)).into_response()
+}
+
+/// Agents listed as models, for clients that populate a model picker
+pub async fn list_models(State(state): State<Arc<AppState>>, tenant: Tenant) -> Json<Value> {
+    let mut names: Vec<String> = state.agents.read().await.agents.keys()
+        .filter(|name| tenant.sees_agent(&state, name))
+        .cloned()
+        .collect();
+    names.sort();
+    let models: Vec<Value> = names.into_iter()
+        .map(|name| json!({ "id": name, "object": "model", "created": 0, "owned_by": "swarmonomicon" }))
+        .collect();
+    Json(json!({ "object": "list", "data": models }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(body: Value) -> ChatCompletionRequest {
+        serde_json::from_value(body).unwrap()
+    }
+
+    #[test]
+    fn test_latest_user_message_is_forwarded() {
+        let text = request(json!({
+            "model": "haiku",
+            "messages": [
+                { "role": "system", "content": "Be brief" },
+                { "role": "user", "content": "first" },
+                { "role": "assistant", "content": "ok" },
+                { "role": "user", "content": "write about rust" },
+            ],
+        }));
+        assert_eq!(agent_message(&text).unwrap().content, "write about rust");
+
+        let parts = request(json!({
+            "model": "haiku",
+            "messages": [{ "role": "user", "content": [
+                { "type": "text", "text": "what is this?" },
+                { "type": "image_url", "image_url": { "url": "data:image/png;base64,iVBO" } },
+                { "type": "image_url", "image_url": { "url": "https://example.com/cat.png" } },
+            ] }],
+        }));
+        let message = agent_message(&parts).unwrap();
+        assert_eq!(message.content, "what is this?");
+        assert!(matches!(&message.attachments[..], [Attachment::Image { mime_type, .. }] if mime_type == "image/png"));
+
+        assert!(agent_message(&request(json!({ "model": "haiku", "messages": [{ "role": "system", "content": "x" }] }))).is_none());
+    }
+
+    #[test]
+    fn test_completion_and_stream_format() {
+        let completion = ChatCompletion::new("haiku", "rust", "Silicon dreams flow");
+        assert_eq!(completion.choices[0]["message"]["content"], "Silicon dreams flow");
+        assert_eq!(completion.usage.total_tokens, completion.usage.prompt_tokens + completion.usage.completion_tokens);
+
+        let chunks = CompletionChunks::new("haiku");
+        let parsed: Vec<Value> = [chunks.role(), chunks.content("Silicon"), chunks.stop()].iter()
+            .map(|c| serde_json::from_str(c).unwrap())
+            .collect();
+        assert!(parsed.iter().all(|c| c["id"] == parsed[0]["id"] && c["object"] == "chat.completion.chunk" && c["model"] == "haiku"));
+        assert_eq!(parsed[0]["choices"][0]["delta"]["role"], "assistant");
+        assert_eq!(parsed[1]["choices"][0]["delta"]["content"], "Silicon");
+        assert_eq!(parsed[2]["choices"][0]["finish_reason"], "stop");
+    }
+
+    #[test]
+    fn test_unsent_reply() {
+        // Nothing streamed, e.g. the provider can't stream
+        assert_eq!(unsent("", "Silicon dreams flow").as_deref(), Some("Silicon dreams flow"));
+        assert_eq!(unsent("Silicon dreams flow", "Silicon dreams flow\n"), None);
+        // The agent appended to the model output
+        assert_eq!(unsent("Silicon dreams", "Silicon dreams flow").as_deref(), Some(" flow"));
+        // The agent replaced it
+        assert_eq!(unsent("Silicon dreams", "Done.").as_deref(), Some("\n\nDone."));
+    }
+}
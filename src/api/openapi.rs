@@ -0,0 +1,133 @@
+use utoipa::OpenApi;
+use utoipa_swagger_ui::SwaggerUi;
+use super::{admin, audit, auth, dead_letters, history, review, models, projects, routes, search, tags, hooks, digest, workflows};
+use crate::agents::concurrency::ConcurrencyStats;
+use crate::agents::middleware::MessageStats;
+use crate::ai::ProviderHealth;
+use crate::events::ErrorRecord;
+use crate::types::{
+    Attachment, AgentInfo, Capability, CostHint, Message, MessageMetadata, TaskPriority, TaskStatus, Tool,
+    api_keys::{ApiKey, Scope},
+    projects::{ProjectDefinition, ProjectUpdate},
+    reporting::{ProjectStats, QueueDepth, TagStats, WorkerStatus},
+    digest::Digest,
+    scheduling::QueuePosition,
+    todo::TaskFailure,
+    dead_letter::DeadLetterEdit,
+    review::Correction,
+    edits::TaskEdit,
+    history::{ChangeKind, FieldChange, TaskChange},
+};
+
+/// OpenAPI 3 description of the agent, task, project and key management routes
+#[derive(OpenApi)]
+#[openapi(
+    info(title = "Swarmonomicon API", description = "Agents, their task queues and the projects tasks belong to"),
+    paths(
+        routes::list_agents,
+        routes::get_agent,
+        routes::get_state_machine_dot,
+        routes::get_agent_queue,
+        routes::get_agent_metrics,
+        routes::process_message,
+        routes::send_message,
+        routes::call_agent_tool,
+        routes::get_tasks,
+        routes::get_task,
+        routes::get_task_queue,
+        routes::get_task_position,
+        routes::cancel_task,
+        routes::edit_task,
+        routes::reassign_task,
+        routes::add_task,
+        routes::get_ingest_metrics,
+        search::search_tasks,
+        history::task_history,
+        tags::tag_stats,
+        digest::get_digest,
+        hooks::receive_hook,
+        workflows::list_workflows,
+        workflows::run_workflow,
+        workflows::list_workflow_runs,
+        workflows::get_workflow_run,
+        dead_letters::list_dead_letters,
+        dead_letters::get_dead_letter,
+        dead_letters::edit_dead_letter,
+        dead_letters::requeue_dead_letter,
+        review::review_queue,
+        review::approve,
+        review::correct,
+        projects::list_projects,
+        projects::get_project,
+        projects::add_project,
+        projects::update_project,
+        projects::archive_project,
+        projects::project_stats,
+        auth::list_keys,
+        auth::create_key,
+        auth::revoke_key,
+        audit::query_audit_log,
+        admin::overview,
+    ),
+    components(schemas(
+        AgentInfo, Capability, CostHint, ConcurrencyStats, MessageStats, Tool, Message, MessageMetadata, Attachment, routes::MessageRequest, routes::ToolCallRequest, routes::ToolCallResponse,
+        models::TaskResponse, TaskPriority, TaskStatus, QueuePosition, crate::ingest::StageMetrics, crate::ingest::OverflowPolicy,
+        search::SearchResultResponse, hooks::HookResponse, workflows::WorkflowSummary, workflows::RunWorkflowRequest, crate::workflow::WorkflowRun, crate::workflow::RunStatus, crate::workflow::StepRecord, dead_letters::DeadLetterResponse, TaskFailure, DeadLetterEdit, Correction, routes::AddTaskRequest, TaskEdit, routes::ReassignRequest, TaskChange, FieldChange, ChangeKind,
+        ProjectDefinition, ProjectUpdate, ProjectStats, TagStats, Digest, projects::AddProjectRequest,
+        ApiKey, Scope, auth::CreateKeyRequest, auth::CreatedKey, crate::audit::AuditEntry,
+        admin::Overview, admin::AgentOverview, admin::ServerStatus, admin::StorageLatency, QueueDepth, WorkerStatus, ProviderHealth, ErrorRecord,
+    )),
+    tags(
+        (name = "agents", description = "Registered agents and messaging"),
+        (name = "tasks", description = "Per-agent task queues"),
+        (name = "hooks", description = "Payloads from CI and monitoring turned into tasks or messages by HOOKS_CONFIG rules"),
+        (name = "workflows", description = "Multi-step pipelines across agents and tools from WORKFLOWS_CONFIG, and their runs"),
+        (name = "projects", description = "Project definitions and statistics"),
+        (name = "keys", description = "API key management; requires the admin scope"),
+        (name = "audit", description = "Append-only record of agent actions and API changes; requires the admin scope"),
+        (name = "admin", description = "Swarm introspection for dashboards; requires the admin scope"),
+    )
+)]
+pub struct ApiDoc;
+
+/// The spec at `/openapi.json` and Swagger UI at `/swagger-ui`, bundled into the binary
+pub fn docs() -> SwaggerUi {
+    SwaggerUi::new("/swagger-ui").url("/openapi.json", ApiDoc::openapi())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use axum::{Router, body::Body, http::{Request, StatusCode}};
+    use tower::ServiceExt;
+
+    #[test]
+    fn test_spec_covers_task_and_agent_routes() {
+        let spec = serde_json::to_value(ApiDoc::openapi()).unwrap();
+        assert!(spec["openapi"].as_str().unwrap().starts_with("3."));
+        let tasks = &spec["paths"]["/api/agents/{name}/tasks"];
+        assert!(tasks.get("get").is_some() && tasks.get("post").is_some());
+        assert_eq!(
+            tasks["get"]["responses"]["200"]["content"]["application/json"]["schema"]["items"]["$ref"],
+            "#/components/schemas/TaskResponse"
+        );
+        let status = &spec["components"]["schemas"]["TaskStatus"]["enum"];
+        assert!(status.as_array().unwrap().iter().any(|s| s == "completed"), "{}", status);
+    }
+
+    #[tokio::test]
+    async fn test_spec_and_swagger_ui_are_served() -> anyhow::Result<()> {
+        let app: Router<Arc<()>> = Router::new().merge(docs());
+        let app = app.with_state(Arc::new(()));
+
+        let response = app.clone().oneshot(Request::get("/openapi.json").body(Body::empty())?).await?;
+        assert_eq!(response.status(), StatusCode::OK);
+        let spec: serde_json::Value = serde_json::from_slice(&axum::body::to_bytes(response.into_body(), usize::MAX).await?)?;
+        assert_eq!(spec["info"]["title"], "Swarmonomicon API");
+
+        let ui = app.oneshot(Request::get("/swagger-ui/").body(Body::empty())?).await?;
+        assert_eq!(ui.status(), StatusCode::OK);
+        Ok(())
+    }
+}
@@ -0,0 +1,155 @@
+use std::sync::Arc;
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    Json,
+};
+use serde::Deserialize;
+use utoipa::{IntoParams, ToSchema};
+use crate::api::AppState;
+use super::tenants::Tenant;
+use crate::types::projects::{ProjectDefinition, ProjectRegistry, ProjectUpdate};
+use crate::types::reporting::{self, ProjectStats};
+
+#[derive(Debug, Deserialize, IntoParams)]
+pub struct ListProjectsQuery {
+    #[serde(default)]
+    pub include_archived: bool,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct AddProjectRequest {
+    pub name: String,
+    pub description: String,
+    pub path: Option<String>,
+    pub parent: Option<String>,
+}
+
+fn registry(state: &AppState, tenant: &Tenant) -> Result<ProjectRegistry, StatusCode> {
+    state.projects.as_ref().map(|projects| tenant.projects(projects)).ok_or(StatusCode::SERVICE_UNAVAILABLE)
+}
+
+#[utoipa::path(
+    get, path = "/api/projects", tag = "projects",
+    params(ListProjectsQuery),
+    responses((status = 200, description = "Known projects", body = [ProjectDefinition]))
+)]
+pub async fn list_projects(
+    State(state): State<Arc<AppState>>,
+    tenant: Tenant,
+    Query(query): Query<ListProjectsQuery>,
+) -> Result<Json<Vec<ProjectDefinition>>, StatusCode> {
+    let projects = registry(&state, &tenant)?.list_projects(query.include_archived).await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    Ok(Json(projects))
+}
+
+#[utoipa::path(
+    get, path = "/api/projects/{name}", tag = "projects",
+    params(("name" = String, Path, description = "Project name")),
+    responses(
+        (status = 200, description = "The project", body = ProjectDefinition),
+        (status = 404, description = "No such project"),
+    )
+)]
+pub async fn get_project(
+    State(state): State<Arc<AppState>>,
+    tenant: Tenant,
+    Path(name): Path<String>,
+) -> Result<Json<ProjectDefinition>, StatusCode> {
+    let project = registry(&state, &tenant)?.get_project(&name).await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+    Ok(Json(project))
+}
+
+#[utoipa::path(
+    post, path = "/api/projects", tag = "projects",
+    request_body = AddProjectRequest,
+    responses(
+        (status = 201, description = "The created project", body = ProjectDefinition),
+        (status = 409, description = "A project with this name exists"),
+    )
+)]
+pub async fn add_project(
+    State(state): State<Arc<AppState>>,
+    tenant: Tenant,
+    Json(request): Json<AddProjectRequest>,
+) -> Result<(StatusCode, Json<ProjectDefinition>), StatusCode> {
+    let registry = registry(&state, &tenant)?;
+    if request.name.trim().is_empty() {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    if registry.get_project(&request.name).await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?.is_some() {
+        return Err(StatusCode::CONFLICT);
+    }
+
+    let mut project = ProjectDefinition::new(request.name.trim(), &request.description);
+    project.path = request.path;
+    project.parent = request.parent;
+    let project = registry.add_project(project).await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    Ok((StatusCode::CREATED, Json(project)))
+}
+
+#[utoipa::path(
+    put, path = "/api/projects/{name}", tag = "projects",
+    params(("name" = String, Path, description = "Project name")),
+    request_body = ProjectUpdate,
+    responses(
+        (status = 200, description = "The updated project", body = ProjectDefinition),
+        (status = 404, description = "No such project"),
+    )
+)]
+pub async fn update_project(
+    State(state): State<Arc<AppState>>,
+    tenant: Tenant,
+    Path(name): Path<String>,
+    Json(update): Json<ProjectUpdate>,
+) -> Result<Json<ProjectDefinition>, StatusCode> {
+    let registry = registry(&state, &tenant)?;
+    if registry.get_project(&name).await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?.is_none() {
+        return Err(StatusCode::NOT_FOUND);
+    }
+    let project = registry.update_project(&name, update).await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    Ok(Json(project))
+}
+
+#[utoipa::path(
+    post, path = "/api/projects/{name}/archive", tag = "projects",
+    params(("name" = String, Path, description = "Project name")),
+    responses(
+        (status = 204, description = "Archived"),
+        (status = 404, description = "No such project"),
+    )
+)]
+pub async fn archive_project(
+    State(state): State<Arc<AppState>>,
+    tenant: Tenant,
+    Path(name): Path<String>,
+) -> Result<StatusCode, StatusCode> {
+    let registry = registry(&state, &tenant)?;
+    if registry.get_project(&name).await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?.is_none() {
+        return Err(StatusCode::NOT_FOUND);
+    }
+    registry.archive_project(&name).await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[utoipa::path(
+    get, path = "/api/projects/{name}/stats", tag = "projects",
+    params(("name" = String, Path, description = "Project name")),
+    responses((status = 200, description = "Task counts and completion metrics", body = ProjectStats))
+)]
+pub async fn project_stats(
+    State(state): State<Arc<AppState>>,
+    tenant: Tenant,
+    Path(name): Path<String>,
+) -> Result<Json<ProjectStats>, StatusCode> {
+    let todos = state.todos.as_ref().map(|todos| tenant.todos(todos)).ok_or(StatusCode::SERVICE_UNAVAILABLE)?;
+    let stats = todos.project_stats(Some(&name)).await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    Ok(Json(reporting::stats_for(stats, &name)))
+}
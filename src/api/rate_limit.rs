@@ -0,0 +1,301 @@
+use std::collections::HashMap;
+use std::env;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use axum::{
+    extract::{ConnectInfo, Request, State},
+    http::{HeaderValue, Method, StatusCode, header},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use tokio::time::Instant;
+use crate::api::AppState;
+use crate::types::api_keys::ApiKey;
+
+/// Idle buckets are dropped once this many clients are tracked
+const PRUNE_THRESHOLD: usize = 10_000;
+
+/// Requests per minute allowed to each client. Zero disables a limit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ClientLimits {
+    pub requests_per_minute: u32,
+    /// Tighter limit for endpoints that call an AI provider
+    pub ai_requests_per_minute: u32,
+    /// Requests failing authentication allowed per address, so keys can't be guessed
+    pub failed_auth_per_minute: u32,
+}
+
+impl Default for ClientLimits {
+    fn default() -> Self {
+        Self {
+            requests_per_minute: 120,
+            ai_requests_per_minute: 20,
+            failed_auth_per_minute: 10,
+        }
+    }
+}
+
+impl ClientLimits {
+    /// Read `API_RATE_LIMIT_PER_MINUTE`, `API_AI_RATE_LIMIT_PER_MINUTE` and
+    /// `API_FAILED_AUTH_PER_MINUTE`
+    pub fn from_env() -> Self {
+        fn var(name: &str) -> Option<u32> {
+            env::var(name).ok().and_then(|v| v.parse().ok())
+        }
+        let defaults = Self::default();
+        Self {
+            requests_per_minute: var("API_RATE_LIMIT_PER_MINUTE").unwrap_or(defaults.requests_per_minute),
+            ai_requests_per_minute: var("API_AI_RATE_LIMIT_PER_MINUTE").unwrap_or(defaults.ai_requests_per_minute),
+            failed_auth_per_minute: var("API_FAILED_AUTH_PER_MINUTE").unwrap_or(defaults.failed_auth_per_minute),
+        }
+    }
+
+    fn per_minute(&self, class: Class) -> u32 {
+        match class {
+            Class::Requests => self.requests_per_minute,
+            Class::Ai => self.ai_requests_per_minute,
+            Class::FailedAuth => self.failed_auth_per_minute,
+        }
+    }
+}
+
+/// What a bucket counts
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Class {
+    Requests,
+    Ai,
+    FailedAuth,
+}
+
+#[derive(Debug)]
+struct Bucket {
+    tokens: f64,
+    refilled_at: Instant,
+}
+
+/// A token bucket per client and endpoint class, refilling continuously so a client
+/// can spend a full minute's allowance in a burst but not more.
+pub struct ClientRateLimiter {
+    limits: ClientLimits,
+    buckets: Mutex<HashMap<(String, Class), Bucket>>,
+}
+
+impl ClientRateLimiter {
+    pub fn new(limits: ClientLimits) -> Self {
+        Self { limits, buckets: Mutex::new(HashMap::new()) }
+    }
+
+    pub fn limits(&self) -> ClientLimits {
+        self.limits
+    }
+
+    /// Take a token for `client`, or return how long until one is available
+    pub fn check(&self, client: &str, ai: bool) -> Result<(), Duration> {
+        self.take(client, if ai { Class::Ai } else { Class::Requests }, true)
+    }
+
+    /// Whether `client` may still fail authentication, or how long until it may
+    pub fn check_failed_auth(&self, client: &str) -> Result<(), Duration> {
+        self.take(client, Class::FailedAuth, false)
+    }
+
+    /// Count a failed authentication by `client`
+    pub fn record_failed_auth(&self, client: &str) {
+        let _ = self.take(client, Class::FailedAuth, true);
+    }
+
+    /// Refill `client`'s bucket for `class` and see whether it holds a token, taking it
+    /// when `spend` is set
+    fn take(&self, client: &str, class: Class, spend: bool) -> Result<(), Duration> {
+        let per_minute = self.limits.per_minute(class);
+        if per_minute == 0 {
+            return Ok(());
+        }
+        let capacity = per_minute as f64;
+        let per_second = capacity / 60.0;
+        let now = Instant::now();
+
+        let mut buckets = self.buckets.lock().unwrap();
+        if buckets.len() >= PRUNE_THRESHOLD {
+            // A bucket idle for a minute is full again, the same as a new one
+            buckets.retain(|_, bucket| now.duration_since(bucket.refilled_at) < Duration::from_secs(60));
+        }
+        let bucket = buckets.entry((client.to_string(), class))
+            .or_insert(Bucket { tokens: capacity, refilled_at: now });
+        let elapsed = now.duration_since(bucket.refilled_at).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * per_second).min(capacity);
+        bucket.refilled_at = now;
+        if bucket.tokens >= 1.0 {
+            if spend {
+                bucket.tokens -= 1.0;
+            }
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64((1.0 - bucket.tokens) / per_second))
+        }
+    }
+}
+
+/// Whether a route calls an AI provider: agent messages, chat completions and task
+/// creation, which enhances descriptions
+pub fn is_ai_backed(method: &Method, path: &str) -> bool {
+    if method != Method::POST {
+        return false;
+    }
+    let segments: Vec<&str> = path.trim_matches('/').split('/').collect();
+    matches!(
+        segments.as_slice(),
+        ["api", "agents", _, "message" | "send" | "tasks"] | ["agents", _, "message"] | ["v1", "chat", "completions"]
+    )
+}
+
+/// The API key when authenticated, otherwise the peer address
+fn client_id(request: &Request) -> String {
+    match request.extensions().get::<ApiKey>() {
+        Some(key) => format!("key:{}", key.id),
+        None => peer_id(request),
+    }
+}
+
+fn peer_id(request: &Request) -> String {
+    match request.extensions().get::<ConnectInfo<SocketAddr>>() {
+        Some(ConnectInfo(addr)) => format!("ip:{}", addr.ip()),
+        None => "unknown".to_string(),
+    }
+}
+
+fn too_many_requests(retry_after: Duration) -> Response {
+    let seconds = retry_after.as_secs_f64().ceil().max(1.0) as u64;
+    let mut response = (StatusCode::TOO_MANY_REQUESTS, "Rate limit exceeded").into_response();
+    response.headers_mut().insert(header::RETRY_AFTER, HeaderValue::from(seconds));
+    response
+}
+
+/// Reject clients over their limit with 429 and a `Retry-After` header. Runs after
+/// authentication so keys are limited individually. Does nothing when unconfigured.
+pub async fn limit_requests(State(state): State<Arc<AppState>>, request: Request, next: Next) -> Response {
+    let Some(limiter) = &state.rate_limiter else {
+        return next.run(request).await;
+    };
+    let ai = is_ai_backed(request.method(), request.uri().path());
+    let client = client_id(&request);
+    if let Err(retry_after) = limiter.check(&client, ai) {
+        tracing::debug!("Rate limited {} on {}", client, request.uri().path());
+        return too_many_requests(retry_after);
+    }
+    next.run(request).await
+}
+
+/// Count requests that fail authentication against their address, refusing the address
+/// with 429 once it is over `failed_auth_per_minute`. Runs before authentication, which
+/// `limit_requests` can't since it limits keys individually. Does nothing when unconfigured.
+pub async fn limit_failed_auth(State(state): State<Arc<AppState>>, request: Request, next: Next) -> Response {
+    let Some(limiter) = state.rate_limiter.clone() else {
+        return next.run(request).await;
+    };
+    let client = peer_id(&request);
+    if let Err(retry_after) = limiter.check_failed_auth(&client) {
+        tracing::debug!("Rate limited {} after failed authentication", client);
+        return too_many_requests(retry_after);
+    }
+    let response = next.run(request).await;
+    if response.status() == StatusCode::UNAUTHORIZED {
+        limiter.record_failed_auth(&client);
+    }
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{Router, body::Body, middleware, routing::{get, post}};
+    use tokio::sync::RwLock;
+    use tower::ServiceExt;
+    use crate::agents::{AgentRegistry, TransferService};
+
+    #[tokio::test(start_paused = true)]
+    async fn test_buckets_refill_per_client() {
+        let limiter = ClientRateLimiter::new(ClientLimits { requests_per_minute: 2, ai_requests_per_minute: 1, failed_auth_per_minute: 0 });
+        assert!(limiter.check("a", false).is_ok());
+        assert!(limiter.check("a", false).is_ok());
+        assert_eq!(limiter.check("a", false), Err(Duration::from_secs(30)));
+        assert!(limiter.check("b", false).is_ok(), "clients have separate buckets");
+        assert!(limiter.check("a", true).is_ok(), "AI endpoints have their own bucket");
+        assert!(limiter.check("a", true).is_err());
+
+        tokio::time::advance(Duration::from_secs(30)).await;
+        assert!(limiter.check("a", false).is_ok());
+        assert!(limiter.check("a", false).is_err());
+
+        let unlimited = ClientRateLimiter::new(ClientLimits { requests_per_minute: 0, ai_requests_per_minute: 0, failed_auth_per_minute: 0 });
+        assert!((0..1000).all(|_| unlimited.check("a", true).is_ok()));
+    }
+
+    #[tokio::test]
+    async fn test_middleware_returns_429_with_retry_after() -> anyhow::Result<()> {
+        assert!(is_ai_backed(&Method::POST, "/v1/chat/completions"));
+        assert!(is_ai_backed(&Method::POST, "/api/agents/git/tasks"));
+        assert!(!is_ai_backed(&Method::GET, "/api/agents/git/tasks"));
+
+        let registry = Arc::new(RwLock::new(AgentRegistry::new()));
+        let state = AppState::new(Arc::new(RwLock::new(TransferService::new(registry))))
+            .with_rate_limiter(ClientRateLimiter::new(ClientLimits { requests_per_minute: 5, ai_requests_per_minute: 1, failed_auth_per_minute: 0 }));
+        let state = Arc::new(state);
+        let app = Router::new()
+            .route("/v1/chat/completions", post(|| async { "reply" }))
+            .route("/tasks/events", get(|| async { "events" }))
+            .layer(middleware::from_fn_with_state(state.clone(), limit_requests))
+            .with_state(state);
+        let send = |method: &str, uri: &str, ip: [u8; 4]| {
+            let request = Request::builder().method(method).uri(uri)
+                .extension(ConnectInfo(SocketAddr::from((ip, 4000))))
+                .body(Body::empty())
+                .unwrap();
+            app.clone().oneshot(request)
+        };
+
+        assert_eq!(send("POST", "/v1/chat/completions", [10, 0, 0, 1]).await?.status(), StatusCode::OK);
+        let limited = send("POST", "/v1/chat/completions", [10, 0, 0, 1]).await?;
+        assert_eq!(limited.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(limited.headers()[header::RETRY_AFTER], "60");
+
+        assert_eq!(send("GET", "/tasks/events", [10, 0, 0, 1]).await?.status(), StatusCode::OK);
+        assert_eq!(send("POST", "/v1/chat/completions", [10, 0, 0, 2]).await?.status(), StatusCode::OK);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_failed_authentication_is_limited_by_address() -> anyhow::Result<()> {
+        let registry = Arc::new(RwLock::new(AgentRegistry::new()));
+        let limits = ClientLimits { requests_per_minute: 0, ai_requests_per_minute: 0, failed_auth_per_minute: 2 };
+        let state = Arc::new(AppState::new(Arc::new(RwLock::new(TransferService::new(registry))))
+            .with_rate_limiter(ClientRateLimiter::new(limits)));
+        // Stands in for the API key check
+        let app = Router::new()
+            .route("/api/tasks", get(|request: Request| async move {
+                match request.headers().contains_key(header::AUTHORIZATION) {
+                    true => StatusCode::OK,
+                    false => StatusCode::UNAUTHORIZED,
+                }
+            }))
+            .layer(middleware::from_fn_with_state(state.clone(), limit_failed_auth))
+            .with_state(state);
+        let send = |authorized: bool, ip: [u8; 4]| {
+            let mut request = Request::builder().uri("/api/tasks").extension(ConnectInfo(SocketAddr::from((ip, 4000))));
+            if authorized {
+                request = request.header(header::AUTHORIZATION, "Bearer key");
+            }
+            app.clone().oneshot(request.body(Body::empty()).unwrap())
+        };
+
+        assert_eq!(send(true, [10, 0, 0, 1]).await?.status(), StatusCode::OK, "successes aren't counted");
+        assert_eq!(send(false, [10, 0, 0, 1]).await?.status(), StatusCode::UNAUTHORIZED);
+        assert_eq!(send(false, [10, 0, 0, 1]).await?.status(), StatusCode::UNAUTHORIZED);
+        let limited = send(true, [10, 0, 0, 1]).await?;
+        assert_eq!(limited.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(limited.headers()[header::RETRY_AFTER], "30");
+        assert_eq!(send(false, [10, 0, 0, 2]).await?.status(), StatusCode::UNAUTHORIZED, "addresses are counted apart");
+        Ok(())
+    }
+}
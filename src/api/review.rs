@@ -0,0 +1,84 @@
+use std::sync::Arc;
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    Json,
+};
+use serde::Deserialize;
+use utoipa::IntoParams;
+use crate::api::AppState;
+use super::tenants::Tenant;
+use crate::types::{TodoList, review::{self, Correction}};
+use super::models::TaskResponse;
+
+#[derive(Debug, Deserialize, IntoParams)]
+pub struct ReviewQuery {
+    /// Confidence under which tasks need review; defaults to `REVIEW_CONFIDENCE_THRESHOLD`
+    pub threshold: Option<f32>,
+    /// Most tasks to return; defaults to 50
+    pub limit: Option<i64>,
+}
+
+fn todos(state: &AppState, tenant: &Tenant) -> Result<TodoList, StatusCode> {
+    state.todos.as_ref().map(|todos| tenant.todos(todos)).ok_or(StatusCode::SERVICE_UNAVAILABLE)
+}
+
+fn storage_error(e: mongodb::error::Error) -> StatusCode {
+    tracing::error!("Review storage error: {}", e);
+    StatusCode::INTERNAL_SERVER_ERROR
+}
+
+#[utoipa::path(
+    get, path = "/api/review", tag = "tasks",
+    params(ReviewQuery),
+    responses((status = 200, description = "Unreviewed tasks with low enhancement confidence, least confident first", body = [TaskResponse]))
+)]
+pub async fn review_queue(
+    State(state): State<Arc<AppState>>,
+    tenant: Tenant,
+    Query(query): Query<ReviewQuery>,
+) -> Result<Json<Vec<TaskResponse>>, StatusCode> {
+    let threshold = query.threshold.unwrap_or_else(review::threshold_from_env);
+    let tasks = todos(&state, &tenant)?.review_queue(threshold, query.limit.unwrap_or(50)).await.map_err(storage_error)?;
+    Ok(Json(tasks.into_iter().map(TaskResponse::from).collect()))
+}
+
+#[utoipa::path(
+    post, path = "/api/review/{id}/approve", tag = "tasks",
+    params(("id" = String, Path, description = "Task id")),
+    responses(
+        (status = 200, description = "The task, marked reviewed as enhanced", body = TaskResponse),
+        (status = 404, description = "No such task"),
+    )
+)]
+pub async fn approve(
+    State(state): State<Arc<AppState>>,
+    tenant: Tenant,
+    Path(id): Path<String>,
+) -> Result<Json<TaskResponse>, StatusCode> {
+    let task = todos(&state, &tenant)?.review(&id, Correction::default()).await.map_err(storage_error)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+    Ok(Json(task.into()))
+}
+
+/// Fix the priority or project enhancement gave a task. Changes are stored as examples
+/// for later enhancements.
+#[utoipa::path(
+    post, path = "/api/review/{id}/correct", tag = "tasks",
+    params(("id" = String, Path, description = "Task id")),
+    request_body = Correction,
+    responses(
+        (status = 200, description = "The corrected task", body = TaskResponse),
+        (status = 404, description = "No such task"),
+    )
+)]
+pub async fn correct(
+    State(state): State<Arc<AppState>>,
+    tenant: Tenant,
+    Path(id): Path<String>,
+    Json(correction): Json<Correction>,
+) -> Result<Json<TaskResponse>, StatusCode> {
+    let task = todos(&state, &tenant)?.review(&id, correction).await.map_err(storage_error)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+    Ok(Json(task.into()))
+}
@@ -1,6 +1,6 @@
 use axum::{
-    extract::{Path, State},
-    http::StatusCode,
+    extract::{Extension, Path, Query, State},
+    http::{header, HeaderMap, StatusCode},
     response::{IntoResponse, Response},
     Json,
 };
@@ -13,15 +13,23 @@ use futures::executor::block_on;
 use async_trait::async_trait;
 use anyhow::anyhow;
 use mongodb::{Client, Collection};
+use utoipa::{IntoParams, ToSchema};
 
 use crate::{
     api::AppState,
-    types::{Message, AgentConfig, Agent, AgentInfo, TodoTask, TaskPriority, TaskStatus, TodoProcessor, TodoList, StateMachine, AgentStateManager, Tool},
-    agents::AgentRegistry,
+    error::SwarmError,
+    types::{scheduling::QueuePosition, Message, Attachment, AgentConfig, Agent, AgentCapabilities, AgentInfo, TodoTask, TaskPriority, TaskStatus, TodoProcessor, TodoList, StateMachine, AgentStateManager, Tool},
+    agents::{AgentRegistry, AgentWrapper, concurrency::ConcurrencyStats, middleware::MessageStats},
     ai::{AiProvider, DefaultAiClient},
+    ingest::{StageMetrics, TaskSubmission},
 };
+use crate::types::todo::NewTask;
+use crate::types::idempotency::{self, Insertion};
+use crate::types::edits::TaskEdit;
+use crate::types::api_keys::ApiKey;
 
 use super::models::TaskResponse;
+use super::tenants::Tenant;
 
 pub async fn index() -> Response {
     "Welcome to the Swarmonomicon API".into_response()
@@ -33,89 +41,281 @@ pub struct AgentResponse {
     description: String,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, ToSchema)]
 pub struct MessageRequest {
     content: String,
+    /// Inline `image` or `json` attachments; `file` would name a path on this server
+    #[serde(default)]
+    attachments: Vec<Attachment>,
 }
 
+impl MessageRequest {
+    fn into_message(self) -> Result<Message, StatusCode> {
+        if !self.attachments.iter().all(Attachment::is_inline) {
+            return Err(StatusCode::BAD_REQUEST);
+        }
+        Ok(Message::new(self.content).with_attachments(self.attachments))
+    }
+}
+
+#[derive(Debug, Default, Deserialize, IntoParams)]
+pub struct AgentQuery {
+    /// Only agents advertising this command
+    pub command: Option<String>,
+    /// Only agents accepting this input format, e.g. `json`
+    pub input_format: Option<String>,
+}
+
+#[utoipa::path(
+    get, path = "/api/agents", tag = "agents",
+    params(AgentQuery),
+    responses((status = 200, description = "Registered agents and their capabilities", body = [AgentInfo]))
+)]
 pub async fn list_agents(
     State(state): State<Arc<AppState>>,
+    tenant: Tenant,
+    Query(query): Query<AgentQuery>,
 ) -> Result<Json<Vec<AgentInfo>>, StatusCode> {
     let registry = state.agents.read().await;
     let mut agents = Vec::new();
 
-    for (name, agent) in registry.agents.iter() {
-        match agent.get_config().await {
-            Ok(config) => agents.push(AgentInfo {
-                name: name.clone(),
-                description: config.public_description,
-                instructions: config.instructions.clone(),
-                tools: config.tools.clone(),
-                downstream_agents: config.downstream_agents.clone(),
-            }),
-            Err(_) => return Err(StatusCode::INTERNAL_SERVER_ERROR),
+    for (name, agent) in registry.agents.iter().filter(|(name, _)| tenant.sees_agent(&state, name)) {
+        let info = agent_info(name, agent).await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        let entry = AgentCapabilities { agent: name.clone(), capabilities: info.capabilities.clone() };
+        if query.command.as_deref().is_some_and(|command| !entry.supports_command(command))
+            || query.input_format.as_deref().is_some_and(|format| !entry.accepts_format(format))
+        {
+            continue;
         }
+        agents.push(info);
     }
 
     Ok(Json(agents))
 }
 
+async fn agent_info(name: &str, agent: &AgentWrapper) -> anyhow::Result<AgentInfo> {
+    let config = agent.get_config().await?;
+    Ok(AgentInfo {
+        name: name.to_string(),
+        description: config.public_description,
+        instructions: config.instructions,
+        tools: config.tools,
+        downstream_agents: config.downstream_agents,
+        capabilities: agent.capabilities().await?,
+    })
+}
+
+#[utoipa::path(
+    get, path = "/api/agents/{name}", tag = "agents",
+    params(("name" = String, Path, description = "Agent name")),
+    responses(
+        (status = 200, description = "The agent", body = AgentInfo),
+        (status = 404, description = "No such agent"),
+    )
+)]
 pub async fn get_agent(
     State(state): State<Arc<AppState>>,
+    tenant: Tenant,
     Path(name): Path<String>,
 ) -> Result<Json<AgentInfo>, StatusCode> {
     let registry = state.agents.read().await;
 
-    if let Some(agent) = registry.get(&name) {
-        match agent.get_config().await {
-            Ok(config) => Ok(Json(AgentInfo {
-                name: config.name,
-                description: config.public_description,
-                instructions: config.instructions.clone(),
-                tools: config.tools.clone(),
-                downstream_agents: config.downstream_agents.clone(),
-            })),
-            Err(_) => Err(StatusCode::INTERNAL_SERVER_ERROR),
-        }
+    if let Some(agent) = registry.get(&name).filter(|_| tenant.sees_agent(&state, &name)) {
+        agent_info(&name, agent).await.map(Json).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)
     } else {
         Err(StatusCode::NOT_FOUND)
     }
 }
 
+/// Graphviz rendering of the agent's state machine
+#[utoipa::path(
+    get, path = "/api/agents/{name}/state-machine.dot", tag = "agents",
+    params(("name" = String, Path, description = "Agent name")),
+    responses(
+        (status = 200, description = "DOT source", body = String, content_type = "text/vnd.graphviz"),
+        (status = 404, description = "No such agent, or it has no state machine"),
+    )
+)]
+pub async fn get_state_machine_dot(
+    State(state): State<Arc<AppState>>,
+    tenant: Tenant,
+    Path(name): Path<String>,
+) -> Result<impl IntoResponse, StatusCode> {
+    let registry = state.agents.read().await;
+    let agent = registry.get(&name).filter(|_| tenant.sees_agent(&state, &name)).ok_or(StatusCode::NOT_FOUND)?;
+    let machine = agent.get_state_machine().await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+    Ok(([(header::CONTENT_TYPE, "text/vnd.graphviz")], machine.to_dot(&name)))
+}
+
+/// How many messages the agent is handling and how many are waiting
+#[utoipa::path(
+    get, path = "/api/agents/{name}/queue", tag = "agents",
+    params(("name" = String, Path, description = "Agent name")),
+    responses(
+        (status = 200, description = "Queue depth and active work", body = ConcurrencyStats),
+        (status = 404, description = "No such agent"),
+    )
+)]
+pub async fn get_agent_queue(
+    State(state): State<Arc<AppState>>,
+    tenant: Tenant,
+    Path(name): Path<String>,
+) -> Result<Json<ConcurrencyStats>, StatusCode> {
+    let registry = state.agents.read().await;
+    let agent = registry.get(&name).filter(|_| tenant.sees_agent(&state, &name)).ok_or(StatusCode::NOT_FOUND)?;
+    Ok(Json(agent.concurrency_stats().await))
+}
+
+/// Messages the agent has handled, failures and reply latency
+#[utoipa::path(
+    get, path = "/api/agents/{name}/metrics", tag = "agents",
+    params(("name" = String, Path, description = "Agent name")),
+    responses(
+        (status = 200, description = "Message counts and latency since the agent started", body = MessageStats),
+        (status = 404, description = "No such agent, or its middleware doesn't include metrics"),
+    )
+)]
+pub async fn get_agent_metrics(
+    State(state): State<Arc<AppState>>,
+    tenant: Tenant,
+    Path(name): Path<String>,
+) -> Result<Json<MessageStats>, StatusCode> {
+    let registry = state.agents.read().await;
+    let agent = registry.get(&name).filter(|_| tenant.sees_agent(&state, &name)).ok_or(StatusCode::NOT_FOUND)?;
+    agent.message_stats().await.map(Json).ok_or(StatusCode::NOT_FOUND)
+}
+
+#[utoipa::path(
+    post, path = "/api/agents/{name}/message", tag = "agents",
+    params(("name" = String, Path, description = "Agent name")),
+    request_body = MessageRequest,
+    responses(
+        (status = 200, description = "The agent's reply", body = Message),
+        (status = 400, description = "A file attachment was sent"),
+        (status = 404, description = "No such agent"),
+        (status = 503, description = "The AI provider or storage is unavailable"),
+    )
+)]
 pub async fn process_message(
     State(state): State<Arc<AppState>>,
+    tenant: Tenant,
     Path(agent_name): Path<String>,
     Json(request): Json<MessageRequest>,
 ) -> Result<Json<Message>, StatusCode> {
-    let registry = state.agents.read().await;
+    let message = request.into_message()?;
+    // Clone the agent out so the registry isn't locked while it works
+    let agent = state.agents.read().await.get(&agent_name).filter(|_| tenant.sees_agent(&state, &agent_name)).cloned();
 
-    if let Some(agent) = registry.get(&agent_name) {
-        match agent.process_message(Message::new(request.content)).await {
+    if let Some(agent) = agent {
+        match agent.process_message(tenant.tag(&agent_name, message)).await {
             Ok(response) => Ok(Json(response)),
-            Err(_) => Err(StatusCode::INTERNAL_SERVER_ERROR),
+            Err(e) => {
+                tracing::error!("Agent '{}' failed to process message: {}", agent_name, e);
+                Err(error_status(&e))
+            }
         }
     } else {
         Err(StatusCode::NOT_FOUND)
     }
 }
 
+#[utoipa::path(
+    post, path = "/api/agents/{name}/send", tag = "agents",
+    params(("name" = String, Path, description = "Agent name")),
+    request_body = MessageRequest,
+    responses(
+        (status = 200, description = "The agent's reply", body = Message),
+        (status = 400, description = "A file attachment was sent"),
+        (status = 404, description = "No such agent"),
+        (status = 503, description = "The AI provider or storage is unavailable"),
+    )
+)]
 pub async fn send_message(
     State(state): State<Arc<AppState>>,
+    tenant: Tenant,
     Path(agent_name): Path<String>,
     Json(request): Json<MessageRequest>,
 ) -> Result<Json<Message>, StatusCode> {
-    let registry = state.agents.read().await;
+    let message = request.into_message()?;
+    // Clone the agent out so the registry isn't locked while it works
+    let agent = state.agents.read().await.get(&agent_name).filter(|_| tenant.sees_agent(&state, &agent_name)).cloned();
 
-    if let Some(agent) = registry.get(&agent_name) {
-        match agent.process_message(Message::new(request.content)).await {
+    if let Some(agent) = agent {
+        match agent.process_message(tenant.tag(&agent_name, message)).await {
             Ok(response) => Ok(Json(response)),
-            Err(_) => Err(StatusCode::INTERNAL_SERVER_ERROR),
+            Err(e) => {
+                tracing::error!("Agent '{}' failed to process message: {}", agent_name, e);
+                Err(error_status(&e))
+            }
         }
     } else {
         Err(StatusCode::NOT_FOUND)
     }
 }
 
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct ToolCallRequest {
+    #[serde(default)]
+    pub params: HashMap<String, String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct ToolCallResponse {
+    pub output: String,
+}
+
+/// Run one of the agent's tools, as a remote agent's proxy does
+#[utoipa::path(
+    post, path = "/api/agents/{name}/tools/{tool}", tag = "agents",
+    params(
+        ("name" = String, Path, description = "Agent name"),
+        ("tool" = String, Path, description = "Tool name"),
+    ),
+    request_body = ToolCallRequest,
+    responses(
+        (status = 200, description = "The tool's output", body = ToolCallResponse),
+        (status = 400, description = "The parameters were rejected"),
+        (status = 404, description = "No such agent"),
+    )
+)]
+pub async fn call_agent_tool(
+    State(state): State<Arc<AppState>>,
+    tenant: Tenant,
+    Path((agent_name, tool_name)): Path<(String, String)>,
+    Json(request): Json<ToolCallRequest>,
+) -> Result<Json<ToolCallResponse>, StatusCode> {
+    // Clone the agent out so the registry isn't locked while the tool runs
+    let agent = state.agents.read().await.get(&agent_name)
+        .filter(|_| tenant.sees_agent(&state, &agent_name))
+        .cloned()
+        .ok_or(StatusCode::NOT_FOUND)?;
+    // The agent's own definition of the tool, when it lists one
+    let config = agent.get_config().await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let tool = config.tools.into_iter()
+        .find(|tool| tool.name == tool_name)
+        .unwrap_or_else(|| Tool::new(tool_name.clone(), String::new()));
+    match agent.call_tool(&tool, request.params).await {
+        Ok(output) => Ok(Json(ToolCallResponse { output })),
+        Err(e) => {
+            tracing::error!("Agent '{}' failed to run tool '{}': {}", agent_name, tool_name, e);
+            Err(error_status(&e))
+        }
+    }
+}
+
+/// HTTP status for a failed agent call, based on its `SwarmError` kind
+pub(crate) fn error_status(err: &anyhow::Error) -> StatusCode {
+    match SwarmError::find(err) {
+        Some(SwarmError::AgentNotFound(_)) => StatusCode::NOT_FOUND,
+        Some(SwarmError::Validation(_)) => StatusCode::BAD_REQUEST,
+        Some(SwarmError::Unsupported(_)) => StatusCode::NOT_IMPLEMENTED,
+        Some(SwarmError::AiProvider(_)) | Some(SwarmError::Storage(_)) | Some(SwarmError::Overloaded(_)) => StatusCode::SERVICE_UNAVAILABLE,
+        _ => StatusCode::INTERNAL_SERVER_ERROR,
+    }
+}
+
 pub fn default_agents() -> Vec<AgentConfig> {
     // vec![ restore default later ?
     //     AgentConfig {
@@ -139,6 +339,10 @@ pub fn default_agents() -> Vec<AgentConfig> {
         downstream_agents: vec!["haiku".to_string()],
         personality: None,
         state_machine: None,
+        script: None,
+        max_concurrency: None,
+        middleware: None,
+        remote: None,
     });
 
     #[cfg(feature = "haiku-agent")]
@@ -150,6 +354,10 @@ pub fn default_agents() -> Vec<AgentConfig> {
         downstream_agents: Vec::new(),
         personality: None,
         state_machine: None,
+        script: None,
+        max_concurrency: None,
+        middleware: None,
+        remote: None,
     });
 
     #[cfg(feature = "git-agent")]
@@ -161,6 +369,11 @@ pub fn default_agents() -> Vec<AgentConfig> {
         downstream_agents: Vec::new(),
         personality: None,
         state_machine: None,
+        script: None,
+        // Concurrent git commands in the same working tree conflict
+        max_concurrency: Some(1),
+        middleware: None,
+        remote: None,
     });
 
     #[cfg(feature = "project-init-agent")]
@@ -172,6 +385,10 @@ pub fn default_agents() -> Vec<AgentConfig> {
         downstream_agents: Vec::new(),
         personality: None,
         state_machine: None,
+        script: None,
+        max_concurrency: None,
+        middleware: None,
+        remote: None,
     });
 
     #[cfg(feature = "browser-agent")]
@@ -183,49 +400,96 @@ pub fn default_agents() -> Vec<AgentConfig> {
         downstream_agents: Vec::new(),
         personality: None,
         state_machine: None,
+        script: None,
+        // The agent drives a single browser session
+        max_concurrency: Some(1),
+        middleware: None,
+        remote: None,
     });
 
     agents
 }
 
-#[derive(Debug, Deserialize, Clone)]
+#[derive(Debug, Deserialize, Clone, ToSchema)]
 pub struct AddTaskRequest {
     pub description: String,
     pub priority: TaskPriority,
     pub source_agent: Option<String>,
     pub project: Option<String>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    /// Add the task even if an identical one was added in the last `TODO_DEDUP_WINDOW_SECS`
+    #[serde(default)]
+    pub allow_duplicate: bool,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+pub struct TaskListQuery {
+    /// Also return tasks moved to the archive
+    #[serde(default)]
+    pub include_archived: bool,
+    /// Comma-separated tags; only tasks with all of them are returned
+    pub tags: Option<String>,
 }
 
 // Get all tasks for an agent
+#[utoipa::path(
+    get, path = "/api/agents/{name}/tasks", tag = "tasks",
+    params(("name" = String, Path, description = "Agent name"), TaskListQuery),
+    responses(
+        (status = 200, description = "The agent's tasks", body = [TaskResponse]),
+        (status = 404, description = "No such agent"),
+        (status = 501, description = "The agent has no todo list"),
+    )
+)]
 pub async fn get_tasks(
     State(state): State<Arc<AppState>>,
+    tenant: Tenant,
     Path(agent_name): Path<String>,
+    Query(query): Query<TaskListQuery>,
 ) -> Result<Json<Vec<TaskResponse>>, StatusCode> {
     let registry = state.agents.read().await;
 
     let agent = registry.get(&agent_name)
+        .filter(|_| tenant.sees_agent(&state, &agent_name))
         .ok_or(StatusCode::NOT_FOUND)?;
 
     let todo_list = <dyn Agent>::get_todo_list(agent)
+        .map(|todos| tenant.todos(todos))
         .ok_or(StatusCode::NOT_IMPLEMENTED)?;
 
-    let tasks = todo_list.get_all_tasks().await
+    let tags = query.tags.as_deref().map(crate::types::tags::parse_tags).unwrap_or_default();
+    let tasks = todo_list.list_tasks(query.include_archived, &tags).await
         .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
 
     Ok(Json(tasks.into_iter().map(TaskResponse::from).collect()))
 }
 
 // Get a specific task by ID
+#[utoipa::path(
+    get, path = "/api/agents/{name}/tasks/{task_id}", tag = "tasks",
+    params(
+        ("name" = String, Path, description = "Agent name"),
+        ("task_id" = String, Path, description = "Task id"),
+    ),
+    responses(
+        (status = 200, description = "The task", body = TaskResponse),
+        (status = 404, description = "No such agent or task"),
+    )
+)]
 pub async fn get_task(
     State(state): State<Arc<AppState>>,
+    tenant: Tenant,
     Path((agent_name, task_id)): Path<(String, String)>,
 ) -> Result<Json<TaskResponse>, StatusCode> {
     let registry = state.agents.read().await;
 
     let agent = registry.get(&agent_name)
+        .filter(|_| tenant.sees_agent(&state, &agent_name))
         .ok_or(StatusCode::NOT_FOUND)?;
 
     let todo_list = <dyn Agent>::get_todo_list(agent)
+        .map(|todos| tenant.todos(todos))
         .ok_or(StatusCode::NOT_IMPLEMENTED)?;
 
     let task = todo_list.get_task(&task_id).await
@@ -235,32 +499,246 @@ pub async fn get_task(
     Ok(Json(TaskResponse::from(task)))
 }
 
+#[derive(Debug, Deserialize, IntoParams)]
+pub struct QueueQuery {
+    /// Most tasks to return; defaults to 20
+    pub limit: Option<usize>,
+}
+
+/// The agent's pending tasks in the order they will be processed
+#[utoipa::path(
+    get, path = "/api/agents/{name}/tasks/queue", tag = "tasks",
+    params(("name" = String, Path, description = "Agent name"), QueueQuery),
+    responses(
+        (status = 200, description = "Pending tasks, next first", body = [TaskResponse]),
+        (status = 404, description = "No such agent"),
+        (status = 501, description = "The agent has no todo list"),
+    )
+)]
+pub async fn get_task_queue(
+    State(state): State<Arc<AppState>>,
+    tenant: Tenant,
+    Path(agent_name): Path<String>,
+    Query(query): Query<QueueQuery>,
+) -> Result<Json<Vec<TaskResponse>>, StatusCode> {
+    let registry = state.agents.read().await;
+    let agent = registry.get(&agent_name).filter(|_| tenant.sees_agent(&state, &agent_name)).ok_or(StatusCode::NOT_FOUND)?;
+    let todo_list = <dyn Agent>::get_todo_list(agent).map(|todos| tenant.todos(todos)).ok_or(StatusCode::NOT_IMPLEMENTED)?;
+    let tasks = todo_list.peek(Some(&agent_name), query.limit.unwrap_or(20)).await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    Ok(Json(tasks.into_iter().map(TaskResponse::from).collect()))
+}
+
+/// Where a pending task stands among all pending tasks
+#[utoipa::path(
+    get, path = "/api/agents/{name}/tasks/{task_id}/position", tag = "tasks",
+    params(
+        ("name" = String, Path, description = "Agent name"),
+        ("task_id" = String, Path, description = "Task id"),
+    ),
+    responses(
+        (status = 200, description = "The task's place in the queue", body = QueuePosition),
+        (status = 404, description = "No such agent, or the task is not pending"),
+    )
+)]
+pub async fn get_task_position(
+    State(state): State<Arc<AppState>>,
+    tenant: Tenant,
+    Path((agent_name, task_id)): Path<(String, String)>,
+) -> Result<Json<QueuePosition>, StatusCode> {
+    let registry = state.agents.read().await;
+    let agent = registry.get(&agent_name).filter(|_| tenant.sees_agent(&state, &agent_name)).ok_or(StatusCode::NOT_FOUND)?;
+    let todo_list = <dyn Agent>::get_todo_list(agent).map(|todos| tenant.todos(todos)).ok_or(StatusCode::NOT_IMPLEMENTED)?;
+    let position = todo_list.position(&task_id).await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+    Ok(Json(position))
+}
+
+/// Cancel a task that hasn't finished. A running task is stopped: straight away if this
+/// server is running it, otherwise when its worker next renews its lease.
+#[utoipa::path(
+    post, path = "/api/agents/{name}/tasks/{task_id}/cancel", tag = "tasks",
+    params(
+        ("name" = String, Path, description = "Agent name"),
+        ("task_id" = String, Path, description = "Task id"),
+    ),
+    responses(
+        (status = 200, description = "The cancelled task", body = TaskResponse),
+        (status = 404, description = "No such agent or task"),
+        (status = 409, description = "The task already finished"),
+    )
+)]
+pub async fn cancel_task(
+    State(state): State<Arc<AppState>>,
+    tenant: Tenant,
+    api_key: Option<Extension<ApiKey>>,
+    Path((agent_name, task_id)): Path<(String, String)>,
+) -> Result<Json<TaskResponse>, StatusCode> {
+    let registry = state.agents.read().await;
+    let agent = registry.get(&agent_name).filter(|_| tenant.sees_agent(&state, &agent_name)).ok_or(StatusCode::NOT_FOUND)?;
+    let todo_list = <dyn Agent>::get_todo_list(agent).map(|todos| tenant.todos(todos)).ok_or(StatusCode::NOT_IMPLEMENTED)?;
+    if let Some(task) = todo_list.cancel_task(&task_id, &actor(api_key.as_ref().map(|Extension(key)| key))).await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)? {
+        return Ok(Json(TaskResponse::from(task)));
+    }
+    match todo_list.get_task(&task_id).await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)? {
+        Some(_) => Err(StatusCode::CONFLICT),
+        None => Err(StatusCode::NOT_FOUND),
+    }
+}
+
+/// Who is changing a task: the API key's name, or `api` when keys aren't required
+pub(crate) fn actor(api_key: Option<&ApiKey>) -> String {
+    api_key.map(|key| format!("key:{}", key.name)).unwrap_or_else(|| "api".to_string())
+}
+
+/// Change a task's description, priority, project or agent. The new agent must be
+/// registered, and the change is kept in the task's history.
+#[utoipa::path(
+    patch, path = "/api/agents/{name}/tasks/{task_id}", tag = "tasks",
+    params(
+        ("name" = String, Path, description = "Agent name"),
+        ("task_id" = String, Path, description = "Task id"),
+    ),
+    request_body = TaskEdit,
+    responses(
+        (status = 200, description = "The edited task", body = TaskResponse),
+        (status = 400, description = "The description is blank, or there's no such target agent"),
+        (status = 404, description = "No such agent or task"),
+    )
+)]
+pub async fn edit_task(
+    State(state): State<Arc<AppState>>,
+    tenant: Tenant,
+    api_key: Option<Extension<ApiKey>>,
+    Path((agent_name, task_id)): Path<(String, String)>,
+    Json(edit): Json<TaskEdit>,
+) -> Result<Json<TaskResponse>, StatusCode> {
+    let registry = state.agents.read().await;
+    let agent = registry.get(&agent_name).filter(|_| tenant.sees_agent(&state, &agent_name)).ok_or(StatusCode::NOT_FOUND)?;
+    let todo_list = <dyn Agent>::get_todo_list(agent).map(|todos| tenant.todos(todos)).ok_or(StatusCode::NOT_IMPLEMENTED)?;
+    edit.validate(|name| registry.exists(name) && tenant.sees_agent(&state, name))
+        .map_err(|_| StatusCode::BAD_REQUEST)?;
+    let actor = actor(api_key.as_ref().map(|Extension(key)| key));
+    let task = todo_list.edit_task(&task_id, edit, &actor).await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+    Ok(Json(TaskResponse::from(task)))
+}
+
+#[derive(Debug, Clone, Deserialize, ToSchema)]
+pub struct ReassignRequest {
+    pub target_agent: String,
+}
+
+/// Move a task to another registered agent's queue
+#[utoipa::path(
+    post, path = "/api/agents/{name}/tasks/{task_id}/reassign", tag = "tasks",
+    params(
+        ("name" = String, Path, description = "Agent name"),
+        ("task_id" = String, Path, description = "Task id"),
+    ),
+    request_body = ReassignRequest,
+    responses(
+        (status = 200, description = "The reassigned task", body = TaskResponse),
+        (status = 400, description = "No such target agent"),
+        (status = 404, description = "No such agent or task"),
+    )
+)]
+pub async fn reassign_task(
+    state: State<Arc<AppState>>,
+    tenant: Tenant,
+    api_key: Option<Extension<ApiKey>>,
+    path: Path<(String, String)>,
+    Json(request): Json<ReassignRequest>,
+) -> Result<Json<TaskResponse>, StatusCode> {
+    edit_task(state, tenant, api_key, path, Json(TaskEdit::reassign(request.target_agent))).await
+}
+
+/// Store `task` in `todos` through the ingest pipeline, or straight away without one. A
+/// task whose idempotency key was seen before gives back the original.
+pub(crate) async fn ingest_task(state: &AppState, todos: TodoList, task: NewTask) -> anyhow::Result<Insertion> {
+    if let Some(key) = &task.idempotency_key {
+        // Skip the queues for retries
+        if let Some(existing) = todos.find_by_idempotency_key(key).await.map_err(SwarmError::from)? {
+            return Ok(Insertion::Existing(existing));
+        }
+    }
+    match &state.ingest {
+        Some(ingest) => ingest.process(TaskSubmission { todos, task }).await,
+        None => {
+            let task = todos.prepare_task(task, None).await;
+            Ok(todos.add_task_once(task).await.map_err(SwarmError::from)?)
+        }
+    }
+}
+
 // Add a task to an agent's todo list
+#[utoipa::path(
+    post, path = "/api/agents/{name}/tasks", tag = "tasks",
+    params(("name" = String, Path, description = "Agent name")),
+    request_body = AddTaskRequest,
+    params(("Idempotency-Key" = Option<String>, Header, description = "Return the task added earlier with this key instead of adding another")),
+    responses(
+        (status = 200, description = "The created task, or the one added earlier with the same idempotency key", body = TaskResponse),
+        (status = 400, description = "The idempotency key is too long"),
+        (status = 404, description = "No such agent"),
+        (status = 503, description = "The ingest queues are full, or task storage is unavailable"),
+    )
+)]
 pub async fn add_task(
     State(state): State<Arc<AppState>>,
+    tenant: Tenant,
     Path(agent_name): Path<String>,
+    headers: HeaderMap,
     Json(request): Json<AddTaskRequest>,
 ) -> Result<Json<TaskResponse>, StatusCode> {
+    let idempotency_key = match headers.get(idempotency::HEADER) {
+        Some(key) => idempotency::normalize_key(key.to_str().map_err(|_| StatusCode::BAD_REQUEST)?)
+            .map_err(|_| StatusCode::BAD_REQUEST)?,
+        None => None,
+    };
     let registry = state.agents.read().await;
 
     let agent = registry.get(&agent_name)
+        .filter(|_| tenant.sees_agent(&state, &agent_name))
         .ok_or(StatusCode::NOT_FOUND)?;
 
     let todo_list = <dyn Agent>::get_todo_list(agent)
+        .map(|todos| tenant.todos(todos))
         .ok_or(StatusCode::NOT_IMPLEMENTED)?;
 
-    // Create task with optional AI enhancement
-    let task = todo_list.create_task_with_enhancement(
-        request.description,
-        request.priority,
-        request.source_agent,
-        agent_name,
-        request.project,
-        None, // No AI enhancement at API level - this should be handled by the agent's todo processor
-    ).await
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let new = NewTask {
+        description: request.description,
+        priority: request.priority,
+        source_agent: request.source_agent,
+        target_agent: agent_name,
+        project: request.project,
+        tags: request.tags,
+        idempotency_key,
+        allow_duplicate: request.allow_duplicate,
+    };
+    // Release the registry while the task waits in the queues
+    drop(registry);
+    let task = ingest_task(&state, todo_list, new).await.map_err(|e| {
+        tracing::warn!("Failed to add task: {}", e);
+        error_status(&e)
+    })?;
+
+    Ok(Json(TaskResponse::from(task.into_task())))
+}
 
-    Ok(Json(TaskResponse::from(task)))
+/// Depth and throughput of the queues new tasks pass through
+#[utoipa::path(
+    get, path = "/api/ingest", tag = "tasks",
+    responses(
+        (status = 200, description = "Each ingest stage's queue depth and counts", body = [StageMetrics]),
+        (status = 404, description = "Tasks are stored as they arrive, without queues"),
+    )
+)]
+pub async fn get_ingest_metrics(State(state): State<Arc<AppState>>) -> Result<Json<Vec<StageMetrics>>, StatusCode> {
+    let ingest = state.ingest.as_ref().ok_or(StatusCode::NOT_FOUND)?;
+    Ok(Json(ingest.metrics()))
 }
 
 #[cfg(test)]
@@ -268,13 +746,14 @@ mod tests {
     use super::*;
     use crate::types::{Message, State, StateMachine, AgentStateManager, TodoProcessor, TodoTask};
     use crate::types::todo::{TaskStatus, TaskPriority, TodoList};
+    use crate::types::history::ChangeKind;
     use std::time::Duration;
     use futures::executor::block_on;
     use crate::agents::{AgentRegistry, GreeterAgent, TransferService};
-    use mongodb::{Client, Collection};
     use std::sync::Arc;
     use tokio::sync::RwLock;
     use axum::http::StatusCode;
+    use crate::testsupport::MongoFixture;
     use axum::extract::Path;
     use axum::Json;
 
@@ -296,23 +775,15 @@ mod tests {
         config: AgentConfig,
         todo_list: TodoList,
         ai_client: Arc<Box<dyn AiProvider + Send + Sync>>,
-        _client: Arc<Client>, // Keep the MongoDB client alive
     }
 
     impl TestAgent {
-        async fn new_with_mocks(config: AgentConfig, client: Arc<Client>) -> Result<Self, anyhow::Error> {
-            // Set up MongoDB connection for testing
-            std::env::set_var("RTK_MONGO_URI", "mongodb://localhost:27017");
-
-            // Create a new TodoList that will use the test database
-            let todo_list = TodoList::new().await?;
-
-            Ok(Self {
-                config: config.clone(),
+        fn new_with_mocks(config: AgentConfig, todo_list: TodoList) -> Self {
+            Self {
+                config,
                 todo_list,
                 ai_client: Arc::new(Box::new(MockAiClient) as Box<dyn AiProvider + Send + Sync>),
-                _client: client,
-            })
+            }
         }
 
         async fn enhance_task_description(&self, description: String) -> Result<String, anyhow::Error> {
@@ -362,7 +833,7 @@ mod tests {
 
     #[async_trait]
     impl TodoProcessor for TestAgent {
-        async fn process_task(&self, task: TodoTask) -> Result<Message, anyhow::Error> {
+        async fn process_task(&self, task: TodoTask, _cancel: crate::tools::CancellationToken) -> Result<Message, anyhow::Error> {
             // Enhance the task description using AI
             let enhanced_description = self.enhance_task_description(task.description.clone()).await?;
 
@@ -388,20 +859,27 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_message_requests_refuse_file_attachments() {
+        let inline: MessageRequest = serde_json::from_value(serde_json::json!({
+            "content": "What is this?",
+            "attachments": [{ "type": "json", "value": { "objects": 2 } }],
+        })).unwrap();
+        assert_eq!(inline.into_message().unwrap().attachments.len(), 1);
+
+        let file: MessageRequest = serde_json::from_value(serde_json::json!({
+            "content": "What is this?",
+            "attachments": [{ "type": "file", "path": "/etc/shadow", "mime_type": "image/png" }],
+        })).unwrap();
+        assert_eq!(file.into_message().unwrap_err(), StatusCode::BAD_REQUEST);
+    }
+
     #[tokio::test]
     async fn test_todo_list_endpoints() -> Result<(), anyhow::Error> {
-        // Set up MongoDB connection for testing
-        std::env::set_var("RTK_MONGO_URI", "mongodb://localhost:27017");
-        std::env::set_var("RTK_MONGO_DB", "swarmonomicon_test");
-
-        let client = Arc::new(Client::with_uri_str("mongodb://localhost:27017").await?);
-        let db = client.database("swarmonomicon_test");
-
-        // Clean up any existing data in the test database
-        db.collection::<TodoTask>("todos").drop(None).await?;
+        let mongo = MongoFixture::start().await?;
 
         // Set up test environment
-        let mut registry = AgentRegistry::new();
+        let mut registry = AgentRegistry::new().with_todos(mongo.todo_list().await);
         let agent = TestAgent::new_with_mocks(AgentConfig {
             name: "test_agent".to_string(),
             public_description: "Test agent".to_string(),
@@ -410,7 +888,11 @@ mod tests {
             downstream_agents: vec!["haiku".to_string()],
             personality: None,
             state_machine: None,
-        }, client.clone()).await?;
+            script: None,
+            max_concurrency: None,
+            middleware: None,
+            remote: None,
+        }, mongo.todo_list().await);
 
         registry.register("test_agent".to_string(), Box::new(agent)).await?;
         let registry = Arc::new(RwLock::new(registry));
@@ -418,6 +900,17 @@ mod tests {
         let state = Arc::new(AppState {
             transfer_service,
             agents: registry,
+            projects: None,
+            todos: None,
+            shutdown: Arc::new(crate::shutdown::ShutdownCoordinator::default()),
+            auth: None,
+            rate_limiter: None,
+            audit: None,
+            hooks: None,
+            workflows: None,
+            tenants: None,
+            ingest: None,
+            recent_errors: None,
         });
 
         // Test 1: Add a task with AI enhancement
@@ -426,11 +919,15 @@ mod tests {
             priority: TaskPriority::High,
             source_agent: Some("user".to_string()),
             project: None,
+            tags: Vec::new(),
+            allow_duplicate: false,
         };
 
         let response = add_task(
             State(state.clone()),
+            Tenant::default(),
             Path("test_agent".to_string()),
+            HeaderMap::new(),
             Json(add_request.clone()),
         ).await.map_err(|e| anyhow!("Failed to add task: {:?}", e))?;
 
@@ -449,6 +946,8 @@ mod tests {
             priority: TaskPriority::Low,
             source_agent: None,
             project: None,
+            tags: Vec::new(),
+            allow_duplicate: false,
         };
 
         let medium_priority_task = AddTaskRequest {
@@ -456,24 +955,43 @@ mod tests {
             priority: TaskPriority::Medium,
             source_agent: None,
             project: None,
+            tags: Vec::new(),
+            allow_duplicate: false,
         };
 
-        add_task(
+        // Sent twice with the same idempotency key, as a retrying producer would
+        let mut retry_headers = HeaderMap::new();
+        retry_headers.insert(idempotency::HEADER, "docs-1".parse()?);
+        let first = add_task(
             State(state.clone()),
+            Tenant::default(),
             Path("test_agent".to_string()),
-            Json(low_priority_task),
+            retry_headers.clone(),
+            Json(low_priority_task.clone()),
         ).await.map_err(|e| anyhow!("Failed to add low priority task: {:?}", e))?;
+        let retried = add_task(
+            State(state.clone()),
+            Tenant::default(),
+            Path("test_agent".to_string()),
+            retry_headers,
+            Json(low_priority_task),
+        ).await.map_err(|e| anyhow!("Failed to retry low priority task: {:?}", e))?;
+        assert_eq!(retried.0.id, first.0.id, "A retry returns the original task");
 
         add_task(
             State(state.clone()),
+            Tenant::default(),
             Path("test_agent".to_string()),
+            HeaderMap::new(),
             Json(medium_priority_task),
         ).await.map_err(|e| anyhow!("Failed to add medium priority task: {:?}", e))?;
 
         // Test 3: Get all tasks and verify ordering
         let tasks = get_tasks(
             State(state.clone()),
+            Tenant::default(),
             Path("test_agent".to_string()),
+            axum::extract::Query(TaskListQuery { include_archived: false, tags: None }),
         ).await.map_err(|e| anyhow!("Failed to get tasks: {:?}", e))?;
 
         assert_eq!(tasks.0.len(), 3);
@@ -482,6 +1000,7 @@ mod tests {
         // Test 4: Get specific task and verify details
         let task = get_task(
             State(state.clone()),
+            Tenant::default(),
             Path(("test_agent".to_string(), task_id.clone())),
         ).await.map_err(|e| anyhow!("Failed to get specific task: {:?}", e))?;
 
@@ -491,6 +1010,7 @@ mod tests {
         // Test 5: Error handling for non-existent task
         let result = get_task(
             State(state.clone()),
+            Tenant::default(),
             Path(("test_agent".to_string(), "non-existent".to_string())),
         ).await;
 
@@ -500,7 +1020,9 @@ mod tests {
         // Test 6: Error handling for non-existent agent
         let result = add_task(
             State(state.clone()),
+            Tenant::default(),
             Path("non-existent".to_string()),
+            HeaderMap::new(),
             Json(add_request),
         ).await;
 
@@ -513,19 +1035,37 @@ mod tests {
             priority: TaskPriority::Medium,
             source_agent: Some("test_agent".to_string()),
             project: None,
+            tags: Vec::new(),
+            allow_duplicate: false,
         };
 
         let response = add_task(
             State(state.clone()),
+            Tenant::default(),
             Path("haiku".to_string()),
+            HeaderMap::new(),
             Json(delegated_task),
         ).await;
 
         assert!(response.is_err()); // Should fail since haiku agent isn't registered
         assert_eq!(response.unwrap_err(), StatusCode::NOT_FOUND);
 
-        // Clean up test database
-        db.collection::<TodoTask>("todos").drop(None).await?;
+        // Test 8: Edits, reassignment and cancellation are kept in the task's history
+        let task_path = || Path(("test_agent".to_string(), task_id.clone()));
+        let edit = TaskEdit { priority: Some(TaskPriority::Critical), ..TaskEdit::default() };
+        let edited = edit_task(State(state.clone()), Tenant::default(), None, task_path(), Json(edit))
+            .await.map_err(|e| anyhow!("Failed to edit task: {:?}", e))?;
+        assert_eq!(edited.0.priority, TaskPriority::Critical);
+
+        let reassign = ReassignRequest { target_agent: "haiku".to_string() };
+        let result = reassign_task(State(state.clone()), Tenant::default(), None, task_path(), Json(reassign)).await;
+        assert_eq!(result.unwrap_err(), StatusCode::BAD_REQUEST, "haiku isn't registered");
+
+        let cancelled = cancel_task(State(state.clone()), Tenant::default(), None, task_path())
+            .await.map_err(|e| anyhow!("Failed to cancel task: {:?}", e))?;
+        let kinds: Vec<ChangeKind> = cancelled.0.history.iter().map(|change| change.kind).collect();
+        assert_eq!(kinds, [ChangeKind::Edited, ChangeKind::Status]);
+        assert!(cancelled.0.history.iter().all(|change| change.actor == "api"));
 
         Ok(())
     }
@@ -0,0 +1,67 @@
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use axum::{
+    extract::{Query, State},
+    http::StatusCode,
+    Json,
+};
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+use crate::api::AppState;
+use super::tenants::Tenant;
+use crate::types::search::SearchHit;
+use crate::types::tags::parse_tags;
+use super::models::TaskResponse;
+
+const MAX_RESULTS: usize = 100;
+
+#[derive(Debug, Deserialize, IntoParams)]
+pub struct SearchQuery {
+    /// Words to look for; `"quoted phrases"` must match and `-word` excludes
+    pub q: String,
+    /// Most results to return; defaults to 20, at most 100
+    pub limit: Option<usize>,
+    /// Comma-separated tags; only tasks with all of them match
+    pub tags: Option<String>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct SearchResultResponse {
+    pub task: TaskResponse,
+    /// Relevance; higher is better
+    pub score: f64,
+    /// The matching fields with matched words marked `**like this**`
+    pub highlights: BTreeMap<String, String>,
+}
+
+impl From<SearchHit> for SearchResultResponse {
+    fn from(hit: SearchHit) -> Self {
+        Self { task: TaskResponse::from(hit.task), score: hit.score, highlights: hit.highlights }
+    }
+}
+
+#[utoipa::path(
+    get, path = "/tasks/search", tag = "tasks",
+    params(SearchQuery),
+    responses(
+        (status = 200, description = "Matching tasks, most relevant first", body = [SearchResultResponse]),
+        (status = 400, description = "Empty query"),
+    )
+)]
+pub async fn search_tasks(
+    State(state): State<Arc<AppState>>,
+    tenant: Tenant,
+    Query(query): Query<SearchQuery>,
+) -> Result<Json<Vec<SearchResultResponse>>, StatusCode> {
+    if query.q.trim().is_empty() {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    let todos = state.todos.as_ref().map(|todos| tenant.todos(todos)).ok_or(StatusCode::SERVICE_UNAVAILABLE)?;
+    let limit = query.limit.unwrap_or(20).clamp(1, MAX_RESULTS);
+    let tags = query.tags.as_deref().map(parse_tags).unwrap_or_default();
+    let hits = todos.search(&query.q, &tags, limit).await.map_err(|e| {
+        tracing::error!("Task search failed: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+    Ok(Json(hits.into_iter().map(SearchResultResponse::from).collect()))
+}
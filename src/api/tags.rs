@@ -0,0 +1,35 @@
+use std::sync::Arc;
+use axum::{
+    extract::{Query, State},
+    http::StatusCode,
+    Json,
+};
+use serde::Deserialize;
+use utoipa::IntoParams;
+use crate::api::AppState;
+use super::tenants::Tenant;
+use crate::types::reporting::TagStats;
+
+#[derive(Debug, Deserialize, IntoParams)]
+pub struct TagStatsQuery {
+    /// Only count tasks in this project
+    pub project: Option<String>,
+}
+
+#[utoipa::path(
+    get, path = "/api/tags", tag = "tasks",
+    params(TagStatsQuery),
+    responses((status = 200, description = "Task counts per tag, most used first", body = [TagStats]))
+)]
+pub async fn tag_stats(
+    State(state): State<Arc<AppState>>,
+    tenant: Tenant,
+    Query(query): Query<TagStatsQuery>,
+) -> Result<Json<Vec<TagStats>>, StatusCode> {
+    let todos = state.todos.as_ref().map(|todos| tenant.todos(todos)).ok_or(StatusCode::SERVICE_UNAVAILABLE)?;
+    let stats = todos.tag_stats(query.project.as_deref()).await.map_err(|e| {
+        tracing::error!("Tag stats failed: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+    Ok(Json(stats))
+}
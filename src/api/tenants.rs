@@ -0,0 +1,99 @@
+use std::sync::Arc;
+use axum::{
+    async_trait,
+    extract::FromRequestParts,
+    http::{StatusCode, request::Parts},
+};
+use crate::api::AppState;
+use crate::types::{Message, TodoList, api_keys::ApiKey, projects::ProjectRegistry, tenants};
+
+/// Header naming the tenant a request is for, when the API key isn't bound to one
+pub const TENANT_HEADER: &str = "x-tenant-id";
+
+/// The tenant a request acts for. Keys bound to a tenant always act for it; other callers
+/// may name one in `X-Tenant-Id`, or act across all tenants by leaving it out.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Tenant(pub Option<String>);
+
+impl Tenant {
+    pub fn id(&self) -> Option<&str> {
+        self.0.as_deref()
+    }
+
+    pub fn todos(&self, todos: &TodoList) -> TodoList {
+        todos.for_tenant(self.id())
+    }
+
+    pub fn projects(&self, projects: &ProjectRegistry) -> ProjectRegistry {
+        projects.for_tenant(self.id())
+    }
+
+    /// Whether the tenant's view of the registry includes `agent`
+    pub fn sees_agent(&self, state: &AppState, agent: &str) -> bool {
+        state.tenants.as_ref().map_or(true, |config| config.sees_agent(self.id(), agent))
+    }
+
+    /// `message` marked with the tenant, so agents keep its sessions apart
+    pub fn tag(&self, agent: &str, message: Message) -> Message {
+        match self.id() {
+            Some(tenant) => tenants::tag_message(message, agent, tenant),
+            None => message,
+        }
+    }
+
+    /// Resolve the tenant from the caller's key and the requested tenant header
+    pub fn resolve(key: Option<&ApiKey>, requested: Option<&str>, config: Option<&tenants::TenantConfig>) -> Result<Self, (StatusCode, String)> {
+        if let Some(bound) = key.and_then(|key| key.tenant.as_deref()) {
+            return match requested {
+                Some(requested) if requested != bound => {
+                    Err((StatusCode::FORBIDDEN, format!("API key is bound to tenant '{}'", bound)))
+                }
+                _ => Ok(Self(Some(bound.to_string()))),
+            };
+        }
+        let Some(requested) = requested else {
+            return Ok(Self(None));
+        };
+        tenants::validate_tenant_id(requested).map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))?;
+        if config.is_some_and(|config| !config.is_known(requested)) {
+            return Err((StatusCode::FORBIDDEN, format!("Unknown tenant '{}'", requested)));
+        }
+        Ok(Self(Some(requested.to_string())))
+    }
+}
+
+#[async_trait]
+impl FromRequestParts<Arc<AppState>> for Tenant {
+    type Rejection = (StatusCode, String);
+
+    async fn from_request_parts(parts: &mut Parts, state: &Arc<AppState>) -> Result<Self, Self::Rejection> {
+        let requested = parts.headers.get(TENANT_HEADER).and_then(|v| v.to_str().ok()).map(str::trim);
+        Self::resolve(parts.extensions.get::<ApiKey>(), requested, state.tenants.as_deref())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::api_keys::Scope;
+    use crate::types::tenants::TenantConfig;
+
+    #[test]
+    fn test_bound_keys_act_for_their_tenant() {
+        let (key, _) = ApiKey::generate("platform-bot", vec![Scope::WriteTasks]);
+        let key = key.with_tenant("platform");
+        assert_eq!(Tenant::resolve(Some(&key), None, None).unwrap().id(), Some("platform"));
+        assert_eq!(Tenant::resolve(Some(&key), Some("platform"), None).unwrap().id(), Some("platform"));
+        assert_eq!(Tenant::resolve(Some(&key), Some("research"), None).unwrap_err().0, StatusCode::FORBIDDEN);
+    }
+
+    #[test]
+    fn test_unbound_callers_choose_a_known_tenant() {
+        let config: TenantConfig = serde_yaml::from_str("tenants: { research: {} }").unwrap();
+        assert_eq!(Tenant::resolve(None, None, Some(&config)).unwrap(), Tenant(None));
+        assert_eq!(Tenant::resolve(None, Some("research"), Some(&config)).unwrap().id(), Some("research"));
+        assert_eq!(Tenant::resolve(None, Some("platform"), Some(&config)).unwrap_err().0, StatusCode::FORBIDDEN);
+        assert_eq!(Tenant::resolve(None, Some("platform"), None).unwrap().id(), Some("platform"));
+        assert_eq!(Tenant::resolve(None, Some("../etc"), None).unwrap_err().0, StatusCode::BAD_REQUEST);
+    }
+}
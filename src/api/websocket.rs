@@ -8,9 +8,10 @@ use futures::{sink::SinkExt, stream::StreamExt};
 use serde::{Deserialize, Serialize};
 use tokio::sync::broadcast;
 use crate::{
-    api::AppState,
+    api::{AppState, tenants::Tenant},
     agents::{AgentRegistry, TransferService, GreeterAgent},
-    types::{AgentConfig, Tool, Message},
+    error::SwarmError,
+    types::{AgentConfig, Tool, Message, sessions},
 };
 
 #[cfg(feature = "haiku-agent")]
@@ -57,18 +58,51 @@ pub enum ServerMessage {
 pub async fn websocket_handler(
     ws: WebSocketUpgrade,
     State(state): State<Arc<AppState>>,
+    tenant: Tenant,
 ) -> Response {
-    ws.on_upgrade(|socket| handle_socket(socket, state))
+    ws.on_upgrade(|socket| handle_socket(socket, state, tenant))
 }
 
-async fn handle_socket(socket: axum::extract::ws::WebSocket, state: Arc<AppState>) {
+/// A client connection: its own session, acting for the tenant it connected as
+struct Connection {
+    session: String,
+    tenant: Tenant,
+}
+
+impl Connection {
+    fn new(session: impl Into<String>, tenant: Tenant) -> Self {
+        Self { session: session.into(), tenant }
+    }
+
+    /// The id the session is routed under, namespaced by tenant like the messages sent in it
+    fn route_key(&self) -> String {
+        sessions::session_key(self.tenant.id(), &self.session)
+    }
+
+    fn message(&self, content: String) -> Message {
+        self.tenant.tag("user", sessions::tag_session(Message::new(content), "user", &self.session))
+    }
+
+    /// `agent`, if the tenant sees it
+    fn visible(&self, state: &AppState, agent: &str) -> Result<(), String> {
+        if self.tenant.sees_agent(state, agent) {
+            Ok(())
+        } else {
+            Err(SwarmError::AgentNotFound(agent.to_string()).to_string())
+        }
+    }
+}
+
+/// Each connection is its own session, routed independently of other clients
+async fn handle_socket(socket: axum::extract::ws::WebSocket, state: Arc<AppState>, tenant: Tenant) {
     let (mut sender, mut receiver) = socket.split();
+    let connection = Connection::new(uuid::Uuid::new_v4().to_string(), tenant);
 
     while let Some(Ok(msg)) = receiver.next().await {
         if let WsMessage::Text(content) = msg {
             let response = match serde_json::from_str::<ClientMessage>(&content) {
                 Ok(client_msg) => {
-                    match handle_client_message(client_msg, state.clone()).await {
+                    match handle_client_message(client_msg, state.clone(), &connection).await {
                         Ok(server_msg) => {
                             match serde_json::to_string(&server_msg) {
                                 Ok(json) => WsMessage::Text(json),
@@ -86,25 +120,33 @@ async fn handle_socket(socket: axum::extract::ws::WebSocket, state: Arc<AppState
             }
         }
     }
+    if let Err(e) = state.transfer_service.read().await.end_session(&connection.route_key()).await {
+        tracing::warn!("Failed to end websocket session {}: {}", connection.session, e);
+    }
 }
 
-async fn handle_client_message(msg: ClientMessage, state: Arc<AppState>) -> Result<ServerMessage, String> {
+async fn handle_client_message(msg: ClientMessage, state: Arc<AppState>, connection: &Connection) -> Result<ServerMessage, String> {
+    let session = connection.route_key();
     match msg {
         ClientMessage::Connect { agent } => {
-            let mut transfer_service = state.transfer_service.write().await;
-            transfer_service.set_current_agent_name(&agent).await.map_err(|e| e.to_string())?;
+            connection.visible(&state, &agent)?;
+            let transfer_service = state.transfer_service.read().await;
+            transfer_service.set_current_agent_for(Some(&session), &agent).await.map_err(|e| e.to_string())?;
             Ok(ServerMessage::Connected { agent })
         },
         ClientMessage::Message { content } => {
             let transfer_service = state.transfer_service.read().await;
-            match transfer_service.process_message(Message::new(content)).await {
+            let current = transfer_service.current_agent_for(Some(&session)).await.map_err(|e| e.to_string())?;
+            connection.visible(&state, &current)?;
+            match transfer_service.process_message(connection.message(content)).await {
                 Ok(response) => Ok(ServerMessage::Message { content: response.content }),
                 Err(e) => Err(e.to_string()),
             }
         },
         ClientMessage::Transfer { from, to } => {
-            let mut transfer_service = state.transfer_service.write().await;
-            transfer_service.set_current_agent_name(&to).await.map_err(|e| e.to_string())?;
+            connection.visible(&state, &to)?;
+            let transfer_service = state.transfer_service.read().await;
+            transfer_service.set_current_agent_for(Some(&session), &to).await.map_err(|e| e.to_string())?;
             Ok(ServerMessage::Transferred { from, to })
         },
         ClientMessage::UpdateSession { instructions, tools, turn_detection } => {
@@ -118,11 +160,12 @@ pub async fn handle_websocket(
     mut socket: WebSocket,
     transfer_service: Arc<RwLock<TransferService>>,
 ) {
+    let session = uuid::Uuid::new_v4().to_string();
     while let Some(msg) = socket.recv().await {
         if let Ok(msg) = msg {
             match msg {
                 WsMessage::Text(text) => {
-                    let response = handle_message(text, &transfer_service).await;
+                    let response = handle_message(text, &transfer_service, &session).await;
                     match response {
                         Ok(response) => {
                             if let Err(e) = socket.send(WsMessage::Text(response)).await {
@@ -150,8 +193,8 @@ pub async fn handle_websocket(
     }
 }
 
-async fn handle_message(text: String, transfer_service: &Arc<RwLock<TransferService>>) -> Result<String> {
-    let mut transfer_service = transfer_service.write().await;
+async fn handle_message(text: String, transfer_service: &Arc<RwLock<TransferService>>, session: &str) -> Result<String> {
+    let transfer_service = transfer_service.read().await;
     let data: Value = serde_json::from_str(&text)?;
 
     match data["type"].as_str() {
@@ -159,9 +202,8 @@ async fn handle_message(text: String, transfer_service: &Arc<RwLock<TransferServ
             let content = data["content"].as_str()
                 .ok_or_else(|| anyhow!("Missing content field"))?;
 
-            let current_agent = transfer_service.get_current_agent_name().await?;
-            let agent = transfer_service.get_agent(&current_agent).await?;
-            let response = agent.process_message(Message::new(content.to_string())).await?;
+            let message = sessions::tag_session(Message::new(content.to_string()), "user", session);
+            let response = transfer_service.process_message(message).await?;
 
             Ok(json!({
                 "type": "response",
@@ -173,10 +215,9 @@ async fn handle_message(text: String, transfer_service: &Arc<RwLock<TransferServ
             let to = data["to"].as_str()
                 .ok_or_else(|| anyhow!("Missing 'to' field"))?;
 
-            let from = transfer_service.get_current_agent_name().await?;
-            let message = Message::new("Transfer request".to_string());
+            let from = transfer_service.current_agent_for(Some(session)).await?;
+            let message = sessions::tag_session(Message::new("Transfer request".to_string()), "user", session);
             let response = transfer_service.transfer(&from, to, message).await?;
-            transfer_service.set_current_agent_name(to).await?;
 
             Ok(json!({
                 "type": "transfer",
@@ -207,6 +248,10 @@ mod tests {
             downstream_agents: vec![],
             personality: None,
             state_machine: None,
+            script: None,
+            max_concurrency: None,
+            middleware: None,
+            remote: None,
         };
 
         let haiku_config = AgentConfig {
@@ -217,6 +262,10 @@ mod tests {
             downstream_agents: vec![],
             personality: None,
             state_machine: None,
+            script: None,
+            max_concurrency: None,
+            middleware: None,
+            remote: None,
         };
 
         let greeter_agent = GreeterAgent::new(greeter_config);
@@ -229,6 +278,17 @@ mod tests {
         Arc::new(AppState {
             transfer_service: Arc::new(RwLock::new(TransferService::new(registry.clone()))),
             agents: registry,
+            projects: None,
+            todos: None,
+            shutdown: Arc::new(crate::shutdown::ShutdownCoordinator::default()),
+            auth: None,
+            rate_limiter: None,
+            audit: None,
+            hooks: None,
+            workflows: None,
+            tenants: None,
+            ingest: None,
+            recent_errors: None,
         })
     }
 
@@ -239,7 +299,7 @@ mod tests {
             agent: "greeter".to_string(),
         };
 
-        let response = handle_client_message(msg, state).await;
+        let response = handle_client_message(msg, state, &Connection::new("test-session", Tenant::default())).await;
         match response {
             Ok(ServerMessage::Connected { agent }) => {
                 assert_eq!(agent, "greeter");
@@ -251,19 +311,20 @@ mod tests {
     #[tokio::test]
     async fn test_handle_message() {
         let state = setup_test_state().await;
+        let connection = Connection::new("test-session", Tenant::default());
 
         // First connect to an agent
         let connect_msg = ClientMessage::Connect {
             agent: "greeter".to_string(),
         };
-        handle_client_message(connect_msg, state.clone()).await.expect("Failed to connect");
+        handle_client_message(connect_msg, state.clone(), &connection).await.expect("Failed to connect");
 
         // Then send a message
         let msg = ClientMessage::Message {
             content: "hi".to_string(),
         };
 
-        let response = handle_client_message(msg, state).await;
+        let response = handle_client_message(msg, state, &connection).await;
         match response {
             Ok(ServerMessage::Message { content }) => {
                 assert!(!content.is_empty());
@@ -276,19 +337,20 @@ mod tests {
     #[tokio::test]
     async fn test_handle_transfer() {
         let state = setup_test_state().await;
+        let connection = Connection::new("test-session", Tenant::default());
 
         // Test 1: Connect to greeter agent
         let connect_msg = ClientMessage::Connect {
             agent: "greeter".to_string(),
         };
-        let response = handle_client_message(connect_msg, state.clone()).await.unwrap();
+        let response = handle_client_message(connect_msg, state.clone(), &connection).await.unwrap();
         assert!(matches!(response, ServerMessage::Connected { agent } if agent == "greeter"));
 
         // Test 2: Send a message to establish context
         let context_msg = ClientMessage::Message {
             content: "I want to write a haiku about coding".to_string(),
         };
-        let response = handle_client_message(context_msg, state.clone()).await.unwrap();
+        let response = handle_client_message(context_msg, state.clone(), &connection).await.unwrap();
         assert!(matches!(response, ServerMessage::Message { .. }));
 
         // Test 3: Transfer to haiku agent with context
@@ -296,14 +358,14 @@ mod tests {
             from: "greeter".to_string(),
             to: "haiku".to_string(),
         };
-        let response = handle_client_message(transfer_msg, state.clone()).await.unwrap();
+        let response = handle_client_message(transfer_msg, state.clone(), &connection).await.unwrap();
         assert!(matches!(response, ServerMessage::Transferred { from, to } if from == "greeter" && to == "haiku"));
 
         // Test 4: Verify haiku agent received context
         let verify_msg = ClientMessage::Message {
             content: "What was I writing about?".to_string(),
         };
-        let response = handle_client_message(verify_msg, state.clone()).await.unwrap();
+        let response = handle_client_message(verify_msg, state.clone(), &connection).await.unwrap();
         match response {
             ServerMessage::Message { content } => {
                 assert!(content.contains("coding"), "Context should be preserved after transfer");
@@ -316,21 +378,21 @@ mod tests {
             from: "greeter".to_string(),
             to: "nonexistent".to_string(),
         };
-        let response = handle_client_message(invalid_transfer, state.clone()).await;
+        let response = handle_client_message(invalid_transfer, state.clone(), &connection).await;
         assert!(response.is_err(), "Transfer to nonexistent agent should fail");
 
         // Test 6: Test transfer with state preservation
         let connect_msg = ClientMessage::Connect {
             agent: "haiku".to_string(),
         };
-        let response = handle_client_message(connect_msg, state.clone()).await.unwrap();
+        let response = handle_client_message(connect_msg, state.clone(), &connection).await.unwrap();
         assert!(matches!(response, ServerMessage::Connected { agent } if agent == "haiku"));
 
         // Set up state in haiku agent
         let state_msg = ClientMessage::Message {
             content: "nature".to_string(),
         };
-        let response = handle_client_message(state_msg, state.clone()).await.unwrap();
+        let response = handle_client_message(state_msg, state.clone(), &connection).await.unwrap();
         assert!(matches!(response, ServerMessage::Message { .. }));
 
         // Transfer back to greeter
@@ -338,14 +400,14 @@ mod tests {
             from: "haiku".to_string(),
             to: "greeter".to_string(),
         };
-        let response = handle_client_message(transfer_msg, state.clone()).await.unwrap();
+        let response = handle_client_message(transfer_msg, state.clone(), &connection).await.unwrap();
         assert!(matches!(response, ServerMessage::Transferred { from, to } if from == "haiku" && to == "greeter"));
 
         // Verify state was preserved
         let verify_msg = ClientMessage::Message {
             content: "What was my last topic?".to_string(),
         };
-        let response = handle_client_message(verify_msg, state).await.unwrap();
+        let response = handle_client_message(verify_msg, state, &connection).await.unwrap();
         match response {
             ServerMessage::Message { content } => {
                 assert!(content.contains("nature"), "State should be preserved after transfer");
@@ -353,4 +415,23 @@ mod tests {
             _ => panic!("Expected message response"),
         }
     }
+
+    #[tokio::test]
+    async fn test_tenants_only_reach_their_agents() {
+        let mut state = Arc::into_inner(setup_test_state().await).unwrap();
+        state.tenants = Some(Arc::new(serde_yaml::from_str("tenants: { platform: { agents: [haiku] } }").unwrap()));
+        let state = Arc::new(state);
+        let connection = Connection::new("test-session", Tenant(Some("platform".to_string())));
+
+        let connect = ClientMessage::Connect { agent: "greeter".to_string() };
+        assert!(handle_client_message(connect, state.clone(), &connection).await.is_err());
+        // Unrouted sessions start with the default agent, which the tenant doesn't see
+        let message = ClientMessage::Message { content: "hi".to_string() };
+        assert!(handle_client_message(message, state.clone(), &connection).await.is_err());
+
+        let connect = ClientMessage::Connect { agent: "haiku".to_string() };
+        assert!(handle_client_message(connect, state.clone(), &connection).await.is_ok());
+        let route = state.transfer_service.read().await.current_agent_for(Some("platform/test-session")).await.unwrap();
+        assert_eq!(route, "haiku");
+    }
 }
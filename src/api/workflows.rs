@@ -0,0 +1,178 @@
+use std::sync::Arc;
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    Json,
+};
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+use crate::api::{AppState, tenants::Tenant};
+use crate::workflow::{WorkflowEngine, WorkflowRun};
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct WorkflowSummary {
+    pub name: String,
+    pub description: Option<String>,
+    pub steps: Vec<String>,
+}
+
+#[derive(Debug, Default, Deserialize, ToSchema)]
+pub struct RunWorkflowRequest {
+    /// Available to step templates as `{{ input }}`
+    #[serde(default)]
+    pub input: String,
+    /// Task to note the outcome on if the run fails and is rolled back
+    #[serde(default)]
+    pub task_id: Option<String>,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+pub struct WorkflowRunsQuery {
+    /// Only runs of this workflow
+    pub workflow: Option<String>,
+    /// Defaults to 20
+    pub limit: Option<usize>,
+}
+
+fn engine(state: &AppState) -> Result<&Arc<WorkflowEngine>, (StatusCode, String)> {
+    state.workflows.as_ref().ok_or((StatusCode::SERVICE_UNAVAILABLE, "No workflows configured".to_string()))
+}
+
+fn store_error(e: anyhow::Error) -> (StatusCode, String) {
+    tracing::error!("Workflow run storage failed: {}", e);
+    (StatusCode::INTERNAL_SERVER_ERROR, "Failed to access workflow runs".to_string())
+}
+
+#[utoipa::path(
+    get, path = "/api/workflows", tag = "workflows",
+    responses(
+        (status = 200, description = "Workflows from WORKFLOWS_CONFIG, by name", body = [WorkflowSummary]),
+        (status = 503, description = "Workflows aren't configured"),
+    )
+)]
+pub async fn list_workflows(State(state): State<Arc<AppState>>) -> Result<Json<Vec<WorkflowSummary>>, (StatusCode, String)> {
+    let mut workflows: Vec<WorkflowSummary> = engine(&state)?.config().workflows.iter()
+        .map(|(name, workflow)| WorkflowSummary {
+            name: name.clone(),
+            description: workflow.description.clone(),
+            steps: workflow.steps.iter().map(|step| step.name.clone()).collect(),
+        })
+        .collect();
+    workflows.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(Json(workflows))
+}
+
+#[utoipa::path(
+    post, path = "/api/workflows/{name}/run", tag = "workflows",
+    params(("name" = String, Path, description = "Workflow name")),
+    request_body = RunWorkflowRequest,
+    responses(
+        (status = 202, description = "The run, started in the background; poll /api/workflow-runs/{id}", body = WorkflowRun),
+        (status = 404, description = "No such workflow, or no such task for the tenant"),
+        (status = 503, description = "Workflows aren't configured"),
+    )
+)]
+pub async fn run_workflow(
+    State(state): State<Arc<AppState>>,
+    tenant: Tenant,
+    Path(name): Path<String>,
+    request: Option<Json<RunWorkflowRequest>>,
+) -> Result<(StatusCode, Json<WorkflowRun>), (StatusCode, String)> {
+    let engine = engine(&state)?.clone();
+    if engine.config().get(&name).is_none() {
+        return Err((StatusCode::NOT_FOUND, format!("No workflow {}", name)));
+    }
+    let Json(request) = request.unwrap_or_default();
+    if let (Some(task_id), Some(_)) = (&request.task_id, tenant.id()) {
+        let todos = state.todos.as_ref().ok_or((StatusCode::SERVICE_UNAVAILABLE, "No task list configured".to_string()))?;
+        let task = tenant.todos(todos).get_task(task_id).await.map_err(|e| store_error(e.into()))?;
+        if task.is_none() {
+            return Err((StatusCode::NOT_FOUND, format!("No task {}", task_id)));
+        }
+    }
+    let run = engine.start_for_tenant(&name, &request.input, request.task_id, tenant.id()).await.map_err(store_error)?;
+    let started = run.clone();
+    tokio::spawn(async move {
+        let id = run.id.clone();
+        if let Err(e) = engine.run(run).await {
+            tracing::error!("Workflow run {} stopped: {}", id, e);
+        }
+    });
+    Ok((StatusCode::ACCEPTED, Json(started)))
+}
+
+#[utoipa::path(
+    get, path = "/api/workflow-runs", tag = "workflows",
+    params(WorkflowRunsQuery),
+    responses((status = 200, description = "Runs, most recent first", body = [WorkflowRun]))
+)]
+pub async fn list_workflow_runs(
+    State(state): State<Arc<AppState>>,
+    tenant: Tenant,
+    Query(query): Query<WorkflowRunsQuery>,
+) -> Result<Json<Vec<WorkflowRun>>, (StatusCode, String)> {
+    let runs = engine(&state)?.store().list(tenant.id(), query.workflow.as_deref(), query.limit.unwrap_or(20)).await.map_err(store_error)?;
+    Ok(Json(runs))
+}
+
+#[utoipa::path(
+    get, path = "/api/workflow-runs/{id}", tag = "workflows",
+    params(("id" = String, Path, description = "Run id")),
+    responses(
+        (status = 200, description = "The run's status, current step, outputs and step history", body = WorkflowRun),
+        (status = 404, description = "No such run"),
+    )
+)]
+pub async fn get_workflow_run(
+    State(state): State<Arc<AppState>>,
+    tenant: Tenant,
+    Path(id): Path<String>,
+) -> Result<Json<WorkflowRun>, (StatusCode, String)> {
+    engine(&state)?.store().load(&id, tenant.id()).await.map_err(store_error)?
+        .map(Json)
+        .ok_or((StatusCode::NOT_FOUND, format!("No workflow run {}", id)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{Router, body::Body, http::Request, routing::{get, post}};
+    use tokio::sync::RwLock;
+    use tower::ServiceExt;
+    use crate::agents::{AgentRegistry, TransferService};
+    use crate::tools::ToolRegistry;
+    use crate::workflow::{InMemoryWorkflowRunStore, RunStatus, WorkflowConfig};
+
+    #[tokio::test]
+    async fn test_run_and_poll_workflow() -> anyhow::Result<()> {
+        let config: WorkflowConfig = serde_yaml::from_str("workflows: { noop: { steps: [{ name: a, tool: { name: missing } }] } }")?;
+        let engine = WorkflowEngine::new(config, Arc::new(InMemoryWorkflowRunStore::new())).with_tools(Arc::new(ToolRegistry::new()));
+        let registry = Arc::new(RwLock::new(AgentRegistry::new()));
+        let state = AppState::new(Arc::new(RwLock::new(TransferService::new(registry)))).with_workflows(engine);
+        let app = Router::new()
+            .route("/api/workflows/:name/run", post(run_workflow))
+            .route("/api/workflow-runs/:id", get(get_workflow_run))
+            .with_state(Arc::new(state));
+
+        let unknown = app.clone().oneshot(Request::post("/api/workflows/deploy/run").body(Body::empty())?).await?;
+        assert_eq!(unknown.status(), StatusCode::NOT_FOUND);
+        let started = app.clone().oneshot(Request::post("/api/workflows/noop/run").body(Body::empty())?).await?;
+        assert_eq!(started.status(), StatusCode::ACCEPTED);
+        let run: serde_json::Value = serde_json::from_slice(&axum::body::to_bytes(started.into_body(), usize::MAX).await?)?;
+
+        // The only step runs a tool that isn't registered, so the run fails
+        let uri = format!("/api/workflow-runs/{}", run["id"].as_str().unwrap());
+        let other_tenant = Request::get(&uri).header(crate::api::tenants::TENANT_HEADER, "platform").body(Body::empty())?;
+        assert_eq!(app.clone().oneshot(other_tenant).await?.status(), StatusCode::NOT_FOUND);
+        for _ in 0..50 {
+            let polled = app.clone().oneshot(Request::get(&uri).body(Body::empty())?).await?;
+            let run: WorkflowRun = serde_json::from_slice(&axum::body::to_bytes(polled.into_body(), usize::MAX).await?)?;
+            if run.status != RunStatus::Running {
+                assert_eq!(run.status, RunStatus::Failed);
+                return Ok(());
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+        panic!("The run never finished");
+    }
+}
@@ -0,0 +1,313 @@
+//! Append-only record of who did what: messages routed to agents, transfers, tool calls
+//! and changes to stored data. Entries are never updated or removed through this module.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use anyhow::Result;
+use async_trait::async_trait;
+use futures::StreamExt;
+use mongodb::{Collection, IndexModel};
+use mongodb::bson::{Document, doc};
+use mongodb::options::FindOptions;
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+use tokio::sync::RwLock;
+use tokio::task::JoinHandle;
+use utoipa::{IntoParams, ToSchema};
+use uuid::Uuid;
+use crate::db::Database;
+use crate::error::SwarmError;
+use crate::events::{DomainEvent, EventBus};
+use crate::secrets;
+
+const DEFAULT_LIMIT: usize = 100;
+const MAX_LIMIT: usize = 1000;
+/// Longer parameter values are cut short, so entries stay small
+const MAX_PARAM_LEN: usize = 200;
+const REDACTED: &str = "[REDACTED]";
+const SENSITIVE_KEYS: &[&str] = &["password", "secret", "token", "key", "auth", "credential"];
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct AuditEntry {
+    pub id: String,
+    pub timestamp: i64,
+    /// Who triggered the action: an agent name, `key:<name>`, `ip:<address>` or `system`
+    pub actor: String,
+    /// e.g. `message_routed`, `tool_executed`, `task_created` or `http_request`
+    pub action: String,
+    /// What was acted on: an agent, tool, task id or API path
+    pub target: String,
+    #[schema(value_type = Object)]
+    pub details: Value,
+}
+
+impl AuditEntry {
+    pub fn new(actor: impl Into<String>, action: impl Into<String>, target: impl Into<String>, details: Value) -> Self {
+        Self {
+            id: Uuid::new_v4().to_string(),
+            timestamp: chrono::Utc::now().timestamp(),
+            actor: actor.into(),
+            action: action.into(),
+            target: target.into(),
+            details,
+        }
+    }
+
+    /// The entry recording a domain event, keeping the event's timestamp
+    pub fn from_event(event: &DomainEvent) -> Self {
+        let action = event.name();
+        let (actor, target, details, timestamp) = match event {
+            DomainEvent::Task(task) => (
+                task.target_agent.clone().unwrap_or_else(|| "system".to_string()),
+                task.task_id.clone(),
+                json!({ "project": task.project, "failure_reason": task.failure_reason }),
+                task.timestamp,
+            ),
+            DomainEvent::MessageRouted { from, to, timestamp } => (
+                from.clone().unwrap_or_else(|| "system".to_string()),
+                to.clone(),
+                json!({ "transfer": from.is_some() }),
+                *timestamp,
+            ),
+            DomainEvent::AgentRegistered { name, timestamp } => ("system".to_string(), name.clone(), Value::Null, *timestamp),
+            DomainEvent::ToolExecuted { tool, agent, params, success, timestamp } => (
+                agent.clone().unwrap_or_else(|| "system".to_string()),
+                tool.clone(),
+                json!({ "params": params, "success": success }),
+                *timestamp,
+            ),
+            DomainEvent::TaskStuck { task_id, worker_id, last_heartbeat, timestamp } => (
+                worker_id.clone().unwrap_or_else(|| "system".to_string()),
+                task_id.clone(),
+                json!({ "last_heartbeat": last_heartbeat }),
+                *timestamp,
+            ),
+            DomainEvent::AiProviderFailed { provider, error, timestamp } => (
+                "system".to_string(),
+                provider.clone(),
+                json!({ "error": error }),
+                *timestamp,
+            ),
+        };
+        Self { timestamp, ..Self::new(actor, action, target, details) }
+    }
+}
+
+/// Replace values whose key looks like a credential, and truncate long values
+pub fn redact_params(params: &HashMap<String, String>) -> HashMap<String, String> {
+    params.iter().map(|(name, value)| {
+        let lower = name.to_lowercase();
+        let value = if SENSITIVE_KEYS.iter().any(|key| lower.contains(key)) {
+            REDACTED.to_string()
+        } else if value.chars().count() > MAX_PARAM_LEN {
+            format!("{}...", value.chars().take(MAX_PARAM_LEN).collect::<String>())
+        } else {
+            value.clone()
+        };
+        (name.clone(), value)
+    }).collect()
+}
+
+/// Filters for reading the log. Results are newest first.
+#[derive(Debug, Clone, Default, Deserialize, IntoParams)]
+pub struct AuditQuery {
+    pub actor: Option<String>,
+    pub action: Option<String>,
+    pub target: Option<String>,
+    /// Unix timestamp, inclusive
+    pub since: Option<i64>,
+    /// Unix timestamp, inclusive
+    pub until: Option<i64>,
+    /// At most 1000; defaults to 100
+    pub limit: Option<usize>,
+}
+
+impl AuditQuery {
+    fn limit(&self) -> usize {
+        self.limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT)
+    }
+
+    fn matches(&self, entry: &AuditEntry) -> bool {
+        self.actor.as_ref().map_or(true, |actor| &entry.actor == actor)
+            && self.action.as_ref().map_or(true, |action| &entry.action == action)
+            && self.target.as_ref().map_or(true, |target| &entry.target == target)
+            && self.since.map_or(true, |since| entry.timestamp >= since)
+            && self.until.map_or(true, |until| entry.timestamp <= until)
+    }
+
+    fn filter(&self) -> Document {
+        let mut filter = Document::new();
+        for (field, value) in [("actor", &self.actor), ("action", &self.action), ("target", &self.target)] {
+            if let Some(value) = value {
+                filter.insert(field, value);
+            }
+        }
+        let mut timestamp = Document::new();
+        if let Some(since) = self.since {
+            timestamp.insert("$gte", since);
+        }
+        if let Some(until) = self.until {
+            timestamp.insert("$lte", until);
+        }
+        if !timestamp.is_empty() {
+            filter.insert("timestamp", timestamp);
+        }
+        filter
+    }
+}
+
+/// Storage backend for the audit log. There is deliberately no update or delete.
+#[async_trait]
+pub trait AuditStore: Send + Sync {
+    async fn append(&self, entry: &AuditEntry) -> Result<()>;
+    async fn query(&self, query: &AuditQuery) -> Result<Vec<AuditEntry>>;
+}
+
+/// Entries stored in the `audit_log` collection
+pub struct MongoAuditStore {
+    collection: Collection<AuditEntry>,
+}
+
+impl MongoAuditStore {
+    pub async fn new() -> Result<Self> {
+        Self::from_database(&Database::shared().await?).await
+    }
+
+    pub async fn from_database(db: &Database) -> Result<Self> {
+        let collection: Collection<AuditEntry> = db.collection("audit_log");
+        let index = IndexModel::builder().keys(doc! { "timestamp": -1 }).build();
+        collection.create_index(index, None).await?;
+        Ok(Self { collection })
+    }
+}
+
+#[async_trait]
+impl AuditStore for MongoAuditStore {
+    async fn append(&self, entry: &AuditEntry) -> Result<()> {
+        self.collection.insert_one(entry, None).await.map_err(SwarmError::from)?;
+        Ok(())
+    }
+
+    async fn query(&self, query: &AuditQuery) -> Result<Vec<AuditEntry>> {
+        use futures_util::TryStreamExt;
+        let options = FindOptions::builder()
+            .sort(doc! { "timestamp": -1 })
+            .limit(query.limit() as i64)
+            .build();
+        let cursor = self.collection.find(query.filter(), options).await.map_err(SwarmError::from)?;
+        Ok(cursor.try_collect().await.map_err(SwarmError::from)?)
+    }
+}
+
+/// Entries held in memory, for tests and single-process setups
+#[derive(Default)]
+pub struct InMemoryAuditStore {
+    entries: RwLock<Vec<AuditEntry>>,
+}
+
+impl InMemoryAuditStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[async_trait]
+impl AuditStore for InMemoryAuditStore {
+    async fn append(&self, entry: &AuditEntry) -> Result<()> {
+        self.entries.write().await.push(entry.clone());
+        Ok(())
+    }
+
+    async fn query(&self, query: &AuditQuery) -> Result<Vec<AuditEntry>> {
+        let entries = self.entries.read().await;
+        let mut matching: Vec<AuditEntry> = entries.iter().filter(|e| query.matches(e)).cloned().collect();
+        matching.sort_by(|a, b| b.timestamp.cmp(&a.timestamp));
+        matching.truncate(query.limit());
+        Ok(matching)
+    }
+}
+
+pub struct AuditLog {
+    store: Arc<dyn AuditStore>,
+}
+
+impl AuditLog {
+    pub fn new(store: Arc<dyn AuditStore>) -> Self {
+        Self { store }
+    }
+
+    /// Backed by MongoDB when `RTK_MONGO_URI` is set, otherwise held in memory
+    pub async fn from_env() -> Result<Self> {
+        if secrets::get(secrets::MONGO_URI).is_none() {
+            return Ok(Self::new(Arc::new(InMemoryAuditStore::new())));
+        }
+        Ok(Self::new(Arc::new(MongoAuditStore::new().await?)))
+    }
+
+    /// Append an entry. Failures are logged rather than returned, so auditing never
+    /// fails the action being audited.
+    pub async fn record(&self, entry: AuditEntry) {
+        if let Err(e) = self.store.append(&entry).await {
+            tracing::error!("Failed to record audit entry {} on {}: {}", entry.action, entry.target, e);
+        }
+    }
+
+    pub async fn query(&self, query: &AuditQuery) -> Result<Vec<AuditEntry>> {
+        self.store.query(query).await
+    }
+
+    /// Record every event from `bus` in a background task
+    pub fn follow(self: &Arc<Self>, bus: &dyn EventBus) -> JoinHandle<()> {
+        let log = self.clone();
+        let mut events = bus.subscribe();
+        tokio::spawn(async move {
+            while let Some(event) = events.next().await {
+                log.record(AuditEntry::from_event(&event)).await;
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::events::BroadcastEventBus;
+
+    #[test]
+    fn test_redact_params_hides_credentials() {
+        let params = HashMap::from([
+            ("api_key".to_string(), "sk-123".to_string()),
+            ("Password".to_string(), "hunter2".to_string()),
+            ("message".to_string(), "x".repeat(500)),
+            ("path".to_string(), "src/lib.rs".to_string()),
+        ]);
+        let redacted = redact_params(&params);
+        assert_eq!(redacted["api_key"], REDACTED);
+        assert_eq!(redacted["Password"], REDACTED);
+        assert_eq!(redacted["message"].len(), MAX_PARAM_LEN + 3);
+        assert_eq!(redacted["path"], "src/lib.rs");
+    }
+
+    #[tokio::test]
+    async fn test_follow_records_events_and_queries_filter() {
+        let bus = BroadcastEventBus::new();
+        let log = Arc::new(AuditLog::new(Arc::new(InMemoryAuditStore::new())));
+        log.follow(&bus);
+        let params = HashMap::from([("token".to_string(), "abc".to_string())]);
+        bus.publish(DomainEvent::message_routed(Some("greeter"), "haiku"));
+        bus.publish(DomainEvent::tool_executed("git", Some("git"), &params, true));
+        bus.publish(DomainEvent::message_routed(None, "git"));
+
+        while log.query(&AuditQuery::default()).await.unwrap().len() < 3 {
+            tokio::task::yield_now().await;
+        }
+        let transfers = log.query(&AuditQuery { actor: Some("greeter".to_string()), ..Default::default() }).await.unwrap();
+        assert_eq!(transfers.len(), 1);
+        assert_eq!((transfers[0].action.as_str(), transfers[0].target.as_str()), ("message_routed", "haiku"));
+
+        let tools = log.query(&AuditQuery { action: Some("tool_executed".to_string()), ..Default::default() }).await.unwrap();
+        assert_eq!(tools[0].details["params"]["token"], REDACTED);
+        assert_eq!(log.query(&AuditQuery { limit: Some(2), ..Default::default() }).await.unwrap().len(), 2);
+        assert!(log.query(&AuditQuery { since: Some(i64::MAX), ..Default::default() }).await.unwrap().is_empty());
+    }
+}
@@ -0,0 +1,25 @@
+use std::net::SocketAddr;
+use anyhow::Result;
+use clap::Parser;
+use swarmonomicon::{api, grpc};
+use swarmonomicon::logging::Logging;
+
+/// Serve the agent and task gRPC services
+#[derive(Parser)]
+#[command(author, version, about, long_about = None)]
+struct Cli {
+    /// Address to listen on
+    #[arg(short = 'a', long, default_value = "127.0.0.1:50051")]
+    addr: SocketAddr,
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let cli = Cli::parse();
+    Logging::new(tracing::Level::INFO).init();
+
+    let state = api::create_app_state().await;
+    state.shutdown.listen_for_signals();
+    tracing::info!("gRPC server listening on {}", cli.addr);
+    grpc::serve(cli.addr, state).await
+}
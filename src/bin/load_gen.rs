@@ -0,0 +1,140 @@
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+use anyhow::{Result, anyhow};
+use clap::{Parser, Subcommand};
+use serde_json::{Value, json};
+use tokio::sync::Mutex;
+
+/// Send load to a running server and report throughput and latency
+#[derive(Parser)]
+#[command(author, version, about, long_about = None)]
+struct Cli {
+    /// Base URL of the server's API
+    #[arg(short, long, default_value = "http://127.0.0.1:3000")]
+    url: String,
+
+    /// Requests to send in total
+    #[arg(short = 'n', long, default_value_t = 1000)]
+    requests: usize,
+
+    /// Requests in flight at once
+    #[arg(short, long, default_value_t = 16)]
+    concurrency: usize,
+
+    /// API key, if the server requires one
+    #[arg(long)]
+    api_key: Option<String>,
+
+    #[command(subcommand)]
+    command: Commands,
+}
+
+#[derive(Subcommand)]
+enum Commands {
+    /// Messages to an agent, routed like any conversation
+    Messages {
+        #[arg(index = 1, default_value = "greeter")]
+        agent: String,
+    },
+    /// Tasks added to an agent's queue, for the todo workers to process
+    Tasks {
+        #[arg(index = 1, default_value = "user")]
+        agent: String,
+    },
+}
+
+impl Commands {
+    fn path(&self) -> String {
+        match self {
+            Commands::Messages { agent } => format!("/api/agents/{}/message", agent),
+            Commands::Tasks { agent } => format!("/api/agents/{}/tasks", agent),
+        }
+    }
+
+    fn body(&self, i: usize) -> Value {
+        match self {
+            Commands::Messages { .. } => json!({ "content": format!("Load test message {}", i) }),
+            Commands::Tasks { .. } => json!({
+                "description": format!("Load test task {}", i),
+                "priority": "Low",
+                "allow_duplicate": true,
+            }),
+        }
+    }
+}
+
+/// The `p`th percentile of sorted `latencies`
+fn percentile(latencies: &[Duration], p: f64) -> Duration {
+    if latencies.is_empty() {
+        return Duration::ZERO;
+    }
+    let index = ((latencies.len() - 1) as f64 * p).round() as usize;
+    latencies[index]
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let cli = Cli::parse();
+    if cli.concurrency == 0 {
+        return Err(anyhow!("Concurrency must be at least 1"));
+    }
+    let client = reqwest::Client::new();
+    let url = format!("{}{}", cli.url.trim_end_matches('/'), cli.command.path());
+    let command = Arc::new(cli.command);
+    let next = Arc::new(AtomicUsize::new(0));
+    let failures = Arc::new(AtomicUsize::new(0));
+    let latencies = Arc::new(Mutex::new(Vec::with_capacity(cli.requests)));
+
+    println!("Sending {} requests to {} with {} in flight", cli.requests, url, cli.concurrency);
+    let start = Instant::now();
+    let workers: Vec<_> = (0..cli.concurrency).map(|_| {
+        let (client, url, api_key) = (client.clone(), url.clone(), cli.api_key.clone());
+        let (command, next, failures, latencies) = (command.clone(), next.clone(), failures.clone(), latencies.clone());
+        let requests = cli.requests;
+        tokio::spawn(async move {
+            loop {
+                let i = next.fetch_add(1, Ordering::Relaxed);
+                if i >= requests {
+                    break;
+                }
+                let mut request = client.post(&url).json(&command.body(i));
+                if let Some(key) = &api_key {
+                    request = request.bearer_auth(key);
+                }
+                let sent = Instant::now();
+                match request.send().await {
+                    Ok(response) if response.status().is_success() => latencies.lock().await.push(sent.elapsed()),
+                    Ok(response) => {
+                        if failures.fetch_add(1, Ordering::Relaxed) == 0 {
+                            eprintln!("First failure: {}", response.status());
+                        }
+                    }
+                    Err(e) => {
+                        if failures.fetch_add(1, Ordering::Relaxed) == 0 {
+                            eprintln!("First failure: {}", e);
+                        }
+                    }
+                }
+            }
+        })
+    }).collect();
+    for worker in workers {
+        worker.await?;
+    }
+    let elapsed = start.elapsed();
+
+    let mut latencies = latencies.lock().await.clone();
+    latencies.sort();
+    let succeeded = latencies.len();
+    println!("{} succeeded, {} failed in {:.2?}", succeeded, failures.load(Ordering::Relaxed), elapsed);
+    println!("{:.1} requests/sec", succeeded as f64 / elapsed.as_secs_f64());
+    println!(
+        "Latency p50 {:.2?}, p90 {:.2?}, p99 {:.2?}, max {:.2?}",
+        percentile(&latencies, 0.5),
+        percentile(&latencies, 0.9),
+        percentile(&latencies, 0.99),
+        latencies.last().copied().unwrap_or_default(),
+    );
+    Ok(())
+}
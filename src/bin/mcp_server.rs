@@ -0,0 +1,54 @@
+use std::net::SocketAddr;
+use std::sync::Arc;
+use anyhow::{Result, bail};
+use clap::Parser;
+use tokio::sync::RwLock;
+use swarmonomicon::agents::AgentRegistry;
+use swarmonomicon::api::{auth::ApiAuth, default_agents};
+use swarmonomicon::logging::Logging;
+use swarmonomicon::mcp::{self, McpServer};
+use swarmonomicon::tools::ToolRegistry;
+
+/// Expose the swarm's tools and agents to MCP clients
+#[derive(Parser)]
+#[command(author, version, about, long_about = None)]
+struct Cli {
+    /// Serve the SSE transport on this address instead of stdio
+    #[arg(long, num_args = 0..=1, default_missing_value = "127.0.0.1:3100")]
+    sse: Option<SocketAddr>,
+
+    /// Tools to expose, comma-separated; every registered tool when unset
+    #[arg(long, value_delimiter = ',')]
+    tools: Vec<String>,
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let cli = Cli::parse();
+
+    // stdout carries the protocol in stdio mode, so logs go to stderr
+    Logging::new(tracing::Level::INFO).with_stderr().init();
+
+    let tools = ToolRegistry::create_default_tools().await?;
+    let names = if cli.tools.is_empty() { tools.tool_names() } else { cli.tools };
+    let toolset = McpServer::toolset(&names, Arc::new(RwLock::new(tools)));
+    let agents = AgentRegistry::create_default_agents(default_agents()).await?;
+    let server = Arc::new(McpServer::new(toolset, Arc::new(RwLock::new(agents))));
+
+    match cli.sse {
+        Some(addr) => {
+            let auth = ApiAuth::from_env().await?.map(Arc::new);
+            if auth.is_none() && !addr.ip().is_loopback() {
+                bail!("Serving MCP on {} needs API keys; set API_KEYS_REQUIRED=true", addr);
+            }
+            tracing::info!("MCP server listening on http://{}/sse", addr);
+            let listener = tokio::net::TcpListener::bind(addr).await?;
+            axum::serve(listener, mcp::sse_router(server, auth)).await?;
+        }
+        None => {
+            tracing::info!("MCP server reading from stdin");
+            mcp::serve_stdio(&server).await?;
+        }
+    }
+    Ok(())
+}
@@ -1,11 +1,14 @@
 use std::time::Duration;
 use std::collections::HashMap;
 use swarmonomicon::types::{TodoTask, TaskPriority, TaskStatus};
-use swarmonomicon::tools::todo::TodoTool;
-use swarmonomicon::tools::ToolExecutor;
-use rumqttc::{MqttOptions, AsyncClient, QoS, Event};
+use swarmonomicon::tools::todo::{TodoRequest, TodoTool, enhancement_sweep_interval};
+use swarmonomicon::ingest::{IngestConfig, IngestPipeline};
+use swarmonomicon::correlation;
+use swarmonomicon::logging::Logging;
+use rumqttc::{AsyncClient, Event};
+use swarmonomicon::config::mqtt::{self, MqttConfig};
 use serde::{Deserialize, Serialize};
-use tokio::{task, time, sync::Semaphore};
+use tokio::time;
 use std::error::Error as StdError;
 use std::process::Command;
 use anyhow::{Result, anyhow};
@@ -19,12 +22,14 @@ use std::sync::atomic::{AtomicU64, Ordering};
 struct McpTodoRequest {
     description: String,
     priority: Option<TaskPriority>,
+    /// Lets a publisher resend the todo without it being added twice
+    #[serde(default)]
+    idempotency_key: Option<String>,
+    /// Add it even if an identical todo was added recently
+    #[serde(default)]
+    allow_duplicate: bool,
 }
 
-// Maximum number of concurrent task processing
-const MAX_CONCURRENT_TASKS: usize = 1;
-// Maximum number of concurrent AI enhancements
-const MAX_CONCURRENT_AI: usize = 1;
 // Task metrics reporting interval
 const METRICS_REPORTING_INTERVAL: u64 = 300;
 
@@ -75,34 +80,29 @@ impl TaskMetrics {
 #[tokio::main]
 async fn main() -> Result<()> {
     // Initialize logging with more verbose output
-    tracing_subscriber::fmt()
-        .with_max_level(tracing::Level::DEBUG)
-        .init();
+    Logging::new(tracing::Level::DEBUG).init();
 
     // Initialize TodoTool
     let todo_tool = Arc::new(TodoTool::new().await.map_err(|e| anyhow!("Failed to initialize TodoTool: {}", e))?);
+    // Pick up todos a previous run stored but never enhanced
+    todo_tool.start_enhancement_sweep(enhancement_sweep_interval());
 
-    // Create semaphores for rate limiting
-    let task_semaphore = Arc::new(Semaphore::new(MAX_CONCURRENT_TASKS));
-    let ai_semaphore = Arc::new(Semaphore::new(MAX_CONCURRENT_AI));
+    // Bounded enhancement and storage queues, so a burst can't pile up model calls
+    let ingest = IngestPipeline::start(todo_tool.clone(), IngestConfig::from_env()?);
 
     // Initialize metrics
     let metrics = Arc::new(TaskMetrics::new());
 
-    let aws_ip = std::env::var("AWSIP").expect("AWSIP environment variable not set");
-    let aws_port = std::env::var("AWSPORT").expect("AWSPORT environment variable not set").parse::<u16>().expect("AWSPORT must be a number");
-
     // Connect to MQTT broker
-    let mut mqtt_options = MqttOptions::new("mcp_todo_server", &aws_ip, aws_port);
-    mqtt_options.set_keep_alive(Duration::from_secs(30));
-    mqtt_options.set_clean_session(true);
+    let mqtt_config = MqttConfig::from_env()?;
+    let mqtt_options = mqtt_config.options(&mqtt_config.client_id("mcp_todo_server"))?;
     let (client, mut event_loop) = AsyncClient::new(mqtt_options, 10);
     let client = Arc::new(client);
-    tracing::info!("Connecting to MQTT broker at {}:{}", aws_ip, aws_port);
+    tracing::info!("Connecting to MQTT broker at {}:{}", mqtt_config.host, mqtt_config.port);
 
     // Subscribe to mcp/+ topic with retry logic
     for attempt in 1..=3 {
-        match client.subscribe("mcp/+", QoS::ExactlyOnce).await {
+        match client.subscribe("mcp/+", mqtt::qos()).await {
             Ok(_) => {
                 tracing::info!("Successfully subscribed to mcp/+");
                 break;
@@ -118,7 +118,7 @@ async fn main() -> Result<()> {
     }
 
     // // Also subscribe to control topic
-    // client.subscribe("mcp_server/control", QoS::ExactlyOnce).await
+    // client.subscribe("mcp_server/control", mqtt::qos()).await
     //     .map_err(|e| anyhow!("Failed to subscribe to control topic: {}", e))?;
 
     tracing::info!("MCP Todo Server started. Listening for new tasks...");
@@ -126,16 +126,21 @@ async fn main() -> Result<()> {
     // Setup metrics reporting task
     let metrics_client = client.clone();
     let metrics_cloned = metrics.clone();
+    let metrics_ingest = ingest.clone();
+    let metrics_todo_tool = todo_tool.clone();
     let metrics_reporter = tokio::spawn(async move {
         let mut interval = tokio::time::interval(Duration::from_secs(METRICS_REPORTING_INTERVAL));
         loop {
             interval.tick().await;
 
-            // Report metrics
-            let metrics_json = metrics_cloned.as_json();
+            // Report metrics, with the depth of each ingest queue
+            let mut metrics_json = metrics_cloned.as_json();
+            let mut stages = metrics_ingest.metrics();
+            stages.extend(metrics_todo_tool.enrichment_metrics());
+            metrics_json["ingest"] = json!(stages);
             let _ = metrics_client.publish(
                 "metrics/response/mcp_todo_server",
-                QoS::ExactlyOnce,
+                mqtt::qos(),
                 false,
                 metrics_json.to_string()
             ).await;
@@ -173,7 +178,7 @@ async fn main() -> Result<()> {
 
                     if let Err(e) = client.publish(
                         "response/mcp_server/status",
-                        QoS::ExactlyOnce,
+                        mqtt::qos(),
                         false,
                         shutdown_payload
                     ).await {
@@ -218,7 +223,7 @@ async fn main() -> Result<()> {
 
                                             if let Err(e) = client.publish(
                                                 "response/mcp_server/status",
-                                                QoS::ExactlyOnce,
+                                                mqtt::qos(),
                                                 false,
                                                 status_payload
                                             ).await {
@@ -232,101 +237,88 @@ async fn main() -> Result<()> {
 
                             // Handle normal MCP task requests
                             if topic.starts_with("mcp/") {
-                                tracing::info!("Received payload on {}: {}", topic, payload);
-
-                                // Increment the task received counter
-                                let task_count = metrics.increment_received();
-                                tracing::debug!("Task count: {}", task_count);
-
-                                // Clone necessary Arc's for the task
-                                let todo_tool = todo_tool.clone();
-                                let task_semaphore = task_semaphore.clone();
-                                let ai_semaphore = ai_semaphore.clone();
-                                let metrics = metrics.clone();
-                                let client = client.clone();
-
-                                // Spawn a new task to handle this request
-                                tokio::spawn(async move {
-                                    // Acquire task processing permit
-                                    let _task_permit = match task_semaphore.acquire().await {
-                                        Ok(permit) => permit,
-                                        Err(e) => {
-                                            tracing::error!("Failed to acquire task permit: {}", e);
-                                            metrics.increment_failed();
-                                            return;
-                                        }
-                                    };
+                                let correlation_id = correlation::from_payload(&payload);
+                                correlation::scope(correlation_id.clone(), "mqtt", async {
+                                    tracing::info!("Received payload on {}: {}", topic, payload);
+
+                                    // Increment the task received counter
+                                    let task_count = metrics.increment_received();
+                                    tracing::debug!("Task count: {}", task_count);
 
                                     // Try to parse as McpTodoRequest, if fails treat as plain text
-                                    let description = match serde_json::from_str::<McpTodoRequest>(&payload) {
-                                        Ok(request) => request.description,
-                                        Err(_) => payload,
+                                    let (description, idempotency_key, allow_duplicate) = match serde_json::from_str::<McpTodoRequest>(&payload) {
+                                        Ok(request) => (request.description, request.idempotency_key, request.allow_duplicate),
+                                        Err(_) => (payload, None, false),
                                     };
-
-                                    let target_agent = topic.split('/').nth(1).unwrap_or("user");
-
-                                    // Add todo using TodoTool
-                                    let mut params = HashMap::new();
-                                    params.insert("command".to_string(), "add".to_string());
-                                    params.insert("description".to_string(), description.clone());
-                                    params.insert("context".to_string(), "mcp_server".to_string());
-                                    params.insert("target_agent".to_string(), target_agent.to_string());
-
-                                    // Acquire AI enhancement permit before processing
-                                    let _ai_permit = match ai_semaphore.acquire().await {
-                                        Ok(permit) => permit,
-                                        Err(e) => {
-                                            tracing::error!("Failed to acquire AI permit: {}", e);
-                                            metrics.increment_failed();
-                                            return;
-                                        }
+                                    let target_agent = topic.split('/').nth(1).unwrap_or("user").to_string();
+                                    let request = TodoRequest {
+                                        description: description.clone(),
+                                        context: Some("mcp_server".to_string()),
+                                        target_agent: target_agent.clone(),
+                                        idempotency_key,
+                                        allow_duplicate,
+                                        ..Default::default()
                                     };
 
-                                    match todo_tool.execute(params).await {
-                                        Ok(result) => {
-                                            tracing::info!("Successfully added todo: {}", description);
-                                            metrics.increment_processed();
-
-                                            // Publish success response
-                                            let response_topic = format!("response/{}/todo", target_agent);
-                                            let response_payload = json!({
-                                                "status": "success",
-                                                "message": result,
-                                                "timestamp": chrono::Utc::now().to_rfc3339()
-                                            }).to_string();
-
-                                            if let Err(e) = client.publish(
-                                                response_topic,
-                                                QoS::ExactlyOnce,
-                                                false,
-                                                response_payload
-                                            ).await {
-                                                tracing::error!("Failed to publish success response: {}", e);
-                                            }
-                                        },
-                                        Err(e) => {
-                                            tracing::error!("Failed to add todo: {}", e);
-                                            metrics.increment_failed();
-
-                                            // Publish error response
-                                            let error_topic = format!("response/{}/error", target_agent);
-                                            let error_payload = json!({
-                                                "status": "error",
-                                                "error": e.to_string(),
-                                                "timestamp": chrono::Utc::now().to_rfc3339()
-                                            }).to_string();
-
-                                            if let Err(e) = client.publish(
-                                                error_topic,
-                                                QoS::ExactlyOnce,
-                                                false,
-                                                error_payload
-                                            ).await {
-                                                tracing::error!("Failed to publish error response: {}", e);
+                                    // Waits while a blocking enhancement queue is full, holding back the broker
+                                    let submitted = ingest.submit(request).await;
+                                    let metrics = metrics.clone();
+                                    let client = client.clone();
+
+                                    // Publish the outcome once the task has been through the pipeline
+                                    correlation::spawn(async move {
+                                        let outcome = match submitted {
+                                            Ok(result) => result.await.unwrap_or_else(|_| Err(anyhow!("The ingest pipeline stopped"))),
+                                            Err(e) => Err(e),
+                                        };
+                                        match outcome {
+                                            Ok(result) => {
+                                                tracing::info!("Successfully added todo: {}", description);
+                                                metrics.increment_processed();
+
+                                                // Publish success response
+                                                let response_topic = format!("response/{}/todo", target_agent);
+                                                let response_payload = json!({
+                                                    "status": "success",
+                                                    "correlation_id": correlation_id,
+                                                    "message": result,
+                                                    "timestamp": chrono::Utc::now().to_rfc3339()
+                                                }).to_string();
+
+                                                if let Err(e) = client.publish(
+                                                    response_topic,
+                                                    mqtt::qos(),
+                                                    false,
+                                                    response_payload
+                                                ).await {
+                                                    tracing::error!("Failed to publish success response: {}", e);
+                                                }
+                                            },
+                                            Err(e) => {
+                                                tracing::error!("Failed to add todo: {}", e);
+                                                metrics.increment_failed();
+
+                                                // Publish error response
+                                                let error_topic = format!("response/{}/error", target_agent);
+                                                let error_payload = json!({
+                                                    "status": "error",
+                                                    "correlation_id": correlation_id,
+                                                    "error": e.to_string(),
+                                                    "timestamp": chrono::Utc::now().to_rfc3339()
+                                                }).to_string();
+
+                                                if let Err(e) = client.publish(
+                                                    error_topic,
+                                                    mqtt::qos(),
+                                                    false,
+                                                    error_payload
+                                                ).await {
+                                                    tracing::error!("Failed to publish error response: {}", e);
+                                                }
                                             }
                                         }
-                                    }
-                                });
+                                    });
+                                }).await;
                             }
                         }
                     }
@@ -1,10 +1,16 @@
 use std::time::Duration;
 use std::collections::HashMap;
 use swarmonomicon::types::{TodoTask, TaskPriority, TaskStatus};
-use swarmonomicon::tools::{TodoTool, ToolExecutor};
-use rumqttc::{MqttOptions, AsyncClient, QoS, Event};
+use swarmonomicon::tools::todo::{TodoTool, enhancement_sweep_interval};
+use swarmonomicon::tools::todo::{PreparedTodo, TodoRequest};
+use swarmonomicon::ingest::{Ingest, IngestConfig, IngestPipeline};
+use swarmonomicon::correlation;
+use swarmonomicon::logging::Logging;
+use async_trait::async_trait;
+use rumqttc::{AsyncClient, Event};
+use swarmonomicon::config::mqtt::{self, MqttConfig};
 use serde::{Deserialize, Serialize};
-use tokio::{task, time, sync::Semaphore};
+use tokio::time;
 use std::error::Error as StdError;
 use std::process::Command;
 use anyhow::{Result, anyhow};
@@ -19,6 +25,12 @@ use uuid::Uuid;
 struct McpTodoRequest {
     description: String,
     priority: Option<TaskPriority>,
+    /// Lets a publisher resend the todo without it being added twice
+    #[serde(default)]
+    idempotency_key: Option<String>,
+    /// Add it even if an identical todo was added recently
+    #[serde(default)]
+    allow_duplicate: bool,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -36,10 +48,6 @@ struct ProjectClassificationResponse {
     reasoning: Option<String>,
 }
 
-// Maximum number of concurrent task processing
-const MAX_CONCURRENT_TASKS: usize = 1;
-// Maximum number of concurrent AI enhancements
-const MAX_CONCURRENT_AI: usize = 1;
 // Task metrics reporting interval
 const METRICS_REPORTING_INTERVAL: u64 = 300;
 // Project classification timeout
@@ -109,37 +117,65 @@ impl TaskMetrics {
     }
 }
 
+/// Classifies each todo's project before TodoTool enhances and stores it
+struct Intake {
+    todo_tool: Arc<TodoTool>,
+    client: Arc<AsyncClient>,
+    metrics: Arc<TaskMetrics>,
+}
+
+/// A todo with its project, once the project worker has classified it
+struct ClassifiedTodo {
+    project: String,
+    todo: PreparedTodo,
+}
+
+#[async_trait]
+impl Ingest for Intake {
+    type Request = TodoRequest;
+    type Prepared = ClassifiedTodo;
+    type Output = (String, String);
+
+    async fn enhance(&self, mut request: TodoRequest) -> Result<ClassifiedTodo> {
+        let project = classify_project(&self.client, &self.metrics, &request.description, &request.target_agent).await?;
+        request.project = Some(project.clone());
+        let todo = self.todo_tool.prepare_todo(request).await?;
+        Ok(ClassifiedTodo { project, todo })
+    }
+
+    async fn store(&self, classified: ClassifiedTodo) -> Result<(String, String)> {
+        let message = self.todo_tool.store_todo(classified.todo).await?;
+        Ok((classified.project, message))
+    }
+}
+
 #[tokio::main]
 async fn main() -> Result<()> {
     // Initialize logging with more verbose output
-    tracing_subscriber::fmt()
-        .with_max_level(tracing::Level::DEBUG)
-        .init();
+    Logging::new(tracing::Level::DEBUG).init();
 
     // Initialize TodoTool - now using MCP server HTTP calls internally
     let todo_tool = Arc::new(TodoTool::new().await.map_err(|e| anyhow!("Failed to initialize TodoTool: {}", e))?);
-
-    // Create semaphores for rate limiting
-    let task_semaphore = Arc::new(Semaphore::new(MAX_CONCURRENT_TASKS));
-    let ai_semaphore = Arc::new(Semaphore::new(MAX_CONCURRENT_AI));
+    // Pick up todos a previous run stored but never enhanced
+    todo_tool.start_enhancement_sweep(enhancement_sweep_interval());
 
     // Initialize metrics
     let metrics = Arc::new(TaskMetrics::new());
 
-    let aws_ip = std::env::var("AWSIP").expect("AWSIP environment variable not set");
-    let aws_port = std::env::var("AWSPORT").expect("AWSPORT environment variable not set").parse::<u16>().expect("AWSPORT must be a number");
-
     // Connect to MQTT broker
-    let mut mqtt_options = MqttOptions::new("mqtt_intake", &aws_ip, aws_port);
-    mqtt_options.set_keep_alive(Duration::from_secs(30));
-    mqtt_options.set_clean_session(true);
+    let mqtt_config = MqttConfig::from_env()?;
+    let mqtt_options = mqtt_config.options(&mqtt_config.client_id("mqtt_intake"))?;
     let (client, mut event_loop) = AsyncClient::new(mqtt_options, 10);
     let client = Arc::new(client);
-    tracing::info!("Connecting to MQTT broker at {}:{}", aws_ip, aws_port);
+
+    // Bounded classification/enhancement and storage queues, so a burst can't pile up model calls
+    let intake = Intake { todo_tool: todo_tool.clone(), client: client.clone(), metrics: metrics.clone() };
+    let ingest = IngestPipeline::start(Arc::new(intake), IngestConfig::from_env()?);
+    tracing::info!("Connecting to MQTT broker at {}:{}", mqtt_config.host, mqtt_config.port);
 
     // Subscribe to mcp/+ topic with retry logic
     for attempt in 1..=3 {
-        match client.subscribe("mcp/+", QoS::ExactlyOnce).await {
+        match client.subscribe("mcp/+", mqtt::qos()).await {
             Ok(_) => {
                 tracing::info!("Successfully subscribed to mcp/+");
                 break;
@@ -159,16 +195,21 @@ async fn main() -> Result<()> {
     // Setup metrics reporting task
     let metrics_client = client.clone();
     let metrics_cloned = metrics.clone();
+    let metrics_ingest = ingest.clone();
+    let metrics_todo_tool = todo_tool.clone();
     let metrics_reporter = tokio::spawn(async move {
         let mut interval = tokio::time::interval(Duration::from_secs(METRICS_REPORTING_INTERVAL));
         loop {
             interval.tick().await;
 
-            // Report metrics
-            let metrics_json = metrics_cloned.as_json();
+            // Report metrics, with the depth of each ingest queue
+            let mut metrics_json = metrics_cloned.as_json();
+            let mut stages = metrics_ingest.metrics();
+            stages.extend(metrics_todo_tool.enrichment_metrics());
+            metrics_json["ingest"] = json!(stages);
             let _ = metrics_client.publish(
                 "metrics/response/mqtt_intake",
-                QoS::ExactlyOnce,
+                mqtt::qos(),
                 false,
                 metrics_json.to_string()
             ).await;
@@ -206,7 +247,7 @@ async fn main() -> Result<()> {
 
                     if let Err(e) = client.publish(
                         "response/mcp_server/status",
-                        QoS::ExactlyOnce,
+                        mqtt::qos(),
                         false,
                         shutdown_payload
                     ).await {
@@ -251,7 +292,7 @@ async fn main() -> Result<()> {
 
                                             if let Err(e) = client.publish(
                                                 "response/mcp_server/status",
-                                                QoS::ExactlyOnce,
+                                                mqtt::qos(),
                                                 false,
                                                 status_payload
                                             ).await {
@@ -265,164 +306,89 @@ async fn main() -> Result<()> {
 
                             // Handle normal MCP task requests
                             if topic.starts_with("mcp/") {
-                                tracing::info!("Received payload on {}: {}", topic, payload);
-
-                                // Increment the task received counter
-                                let task_count = metrics.increment_received();
-                                tracing::debug!("Task count: {}", task_count);
-
-                                // Clone necessary Arc's for the task
-                                let task_semaphore = task_semaphore.clone();
-                                let ai_semaphore = ai_semaphore.clone();
-                                let metrics = metrics.clone();
-                                let client = client.clone();
-                                let todo_tool = todo_tool.clone();
-
-                                // Spawn a new task to handle this request
-                                tokio::spawn(async move {
-                                    // Acquire task processing permit
-                                    let _task_permit = match task_semaphore.acquire().await {
-                                        Ok(permit) => permit,
-                                        Err(e) => {
-                                            tracing::error!("Failed to acquire task permit: {}", e);
-                                            metrics.increment_failed();
-                                            return;
-                                        }
-                                    };
+                                let correlation_id = correlation::from_payload(&payload);
+                                correlation::scope(correlation_id.clone(), "mqtt", async {
+                                    tracing::info!("Received payload on {}: {}", topic, payload);
+
+                                    // Increment the task received counter
+                                    let task_count = metrics.increment_received();
+                                    tracing::debug!("Task count: {}", task_count);
 
                                     // Try to parse as McpTodoRequest, if fails treat as plain text
-                                    let description = match serde_json::from_str::<McpTodoRequest>(&payload) {
-                                        Ok(request) => request.description,
-                                        Err(_) => payload, // Default priority for plain text
+                                    let (description, idempotency_key, allow_duplicate) = match serde_json::from_str::<McpTodoRequest>(&payload) {
+                                        Ok(request) => (request.description, request.idempotency_key, request.allow_duplicate),
+                                        Err(_) => (payload, None, false), // Default priority for plain text
                                     };
-
-                                    let target_agent = topic.split('/').nth(1).unwrap_or("user");
-
-                                    // Request project classification from project worker
-                                    let request_id = Uuid::new_v4().to_string();
-                                    let classification_request = ProjectClassificationRequest {
+                                    let target_agent = topic.split('/').nth(1).unwrap_or("user").to_string();
+                                    let request = TodoRequest {
                                         description: description.clone(),
-                                        request_id: Some(request_id.clone()),
-                                        context: Some({
-                                            let mut context = HashMap::new();
-                                            context.insert("source".to_string(), "mqtt_intake".to_string());
-                                            context.insert("target_agent".to_string(), target_agent.to_string());
-                                            context
-                                        }),
+                                        context: Some("mqtt_intake".to_string()),
+                                        target_agent: target_agent.clone(),
+                                        idempotency_key,
+                                        allow_duplicate,
+                                        ..Default::default()
                                     };
 
-                                    metrics.increment_classification_requested();
-
-                                    // Subscribe to classification response topic with request ID
-                                    let response_topic = format!("response/project/classify/{}", request_id);
-                                    let subscription_client = client.clone();
-                                    if let Err(e) = subscription_client.subscribe(&response_topic, QoS::ExactlyOnce).await {
-                                        tracing::error!("Failed to subscribe to classification response topic: {}", e);
-                                        metrics.increment_failed();
-                                        return;
-                                    }
-
-                                    // Publish classification request
-                                    let classification_payload = serde_json::to_string(&classification_request)
-                                        .unwrap_or_else(|_| description.clone());
-
-                                    if let Err(e) = client.publish(
-                                        "project/classify",
-                                        QoS::ExactlyOnce,
-                                        false,
-                                        classification_payload
-                                    ).await {
-                                        tracing::error!("Failed to publish classification request: {}", e);
-                                        metrics.increment_failed();
-                                        return;
-                                    }
-
-                                    // Wait for project classification response with timeout
-                                    let project_name = match tokio::time::timeout(
-                                        Duration::from_secs(PROJECT_CLASSIFICATION_TIMEOUT),
-                                        wait_for_project_classification(&client, &request_id)
-                                    ).await {
-                                        Ok(Ok(response)) => {
-                                            metrics.increment_classification_successful();
-                                            tracing::info!("Received project classification: {} -> {}",
-                                                description, response.project_name);
-                                            response.project_name
-                                        },
-                                        Ok(Err(e)) => {
-                                            tracing::warn!("Project classification failed: {}. Using default.", e);
-                                            "madness_interactive".to_string()
-                                        },
-                                        Err(_) => {
-                                            tracing::warn!("Project classification timed out. Using default.");
-                                            "madness_interactive".to_string()
-                                        }
-                                    };
-
-                                    // Acquire AI enhancement permit before processing
-                                    let _ai_permit = match ai_semaphore.acquire().await {
-                                        Ok(permit) => permit,
-                                        Err(e) => {
-                                            tracing::error!("Failed to acquire AI permit: {}", e);
-                                            metrics.increment_failed();
-                                            return;
-                                        }
-                                    };
-
-                                    // Use TodoTool to add the todo - it will handle MCP server calls internally
-                                    let mut params = HashMap::new();
-                                    params.insert("command".to_string(), "add".to_string());
-                                    params.insert("description".to_string(), description.clone());
-                                    params.insert("context".to_string(), "mqtt_intake".to_string());
-                                    params.insert("target_agent".to_string(), target_agent.to_string());
-                                    params.insert("project".to_string(), project_name.clone());
-
-                                    match todo_tool.execute(params).await {
-                                        Ok(result) => {
-                                            tracing::info!("Successfully added todo: {} (project: {})", description, project_name);
-                                            metrics.increment_processed();
-
-                                            // Publish success response
-                                            let response_topic = format!("response/{}/todo", target_agent);
-                                            let response_payload = json!({
-                                                "status": "success",
-                                                "message": result,
-                                                "project": project_name,
-                                                "timestamp": chrono::Utc::now().to_rfc3339()
-                                            }).to_string();
-
-                                            if let Err(e) = client.publish(
-                                                response_topic,
-                                                QoS::ExactlyOnce,
-                                                false,
-                                                response_payload
-                                            ).await {
-                                                tracing::error!("Failed to publish success response: {}", e);
-                                            }
-                                        },
-                                        Err(e) => {
-                                            tracing::error!("Failed to add todo: {}", e);
-                                            metrics.increment_failed();
-
-                                            // Publish error response
-                                            let error_topic = format!("response/{}/error", target_agent);
-                                            let error_payload = json!({
-                                                "status": "error",
-                                                "error": e.to_string(),
-                                                "project": project_name,
-                                                "timestamp": chrono::Utc::now().to_rfc3339()
-                                            }).to_string();
-
-                                            if let Err(e) = client.publish(
-                                                error_topic,
-                                                QoS::ExactlyOnce,
-                                                false,
-                                                error_payload
-                                            ).await {
-                                                tracing::error!("Failed to publish error response: {}", e);
+                                    // Waits while a blocking enhancement queue is full, holding back the broker
+                                    let submitted = ingest.submit(request).await;
+                                    let metrics = metrics.clone();
+                                    let client = client.clone();
+
+                                    // Publish the outcome once the todo has been through the pipeline
+                                    correlation::spawn(async move {
+                                        let outcome = match submitted {
+                                            Ok(result) => result.await.unwrap_or_else(|_| Err(anyhow!("The ingest pipeline stopped"))),
+                                            Err(e) => Err(e),
+                                        };
+                                        match outcome {
+                                            Ok((project_name, result)) => {
+                                                tracing::info!("Successfully added todo: {} (project: {})", description, project_name);
+                                                metrics.increment_processed();
+
+                                                // Publish success response
+                                                let response_topic = format!("response/{}/todo", target_agent);
+                                                let response_payload = json!({
+                                                    "status": "success",
+                                                    "correlation_id": correlation_id,
+                                                    "message": result,
+                                                    "project": project_name,
+                                                    "timestamp": chrono::Utc::now().to_rfc3339()
+                                                }).to_string();
+
+                                                if let Err(e) = client.publish(
+                                                    response_topic,
+                                                    mqtt::qos(),
+                                                    false,
+                                                    response_payload
+                                                ).await {
+                                                    tracing::error!("Failed to publish success response: {}", e);
+                                                }
+                                            },
+                                            Err(e) => {
+                                                tracing::error!("Failed to add todo: {}", e);
+                                                metrics.increment_failed();
+
+                                                // Publish error response
+                                                let error_topic = format!("response/{}/error", target_agent);
+                                                let error_payload = json!({
+                                                    "status": "error",
+                                                    "correlation_id": correlation_id,
+                                                    "error": e.to_string(),
+                                                    "timestamp": chrono::Utc::now().to_rfc3339()
+                                                }).to_string();
+
+                                                if let Err(e) = client.publish(
+                                                    error_topic,
+                                                    mqtt::qos(),
+                                                    false,
+                                                    error_payload
+                                                ).await {
+                                                    tracing::error!("Failed to publish error response: {}", e);
+                                                }
                                             }
                                         }
-                                    }
-                                });
+                                    });
+                                }).await;
                             }
                         }
                     }
@@ -438,37 +404,87 @@ async fn main() -> Result<()> {
     Ok(())
 }
 
+/// Ask the project worker which project `description` belongs to, falling back to the
+/// default project if it doesn't answer in time
+async fn classify_project(
+    client: &Arc<AsyncClient>,
+    metrics: &TaskMetrics,
+    description: &str,
+    target_agent: &str
+) -> Result<String> {
+    // Request project classification from project worker
+    let request_id = Uuid::new_v4().to_string();
+    let classification_request = ProjectClassificationRequest {
+        description: description.to_string(),
+        request_id: Some(request_id.clone()),
+        context: Some({
+            let mut context = HashMap::new();
+            context.insert("source".to_string(), "mqtt_intake".to_string());
+            context.insert("target_agent".to_string(), target_agent.to_string());
+            context
+        }),
+    };
+
+    metrics.increment_classification_requested();
+
+    // Subscribe to classification response topic with request ID
+    let response_topic = format!("response/project/classify/{}", request_id);
+    client.subscribe(&response_topic, mqtt::qos()).await
+        .map_err(|e| anyhow!("Failed to subscribe to classification response topic: {}", e))?;
+
+    // Publish classification request
+    let classification_payload = serde_json::to_string(&classification_request)
+        .unwrap_or_else(|_| description.to_string());
+
+    client.publish(
+        "project/classify",
+        mqtt::qos(),
+        false,
+        classification_payload
+    ).await
+        .map_err(|e| anyhow!("Failed to publish classification request: {}", e))?;
+
+    // Wait for project classification response with timeout
+    let project_name = match tokio::time::timeout(
+        Duration::from_secs(PROJECT_CLASSIFICATION_TIMEOUT),
+        wait_for_project_classification(client, &request_id)
+    ).await {
+        Ok(Ok(response)) => {
+            metrics.increment_classification_successful();
+            tracing::info!("Received project classification: {} -> {}",
+                description, response.project_name);
+            response.project_name
+        },
+        Ok(Err(e)) => {
+            tracing::warn!("Project classification failed: {}. Using default.", e);
+            "madness_interactive".to_string()
+        },
+        Err(_) => {
+            tracing::warn!("Project classification timed out. Using default.");
+            "madness_interactive".to_string()
+        }
+    };
+    Ok(project_name)
+}
+
 /// Wait for project classification response from project worker
 async fn wait_for_project_classification(
     client: &Arc<AsyncClient>,
     request_id: &str
 ) -> Result<ProjectClassificationResponse> {
-    use rumqttc::EventLoop;
     use std::sync::Arc;
 
     // Create a new event loop to listen specifically for our response
-    let aws_ip = std::env::var("AWSIP").expect("AWSIP environment variable not set");
-    let aws_port = std::env::var("AWSPORT")
-        .expect("AWSPORT environment variable not set")
-        .parse::<u16>()
-        .expect("AWSPORT must be a number");
-
-    let mut mqtt_options = MqttOptions::new(
-        format!("classification_waiter_{}", request_id),
-        &aws_ip,
-        aws_port
-    );
-    mqtt_options.set_keep_alive(Duration::from_secs(30));
-    mqtt_options.set_clean_session(true);
+    let mqtt_options = MqttConfig::from_env()?.options(&format!("classification_waiter_{}", request_id))?;
 
     let (temp_client, mut temp_event_loop) = AsyncClient::new(mqtt_options, 10);
 
     // Subscribe to our specific response topic
     let response_topic = format!("response/project/classify/{}", request_id);
-    temp_client.subscribe(&response_topic, QoS::ExactlyOnce).await?;
+    temp_client.subscribe(&response_topic, mqtt::qos()).await?;
 
     // Also subscribe to general response topic as fallback
-    temp_client.subscribe("response/project/classify", QoS::ExactlyOnce).await?;
+    temp_client.subscribe("response/project/classify", mqtt::qos()).await?;
 
     // Wait for response
     loop {
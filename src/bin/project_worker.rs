@@ -3,7 +3,9 @@ use std::collections::HashMap;
 use swarmonomicon::agents::project::{ProjectAgent, ProjectClassificationRequest, ProjectClassificationResponse};
 use swarmonomicon::types::{AgentConfig, Message};
 use swarmonomicon::Agent;
-use rumqttc::{MqttOptions, AsyncClient, QoS, Event};
+use swarmonomicon::logging::Logging;
+use rumqttc::{AsyncClient, Event};
+use swarmonomicon::config::mqtt::{self, MqttConfig};
 use serde::{Deserialize, Serialize};
 use tokio::{time, sync::Semaphore};
 use std::error::Error as StdError;
@@ -68,9 +70,7 @@ const METRICS_REPORTING_INTERVAL: u64 = 300; // 5 minutes
 #[tokio::main]
 async fn main() -> Result<()> {
     // Initialize logging
-    tracing_subscriber::fmt()
-        .with_max_level(tracing::Level::INFO)
-        .init();
+    Logging::new(tracing::Level::INFO).init();
 
     // Initialize ProjectAgent
     let project_config = AgentConfig {
@@ -81,6 +81,10 @@ async fn main() -> Result<()> {
         downstream_agents: vec![],
         personality: None,
         state_machine: None,
+        script: None,
+        max_concurrency: None,
+        middleware: None,
+        remote: None,
     };
 
     let project_agent = Arc::new(ProjectAgent::new(project_config).await
@@ -92,23 +96,16 @@ async fn main() -> Result<()> {
     // Initialize metrics
     let metrics = Arc::new(ProjectMetrics::new());
 
-    let aws_ip = std::env::var("AWSIP").expect("AWSIP environment variable not set");
-    let aws_port = std::env::var("AWSPORT")
-        .expect("AWSPORT environment variable not set")
-        .parse::<u16>()
-        .expect("AWSPORT must be a number");
-
     // Connect to MQTT broker
-    let mut mqtt_options = MqttOptions::new("project_worker", &aws_ip, aws_port);
-    mqtt_options.set_keep_alive(Duration::from_secs(30));
-    mqtt_options.set_clean_session(true);
+    let mqtt_config = MqttConfig::from_env()?;
+    let mqtt_options = mqtt_config.options(&mqtt_config.client_id("project_worker"))?;
     let (client, mut event_loop) = AsyncClient::new(mqtt_options, 10);
     let client = Arc::new(client);
-    tracing::info!("Connecting to MQTT broker at {}:{}", aws_ip, aws_port);
+    tracing::info!("Connecting to MQTT broker at {}:{}", mqtt_config.host, mqtt_config.port);
 
     // Subscribe to project classification topic
     for attempt in 1..=3 {
-        match client.subscribe("project/classify", QoS::ExactlyOnce).await {
+        match client.subscribe("project/classify", mqtt::qos()).await {
             Ok(_) => {
                 tracing::info!("Successfully subscribed to project/classify");
                 break;
@@ -124,7 +121,7 @@ async fn main() -> Result<()> {
     }
 
     // Also subscribe to control topic
-    client.subscribe("project_worker/control", QoS::ExactlyOnce).await
+    client.subscribe("project_worker/control", mqtt::qos()).await
         .map_err(|e| anyhow!("Failed to subscribe to control topic: {}", e))?;
 
     tracing::info!("Project Worker started. Listening for classification requests...");
@@ -141,7 +138,7 @@ async fn main() -> Result<()> {
             let metrics_json = metrics_cloned.as_json();
             let _ = metrics_client.publish(
                 "metrics/response/project_worker",
-                QoS::ExactlyOnce,
+                mqtt::qos(),
                 false,
                 metrics_json.to_string()
             ).await;
@@ -179,7 +176,7 @@ async fn main() -> Result<()> {
 
                     if let Err(e) = client.publish(
                         "response/project_worker/status",
-                        QoS::ExactlyOnce,
+                        mqtt::qos(),
                         false,
                         shutdown_payload
                     ).await {
@@ -224,7 +221,7 @@ async fn main() -> Result<()> {
 
                                             if let Err(e) = client.publish(
                                                 "response/project_worker/status",
-                                                QoS::ExactlyOnce,
+                                                mqtt::qos(),
                                                 false,
                                                 status_payload
                                             ).await {
@@ -298,7 +295,7 @@ async fn main() -> Result<()> {
 
                                             if let Err(e) = client.publish(
                                                 response_topic,
-                                                QoS::ExactlyOnce,
+                                                mqtt::qos(),
                                                 false,
                                                 response_payload
                                             ).await {
@@ -321,7 +318,7 @@ async fn main() -> Result<()> {
 
                                             if let Err(e) = client.publish(
                                                 error_topic,
-                                                QoS::ExactlyOnce,
+                                                mqtt::qos(),
                                                 false,
                                                 error_payload
                                             ).await {
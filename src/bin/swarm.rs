@@ -1,10 +1,30 @@
 use clap::{Parser, Subcommand};
 use swarmonomicon::{
     agents::{self, AgentRegistry, TransferService, GitAssistantAgent, HaikuAgent, GreeterAgent},
-    types::{AgentConfig, Message, Agent, TodoProcessor, TodoTask, TaskPriority, TaskStatus},
+    ai::{AiProvider, DefaultAiClient},
+    api,
+    config,
+    correlation,
+    logging::Logging,
+    repl::Repl,
+    tools::{CancellationToken, SyncOptions, ToolRegistry, sync_file},
+    types::tags::parse_tags,
+    types::export::{ExportFilter, ExportFormat, read_tasks, write_tasks},
+    types::{AgentConfig, Message, MessageMetadata, Agent, StateMachine, TodoList, TodoProcessor, TodoTask, TaskPriority, TaskStatus},
+    types::sessions::SESSION_ID_KEY,
+    types::snapshot::Snapshot,
+    types::state_store,
+    types::projects::ProjectRegistry,
+    workflow::{WorkflowEngine, WorkflowRun},
+    knowledge::{self, KnowledgeBase},
     error::Error,
 };
+use std::collections::HashMap;
+use std::env;
+use std::net::SocketAddr;
+use std::path::PathBuf;
 use std::sync::Arc;
+use tokio::process::{Child, Command};
 use tokio::sync::RwLock;
 use std::io::Write;
 use anyhow::{Result, anyhow};
@@ -13,6 +33,8 @@ use uuid::Uuid;
 
 #[cfg(feature = "project-agent")]
 use swarmonomicon::agents::project::ProjectAgent;
+#[cfg(feature = "speech")]
+use swarmonomicon::{speech::{self, Speech}, types::sessions};
 
 #[derive(Parser)]
 #[command(author, version, about, long_about = None)]
@@ -59,6 +81,314 @@ enum Commands {
         /// The message to send
         message: String,
     },
+
+    /// Print an agent's state machine as Graphviz DOT or Mermaid
+    StateMachine {
+        /// Agent whose state machine to render
+        agent: Option<String>,
+
+        /// Render a YAML definition instead of a registered agent
+        #[arg(short = 'f', long)]
+        file: Option<String>,
+
+        /// Output format: dot or mermaid
+        #[arg(long, default_value = "dot")]
+        format: String,
+    },
+
+    /// Start the API server, optionally with the todo worker and MQTT intake
+    Serve {
+        /// Address to listen on
+        #[arg(short = 'a', long, default_value = "127.0.0.1:3000")]
+        addr: SocketAddr,
+
+        /// Also run the todo worker
+        #[arg(long)]
+        worker: bool,
+
+        /// Also run the MQTT intake
+        #[arg(long)]
+        mqtt: bool,
+    },
+
+    /// Talk to agents
+    Agent {
+        #[command(subcommand)]
+        command: AgentCommands,
+    },
+
+    /// Manage the todo list
+    Todo {
+        #[command(subcommand)]
+        command: TodoCommands,
+    },
+
+    /// Interactive chat, starting with the greeter (the default when no command is given)
+    Repl {
+        /// Agent to start with
+        #[arg(short = 'a', long, default_value = "greeter")]
+        agent: String,
+    },
+
+    /// Inspect the agent registry
+    Registry {
+        #[command(subcommand)]
+        command: RegistryCommands,
+    },
+
+    /// Check configuration files
+    Config {
+        #[command(subcommand)]
+        command: ConfigCommands,
+    },
+
+    /// Run the multi-step workflows in WORKFLOWS_CONFIG
+    Workflow {
+        #[command(subcommand)]
+        command: WorkflowCommands,
+    },
+
+    /// Back up or restore tasks, agent state, sessions and projects
+    Snapshot {
+        #[command(subcommand)]
+        command: SnapshotCommands,
+    },
+
+    /// Index project documentation and code for agents to ground answers in
+    Knowledge {
+        #[command(subcommand)]
+        command: KnowledgeCommands,
+    },
+}
+
+#[derive(Subcommand)]
+enum AgentCommands {
+    /// Chat with an agent, following transfers, until 'quit'
+    Chat {
+        /// Agent to start with
+        name: String,
+
+        /// Session id, so stateful agents can resume the conversation later
+        #[arg(short = 's', long)]
+        session: Option<String>,
+    },
+
+    /// Talk to agents hands-free: speak, hear the reply, say 'goodbye' to stop
+    #[cfg(feature = "speech")]
+    Voice {
+        /// Agent to start with
+        #[arg(default_value = "greeter")]
+        name: String,
+
+        /// Session id, so stateful agents can resume the conversation later
+        #[arg(short = 's', long)]
+        session: Option<String>,
+    },
+}
+
+#[derive(Subcommand)]
+enum TodoCommands {
+    /// Add a task
+    Add {
+        description: String,
+
+        /// Agent that should handle the task
+        #[arg(short = 'a', long, default_value = "greeter")]
+        agent: String,
+
+        /// low, medium, high or critical
+        #[arg(short = 'p', long, default_value = "medium")]
+        priority: String,
+
+        #[arg(long)]
+        project: Option<String>,
+
+        /// Enhance the description and predict priority/project/tags with the AI client
+        #[arg(long)]
+        enhance: bool,
+
+        /// Comma-separated tags, e.g. bug,frontend
+        #[arg(short = 't', long)]
+        tags: Option<String>,
+    },
+
+    /// List tasks
+    List {
+        /// Only tasks for this agent
+        #[arg(short = 'a', long)]
+        agent: Option<String>,
+
+        /// Only tasks with this status, e.g. pending or failed
+        #[arg(short = 's', long)]
+        status: Option<String>,
+
+        /// Only tasks with all of these comma-separated tags
+        #[arg(short = 't', long)]
+        tags: Option<String>,
+    },
+
+    /// Mark a task completed
+    Complete {
+        id: String,
+    },
+
+    /// Write tasks to a JSON Lines or CSV file, or stdout
+    Export {
+        /// Defaults to stdout
+        #[arg(short = 'o', long)]
+        output: Option<PathBuf>,
+
+        /// jsonl or csv; defaults to the output's extension, else jsonl
+        #[arg(short = 'f', long)]
+        format: Option<String>,
+
+        #[arg(short = 'a', long)]
+        agent: Option<String>,
+
+        #[arg(long)]
+        project: Option<String>,
+
+        /// e.g. pending or completed
+        #[arg(short = 's', long)]
+        status: Option<String>,
+
+        /// Only tasks with all of these comma-separated tags
+        #[arg(short = 't', long)]
+        tags: Option<String>,
+
+        /// Include archived tasks
+        #[arg(long)]
+        include_archived: bool,
+    },
+
+    /// Add tasks from a file written by `export`, skipping ones already present
+    Import {
+        input: PathBuf,
+
+        /// jsonl or csv; defaults to the file's extension, else jsonl
+        #[arg(short = 'f', long)]
+        format: Option<String>,
+
+        /// Only skip tasks whose id is already present, not repeats of recent tasks
+        #[arg(long)]
+        allow_duplicate: bool,
+
+        /// Report what would be imported without writing anything
+        #[arg(long)]
+        dry_run: bool,
+    },
+
+    /// Sync a TODO.md checklist with the queue: add tasks for new items, tick finished ones
+    Sync {
+        /// The checklist, or a directory holding TODO.md
+        #[arg(default_value = ".")]
+        path: PathBuf,
+
+        /// Agent new tasks are assigned to
+        #[arg(short = 'a', long, default_value = "user")]
+        agent: String,
+
+        #[arg(long)]
+        project: Option<String>,
+
+        /// Report what would change without touching the file or the queue
+        #[arg(long)]
+        dry_run: bool,
+    },
+}
+
+#[derive(Subcommand)]
+enum SnapshotCommands {
+    /// Write everything to a snapshot file, or stdout
+    Create {
+        /// Defaults to stdout
+        #[arg(short = 'o', long)]
+        output: Option<PathBuf>,
+    },
+
+    /// Load a snapshot, replacing tasks, state and projects it also holds
+    Restore {
+        input: PathBuf,
+    },
+}
+
+#[derive(Subcommand)]
+enum KnowledgeCommands {
+    /// Index a project's markdown and source files, re-embedding only what changed
+    Ingest {
+        /// The project's root directory
+        #[arg(default_value = ".")]
+        path: PathBuf,
+
+        /// Defaults to the directory's name
+        #[arg(short = 'p', long)]
+        project: Option<String>,
+    },
+
+    /// Show the passages agents would retrieve for a query
+    Search {
+        query: String,
+
+        /// Defaults to the current directory's name
+        #[arg(short = 'p', long)]
+        project: Option<String>,
+
+        #[arg(short = 'n', long, default_value_t = knowledge::DEFAULT_PASSAGES)]
+        limit: usize,
+    },
+
+    /// Drop everything indexed for a project
+    Forget {
+        project: String,
+    },
+}
+
+#[derive(Subcommand)]
+enum RegistryCommands {
+    /// List the agents the server registers
+    List,
+}
+
+#[derive(Subcommand)]
+enum ConfigCommands {
+    /// Validate agent config files (JSON or YAML) and state machine definitions
+    Validate {
+        #[arg(required = true)]
+        files: Vec<PathBuf>,
+
+        /// Treat the files as state machine definitions
+        #[arg(long)]
+        state_machine: bool,
+    },
+}
+
+#[derive(Subcommand)]
+enum WorkflowCommands {
+    /// List the configured workflows
+    List,
+
+    /// Run a workflow to the end
+    Run {
+        name: String,
+
+        /// Available to step templates as {{ input }}
+        #[arg(short = 'i', long, default_value = "")]
+        input: String,
+
+        /// Task to note the outcome on if the run fails and is rolled back
+        #[arg(long)]
+        task: Option<String>,
+    },
+
+    /// Continue a stopped run from the step it stopped at
+    Resume {
+        id: String,
+    },
+
+    /// Show a run's status and step history
+    Show {
+        id: String,
+    },
 }
 
 async fn initialize_registry() -> Result<AgentRegistry> {
@@ -72,6 +402,10 @@ async fn initialize_registry() -> Result<AgentRegistry> {
         downstream_agents: vec![],
         personality: None,
         state_machine: None,
+        script: None,
+        max_concurrency: None,
+        middleware: None,
+        remote: None,
     });
 
     let haiku = HaikuAgent::new(AgentConfig {
@@ -82,6 +416,10 @@ async fn initialize_registry() -> Result<AgentRegistry> {
         downstream_agents: vec![],
         personality: None,
         state_machine: None,
+        script: None,
+        max_concurrency: None,
+        middleware: None,
+        remote: None,
     });
 
     let greeter = GreeterAgent::new(AgentConfig {
@@ -92,6 +430,10 @@ async fn initialize_registry() -> Result<AgentRegistry> {
         downstream_agents: vec![],
         personality: None,
         state_machine: None,
+        script: None,
+        max_concurrency: None,
+        middleware: None,
+        remote: None,
     });
 
     reg.register("git".to_string(), Box::new(git_assistant)).await
@@ -104,6 +446,388 @@ async fn initialize_registry() -> Result<AgentRegistry> {
     Ok(reg)
 }
 
+async fn handle_state_machine_command(
+    reg: &AgentRegistry,
+    agent: Option<String>,
+    file: Option<String>,
+    format: String,
+) -> Result<()> {
+    let (name, machine) = match (file, agent) {
+        (Some(file), _) => (file.clone(), StateMachine::from_yaml_file(&file)?),
+        (None, Some(agent)) => {
+            let wrapper = reg.get(&agent)
+                .ok_or_else(|| anyhow!("Agent '{}' not found", agent))?;
+            let machine = wrapper.get_state_machine().await?
+                .ok_or_else(|| anyhow!("Agent '{}' has no state machine", agent))?;
+            (agent, machine)
+        }
+        (None, None) => return Err(anyhow!("Specify an agent or --file")),
+    };
+
+    match format.as_str() {
+        "dot" => print!("{}", machine.to_dot(&name)),
+        "mermaid" => print!("{}", machine.to_mermaid()),
+        other => return Err(anyhow!("Unknown format '{}', expected dot or mermaid", other)),
+    }
+    Ok(())
+}
+
+/// Start a companion binary installed next to this one; it is killed when the handle drops
+fn spawn_sibling(name: &str) -> Result<Child> {
+    let exe = env::current_exe()?;
+    let path = exe.with_file_name(name);
+    Command::new(&path)
+        .kill_on_drop(true)
+        .spawn()
+        .map_err(|e| anyhow!("Failed to start {}: {}", path.display(), e))
+}
+
+async fn handle_serve_command(addr: SocketAddr, worker: bool, mqtt: bool) -> Result<()> {
+    let mut children = Vec::new();
+    if worker {
+        children.push(spawn_sibling("todo_worker")?);
+    }
+    if mqtt {
+        children.push(spawn_sibling("mqtt_intake")?);
+    }
+
+    let app_state = api::create_app_state().await;
+    api::serve(addr, app_state.transfer_service.clone()).await;
+    drop(children);
+    Ok(())
+}
+
+async fn handle_chat_command(reg: &mut AgentRegistry, name: String, session: Option<String>) -> Result<()> {
+    if !reg.exists(&name) {
+        return Err(anyhow!("Agent '{}' not found", name));
+    }
+    let mut current = name;
+    println!("Chatting with {} (type 'quit' to exit)", current);
+
+    loop {
+        print!("{}> ", current);
+        std::io::stdout().flush()?;
+        let mut input = String::new();
+        if std::io::stdin().read_line(&mut input)? == 0 || input.trim() == "quit" {
+            break;
+        }
+        if input.trim().is_empty() {
+            continue;
+        }
+
+        let mut message = Message::new(input.trim().to_string()).with_role(Some("user".to_string()));
+        if let Some(session) = &session {
+            message = message.with_metadata(MessageMetadata::new("swarm".to_string())
+                .with_context(HashMap::from([(SESSION_ID_KEY.to_string(), session.clone())])));
+        }
+        let agent = reg.get(&current).ok_or_else(|| anyhow!("Agent '{}' not found", current))?;
+        let response = agent.process_message(message).await?;
+        println!("{}", response.content);
+
+        let target = response.metadata.and_then(|m| m.transfer_target);
+        if let Some(target) = target.filter(|t| reg.exists(t)) {
+            println!("Transferred to {}", target);
+            current = target;
+        }
+    }
+    Ok(())
+}
+
+/// Like `handle_chat_command`, but listening and speaking. Each utterance goes through a
+/// `TransferService` session, which follows the agents' transfers.
+#[cfg(feature = "speech")]
+async fn handle_voice_command(reg: AgentRegistry, name: String, session: Option<String>) -> Result<()> {
+    if !reg.exists(&name) {
+        return Err(anyhow!("Agent '{}' not found", name));
+    }
+    let speech = Speech::from_env()?;
+    let service = TransferService::new(Arc::new(RwLock::new(reg)));
+    let session = session.unwrap_or_else(|| Uuid::new_v4().to_string());
+    service.set_current_agent_for(Some(&session), &name).await?;
+    println!("Talking to {} (say 'goodbye' to stop)", name);
+
+    loop {
+        println!("Listening...");
+        let heard = speech.listen().await?;
+        if heard.is_empty() {
+            continue;
+        }
+        println!("you> {}", heard);
+        if speech::is_stop_phrase(&heard) {
+            break;
+        }
+
+        let agent = service.current_agent_for(Some(&session)).await?;
+        let message = sessions::tag_session(Message::new(heard), "user", &session);
+        let response = match service.process_message(message).await {
+            Ok(response) => response,
+            Err(e) => {
+                eprintln!("{} failed: {}", agent, e);
+                speech.speak("Sorry, that didn't work.").await?;
+                continue;
+            }
+        };
+        println!("{}> {}", agent, response.content);
+        speech.speak(&response.content).await?;
+
+        if let Some(target) = response.metadata.and_then(|m| m.transfer_target) {
+            match service.set_current_agent_for(Some(&session), &target).await {
+                Ok(()) => println!("Transferred to {}", target),
+                Err(e) => eprintln!("Can't transfer to {}: {}", target, e),
+            }
+        }
+    }
+    service.end_session(&session).await
+}
+
+fn parse_priority(priority: &str) -> Result<TaskPriority> {
+    match priority.to_lowercase().as_str() {
+        "low" => Ok(TaskPriority::Low),
+        "medium" => Ok(TaskPriority::Medium),
+        "high" => Ok(TaskPriority::High),
+        "critical" => Ok(TaskPriority::Critical),
+        other => Err(anyhow!("Unknown priority '{}', expected low, medium, high or critical", other)),
+    }
+}
+
+async fn connect_todo_list() -> Result<TodoList> {
+    if swarmonomicon::secrets::get(swarmonomicon::secrets::MONGO_URI).is_none() {
+        return Err(anyhow!("RTK_MONGO_URI must be set to use the todo list"));
+    }
+    TodoList::shared().await
+}
+
+async fn handle_todo_command(command: TodoCommands) -> Result<()> {
+    let todos = connect_todo_list().await?;
+    match command {
+        TodoCommands::Add { description, agent, priority, project, enhance, tags } => {
+            let ai_client = enhance.then(DefaultAiClient::new);
+            let task = todos.create_task_with_enhancement(
+                description,
+                parse_priority(&priority)?,
+                Some("swarm".to_string()),
+                agent,
+                project,
+                tags.as_deref().map(parse_tags).unwrap_or_default(),
+                ai_client.as_ref().map(|c| c as &dyn AiProvider),
+            ).await?;
+            println!("Added task {}", task.id);
+        }
+        TodoCommands::List { agent, status, tags } => {
+            let tags = tags.as_deref().map(parse_tags).unwrap_or_default();
+            let mut tasks = todos.list_tasks(false, &tags).await?;
+            tasks.retain(|task| {
+                agent.as_ref().is_none_or(|a| &task.target_agent == a)
+                    && status.as_ref().is_none_or(|s| serde_json::to_value(&task.status).ok() == Some(serde_json::json!(s.to_lowercase())))
+            });
+            tasks.sort_by(|a, b| b.priority.cmp(&a.priority).then(a.created_at.cmp(&b.created_at)));
+            for task in &tasks {
+                let status = serde_json::to_value(&task.status)?;
+                println!("{}  {:<10} {:<8?} {:<10} {}", task.id, status.as_str().unwrap_or_default(), task.priority, task.target_agent, task.description);
+            }
+            println!("{} task(s)", tasks.len());
+        }
+        TodoCommands::Complete { id } => {
+            if todos.get_task(&id).await?.is_none() {
+                return Err(anyhow!("Task '{}' not found", id));
+            }
+            todos.mark_task_completed(&id).await?;
+            println!("Completed task {}", id);
+        }
+        TodoCommands::Export { output, format, agent, project, status, tags, include_archived } => {
+            let format = match (format, &output) {
+                (Some(format), _) => format.parse()?,
+                (None, Some(path)) => ExportFormat::for_path(path),
+                (None, None) => ExportFormat::JsonLines,
+            };
+            let filter = ExportFilter {
+                target_agent: agent,
+                project,
+                status: status.map(|s| serde_json::from_value(serde_json::json!(s.to_lowercase())))
+                    .transpose()
+                    .map_err(|_| anyhow!("Unknown status"))?,
+                tags: tags.as_deref().map(parse_tags).unwrap_or_default(),
+                include_archived,
+            };
+            let tasks = todos.export_tasks(&filter).await?;
+            let written = match &output {
+                Some(path) => write_tasks(&tasks, format, std::io::BufWriter::new(std::fs::File::create(path)?))?,
+                None => write_tasks(&tasks, format, std::io::stdout().lock())?,
+            };
+            // stdout may be the export itself
+            eprintln!("Exported {} task(s)", written);
+        }
+        TodoCommands::Import { input, format, allow_duplicate, dry_run } => {
+            let format = match format {
+                Some(format) => format.parse()?,
+                None => ExportFormat::for_path(&input),
+            };
+            let tasks = read_tasks(format, std::fs::File::open(&input)?)?;
+            let report = todos.import_tasks(tasks, allow_duplicate, dry_run).await?;
+            let verb = if dry_run { "Would import" } else { "Imported" };
+            println!("{} {} task(s), skipped {} duplicate(s)", verb, report.imported, report.duplicates);
+        }
+        TodoCommands::Sync { path, agent, project, dry_run } => {
+            let options = SyncOptions { target_agent: agent, project, dry_run };
+            let report = sync_file(&todos, &path, &options).await?;
+            println!("{}", report.summary());
+        }
+    }
+    Ok(())
+}
+
+async fn handle_snapshot_command(command: SnapshotCommands) -> Result<()> {
+    let todos = connect_todo_list().await?;
+    let state = state_store::from_env().await?;
+    let projects = ProjectRegistry::connect().await?;
+    match command {
+        SnapshotCommands::Create { output } => {
+            let snapshot = Snapshot::capture(&todos, state.as_deref(), Some(&projects)).await?;
+            match &output {
+                Some(path) => snapshot.write(std::io::BufWriter::new(std::fs::File::create(path)?))?,
+                None => snapshot.write(std::io::stdout().lock())?,
+            }
+            // stdout may be the snapshot itself
+            eprintln!(
+                "Snapshot of {} task(s), {} archived, {} dead-lettered, {} state entries, {} project(s)",
+                snapshot.tasks.len(), snapshot.archived_tasks.len(), snapshot.dead_letters.len(), snapshot.state.len(), snapshot.projects.len(),
+            );
+        }
+        SnapshotCommands::Restore { input } => {
+            let snapshot = Snapshot::read(std::io::BufReader::new(std::fs::File::open(&input)?))?;
+            if state.is_none() && !snapshot.state.is_empty() {
+                eprintln!("No state store is configured (STATE_STORE); skipping {} state entries", snapshot.state.len());
+            }
+            let report = snapshot.restore(&todos, state.as_deref(), Some(&projects)).await?;
+            println!("Restored {}", report.summary());
+        }
+    }
+    Ok(())
+}
+
+async fn handle_knowledge_command(command: KnowledgeCommands) -> Result<()> {
+    let base = KnowledgeBase::connect().await?;
+    match command {
+        KnowledgeCommands::Ingest { path, project } => {
+            let project = project.unwrap_or_else(|| knowledge::project_name(&path));
+            let report = base.ingest(&project, &path).await?;
+            println!("{}: {}", project, report.summary());
+        }
+        KnowledgeCommands::Search { query, project, limit } => {
+            let project = project.unwrap_or_else(|| knowledge::project_name(std::path::Path::new(".")));
+            let passages = base.retrieve(&project, &query, limit).await?;
+            if passages.is_empty() {
+                println!("No passages matched");
+            }
+            for passage in passages {
+                println!("[{}] ({:.2})\n{}\n", passage.citation(), passage.score, passage.text.trim());
+            }
+        }
+        KnowledgeCommands::Forget { project } => {
+            println!("Removed {} chunk(s) of {}", base.forget(&project).await?, project);
+        }
+    }
+    Ok(())
+}
+
+fn print_run(run: &WorkflowRun) {
+    println!("Run {} of {}: {:?}", run.id, run.workflow, run.status);
+    for record in &run.history {
+        let outcome = if record.success { "ok" } else { "failed" };
+        println!("  {} (attempt {}) {}: {}", record.step, record.attempt, outcome, record.output.trim());
+    }
+    if let Some(error) = &run.error {
+        println!("Error: {}", error);
+    }
+    for record in &run.compensations {
+        let outcome = if record.success { "undone" } else { "not undone" };
+        println!("  {} {}: {}", record.step, outcome, record.output.trim());
+    }
+}
+
+async fn handle_workflow_command(command: WorkflowCommands) -> Result<()> {
+    let engine = WorkflowEngine::from_env().await?
+        .ok_or_else(|| anyhow!("Set WORKFLOWS_CONFIG to a workflow file"))?;
+    match &command {
+        WorkflowCommands::List => {
+            let mut names: Vec<_> = engine.config().workflows.iter().collect();
+            names.sort_by(|a, b| a.0.cmp(b.0));
+            for (name, workflow) in names {
+                let steps: Vec<&str> = workflow.steps.iter().map(|step| step.name.as_str()).collect();
+                println!("{:<16} {} ({})", name, workflow.description.as_deref().unwrap_or(""), steps.join(" → "));
+            }
+            return Ok(());
+        }
+        WorkflowCommands::Show { id } => {
+            print_run(&engine.store().load(id, None).await?.ok_or_else(|| anyhow!("No workflow run {}", id))?);
+            return Ok(());
+        }
+        WorkflowCommands::Run { .. } | WorkflowCommands::Resume { .. } => {}
+    }
+
+    // Running steps needs the agents and tools they use
+    let registry = AgentRegistry::create_default_agents(agents::default_agents()).await?;
+    let engine = engine
+        .with_agents(Arc::new(RwLock::new(registry)))
+        .with_tools(Arc::new(ToolRegistry::create_default_tools().await?));
+    let run = match command {
+        WorkflowCommands::Run { name, input, task } => engine.run(engine.start_for_task(&name, &input, task).await?).await?,
+        WorkflowCommands::Resume { id } => engine.resume(&id).await?,
+        WorkflowCommands::List | WorkflowCommands::Show { .. } => unreachable!(),
+    };
+    print_run(&run);
+    Ok(())
+}
+
+fn handle_registry_command(command: RegistryCommands) -> Result<()> {
+    match command {
+        RegistryCommands::List => {
+            for agent in agents::default_agents() {
+                let downstream = if agent.downstream_agents.is_empty() {
+                    String::new()
+                } else {
+                    format!(" (transfers to: {})", agent.downstream_agents.join(", "))
+                };
+                println!("{:<12} {}{}", agent.name, agent.public_description, downstream);
+            }
+        }
+    }
+    Ok(())
+}
+
+fn handle_config_command(command: ConfigCommands) -> Result<()> {
+    match command {
+        ConfigCommands::Validate { files, state_machine } => {
+            let mut failed = 0;
+            for file in &files {
+                let problems = if state_machine {
+                    match StateMachine::from_yaml_file(file) {
+                        Ok(_) => Vec::new(),
+                        Err(e) => vec![e.to_string()],
+                    }
+                } else {
+                    match config::load_agent_configs(file) {
+                        Ok(agents) => config::validate_agent_configs(&agents),
+                        Err(e) => vec![e.to_string()],
+                    }
+                };
+                if problems.is_empty() {
+                    println!("{}: ok", file.display());
+                } else {
+                    failed += 1;
+                    for problem in problems {
+                        eprintln!("{}: {}", file.display(), problem);
+                    }
+                }
+            }
+            if failed > 0 {
+                return Err(anyhow!("{} of {} file(s) failed validation", failed, files.len()));
+            }
+        }
+    }
+    Ok(())
+}
+
 async fn handle_git_command(
     reg: &mut AgentRegistry,
     git_message: String,
@@ -131,9 +855,20 @@ async fn handle_git_command(
         notes: None,
         ticket: None,
         last_modified: Some(Utc::now().timestamp()),
+        failure_reason: None,
+        embedding: None,
+        claim: None,
+        tags: Vec::new(),
+        failures: Vec::new(),
+        confidence: None,
+        reviewed_at: None,
+        tenant_id: None,
+        idempotency_key: None,
+        content_hash: None,
+        history: Vec::new(),
     };
     let agent = reg.get("git").ok_or_else(|| anyhow!("Git agent not found"))?;
-    agent.process_task(task).await.map_err(|e| anyhow!(e))?;
+    agent.process_task(task, CancellationToken::new()).await.map_err(|e| anyhow!(e))?;
     Ok(())
 }
 
@@ -158,9 +893,20 @@ async fn handle_init_command(
         notes: None,
         ticket: None,
         last_modified: Some(Utc::now().timestamp()),
+        failure_reason: None,
+        embedding: None,
+        claim: None,
+        tags: Vec::new(),
+        failures: Vec::new(),
+        confidence: None,
+        reviewed_at: None,
+        tenant_id: None,
+        idempotency_key: None,
+        content_hash: None,
+        history: Vec::new(),
     };
     let agent = reg.get("greeter").ok_or_else(|| anyhow!("Greeter agent not found"))?;
-    agent.process_task(task).await.map_err(|e| anyhow!(e))?;
+    agent.process_task(task, CancellationToken::new()).await.map_err(|e| anyhow!(e))?;
     Ok(())
 }
 
@@ -186,33 +932,57 @@ async fn handle_message(
         notes: None,
         ticket: None,
         last_modified: Some(Utc::now().timestamp()),
+        failure_reason: None,
+        embedding: None,
+        claim: None,
+        tags: Vec::new(),
+        failures: Vec::new(),
+        confidence: None,
+        reviewed_at: None,
+        tenant_id: None,
+        idempotency_key: None,
+        content_hash: None,
+        history: Vec::new(),
     };
-    agent.process_task(task).await.map_err(|e| anyhow!(e))?;
+    agent.process_task(task, CancellationToken::new()).await.map_err(|e| anyhow!(e))?;
     Ok(())
 }
 
-async fn interactive_mode(reg: &mut AgentRegistry) -> Result<()> {
-    println!("Enter your message (or 'quit' to exit):");
-    let mut input = String::new();
-    std::io::stdin().read_line(&mut input)?;
+#[tokio::main]
+async fn main() -> Result<()> {
+    let cli = Cli::parse();
+    // Other commands print to stdout, so keep quiet there unless RUST_LOG asks for more
+    let serving = matches!(cli.command, Some(Commands::Serve { .. }));
+    Logging::new(if serving { tracing::Level::INFO } else { tracing::Level::WARN }).with_stderr().init();
 
-    while input.trim() != "quit" {
-        handle_message(reg, input.trim().to_string()).await?;
+    if let Some(Commands::Serve { addr, worker, mqtt }) = cli.command {
+        return handle_serve_command(addr, worker, mqtt).await;
+    }
 
-        println!("Enter your message (or 'quit' to exit):");
-        input.clear();
-        std::io::stdin().read_line(&mut input)?;
+    // Each command's logs share a correlation id, shown on failure so they can be found
+    let correlation_id = correlation::new_id();
+    let result = correlation::scope(correlation_id.clone(), "cli", run(cli.command)).await;
+    if result.is_err() {
+        eprintln!("Correlation id: {}", correlation_id);
     }
-    Ok(())
+    result
 }
 
-#[tokio::main]
-async fn main() -> Result<()> {
-    let cli = Cli::parse();
+async fn run(command: Option<Commands>) -> Result<()> {
+    // Commands that don't talk to agents shouldn't need their backends
+    let command = match command {
+        Some(Commands::Todo { command }) => return handle_todo_command(command).await,
+        Some(Commands::Registry { command }) => return handle_registry_command(command),
+        Some(Commands::Config { command }) => return handle_config_command(command),
+        Some(Commands::Workflow { command }) => return handle_workflow_command(command).await,
+        Some(Commands::Snapshot { command }) => return handle_snapshot_command(command).await,
+        Some(Commands::Knowledge { command }) => return handle_knowledge_command(command).await,
+        command => command,
+    };
 
     let mut reg = initialize_registry().await?;
 
-    if let Some(command) = cli.command {
+    if let Some(command) = command {
         match command {
             Commands::Git { message, branch, merge } => {
                 let git_message = message.unwrap_or_else(|| "".to_string());
@@ -228,9 +998,23 @@ async fn main() -> Result<()> {
             Commands::Message { message } => {
                 handle_message(&mut reg, message).await?;
             }
+            Commands::StateMachine { agent, file, format } => {
+                handle_state_machine_command(&reg, agent, file, format).await?;
+            }
+            Commands::Agent { command: AgentCommands::Chat { name, session } } => {
+                handle_chat_command(&mut reg, name, session).await?;
+            }
+            #[cfg(feature = "speech")]
+            Commands::Agent { command: AgentCommands::Voice { name, session } } => {
+                handle_voice_command(reg, name, session).await?;
+            }
+            Commands::Repl { agent } => {
+                Repl::new(reg).with_agent(agent).run().await?;
+            }
+            Commands::Serve { .. } | Commands::Todo { .. } | Commands::Registry { .. } | Commands::Config { .. } | Commands::Workflow { .. } | Commands::Snapshot { .. } | Commands::Knowledge { .. } => unreachable!(),
         }
     } else {
-        interactive_mode(&mut reg).await?;
+        Repl::new(reg).run().await?;
     }
 
     Ok(())
@@ -263,6 +1047,10 @@ mod tests {
                 downstream_agents: vec![],
                 personality: None,
                 state_machine: None,
+                script: None,
+                max_concurrency: None,
+                middleware: None,
+                remote: None,
             });
 
             #[cfg(feature = "git-agent")]
@@ -280,6 +1068,10 @@ mod tests {
                 downstream_agents: vec!["git".to_string()],
                 personality: None,
                 state_machine: None,
+                script: None,
+                max_concurrency: None,
+                middleware: None,
+                remote: None,
             });
 
             #[cfg(feature = "project-agent")]
@@ -291,6 +1083,10 @@ mod tests {
                 downstream_agents: vec!["git".to_string()],
                 personality: None,
                 state_machine: None,
+                script: None,
+                max_concurrency: None,
+                middleware: None,
+                remote: None,
             }).await.map_err(|e| anyhow!(e))?;
 
             registry.register("haiku".to_string(), Box::new(haiku_agent)).await?;
@@ -0,0 +1,387 @@
+//! A live view of a running server for operators on a headless box. Polls `/admin/overview`
+//! for agents, queues, workers, AI providers and storage, and follows `/admin/events` for
+//! transfers and tasks as they happen. Needs an admin API key when the server checks keys.
+//! `q` or Esc quits.
+
+use std::collections::{BTreeMap, VecDeque};
+use std::io::{Stdout, stdout};
+use std::sync::Arc;
+use std::time::Duration;
+use anyhow::Result;
+use chrono::Utc;
+use clap::Parser;
+use ratatui::crossterm::event::{self, Event as TermEvent, KeyCode, KeyEventKind, KeyModifiers};
+use ratatui::crossterm::execute;
+use ratatui::crossterm::terminal::{EnterAlternateScreen, LeaveAlternateScreen, disable_raw_mode, enable_raw_mode};
+use ratatui::{
+    Frame, Terminal,
+    backend::CrosstermBackend,
+    layout::{Constraint, Layout, Rect},
+    style::{Color, Style, Stylize},
+    text::{Line, Span},
+    widgets::{Block, Paragraph, Row, Table},
+};
+use reqwest::RequestBuilder;
+use tokio::sync::mpsc;
+use swarmonomicon::api::admin::Overview;
+use swarmonomicon::events::DomainEvent;
+use swarmonomicon::types::TaskEventKind;
+
+/// Transfers kept for display
+const KEPT_TRANSFERS: usize = 20;
+
+/// Wait before reconnecting to the event stream
+const RECONNECT_DELAY: Duration = Duration::from_secs(5);
+
+#[derive(Parser)]
+#[command(author, version, about, long_about = None)]
+struct Cli {
+    /// Base URL of the server's API
+    #[arg(short, long, default_value = "http://127.0.0.1:3000")]
+    url: String,
+
+    /// API key with the admin scope, if the server requires keys
+    #[arg(long)]
+    api_key: Option<String>,
+
+    /// Seconds between polls of the overview
+    #[arg(short, long, default_value_t = 2)]
+    interval: u64,
+}
+
+enum Update {
+    Overview(Box<Overview>),
+    Unreachable(String),
+    Event(DomainEvent),
+}
+
+struct InFlightTask {
+    agent: String,
+    description: String,
+    started_at: i64,
+}
+
+/// What the screen shows, built up from the feeds
+#[derive(Default)]
+struct Monitor {
+    overview: Option<Overview>,
+    /// Why the latest request failed; cleared by the next overview
+    error: Option<String>,
+    /// (from, to, timestamp), newest first
+    transfers: VecDeque<(String, String, i64)>,
+    /// Tasks seen starting and not yet finishing, by id
+    in_flight: BTreeMap<String, InFlightTask>,
+}
+
+impl Monitor {
+    fn apply(&mut self, update: Update) {
+        match update {
+            Update::Overview(overview) => {
+                self.overview = Some(*overview);
+                self.error = None;
+            }
+            Update::Unreachable(error) => self.error = Some(error),
+            Update::Event(DomainEvent::MessageRouted { from: Some(from), to, timestamp }) => {
+                self.transfers.push_front((from, to, timestamp));
+                self.transfers.truncate(KEPT_TRANSFERS);
+            }
+            Update::Event(DomainEvent::Task(task)) => match task.kind {
+                TaskEventKind::Started => {
+                    self.in_flight.insert(task.task_id.clone(), InFlightTask {
+                        agent: task.target_agent.unwrap_or_default(),
+                        description: task.description.unwrap_or(task.task_id),
+                        started_at: task.timestamp,
+                    });
+                }
+                TaskEventKind::Completed | TaskEventKind::Failed | TaskEventKind::Cancelled => {
+                    self.in_flight.remove(&task.task_id);
+                }
+                TaskEventKind::Created => {}
+            },
+            Update::Event(_) => {}
+        }
+    }
+}
+
+struct ApiClient {
+    http: reqwest::Client,
+    url: String,
+    api_key: Option<String>,
+}
+
+impl ApiClient {
+    fn get(&self, path: &str) -> RequestBuilder {
+        let request = self.http.get(format!("{}{}", self.url.trim_end_matches('/'), path));
+        match &self.api_key {
+            Some(key) => request.bearer_auth(key),
+            None => request,
+        }
+    }
+
+    async fn overview(&self) -> Result<Overview> {
+        Ok(self.get("/admin/overview").send().await?.error_for_status()?.json().await?)
+    }
+
+    /// Send events to `updates` until the stream ends or nobody is listening
+    async fn follow_events(&self, updates: &mpsc::UnboundedSender<Update>) -> Result<()> {
+        let mut response = self.get("/admin/events").send().await?.error_for_status()?;
+        let mut buffer = Vec::new();
+        while let Some(chunk) = response.chunk().await? {
+            buffer.extend_from_slice(&chunk);
+            while let Some(end) = buffer.windows(2).position(|window| window == b"\n\n") {
+                let block: Vec<u8> = buffer.drain(..end + 2).collect();
+                let Some(event) = parse_sse(&String::from_utf8_lossy(&block)) else { continue };
+                if updates.send(Update::Event(event)).is_err() {
+                    return Ok(());
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The event in one SSE block; keep-alive comments and unknown events are skipped
+fn parse_sse(block: &str) -> Option<DomainEvent> {
+    let data: Vec<&str> = block.lines()
+        .filter_map(|line| line.strip_prefix("data:"))
+        .map(str::trim_start)
+        .collect();
+    serde_json::from_str(&data.join("\n")).ok()
+}
+
+fn start_feeds(client: Arc<ApiClient>, interval: Duration, updates: mpsc::UnboundedSender<Update>) {
+    tokio::spawn({
+        let (client, updates) = (client.clone(), updates.clone());
+        async move {
+            loop {
+                let update = match client.overview().await {
+                    Ok(overview) => Update::Overview(Box::new(overview)),
+                    Err(e) => Update::Unreachable(e.to_string()),
+                };
+                if updates.send(update).is_err() {
+                    return;
+                }
+                tokio::time::sleep(interval).await;
+            }
+        }
+    });
+    tokio::spawn(async move {
+        while !updates.is_closed() {
+            if let Err(e) = client.follow_events(&updates).await {
+                let _ = updates.send(Update::Unreachable(format!("Event stream: {}", e)));
+            }
+            tokio::time::sleep(RECONNECT_DELAY).await;
+        }
+    });
+}
+
+/// e.g. `42s` or `3m`
+fn age(since: i64, now: i64) -> String {
+    match (now - since).max(0) {
+        secs if secs < 60 => format!("{}s", secs),
+        secs if secs < 3600 => format!("{}m", secs / 60),
+        secs => format!("{}h", secs / 3600),
+    }
+}
+
+/// Agent, active and queued messages, concurrency limit, pending and in-progress tasks.
+/// Agents with tasks but not registered on this server are listed after the rest.
+fn agent_rows(overview: &Overview) -> Vec<[String; 6]> {
+    let tasks = |name: &str| overview.task_queues.iter().find(|queue| queue.agent == name);
+    let mut rows: Vec<[String; 6]> = overview.agents.iter().map(|agent| {
+        let queue = tasks(&agent.name);
+        [
+            agent.name.clone(),
+            agent.messages.active.to_string(),
+            agent.messages.queued.to_string(),
+            agent.messages.limit.map_or("-".to_string(), |limit| limit.to_string()),
+            queue.map_or(0, |queue| queue.pending).to_string(),
+            queue.map_or(0, |queue| queue.in_progress).to_string(),
+        ]
+    }).collect();
+    for queue in &overview.task_queues {
+        if !overview.agents.iter().any(|agent| agent.name == queue.agent) {
+            rows.push([queue.agent.clone(), "-".into(), "-".into(), "-".into(), queue.pending.to_string(), queue.in_progress.to_string()]);
+        }
+    }
+    rows
+}
+
+fn table<'a>(title: &'a str, header: &'a [&'a str], rows: Vec<Row<'a>>, widths: &'a [Constraint]) -> Table<'a> {
+    Table::new(rows, widths.iter().copied())
+        .header(Row::new(header.iter().copied()).bold())
+        .block(Block::bordered().title(title))
+}
+
+fn draw(frame: &mut Frame, monitor: &Monitor, url: &str) {
+    let now = Utc::now().timestamp();
+    let [header, agents, middle, bottom] = Layout::vertical([
+        Constraint::Length(1),
+        Constraint::Percentage(35),
+        Constraint::Percentage(35),
+        Constraint::Percentage(30),
+    ]).areas(frame.area());
+
+    let mut status = vec![Span::from("swarm-top ").bold(), Span::from(url.to_string())];
+    if let Some(overview) = &monitor.overview {
+        status.push(Span::from(format!(
+            "  updated {} ago, {} request(s) in flight",
+            age(overview.generated_at, now), overview.server.in_flight_requests,
+        )));
+        if overview.server.shutting_down {
+            status.push(Span::from("  shutting down").yellow());
+        }
+    }
+    if let Some(error) = &monitor.error {
+        status.push(Span::from(format!("  {}", error)).red());
+    }
+    frame.render_widget(Paragraph::new(Line::from(status)), header);
+
+    let Some(overview) = &monitor.overview else {
+        frame.render_widget(Paragraph::new("Waiting for the server...").block(Block::bordered()), agents);
+        return;
+    };
+
+    let rows = agent_rows(overview).into_iter().map(Row::new).collect();
+    let widths = [Constraint::Fill(2), Constraint::Fill(1), Constraint::Fill(1), Constraint::Fill(1), Constraint::Fill(1), Constraint::Fill(1)];
+    frame.render_widget(table("Agents", &["Agent", "Active", "Queued", "Limit", "Pending tasks", "In progress"], rows, &widths), agents);
+
+    let [in_flight, workers, transfers] = Layout::horizontal([Constraint::Fill(2), Constraint::Fill(1), Constraint::Fill(1)]).areas(middle);
+    let rows = monitor.in_flight.values()
+        .map(|task| Row::new([task.agent.clone(), task.description.clone(), age(task.started_at, now)]))
+        .collect();
+    let widths = [Constraint::Length(12), Constraint::Fill(1), Constraint::Length(5)];
+    frame.render_widget(table("In-flight tasks", &["Agent", "Task", "For"], rows, &widths), in_flight);
+
+    let rows = overview.workers.iter()
+        .map(|worker| Row::new([
+            worker.worker_id.clone(),
+            worker.tasks.to_string(),
+            worker.last_heartbeat.map_or("-".to_string(), |heartbeat| age(heartbeat, now)),
+        ]))
+        .collect();
+    let widths = [Constraint::Fill(1), Constraint::Length(5), Constraint::Length(9)];
+    frame.render_widget(table("Workers", &["Worker", "Tasks", "Heartbeat"], rows, &widths), workers);
+
+    let rows = monitor.transfers.iter()
+        .map(|(from, to, timestamp)| Row::new([from.clone(), to.clone(), age(*timestamp, now)]))
+        .collect();
+    let widths = [Constraint::Fill(1), Constraint::Fill(1), Constraint::Length(5)];
+    frame.render_widget(table("Recent transfers", &["From", "To", "Ago"], rows, &widths), transfers);
+
+    draw_health(frame, overview, bottom, now);
+}
+
+fn draw_health(frame: &mut Frame, overview: &Overview, area: Rect, now: i64) {
+    let [providers, storage, errors] = Layout::horizontal([Constraint::Fill(1), Constraint::Fill(1), Constraint::Fill(2)]).areas(area);
+    let latency = |ms: Option<f64>| ms.map_or("-".to_string(), |ms| format!("{:.0} ms", ms));
+
+    let rows = overview.ai_providers.iter()
+        .map(|provider| {
+            let row = Row::new([
+                provider.name.clone(),
+                if provider.open { "skipped".to_string() } else { "ok".to_string() },
+                provider.consecutive_failures.to_string(),
+                latency(provider.latency_ms),
+            ]);
+            if provider.open { row.style(Style::new().fg(Color::Red)) } else { row }
+        })
+        .collect();
+    let widths = [Constraint::Fill(1), Constraint::Length(7), Constraint::Length(8), Constraint::Length(8)];
+    frame.render_widget(table("AI providers", &["Provider", "State", "Failures", "Latency"], rows, &widths), providers);
+
+    let rows = overview.storage.iter()
+        .map(|store| match &store.error {
+            Some(error) => Row::new([store.store.clone(), error.clone()]).style(Style::new().fg(Color::Red)),
+            None => Row::new([store.store.clone(), latency(store.latency_ms)]),
+        })
+        .collect();
+    let widths = [Constraint::Length(12), Constraint::Fill(1)];
+    frame.render_widget(table("Storage", &["Store", "Latency"], rows, &widths), storage);
+
+    let rows = overview.recent_errors.iter()
+        .map(|error| Row::new([age(error.timestamp, now), error.kind.clone(), error.source.clone(), error.message.clone()]))
+        .collect();
+    let widths = [Constraint::Length(5), Constraint::Length(18), Constraint::Length(14), Constraint::Fill(1)];
+    frame.render_widget(table("Recent errors", &["Ago", "Kind", "Source", "Message"], rows, &widths), errors);
+}
+
+fn run(terminal: &mut Terminal<CrosstermBackend<Stdout>>, updates: &mut mpsc::UnboundedReceiver<Update>, url: &str) -> Result<()> {
+    let mut monitor = Monitor::default();
+    loop {
+        while let Ok(update) = updates.try_recv() {
+            monitor.apply(update);
+        }
+        terminal.draw(|frame| draw(frame, &monitor, url))?;
+        if event::poll(Duration::from_millis(250))? {
+            if let TermEvent::Key(key) = event::read()? {
+                let ctrl_c = key.code == KeyCode::Char('c') && key.modifiers.contains(KeyModifiers::CONTROL);
+                if key.kind == KeyEventKind::Press && (ctrl_c || matches!(key.code, KeyCode::Char('q') | KeyCode::Esc)) {
+                    return Ok(());
+                }
+            }
+        }
+    }
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let cli = Cli::parse();
+    let client = Arc::new(ApiClient { http: reqwest::Client::new(), url: cli.url.clone(), api_key: cli.api_key });
+    let (updates, mut receiver) = mpsc::unbounded_channel();
+    start_feeds(client, Duration::from_secs(cli.interval.max(1)), updates);
+
+    enable_raw_mode()?;
+    execute!(stdout(), EnterAlternateScreen)?;
+    let mut terminal = Terminal::new(CrosstermBackend::new(stdout()))?;
+    // Drawing and reading keys block, so keep them off the feeds' worker threads
+    let result = tokio::task::block_in_place(|| run(&mut terminal, &mut receiver, &cli.url));
+    disable_raw_mode()?;
+    execute!(terminal.backend_mut(), LeaveAlternateScreen)?;
+    terminal.show_cursor()?;
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use swarmonomicon::types::TaskEvent;
+
+    #[test]
+    fn test_events_track_transfers_and_in_flight_tasks() {
+        let mut monitor = Monitor::default();
+        let block = format!("event: message_routed\ndata: {}\n\n", serde_json::to_string(&DomainEvent::message_routed(Some("greeter"), "haiku")).unwrap());
+        monitor.apply(Update::Event(parse_sse(&block).unwrap()));
+        // Routing without a transfer isn't one
+        monitor.apply(Update::Event(DomainEvent::message_routed(None, "git")));
+        assert_eq!(monitor.transfers.iter().map(|(from, to, _)| (from.as_str(), to.as_str())).collect::<Vec<_>>(), [("greeter", "haiku")]);
+
+        monitor.apply(Update::Event(TaskEvent::new(TaskEventKind::Started, "t1").into()));
+        monitor.apply(Update::Event(TaskEvent::new(TaskEventKind::Started, "t2").into()));
+        monitor.apply(Update::Event(TaskEvent::new(TaskEventKind::Completed, "t1").into()));
+        assert_eq!(monitor.in_flight.keys().collect::<Vec<_>>(), ["t2"]);
+        assert!(parse_sse(": keep-alive\n\n").is_none());
+    }
+
+    #[test]
+    fn test_agent_rows_include_queues_of_unregistered_agents() {
+        let overview: Overview = serde_json::from_value(serde_json::json!({
+            "generated_at": 0,
+            "agents": [{ "name": "git", "description": "", "messages": { "limit": 2, "active": 1, "queued": 3, "peak_queued": 3, "completed": 9 } }],
+            "task_queues": [
+                { "agent": "git", "pending": 4, "in_progress": 1 },
+                { "agent": "user", "pending": 2, "in_progress": 0 },
+            ],
+            "workers": [],
+            "server": { "shutting_down": false, "in_flight_requests": 0 },
+            "ai_providers": [],
+            "storage": [],
+            "recent_errors": [],
+        })).unwrap();
+        assert_eq!(agent_rows(&overview), [
+            ["git", "1", "3", "2", "4", "1"].map(String::from),
+            ["user", "-", "-", "-", "2", "0"].map(String::from),
+        ]);
+        assert_eq!((age(100, 142), age(0, 180), age(0, 7200)), ("42s".to_string(), "3m".to_string(), "2h".to_string()));
+    }
+}
@@ -3,27 +3,38 @@ use swarmonomicon::agents::{self, AgentRegistry, AgentWrapper};
 use swarmonomicon::types::{AgentConfig, Message, TodoList, TodoTask, TaskStatus, TaskPriority};
 use swarmonomicon::Agent;
 use swarmonomicon::types::TodoProcessor;
-use rumqttc::{MqttOptions, AsyncClient, QoS, Event, Packet, EventLoop};
+use rumqttc::{AsyncClient, Event, Packet};
 use tokio::task;
 use std::collections::HashMap;
 use std::sync::Arc;
 use tokio::sync::{RwLock, Mutex};
 use swarmonomicon::tools::ToolRegistry;
+use swarmonomicon::tools::CancellationToken;
+use swarmonomicon::tools::GitHubIssuesTool;
+use swarmonomicon::tools::{DesktopNotifyTool, NotificationTool};
+use swarmonomicon::types::cancellation::{self, RunningTask};
+use swarmonomicon::types::archive::ArchivePolicy;
+use swarmonomicon::types::reaper::StuckTaskPolicy;
+use swarmonomicon::shutdown::ShutdownCoordinator;
+use swarmonomicon::scheduler::Scheduler;
+use swarmonomicon::ai::TokenBudgets;
+use swarmonomicon::events::{self, EventMetrics};
+use swarmonomicon::secrets;
+use swarmonomicon::config::mqtt::{self, MqttConfig};
 use anyhow::{Result, anyhow, Context};
 use std::env;
 use std::time::Instant;
 use serde_json::{self, json};
 use chrono;
 use tracing::{info, error, warn, debug};
-use tracing_subscriber::{self, fmt::format::FmtSpan};
+use swarmonomicon::correlation;
+use swarmonomicon::logging::Logging;
 use std::sync::atomic::{AtomicU64, Ordering};
 use tokio::time::timeout;
 use std::time::SystemTime;
 use tokio::sync::broadcast;
 
 // Constants for configuration
-const DEFAULT_MQTT_HOST: &str = "localhost";
-const DEFAULT_MQTT_PORT: u16 = 1883;
 const DEFAULT_CLIENT_ID: &str = "todo_worker";
 const DEFAULT_CHECK_INTERVAL: u64 = 30;
 const METRICS_REPORTING_INTERVAL: u64 = 10;
@@ -43,6 +54,8 @@ struct Metrics {
     medium_tasks_processed: AtomicU64,
     high_tasks_processed: AtomicU64,
     critical_tasks_processed: AtomicU64,
+    /// Counts of domain events seen in this process
+    events: Arc<EventMetrics>,
     start_time: Instant,
     last_report_time: Mutex<Instant>,
 }
@@ -60,6 +73,7 @@ impl Metrics {
             medium_tasks_processed: AtomicU64::new(0),
             high_tasks_processed: AtomicU64::new(0),
             critical_tasks_processed: AtomicU64::new(0),
+            events: Arc::new(EventMetrics::default()),
             start_time: now,
             last_report_time: Mutex::new(now),
         }
@@ -132,6 +146,8 @@ impl Metrics {
             "high_tasks_processed": self.high_tasks_processed.load(Ordering::Relaxed),
             "critical_tasks_processed": self.critical_tasks_processed.load(Ordering::Relaxed),
             "healthy": self.is_healthy(),
+            "ai_token_usage": TokenBudgets::global().snapshot(),
+            "events": self.events.snapshot(),
             "timestamp": chrono::Utc::now().to_rfc3339()
         })
     }
@@ -143,23 +159,13 @@ async fn main() -> Result<()> {
     dotenv::dotenv().ok();
     
     // Initialize the tracing subscriber with more detailed logging
-    tracing_subscriber::fmt()
-        .with_span_events(FmtSpan::CLOSE)
-        .with_max_level(tracing::Level::DEBUG)
-        .init();
+    Logging::new(tracing::Level::DEBUG).with_span_timings().init();
     
     info!("Starting todo worker");
 
     // Parse MQTT configuration
-    let mqtt_host = env::var("MQTT_HOST").unwrap_or_else(|_| DEFAULT_MQTT_HOST.to_string());
-    let mqtt_port: u16 = env::var("MQTT_PORT")
-        .ok()
-        .and_then(|s| s.parse().ok())
-        .unwrap_or(DEFAULT_MQTT_PORT);
-    let mqtt_username = env::var("MQTT_USERNAME").ok();
-    let mqtt_password = env::var("MQTT_PASSWORD").ok();
-    let mqtt_client_id = env::var("MQTT_CLIENT_ID")
-        .unwrap_or_else(|_| format!("{}-{}", DEFAULT_CLIENT_ID, uuid::Uuid::new_v4()));
+    let mqtt_config = MqttConfig::from_env()?;
+    let mqtt_client_id = mqtt_config.client_id(&format!("{}-{}", DEFAULT_CLIENT_ID, uuid::Uuid::new_v4()));
 
     // Get check interval from environment or use default
     let check_interval: u64 = env::var("TODO_CHECK_INTERVAL_SECS")
@@ -167,27 +173,99 @@ async fn main() -> Result<()> {
         .and_then(|s| s.parse().ok())
         .unwrap_or(DEFAULT_CHECK_INTERVAL);
 
-    info!("Connecting to MQTT broker at {}:{}", mqtt_host, mqtt_port);
+    info!("Connecting to MQTT broker at {}:{}", mqtt_config.host, mqtt_config.port);
     info!("Using client ID: {}", mqtt_client_id);
 
     // Create metrics tracking
     let metrics = Arc::new(Metrics::new());
+    metrics.events.follow(events::global());
     
     // Initialize agent registry
     let agent_registry = Arc::new(RwLock::new(AgentRegistry::new()));
+
+    // On ctrl-c/SIGTERM: stop picking up tasks, let running ones finish, then shut agents down
+    let shutdown = Arc::new(ShutdownCoordinator::default());
+    {
+        let registry = agent_registry.clone();
+        shutdown.on_shutdown("agents", move || async move {
+            registry.read().await.shutdown_all().await
+        }).await;
+    }
+    shutdown.listen_for_signals();
+
+    // Keep the todos collection small by moving old finished tasks to the archive
+    let _archiver = match ArchivePolicy::from_env() {
+        Some(policy) => match TodoList::shared().await {
+            Ok(todo_list) => Some(todo_list.spawn_archiver(policy)),
+            Err(e) => {
+                warn!("Task archiving disabled, could not connect to MongoDB: {}", e);
+                None
+            }
+        },
+        None => None,
+    };
+
+    // Retry tasks whose worker stopped sending heartbeats, wherever that worker runs
+    let _reaper = match StuckTaskPolicy::from_env() {
+        Some(policy) => match TodoList::shared().await {
+            Ok(todo_list) => Some(todo_list.spawn_reaper(policy)),
+            Err(e) => {
+                warn!("Stuck task detection disabled, could not connect to MongoDB: {}", e);
+                None
+            }
+        },
+        None => None,
+    };
+
+    // Close linked GitHub issues as this worker finishes their tasks
+    let _issue_mirror = match secrets::get(secrets::GITHUB_TOKEN) {
+        Some(_) => match TodoList::shared().await.and_then(GitHubIssuesTool::new) {
+            Ok(issues) => Some(issues.spawn_mirror()),
+            Err(e) => {
+                warn!("GitHub issue mirroring disabled: {}", e);
+                None
+            }
+        },
+        None => None,
+    };
     
+    // Announce completions, failures and transfers on Slack or Discord
+    let _notifier = match NotificationTool::from_env() {
+        Ok(notify) => notify.map(|notify| notify.spawn_notifier()),
+        Err(e) => {
+            warn!("Notifications disabled: {}", e);
+            None
+        }
+    };
+
+    // Raise desktop notifications as the local user's tasks finish
+    let _desktop_notifier = match DesktopNotifyTool::from_env() {
+        Ok(desktop) => desktop.map(|desktop| desktop.spawn_notifier()),
+        Err(e) => {
+            warn!("Desktop notifications disabled: {}", e);
+            None
+        }
+    };
+
+    // Run the recurring jobs in SCHEDULE_CONFIG
+    let _scheduler = match start_scheduler(agent_registry.clone()).await {
+        Ok(scheduler) => scheduler,
+        Err(e) => {
+            warn!("Scheduled jobs disabled: {}", e);
+            None
+        }
+    };
+
     // Setup MQTT and run main loop with reconnection attempts
     let mut reconnect_attempts = 0;
     while reconnect_attempts < MAX_RECONNECT_ATTEMPTS {
         match setup_and_run_mqtt_loop(
-            &mqtt_host,
-            mqtt_port,
-            mqtt_username.clone(),
-            mqtt_password.clone(),
+            &mqtt_config,
             mqtt_client_id.clone(),
             agent_registry.clone(),
             metrics.clone(),
             Duration::from_secs(check_interval),
+            shutdown.clone(),
         ).await {
             Ok(_) => {
                 // If we exit cleanly, break out of the reconnection loop
@@ -207,10 +285,7 @@ async fn main() -> Result<()> {
                 
                 // Report the error via metrics before reconnecting
                 if let Ok(client) = setup_mqtt_client(
-                    &mqtt_host,
-                    mqtt_port,
-                    mqtt_username.clone(),
-                    mqtt_password.clone(),
+                    &mqtt_config,
                     format!("{}-error-reporter", mqtt_client_id),
                 ).await {
                     let error_metrics = json!({
@@ -221,7 +296,7 @@ async fn main() -> Result<()> {
                     
                     let _ = client.publish(
                         "metrics/todo_worker/error",
-                        QoS::ExactlyOnce,
+                        mqtt::qos(),
                         false,
                         error_metrics.to_string()
                     ).await;
@@ -237,52 +312,35 @@ async fn main() -> Result<()> {
 }
 
 async fn setup_mqtt_client(
-    mqtt_host: &str,
-    mqtt_port: u16,
-    mqtt_username: Option<String>,
-    mqtt_password: Option<String>,
+    mqtt_config: &MqttConfig,
     mqtt_client_id: String,
 ) -> Result<AsyncClient> {
-    // Set up MQTT client options
-    let mut mqtt_options = MqttOptions::new(mqtt_client_id, mqtt_host, mqtt_port);
-    mqtt_options.set_keep_alive(Duration::from_secs(20));
-    mqtt_options.set_clean_session(true);
-
-    if let (Some(username), Some(password)) = (mqtt_username, mqtt_password) {
-        mqtt_options.set_credentials(username, password);
-    }
-
+    let mqtt_options = mqtt_config.options(&mqtt_client_id)?;
     let (client, _) = AsyncClient::new(mqtt_options, 100);
     Ok(client)
 }
 
 async fn setup_and_run_mqtt_loop(
-    mqtt_host: &str,
-    mqtt_port: u16,
-    mqtt_username: Option<String>,
-    mqtt_password: Option<String>,
+    mqtt_config: &MqttConfig,
     mqtt_client_id: String,
     agent_registry: Arc<RwLock<AgentRegistry>>,
     metrics: Arc<Metrics>,
     check_interval: Duration,
+    shutdown: Arc<ShutdownCoordinator>,
 ) -> Result<()> {
     // Set up MQTT client options
-    let mut mqtt_options = MqttOptions::new(mqtt_client_id, mqtt_host, mqtt_port);
-    mqtt_options.set_keep_alive(Duration::from_secs(20));
-    mqtt_options.set_clean_session(true);
-
-    if let (Some(username), Some(password)) = (mqtt_username, mqtt_password) {
-        mqtt_options.set_credentials(username, password);
-    }
-
+    let mqtt_options = mqtt_config.options(&mqtt_client_id)?;
     let (client, mut eventloop) = AsyncClient::new(mqtt_options, 100);
     let client = Arc::new(client);
 
     // Subscribe to the topics
-    client.subscribe("agent/+/todo/process", QoS::ExactlyOnce).await?;
+    client.subscribe("agent/+/todo/process", mqtt::qos()).await?;
     info!("Subscribed to topic: agent/+/todo/process");
-    client.subscribe("todo_worker/control", QoS::ExactlyOnce).await?;
+    client.subscribe("todo_worker/control", mqtt::qos()).await?;
     info!("Subscribed to topic: todo_worker/control");
+
+    // Task and routing events go out as events/<name>, e.g. events/task_completed
+    events::forward_to_mqtt(events::global(), client.clone(), "events");
     
     // Create default agents
     if load_agents(&agent_registry).await.is_err() {
@@ -309,11 +367,12 @@ async fn setup_and_run_mqtt_loop(
         let registry = agent_registry.clone();
         let client = client.clone();
         let metrics = metrics.clone();
+        let shutdown = shutdown.clone();
         tokio::spawn(async move {
             let mut interval = tokio::time::interval(check_interval);
             loop {
                 interval.tick().await;
-                if let Err(e) = check_agent_tasks(&registry, &client, &metrics).await {
+                if let Err(e) = check_agent_tasks(&registry, &client, &metrics, &shutdown).await {
                     error!("Error checking agent tasks: {}", e);
                 }
             }
@@ -321,57 +380,57 @@ async fn setup_and_run_mqtt_loop(
     };
 
     // Main event loop with graceful shutdown support
-    let (shutdown_tx, mut shutdown_rx) = tokio::sync::broadcast::channel::<()>(1);
-    
-    // Set up graceful shutdown on ctrl-c
-    let shutdown_tx_ctrl_c = shutdown_tx.clone();
-    tokio::spawn(async move {
-        if let Err(e) = tokio::signal::ctrl_c().await {
-            error!("Failed to listen for ctrl-c: {}", e);
-            return;
-        }
-        info!("Received shutdown signal, initiating graceful shutdown...");
-        let _ = shutdown_tx_ctrl_c.send(());
-    });
-    
     loop {
         tokio::select! {
             // Check for shutdown signal
-            result = shutdown_rx.recv() => {
-                if result.is_ok() {
-                    info!("Shutdown signal received, closing MQTT connection...");
-                    
-                    // Report final metrics
-                    if let Err(e) = report_metrics(&metrics, &client).await {
-                        error!("Failed to report final metrics: {}", e);
+            _ = shutdown.signalled() => {
+                // Wait for in-flight tasks to drain (bounded) and agents to shut down,
+                // polling the event loop so their responses still get published
+                let drained = shutdown.finished();
+                tokio::pin!(drained);
+                loop {
+                    tokio::select! {
+                        _ = &mut drained => break,
+                        event = eventloop.poll() => if let Err(e) = event {
+                            error!("Error from MQTT eventloop while draining: {}", e);
+                            tokio::time::sleep(Duration::from_secs(1)).await;
+                        },
                     }
-                    
-                    // Publish shutdown status
-                    let shutdown_payload = json!({
-                        "status": "shutdown",
-                        "timestamp": chrono::Utc::now().to_rfc3339(),
-                        "final_metrics": metrics.get_metrics_json().await
-                    }).to_string();
-                    
-                    if let Err(e) = client.publish(
-                        "todo_worker/status", 
-                        QoS::ExactlyOnce, 
-                        false, 
-                        shutdown_payload
-                    ).await {
-                        error!("Failed to publish shutdown status: {}", e);
-                    }
-                    
-                    // Disconnect from MQTT
-                    if let Err(e) = client.disconnect().await {
-                        error!("Error disconnecting from MQTT: {}", e);
-                    }
-                    
-                    // Allow time for final messages to be sent
-                    tokio::time::sleep(Duration::from_secs(1)).await;
-                    info!("Graceful shutdown complete");
-                    break Ok(());
                 }
+                info!("Tasks drained, closing MQTT connection...");
+                
+                // Report final metrics
+                if let Err(e) = report_metrics(&metrics, &client).await {
+                    error!("Failed to report final metrics: {}", e);
+                }
+                
+                // Publish shutdown status
+                let shutdown_payload = json!({
+                    "status": "shutdown",
+                    "timestamp": chrono::Utc::now().to_rfc3339(),
+                    "final_metrics": metrics.get_metrics_json().await
+                }).to_string();
+                
+                if let Err(e) = client.publish(
+                    "todo_worker/status", 
+                    mqtt::qos(), 
+                    false, 
+                    shutdown_payload
+                ).await {
+                    error!("Failed to publish shutdown status: {}", e);
+                }
+                
+                // Disconnect from MQTT
+                if let Err(e) = client.disconnect().await {
+                    error!("Error disconnecting from MQTT: {}", e);
+                }
+                
+                // Allow time for final messages to be sent
+                let _ = timeout(Duration::from_secs(1), async {
+                    while eventloop.poll().await.is_ok() {}
+                }).await;
+                info!("Graceful shutdown complete");
+                break Ok(());
             }
             
             // Handle MQTT events
@@ -396,13 +455,18 @@ async fn setup_and_run_mqtt_loop(
                                     if let Some(agent_name) = topic.split('/').nth(1) {
                                         info!("Processing todo for agent: {}", agent_name);
                                         
-                                        process_agent_message(
+                                        let Some(_guard) = shutdown.begin() else {
+                                            warn!("Shutting down, ignoring todo for agent {}", agent_name);
+                                            continue;
+                                        };
+                                        let correlation_id = correlation::from_payload(payload);
+                                        correlation::scope(correlation_id, "mqtt", process_agent_message(
                                             &agent_registry,
                                             agent_name,
                                             payload,
                                             &client,
                                             &metrics
-                                        ).await;
+                                        )).await;
                                     }
                                 } else if topic == "todo_worker/control" {
                                     if let Err(e) = handle_control_message(payload, &client, &metrics).await {
@@ -447,7 +511,7 @@ async fn handle_control_message(
                         let status = metrics.get_metrics_json().await;
                         client.publish(
                             "todo_worker/status",
-                            QoS::ExactlyOnce,
+                            mqtt::qos(),
                             false,
                             status.to_string()
                         ).await?;
@@ -459,7 +523,7 @@ async fn handle_control_message(
                         let status = metrics.get_metrics_json().await;
                         client.publish(
                             "todo_worker/metrics_reset_response",
-                            QoS::ExactlyOnce,
+                            mqtt::qos(),
                             false,
                             json!({
                                 "status": "acknowledged",
@@ -472,7 +536,7 @@ async fn handle_control_message(
                         warn!("Unknown control command: {}", unknown);
                         client.publish(
                             "todo_worker/error",
-                            QoS::ExactlyOnce,
+                            mqtt::qos(),
                             false,
                             json!({
                                 "error": format!("Unknown command: {}", unknown),
@@ -487,7 +551,7 @@ async fn handle_control_message(
             error!("Failed to parse control message: {}", e);
             client.publish(
                 "todo_worker/error",
-                QoS::ExactlyOnce,
+                mqtt::qos(),
                 false,
                 json!({
                     "error": format!("Invalid control message: {}", e),
@@ -500,6 +564,23 @@ async fn handle_control_message(
     Ok(())
 }
 
+async fn start_scheduler(agent_registry: Arc<RwLock<AgentRegistry>>) -> Result<Option<task::JoinHandle<()>>> {
+    let Some(mut scheduler) = Scheduler::from_env()? else {
+        return Ok(None);
+    };
+    scheduler = scheduler
+        .with_agents(agent_registry)
+        .with_tools(Arc::new(ToolRegistry::create_default_tools().await?));
+    if secrets::get(secrets::MONGO_URI).is_some() {
+        scheduler = scheduler.with_todos(TodoList::shared().await?);
+    }
+    if let Some(notifier) = NotificationTool::from_env()? {
+        scheduler = scheduler.with_notifier(notifier);
+    }
+    info!("Scheduled jobs: {}", scheduler.job_names().join(", "));
+    Ok(Some(scheduler.spawn()))
+}
+
 async fn load_agents(agent_registry: &Arc<RwLock<AgentRegistry>>) -> Result<()> {
     let config_agents = agents::default_agents();
     for config in config_agents {
@@ -555,7 +636,7 @@ async fn process_agent_message(
                 "timestamp": chrono::Utc::now().to_rfc3339()
             }).to_string();
             
-            if let Err(e) = client.publish(error_topic, QoS::ExactlyOnce, false, error_payload).await {
+            if let Err(e) = client.publish(error_topic, mqtt::qos(), false, error_payload).await {
                 error!("Failed to publish error message: {}", e);
             }
             
@@ -574,9 +655,10 @@ async fn process_agent_message(
     
     info!("Processing task {} with priority {} (count: {})", task.id, priority_str, task_count);
     
+    let run = RunningTask::start(&task.id);
     let processing_result = tokio::time::timeout(
         Duration::from_secs(TASK_PROCESSING_TIMEOUT),
-        process_todo_for_agent(agent_registry, agent_name, &task, client)
+        process_todo_for_agent(agent_registry, agent_name, &task, client, run.token())
     ).await;
     
     match processing_result {
@@ -603,7 +685,7 @@ async fn process_agent_message(
                 "timestamp": chrono::Utc::now().to_rfc3339()
             }).to_string();
             
-            if let Err(e) = client.publish(error_topic, QoS::ExactlyOnce, false, error_payload).await {
+            if let Err(e) = client.publish(error_topic, mqtt::qos(), false, error_payload).await {
                 error!("Failed to publish error message: {}", e);
             }
         },
@@ -621,14 +703,15 @@ async fn process_agent_message(
                 "timestamp": chrono::Utc::now().to_rfc3339()
             }).to_string();
             
-            if let Err(e) = client.publish(error_topic, QoS::ExactlyOnce, false, error_payload).await {
+            if let Err(e) = client.publish(error_topic, mqtt::qos(), false, error_payload).await {
                 error!("Failed to publish timeout error message: {}", e);
             }
             
             // Try to mark the task as failed in the agent's todo list
             if let Some(agent) = agent_registry.read().await.get(agent_name) {
                 let todo_list = TodoProcessor::get_todo_list(agent);
-                if let Err(mark_err) = todo_list.mark_task_failed(&task.id).await {
+                let reason = format!("Timed out: task processing exceeded {} seconds", TASK_PROCESSING_TIMEOUT);
+                if let Err(mark_err) = todo_list.fail_attempt(&task.id, &reason).await {
                     error!("Failed to mark task as failed after timeout: {}", mark_err);
                 }
             }
@@ -641,6 +724,7 @@ async fn process_todo_for_agent(
     agent_name: &str,
     task: &TodoTask,
     mqtt_client: &Arc<AsyncClient>,
+    cancel: CancellationToken,
 ) -> Result<()> {
     // Get agent to process the task
     let registry = agent_registry.read().await;
@@ -651,7 +735,7 @@ async fn process_todo_for_agent(
     let start_time = Instant::now();
     
     // Process the task
-    match agent.process_task(task.clone()).await {
+    match agent.process_task(task.clone(), cancel).await {
         Ok(response) => {
             let processing_time = start_time.elapsed().as_millis();
             
@@ -664,20 +748,25 @@ async fn process_todo_for_agent(
                 "timestamp": chrono::Utc::now().to_rfc3339()
             }).to_string();
             
-            mqtt_client.publish(response_topic, QoS::ExactlyOnce, false, response_payload).await
+            mqtt_client.publish(response_topic, mqtt::qos(), false, response_payload).await
                 .context("Failed to publish response")?;
             
             // Mark task as completed
             let todo_list = TodoProcessor::get_todo_list(agent);
-            todo_list.mark_task_completed(&task.id).await
+            todo_list.complete_with_response(&task.id, agent_name, &response).await
                 .context("Failed to mark task as completed")?;
             
             Ok(())
         },
+        Err(e) if cancellation::is_cancelled(&e) => {
+            // Whoever cancelled the task has already marked it cancelled
+            info!("Task {} was cancelled", task.id);
+            Ok(())
+        },
         Err(e) => {
-            // Mark task as failed
+            // Record the failed attempt; the task is retried or dead-lettered
             let todo_list = TodoProcessor::get_todo_list(agent);
-            if let Err(mark_err) = todo_list.mark_task_failed(&task.id).await {
+            if let Err(mark_err) = todo_list.fail_attempt(&task.id, &e.to_string()).await {
                 error!("Failed to mark task as failed: {}", mark_err);
             }
             
@@ -689,7 +778,8 @@ async fn process_todo_for_agent(
 async fn check_agent_tasks(
     agent_registry: &Arc<RwLock<AgentRegistry>>, 
     mqtt_client: &Arc<AsyncClient>,
-    metrics: &Arc<Metrics>
+    metrics: &Arc<Metrics>,
+    shutdown: &Arc<ShutdownCoordinator>,
 ) -> Result<()> {
     debug!("Checking for pending agent tasks");
     
@@ -714,9 +804,17 @@ async fn check_agent_tasks(
                         Ok(permit) => permit,
                         Err(_) => {
                             debug!("Too many concurrent tasks, skipping task {} until next check", task.id);
+                            todo_list.release_task(&task.id).await?;
                             continue;
                         }
                     };
+                    let Some(in_flight) = shutdown.begin() else {
+                        debug!("Shutting down, leaving task {} for the next worker", task.id);
+                        todo_list.release_task(&task.id).await?;
+                        return Ok(());
+                    };
+                    let run = RunningTask::start(&task.id);
+                    let lease = todo_list.keep_lease(&task.id, run.token());
                     
                     // Clone necessary values for task processing
                     let agent_registry_clone = agent_registry.clone();
@@ -729,18 +827,22 @@ async fn check_agent_tasks(
                     let task_json = serde_json::to_string(&task)?;
                     let topic = format!("agent/{}/todo/process", agent_name);
                     
-                    // Add a processed flag to the JSON to avoid double-processing
+                    // Add a processed flag to the JSON to avoid double-processing, and the
+                    // correlation id this worker logs the task's processing under
+                    let correlation_id = correlation::new_id();
                     let mut task_json_value: serde_json::Value = serde_json::from_str(&task_json)?;
                     if let serde_json::Value::Object(ref mut obj) = task_json_value {
                         obj.insert("_processed_by_background".to_string(), serde_json::Value::Bool(true));
+                        obj.insert("correlation_id".to_string(), serde_json::Value::String(correlation_id.clone()));
                     }
                     let task_json = serde_json::to_string(&task_json_value)?;
                     
                     // Publish the task to the appropriate topic
-                    mqtt_client.publish(topic, QoS::ExactlyOnce, false, task_json).await?;
+                    mqtt_client.publish(topic, mqtt::qos(), false, task_json).await?;
                     
                     // Spawn a background task to handle the permit release after processing
-                    tokio::spawn(async move {
+                    tokio::spawn(correlation::scope(correlation_id, "worker", async move {
+                        let _lease = lease;
                         // Create a timeout for task processing
                         let processing_result = tokio::time::timeout(
                             Duration::from_secs(TASK_PROCESSING_TIMEOUT),
@@ -748,7 +850,8 @@ async fn check_agent_tasks(
                                 &agent_registry_clone, 
                                 &agent_name_clone, 
                                 &task_clone, 
-                                &mqtt_client_clone
+                                &mqtt_client_clone,
+                                run.token(),
                             )
                         ).await;
                         
@@ -765,12 +868,21 @@ async fn check_agent_tasks(
                                 metrics_clone.increment_timeout();
                                 metrics_clone.increment_failed();
                                 error!("Task {} processing timed out", task_clone.id);
+
+                                if let Some(agent) = agent_registry_clone.read().await.get(&agent_name_clone) {
+                                    let reason = format!("Timed out: task processing exceeded {} seconds", TASK_PROCESSING_TIMEOUT);
+                                    if let Err(mark_err) = TodoProcessor::get_todo_list(agent)
+                                        .fail_attempt(&task_clone.id, &reason).await {
+                                        error!("Failed to mark task as failed after timeout: {}", mark_err);
+                                    }
+                                }
                             }
                         }
                         
                         // The permit is automatically dropped here, releasing the semaphore
                         drop(permit);
-                    });
+                        drop(in_flight);
+                    }));
                 },
                 Ok(None) => {
                     // No tasks to process, continue checking other agents
@@ -804,13 +916,13 @@ async fn report_metrics(
     let metrics_json = metrics.get_metrics_json().await;
     
     let metrics_topic = "metrics/todo_worker";
-    mqtt_client.publish(metrics_topic, QoS::ExactlyOnce, false, metrics_json.to_string()).await?;
+    mqtt_client.publish(metrics_topic, mqtt::qos(), false, metrics_json.to_string()).await?;
     info!("Published metrics: {}", metrics_json);
     
     // Also publish health status
     let health_status = if metrics.is_healthy() { "healthy" } else { "unhealthy" };
     let health_topic = "health/todo_worker";
-    mqtt_client.publish(health_topic, QoS::ExactlyOnce, false, health_status).await?;
+    mqtt_client.publish(health_topic, mqtt::qos(), false, health_status).await?;
     
     Ok(())
 }
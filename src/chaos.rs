@@ -0,0 +1,182 @@
+//! Fault injection for resilience testing. Built with the `chaos` feature, AI calls time
+//! out, MongoDB operations fail and tools error at the rates set by `CHAOS_AI_TIMEOUT_RATE`,
+//! `CHAOS_MONGO_ERROR_RATE` and `CHAOS_TOOL_FAILURE_RATE` (each from 0 to 1, default 0), so
+//! retries, fallbacks and the offline queue can be watched at work before production.
+//! Without the feature the hooks never fail.
+
+use std::collections::HashMap;
+use std::env;
+use std::time::Duration;
+use anyhow::Result;
+use mongodb::error::Error as MongoError;
+use serde_json::Value;
+use crate::ai::{AiProvider, ChatResponse, JsonSchema};
+use crate::error::SwarmError;
+use crate::types::{Attachment, Tool};
+
+const INJECTED: &str = "injected by chaos mode";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Fault {
+    AiTimeout,
+    Mongo,
+    Tool,
+}
+
+/// How often each fault is injected
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ChaosConfig {
+    pub ai_timeout_rate: f64,
+    pub mongo_error_rate: f64,
+    pub tool_failure_rate: f64,
+    /// How long an AI call hangs before timing out, to trip callers' own timeouts
+    pub ai_delay: Duration,
+}
+
+impl ChaosConfig {
+    /// The rates, and `CHAOS_AI_DELAY_MS` for the AI delay (default 0)
+    pub fn from_env() -> Self {
+        fn rate(name: &str) -> f64 {
+            env::var(name).ok().and_then(|r| r.parse().ok()).unwrap_or(0.0_f64).clamp(0.0, 1.0)
+        }
+        Self {
+            ai_timeout_rate: rate("CHAOS_AI_TIMEOUT_RATE"),
+            mongo_error_rate: rate("CHAOS_MONGO_ERROR_RATE"),
+            tool_failure_rate: rate("CHAOS_TOOL_FAILURE_RATE"),
+            ai_delay: env::var("CHAOS_AI_DELAY_MS").ok()
+                .and_then(|ms| ms.parse().ok())
+                .map(Duration::from_millis)
+                .unwrap_or_default(),
+        }
+    }
+
+    pub fn rate(&self, fault: Fault) -> f64 {
+        match fault {
+            Fault::AiTimeout => self.ai_timeout_rate,
+            Fault::Mongo => self.mongo_error_rate,
+            Fault::Tool => self.tool_failure_rate,
+        }
+    }
+
+    /// Whether a `roll` drawn uniformly from [0, 1) injects `fault`
+    pub fn strikes(&self, fault: Fault, roll: f64) -> bool {
+        roll < self.rate(fault)
+    }
+}
+
+#[cfg(feature = "chaos")]
+fn config() -> &'static ChaosConfig {
+    static CONFIG: std::sync::OnceLock<ChaosConfig> = std::sync::OnceLock::new();
+    CONFIG.get_or_init(|| {
+        let config = ChaosConfig::from_env();
+        if [Fault::AiTimeout, Fault::Mongo, Fault::Tool].iter().any(|&fault| config.rate(fault) > 0.0) {
+            tracing::warn!("Chaos mode is injecting faults: {:?}", config);
+        }
+        config
+    })
+}
+
+#[cfg(feature = "chaos")]
+fn injects(fault: Fault) -> bool {
+    let injected = config().strikes(fault, rand::random());
+    if injected {
+        tracing::debug!("Injecting {:?}", fault);
+    }
+    injected
+}
+
+#[cfg(not(feature = "chaos"))]
+fn injects(_fault: Fault) -> bool {
+    false
+}
+
+/// Call before a MongoDB operation. The error looks like a dropped connection, so callers
+/// treat it as the server being unreachable.
+pub fn mongo() -> Result<(), MongoError> {
+    if injects(Fault::Mongo) {
+        return Err(std::io::Error::new(std::io::ErrorKind::ConnectionReset, INJECTED).into());
+    }
+    Ok(())
+}
+
+/// Call before running the tool `name`
+pub fn tool(name: &str) -> Result<()> {
+    if injects(Fault::Tool) {
+        return Err(SwarmError::tool_failure(name, INJECTED).into());
+    }
+    Ok(())
+}
+
+/// Passes calls through to `inner`, except those chosen to time out
+pub struct ChaosAiClient<P> {
+    inner: P,
+    delay: Duration,
+}
+
+impl<P: AiProvider> ChaosAiClient<P> {
+    pub fn new(inner: P, delay: Duration) -> Self {
+        Self { inner, delay }
+    }
+
+    async fn maybe_time_out(&self) -> Result<()> {
+        if !injects(Fault::AiTimeout) {
+            return Ok(());
+        }
+        tokio::time::sleep(self.delay).await;
+        Err(SwarmError::AiProvider(format!("timed out ({})", INJECTED)).into())
+    }
+}
+
+/// `inner` wrapped so it times out at the configured rate
+#[cfg(feature = "chaos")]
+pub fn ai<P: AiProvider>(inner: P) -> ChaosAiClient<P> {
+    ChaosAiClient::new(inner, config().ai_delay)
+}
+
+#[async_trait::async_trait]
+impl<P: AiProvider> AiProvider for ChaosAiClient<P> {
+    async fn chat(&self, system_prompt: &str, messages: Vec<HashMap<String, String>>) -> Result<String> {
+        self.maybe_time_out().await?;
+        self.inner.chat(system_prompt, messages).await
+    }
+
+    async fn chat_with_tools(&self, system_prompt: &str, messages: Vec<HashMap<String, String>>, tools: &[Tool]) -> Result<ChatResponse> {
+        self.maybe_time_out().await?;
+        self.inner.chat_with_tools(system_prompt, messages, tools).await
+    }
+
+    async fn chat_structured(&self, system_prompt: &str, messages: Vec<HashMap<String, String>>, schema: &JsonSchema) -> Result<Value> {
+        self.maybe_time_out().await?;
+        self.inner.chat_structured(system_prompt, messages, schema).await
+    }
+
+    async fn embed(&self, text: &str) -> Result<Vec<f32>> {
+        self.maybe_time_out().await?;
+        self.inner.embed(text).await
+    }
+
+    async fn chat_with_images(&self, system_prompt: &str, messages: Vec<HashMap<String, String>>, images: &[Attachment]) -> Result<String> {
+        self.maybe_time_out().await?;
+        self.inner.chat_with_images(system_prompt, messages, images).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_strikes_at_the_configured_rate() {
+        let config = ChaosConfig { mongo_error_rate: 0.25, tool_failure_rate: 1.0, ..ChaosConfig::default() };
+        assert!(config.strikes(Fault::Mongo, 0.1));
+        assert!(!config.strikes(Fault::Mongo, 0.25));
+        assert!(config.strikes(Fault::Tool, 0.999));
+        assert!(!config.strikes(Fault::AiTimeout, 0.0), "a zero rate never injects");
+    }
+
+    #[test]
+    fn test_injected_mongo_errors_look_unreachable() {
+        let err: MongoError = std::io::Error::new(std::io::ErrorKind::ConnectionReset, INJECTED).into();
+        assert!(crate::types::offline_queue::is_unreachable(&err));
+    }
+}
@@ -1,8 +1,14 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::Path;
 use serde::{Deserialize, Serialize};
 use crate::types::{AgentConfig, Tool, ToolParameter};
 use crate::Result;
 
+pub mod reload;
+pub mod mqtt;
+pub mod mongo;
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AgentSet {
     pub name: String,
@@ -67,6 +73,130 @@ pub fn get_transfer_tool() -> Tool {
     }
 }
 
+/// Agent configs from a JSON or YAML file holding an agent set, a list of agents or a single
+/// agent, or from a `swarmonomicon.toml`
+pub fn load_agent_configs(path: impl AsRef<Path>) -> Result<Vec<AgentConfig>> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum ConfigFile {
+        Set(AgentSet),
+        List(Vec<AgentConfig>),
+        Single(Box<AgentConfig>),
+    }
+
+    let path = path.as_ref();
+    if path.extension().and_then(|e| e.to_str()) == Some("toml") {
+        return Ok(reload::SwarmConfig::from_file(path)?.agent_configs()?);
+    }
+    let source = fs::read_to_string(path)
+        .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+    let yaml = matches!(path.extension().and_then(|e| e.to_str()), Some("yaml" | "yml"));
+    let parsed: ConfigFile = if yaml {
+        serde_yaml::from_str(&source).map_err(|e| format!("{}: {}", path.display(), e))?
+    } else {
+        serde_json::from_str(&source).map_err(|e| format!("{}: {}", path.display(), e))?
+    };
+    Ok(match parsed {
+        ConfigFile::Set(set) => set.agents,
+        ConfigFile::List(agents) => agents,
+        ConfigFile::Single(agent) => vec![*agent],
+    })
+}
+
+/// Problems with a set of agent configs: empty or duplicate names, downstream agents
+/// that aren't defined, and invalid state machines
+pub fn validate_agent_configs(agents: &[AgentConfig]) -> Vec<String> {
+    let mut problems = Vec::new();
+    let mut names = HashSet::new();
+    for agent in agents {
+        if agent.name.trim().is_empty() {
+            problems.push("agent with an empty name".to_string());
+        } else if !names.insert(agent.name.as_str()) {
+            problems.push(format!("agent '{}' is defined more than once", agent.name));
+        }
+    }
+
+    problems.extend(dependency_problems(agents, &names, cycles_allowed()));
+    for agent in agents {
+        if let Some(machine) = &agent.state_machine {
+            for error in machine.validate() {
+                problems.push(format!("agent '{}': {}", agent.name, error.message));
+            }
+        }
+    }
+    problems
+}
+
+/// Whether downstream agents may lead back to an agent that transferred to them. Swarms
+/// whose agents hand conversations back and forth set `ALLOW_AGENT_CYCLES=true`.
+pub fn cycles_allowed() -> bool {
+    std::env::var("ALLOW_AGENT_CYCLES").is_ok_and(|value| matches!(value.trim(), "1" | "true" | "yes"))
+}
+
+/// `true`/`false` in the spellings environment variables use: `1`/`0`, `true`/`false`, `yes`/`no`
+pub(crate) fn parse_bool(value: &str) -> Option<bool> {
+    match value.trim().to_lowercase().as_str() {
+        "1" | "true" | "yes" => Some(true),
+        "0" | "false" | "no" => Some(false),
+        _ => None,
+    }
+}
+
+/// Downstream agents missing from `defined`, and cycles among the agents unless allowed
+pub fn dependency_problems(agents: &[AgentConfig], defined: &HashSet<&str>, allow_cycles: bool) -> Vec<String> {
+    let mut problems = Vec::new();
+    for agent in agents {
+        for downstream in &agent.downstream_agents {
+            if !defined.contains(downstream.as_str()) {
+                problems.push(format!("agent '{}' lists undefined downstream agent '{}'", agent.name, downstream));
+            }
+        }
+    }
+    if !allow_cycles {
+        for cycle in downstream_cycles(agents) {
+            problems.push(format!("downstream agents form a cycle: {}", cycle.join(" -> ")));
+        }
+    }
+    problems
+}
+
+/// Cycles in the downstream graph, each listing its agents from the first one reached
+/// back to that agent again
+pub fn downstream_cycles(agents: &[AgentConfig]) -> Vec<Vec<String>> {
+    fn visit<'a>(
+        name: &'a str,
+        edges: &HashMap<&'a str, &'a [String]>,
+        path: &mut Vec<&'a str>,
+        done: &mut HashSet<&'a str>,
+        cycles: &mut Vec<Vec<String>>,
+    ) {
+        if let Some(start) = path.iter().position(|visited| *visited == name) {
+            let mut cycle: Vec<String> = path[start..].iter().map(|agent| agent.to_string()).collect();
+            cycle.push(name.to_string());
+            cycles.push(cycle);
+            return;
+        }
+        if !done.insert(name) {
+            return;
+        }
+        path.push(name);
+        for next in edges.get(name).copied().unwrap_or_default() {
+            visit(next, edges, path, done, cycles);
+        }
+        path.pop();
+    }
+
+    let edges: HashMap<&str, &[String]> = agents.iter()
+        .map(|agent| (agent.name.as_str(), agent.downstream_agents.as_slice()))
+        .collect();
+    let mut done = HashSet::new();
+    let mut cycles = Vec::new();
+    for agent in agents {
+        visit(&agent.name, &edges, &mut Vec::new(), &mut done, &mut cycles);
+    }
+    cycles
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -84,6 +214,10 @@ mod tests {
                     downstream_agents: vec!["haiku".to_string()],
                     personality: None,
                     state_machine: None,
+                    script: None,
+                    max_concurrency: None,
+                    middleware: None,
+                    remote: None,
                 },
             ],
         }
@@ -111,4 +245,49 @@ mod tests {
         assert_eq!(agent.tools.len(), 1);
         assert_eq!(agent.tools[0].name, "agent_transfer");
     }
+
+    #[test]
+    fn test_validate_agent_configs() {
+        let mut agents = create_test_agent_set().agents;
+        assert_eq!(validate_agent_configs(&agents), vec!["agent 'greeter' lists undefined downstream agent 'haiku'"]);
+
+        agents.push(AgentConfig { downstream_agents: vec![], ..agents[0].clone() });
+        agents[1].name = "haiku".to_string();
+        assert!(validate_agent_configs(&agents).is_empty());
+
+        agents.push(agents[1].clone());
+        assert_eq!(validate_agent_configs(&agents), vec!["agent 'haiku' is defined more than once"]);
+    }
+
+    #[test]
+    fn test_downstream_cycles() {
+        let agent = |name: &str, downstream: &[&str]| AgentConfig {
+            downstream_agents: downstream.iter().map(|d| d.to_string()).collect(),
+            ..AgentConfig::builder(name).build().unwrap()
+        };
+        let agents = vec![agent("greeter", &["haiku", "git"]), agent("haiku", &["greeter"]), agent("git", &[])];
+        assert_eq!(downstream_cycles(&agents), vec![vec!["greeter", "haiku", "greeter"]]);
+
+        let defined: HashSet<&str> = ["greeter", "haiku"].into();
+        assert_eq!(dependency_problems(&agents, &defined, false), vec![
+            "agent 'greeter' lists undefined downstream agent 'git'",
+            "downstream agents form a cycle: greeter -> haiku -> greeter",
+        ]);
+        assert_eq!(dependency_problems(&agents, &defined, true).len(), 1);
+        assert!(downstream_cycles(&agents[1..]).is_empty());
+    }
+
+    #[test]
+    fn test_load_agent_configs() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("agents.yaml");
+        fs::write(&path, serde_yaml::to_string(&create_test_agent_set()).unwrap()).unwrap();
+        let agents = load_agent_configs(&path).unwrap();
+        assert_eq!(agents.len(), 1);
+        assert_eq!(agents[0].downstream_agents, vec!["haiku"]);
+
+        let path = dir.path().join("greeter.json");
+        fs::write(&path, serde_json::to_string(&agents[0]).unwrap()).unwrap();
+        assert_eq!(load_agent_configs(&path).unwrap()[0].name, "greeter");
+    }
 }
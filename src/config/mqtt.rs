@@ -0,0 +1,242 @@
+//! How processes connect to the MQTT broker, read from the environment:
+//!
+//! - `MQTT_HOST` / `MQTT_PORT`: the broker; `AWSIP` / `AWSPORT` are read when unset.
+//!   Defaults to `localhost`, on 1883, or 8883 with TLS.
+//! - `MQTT_USERNAME` / `MQTT_PASSWORD`: credentials, looked up like the other secrets
+//! - `MQTT_TLS`: connect over TLS, checking the broker against the system roots. Implied by
+//!   `MQTT_CA_FILE`, the PEM CA certificates to check it against instead.
+//! - `MQTT_CLIENT_CERT` / `MQTT_CLIENT_KEY`: PEM certificate and key for mutual TLS
+//! - `MQTT_CLIENT_ID`: the client id of a process's main connection, in place of its own
+//! - `MQTT_QOS`: `0`, `1` or `2` (the default) for subscriptions and publishes
+//! - `MQTT_CLEAN_SESSION`: `false` to have the broker keep subscriptions and queued messages
+//!   across reconnects (default `true`)
+//! - `MQTT_KEEP_ALIVE_SECS`: keep-alive interval (default 30)
+
+use std::path::PathBuf;
+use std::sync::OnceLock;
+use std::time::Duration;
+use anyhow::{Result, anyhow};
+use rumqttc::{MqttOptions, QoS, Transport};
+use crate::error::SwarmError;
+use super::parse_bool;
+
+pub const DEFAULT_PORT: u16 = 1883;
+pub const DEFAULT_TLS_PORT: u16 = 8883;
+const DEFAULT_KEEP_ALIVE_SECS: u64 = 30;
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MqttTls {
+    /// CA certificates the broker is checked against; the system roots when unset
+    pub ca_file: Option<PathBuf>,
+    /// Client certificate for brokers that require mutual TLS; needs `client_key` and `ca_file`
+    pub client_cert: Option<PathBuf>,
+    pub client_key: Option<PathBuf>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct MqttConfig {
+    pub host: String,
+    pub port: u16,
+    /// Overrides the client id each process picks for itself
+    pub client_id: Option<String>,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    pub tls: Option<MqttTls>,
+    pub qos: QoS,
+    pub clean_session: bool,
+    pub keep_alive: Duration,
+}
+
+impl Default for MqttConfig {
+    fn default() -> Self {
+        Self {
+            host: "localhost".to_string(),
+            port: DEFAULT_PORT,
+            client_id: None,
+            username: None,
+            password: None,
+            tls: None,
+            qos: QoS::ExactlyOnce,
+            clean_session: true,
+            keep_alive: Duration::from_secs(DEFAULT_KEEP_ALIVE_SECS),
+        }
+    }
+}
+
+impl MqttConfig {
+    pub fn from_env() -> Result<Self> {
+        Self::from_lookup(|key| match key {
+            "MQTT_USERNAME" | "MQTT_PASSWORD" => crate::secrets::get(key),
+            _ => std::env::var(key).ok().filter(|value| !value.is_empty()),
+        })
+    }
+
+    /// The config from the variables `lookup` returns
+    pub fn from_lookup(lookup: impl Fn(&str) -> Option<String>) -> Result<Self> {
+        let invalid = |key: &str, value: &str| -> anyhow::Error {
+            SwarmError::Validation(format!("{} has an invalid value '{}'", key, value)).into()
+        };
+        let flag = |key: &str| -> Result<Option<bool>> {
+            lookup(key).map(|value| parse_bool(&value).ok_or_else(|| invalid(key, &value))).transpose()
+        };
+
+        let ca_file = lookup("MQTT_CA_FILE").map(PathBuf::from);
+        let client_cert = lookup("MQTT_CLIENT_CERT").map(PathBuf::from);
+        let client_key = lookup("MQTT_CLIENT_KEY").map(PathBuf::from);
+        let tls = flag("MQTT_TLS")?.unwrap_or(ca_file.is_some() || client_cert.is_some());
+        let tls = tls.then_some(MqttTls { ca_file, client_cert, client_key });
+
+        let port = match lookup("MQTT_PORT").or_else(|| lookup("AWSPORT")) {
+            Some(port) => port.parse().map_err(|_| invalid("MQTT_PORT", &port))?,
+            None if tls.is_some() => DEFAULT_TLS_PORT,
+            None => DEFAULT_PORT,
+        };
+        let defaults = Self::default();
+        let config = Self {
+            host: lookup("MQTT_HOST").or_else(|| lookup("AWSIP")).unwrap_or(defaults.host),
+            port,
+            client_id: lookup("MQTT_CLIENT_ID"),
+            username: lookup("MQTT_USERNAME"),
+            password: lookup("MQTT_PASSWORD"),
+            tls,
+            qos: match lookup("MQTT_QOS") {
+                Some(qos) => parse_qos(&qos).ok_or_else(|| invalid("MQTT_QOS", &qos))?,
+                None => defaults.qos,
+            },
+            clean_session: flag("MQTT_CLEAN_SESSION")?.unwrap_or(defaults.clean_session),
+            keep_alive: match lookup("MQTT_KEEP_ALIVE_SECS") {
+                Some(secs) => Duration::from_secs(secs.parse().map_err(|_| invalid("MQTT_KEEP_ALIVE_SECS", &secs))?),
+                None => defaults.keep_alive,
+            },
+        };
+        config.validate()?;
+        Ok(config)
+    }
+
+    fn validate(&self) -> Result<()> {
+        let invalid = |message: &str| -> Result<()> { Err(SwarmError::Validation(message.to_string()).into()) };
+        if self.username.is_some() != self.password.is_some() {
+            return invalid("MQTT_USERNAME and MQTT_PASSWORD must be set together");
+        }
+        if let Some(tls) = &self.tls {
+            if tls.client_cert.is_some() != tls.client_key.is_some() {
+                return invalid("MQTT_CLIENT_CERT and MQTT_CLIENT_KEY must be set together");
+            }
+            if tls.client_cert.is_some() && tls.ca_file.is_none() {
+                return invalid("Mutual TLS needs MQTT_CA_FILE");
+            }
+        }
+        Ok(())
+    }
+
+    /// `MQTT_CLIENT_ID`, or `default` for processes without one configured
+    pub fn client_id(&self, default: &str) -> String {
+        self.client_id.clone().unwrap_or_else(|| default.to_string())
+    }
+
+    /// Client options connecting as `client_id`, reading any certificates from disk
+    pub fn options(&self, client_id: &str) -> Result<MqttOptions> {
+        let mut options = MqttOptions::new(client_id, &self.host, self.port);
+        options.set_keep_alive(self.keep_alive);
+        options.set_clean_session(self.clean_session);
+        if let (Some(username), Some(password)) = (&self.username, &self.password) {
+            options.set_credentials(username, password);
+        }
+        if let Some(tls) = &self.tls {
+            options.set_transport(tls.transport()?);
+        }
+        Ok(options)
+    }
+}
+
+impl MqttTls {
+    fn transport(&self) -> Result<Transport> {
+        let Some(ca_file) = &self.ca_file else {
+            return Ok(Transport::tls_with_default_config());
+        };
+        let read = |path: &PathBuf| std::fs::read(path).map_err(|e| anyhow!("Failed to read {}: {}", path.display(), e));
+        let client_auth = match (&self.client_cert, &self.client_key) {
+            (Some(cert), Some(key)) => Some((read(cert)?, read(key)?)),
+            _ => None,
+        };
+        Ok(Transport::tls(read(ca_file)?, client_auth, None))
+    }
+}
+
+/// `0`, `1` or `2`, or the level's name, e.g. `at_least_once`
+pub fn parse_qos(value: &str) -> Option<QoS> {
+    match value.trim().to_lowercase().as_str() {
+        "0" | "at_most_once" => Some(QoS::AtMostOnce),
+        "1" | "at_least_once" => Some(QoS::AtLeastOnce),
+        "2" | "exactly_once" => Some(QoS::ExactlyOnce),
+        _ => None,
+    }
+}
+
+/// The QoS in `MQTT_QOS`, for processes that publish from many places. An invalid value
+/// is logged once and the default used.
+pub fn qos() -> QoS {
+    static QOS: OnceLock<QoS> = OnceLock::new();
+    *QOS.get_or_init(|| match std::env::var("MQTT_QOS") {
+        Ok(value) => parse_qos(&value).unwrap_or_else(|| {
+            tracing::warn!("Ignoring invalid MQTT_QOS '{}'", value);
+            MqttConfig::default().qos
+        }),
+        Err(_) => MqttConfig::default().qos,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn config(vars: &[(&str, &str)]) -> Result<MqttConfig> {
+        let vars: HashMap<String, String> = vars.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect();
+        MqttConfig::from_lookup(|key| vars.get(key).cloned())
+    }
+
+    #[test]
+    fn test_defaults_and_legacy_variables() -> Result<()> {
+        assert_eq!(config(&[])?, MqttConfig::default());
+        let legacy = config(&[("AWSIP", "10.0.0.5"), ("AWSPORT", "3003")])?;
+        assert_eq!((legacy.host.as_str(), legacy.port), ("10.0.0.5", 3003));
+        let explicit = config(&[("MQTT_HOST", "broker"), ("AWSIP", "10.0.0.5")])?;
+        assert_eq!(explicit.host, "broker");
+        Ok(())
+    }
+
+    #[test]
+    fn test_tls_auth_and_session_settings() -> Result<()> {
+        let config = config(&[
+            ("MQTT_CA_FILE", "/etc/mqtt/ca.pem"),
+            ("MQTT_CLIENT_CERT", "/etc/mqtt/client.pem"),
+            ("MQTT_CLIENT_KEY", "/etc/mqtt/client.key"),
+            ("MQTT_USERNAME", "worker"),
+            ("MQTT_PASSWORD", "s3cret"),
+            ("MQTT_CLIENT_ID", "worker-1"),
+            ("MQTT_QOS", "1"),
+            ("MQTT_CLEAN_SESSION", "false"),
+        ])?;
+        assert_eq!(config.port, DEFAULT_TLS_PORT);
+        assert_eq!(config.tls.as_ref().and_then(|tls| tls.client_key.clone()), Some(PathBuf::from("/etc/mqtt/client.key")));
+        assert_eq!((config.qos, config.clean_session), (QoS::AtLeastOnce, false));
+
+        let options = MqttConfig { tls: None, ..config.clone() }.options(&config.client_id("todo_worker"))?;
+        assert_eq!(options.client_id(), "worker-1");
+        assert_eq!(options.credentials(), Some(("worker".to_string(), "s3cret".to_string())));
+        assert!(!options.clean_session());
+        Ok(())
+    }
+
+    #[test]
+    fn test_incomplete_or_invalid_settings_are_rejected() {
+        let is_validation = |result: Result<MqttConfig>| matches!(SwarmError::find(&result.unwrap_err()), Some(SwarmError::Validation(_)));
+        assert!(is_validation(config(&[("MQTT_USERNAME", "worker")])));
+        assert!(is_validation(config(&[("MQTT_CA_FILE", "ca.pem"), ("MQTT_CLIENT_CERT", "client.pem")])));
+        assert!(is_validation(config(&[("MQTT_TLS", "true"), ("MQTT_CLIENT_CERT", "c.pem"), ("MQTT_CLIENT_KEY", "c.key")])));
+        assert!(is_validation(config(&[("MQTT_QOS", "3")])));
+        assert!(is_validation(config(&[("MQTT_CLEAN_SESSION", "sometimes")])));
+        assert!(config(&[("MQTT_TLS", "true")]).is_ok_and(|config| config.tls == Some(MqttTls::default())));
+    }
+}
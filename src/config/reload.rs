@@ -0,0 +1,334 @@
+//! Agents defined in `swarmonomicon.toml` (or the file `SWARM_CONFIG` names), reloaded
+//! while the process runs. The file lists agents like `AgentConfig`, plus an optional
+//! directory of prompt files whose text replaces an agent's instructions:
+//!
+//! ```toml
+//! prompts_dir = "prompts"   # prompts/greeter.md becomes the greeter's instructions
+//!
+//! [[agents]]
+//! name = "greeter"
+//! public_description = "Agent that greets the user."
+//! downstream_agents = ["haiku"]
+//!
+//! [[agents]]
+//! name = "haiku"
+//! ```
+//!
+//! `ConfigReloader` polls the file and prompts for changes and brings the registry in line:
+//! new agents are registered, agents whose config or prompt changed are replaced, and removed
+//! agents are deregistered once their in-flight messages drain. A file that fails to load or
+//! validate is logged and the running agents are kept.
+
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+use anyhow::{Result, anyhow};
+use serde::Deserialize;
+use tokio::sync::RwLock;
+use tokio::task::JoinHandle;
+use crate::agents::{AgentRegistry, AgentWrapper, capability_catalog, create_agent, create_routed_agent};
+use crate::error::SwarmError;
+use crate::types::{Agent, AgentConfig};
+
+pub const SWARM_CONFIG_ENV: &str = "SWARM_CONFIG";
+pub const DEFAULT_SWARM_CONFIG: &str = "swarmonomicon.toml";
+const PROMPT_EXTENSIONS: [&str; 2] = ["md", "txt"];
+const DEFAULT_POLL_SECS: u64 = 2;
+const DEFAULT_DRAIN_SECS: u64 = 30;
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct SwarmConfig {
+    /// Directory of `<agent>.md` or `<agent>.txt` prompts, relative to the config file
+    #[serde(default)]
+    pub prompts_dir: Option<PathBuf>,
+    #[serde(default)]
+    pub agents: Vec<AgentConfig>,
+}
+
+impl SwarmConfig {
+    /// The file named by `SWARM_CONFIG`, else `swarmonomicon.toml` if it exists
+    pub fn path_from_env() -> Option<PathBuf> {
+        std::env::var(SWARM_CONFIG_ENV).ok().map(PathBuf::from)
+            .or_else(|| Some(PathBuf::from(DEFAULT_SWARM_CONFIG)).filter(|path| path.exists()))
+    }
+
+    /// Parse `path`, resolving `prompts_dir` against the file's directory
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let source = std::fs::read_to_string(path).map_err(|e| anyhow!("Failed to read {}: {}", path.display(), e))?;
+        let mut config: Self = toml::from_str(&source).map_err(|e| anyhow!("{}: {}", path.display(), e))?;
+        if let (Some(dir), Some(base)) = (&config.prompts_dir, path.parent()) {
+            config.prompts_dir = Some(base.join(dir));
+        }
+        Ok(config)
+    }
+
+    /// The agents, with instructions taken from their prompt files where there is one
+    pub fn agent_configs(&self) -> Result<Vec<AgentConfig>> {
+        let mut agents = self.agents.clone();
+        let Some(dir) = &self.prompts_dir else {
+            return Ok(agents);
+        };
+        for agent in &mut agents {
+            let prompt = PROMPT_EXTENSIONS.iter()
+                .map(|extension| dir.join(format!("{}.{}", agent.name, extension)))
+                .find(|path| path.is_file());
+            if let Some(path) = prompt {
+                agent.instructions = std::fs::read_to_string(&path)
+                    .map_err(|e| anyhow!("Failed to read {}: {}", path.display(), e))?
+                    .trim_end()
+                    .to_string();
+            }
+        }
+        Ok(agents)
+    }
+
+    /// Load `path` and check its agents, failing with every problem found
+    pub fn load(path: impl AsRef<Path>) -> Result<Vec<AgentConfig>> {
+        let agents = Self::from_file(path)?.agent_configs()?;
+        let problems = super::validate_agent_configs(&agents);
+        if !problems.is_empty() {
+            return Err(SwarmError::Validation(problems.join("; ")).into());
+        }
+        Ok(agents)
+    }
+}
+
+/// What changes between the agents running and the agents configured
+#[derive(Debug, Clone, Default)]
+pub struct ReloadPlan {
+    pub added: Vec<AgentConfig>,
+    pub changed: Vec<AgentConfig>,
+    pub removed: Vec<String>,
+}
+
+impl ReloadPlan {
+    pub fn between(running: &[AgentConfig], desired: &[AgentConfig]) -> Self {
+        let mut plan = Self::default();
+        for config in desired {
+            match running.iter().find(|current| current.name == config.name) {
+                None => plan.added.push(config.clone()),
+                Some(current) if !same_config(current, config) => plan.changed.push(config.clone()),
+                Some(_) => {}
+            }
+        }
+        let names: HashSet<&str> = desired.iter().map(|config| config.name.as_str()).collect();
+        plan.removed = running.iter()
+            .filter(|config| !names.contains(config.name.as_str()))
+            .map(|config| config.name.clone())
+            .collect();
+        // The greeter's routing catalog is fixed when it's built
+        let rebuilt = plan.added.iter().chain(&plan.changed).any(|config| config.name == "greeter");
+        if (!plan.added.is_empty() || !plan.removed.is_empty()) && !rebuilt {
+            plan.changed.extend(desired.iter().find(|config| config.name == "greeter").cloned());
+        }
+        plan
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.changed.is_empty() && self.removed.is_empty()
+    }
+}
+
+fn same_config(a: &AgentConfig, b: &AgentConfig) -> bool {
+    serde_json::to_value(a).ok() == serde_json::to_value(b).ok()
+}
+
+/// Modification times of the config file and every prompt, to notice edits
+type Fingerprint = Vec<(PathBuf, Option<SystemTime>, u64)>;
+
+fn fingerprint(path: &Path, prompts_dir: Option<&Path>) -> Fingerprint {
+    let stamp = |path: PathBuf| {
+        let metadata = std::fs::metadata(&path).ok();
+        let modified = metadata.as_ref().and_then(|m| m.modified().ok());
+        let len = metadata.map(|m| m.len()).unwrap_or_default();
+        (path, modified, len)
+    };
+    let mut files = vec![stamp(path.to_path_buf())];
+    if let Some(entries) = prompts_dir.and_then(|dir| std::fs::read_dir(dir).ok()) {
+        let mut prompts: Vec<_> = entries.flatten().map(|entry| stamp(entry.path())).collect();
+        prompts.sort_by(|a, b| a.0.cmp(&b.0));
+        files.extend(prompts);
+    }
+    files
+}
+
+pub struct ConfigReloader {
+    path: PathBuf,
+    registry: Arc<RwLock<AgentRegistry>>,
+    /// The agents last applied from the file; agents registered otherwise are left alone
+    applied: Vec<AgentConfig>,
+    seen: Fingerprint,
+    drain_timeout: Duration,
+}
+
+impl ConfigReloader {
+    /// Watch `path` for a registry already running `applied`
+    pub fn new(path: impl Into<PathBuf>, registry: Arc<RwLock<AgentRegistry>>, applied: Vec<AgentConfig>) -> Self {
+        let path = path.into();
+        let prompts_dir = SwarmConfig::from_file(&path).ok().and_then(|config| config.prompts_dir);
+        Self {
+            seen: fingerprint(&path, prompts_dir.as_deref()),
+            path,
+            registry,
+            applied,
+            drain_timeout: Duration::from_secs(DEFAULT_DRAIN_SECS),
+        }
+    }
+
+    /// How long removed and replaced agents get to finish their messages
+    pub fn with_drain_timeout(mut self, timeout: Duration) -> Self {
+        self.drain_timeout = timeout;
+        self
+    }
+
+    /// Reload if the file or a prompt changed since the last check, returning what was applied.
+    /// A reload that fails is retried on the next check.
+    pub async fn check(&mut self) -> Result<Option<ReloadPlan>> {
+        let config = SwarmConfig::from_file(&self.path);
+        let prompts_dir = config.as_ref().ok().and_then(|config| config.prompts_dir.clone());
+        let seen = fingerprint(&self.path, prompts_dir.as_deref());
+        if seen == self.seen {
+            return Ok(None);
+        }
+        let desired = SwarmConfig::load(&self.path)?;
+        let plan = ReloadPlan::between(&self.applied, &desired);
+        if !plan.is_empty() {
+            self.apply(&plan).await?;
+        }
+        self.applied = desired;
+        self.seen = seen;
+        Ok(Some(plan))
+    }
+
+    async fn apply(&self, plan: &ReloadPlan) -> Result<()> {
+        // Build every new agent before touching the registry, so a failure leaves it as it was
+        let (todos, running) = {
+            let registry = self.registry.read().await;
+            (registry.todo_list().await?, registry.agents.clone())
+        };
+        let (greeters, others): (Vec<&AgentConfig>, Vec<&AgentConfig>) = plan.added.iter()
+            .chain(&plan.changed)
+            .partition(|config| config.name == "greeter");
+        let mut built = HashMap::new();
+        for config in others {
+            let agent = create_agent(config.clone()).await
+                .map_err(|e| anyhow!("Failed to create agent {}: {}", config.name, e))?;
+            built.insert(config.name.clone(), AgentWrapper::new(agent, todos.for_agent(&config.name)));
+        }
+        // Greeters route by the other agents' capabilities, so build them last
+        if !greeters.is_empty() {
+            let mut agents: HashMap<&String, &AgentWrapper> = running.iter()
+                .filter(|(name, _)| !plan.removed.contains(name))
+                .collect();
+            agents.extend(&built);
+            let catalog = capability_catalog(agents).await;
+            for config in greeters {
+                let agent = create_routed_agent(config.clone(), catalog.clone()).await
+                    .map_err(|e| anyhow!("Failed to create agent {}: {}", config.name, e))?;
+                built.insert(config.name.clone(), AgentWrapper::new(agent, todos.for_agent(&config.name)));
+            }
+        }
+
+        let mut retired = Vec::new();
+        {
+            let mut registry = self.registry.write().await;
+            for name in &plan.removed {
+                retired.extend(registry.deregister(name).map(|agent| (name.clone(), agent)));
+            }
+            for (name, agent) in built {
+                retired.extend(registry.insert(name.clone(), agent).map(|previous| (name, previous)));
+            }
+        }
+        // New messages already reach the new agents; let the old ones finish theirs
+        for (name, agent) in retired {
+            retire(&name, agent, self.drain_timeout).await;
+        }
+        Ok(())
+    }
+
+    /// Check for changes every `interval`
+    pub fn spawn(mut self, interval: Duration) -> JoinHandle<()> {
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            loop {
+                ticker.tick().await;
+                match self.check().await {
+                    Ok(Some(plan)) => tracing::info!(
+                        "Reloaded {}: {} added, {} changed, {} removed",
+                        self.path.display(), plan.added.len(), plan.changed.len(), plan.removed.len()
+                    ),
+                    Ok(None) => {}
+                    Err(e) => tracing::error!("Keeping the running agents; {} could not be reloaded: {}", self.path.display(), e),
+                }
+            }
+        })
+    }
+}
+
+/// Poll interval from `SWARM_CONFIG_POLL_SECS`, defaulting to 2 seconds
+pub fn poll_interval() -> Duration {
+    let secs = std::env::var("SWARM_CONFIG_POLL_SECS").ok().and_then(|s| s.parse().ok()).unwrap_or(DEFAULT_POLL_SECS);
+    Duration::from_secs(secs.max(1))
+}
+
+async fn retire(name: &str, agent: AgentWrapper, timeout: Duration) {
+    if !agent.drain(timeout).await {
+        tracing::warn!("Agent {} still had messages in flight after {:?}", name, timeout);
+    }
+    if let Err(e) = agent.shutdown().await {
+        tracing::warn!("Failed to shut down agent {}: {}", name, e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_config_file_and_prompts() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir(dir.path().join("prompts")).unwrap();
+        std::fs::write(dir.path().join("prompts/greeter.md"), "Welcome everyone warmly.\n").unwrap();
+        let path = dir.path().join(DEFAULT_SWARM_CONFIG);
+        std::fs::write(&path, r#"
+prompts_dir = "prompts"
+
+[[agents]]
+name = "greeter"
+public_description = "Greets"
+instructions = "Say hi"
+downstream_agents = ["haiku"]
+
+[[agents]]
+name = "haiku"
+max_concurrency = 2
+"#).unwrap();
+
+        let agents = SwarmConfig::load(&path).unwrap();
+        assert_eq!(agents[0].instructions, "Welcome everyone warmly.");
+        assert_eq!(agents[1].max_concurrency, Some(2));
+        assert!(agents[1].tools.is_empty());
+
+        std::fs::write(&path, "[[agents]]\nname = \"greeter\"\ndownstream_agents = [\"ghost\"]\n").unwrap();
+        let err = SwarmConfig::load(&path).unwrap_err();
+        assert!(matches!(SwarmError::find(&err), Some(SwarmError::Validation(_))));
+    }
+
+    #[test]
+    fn test_plan_diffs_running_and_desired_agents() {
+        let agent = |name: &str, instructions: &str| AgentConfig::builder(name).instructions(instructions).build().unwrap();
+        let running = vec![agent("greeter", "Say hi"), agent("haiku", "Write haikus"), agent("git", "Run git")];
+        let desired = vec![agent("greeter", "Say hello"), agent("haiku", "Write haikus"), agent("digest", "Summarize")];
+        let plan = ReloadPlan::between(&running, &desired);
+        assert_eq!(plan.added.iter().map(|c| c.name.as_str()).collect::<Vec<_>>(), ["digest"]);
+        assert_eq!(plan.changed.iter().map(|c| c.name.as_str()).collect::<Vec<_>>(), ["greeter"]);
+        assert_eq!(plan.removed, ["git"]);
+        assert!(ReloadPlan::between(&desired, &desired).is_empty());
+
+        // Adding an agent rebuilds the greeter, which routes to it
+        let plan = ReloadPlan::between(&desired[..2], &desired);
+        assert_eq!(plan.changed.iter().map(|c| c.name.as_str()).collect::<Vec<_>>(), ["greeter"]);
+    }
+}
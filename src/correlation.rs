@@ -0,0 +1,147 @@
+//! Correlation ids tie together everything done for one request. Each ingress point (an API
+//! request, an MQTT message, a CLI command) takes the id its caller sent or makes a new one
+//! and does its work in `scope`, whose span puts the id on every log line below it, through
+//! routing, tools and AI calls. `Context` carries it onto other tasks, e.g. across a queue.
+
+use std::future::Future;
+use axum::{extract::Request, http::HeaderValue, middleware::Next, response::Response};
+use tokio::task::JoinHandle;
+use tracing::{Instrument, Span};
+use uuid::Uuid;
+
+/// The header an API caller may set, and every response carries
+pub const HEADER: &str = "x-correlation-id";
+
+/// Longest id accepted from a caller
+const MAX_LEN: usize = 128;
+
+tokio::task_local! {
+    static CORRELATION_ID: String;
+}
+
+pub fn new_id() -> String {
+    Uuid::new_v4().simple().to_string()
+}
+
+/// `id` if it's safe to log and echo back
+pub fn accept(id: &str) -> Option<String> {
+    let id = id.trim();
+    let valid = !id.is_empty()
+        && id.len() <= MAX_LEN
+        && id.chars().all(|c| c.is_ascii_alphanumeric() || "-_.:".contains(c));
+    valid.then(|| id.to_string())
+}
+
+/// The `correlation_id` field of a JSON payload, e.g. an MQTT message, or a new id
+pub fn from_payload(payload: &str) -> String {
+    serde_json::from_str::<serde_json::Value>(payload).ok()
+        .and_then(|value| value.get("correlation_id")?.as_str().and_then(accept))
+        .unwrap_or_else(new_id)
+}
+
+/// The id of the work this task is doing, if it came through an ingress point
+pub fn current() -> Option<String> {
+    CORRELATION_ID.try_with(Clone::clone).ok()
+}
+
+/// Run `future` as the work for `id`, which arrived through `ingress` (`api`, `mqtt`, `cli`, ...)
+pub async fn scope<F: Future>(id: String, ingress: &'static str, future: F) -> F::Output {
+    let span = tracing::info_span!("request", correlation_id = %id, ingress);
+    CORRELATION_ID.scope(id, future.instrument(span)).await
+}
+
+/// The current correlation id and span, to continue the work on another task
+#[derive(Debug, Clone)]
+pub struct Context {
+    id: Option<String>,
+    span: Span,
+}
+
+impl Context {
+    pub fn capture() -> Self {
+        Self { id: current(), span: Span::current() }
+    }
+
+    pub async fn run<F: Future>(self, future: F) -> F::Output {
+        let future = future.instrument(self.span);
+        match self.id {
+            Some(id) => CORRELATION_ID.scope(id, future).await,
+            None => future.await,
+        }
+    }
+}
+
+/// `tokio::spawn`, keeping the current correlation id and span
+pub fn spawn<F>(future: F) -> JoinHandle<F::Output>
+where
+    F: Future + Send + 'static,
+    F::Output: Send + 'static,
+{
+    tokio::spawn(Context::capture().run(future))
+}
+
+/// Middleware giving each API request a correlation id, or keeping a valid one from the
+/// `X-Correlation-Id` header, and returning it in that header
+pub async fn correlate_requests(request: Request, next: Next) -> Response {
+    let id = request.headers().get(HEADER)
+        .and_then(|value| value.to_str().ok())
+        .and_then(accept)
+        .unwrap_or_else(new_id);
+    let (method, path) = (request.method().clone(), request.uri().path().to_string());
+    let mut response = scope(id.clone(), "api", async move {
+        let response = next.run(request).await;
+        tracing::info!("{} {} -> {}", method, path, response.status().as_u16());
+        response
+    }).await;
+    if let Ok(value) = HeaderValue::from_str(&id) {
+        response.headers_mut().insert(HEADER, value);
+    }
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{Router, body::Body, middleware, routing::get};
+    use tower::ServiceExt;
+
+    #[test]
+    fn test_only_plain_ids_are_accepted() {
+        assert_eq!(accept(" req-42.a:b_c ").as_deref(), Some("req-42.a:b_c"));
+        assert_eq!(accept(""), None);
+        assert_eq!(accept("two words"), None);
+        assert_eq!(accept("line\nbreak"), None);
+        assert_eq!(accept(&"x".repeat(MAX_LEN + 1)), None);
+
+        assert_eq!(from_payload(r#"{"description": "x", "correlation_id": "abc"}"#), "abc");
+        assert_ne!(from_payload("plain text todo"), from_payload("plain text todo"));
+    }
+
+    #[tokio::test]
+    async fn test_the_id_follows_spawned_work() {
+        assert_eq!(current(), None);
+        let seen = scope("abc".to_string(), "test", async {
+            spawn(async { current() }).await.unwrap()
+        }).await;
+        assert_eq!(seen.as_deref(), Some("abc"));
+    }
+
+    #[tokio::test]
+    async fn test_responses_carry_the_correlation_id() {
+        let app = Router::new()
+            .route("/", get(|| async { current().unwrap_or_default() }))
+            .layer(middleware::from_fn(correlate_requests));
+        let header = |response: &Response| response.headers()[HEADER].to_str().unwrap().to_string();
+
+        let request = Request::get("/").header(HEADER, "client-7").body(Body::empty()).unwrap();
+        let response = app.clone().oneshot(request).await.unwrap();
+        assert_eq!(header(&response), "client-7");
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        assert_eq!(body, "client-7");
+
+        let request = Request::get("/").header(HEADER, "not valid!").body(Body::empty()).unwrap();
+        let response = app.oneshot(request).await.unwrap();
+        assert_ne!(header(&response), "not valid!");
+        assert_eq!(header(&response).len(), 32);
+    }
+}
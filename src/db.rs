@@ -0,0 +1,70 @@
+//! The MongoDB connection every store shares. The task list, project registry, state store,
+//! audit log and the rest take a `Database` rather than opening their own client, so a
+//! process keeps a single connection pool, configured by `config::mongo`.
+
+use anyhow::Result;
+use mongodb::{Client, Collection};
+use mongodb::bson::doc;
+use tokio::sync::OnceCell;
+use crate::config::mongo::MongoConfig;
+use crate::error::SwarmError;
+use crate::types::offline_queue::{self, OfflineQueue};
+
+static SHARED: OnceCell<Database> = OnceCell::const_new();
+
+#[derive(Debug, Clone)]
+pub struct Database {
+    client: Client,
+    database: mongodb::Database,
+}
+
+impl Database {
+    /// Connect and ping the server, so an unreachable or misconfigured database is
+    /// reported here rather than by the first query. While the offline task queue is on,
+    /// an unreachable server is only logged: the client reconnects once it is back.
+    pub async fn connect(config: &MongoConfig) -> Result<Self> {
+        let options = config.client_options().await?;
+        let hosts = options.hosts.iter().map(|host| host.to_string()).collect::<Vec<_>>().join(",");
+        let client = Client::with_options(options)
+            .map_err(|e| SwarmError::Storage(format!("Invalid MongoDB options: {}", e)))?;
+        let database = Self::with_client(client, &config.database);
+        match database.database.run_command(doc! { "ping": 1 }, None).await {
+            Ok(_) => tracing::info!("Connected to MongoDB database {} at {}", config.database, hosts),
+            Err(e) if offline_queue::is_unreachable(&e) && OfflineQueue::enabled() => {
+                tracing::warn!("Could not reach MongoDB at {} yet: {}", hosts, e)
+            }
+            Err(e) => return Err(SwarmError::Storage(format!("Could not reach MongoDB at {}: {}", hosts, e)).into()),
+        }
+        Ok(database)
+    }
+
+    /// A database on `client` that hasn't been pinged, e.g. one that never connects
+    pub fn with_client(client: Client, name: &str) -> Self {
+        Self { database: client.database(name), client }
+    }
+
+    /// The process-wide connection from `MongoConfig::from_env`, opened on first use. A
+    /// failed attempt isn't kept, so callers retry once the database is back.
+    pub async fn shared() -> Result<Self> {
+        SHARED.get_or_try_init(|| async { Self::connect(&MongoConfig::from_env()?).await })
+            .await
+            .cloned()
+    }
+
+    pub async fn ping(&self) -> Result<()> {
+        self.database.run_command(doc! { "ping": 1 }, None).await.map_err(SwarmError::from)?;
+        Ok(())
+    }
+
+    pub fn collection<T>(&self, name: &str) -> Collection<T> {
+        self.database.collection(name)
+    }
+
+    pub fn name(&self) -> &str {
+        self.database.name()
+    }
+
+    pub fn client(&self) -> &Client {
+        &self.client
+    }
+}
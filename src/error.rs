@@ -19,8 +19,35 @@ pub enum Error {
     State(String),
 }
 
+/// Failure kinds raised across the crate. Functions still return `anyhow::Result`;
+/// use `SwarmError::find` to recover the kind from an error chain.
 #[derive(Debug, thiserror::Error)]
 pub enum SwarmError {
+    #[error("Agent '{0}' not found")]
+    AgentNotFound(String),
+
+    #[error("Tool '{tool}' failed: {message}")]
+    ToolFailure { tool: String, message: String },
+
+    #[error("AI provider error: {0}")]
+    AiProvider(String),
+
+    #[error("Storage error: {0}")]
+    Storage(String),
+
+    #[error("Validation error: {0}")]
+    Validation(String),
+
+    #[error("Transfer error: {0}")]
+    Transfer(String),
+
+    #[error("Overloaded: {0}")]
+    Overloaded(String),
+
+    /// A capability the provider or agent doesn't offer, as opposed to one that failed
+    #[error("Not supported: {0}")]
+    Unsupported(String),
+
     #[error("Agent error: {0}")]
     Agent(String),
 
@@ -31,6 +58,25 @@ pub enum SwarmError {
     State(String),
 }
 
+impl SwarmError {
+    /// The first `SwarmError` in the chain, if any
+    pub fn find(err: &anyhow::Error) -> Option<&SwarmError> {
+        // `anyhow::Error::downcast_ref` also sees context attached with `.context()`
+        err.downcast_ref::<SwarmError>()
+            .or_else(|| err.chain().find_map(|cause| cause.downcast_ref::<SwarmError>()))
+    }
+
+    pub fn tool_failure(tool: &str, message: impl fmt::Display) -> Self {
+        SwarmError::ToolFailure { tool: tool.to_string(), message: message.to_string() }
+    }
+}
+
+impl From<mongodb::error::Error> for SwarmError {
+    fn from(err: mongodb::error::Error) -> Self {
+        SwarmError::Storage(err.to_string())
+    }
+}
+
 impl From<&str> for Error {
     fn from(msg: &str) -> Self {
         Error::Agent(msg.to_string())
@@ -49,6 +95,8 @@ impl From<SwarmError> for Error {
             SwarmError::Agent(msg) => Error::Agent(msg),
             SwarmError::Tool(msg) => Error::Tool(msg),
             SwarmError::State(msg) => Error::State(msg),
+            SwarmError::ToolFailure { .. } => Error::Tool(err.to_string()),
+            other => Error::Agent(other.to_string()),
         }
     }
 }
@@ -58,3 +106,22 @@ impl From<Box<dyn std::error::Error + Send + Sync>> for Error {
         Error::Agent(err.to_string())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::Context;
+
+    #[test]
+    fn test_find_swarm_error_in_chain() {
+        let err: anyhow::Error = SwarmError::AgentNotFound("haiku".to_string()).into();
+        let err = err.context("Failed to route message");
+        assert!(matches!(SwarmError::find(&err), Some(SwarmError::AgentNotFound(name)) if name == "haiku"));
+
+        let wrapped = anyhow::anyhow!("disk full").context(SwarmError::Storage("disk full".to_string()));
+        assert!(matches!(SwarmError::find(&wrapped), Some(SwarmError::Storage(_))));
+
+        let plain = anyhow::anyhow!("something else");
+        assert!(SwarmError::find(&plain).is_none());
+    }
+}
@@ -0,0 +1,355 @@
+//! Domain events. Producers publish to an `EventBus` without knowing who listens; the
+//! API, gRPC service, MQTT forwarder and metrics subscribe to what they need.
+
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+use futures::{Stream, StreamExt, future, stream::BoxStream};
+use lazy_static::lazy_static;
+use rumqttc::{AsyncClient, QoS};
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast;
+use tokio::task::JoinHandle;
+use utoipa::ToSchema;
+use crate::types::{TaskEvent, TaskEventKind};
+
+const BUS_CAPACITY: usize = 1024;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum DomainEvent {
+    /// A task was created, started, completed or failed
+    Task(TaskEvent),
+    /// A message was handed to an agent; `from` is set for transfers between agents
+    MessageRouted {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        from: Option<String>,
+        to: String,
+        timestamp: i64,
+    },
+    AgentRegistered { name: String, timestamp: i64 },
+    /// A tool ran; secrets in `params` are redacted
+    ToolExecuted {
+        tool: String,
+        /// The agent whose toolset made the call, if any
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        agent: Option<String>,
+        params: HashMap<String, String>,
+        success: bool,
+        timestamp: i64,
+    },
+    /// A task's worker stopped sending heartbeats, so the task was put back for a retry;
+    /// see `types::reaper`
+    TaskStuck {
+        task_id: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        worker_id: Option<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        last_heartbeat: Option<i64>,
+        timestamp: i64,
+    },
+    /// An AI provider in a fallback chain failed or timed out
+    AiProviderFailed { provider: String, error: String, timestamp: i64 },
+}
+
+impl DomainEvent {
+    pub fn message_routed(from: Option<&str>, to: &str) -> Self {
+        DomainEvent::MessageRouted {
+            from: from.map(str::to_string),
+            to: to.to_string(),
+            timestamp: chrono::Utc::now().timestamp(),
+        }
+    }
+
+    pub fn agent_registered(name: &str) -> Self {
+        DomainEvent::AgentRegistered { name: name.to_string(), timestamp: chrono::Utc::now().timestamp() }
+    }
+
+    pub fn tool_executed(tool: &str, agent: Option<&str>, params: &HashMap<String, String>, success: bool) -> Self {
+        DomainEvent::ToolExecuted {
+            tool: tool.to_string(),
+            agent: agent.map(str::to_string),
+            params: crate::audit::redact_params(params),
+            success,
+            timestamp: chrono::Utc::now().timestamp(),
+        }
+    }
+
+    pub fn task_stuck(task_id: &str, worker_id: Option<&str>, last_heartbeat: Option<i64>) -> Self {
+        DomainEvent::TaskStuck {
+            task_id: task_id.to_string(),
+            worker_id: worker_id.map(str::to_string),
+            last_heartbeat,
+            timestamp: chrono::Utc::now().timestamp(),
+        }
+    }
+
+    pub fn ai_provider_failed(provider: &str, error: &str) -> Self {
+        DomainEvent::AiProviderFailed {
+            provider: provider.to_string(),
+            error: error.to_string(),
+            timestamp: chrono::Utc::now().timestamp(),
+        }
+    }
+
+    /// e.g. `task_created` or `message_routed`
+    pub fn name(&self) -> &'static str {
+        match self {
+            DomainEvent::Task(event) => match event.kind {
+                TaskEventKind::Created => "task_created",
+                TaskEventKind::Started => "task_started",
+                TaskEventKind::Completed => "task_completed",
+                TaskEventKind::Failed => "task_failed",
+                TaskEventKind::Cancelled => "task_cancelled",
+            },
+            DomainEvent::MessageRouted { .. } => "message_routed",
+            DomainEvent::AgentRegistered { .. } => "agent_registered",
+            DomainEvent::ToolExecuted { .. } => "tool_executed",
+            DomainEvent::TaskStuck { .. } => "task_stuck",
+            DomainEvent::AiProviderFailed { .. } => "ai_provider_failed",
+        }
+    }
+}
+
+impl From<TaskEvent> for DomainEvent {
+    fn from(event: TaskEvent) -> Self {
+        DomainEvent::Task(event)
+    }
+}
+
+pub trait EventBus: Send + Sync {
+    fn publish(&self, event: DomainEvent);
+
+    /// Events published from now on
+    fn subscribe(&self) -> BoxStream<'static, DomainEvent>;
+}
+
+/// In-process fan-out. Slow subscribers miss events rather than block publishers.
+pub struct BroadcastEventBus {
+    sender: broadcast::Sender<DomainEvent>,
+}
+
+impl BroadcastEventBus {
+    pub fn new() -> Self {
+        Self { sender: broadcast::channel(BUS_CAPACITY).0 }
+    }
+}
+
+impl Default for BroadcastEventBus {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl EventBus for BroadcastEventBus {
+    fn publish(&self, event: DomainEvent) {
+        // No subscribers is fine
+        let _ = self.sender.send(event);
+    }
+
+    fn subscribe(&self) -> BoxStream<'static, DomainEvent> {
+        futures::stream::unfold(self.sender.subscribe(), |mut receiver| async move {
+            loop {
+                match receiver.recv().await {
+                    Ok(event) => return Some((event, receiver)),
+                    Err(broadcast::error::RecvError::Lagged(missed)) => {
+                        tracing::warn!("Event subscriber missed {} events", missed);
+                    }
+                    Err(broadcast::error::RecvError::Closed) => return None,
+                }
+            }
+        }).boxed()
+    }
+}
+
+lazy_static! {
+    static ref GLOBAL_BUS: BroadcastEventBus = BroadcastEventBus::new();
+}
+
+/// The process-wide bus the built-in producers publish to
+pub fn global() -> &'static dyn EventBus {
+    &*GLOBAL_BUS
+}
+
+/// Only the task lifecycle events
+pub fn task_events(bus: &dyn EventBus) -> impl Stream<Item = TaskEvent> {
+    bus.subscribe().filter_map(|event| future::ready(match event {
+        DomainEvent::Task(event) => Some(event),
+        _ => None,
+    }))
+}
+
+/// Counts of each event name seen, for reporting
+#[derive(Default)]
+pub struct EventMetrics {
+    counts: Mutex<BTreeMap<String, u64>>,
+}
+
+impl EventMetrics {
+    /// Count events from `bus` in a background task
+    pub fn follow(self: &Arc<Self>, bus: &dyn EventBus) -> JoinHandle<()> {
+        let counter = self.clone();
+        tokio::spawn(bus.subscribe().for_each(move |event| {
+            counter.record(&event);
+            future::ready(())
+        }))
+    }
+
+    pub fn record(&self, event: &DomainEvent) {
+        *self.counts.lock().unwrap().entry(event.name().to_string()).or_default() += 1;
+    }
+
+    pub fn snapshot(&self) -> BTreeMap<String, u64> {
+        self.counts.lock().unwrap().clone()
+    }
+}
+
+/// A failure seen on the bus
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct ErrorRecord {
+    /// The event's name, e.g. `task_failed`
+    pub kind: String,
+    /// The task, tool or provider that failed
+    pub source: String,
+    pub message: String,
+    pub timestamp: i64,
+}
+
+impl ErrorRecord {
+    /// The failure `event` reports, if it is one
+    pub fn from_event(event: &DomainEvent) -> Option<Self> {
+        let (source, message, timestamp) = match event {
+            DomainEvent::Task(task) if task.kind == TaskEventKind::Failed => (
+                task.task_id.clone(),
+                task.failure_reason.clone().unwrap_or_else(|| "no reason given".to_string()),
+                task.timestamp,
+            ),
+            DomainEvent::ToolExecuted { tool, agent, success: false, timestamp, .. } => (
+                tool.clone(),
+                format!("failed for {}", agent.as_deref().unwrap_or("a caller outside any agent")),
+                *timestamp,
+            ),
+            DomainEvent::TaskStuck { task_id, worker_id, timestamp, .. } => (
+                task_id.clone(),
+                format!("no heartbeat from {}", worker_id.as_deref().unwrap_or("its worker")),
+                *timestamp,
+            ),
+            DomainEvent::AiProviderFailed { provider, error, timestamp } => (provider.clone(), error.clone(), *timestamp),
+            _ => return None,
+        };
+        Some(Self { kind: event.name().to_string(), source, message, timestamp })
+    }
+}
+
+/// The last few failures on a bus, for dashboards
+pub struct RecentErrors {
+    capacity: usize,
+    errors: Mutex<VecDeque<ErrorRecord>>,
+}
+
+impl RecentErrors {
+    pub fn new(capacity: usize) -> Self {
+        Self { capacity, errors: Mutex::new(VecDeque::with_capacity(capacity)) }
+    }
+
+    /// Keep failures from `bus` in a background task
+    pub fn follow(self: &Arc<Self>, bus: &dyn EventBus) -> JoinHandle<()> {
+        let errors = self.clone();
+        tokio::spawn(bus.subscribe().for_each(move |event| {
+            errors.record(&event);
+            future::ready(())
+        }))
+    }
+
+    pub fn record(&self, event: &DomainEvent) {
+        let Some(error) = ErrorRecord::from_event(event) else { return };
+        let mut errors = self.errors.lock().unwrap();
+        if errors.len() == self.capacity {
+            errors.pop_back();
+        }
+        errors.push_front(error);
+    }
+
+    /// Newest first
+    pub fn snapshot(&self) -> Vec<ErrorRecord> {
+        self.errors.lock().unwrap().iter().cloned().collect()
+    }
+}
+
+/// Forward events from `bus` to MQTT as JSON on `<prefix>/<event name>`, until the
+/// client is disconnected
+pub fn forward_to_mqtt(bus: &dyn EventBus, client: Arc<AsyncClient>, prefix: &str) -> JoinHandle<()> {
+    let prefix = prefix.trim_end_matches('/').to_string();
+    let mut events = bus.subscribe();
+    tokio::spawn(async move {
+        while let Some(event) = events.next().await {
+            let payload = match serde_json::to_string(&event) {
+                Ok(payload) => payload,
+                Err(e) => {
+                    tracing::warn!("Skipping unserializable {} event: {}", event.name(), e);
+                    continue;
+                }
+            };
+            let topic = format!("{}/{}", prefix, event.name());
+            if let Err(e) = client.publish(topic, QoS::AtLeastOnce, false, payload).await {
+                tracing::debug!("Stopping MQTT event forwarding: {}", e);
+                break;
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_bus_delivers_to_every_subscriber() {
+        let bus = BroadcastEventBus::new();
+        let mut first = task_events(&bus).boxed();
+        let mut second = bus.subscribe();
+
+        bus.publish(DomainEvent::agent_registered("git"));
+        bus.publish(TaskEvent::new(TaskEventKind::Created, "t1").into());
+        bus.publish(TaskEvent::new(TaskEventKind::Failed, "t1").with_failure_reason("timed out").into());
+
+        assert_eq!(first.next().await.unwrap().kind, TaskEventKind::Created);
+        let failed = first.next().await.unwrap();
+        assert_eq!((failed.kind, failed.failure_reason.as_deref()), (TaskEventKind::Failed, Some("timed out")));
+        assert_eq!(second.next().await.unwrap().name(), "agent_registered");
+        assert_eq!(second.next().await.unwrap().name(), "task_created");
+    }
+
+    #[tokio::test]
+    async fn test_metrics_count_events_by_name() {
+        let bus = BroadcastEventBus::new();
+        let metrics = Arc::new(EventMetrics::default());
+        metrics.follow(&bus);
+        bus.publish(DomainEvent::message_routed(Some("greeter"), "haiku"));
+        bus.publish(DomainEvent::message_routed(None, "git"));
+        bus.publish(TaskEvent::new(TaskEventKind::Completed, "t1").into());
+
+        while metrics.snapshot().values().sum::<u64>() < 3 {
+            tokio::task::yield_now().await;
+        }
+        let counts = metrics.snapshot();
+        assert_eq!(counts["message_routed"], 2);
+        assert_eq!(counts["task_completed"], 1);
+
+        let json = serde_json::to_value(DomainEvent::message_routed(Some("greeter"), "haiku")).unwrap();
+        assert_eq!((json["type"].as_str(), json["from"].as_str()), (Some("message_routed"), Some("greeter")));
+    }
+
+    #[test]
+    fn test_recent_errors_keep_the_newest_failures() {
+        let errors = RecentErrors::new(2);
+        errors.record(&TaskEvent::new(TaskEventKind::Failed, "t1").with_failure_reason("timed out").into());
+        errors.record(&TaskEvent::new(TaskEventKind::Completed, "t2").into());
+        errors.record(&DomainEvent::tool_executed("git", Some("git"), &HashMap::new(), false));
+        errors.record(&DomainEvent::ai_provider_failed("openai", "rate limited"));
+
+        let recent = errors.snapshot();
+        let summary: Vec<_> = recent.iter().map(|e| (e.kind.as_str(), e.source.as_str())).collect();
+        assert_eq!(summary, [("ai_provider_failed", "openai"), ("tool_executed", "git")]);
+        assert_eq!(recent[0].message, "rate limited");
+    }
+}
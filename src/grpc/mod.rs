@@ -0,0 +1,316 @@
+//! gRPC versions of the agent and task APIs, for services that want typed contracts
+//! instead of JSON over HTTP. The contract is proto/swarmonomicon.proto.
+
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::sync::Arc;
+use futures::{Stream, StreamExt, stream::BoxStream};
+use tonic::{Request, Response, Status};
+use tonic::transport::server::Router;
+use crate::api::{AppState, tenants::{TENANT_HEADER, Tenant}};
+use crate::error::SwarmError;
+use crate::events;
+use crate::types::{self, Agent, Message, TodoList, api_keys::Scope};
+
+pub mod proto;
+
+pub use proto::agent_service_client::AgentServiceClient;
+pub use proto::agent_service_server::{AgentService, AgentServiceServer};
+pub use proto::task_service_client::TaskServiceClient;
+pub use proto::task_service_server::{TaskService, TaskServiceServer};
+
+/// gRPC status for a failed agent or task call, based on its `SwarmError` kind
+fn error_status(err: anyhow::Error) -> Status {
+    match SwarmError::find(&err) {
+        Some(SwarmError::AgentNotFound(_)) => Status::not_found(err.to_string()),
+        Some(SwarmError::Validation(_)) => Status::invalid_argument(err.to_string()),
+        Some(SwarmError::Unsupported(_)) => Status::unimplemented(err.to_string()),
+        Some(SwarmError::AiProvider(_)) | Some(SwarmError::Storage(_)) | Some(SwarmError::Overloaded(_)) => Status::unavailable(err.to_string()),
+        _ => Status::internal(err.to_string()),
+    }
+}
+
+/// Check the API key in the `authorization` (`Bearer <key>`) or `x-api-key` metadata
+/// against the HTTP API's key store, when keys are required, and resolve the tenant the
+/// call acts for from the key and the `x-tenant-id` metadata as the HTTP API does
+async fn authorize<T>(state: &AppState, request: &Request<T>, scope: Scope) -> Result<Tenant, Status> {
+    let metadata = request.metadata();
+    let requested = metadata.get(TENANT_HEADER).and_then(|v| v.to_str().ok()).map(str::trim);
+    let key = match &state.auth {
+        Some(auth) => {
+            let secret = metadata.get("authorization")
+                .and_then(|v| v.to_str().ok())
+                .and_then(|v| v.strip_prefix("Bearer "))
+                .or_else(|| metadata.get(crate::api::auth::API_KEY_HEADER).and_then(|v| v.to_str().ok()))
+                .ok_or_else(|| Status::unauthenticated("API key required"))?;
+            match auth.authenticate(secret.trim()).await {
+                Ok(Some(key)) if key.allows(scope) => Some(key),
+                Ok(Some(key)) if !key.revoked => return Err(Status::permission_denied(format!("API key '{}' lacks the {:?} scope", key.name, scope))),
+                Ok(_) => return Err(Status::unauthenticated("Invalid API key")),
+                Err(e) => return Err(Status::unavailable(format!("API key lookup failed: {}", e))),
+            }
+        }
+        None => None,
+    };
+    Tenant::resolve(key.as_ref(), requested, state.tenants.as_deref()).map_err(|(code, message)| {
+        if code == axum::http::StatusCode::BAD_REQUEST { Status::invalid_argument(message) } else { Status::permission_denied(message) }
+    })
+}
+
+fn agent_not_found(agent: &str) -> Status {
+    Status::not_found(format!("Agent '{}' not found", agent))
+}
+
+fn reply(message: Message, agent: &str) -> proto::AgentReply {
+    let metadata = message.metadata.as_ref();
+    proto::AgentReply {
+        agent: metadata.map(|m| m.agent.clone()).unwrap_or_else(|| agent.to_string()),
+        state: metadata.and_then(|m| m.state.clone()),
+        transfer_target: metadata.and_then(|m| m.transfer_target.clone()),
+        content: message.content,
+    }
+}
+
+pub struct AgentGrpc {
+    state: Arc<AppState>,
+}
+
+impl AgentGrpc {
+    pub fn new(state: Arc<AppState>) -> Self {
+        Self { state }
+    }
+}
+
+#[tonic::async_trait]
+impl AgentService for AgentGrpc {
+    async fn send_message(&self, request: Request<proto::SendMessageRequest>) -> Result<Response<proto::AgentReply>, Status> {
+        let tenant = authorize(&self.state, &request, Scope::Chat).await?;
+        let request = request.into_inner();
+        // Clone the agent out so the registry isn't locked while it works
+        let agent = self.state.agents.read().await.get(&request.agent)
+            .filter(|_| tenant.sees_agent(&self.state, &request.agent))
+            .cloned()
+            .ok_or_else(|| agent_not_found(&request.agent))?;
+        let message = tenant.tag(&request.agent, Message::new(request.content).with_role(Some("user".to_string())));
+        let response = agent.process_message(message).await.map_err(error_status)?;
+        Ok(Response::new(reply(response, &request.agent)))
+    }
+
+    async fn transfer(&self, request: Request<proto::TransferRequest>) -> Result<Response<proto::AgentReply>, Status> {
+        let tenant = authorize(&self.state, &request, Scope::Chat).await?;
+        let request = request.into_inner();
+        for agent in [&request.from_agent, &request.to_agent] {
+            if !tenant.sees_agent(&self.state, agent) {
+                return Err(agent_not_found(agent));
+            }
+        }
+        let message = tenant.tag(&request.from_agent, Message::new(request.content).with_role(Some("user".to_string())));
+        let response = self.state.transfer_service.read().await
+            .transfer(&request.from_agent, &request.to_agent, message).await
+            .map_err(error_status)?;
+        Ok(Response::new(reply(response, &request.to_agent)))
+    }
+
+    async fn list_agents(&self, request: Request<proto::ListAgentsRequest>) -> Result<Response<proto::ListAgentsResponse>, Status> {
+        let tenant = authorize(&self.state, &request, Scope::Chat).await?;
+        let registry = self.state.agents.read().await;
+        let mut agents = Vec::new();
+        for (name, agent) in registry.agents.iter().filter(|(name, _)| tenant.sees_agent(&self.state, name)) {
+            let config = agent.get_config().await.map_err(error_status)?;
+            agents.push(proto::AgentSummary {
+                name: name.clone(),
+                description: config.public_description,
+                downstream_agents: config.downstream_agents,
+            });
+        }
+        agents.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(Response::new(proto::ListAgentsResponse { agents }))
+    }
+}
+
+pub struct TaskGrpc {
+    state: Arc<AppState>,
+}
+
+impl TaskGrpc {
+    pub fn new(state: Arc<AppState>) -> Self {
+        Self { state }
+    }
+
+    fn todos(&self) -> Result<&TodoList, Status> {
+        self.state.todos.as_ref().ok_or_else(|| Status::unavailable("Task storage is not configured"))
+    }
+}
+
+type TaskEventStream = Pin<Box<dyn Stream<Item = Result<proto::TaskEvent, Status>> + Send>>;
+
+#[tonic::async_trait]
+impl TaskService for TaskGrpc {
+    async fn create(&self, request: Request<proto::CreateTaskRequest>) -> Result<Response<proto::Task>, Status> {
+        let tenant = authorize(&self.state, &request, Scope::WriteTasks).await?;
+        let request = request.into_inner();
+        if request.description.trim().is_empty() || request.target_agent.trim().is_empty() {
+            return Err(Status::invalid_argument("description and target_agent are required"));
+        }
+        let priority = proto::TaskPriority::try_from(request.priority)
+            .map_err(|_| Status::invalid_argument(format!("Unknown priority {}", request.priority)))?;
+        let task = tenant.todos(self.todos()?).create_task_with_enhancement(
+            request.description,
+            priority.to_task_priority(),
+            request.source_agent,
+            request.target_agent,
+            request.project,
+            Vec::new(),
+            None,
+        ).await.map_err(|e| error_status(SwarmError::from(e).into()))?;
+        Ok(Response::new(task.into()))
+    }
+
+    async fn list(&self, request: Request<proto::ListTasksRequest>) -> Result<Response<proto::ListTasksResponse>, Status> {
+        let tenant = authorize(&self.state, &request, Scope::ReadTasks).await?;
+        let request = request.into_inner();
+        let tasks = tenant.todos(self.todos()?).get_all_tasks().await.map_err(|e| error_status(SwarmError::from(e).into()))?;
+        let tasks = tasks.into_iter()
+            .filter(|task| request.target_agent.as_ref().map_or(true, |agent| &task.target_agent == agent))
+            .map(proto::Task::from)
+            .filter(|task| request.status == proto::TaskStatus::Unspecified as i32 || task.status == request.status)
+            .collect();
+        Ok(Response::new(proto::ListTasksResponse { tasks }))
+    }
+
+    type WatchStream = TaskEventStream;
+
+    /// Follows the MongoDB change stream when available, like `/tasks/events`, so tasks
+    /// changed by other processes are seen; otherwise only this server's changes are
+    async fn watch(&self, request: Request<proto::WatchTasksRequest>) -> Result<Response<Self::WatchStream>, Status> {
+        let tenant = authorize(&self.state, &request, Scope::ReadTasks).await?;
+        let events: BoxStream<'static, types::TaskEvent> = match self.todos().ok() {
+            Some(todos) => match tenant.todos(todos).watch_changes().await {
+                Ok(changes) => changes.boxed(),
+                Err(e) => {
+                    tracing::debug!("Task change stream unavailable, using in-process events: {}", e);
+                    events::task_events(events::global()).boxed()
+                }
+            },
+            None => events::task_events(events::global()).boxed(),
+        };
+        let tenant = tenant.0;
+        let events = events.filter(move |event| std::future::ready(tenant.is_none() || event.tenant_id == tenant));
+        Ok(Response::new(Box::pin(events.map(|event| Ok(event.into())))))
+    }
+}
+
+/// Both services over the shared application state
+pub fn router(state: Arc<AppState>) -> Router {
+    tonic::transport::Server::builder()
+        .add_service(AgentServiceServer::new(AgentGrpc::new(state.clone())))
+        .add_service(TaskServiceServer::new(TaskGrpc::new(state)))
+}
+
+/// Serve until the state's shutdown coordinator is signalled
+pub async fn serve(addr: SocketAddr, state: Arc<AppState>) -> anyhow::Result<()> {
+    let shutdown = state.shutdown.clone();
+    router(state).serve_with_shutdown(addr, async move { shutdown.signalled().await }).await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::sync::RwLock;
+    use crate::agents::{AgentRegistry, TransferService};
+    use crate::api::auth::ApiAuth;
+    use crate::testing::EchoAgent;
+    use crate::testsupport::MongoFixture;
+    use crate::types::{TaskEvent, TaskEventKind, api_keys::{ApiKey, ApiKeyStore, InMemoryApiKeyStore}};
+
+    fn state() -> AppState {
+        let registry = Arc::new(RwLock::new(AgentRegistry::new()));
+        AppState::new(Arc::new(RwLock::new(TransferService::new(registry))))
+    }
+
+    #[tokio::test]
+    async fn test_agent_and_task_calls_map_errors() {
+        let agents = AgentGrpc::new(Arc::new(state()));
+        let listed = agents.list_agents(Request::new(proto::ListAgentsRequest {})).await.unwrap();
+        assert!(listed.into_inner().agents.is_empty());
+
+        let missing = agents.send_message(Request::new(proto::SendMessageRequest {
+            agent: "nobody".to_string(),
+            content: "hi".to_string(),
+        })).await.unwrap_err();
+        assert_eq!(missing.code(), tonic::Code::NotFound);
+        let transfer = agents.transfer(Request::new(proto::TransferRequest {
+            from_agent: "a".to_string(),
+            to_agent: "b".to_string(),
+            content: "hi".to_string(),
+        })).await.unwrap_err();
+        assert_eq!(transfer.code(), tonic::Code::NotFound);
+
+        let tasks = TaskGrpc::new(Arc::new(state()));
+        let invalid = tasks.create(Request::new(proto::CreateTaskRequest { description: "  ".to_string(), ..Default::default() }))
+            .await.unwrap_err();
+        assert_eq!(invalid.code(), tonic::Code::InvalidArgument);
+        let unavailable = tasks.list(Request::new(proto::ListTasksRequest::default())).await.unwrap_err();
+        assert_eq!(unavailable.code(), tonic::Code::Unavailable);
+    }
+
+    #[tokio::test]
+    async fn test_tenants_only_reach_their_agents() -> anyhow::Result<()> {
+        let mongo = MongoFixture::start().await?;
+        let mut registry = AgentRegistry::new().with_todos(mongo.todo_list().await);
+        for name in ["git", "haiku"] {
+            registry.register(name.to_string(), Box::new(EchoAgent::new(name))).await?;
+        }
+        let registry = Arc::new(RwLock::new(registry));
+        let mut state = AppState::new(Arc::new(RwLock::new(TransferService::new(registry.clone()))));
+        state.agents = registry;
+        state.tenants = Some(Arc::new(serde_yaml::from_str("tenants: { platform: { agents: [git] } }")?));
+        let agents = AgentGrpc::new(Arc::new(state));
+        fn for_platform<T>(mut request: Request<T>) -> Request<T> {
+            request.metadata_mut().insert(TENANT_HEADER, "platform".parse().unwrap());
+            request
+        }
+
+        let listed = agents.list_agents(for_platform(Request::new(proto::ListAgentsRequest {}))).await?.into_inner();
+        assert_eq!(listed.agents.iter().map(|a| a.name.as_str()).collect::<Vec<_>>(), ["git"]);
+        let hidden = agents.send_message(for_platform(Request::new(proto::SendMessageRequest {
+            agent: "haiku".to_string(),
+            content: "hi".to_string(),
+        }))).await.unwrap_err();
+        assert_eq!(hidden.code(), tonic::Code::NotFound);
+        let mut unknown = Request::new(proto::ListAgentsRequest {});
+        unknown.metadata_mut().insert(TENANT_HEADER, "research".parse().unwrap());
+        assert_eq!(agents.list_agents(unknown).await.unwrap_err().code(), tonic::Code::PermissionDenied);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_watch_streams_events_to_authorized_clients() {
+        let store = Arc::new(InMemoryApiKeyStore::new());
+        let (reader, secret) = ApiKey::generate("reader", vec![Scope::ReadTasks]);
+        store.insert(&reader).await.unwrap();
+        let state = Arc::new(state().with_auth(ApiAuth::new(store)));
+        let tasks = TaskGrpc::new(state.clone());
+
+        let denied = tasks.watch(Request::new(proto::WatchTasksRequest {})).await.err().unwrap();
+        assert_eq!(denied.code(), tonic::Code::Unauthenticated);
+        let mut request = Request::new(proto::SendMessageRequest::default());
+        request.metadata_mut().insert("x-api-key", secret.parse().unwrap());
+        let forbidden = AgentGrpc::new(state).send_message(request).await.unwrap_err();
+        assert_eq!(forbidden.code(), tonic::Code::PermissionDenied);
+
+        let mut request = Request::new(proto::WatchTasksRequest {});
+        request.metadata_mut().insert("authorization", format!("Bearer {}", secret).parse().unwrap());
+        let mut events = tasks.watch(request).await.unwrap().into_inner();
+        events::global().publish(TaskEvent::new(TaskEventKind::Failed, "grpc-watch-task").with_failure_reason("boom").into());
+        let event = loop {
+            let event = events.next().await.unwrap().unwrap();
+            if event.task_id == "grpc-watch-task" {
+                break event;
+            }
+        };
+        assert_eq!(event.kind, proto::TaskEventKind::Failed as i32);
+        assert_eq!(event.failure_reason.as_deref(), Some("boom"));
+    }
+}
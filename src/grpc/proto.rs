@@ -0,0 +1,244 @@
+//! Messages from proto/swarmonomicon.proto, plus the generated service stubs.
+//! Field tags must match the `.proto` file.
+
+use crate::types::{self, TaskEventKind as EventKind, TodoTask};
+
+include!(concat!(env!("OUT_DIR"), "/swarmonomicon.v1.AgentService.rs"));
+include!(concat!(env!("OUT_DIR"), "/swarmonomicon.v1.TaskService.rs"));
+
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct SendMessageRequest {
+    #[prost(string, tag = "1")]
+    pub agent: String,
+    #[prost(string, tag = "2")]
+    pub content: String,
+}
+
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct TransferRequest {
+    #[prost(string, tag = "1")]
+    pub from_agent: String,
+    #[prost(string, tag = "2")]
+    pub to_agent: String,
+    #[prost(string, tag = "3")]
+    pub content: String,
+}
+
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct AgentReply {
+    #[prost(string, tag = "1")]
+    pub content: String,
+    #[prost(string, tag = "2")]
+    pub agent: String,
+    #[prost(string, optional, tag = "3")]
+    pub state: Option<String>,
+    #[prost(string, optional, tag = "4")]
+    pub transfer_target: Option<String>,
+}
+
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct ListAgentsRequest {}
+
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct AgentSummary {
+    #[prost(string, tag = "1")]
+    pub name: String,
+    #[prost(string, tag = "2")]
+    pub description: String,
+    #[prost(string, repeated, tag = "3")]
+    pub downstream_agents: Vec<String>,
+}
+
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct ListAgentsResponse {
+    #[prost(message, repeated, tag = "1")]
+    pub agents: Vec<AgentSummary>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, ::prost::Enumeration)]
+#[repr(i32)]
+pub enum TaskPriority {
+    Unspecified = 0,
+    Initial = 1,
+    Low = 2,
+    Medium = 3,
+    High = 4,
+    Critical = 5,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, ::prost::Enumeration)]
+#[repr(i32)]
+pub enum TaskStatus {
+    Unspecified = 0,
+    Initial = 1,
+    Pending = 2,
+    Review = 3,
+    Completed = 4,
+    Failed = 5,
+    InProgress = 6,
+    Cancelled = 7,
+}
+
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct Task {
+    #[prost(string, tag = "1")]
+    pub id: String,
+    #[prost(string, tag = "2")]
+    pub description: String,
+    #[prost(string, optional, tag = "3")]
+    pub enhanced_description: Option<String>,
+    #[prost(enumeration = "TaskPriority", tag = "4")]
+    pub priority: i32,
+    #[prost(string, optional, tag = "5")]
+    pub project: Option<String>,
+    #[prost(string, optional, tag = "6")]
+    pub source_agent: Option<String>,
+    #[prost(string, tag = "7")]
+    pub target_agent: String,
+    #[prost(enumeration = "TaskStatus", tag = "8")]
+    pub status: i32,
+    #[prost(int64, tag = "9")]
+    pub created_at: i64,
+    #[prost(int64, optional, tag = "10")]
+    pub completed_at: Option<i64>,
+    #[prost(string, optional, tag = "11")]
+    pub failure_reason: Option<String>,
+}
+
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct CreateTaskRequest {
+    #[prost(string, tag = "1")]
+    pub description: String,
+    #[prost(enumeration = "TaskPriority", tag = "2")]
+    pub priority: i32,
+    #[prost(string, tag = "3")]
+    pub target_agent: String,
+    #[prost(string, optional, tag = "4")]
+    pub source_agent: Option<String>,
+    #[prost(string, optional, tag = "5")]
+    pub project: Option<String>,
+}
+
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct ListTasksRequest {
+    #[prost(string, optional, tag = "1")]
+    pub target_agent: Option<String>,
+    #[prost(enumeration = "TaskStatus", tag = "2")]
+    pub status: i32,
+}
+
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct ListTasksResponse {
+    #[prost(message, repeated, tag = "1")]
+    pub tasks: Vec<Task>,
+}
+
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct WatchTasksRequest {}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, ::prost::Enumeration)]
+#[repr(i32)]
+pub enum TaskEventKind {
+    Unspecified = 0,
+    Created = 1,
+    Started = 2,
+    Completed = 3,
+    Failed = 4,
+    Cancelled = 5,
+}
+
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct TaskEvent {
+    #[prost(enumeration = "TaskEventKind", tag = "1")]
+    pub kind: i32,
+    #[prost(string, tag = "2")]
+    pub task_id: String,
+    #[prost(string, optional, tag = "3")]
+    pub description: Option<String>,
+    #[prost(string, optional, tag = "4")]
+    pub target_agent: Option<String>,
+    #[prost(string, optional, tag = "5")]
+    pub project: Option<String>,
+    #[prost(string, optional, tag = "6")]
+    pub failure_reason: Option<String>,
+    #[prost(int64, tag = "7")]
+    pub timestamp: i64,
+}
+
+impl From<types::TaskPriority> for TaskPriority {
+    fn from(priority: types::TaskPriority) -> Self {
+        match priority {
+            types::TaskPriority::Inital => TaskPriority::Initial,
+            types::TaskPriority::Low => TaskPriority::Low,
+            types::TaskPriority::Medium => TaskPriority::Medium,
+            types::TaskPriority::High => TaskPriority::High,
+            types::TaskPriority::Critical => TaskPriority::Critical,
+        }
+    }
+}
+
+impl TaskPriority {
+    /// The stored priority; unspecified means medium
+    pub fn to_task_priority(self) -> types::TaskPriority {
+        match self {
+            TaskPriority::Initial => types::TaskPriority::Inital,
+            TaskPriority::Low => types::TaskPriority::Low,
+            TaskPriority::Unspecified | TaskPriority::Medium => types::TaskPriority::Medium,
+            TaskPriority::High => types::TaskPriority::High,
+            TaskPriority::Critical => types::TaskPriority::Critical,
+        }
+    }
+}
+
+impl From<types::TaskStatus> for TaskStatus {
+    fn from(status: types::TaskStatus) -> Self {
+        match status {
+            types::TaskStatus::Initial => TaskStatus::Initial,
+            types::TaskStatus::Pending => TaskStatus::Pending,
+            types::TaskStatus::InProgress => TaskStatus::InProgress,
+            types::TaskStatus::Review => TaskStatus::Review,
+            types::TaskStatus::Completed => TaskStatus::Completed,
+            types::TaskStatus::Failed => TaskStatus::Failed,
+            types::TaskStatus::Cancelled => TaskStatus::Cancelled,
+        }
+    }
+}
+
+impl From<TodoTask> for Task {
+    fn from(task: TodoTask) -> Self {
+        Self {
+            id: task.id,
+            description: task.description,
+            enhanced_description: task.enhanced_description,
+            priority: TaskPriority::from(task.priority) as i32,
+            project: task.project,
+            source_agent: task.source_agent,
+            target_agent: task.target_agent,
+            status: TaskStatus::from(task.status) as i32,
+            created_at: task.created_at,
+            completed_at: task.completed_at,
+            failure_reason: task.failure_reason,
+        }
+    }
+}
+
+impl From<types::TaskEvent> for TaskEvent {
+    fn from(event: types::TaskEvent) -> Self {
+        let kind = match event.kind {
+            EventKind::Created => TaskEventKind::Created,
+            EventKind::Started => TaskEventKind::Started,
+            EventKind::Completed => TaskEventKind::Completed,
+            EventKind::Failed => TaskEventKind::Failed,
+            EventKind::Cancelled => TaskEventKind::Cancelled,
+        };
+        Self {
+            kind: kind as i32,
+            task_id: event.task_id,
+            description: event.description,
+            target_agent: event.target_agent,
+            project: event.project,
+            failure_reason: event.failure_reason,
+            timestamp: event.timestamp,
+        }
+    }
+}
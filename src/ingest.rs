@@ -0,0 +1,558 @@
+//! Bounded queues between the stages a new task passes through: intake (MQTT or the API),
+//! AI enhancement, and storage. Each stage holds at most `capacity` items and a fixed
+//! number of workers drain it, so a burst of tasks waits in memory that is bounded
+//! instead of spawning a model call per message. What happens when a stage is full is
+//! its overflow policy:
+//!
+//! - `block`: the producer waits for room, which slows the stage before it (the default)
+//! - `drop-oldest`: the longest-waiting item is dropped and its producer told so
+//! - `reject`: the new item is refused with `SwarmError::Overloaded`
+//!
+//! Each stage reads `INGEST_<STAGE>_CAPACITY`, `INGEST_<STAGE>_OVERFLOW` and
+//! `INGEST_<STAGE>_WORKERS`, where the stages are `ENHANCE` and `STORE`, and `ENRICH` for
+//! the todo tool's background enhancement of todos already stored.
+
+use std::collections::VecDeque;
+use std::env;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use anyhow::Result;
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use tokio::sync::{Notify, oneshot};
+use utoipa::ToSchema;
+use crate::correlation;
+use crate::error::SwarmError;
+use crate::types::{TodoList, TodoTask};
+use crate::types::todo::NewTask;
+use crate::types::idempotency::Insertion;
+
+const DEFAULT_CAPACITY: usize = 100;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "kebab-case")]
+pub enum OverflowPolicy {
+    Block,
+    DropOldest,
+    Reject,
+}
+
+impl FromStr for OverflowPolicy {
+    type Err = SwarmError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.trim().to_lowercase().replace('_', "-").as_str() {
+            "block" => Ok(OverflowPolicy::Block),
+            "drop-oldest" => Ok(OverflowPolicy::DropOldest),
+            "reject" => Ok(OverflowPolicy::Reject),
+            other => Err(SwarmError::Validation(format!(
+                "Unknown overflow policy '{}', expected block, drop-oldest or reject", other
+            ))),
+        }
+    }
+}
+
+impl fmt::Display for OverflowPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            OverflowPolicy::Block => "block",
+            OverflowPolicy::DropOldest => "drop-oldest",
+            OverflowPolicy::Reject => "reject",
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StageConfig {
+    pub capacity: usize,
+    pub overflow: OverflowPolicy,
+    /// Items processed at once
+    pub workers: usize,
+}
+
+impl Default for StageConfig {
+    fn default() -> Self {
+        Self { capacity: DEFAULT_CAPACITY, overflow: OverflowPolicy::Block, workers: 1 }
+    }
+}
+
+impl StageConfig {
+    /// `defaults` overridden by `INGEST_<STAGE>_*`
+    pub fn from_env(stage: &str, defaults: StageConfig) -> Result<Self> {
+        Self::from_lookup(stage, defaults, |key| env::var(key).ok().filter(|value| !value.is_empty()))
+    }
+
+    pub fn from_lookup(stage: &str, defaults: StageConfig, lookup: impl Fn(&str) -> Option<String>) -> Result<Self> {
+        let key = |setting: &str| format!("INGEST_{}_{}", stage.to_uppercase(), setting);
+        let count = |setting: &str, default: usize| -> Result<usize> {
+            match lookup(&key(setting)) {
+                Some(value) => value.parse().ok().filter(|&n| n > 0).ok_or_else(|| {
+                    SwarmError::Validation(format!("{} must be a positive number, not '{}'", key(setting), value)).into()
+                }),
+                None => Ok(default),
+            }
+        };
+        Ok(Self {
+            capacity: count("CAPACITY", defaults.capacity)?,
+            overflow: lookup(&key("OVERFLOW")).map(|value| value.parse()).transpose()?.unwrap_or(defaults.overflow),
+            workers: count("WORKERS", defaults.workers)?,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IngestConfig {
+    pub enhance: StageConfig,
+    pub store: StageConfig,
+}
+
+impl IngestConfig {
+    /// One enhancement at a time so a burst doesn't starve the model, queueing up to 100 of each
+    pub fn from_env() -> Result<Self> {
+        Ok(Self {
+            enhance: StageConfig::from_env("enhance", StageConfig::default())?,
+            store: StageConfig::from_env("store", StageConfig::default())?,
+        })
+    }
+}
+
+/// A stage's queue depth and what it has done with the items offered to it
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct StageMetrics {
+    pub name: String,
+    pub capacity: usize,
+    pub overflow: OverflowPolicy,
+    pub depth: usize,
+    /// Deepest the queue has been
+    pub high_water: usize,
+    pub accepted: u64,
+    pub dropped: u64,
+    pub rejected: u64,
+    pub processed: u64,
+    pub failed: u64,
+}
+
+/// A bounded queue applying an overflow policy when full
+pub struct Stage<T> {
+    name: String,
+    config: StageConfig,
+    items: Mutex<VecDeque<T>>,
+    added: Notify,
+    removed: Notify,
+    /// An item was processed or failed
+    recorded: Notify,
+    closed: AtomicBool,
+    high_water: AtomicUsize,
+    accepted: AtomicU64,
+    dropped: AtomicU64,
+    rejected: AtomicU64,
+    processed: AtomicU64,
+    failed: AtomicU64,
+}
+
+impl<T> Stage<T> {
+    pub fn new(name: impl Into<String>, config: StageConfig) -> Self {
+        Self {
+            name: name.into(),
+            config,
+            items: Mutex::new(VecDeque::with_capacity(config.capacity)),
+            added: Notify::new(),
+            removed: Notify::new(),
+            recorded: Notify::new(),
+            closed: AtomicBool::new(false),
+            high_water: AtomicUsize::new(0),
+            accepted: AtomicU64::new(0),
+            dropped: AtomicU64::new(0),
+            rejected: AtomicU64::new(0),
+            processed: AtomicU64::new(0),
+            failed: AtomicU64::new(0),
+        }
+    }
+
+    fn overloaded(&self, what: &str) -> SwarmError {
+        SwarmError::Overloaded(format!("{} the {} queue ({} items)", what, self.name, self.config.capacity))
+    }
+
+    /// Queue `item`, returning the item it displaced under `drop-oldest`. Fails when the
+    /// stage rejects it or has been closed.
+    pub async fn push(&self, item: T) -> Result<Option<T>> {
+        let mut item = Some(item);
+        loop {
+            let removed = self.removed.notified();
+            tokio::pin!(removed);
+            removed.as_mut().enable();
+            // `None` while a blocking stage is full; otherwise the item it displaced, if any
+            let admitted = {
+                let mut items = self.items.lock().unwrap();
+                if self.closed.load(Ordering::Acquire) {
+                    return Err(SwarmError::Overloaded(format!("The {} queue is closed", self.name)).into());
+                }
+                let admitted = if items.len() < self.config.capacity {
+                    Some(None)
+                } else {
+                    match self.config.overflow {
+                        OverflowPolicy::Block => None,
+                        OverflowPolicy::Reject => {
+                            self.rejected.fetch_add(1, Ordering::Relaxed);
+                            return Err(self.overloaded("Rejected by the full").into());
+                        }
+                        OverflowPolicy::DropOldest => {
+                            self.dropped.fetch_add(1, Ordering::Relaxed);
+                            Some(items.pop_front())
+                        }
+                    }
+                };
+                if admitted.is_some() {
+                    items.push_back(item.take().expect("pushed once"));
+                    self.high_water.fetch_max(items.len(), Ordering::Relaxed);
+                    self.accepted.fetch_add(1, Ordering::Relaxed);
+                }
+                admitted
+            };
+            match admitted {
+                Some(displaced) => {
+                    self.added.notify_one();
+                    return Ok(displaced);
+                }
+                None => removed.await,
+            }
+        }
+    }
+
+    /// The oldest item, waiting for one; `None` once the stage is closed and drained
+    pub async fn pop(&self) -> Option<T> {
+        loop {
+            let added = self.added.notified();
+            tokio::pin!(added);
+            added.as_mut().enable();
+            {
+                let mut items = self.items.lock().unwrap();
+                if let Some(item) = items.pop_front() {
+                    drop(items);
+                    self.removed.notify_one();
+                    return Some(item);
+                }
+                if self.closed.load(Ordering::Acquire) {
+                    return None;
+                }
+            }
+            added.await;
+        }
+    }
+
+    /// Refuse new items and let workers finish once the queue is empty
+    pub fn close(&self) {
+        self.closed.store(true, Ordering::Release);
+        self.added.notify_waiters();
+        self.removed.notify_waiters();
+    }
+
+    pub fn depth(&self) -> usize {
+        self.items.lock().unwrap().len()
+    }
+
+    pub(crate) fn record(&self, ok: bool) {
+        let counter = if ok { &self.processed } else { &self.failed };
+        counter.fetch_add(1, Ordering::Relaxed);
+        self.recorded.notify_waiters();
+    }
+
+    /// Wait until every item accepted so far has been processed, failed or dropped
+    pub async fn settled(&self) {
+        loop {
+            let recorded = self.recorded.notified();
+            tokio::pin!(recorded);
+            recorded.as_mut().enable();
+            let finished = self.processed.load(Ordering::Relaxed) + self.failed.load(Ordering::Relaxed) + self.dropped.load(Ordering::Relaxed);
+            if finished >= self.accepted.load(Ordering::Relaxed) {
+                return;
+            }
+            recorded.await;
+        }
+    }
+
+    pub fn metrics(&self) -> StageMetrics {
+        StageMetrics {
+            name: self.name.clone(),
+            capacity: self.config.capacity,
+            overflow: self.config.overflow,
+            depth: self.depth(),
+            high_water: self.high_water.load(Ordering::Relaxed),
+            accepted: self.accepted.load(Ordering::Relaxed),
+            dropped: self.dropped.load(Ordering::Relaxed),
+            rejected: self.rejected.load(Ordering::Relaxed),
+            processed: self.processed.load(Ordering::Relaxed),
+            failed: self.failed.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// The two stages after intake: enhancing a request, then storing the result
+#[async_trait]
+pub trait Ingest: Send + Sync + 'static {
+    type Request: Send + 'static;
+    type Prepared: Send + 'static;
+    type Output: Send + 'static;
+
+    async fn enhance(&self, request: Self::Request) -> Result<Self::Prepared>;
+    async fn store(&self, prepared: Self::Prepared) -> Result<Self::Output>;
+}
+
+struct Job<T, O> {
+    item: T,
+    reply: oneshot::Sender<Result<O>>,
+    /// The submitter's correlation id, so the stages' logs carry it
+    context: correlation::Context,
+}
+
+impl<T, O> Job<T, O> {
+    fn dropped(self, stage: &str) {
+        let error = SwarmError::Overloaded(format!("Dropped from the full {} queue for newer items", stage));
+        let _ = self.reply.send(Err(error.into()));
+    }
+}
+
+pub struct IngestPipeline<I: Ingest> {
+    enhance: Arc<Stage<Job<I::Request, I::Output>>>,
+    store: Arc<Stage<Job<I::Prepared, I::Output>>>,
+}
+
+impl<I: Ingest> IngestPipeline<I> {
+    /// Start the stages' workers
+    pub fn start(ingest: Arc<I>, config: IngestConfig) -> Arc<Self> {
+        let pipeline = Arc::new(Self {
+            enhance: Arc::new(Stage::new("enhance", config.enhance)),
+            store: Arc::new(Stage::new("store", config.store)),
+        });
+
+        for _ in 0..config.enhance.workers {
+            let (ingest, enhance, store) = (ingest.clone(), pipeline.enhance.clone(), pipeline.store.clone());
+            tokio::spawn(async move {
+                while let Some(Job { item, reply, context }) = enhance.pop().await {
+                    let prepared = context.clone().run(ingest.enhance(item)).await;
+                    enhance.record(prepared.is_ok());
+                    match prepared {
+                        // Blocks here while storage is full, which backs up enhancement in turn
+                        Ok(prepared) => match store.push(Job { item: prepared, reply, context }).await {
+                            Ok(Some(displaced)) => displaced.dropped("store"),
+                            Ok(None) => {}
+                            Err(e) => tracing::warn!("Enhanced task not stored: {}", e),
+                        },
+                        Err(e) => {
+                            let _ = reply.send(Err(e));
+                        }
+                    }
+                }
+            });
+        }
+        for _ in 0..config.store.workers {
+            let (ingest, store) = (ingest.clone(), pipeline.store.clone());
+            tokio::spawn(async move {
+                while let Some(job) = store.pop().await {
+                    let output = job.context.run(ingest.store(job.item)).await;
+                    store.record(output.is_ok());
+                    let _ = job.reply.send(output);
+                }
+            });
+        }
+        pipeline
+    }
+
+    /// Queue `request`, returning where its result will arrive. Fails straight away if the
+    /// enhancement stage rejects it.
+    pub async fn submit(&self, request: I::Request) -> Result<oneshot::Receiver<Result<I::Output>>> {
+        let (reply, result) = oneshot::channel();
+        if let Some(displaced) = self.enhance.push(Job { item: request, reply, context: correlation::Context::capture() }).await? {
+            displaced.dropped("enhance");
+        }
+        Ok(result)
+    }
+
+    /// Queue `request` and wait for it to be stored
+    pub async fn process(&self, request: I::Request) -> Result<I::Output> {
+        self.submit(request).await?
+            .await
+            .map_err(|_| SwarmError::Overloaded("The ingest pipeline stopped before finishing".to_string()))?
+    }
+
+    pub fn metrics(&self) -> Vec<StageMetrics> {
+        vec![self.enhance.metrics(), self.store.metrics()]
+    }
+
+    /// Stop taking requests; queued ones still finish
+    pub fn close(&self) {
+        self.enhance.close();
+        self.store.close();
+    }
+}
+
+/// A task for one tenant's list, as the API and hooks create them
+pub struct TaskSubmission {
+    pub todos: TodoList,
+    pub task: NewTask,
+}
+
+/// Tasks created through the API, stored in the caller's task list
+pub struct TaskIntake;
+
+#[async_trait]
+impl Ingest for TaskIntake {
+    type Request = TaskSubmission;
+    type Prepared = (TodoList, TodoTask);
+    type Output = Insertion;
+
+    async fn enhance(&self, request: TaskSubmission) -> Result<(TodoList, TodoTask)> {
+        // Agents' todo processors enhance API tasks when they pick them up
+        let task = request.todos.prepare_task(request.task, None).await;
+        Ok((request.todos, task))
+    }
+
+    async fn store(&self, (todos, task): (TodoList, TodoTask)) -> Result<Insertion> {
+        Ok(todos.add_task_once(task).await.map_err(SwarmError::from)?)
+    }
+}
+
+#[async_trait]
+impl Ingest for crate::tools::TodoTool {
+    type Request = crate::tools::todo::TodoRequest;
+    type Prepared = crate::tools::todo::PreparedTodo;
+    type Output = String;
+
+    async fn enhance(&self, request: Self::Request) -> Result<Self::Prepared> {
+        self.prepare_todo(request).await
+    }
+
+    async fn store(&self, prepared: Self::Prepared) -> Result<String> {
+        self.store_todo(prepared).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use std::time::Duration;
+    use tokio::sync::Semaphore;
+
+    fn stage(capacity: usize, overflow: OverflowPolicy) -> Stage<u32> {
+        Stage::new("test", StageConfig { capacity, overflow, workers: 1 })
+    }
+
+    #[tokio::test]
+    async fn test_overflow_policies() -> Result<()> {
+        let rejecting = stage(2, OverflowPolicy::Reject);
+        rejecting.push(1).await?;
+        rejecting.push(2).await?;
+        let err = rejecting.push(3).await.unwrap_err();
+        assert!(matches!(SwarmError::find(&err), Some(SwarmError::Overloaded(_))));
+
+        let dropping = stage(2, OverflowPolicy::DropOldest);
+        dropping.push(1).await?;
+        dropping.push(2).await?;
+        assert_eq!(dropping.push(3).await?, Some(1));
+        assert_eq!((dropping.pop().await, dropping.pop().await), (Some(2), Some(3)));
+
+        let blocking = Arc::new(stage(1, OverflowPolicy::Block));
+        blocking.push(1).await?;
+        let waiting = tokio::spawn({
+            let blocking = blocking.clone();
+            async move { blocking.push(2).await }
+        });
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        assert!(!waiting.is_finished());
+        assert_eq!(blocking.pop().await, Some(1));
+        assert_eq!(waiting.await??, None);
+        assert_eq!(blocking.pop().await, Some(2));
+
+        let metrics = [rejecting.metrics(), dropping.metrics(), blocking.metrics()];
+        assert_eq!(metrics.iter().map(|m| (m.accepted, m.dropped, m.rejected, m.high_water)).collect::<Vec<_>>(),
+            [(2, 0, 1, 2), (3, 1, 0, 2), (2, 0, 0, 1)]);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_closed_stage_drains_then_stops() -> Result<()> {
+        let stage = stage(4, OverflowPolicy::Block);
+        stage.push(1).await?;
+        stage.close();
+        assert!(stage.push(2).await.is_err());
+        assert_eq!(stage.pop().await, Some(1));
+        assert_eq!(stage.pop().await, None);
+        Ok(())
+    }
+
+    /// Enhancement waits for a permit, standing in for a slow model
+    struct Gated {
+        model: Semaphore,
+        stored: Mutex<Vec<String>>,
+    }
+
+    #[async_trait]
+    impl Ingest for Gated {
+        type Request = String;
+        type Prepared = String;
+        type Output = usize;
+
+        async fn enhance(&self, request: String) -> Result<String> {
+            let _permit = self.model.acquire().await?;
+            if request.is_empty() {
+                return Err(SwarmError::Validation("empty task".to_string()).into());
+            }
+            Ok(request.to_uppercase())
+        }
+
+        async fn store(&self, prepared: String) -> Result<usize> {
+            let mut stored = self.stored.lock().unwrap();
+            stored.push(prepared);
+            Ok(stored.len())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_pipeline_bounds_a_burst_while_the_model_is_slow() -> Result<()> {
+        let gated = Arc::new(Gated { model: Semaphore::new(0), stored: Mutex::new(Vec::new()) });
+        fn lookup(vars: HashMap<&'static str, &'static str>) -> impl Fn(&str) -> Option<String> {
+            move |key| vars.get(key).map(|v| v.to_string())
+        }
+        let config = IngestConfig {
+            enhance: StageConfig::from_lookup("enhance", StageConfig::default(),
+                lookup(HashMap::from([("INGEST_ENHANCE_CAPACITY", "2"), ("INGEST_ENHANCE_OVERFLOW", "reject")])))?,
+            store: StageConfig::default(),
+        };
+        let pipeline = IngestPipeline::start(gated.clone(), config);
+
+        // One job is taken by the worker, two wait, the rest are refused
+        let mut accepted = Vec::new();
+        let mut rejected = 0;
+        for description in ["a", "b", "c", "d", "e"] {
+            match pipeline.submit(description.to_string()).await {
+                Ok(result) => accepted.push(result),
+                Err(_) => rejected += 1,
+            }
+            tokio::time::sleep(Duration::from_millis(5)).await;
+        }
+        assert_eq!((accepted.len(), rejected), (3, 2));
+        assert_eq!(pipeline.metrics()[0].depth, 2);
+
+        gated.model.add_permits(10);
+        for result in accepted {
+            result.await??;
+        }
+        assert_eq!(*gated.stored.lock().unwrap(), ["A", "B", "C"]);
+        let err = pipeline.process(String::new()).await.unwrap_err();
+        assert!(matches!(SwarmError::find(&err), Some(SwarmError::Validation(_))));
+        assert_eq!(pipeline.metrics()[0].failed, 1);
+        Ok(())
+    }
+
+    #[test]
+    fn test_invalid_stage_settings_are_rejected() {
+        let lookup = |key: &'static str, value: &'static str| move |k: &str| (k == key).then(|| value.to_string());
+        assert!(StageConfig::from_lookup("store", StageConfig::default(), lookup("INGEST_STORE_CAPACITY", "0")).is_err());
+        assert!(StageConfig::from_lookup("store", StageConfig::default(), lookup("INGEST_STORE_OVERFLOW", "spill")).is_err());
+        let config = StageConfig::from_lookup("store", StageConfig::default(), lookup("INGEST_STORE_OVERFLOW", "drop_oldest")).unwrap();
+        assert_eq!(config.overflow, OverflowPolicy::DropOldest);
+    }
+}
@@ -0,0 +1,472 @@
+//! Project documentation and code as context for agents. `KnowledgeBase::ingest` splits a
+//! project's markdown and source files into chunks, embeds them with an `AiProvider` and
+//! keeps them in the `knowledge` collection; `retrieve` returns the chunks most similar to a
+//! query, for agents to ground their answers in and cite. A file is embedded again only
+//! when it changes, and files removed from the project are dropped on the next ingest.
+//!
+//! Embeddings from different models can't be compared, so one provider embeds both chunks
+//! and queries: Ollama, or OpenAI with `KNOWLEDGE_EMBEDDINGS=openai`. Each chunk records
+//! its model, and chunks from another model are embedded again on the next ingest.
+
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use anyhow::{Result, anyhow};
+use chrono::Utc;
+use futures_util::TryStreamExt;
+use mongodb::{Collection, IndexModel};
+use mongodb::bson::{doc, Document};
+use mongodb::options::FindOptions;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use crate::ai::{AiProvider, LocalAiClient, OpenAiClient, cosine_similarity};
+use crate::db::Database;
+use crate::error::SwarmError;
+
+pub const COLLECTION: &str = "knowledge";
+/// Passages agents put in a prompt
+pub const DEFAULT_PASSAGES: usize = 4;
+/// How similar a chunk must be to a query to be retrieved
+pub const DEFAULT_MIN_SIMILARITY: f32 = 0.25;
+/// Most chunks of a project compared with a query; the most recently indexed are kept
+pub const MAX_CANDIDATES: i64 = 5000;
+
+/// Longest chunk; longer sections are split at paragraphs
+const MAX_CHUNK_CHARS: usize = 1500;
+const CODE_CHUNK_LINES: usize = 60;
+/// Larger files are generated or data, not documentation
+const MAX_FILE_BYTES: u64 = 256 * 1024;
+
+const MARKDOWN_EXTENSIONS: &[&str] = &["md", "markdown"];
+const TEXT_EXTENSIONS: &[&str] = &[
+    "txt", "rst", "rs", "py", "js", "ts", "tsx", "go", "java", "c", "h", "cpp", "sh", "toml", "yaml", "yml",
+];
+/// Build output and dependencies; hidden directories are skipped as well, but for `.github`,
+/// where CONTRIBUTING.md often is
+const SKIPPED_DIRS: &[&str] = &["target", "node_modules", "dist", "build", "vendor", "__pycache__"];
+
+/// A piece of a file, before it is embedded
+#[derive(Debug, Clone, PartialEq)]
+pub struct Section {
+    /// Heading of a markdown section, or the lines of code it covers
+    pub title: Option<String>,
+    pub text: String,
+}
+
+/// A stored chunk of a project file
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct KnowledgeChunk {
+    pub project: String,
+    /// Relative to the project root, with `/` separators
+    pub path: String,
+    pub title: Option<String>,
+    pub text: String,
+    /// Of the whole file, to tell when it changed
+    pub file_hash: String,
+    pub embedding: Vec<f32>,
+    /// The model that produced `embedding`
+    #[serde(default)]
+    pub embedding_model: String,
+    pub indexed_at: i64,
+}
+
+/// A retrieved chunk
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Passage {
+    pub path: String,
+    pub title: Option<String>,
+    pub text: String,
+    pub score: f32,
+}
+
+impl Passage {
+    /// Where the passage comes from, e.g. `CONTRIBUTING.md § Commit messages`
+    pub fn citation(&self) -> String {
+        match &self.title {
+            Some(title) => format!("{} § {}", self.path, title),
+            None => self.path.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct IngestReport {
+    /// Files new or changed since the last ingest
+    pub indexed: usize,
+    pub chunks: usize,
+    pub unchanged: usize,
+    /// Files gone from the project
+    pub removed: usize,
+    /// Files that aren't UTF-8 text
+    pub skipped: usize,
+}
+
+impl IngestReport {
+    pub fn summary(&self) -> String {
+        format!(
+            "{} file(s) indexed in {} chunk(s), {} unchanged, {} removed, {} skipped",
+            self.indexed, self.chunks, self.unchanged, self.removed, self.skipped,
+        )
+    }
+}
+
+/// The project a directory holds knowledge for: its name
+pub fn project_name(root: &Path) -> String {
+    let root = root.canonicalize().unwrap_or_else(|_| root.to_path_buf());
+    root.file_name().map(|name| name.to_string_lossy().to_string()).unwrap_or_else(|| root.display().to_string())
+}
+
+fn extension(path: &Path) -> Option<String> {
+    path.extension().and_then(|e| e.to_str()).map(str::to_lowercase)
+}
+
+fn is_markdown(path: &Path) -> bool {
+    extension(path).is_some_and(|e| MARKDOWN_EXTENSIONS.contains(&e.as_str()))
+}
+
+fn indexable(path: &Path) -> bool {
+    is_markdown(path) || extension(path).is_some_and(|e| TEXT_EXTENSIONS.contains(&e.as_str()))
+}
+
+/// The files under `root` worth indexing, sorted
+pub fn project_files(root: &Path) -> Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    let mut dirs = vec![root.to_path_buf()];
+    while let Some(dir) = dirs.pop() {
+        for entry in std::fs::read_dir(&dir).map_err(|e| anyhow!("Failed to read {}: {}", dir.display(), e))? {
+            let entry = entry?;
+            let path = entry.path();
+            let name = entry.file_name().to_string_lossy().to_string();
+            let file_type = entry.file_type()?;
+            if file_type.is_dir() {
+                if (name == ".github" || !name.starts_with('.')) && !SKIPPED_DIRS.contains(&name.as_str()) {
+                    dirs.push(path);
+                }
+            } else if file_type.is_file() && indexable(&path) && entry.metadata()?.len() <= MAX_FILE_BYTES {
+                files.push(path);
+            }
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+/// `text` cut into pieces of at most `MAX_CHUNK_CHARS`, at paragraphs where possible
+fn split_long(title: Option<String>, text: &str) -> Vec<Section> {
+    let mut pieces: Vec<String> = Vec::new();
+    let mut current = String::new();
+    for paragraph in text.split("\n\n").filter(|p| !p.trim().is_empty()) {
+        if !current.is_empty() && current.len() + paragraph.len() + 2 > MAX_CHUNK_CHARS {
+            pieces.push(std::mem::take(&mut current));
+        }
+        if paragraph.len() > MAX_CHUNK_CHARS {
+            let chars: Vec<char> = paragraph.chars().collect();
+            pieces.extend(chars.chunks(MAX_CHUNK_CHARS).map(|piece| piece.iter().collect::<String>()));
+            continue;
+        }
+        if !current.is_empty() {
+            current.push_str("\n\n");
+        }
+        current.push_str(paragraph);
+    }
+    if !current.is_empty() {
+        pieces.push(current);
+    }
+    pieces.into_iter().map(|text| Section { title: title.clone(), text }).collect()
+}
+
+/// Markdown split at its headings, each section titled by its heading
+pub fn split_markdown(text: &str) -> Vec<Section> {
+    let mut sections = Vec::new();
+    let mut title = None;
+    let mut current = String::new();
+    let mut in_fence = false;
+    for line in text.lines() {
+        if line.trim_start().starts_with("```") {
+            in_fence = !in_fence;
+        }
+        let heading = line.trim_start_matches('#');
+        if !in_fence && line.starts_with('#') && heading.starts_with(' ') {
+            sections.extend(split_long(title.take(), current.trim()));
+            current.clear();
+            title = Some(heading.trim().to_string());
+        }
+        current.push_str(line);
+        current.push('\n');
+    }
+    sections.extend(split_long(title, current.trim()));
+    sections
+}
+
+/// Source split into runs of lines, titled by the lines they cover
+pub fn split_code(text: &str) -> Vec<Section> {
+    let lines: Vec<&str> = text.lines().collect();
+    lines.chunks(CODE_CHUNK_LINES)
+        .enumerate()
+        .filter(|(_, chunk)| chunk.iter().any(|line| !line.trim().is_empty()))
+        .flat_map(|(i, chunk)| {
+            let start = i * CODE_CHUNK_LINES + 1;
+            split_long(Some(format!("lines {}-{}", start, start + chunk.len() - 1)), &chunk.join("\n"))
+        })
+        .collect()
+}
+
+pub fn sections(path: &Path, text: &str) -> Vec<Section> {
+    if is_markdown(path) { split_markdown(text) } else { split_code(text) }
+}
+
+/// A prompt section quoting `passages` for the model to ground its answer in; empty without any
+pub fn context_prompt(passages: &[Passage]) -> String {
+    if passages.is_empty() {
+        return String::new();
+    }
+    let quoted: Vec<String> = passages.iter()
+        .map(|passage| format!("[{}]\n{}", passage.citation(), passage.text.trim()))
+        .collect();
+    format!(
+        "\n\nFrom the project's own documentation; follow it where it applies, and cite the file when you do:\n\n{}",
+        quoted.join("\n\n"),
+    )
+}
+
+/// The provider named by `KNOWLEDGE_EMBEDDINGS`, and its embedding model
+fn embedder_from_env() -> (String, Arc<dyn AiProvider + Send + Sync>) {
+    match std::env::var("KNOWLEDGE_EMBEDDINGS").as_deref() {
+        Ok("openai") => {
+            let client = OpenAiClient::new();
+            (format!("openai/{}", client.embed_model()), Arc::new(client))
+        }
+        other => {
+            if let Some(other) = other.ok().filter(|name| *name != "ollama") {
+                tracing::warn!("Unknown KNOWLEDGE_EMBEDDINGS '{}', using Ollama", other);
+            }
+            let client = LocalAiClient::new();
+            (format!("ollama/{}", client.embed_model()), Arc::new(client))
+        }
+    }
+}
+
+/// Chunks of project files with their embeddings, in MongoDB
+#[derive(Clone)]
+pub struct KnowledgeBase {
+    collection: Collection<KnowledgeChunk>,
+    embedder: Arc<dyn AiProvider + Send + Sync>,
+    embedding_model: String,
+    min_similarity: f32,
+}
+
+impl KnowledgeBase {
+    pub fn from_database(db: &Database) -> Self {
+        let (embedding_model, embedder) = embedder_from_env();
+        Self {
+            collection: db.collection(COLLECTION),
+            embedder,
+            embedding_model,
+            min_similarity: DEFAULT_MIN_SIMILARITY,
+        }
+    }
+
+    /// The knowledge base in the shared database
+    pub async fn connect() -> Result<Self> {
+        let knowledge = Self::from_database(&Database::shared().await?);
+        knowledge.ensure_indexes().await?;
+        Ok(knowledge)
+    }
+
+    /// The shared knowledge base, or `None` when MongoDB isn't configured
+    pub async fn from_env() -> Result<Option<Self>> {
+        if crate::secrets::get(crate::secrets::MONGO_URI).is_none() {
+            return Ok(None);
+        }
+        Self::connect().await.map(Some)
+    }
+
+    /// Embed chunks and queries with `client`, whose embeddings `model` names
+    pub fn with_embedder<T: AiProvider + Send + Sync + 'static>(mut self, model: impl Into<String>, client: T) -> Self {
+        self.embedder = Arc::new(client);
+        self.embedding_model = model.into();
+        self
+    }
+
+    pub fn with_min_similarity(mut self, min_similarity: f32) -> Self {
+        self.min_similarity = min_similarity;
+        self
+    }
+
+    pub async fn ensure_indexes(&self) -> Result<()> {
+        let files = IndexModel::builder().keys(doc! { "project": 1, "path": 1 }).build();
+        let candidates = IndexModel::builder().keys(doc! { "project": 1, "embedding_model": 1, "indexed_at": -1 }).build();
+        self.collection.create_indexes([files, candidates], None).await.map_err(SwarmError::from)?;
+        Ok(())
+    }
+
+    /// Hash of each file stored for `project`, by path, and whether it was embedded with
+    /// this knowledge base's model
+    async fn indexed_files(&self, project: &str) -> Result<HashMap<String, (String, bool)>> {
+        let options = FindOptions::builder().projection(doc! { "path": 1, "file_hash": 1, "embedding_model": 1 }).build();
+        let mut cursor = self.collection.clone_with_type::<Document>()
+            .find(doc! { "project": project }, options).await.map_err(SwarmError::from)?;
+        let mut files = HashMap::new();
+        while let Some(chunk) = cursor.try_next().await.map_err(SwarmError::from)? {
+            if let (Ok(path), Ok(hash)) = (chunk.get_str("path"), chunk.get_str("file_hash")) {
+                let current = chunk.get_str("embedding_model").is_ok_and(|model| model == self.embedding_model);
+                let (_, all_current) = files.entry(path.to_string()).or_insert((hash.to_string(), true));
+                *all_current &= current;
+            }
+        }
+        Ok(files)
+    }
+
+    /// Index the files under `root` as `project`'s
+    pub async fn ingest(&self, project: &str, root: &Path) -> Result<IngestReport> {
+        let indexed = self.indexed_files(project).await?;
+        let mut report = IngestReport::default();
+        let mut present = HashSet::new();
+        for file in project_files(root)? {
+            let path = file.strip_prefix(root).unwrap_or(&file)
+                .components()
+                .map(|part| part.as_os_str().to_string_lossy())
+                .collect::<Vec<_>>()
+                .join("/");
+            let Ok(text) = std::fs::read_to_string(&file) else {
+                report.skipped += 1;
+                continue;
+            };
+            present.insert(path.clone());
+            let file_hash = hex::encode(Sha256::digest(text.as_bytes()));
+            if indexed.get(&path) == Some(&(file_hash.clone(), true)) {
+                report.unchanged += 1;
+                continue;
+            }
+
+            let mut chunks = Vec::new();
+            for section in sections(&file, &text) {
+                // The path and heading help queries like "contributing guidelines" find it
+                let embedding = self.embedder
+                    .embed(&format!("{}\n{}\n{}", path, section.title.as_deref().unwrap_or_default(), section.text))
+                    .await?;
+                chunks.push(KnowledgeChunk {
+                    project: project.to_string(),
+                    path: path.clone(),
+                    title: section.title,
+                    text: section.text,
+                    file_hash: file_hash.clone(),
+                    embedding,
+                    embedding_model: self.embedding_model.clone(),
+                    indexed_at: Utc::now().timestamp(),
+                });
+            }
+            self.collection.delete_many(doc! { "project": project, "path": &path }, None).await.map_err(SwarmError::from)?;
+            if !chunks.is_empty() {
+                self.collection.insert_many(&chunks, None).await.map_err(SwarmError::from)?;
+            }
+            report.indexed += 1;
+            report.chunks += chunks.len();
+        }
+
+        let removed: Vec<String> = indexed.into_keys().filter(|path| !present.contains(path)).collect();
+        if !removed.is_empty() {
+            self.collection.delete_many(doc! { "project": project, "path": { "$in": &removed } }, None).await.map_err(SwarmError::from)?;
+            report.removed = removed.len();
+        }
+        Ok(report)
+    }
+
+    /// Up to `limit` passages of `project` most similar to `query`, best first. Only chunks
+    /// embedded with this knowledge base's model are compared, at most `MAX_CANDIDATES`.
+    pub async fn retrieve(&self, project: &str, query: &str, limit: usize) -> Result<Vec<Passage>> {
+        let filter = doc! { "project": project, "embedding_model": &self.embedding_model };
+        let options = FindOptions::builder().sort(doc! { "indexed_at": -1 }).limit(MAX_CANDIDATES).build();
+        let mut cursor = self.collection.find(filter, options).await.map_err(SwarmError::from)?;
+        let mut query_embedding = None;
+        let mut passages: Vec<Passage> = Vec::new();
+        while let Some(chunk) = cursor.try_next().await.map_err(SwarmError::from)? {
+            // Embed the query only once there is something to compare it with
+            let query = match &query_embedding {
+                Some(query) => query,
+                None => query_embedding.insert(self.embedder.embed(query).await?),
+            };
+            if chunk.embedding.len() != query.len() {
+                continue;
+            }
+            let score = cosine_similarity(query, &chunk.embedding);
+            if score < self.min_similarity {
+                continue;
+            }
+            // Keep only the best `limit`, so memory doesn't grow with the project
+            let at = passages.partition_point(|passage| passage.score >= score);
+            if at < limit {
+                passages.insert(at, Passage { path: chunk.path, title: chunk.title, text: chunk.text, score });
+                passages.truncate(limit);
+            }
+        }
+        Ok(passages)
+    }
+
+    /// Drop everything indexed for `project`, returning how many chunks there were
+    pub async fn forget(&self, project: &str) -> Result<u64> {
+        let result = self.collection.delete_many(doc! { "project": project }, None).await.map_err(SwarmError::from)?;
+        Ok(result.deleted_count)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ai::MockAiProvider;
+    use crate::testsupport::MongoFixture;
+
+    #[test]
+    fn test_files_are_split_into_sections() {
+        let markdown = "Intro line\n\n## Commit messages\nUse `type(scope): summary`.\n\n```sh\n# not a heading\n```\n\n## Tests\nRun cargo test.\n";
+        let sections = split_markdown(markdown);
+        let titles: Vec<Option<&str>> = sections.iter().map(|s| s.title.as_deref()).collect();
+        assert_eq!(titles, [None, Some("Commit messages"), Some("Tests")]);
+        assert!(sections[1].text.starts_with("## Commit messages\nUse"));
+        assert!(sections[1].text.contains("# not a heading"));
+
+        let code = (1..=130).map(|i| format!("let x{} = {};", i, i)).collect::<Vec<_>>().join("\n");
+        let titles: Vec<String> = split_code(&code).into_iter().filter_map(|s| s.title).collect();
+        assert_eq!(titles, ["lines 1-60", "lines 61-120", "lines 121-130"]);
+
+        let long = vec!["word ".repeat(100); 8].join("\n\n");
+        assert!(split_long(None, &long).iter().all(|s| s.text.len() <= MAX_CHUNK_CHARS));
+        assert!(split_long(None, &long).len() > 1);
+
+        let passage = Passage { path: "CONTRIBUTING.md".to_string(), title: Some("Commit messages".to_string()), text: "Use feat:".to_string(), score: 0.9 };
+        assert_eq!(context_prompt(&[passage.clone()]).lines().last(), Some("Use feat:"));
+        assert!(context_prompt(&[passage]).contains("[CONTRIBUTING.md § Commit messages]"));
+        assert_eq!(context_prompt(&[]), "");
+    }
+
+    #[tokio::test]
+    async fn test_ingest_and_retrieve() -> Result<()> {
+        let mongo = MongoFixture::start().await?;
+        let ai = MockAiProvider::new();
+        let knowledge = KnowledgeBase::from_database(&mongo.db).with_embedder("mock", ai.clone());
+        let dir = tempfile::tempdir()?;
+        std::fs::write(dir.path().join("CONTRIBUTING.md"), "# Contributing\n\n## Commit messages\nStart commit messages with the ticket number.\n")?;
+        std::fs::create_dir_all(dir.path().join("src"))?;
+        std::fs::write(dir.path().join("src/lib.rs"), "pub fn parse_config() {}\n")?;
+        std::fs::create_dir_all(dir.path().join("target"))?;
+        std::fs::write(dir.path().join("target/notes.md"), "# Build output\n")?;
+
+        let report = knowledge.ingest("demo", dir.path()).await?;
+        assert_eq!((report.indexed, report.chunks), (2, 3));
+
+        let passages = knowledge.retrieve("demo", "How should commit messages start?", 1).await?;
+        assert_eq!(passages[0].citation(), "CONTRIBUTING.md § Commit messages");
+        assert!(knowledge.retrieve("other", "commit messages", 5).await?.is_empty());
+        // Chunks embedded by another model can't be compared with this one's queries
+        let other_model = KnowledgeBase::from_database(&mongo.db).with_embedder("other", ai.clone());
+        assert!(other_model.retrieve("demo", "How should commit messages start?", 5).await?.is_empty());
+
+        // Only changed files are indexed again, and removed files are forgotten
+        std::fs::remove_file(dir.path().join("src/lib.rs"))?;
+        let report = knowledge.ingest("demo", dir.path()).await?;
+        assert_eq!((report.indexed, report.unchanged, report.removed), (0, 1, 1));
+        assert!(knowledge.retrieve("demo", "parse_config", 5).await?.iter().all(|p| p.path != "src/lib.rs"));
+        assert_eq!(knowledge.forget("demo").await?, 2);
+        Ok(())
+    }
+}
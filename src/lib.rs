@@ -4,8 +4,40 @@ pub mod tools;
 pub mod config;
 pub mod api;
 pub mod error;
+pub mod db;
 pub mod types;
 pub mod ai;
+pub mod repl;
+pub mod shutdown;
+pub mod events;
+pub mod mesh;
+pub mod audit;
+pub mod scheduler;
+pub mod ingest;
+pub mod workflow;
+pub mod secrets;
+pub mod correlation;
+pub mod logging;
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
+#[cfg(test)]
+pub(crate) mod testsupport;
+pub mod mcp;
+pub mod chaos;
+pub mod knowledge;
+#[cfg(feature = "grpc")]
+pub mod grpc;
+#[cfg(feature = "speech")]
+pub mod speech;
+
+// Lets `#[derive(Agent)]` name this crate from inside it
+extern crate self as swarmonomicon;
+
+#[doc(hidden)]
+pub mod __private {
+    pub use anyhow;
+    pub use async_trait::async_trait;
+}
 
 pub use error::Error;
 pub type Result<T> = std::result::Result<T, Box<dyn std::error::Error + Send + Sync>>;
@@ -0,0 +1,46 @@
+//! Log output for the binaries. `RUST_LOG` overrides the default level, and `LOG_FORMAT=json`
+//! writes one JSON object per line, including the fields of the spans the event was logged
+//! in, so log aggregators can filter on a `correlation_id`.
+
+use std::env;
+use tracing::Level;
+use tracing_subscriber::{EnvFilter, filter::LevelFilter, fmt::format::FmtSpan};
+
+pub struct Logging {
+    level: Level,
+    stderr: bool,
+    span_timings: bool,
+}
+
+impl Logging {
+    pub fn new(level: Level) -> Self {
+        Self { level, stderr: false, span_timings: false }
+    }
+
+    /// Log to stderr, e.g. where stdout carries a protocol or command output
+    pub fn with_stderr(mut self) -> Self {
+        self.stderr = true;
+        self
+    }
+
+    /// Also log each span as it closes, with how long it took
+    pub fn with_span_timings(mut self) -> Self {
+        self.span_timings = true;
+        self
+    }
+
+    pub fn init(self) {
+        let filter = EnvFilter::builder()
+            .with_default_directive(LevelFilter::from_level(self.level).into())
+            .from_env_lossy();
+        let spans = if self.span_timings { FmtSpan::CLOSE } else { FmtSpan::NONE };
+        let builder = tracing_subscriber::fmt().with_env_filter(filter).with_span_events(spans);
+        let json = env::var("LOG_FORMAT").is_ok_and(|format| format.eq_ignore_ascii_case("json"));
+        match (json, self.stderr) {
+            (true, true) => builder.json().with_writer(std::io::stderr).init(),
+            (true, false) => builder.json().init(),
+            (false, true) => builder.with_writer(std::io::stderr).init(),
+            (false, false) => builder.init(),
+        }
+    }
+}
@@ -1,10 +1,11 @@
 use std::net::SocketAddr;
 use swarmonomicon::api::{serve, create_app_state};
+use swarmonomicon::logging::Logging;
 
 #[tokio::main]
 async fn main() {
     // Initialize the logger
-    env_logger::init();
+    Logging::new(tracing::Level::INFO).init();
 
     // Set up the server address
     let addr = SocketAddr::from(([127, 0, 0, 1], 3000));
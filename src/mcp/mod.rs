@@ -0,0 +1,302 @@
+//! A Model Context Protocol server, so MCP clients such as Cursor or Claude Desktop can
+//! drive the swarm directly.
+//!
+//! The tools of an `AgentToolset` are exposed as MCP tools taking string arguments, and
+//! run on its behalf; every agent as an `agent_<name>` tool taking a `message`. Agents are also listed
+//! as `agent://<name>` resources describing their config and current state.
+//! See `transport` for the stdio and SSE transports.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use base64::Engine as _;
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+use tokio::sync::RwLock;
+use crate::agents::{AgentRegistry, AgentWrapper};
+use crate::tools::{AgentToolset, ToolOutput, ToolRegistry};
+use crate::types::{Agent, AgentConfig, Message, Tool};
+
+pub mod transport;
+
+pub use transport::{serve_lines, serve_stdio, sse_router};
+
+/// The protocol revision this server speaks
+pub const PROTOCOL_VERSION: &str = "2024-11-05";
+/// Prefix of the tool names under which agents are exposed
+pub const AGENT_TOOL_PREFIX: &str = "agent_";
+const AGENT_URI_SCHEME: &str = "agent://";
+
+const PARSE_ERROR: i64 = -32700;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+const INTERNAL_ERROR: i64 = -32603;
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct JsonRpcRequest {
+    /// Absent for notifications, which get no response
+    #[serde(default)]
+    pub id: Option<Value>,
+    pub method: String,
+    #[serde(default)]
+    pub params: Value,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct JsonRpcError {
+    pub code: i64,
+    pub message: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JsonRpcResponse {
+    pub jsonrpc: String,
+    pub id: Value,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub result: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<JsonRpcError>,
+}
+
+impl JsonRpcResponse {
+    fn new(id: Value, outcome: std::result::Result<Value, JsonRpcError>) -> Self {
+        let (result, error) = match outcome {
+            Ok(result) => (Some(result), None),
+            Err(error) => (None, Some(error)),
+        };
+        Self { jsonrpc: "2.0".to_string(), id, result, error }
+    }
+}
+
+fn rpc_error(code: i64, message: impl Into<String>) -> JsonRpcError {
+    JsonRpcError { code, message: message.into() }
+}
+
+/// Name under which MCP clients run tools
+pub const MCP_AGENT_NAME: &str = "mcp";
+
+/// Handles MCP requests against a toolset and a set of agents
+pub struct McpServer {
+    tools: AgentToolset,
+    agents: Arc<RwLock<AgentRegistry>>,
+}
+
+impl McpServer {
+    pub fn new(tools: AgentToolset, agents: Arc<RwLock<AgentRegistry>>) -> Self {
+        Self { tools, agents }
+    }
+
+    /// The toolset for MCP clients: the named tools of `registry`
+    pub fn toolset(names: &[String], registry: Arc<RwLock<ToolRegistry>>) -> AgentToolset {
+        let config = AgentConfig {
+            name: MCP_AGENT_NAME.to_string(),
+            public_description: "MCP clients".to_string(),
+            instructions: String::new(),
+            tools: names.iter().map(|name| Tool { name: name.clone(), description: String::new(), parameters: HashMap::new() }).collect(),
+            downstream_agents: Vec::new(),
+            personality: None,
+            state_machine: None,
+            script: None,
+            max_concurrency: None,
+            middleware: None,
+            remote: None,
+        };
+        AgentToolset::new(&config, registry)
+    }
+
+    /// Handle one JSON-RPC message, returning the serialized response if one is due
+    pub async fn handle_json(&self, text: &str) -> Option<String> {
+        let response = match serde_json::from_str::<JsonRpcRequest>(text) {
+            Ok(request) => self.handle(request).await?,
+            Err(e) => JsonRpcResponse::new(Value::Null, Err(rpc_error(PARSE_ERROR, format!("Invalid request: {}", e)))),
+        };
+        serde_json::to_string(&response).ok()
+    }
+
+    pub async fn handle(&self, request: JsonRpcRequest) -> Option<JsonRpcResponse> {
+        let id = request.id?;
+        let outcome = match request.method.as_str() {
+            "initialize" => Ok(json!({
+                "protocolVersion": PROTOCOL_VERSION,
+                "capabilities": { "tools": {}, "resources": {} },
+                "serverInfo": { "name": "swarmonomicon", "version": env!("CARGO_PKG_VERSION") },
+            })),
+            "ping" => Ok(json!({})),
+            "tools/list" => Ok(self.list_tools().await),
+            "tools/call" => self.call_tool(&request.params).await,
+            "resources/list" => Ok(self.list_resources().await),
+            "resources/read" => self.read_resource(&request.params).await,
+            method => Err(rpc_error(METHOD_NOT_FOUND, format!("Unknown method '{}'", method))),
+        };
+        Some(JsonRpcResponse::new(id, outcome))
+    }
+
+    async fn list_tools(&self) -> Value {
+        let mut tools: Vec<Value> = self.tools.available_tools().await.into_iter().map(|name| json!({
+            "name": name,
+            "description": format!("Run the '{}' tool", name),
+            "inputSchema": { "type": "object", "additionalProperties": { "type": "string" } },
+        })).collect();
+
+        let agents = self.agents.read().await;
+        let mut names: Vec<&String> = agents.agents.keys().collect();
+        names.sort();
+        for name in names {
+            let description = match agents.agents[name].get_config().await {
+                Ok(config) => config.public_description,
+                Err(_) => format!("Send a message to the {} agent", name),
+            };
+            tools.push(json!({
+                "name": format!("{}{}", AGENT_TOOL_PREFIX, name),
+                "description": description,
+                "inputSchema": {
+                    "type": "object",
+                    "properties": { "message": { "type": "string" } },
+                    "required": ["message"],
+                },
+            }));
+        }
+        json!({ "tools": tools })
+    }
+
+    async fn call_tool(&self, params: &Value) -> std::result::Result<Value, JsonRpcError> {
+        let name = params["name"].as_str().ok_or_else(|| rpc_error(INVALID_PARAMS, "Missing tool name"))?;
+        let arguments: HashMap<String, String> = params["arguments"].as_object().into_iter().flatten()
+            .map(|(k, v)| (k.clone(), v.as_str().map_or_else(|| v.to_string(), str::to_string)))
+            .collect();
+
+        let agent = match name.strip_prefix(AGENT_TOOL_PREFIX) {
+            Some(agent) => self.agent(agent).await,
+            None => None,
+        };
+        let outcome = if let Some(agent) = agent {
+            let message = arguments.get("message").cloned()
+                .ok_or_else(|| rpc_error(INVALID_PARAMS, "Agent tools require a 'message' argument"))?;
+            agent.process_message(Message::new(message)).await.map(|reply| vec![text_content(reply.content)])
+        } else {
+            if !self.tools.available_tools().await.iter().any(|tool| tool == name) {
+                return Err(rpc_error(INVALID_PARAMS, format!("Unknown tool '{}'", name)));
+            }
+            let tool = Tool { name: name.to_string(), description: String::new(), parameters: HashMap::new() };
+            self.tools.execute_structured(&tool, arguments).await.map(|output| vec![output_content(name, output)])
+        };
+
+        Ok(match outcome {
+            Ok(content) => json!({ "content": content, "isError": false }),
+            Err(e) => json!({ "content": [text_content(format!("{:#}", e))], "isError": true }),
+        })
+    }
+
+    /// A handle to the named agent, so the registry isn't locked while it works
+    async fn agent(&self, name: &str) -> Option<AgentWrapper> {
+        self.agents.read().await.get(name).cloned()
+    }
+
+    async fn list_resources(&self) -> Value {
+        let agents = self.agents.read().await;
+        let mut names: Vec<&String> = agents.agents.keys().collect();
+        names.sort();
+        let resources: Vec<Value> = names.into_iter().map(|name| json!({
+            "uri": format!("{}{}", AGENT_URI_SCHEME, name),
+            "name": name,
+            "description": format!("Config and current state of the {} agent", name),
+            "mimeType": "application/json",
+        })).collect();
+        json!({ "resources": resources })
+    }
+
+    async fn read_resource(&self, params: &Value) -> std::result::Result<Value, JsonRpcError> {
+        let uri = params["uri"].as_str().ok_or_else(|| rpc_error(INVALID_PARAMS, "Missing resource uri"))?;
+        let agent = match uri.strip_prefix(AGENT_URI_SCHEME) {
+            Some(name) => self.agent(name).await,
+            None => None,
+        }.ok_or_else(|| rpc_error(INVALID_PARAMS, format!("Unknown resource '{}'", uri)))?;
+
+        let internal = |e: anyhow::Error| rpc_error(INTERNAL_ERROR, e.to_string());
+        let config = agent.get_config().await.map_err(internal)?;
+        let state = agent.get_current_state().await.map_err(internal)?;
+        let text = serde_json::to_string_pretty(&json!({ "config": config, "state": state }))
+            .map_err(|e| internal(e.into()))?;
+        Ok(json!({ "contents": [{ "uri": uri, "mimeType": "application/json", "text": text }] }))
+    }
+}
+
+fn text_content(text: impl Into<String>) -> Value {
+    json!({ "type": "text", "text": text.into() })
+}
+
+fn output_content(tool: &str, output: ToolOutput) -> Value {
+    match output {
+        ToolOutput::Binary(bytes) => json!({
+            "type": "resource",
+            "resource": {
+                "uri": format!("tool://{}/output", tool),
+                "mimeType": "application/octet-stream",
+                "blob": base64::engine::general_purpose::STANDARD.encode(bytes),
+            },
+        }),
+        ToolOutput::Json(value) => text_content(serde_json::to_string_pretty(&value).unwrap_or_default()),
+        other => text_content(other.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use async_trait::async_trait;
+    use crate::tools::ToolExecutor;
+
+    struct Echo;
+
+    #[async_trait]
+    impl ToolExecutor for Echo {
+        async fn execute(&self, params: HashMap<String, String>) -> anyhow::Result<String> {
+            params.get("text").cloned().ok_or_else(|| anyhow::anyhow!("text is required"))
+        }
+    }
+
+    pub(crate) fn server() -> McpServer {
+        let mut tools = ToolRegistry::new();
+        tools.register("echo".to_string(), Echo);
+        tools.register("hidden".to_string(), Echo);
+        let toolset = McpServer::toolset(&["echo".to_string()], Arc::new(RwLock::new(tools)));
+        McpServer::new(toolset, Arc::new(RwLock::new(AgentRegistry::new())))
+    }
+
+    async fn call(server: &McpServer, method: &str, params: Value) -> Value {
+        let request = json!({ "jsonrpc": "2.0", "id": 1, "method": method, "params": params });
+        let response = server.handle_json(&request.to_string()).await.unwrap();
+        serde_json::from_str(&response).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_lists_and_calls_tools() {
+        let server = server();
+        let initialized = call(&server, "initialize", json!({})).await;
+        assert_eq!(initialized["result"]["protocolVersion"], PROTOCOL_VERSION);
+
+        let listed = call(&server, "tools/list", json!({})).await;
+        assert_eq!(listed["result"]["tools"][0]["name"], "echo");
+        assert_eq!(listed["result"]["tools"].as_array().unwrap().len(), 1);
+
+        let echoed = call(&server, "tools/call", json!({ "name": "echo", "arguments": { "text": "hi" } })).await;
+        assert_eq!(echoed["result"], json!({ "content": [{ "type": "text", "text": "hi" }], "isError": false }));
+
+        let failed = call(&server, "tools/call", json!({ "name": "echo", "arguments": {} })).await;
+        assert_eq!(failed["result"]["isError"], true);
+        assert!(failed["result"]["content"][0]["text"].as_str().unwrap().contains("text is required"));
+    }
+
+    #[tokio::test]
+    async fn test_protocol_errors() {
+        let server = server();
+        assert_eq!(call(&server, "tools/call", json!({ "name": "nope" })).await["error"]["code"], INVALID_PARAMS);
+        assert_eq!(call(&server, "tools/call", json!({ "name": "hidden" })).await["error"]["code"], INVALID_PARAMS);
+        assert_eq!(call(&server, "sampling/createMessage", json!({})).await["error"]["code"], METHOD_NOT_FOUND);
+        assert_eq!(call(&server, "resources/read", json!({ "uri": "agent://nope" })).await["error"]["code"], INVALID_PARAMS);
+
+        let notification = json!({ "jsonrpc": "2.0", "method": "notifications/initialized" });
+        assert!(server.handle_json(&notification.to_string()).await.is_none());
+        let garbage: Value = serde_json::from_str(&server.handle_json("{not json").await.unwrap()).unwrap();
+        assert_eq!(garbage["error"]["code"], PARSE_ERROR);
+    }
+}
@@ -0,0 +1,184 @@
+//! MCP transports: newline-delimited JSON over stdio, and the HTTP+SSE transport where
+//! clients open `GET /sse`, are told where to POST requests, and receive responses as
+//! `message` events on the stream.
+
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::sync::Arc;
+use std::time::Duration;
+use anyhow::Result;
+use axum::{
+    Router,
+    extract::{Query, State},
+    http::StatusCode,
+    middleware,
+    response::sse::{Event, KeepAlive, Sse},
+    routing::{get, post},
+};
+use futures::Stream;
+use serde::Deserialize;
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncWrite, AsyncWriteExt, BufReader};
+use tokio::sync::{Mutex, mpsc};
+use uuid::Uuid;
+use crate::api::auth::{ApiAuth, require_scope};
+use crate::types::api_keys::Scope;
+use super::McpServer;
+
+/// Answer requests read line by line from `reader`, writing one response per line
+pub async fn serve_lines<R, W>(server: &McpServer, reader: R, mut writer: W) -> Result<()>
+where
+    R: AsyncBufRead + Unpin,
+    W: AsyncWrite + Unpin,
+{
+    let mut lines = reader.lines();
+    while let Some(line) = lines.next_line().await? {
+        if line.trim().is_empty() {
+            continue;
+        }
+        if let Some(response) = server.handle_json(&line).await {
+            writer.write_all(response.as_bytes()).await?;
+            writer.write_all(b"\n").await?;
+            writer.flush().await?;
+        }
+    }
+    Ok(())
+}
+
+/// Serve over stdin/stdout until stdin closes. Logs must go to stderr in this mode.
+pub async fn serve_stdio(server: &McpServer) -> Result<()> {
+    serve_lines(server, BufReader::new(tokio::io::stdin()), tokio::io::stdout()).await
+}
+
+struct SseState {
+    server: Arc<McpServer>,
+    sessions: Mutex<HashMap<String, mpsc::Sender<Event>>>,
+}
+
+#[derive(Deserialize)]
+struct SessionQuery {
+    #[serde(rename = "sessionId")]
+    session_id: String,
+}
+
+/// Routes for the SSE transport: `GET /sse` and `POST /messages?sessionId=...`.
+/// With `auth`, both need a key with the admin scope.
+pub fn sse_router(server: Arc<McpServer>, auth: Option<Arc<ApiAuth>>) -> Router {
+    let state = Arc::new(SseState { server, sessions: Mutex::new(HashMap::new()) });
+    let router = Router::new()
+        .route("/sse", get(open_session))
+        .route("/messages", post(post_message))
+        .with_state(state);
+    match auth {
+        Some(auth) => router.layer(middleware::from_fn_with_state((auth, Scope::Admin), require_scope)),
+        None => router,
+    }
+}
+
+async fn open_session(State(state): State<Arc<SseState>>) -> Sse<impl Stream<Item = std::result::Result<Event, Infallible>>> {
+    let session_id = Uuid::new_v4().to_string();
+    let (sender, receiver) = mpsc::channel(32);
+    let endpoint = Event::default().event("endpoint").data(format!("/messages?sessionId={}", session_id));
+    // The receiver was just created, so the first event always fits
+    let _ = sender.try_send(endpoint);
+    state.sessions.lock().await.insert(session_id, sender);
+
+    let stream = futures::stream::unfold(receiver, |mut receiver| async move {
+        receiver.recv().await.map(|event| (Ok(event), receiver))
+    });
+    Sse::new(stream).keep_alive(KeepAlive::new().interval(Duration::from_secs(15)))
+}
+
+/// Accept a request for a session; its response is delivered on the session's stream
+async fn post_message(
+    State(state): State<Arc<SseState>>,
+    Query(query): Query<SessionQuery>,
+    body: String,
+) -> StatusCode {
+    let Some(sender) = state.sessions.lock().await.get(&query.session_id).cloned() else {
+        return StatusCode::NOT_FOUND;
+    };
+    tokio::spawn(async move {
+        let Some(response) = state.server.handle_json(&body).await else {
+            return;
+        };
+        if sender.send(Event::default().event("message").data(response)).await.is_err() {
+            tracing::debug!("MCP session {} closed", query.session_id);
+            state.sessions.lock().await.remove(&query.session_id);
+        }
+    });
+    StatusCode::ACCEPTED
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::{Value, json};
+    use crate::mcp::tests::server;
+    use crate::types::api_keys::InMemoryApiKeyStore;
+
+    /// The data of the next event on an SSE response, buffering partial chunks in `received`
+    async fn next_data(stream: &mut reqwest::Response, received: &mut String) -> Result<String> {
+        loop {
+            if let Some(end) = received.find("\n\n") {
+                let event: String = received.drain(..end + 2).collect();
+                if let Some(data) = event.lines().find_map(|l| l.strip_prefix("data: ")) {
+                    return Ok(data.to_string());
+                }
+                continue;
+            }
+            let chunk = stream.chunk().await?.ok_or_else(|| anyhow::anyhow!("SSE stream ended"))?;
+            received.push_str(std::str::from_utf8(&chunk)?);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_stdio_transport() -> Result<()> {
+        let input = [
+            json!({ "jsonrpc": "2.0", "id": 1, "method": "ping" }).to_string(),
+            json!({ "jsonrpc": "2.0", "method": "notifications/initialized" }).to_string(),
+            String::new(),
+            json!({ "jsonrpc": "2.0", "id": "two", "method": "tools/call", "params": { "name": "echo", "arguments": { "text": "hi" } } }).to_string(),
+        ].join("\n");
+        let mut output = Vec::new();
+        serve_lines(&server(), input.as_bytes(), &mut output).await?;
+
+        let responses: Vec<Value> = String::from_utf8(output)?.lines().map(serde_json::from_str).collect::<std::result::Result<_, _>>()?;
+        assert_eq!(responses.len(), 2);
+        assert_eq!(responses[0], json!({ "jsonrpc": "2.0", "id": 1, "result": {} }));
+        assert_eq!(responses[1]["id"], "two");
+        assert_eq!(responses[1]["result"]["content"][0]["text"], "hi");
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_sse_transport() -> Result<()> {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+        let base = format!("http://{}", listener.local_addr()?);
+        let auth = ApiAuth::new(Arc::new(InMemoryApiKeyStore::new())).with_bootstrap_key("root-secret");
+        let app = sse_router(Arc::new(server()), Some(Arc::new(auth)));
+        let handle = tokio::spawn(async move { axum::serve(listener, app).await });
+
+        let client = reqwest::Client::new();
+        assert_eq!(client.get(format!("{}/sse", base)).send().await?.status().as_u16(), 401);
+        let client = reqwest::Client::builder().default_headers(reqwest::header::HeaderMap::from_iter([(
+            reqwest::header::AUTHORIZATION, "Bearer root-secret".parse()?,
+        )])).build()?;
+        let mut stream = client.get(format!("{}/sse", base)).send().await?;
+        let mut received = String::new();
+
+        let endpoint = next_data(&mut stream, &mut received).await?;
+        assert!(endpoint.starts_with("/messages?sessionId="));
+        let request = json!({ "jsonrpc": "2.0", "id": 7, "method": "tools/list" });
+        let posted = client.post(format!("{}{}", base, endpoint)).body(request.to_string()).send().await?;
+        assert_eq!(posted.status().as_u16(), 202);
+
+        let response: Value = serde_json::from_str(&next_data(&mut stream, &mut received).await?)?;
+        assert_eq!(response["id"], 7);
+        assert_eq!(response["result"]["tools"][0]["name"], "echo");
+
+        let unknown = client.post(format!("{}/messages?sessionId=nope", base)).body(request.to_string()).send().await?;
+        assert_eq!(unknown.status().as_u16(), 404);
+        handle.abort();
+        Ok(())
+    }
+}
@@ -0,0 +1,453 @@
+//! Agents on several machines acting as one registry, over MQTT. Each node announces its
+//! agents and their capabilities, retained, on `<prefix>/discovery/<node>`. Messages for an
+//! agent on another node go out on `<prefix>/request/<node>` and the reply comes back on
+//! `<prefix>/response/<node>`, matched by correlation id. Set `SWARM_MESH_NODE` to join.
+
+use std::collections::HashMap;
+use std::env;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use anyhow::Result;
+use rumqttc::{AsyncClient, Event, EventLoop, Incoming, LastWill, QoS};
+use serde::{Deserialize, Serialize};
+use tokio::sync::{Mutex, RwLock, oneshot};
+use tokio::task::JoinHandle;
+use crate::agents::AgentRegistry;
+use crate::config::mqtt::MqttConfig;
+use crate::error::SwarmError;
+use crate::types::{Agent, AgentCapabilities, Attachment, Message};
+
+const DEFAULT_PREFIX: &str = "swarm/mesh";
+const DEFAULT_TIMEOUT_SECS: u64 = 30;
+const ANNOUNCE_INTERVAL: Duration = Duration::from_secs(30);
+/// Nodes that miss this many announcements are treated as gone
+const MISSED_ANNOUNCEMENTS: u32 = 3;
+
+#[derive(Debug, Clone)]
+pub struct MeshConfig {
+    /// This node's name; unique across the mesh
+    pub node: String,
+    pub prefix: String,
+    pub mqtt: MqttConfig,
+    /// How long to wait for a remote agent's reply
+    pub timeout: Duration,
+    pub announce_interval: Duration,
+}
+
+impl MeshConfig {
+    pub fn new(node: impl Into<String>) -> Self {
+        Self {
+            node: node.into(),
+            prefix: DEFAULT_PREFIX.to_string(),
+            mqtt: MqttConfig::default(),
+            timeout: Duration::from_secs(DEFAULT_TIMEOUT_SECS),
+            announce_interval: ANNOUNCE_INTERVAL,
+        }
+    }
+
+    /// The mesh named by `SWARM_MESH_NODE`, on the broker the `MQTT_*` variables describe,
+    /// or `None` if this node doesn't join one
+    pub fn from_env() -> Result<Option<Self>> {
+        let Ok(node) = env::var("SWARM_MESH_NODE") else {
+            return Ok(None);
+        };
+        let mut config = Self::new(node);
+        config.mqtt = MqttConfig::from_env()?;
+        if let Ok(prefix) = env::var("SWARM_MESH_PREFIX") {
+            config.prefix = prefix.trim_end_matches('/').to_string();
+        }
+        if let Some(secs) = env::var("SWARM_MESH_TIMEOUT_SECS").ok().and_then(|secs| secs.parse().ok()) {
+            config.timeout = Duration::from_secs(secs);
+        }
+        Ok(Some(config))
+    }
+
+    fn topic(&self, kind: &str, node: &str) -> String {
+        format!("{}/{}/{}", self.prefix, kind, node)
+    }
+}
+
+/// What a node publishes on its discovery topic
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NodeAnnouncement {
+    pub node: String,
+    pub agents: Vec<AgentCapabilities>,
+    pub timestamp: i64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MeshRequest {
+    pub correlation_id: String,
+    /// Node the response goes to
+    pub reply_to: String,
+    pub agent: String,
+    pub message: Message,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MeshResponse {
+    pub correlation_id: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub message: Option<Message>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+struct RemoteNode {
+    agents: Vec<AgentCapabilities>,
+    seen: Instant,
+}
+
+pub struct AgentMesh {
+    config: MeshConfig,
+    client: AsyncClient,
+    registry: Arc<RwLock<AgentRegistry>>,
+    nodes: RwLock<HashMap<String, RemoteNode>>,
+    pending: Mutex<HashMap<String, oneshot::Sender<MeshResponse>>>,
+}
+
+impl AgentMesh {
+    fn new(config: MeshConfig, client: AsyncClient, registry: Arc<RwLock<AgentRegistry>>) -> Self {
+        Self {
+            config,
+            client,
+            registry,
+            nodes: RwLock::new(HashMap::new()),
+            pending: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Connect to the broker and join the mesh, announcing the agents in `registry` and
+    /// answering requests for them until the process exits
+    pub async fn start(config: MeshConfig, registry: Arc<RwLock<AgentRegistry>>) -> Result<Arc<Self>> {
+        let mut options = config.mqtt.options(&format!("swarm-mesh-{}", config.node))?;
+        // Clears the retained announcement if this node drops off without leaving
+        options.set_last_will(LastWill::new(config.topic("discovery", &config.node), Vec::new(), QoS::AtLeastOnce, true));
+
+        let (client, event_loop) = AsyncClient::new(options, 100);
+        let mesh = Arc::new(Self::new(config, client, registry));
+        tracing::info!("Joining agent mesh as {} on {}:{}", mesh.config.node, mesh.config.mqtt.host, mesh.config.mqtt.port);
+        mesh.clone().run(event_loop);
+        mesh.clone().announce_periodically();
+        Ok(mesh)
+    }
+
+    pub fn node(&self) -> &str {
+        &self.config.node
+    }
+
+    fn run(self: Arc<Self>, mut event_loop: EventLoop) -> JoinHandle<()> {
+        tokio::spawn(async move {
+            loop {
+                match event_loop.poll().await {
+                    // Subscriptions don't survive a clean-session reconnect
+                    Ok(Event::Incoming(Incoming::ConnAck(_))) => {
+                        if let Err(e) = self.subscribe().await {
+                            tracing::warn!("Agent mesh subscription failed: {}", e);
+                        }
+                        if let Err(e) = self.announce().await {
+                            tracing::warn!("Agent mesh announcement failed: {}", e);
+                        }
+                    }
+                    Ok(Event::Incoming(Incoming::Publish(publish))) => {
+                        let mesh = self.clone();
+                        tokio::spawn(async move { mesh.handle_publish(&publish.topic, &publish.payload).await });
+                    }
+                    Ok(_) => {}
+                    Err(e) => {
+                        tracing::warn!("Agent mesh connection error: {}", e);
+                        tokio::time::sleep(Duration::from_secs(1)).await;
+                    }
+                }
+            }
+        })
+    }
+
+    fn announce_periodically(self: Arc<Self>) -> JoinHandle<()> {
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(self.config.announce_interval);
+            // The first announcement goes out on connect
+            interval.tick().await;
+            loop {
+                interval.tick().await;
+                if let Err(e) = self.announce().await {
+                    tracing::warn!("Agent mesh announcement failed: {}", e);
+                }
+            }
+        })
+    }
+
+    async fn subscribe(&self) -> Result<()> {
+        self.client.subscribe(self.config.topic("discovery", "+"), QoS::AtLeastOnce).await?;
+        self.client.subscribe(self.config.topic("request", &self.config.node), QoS::AtLeastOnce).await?;
+        self.client.subscribe(self.config.topic("response", &self.config.node), QoS::AtLeastOnce).await?;
+        Ok(())
+    }
+
+    /// Publish this node's agents and capabilities
+    pub async fn announce(&self) -> Result<()> {
+        let announcement = NodeAnnouncement {
+            node: self.config.node.clone(),
+            agents: self.registry.read().await.capability_catalog().await,
+            timestamp: chrono::Utc::now().timestamp(),
+        };
+        let topic = self.config.topic("discovery", &self.config.node);
+        self.client.publish(topic, QoS::AtLeastOnce, true, serde_json::to_vec(&announcement)?).await?;
+        Ok(())
+    }
+
+    /// Withdraw this node's announcement, e.g. on shutdown
+    pub async fn leave(&self) -> Result<()> {
+        let topic = self.config.topic("discovery", &self.config.node);
+        self.client.publish(topic, QoS::AtLeastOnce, true, Vec::new()).await?;
+        Ok(())
+    }
+
+    async fn handle_publish(&self, topic: &str, payload: &[u8]) {
+        let Some(rest) = topic.strip_prefix(&self.config.prefix).and_then(|rest| rest.strip_prefix('/')) else {
+            return;
+        };
+        let result = match rest.split_once('/') {
+            Some(("discovery", node)) => self.handle_announcement(node, payload).await,
+            Some(("request", _)) => match serde_json::from_slice(payload) {
+                Ok(request) => self.handle_request(request).await,
+                Err(e) => Err(e.into()),
+            },
+            Some(("response", _)) => match serde_json::from_slice(payload) {
+                Ok(response) => {
+                    self.handle_response(response).await;
+                    Ok(())
+                }
+                Err(e) => Err(e.into()),
+            },
+            _ => Ok(()),
+        };
+        if let Err(e) = result {
+            tracing::warn!("Ignoring agent mesh message on {}: {}", topic, e);
+        }
+    }
+
+    /// An empty announcement means the node left
+    async fn handle_announcement(&self, node: &str, payload: &[u8]) -> Result<()> {
+        if node == self.config.node {
+            return Ok(());
+        }
+        if payload.is_empty() {
+            if self.nodes.write().await.remove(node).is_some() {
+                tracing::info!("Agent mesh node {} left", node);
+            }
+            return Ok(());
+        }
+        let announcement: NodeAnnouncement = serde_json::from_slice(payload)?;
+        let joined = self.nodes.write().await
+            .insert(node.to_string(), RemoteNode { agents: announcement.agents, seen: Instant::now() })
+            .is_none();
+        if joined {
+            tracing::info!("Agent mesh node {} joined", node);
+        }
+        Ok(())
+    }
+
+    async fn handle_request(&self, request: MeshRequest) -> Result<()> {
+        let result = {
+            let registry = self.registry.read().await;
+            match registry.get(&request.agent) {
+                // File attachments name paths on the sending node, not this one
+                Some(_) if !request.message.attachments.iter().all(Attachment::is_inline) => {
+                    Err(SwarmError::Validation("Agent mesh requests may only carry inline attachments".to_string()).into())
+                }
+                Some(agent) => agent.process_message(request.message).await,
+                None => Err(SwarmError::AgentNotFound(request.agent.clone()).into()),
+            }
+        };
+        let response = match result {
+            Ok(message) => MeshResponse { correlation_id: request.correlation_id, message: Some(message), error: None },
+            Err(e) => MeshResponse { correlation_id: request.correlation_id, message: None, error: Some(e.to_string()) },
+        };
+        let topic = self.config.topic("response", &request.reply_to);
+        self.client.publish(topic, QoS::AtLeastOnce, false, serde_json::to_vec(&response)?).await?;
+        Ok(())
+    }
+
+    async fn handle_response(&self, response: MeshResponse) {
+        match self.pending.lock().await.remove(&response.correlation_id) {
+            // The requester may have given up already
+            Some(sender) => { let _ = sender.send(response); }
+            None => tracing::debug!("Dropping late agent mesh response {}", response.correlation_id),
+        }
+    }
+
+    fn is_live(&self, node: &RemoteNode) -> bool {
+        node.seen.elapsed() < self.config.announce_interval * MISSED_ANNOUNCEMENTS
+    }
+
+    /// The node running `agent`, if another node in the mesh announced it. Ties go to the
+    /// first node by name, so every caller picks the same one.
+    pub async fn node_for(&self, agent: &str) -> Option<String> {
+        let nodes = self.nodes.read().await;
+        let mut candidates: Vec<&String> = nodes.iter()
+            .filter(|(_, node)| self.is_live(node) && node.agents.iter().any(|entry| entry.agent == agent))
+            .map(|(name, _)| name)
+            .collect();
+        candidates.sort();
+        candidates.first().map(|name| name.to_string())
+    }
+
+    /// Capabilities of the agents on other nodes, sorted by agent name
+    pub async fn remote_catalog(&self) -> Vec<AgentCapabilities> {
+        let nodes = self.nodes.read().await;
+        let mut catalog: Vec<AgentCapabilities> = nodes.values()
+            .filter(|node| self.is_live(node))
+            .flat_map(|node| node.agents.iter().cloned())
+            .collect();
+        catalog.sort_by(|a, b| a.agent.cmp(&b.agent));
+        catalog.dedup_by(|a, b| a.agent == b.agent);
+        catalog
+    }
+
+    /// Have `agent` on another node process `message`, waiting up to the configured timeout
+    pub async fn request(&self, agent: &str, message: Message) -> Result<Message> {
+        let node = self.node_for(agent).await
+            .ok_or_else(|| SwarmError::AgentNotFound(agent.to_string()))?;
+        let correlation_id = uuid::Uuid::new_v4().to_string();
+        let (sender, receiver) = oneshot::channel();
+        self.pending.lock().await.insert(correlation_id.clone(), sender);
+
+        let request = MeshRequest {
+            correlation_id: correlation_id.clone(),
+            reply_to: self.config.node.clone(),
+            agent: agent.to_string(),
+            message,
+        };
+        let published = self.client
+            .publish(self.config.topic("request", &node), QoS::AtLeastOnce, false, serde_json::to_vec(&request)?)
+            .await;
+        if let Err(e) = published {
+            self.pending.lock().await.remove(&correlation_id);
+            return Err(SwarmError::Transfer(format!("Could not reach agent {} on {}: {}", agent, node, e)).into());
+        }
+
+        match tokio::time::timeout(self.config.timeout, receiver).await {
+            Ok(Ok(MeshResponse { message: Some(message), .. })) => Ok(message),
+            Ok(Ok(MeshResponse { error, .. })) => Err(SwarmError::Agent(
+                error.unwrap_or_else(|| format!("Agent {} on {} sent an empty response", agent, node))
+            ).into()),
+            Ok(Err(_)) | Err(_) => {
+                self.pending.lock().await.remove(&correlation_id);
+                Err(SwarmError::Transfer(format!(
+                    "Agent {} on {} did not answer within {}s", agent, node, self.config.timeout.as_secs()
+                )).into())
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rumqttc::MqttOptions;
+    use crate::testing::EchoAgent;
+    use crate::types::{AgentCapabilities, Capability};
+
+    /// A mesh whose broker connection is never polled; the event loop must outlive it
+    fn mesh(timeout: Duration) -> (Arc<AgentMesh>, EventLoop) {
+        let mut config = MeshConfig::new("laptop");
+        config.timeout = timeout;
+        let (client, event_loop) = AsyncClient::new(MqttOptions::new("test", "localhost", 1883), 10);
+        (Arc::new(AgentMesh::new(config, client, Arc::new(RwLock::new(AgentRegistry::new())))), event_loop)
+    }
+
+    fn announcement(node: &str, agents: &[&str]) -> Vec<u8> {
+        serde_json::to_vec(&NodeAnnouncement {
+            node: node.to_string(),
+            agents: agents.iter()
+                .map(|agent| AgentCapabilities { agent: agent.to_string(), capabilities: vec![Capability::new(*agent, "test")] })
+                .collect(),
+            timestamp: 0,
+        }).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_announced_agents_are_routable_until_their_node_leaves() {
+        let (mesh, _event_loop) = mesh(Duration::from_secs(1));
+        mesh.handle_publish("swarm/mesh/discovery/gpu-box", &announcement("gpu-box", &["browser", "haiku"])).await;
+        mesh.handle_publish("swarm/mesh/discovery/pi", &announcement("pi", &["haiku"])).await;
+        mesh.handle_publish("swarm/mesh/discovery/laptop", &announcement("laptop", &["git"])).await;
+
+        assert_eq!(mesh.node_for("browser").await.as_deref(), Some("gpu-box"));
+        assert_eq!(mesh.node_for("haiku").await.as_deref(), Some("gpu-box"));
+        // This node's own agents are local, not remote
+        assert_eq!(mesh.node_for("git").await, None);
+        let agents: Vec<String> = mesh.remote_catalog().await.into_iter().map(|entry| entry.agent).collect();
+        assert_eq!(agents, vec!["browser", "haiku"]);
+
+        mesh.handle_publish("swarm/mesh/discovery/gpu-box", &[]).await;
+        assert_eq!(mesh.node_for("browser").await, None);
+        assert_eq!(mesh.node_for("haiku").await.as_deref(), Some("pi"));
+    }
+
+    #[tokio::test]
+    async fn test_requests_are_answered_by_correlation_id() -> Result<()> {
+        let (mesh, _event_loop) = mesh(Duration::from_secs(5));
+        mesh.handle_publish("swarm/mesh/discovery/gpu-box", &announcement("gpu-box", &["browser"])).await;
+
+        let request = tokio::spawn({
+            let mesh = mesh.clone();
+            async move { mesh.request("browser", Message::new("open example.com".to_string())).await }
+        });
+        let correlation_id = loop {
+            if let Some(id) = mesh.pending.lock().await.keys().next().cloned() {
+                break id;
+            }
+            tokio::task::yield_now().await;
+        };
+        let stray = MeshResponse { correlation_id: "other".to_string(), message: None, error: Some("wrong".to_string()) };
+        mesh.handle_publish("swarm/mesh/response/laptop", &serde_json::to_vec(&stray)?).await;
+        let response = MeshResponse { correlation_id, message: Some(Message::new("opened".to_string())), error: None };
+        mesh.handle_publish("swarm/mesh/response/laptop", &serde_json::to_vec(&response)?).await;
+
+        assert_eq!(request.await??.content, "opened");
+        assert!(mesh.pending.lock().await.is_empty());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_unanswered_and_unknown_requests_fail() {
+        let (mesh, _event_loop) = mesh(Duration::from_millis(50));
+        let err = mesh.request("browser", Message::new("hi".to_string())).await.unwrap_err();
+        assert!(matches!(SwarmError::find(&err), Some(SwarmError::AgentNotFound(name)) if name == "browser"));
+
+        mesh.handle_publish("swarm/mesh/discovery/gpu-box", &announcement("gpu-box", &["browser"])).await;
+        let err = mesh.request("browser", Message::new("hi".to_string())).await.unwrap_err();
+        assert!(matches!(SwarmError::find(&err), Some(SwarmError::Transfer(_))), "{}", err);
+        assert!(mesh.pending.lock().await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_requests_reach_agents_through_a_broker() -> Result<()> {
+        let (host, port) = crate::testsupport::mqtt_broker().await?;
+        // The broker is shared with other tests
+        let prefix = format!("swarm-test/{}", uuid::Uuid::new_v4().simple());
+        let config = |node: &str| {
+            let mut config = MeshConfig::new(node);
+            config.prefix = prefix.clone();
+            config.mqtt = MqttConfig { host: host.clone(), port, ..MqttConfig::default() };
+            config.timeout = Duration::from_secs(5);
+            config
+        };
+        let mongo = crate::testsupport::MongoFixture::start().await?;
+        let mut agents = AgentRegistry::new().with_todos(mongo.todo_list().await);
+        agents.register("echo".to_string(), Box::new(EchoAgent::new("echo"))).await?;
+        let _gpu_box = AgentMesh::start(config("gpu-box"), Arc::new(RwLock::new(agents))).await?;
+        let laptop = AgentMesh::start(config("laptop"), Arc::new(RwLock::new(AgentRegistry::new()))).await?;
+
+        tokio::time::timeout(Duration::from_secs(10), async {
+            while laptop.node_for("echo").await.is_none() {
+                tokio::time::sleep(Duration::from_millis(50)).await;
+            }
+        }).await?;
+        let reply = laptop.request("echo", Message::new("ping".to_string())).await?;
+        assert_eq!(reply.content, "ping");
+        Ok(())
+    }
+}
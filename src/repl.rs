@@ -0,0 +1,241 @@
+use std::collections::HashMap;
+use std::io::Write;
+use anyhow::{Result, anyhow};
+use rustyline::DefaultEditor;
+use rustyline::error::ReadlineError;
+use tokio::sync::mpsc::unbounded_channel;
+use uuid::Uuid;
+use crate::agents::AgentRegistry;
+use crate::ai::streaming;
+use crate::types::{Agent, Message, MessageMetadata};
+use crate::types::sessions::SESSION_ID_KEY;
+
+const HELP: &str = "Commands:
+  /transfer <agent>  switch to another agent
+  /agents            list agents
+  /state             show the current agent's state
+  /history           show this conversation
+  /help              show this help
+  /quit              exit
+Anything else is sent to the current agent.";
+
+/// A line of REPL input
+#[derive(Debug, Clone, PartialEq)]
+pub enum ReplCommand {
+    Transfer(String),
+    Agents,
+    State,
+    History,
+    Help,
+    Quit,
+    Message(String),
+}
+
+impl ReplCommand {
+    /// Parse a line; None for blank input
+    pub fn parse(line: &str) -> Result<Option<Self>> {
+        let line = line.trim();
+        if line.is_empty() {
+            return Ok(None);
+        }
+        let Some(command) = line.strip_prefix('/') else {
+            return Ok(Some(ReplCommand::Message(line.to_string())));
+        };
+
+        let (name, argument) = command.split_once(char::is_whitespace).unwrap_or((command, ""));
+        let argument = argument.trim();
+        Ok(Some(match name {
+            "transfer" if argument.is_empty() => return Err(anyhow!("Usage: /transfer <agent>")),
+            "transfer" => ReplCommand::Transfer(argument.to_string()),
+            "agents" => ReplCommand::Agents,
+            "state" => ReplCommand::State,
+            "history" => ReplCommand::History,
+            "help" => ReplCommand::Help,
+            "quit" | "exit" => ReplCommand::Quit,
+            other => return Err(anyhow!("Unknown command /{}, try /help", other)),
+        }))
+    }
+}
+
+/// Interactive chat session with the agents in a registry, starting at the greeter
+pub struct Repl {
+    registry: AgentRegistry,
+    current: String,
+    session_id: String,
+    history: Vec<(String, String)>,
+}
+
+impl Repl {
+    pub fn new(registry: AgentRegistry) -> Self {
+        Self {
+            registry,
+            current: "greeter".to_string(),
+            session_id: Uuid::new_v4().to_string(),
+            history: Vec::new(),
+        }
+    }
+
+    pub fn with_agent(mut self, agent: impl Into<String>) -> Self {
+        self.current = agent.into();
+        self
+    }
+
+    /// `agent> `, or `agent[state]> ` for agents with a state machine
+    async fn prompt(&self) -> String {
+        let state = match self.registry.get(&self.current) {
+            Some(agent) => agent.get_current_state().await.ok().flatten().map(|s| s.name),
+            None => None,
+        };
+        match state {
+            Some(state) => format!("{}[{}]> ", self.current, state),
+            None => format!("{}> ", self.current),
+        }
+    }
+
+    pub async fn run(mut self) -> Result<()> {
+        if !self.registry.exists(&self.current) {
+            return Err(anyhow!("Agent '{}' not found", self.current));
+        }
+        let mut editor = DefaultEditor::new()?;
+        println!("Chatting with {}. Type /help for commands.", self.current);
+
+        loop {
+            let line = match editor.readline(&self.prompt().await) {
+                Ok(line) => line,
+                Err(ReadlineError::Interrupted) | Err(ReadlineError::Eof) => break,
+                Err(e) => return Err(e.into()),
+            };
+            let command = match ReplCommand::parse(&line) {
+                Ok(Some(command)) => command,
+                Ok(None) => continue,
+                Err(e) => {
+                    println!("{}", e);
+                    continue;
+                }
+            };
+            let _ = editor.add_history_entry(line.trim());
+
+            if let Err(e) = self.execute(command.clone()).await {
+                println!("Error: {}", e);
+            }
+            if command == ReplCommand::Quit {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    async fn execute(&mut self, command: ReplCommand) -> Result<()> {
+        match command {
+            ReplCommand::Transfer(agent) => self.switch_to(agent)?,
+            ReplCommand::Agents => {
+                let mut names: Vec<&String> = self.registry.agents.keys().collect();
+                names.sort();
+                for name in names {
+                    let marker = if *name == self.current { "*" } else { " " };
+                    let description = self.registry.agents[name].get_config().await
+                        .map(|c| c.public_description)
+                        .unwrap_or_default();
+                    println!("{} {:<12} {}", marker, name, description);
+                }
+            }
+            ReplCommand::State => {
+                let agent = self.registry.get(&self.current)
+                    .ok_or_else(|| anyhow!("Agent '{}' not found", self.current))?;
+                match agent.get_current_state().await? {
+                    Some(state) => {
+                        println!("{}", state.name);
+                        if let Some(prompt) = state.prompt {
+                            println!("  {}", prompt);
+                        }
+                    }
+                    None => println!("{} has no state machine", self.current),
+                }
+            }
+            ReplCommand::History => {
+                for (speaker, content) in &self.history {
+                    println!("{}: {}", speaker, content);
+                }
+            }
+            ReplCommand::Help => println!("{}", HELP),
+            ReplCommand::Quit => {}
+            ReplCommand::Message(content) => self.send(content).await?,
+        }
+        Ok(())
+    }
+
+    fn switch_to(&mut self, agent: String) -> Result<()> {
+        if !self.registry.exists(&agent) {
+            return Err(anyhow!("Agent '{}' not found", agent));
+        }
+        println!("Transferred to {}", agent);
+        self.registry.set_current_agent(agent.clone());
+        self.current = agent;
+        Ok(())
+    }
+
+    /// Send a message, printing AI output as it streams in, and follow any transfer
+    async fn send(&mut self, content: String) -> Result<()> {
+        let agent = self.registry.get(&self.current)
+            .ok_or_else(|| anyhow!("Agent '{}' not found", self.current))?;
+        let message = Message::new(content.clone())
+            .with_role(Some("user".to_string()))
+            .with_metadata(MessageMetadata::new("repl".to_string())
+                .with_context(HashMap::from([(SESSION_ID_KEY.to_string(), self.session_id.clone())])));
+        self.history.push(("you".to_string(), content));
+
+        let (sink, mut chunks) = unbounded_channel();
+        let mut streamed = String::new();
+        let response = {
+            let response = streaming::with_chunk_sink(sink, agent.process_message(message));
+            tokio::pin!(response);
+            loop {
+                tokio::select! {
+                    Some(chunk) = chunks.recv() => {
+                        print!("{}", chunk);
+                        std::io::stdout().flush()?;
+                        streamed.push_str(&chunk);
+                    }
+                    response = &mut response => break response?,
+                }
+            }
+        };
+        while let Ok(chunk) = chunks.try_recv() {
+            print!("{}", chunk);
+            streamed.push_str(&chunk);
+        }
+
+        // Agents may post-process or replace what the model produced
+        if streamed.trim() != response.content.trim() {
+            if !streamed.is_empty() {
+                println!();
+            }
+            print!("{}", response.content);
+        }
+        println!();
+        self.history.push((self.current.clone(), response.content.clone()));
+
+        if let Some(target) = response.metadata.and_then(|m| m.transfer_target) {
+            if target != self.current {
+                self.switch_to(target)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_commands() {
+        assert_eq!(ReplCommand::parse("  ").unwrap(), None);
+        assert_eq!(ReplCommand::parse("write about rust").unwrap(), Some(ReplCommand::Message("write about rust".to_string())));
+        assert_eq!(ReplCommand::parse("/transfer  haiku ").unwrap(), Some(ReplCommand::Transfer("haiku".to_string())));
+        assert_eq!(ReplCommand::parse("/agents").unwrap(), Some(ReplCommand::Agents));
+        assert_eq!(ReplCommand::parse("/exit").unwrap(), Some(ReplCommand::Quit));
+        assert!(ReplCommand::parse("/transfer").is_err());
+        assert!(ReplCommand::parse("/dance").unwrap_err().to_string().contains("/help"));
+    }
+}
@@ -0,0 +1,295 @@
+//! Recurring actions on cron schedules: message an agent, run a tool, or summarize open
+//! todos, optionally posting the result through the notification tool. Jobs come from the
+//! file at `SCHEDULE_CONFIG` (YAML or JSON):
+//!
+//! ```yaml
+//! timezone: local        # or utc, the default
+//! jobs:
+//!   - name: nightly-summary
+//!     cron: "0 18 * * 1-5"
+//!     action: { report: { per_project: 5 } }
+//!     notify: true
+//!   - name: sync-todo-md
+//!     cron: "0 * * * *"
+//!     action: { tool: { name: todo_sync, params: { path: /srv/repo } } }
+//!   - name: standup
+//!     cron: "0 9 * * 1-5"
+//!     action: { message: { agent: greeter, content: "What's on today?" } }
+//!     notify: true
+//!     channel: team
+//! ```
+//!
+//! Cron expressions have five fields (minute to weekday), or six or seven starting with
+//! seconds and optionally ending with the year.
+
+use std::collections::HashMap;
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::Arc;
+use anyhow::{Result, anyhow, bail};
+use chrono::{DateTime, Local, Utc};
+use cron::Schedule;
+use serde::Deserialize;
+use tokio::sync::RwLock;
+use tokio::task::JoinHandle;
+use crate::agents::AgentRegistry;
+use crate::tools::{NotificationKind, NotificationTool, ToolRegistry};
+use crate::types::{Agent, Message, Tool, TodoList};
+use crate::types::reporting::format_open_summary;
+
+const DEFAULT_PER_PROJECT: usize = 5;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ScheduleTimezone {
+    #[default]
+    Utc,
+    Local,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum JobAction {
+    /// Send `content` to `agent`; the result is its reply
+    Message { agent: String, content: String },
+    /// Run a registered tool; the result is its output
+    Tool {
+        name: String,
+        #[serde(default)]
+        params: HashMap<String, String>,
+    },
+    /// Summarize open todos per project, or for one project
+    Report {
+        #[serde(default)]
+        project: Option<String>,
+        #[serde(default = "default_per_project")]
+        per_project: usize,
+    },
+}
+
+fn default_per_project() -> usize {
+    DEFAULT_PER_PROJECT
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct JobConfig {
+    pub name: String,
+    pub cron: String,
+    /// Written `{ report: {...} }` rather than YAML's `!report` tag
+    #[serde(with = "serde_yaml::with::singleton_map")]
+    pub action: JobAction,
+    /// Post the result through the notification tool
+    #[serde(default)]
+    pub notify: bool,
+    /// Notification channel; the project's routing when unset
+    #[serde(default)]
+    pub channel: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct ScheduleConfig {
+    #[serde(default)]
+    pub timezone: ScheduleTimezone,
+    #[serde(default)]
+    pub jobs: Vec<JobConfig>,
+}
+
+impl ScheduleConfig {
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let source = std::fs::read_to_string(path).map_err(|e| anyhow!("Failed to read {}: {}", path.display(), e))?;
+        // YAML is a superset of JSON
+        serde_yaml::from_str(&source).map_err(|e| anyhow!("{}: {}", path.display(), e))
+    }
+}
+
+/// A cron expression, with five-field expressions run at second 0
+pub fn parse_cron(expression: &str) -> Result<Schedule> {
+    let expression = expression.trim();
+    let expression = match expression.split_whitespace().count() {
+        5 => format!("0 {}", expression),
+        6 | 7 => expression.to_string(),
+        n => bail!("Cron expression '{}' has {} fields, expected 5, 6 or 7", expression, n),
+    };
+    Schedule::from_str(&expression).map_err(|e| anyhow!("Invalid cron expression '{}': {}", expression, e))
+}
+
+struct ScheduledJob {
+    config: JobConfig,
+    schedule: Schedule,
+}
+
+pub struct Scheduler {
+    jobs: Vec<ScheduledJob>,
+    timezone: ScheduleTimezone,
+    agents: Option<Arc<RwLock<AgentRegistry>>>,
+    tools: Option<Arc<ToolRegistry>>,
+    todos: Option<TodoList>,
+    notifier: Option<NotificationTool>,
+}
+
+impl Scheduler {
+    pub fn new(config: ScheduleConfig) -> Result<Self> {
+        let jobs = config.jobs.into_iter()
+            .map(|job| {
+                let schedule = parse_cron(&job.cron).map_err(|e| anyhow!("Job {}: {}", job.name, e))?;
+                Ok(ScheduledJob { config: job, schedule })
+            })
+            .collect::<Result<Vec<_>>>()?;
+        Ok(Self { jobs, timezone: config.timezone, agents: None, tools: None, todos: None, notifier: None })
+    }
+
+    /// The jobs in `SCHEDULE_CONFIG`, or `None` if it isn't set
+    pub fn from_env() -> Result<Option<Self>> {
+        std::env::var("SCHEDULE_CONFIG").ok()
+            .map(|path| Self::new(ScheduleConfig::from_file(path)?))
+            .transpose()
+    }
+
+    pub fn with_agents(mut self, agents: Arc<RwLock<AgentRegistry>>) -> Self {
+        self.agents = Some(agents);
+        self
+    }
+
+    pub fn with_tools(mut self, tools: Arc<ToolRegistry>) -> Self {
+        self.tools = Some(tools);
+        self
+    }
+
+    pub fn with_todos(mut self, todos: TodoList) -> Self {
+        self.todos = Some(todos);
+        self
+    }
+
+    pub fn with_notifier(mut self, notifier: NotificationTool) -> Self {
+        self.notifier = Some(notifier);
+        self
+    }
+
+    pub fn job_names(&self) -> Vec<&str> {
+        self.jobs.iter().map(|job| job.config.name.as_str()).collect()
+    }
+
+    fn next_for(&self, job: &ScheduledJob, after: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        match self.timezone {
+            ScheduleTimezone::Utc => job.schedule.after(&after).next(),
+            ScheduleTimezone::Local => job.schedule.after(&after.with_timezone(&Local)).next().map(|t| t.with_timezone(&Utc)),
+        }
+    }
+
+    /// The next time any job is due after `after`, and the indices of the jobs due then
+    pub fn next_run(&self, after: DateTime<Utc>) -> Option<(DateTime<Utc>, Vec<usize>)> {
+        let upcoming: Vec<(usize, DateTime<Utc>)> = self.jobs.iter().enumerate()
+            .filter_map(|(index, job)| Some((index, self.next_for(job, after)?)))
+            .collect();
+        let first = upcoming.iter().map(|(_, at)| *at).min()?;
+        Some((first, upcoming.into_iter().filter(|(_, at)| *at == first).map(|(index, _)| index).collect()))
+    }
+
+    async fn perform(&self, action: &JobAction) -> Result<String> {
+        match action {
+            JobAction::Message { agent, content } => {
+                let agents = self.agents.as_ref().ok_or_else(|| anyhow!("No agents to message"))?;
+                // Clone the agent out so the registry isn't locked while it works
+                let target = agents.read().await.get(agent).cloned().ok_or_else(|| anyhow!("No agent {}", agent))?;
+                Ok(target.process_message(Message::new(content.clone())).await?.content)
+            }
+            JobAction::Tool { name, params } => {
+                let tools = self.tools.as_ref().ok_or_else(|| anyhow!("No tools to run"))?;
+                let tool = Tool { name: name.clone(), description: String::new(), parameters: HashMap::new() };
+                tools.execute(&tool, params.clone()).await
+            }
+            JobAction::Report { project, per_project } => {
+                let todos = self.todos.as_ref().ok_or_else(|| anyhow!("Reports need MongoDB (RTK_MONGO_URI)"))?;
+                let mut tasks = todos.list_tasks(false, &[]).await?;
+                if let Some(project) = project {
+                    tasks.retain(|task| task.project.as_ref() == Some(project));
+                }
+                Ok(format_open_summary(&tasks, *per_project))
+            }
+        }
+    }
+
+    /// Run the job at `index` now, notifying with its result if it asks to
+    pub async fn run_job(&self, index: usize) -> Result<String> {
+        let job = &self.jobs.get(index).ok_or_else(|| anyhow!("No job {}", index))?.config;
+        let output = self.perform(&job.action).await?;
+        if job.notify {
+            let notifier = self.notifier.as_ref().ok_or_else(|| anyhow!("Job {} notifies, but no channels are configured", job.name))?;
+            let mut vars = HashMap::from([("text".to_string(), output.clone()), ("job".to_string(), job.name.clone())]);
+            if let JobAction::Report { project: Some(project), .. } = &job.action {
+                vars.insert("project".to_string(), project.clone());
+            }
+            notifier.notify(NotificationKind::Message, &vars, job.channel.as_deref()).await?;
+        }
+        Ok(output)
+    }
+
+    /// Run jobs as they come due, each in its own task so a slow job doesn't delay the rest
+    pub fn spawn(self) -> JoinHandle<()> {
+        let scheduler = Arc::new(self);
+        tokio::spawn(async move {
+            let mut after = Utc::now();
+            while let Some((at, due)) = scheduler.next_run(after) {
+                let wait = (at - Utc::now()).to_std().unwrap_or_default();
+                tokio::time::sleep(wait).await;
+                for index in due {
+                    let scheduler = scheduler.clone();
+                    tokio::spawn(async move {
+                        let name = scheduler.jobs[index].config.name.clone();
+                        match scheduler.run_job(index).await {
+                            Ok(_) => tracing::info!("Scheduled job {} ran", name),
+                            Err(e) => tracing::warn!("Scheduled job {} failed: {}", name, e),
+                        }
+                    });
+                }
+                after = at;
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    fn test_parse_cron_accepts_five_to_seven_fields() {
+        let at = Utc.with_ymd_and_hms(2026, 3, 2, 17, 30, 0).unwrap();
+        let nightly = parse_cron("0 18 * * *").unwrap();
+        assert_eq!(nightly.after(&at).next(), Some(Utc.with_ymd_and_hms(2026, 3, 2, 18, 0, 0).unwrap()));
+        let with_seconds = parse_cron("30 */5 * * * *").unwrap();
+        assert_eq!(with_seconds.after(&at).next(), Some(Utc.with_ymd_and_hms(2026, 3, 2, 17, 30, 30).unwrap()));
+        assert!(parse_cron("* * *").is_err());
+        assert!(parse_cron("0 25 * * *").is_err());
+    }
+
+    #[test]
+    fn test_next_run_groups_jobs_due_together() {
+        let config: ScheduleConfig = serde_yaml::from_str(r#"
+jobs:
+  - name: hourly
+    cron: "0 * * * *"
+    action: { tool: { name: todo_sync } }
+  - name: nightly
+    cron: "0 18 * * *"
+    action: { report: {} }
+    notify: true
+  - name: half-hourly
+    cron: "*/30 * * * *"
+    action: { message: { agent: greeter, content: hi } }
+"#).unwrap();
+        assert!(matches!(config.jobs[1].action, JobAction::Report { project: None, per_project: DEFAULT_PER_PROJECT }));
+        let scheduler = Scheduler::new(config).unwrap();
+        assert_eq!(scheduler.job_names(), ["hourly", "nightly", "half-hourly"]);
+
+        let at = |h, m| Utc.with_ymd_and_hms(2026, 3, 2, h, m, 0).unwrap();
+        assert_eq!(scheduler.next_run(at(17, 10)), Some((at(17, 30), vec![2])));
+        assert_eq!(scheduler.next_run(at(17, 30)), Some((at(18, 0), vec![0, 1, 2])));
+
+        let bad: ScheduleConfig = serde_yaml::from_str("jobs: [{ name: x, cron: nope, action: { report: {} } }]").unwrap();
+        let error = Scheduler::new(bad).err().unwrap();
+        assert!(error.to_string().starts_with("Job x:"), "{}", error);
+    }
+}
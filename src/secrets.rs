@@ -0,0 +1,284 @@
+//! Credentials (provider keys, tokens, passwords, connection strings) come from here
+//! rather than from the environment directly, so they can also be kept in an encrypted
+//! file or the OS keychain.
+//!
+//! The default chain checks the environment first, then `SWARM_SECRETS_FILE` if set,
+//! then the keychain if `SWARM_SECRETS_KEYCHAIN=true`.
+
+use std::collections::HashMap;
+use std::env;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use anyhow::{Context, Result, anyhow, bail};
+use lazy_static::lazy_static;
+
+pub const OPENAI_API_KEY: &str = "OPENAI_API_KEY";
+pub const GEMMA_API_KEY: &str = "GEMMA_API_KEY";
+pub const HF_API_TOKEN: &str = "HF_API_TOKEN";
+pub const GITHUB_TOKEN: &str = "GITHUB_TOKEN";
+pub const MQTT_USERNAME: &str = "MQTT_USERNAME";
+pub const MQTT_PASSWORD: &str = "MQTT_PASSWORD";
+pub const MAIL_PASSWORD: &str = "MAIL_PASSWORD";
+pub const MONGO_URI: &str = "RTK_MONGO_URI";
+pub const ADMIN_API_KEY: &str = "SWARM_ADMIN_API_KEY";
+pub const SLACK_WEBHOOK_URL: &str = "SLACK_WEBHOOK_URL";
+pub const DISCORD_WEBHOOK_URL: &str = "DISCORD_WEBHOOK_URL";
+
+/// Service name keychain entries are stored under
+pub const KEYCHAIN_SERVICE: &str = "swarmonomicon";
+
+pub trait SecretsProvider: Send + Sync {
+    fn name(&self) -> &str;
+
+    /// The secret called `key`, or `None` if this provider doesn't have it
+    fn get(&self, key: &str) -> Result<Option<String>>;
+}
+
+/// Secrets from environment variables of the same name. Empty values count as unset.
+pub struct EnvSecrets;
+
+impl SecretsProvider for EnvSecrets {
+    fn name(&self) -> &str {
+        "env"
+    }
+
+    fn get(&self, key: &str) -> Result<Option<String>> {
+        Ok(env::var(key).ok().filter(|value| !value.is_empty()))
+    }
+}
+
+/// `KEY=value` lines read once from a file. Files ending in `.age` are decrypted with
+/// the identities in `SWARM_SECRETS_IDENTITY` (needs the `secrets-age` feature), and
+/// files ending in `.gpg` or `.asc` with the `gpg` CLI and its agent.
+pub struct FileSecrets {
+    path: PathBuf,
+    values: HashMap<String, String>,
+}
+
+impl FileSecrets {
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let contents = match path.extension().and_then(|ext| ext.to_str()) {
+            Some("age") => decrypt_age(&path)?,
+            Some("gpg") | Some("asc") => decrypt_gpg(&path)?,
+            _ => std::fs::read_to_string(&path)
+                .with_context(|| format!("Failed to read secrets file {}", path.display()))?,
+        };
+        Ok(Self { path, values: parse_secrets(&contents) })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl SecretsProvider for FileSecrets {
+    fn name(&self) -> &str {
+        "file"
+    }
+
+    fn get(&self, key: &str) -> Result<Option<String>> {
+        Ok(self.values.get(key).cloned())
+    }
+}
+
+/// `KEY=value` lines; blank lines, `#` comments and an `export ` prefix are allowed,
+/// and values may be quoted
+fn parse_secrets(contents: &str) -> HashMap<String, String> {
+    contents.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .filter_map(|line| {
+            let (key, value) = line.strip_prefix("export ").unwrap_or(line).split_once('=')?;
+            let value = value.trim();
+            let value = value.strip_prefix('"').and_then(|v| v.strip_suffix('"'))
+                .or_else(|| value.strip_prefix('\'').and_then(|v| v.strip_suffix('\'')))
+                .unwrap_or(value);
+            Some((key.trim().to_string(), value.to_string()))
+        })
+        .collect()
+}
+
+#[cfg(feature = "secrets-age")]
+fn decrypt_age(path: &Path) -> Result<String> {
+    use std::io::Read;
+    let identity_path = env::var("SWARM_SECRETS_IDENTITY")
+        .map_err(|_| anyhow!("SWARM_SECRETS_IDENTITY must name an age identity file to decrypt {}", path.display()))?;
+    let identities = age::IdentityFile::from_file(identity_path)?.into_identities()?;
+    let file = std::io::BufReader::new(std::fs::File::open(path)?);
+    let decryptor = age::Decryptor::new_buffered(file)?;
+    let mut contents = String::new();
+    decryptor.decrypt(identities.iter().map(|identity| identity.as_ref() as &dyn age::Identity))?
+        .read_to_string(&mut contents)?;
+    Ok(contents)
+}
+
+#[cfg(not(feature = "secrets-age"))]
+fn decrypt_age(path: &Path) -> Result<String> {
+    bail!("{} is age-encrypted; build with the secrets-age feature to read it", path.display())
+}
+
+fn decrypt_gpg(path: &Path) -> Result<String> {
+    let output = Command::new("gpg")
+        .args(["--batch", "--quiet", "--decrypt"])
+        .arg(path)
+        .output()
+        .context("Failed to run gpg")?;
+    if !output.status.success() {
+        bail!("gpg could not decrypt {}: {}", path.display(), String::from_utf8_lossy(&output.stderr).trim());
+    }
+    Ok(String::from_utf8(output.stdout)?)
+}
+
+/// Secrets in the OS keychain (Keychain, Secret Service or Credential Manager), stored
+/// under the `swarmonomicon` service with the secret's name as the user
+#[cfg(feature = "secrets-keychain")]
+pub struct KeychainSecrets {
+    service: String,
+}
+
+#[cfg(feature = "secrets-keychain")]
+impl KeychainSecrets {
+    pub fn new() -> Self {
+        Self { service: KEYCHAIN_SERVICE.to_string() }
+    }
+
+    pub fn with_service(mut self, service: impl Into<String>) -> Self {
+        self.service = service.into();
+        self
+    }
+}
+
+#[cfg(feature = "secrets-keychain")]
+impl SecretsProvider for KeychainSecrets {
+    fn name(&self) -> &str {
+        "keychain"
+    }
+
+    fn get(&self, key: &str) -> Result<Option<String>> {
+        match keyring::Entry::new(&self.service, key)?.get_password() {
+            Ok(secret) => Ok(Some(secret)),
+            Err(keyring::Error::NoEntry) => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+}
+
+/// Providers consulted in order; the first to have a secret wins
+#[derive(Default)]
+pub struct ChainedSecrets {
+    providers: Vec<Box<dyn SecretsProvider>>,
+}
+
+impl ChainedSecrets {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_provider(mut self, provider: impl SecretsProvider + 'static) -> Self {
+        self.providers.push(Box::new(provider));
+        self
+    }
+
+    /// The environment, then `SWARM_SECRETS_FILE`, then the keychain when
+    /// `SWARM_SECRETS_KEYCHAIN=true`. A secrets file that can't be read is logged and skipped.
+    pub fn from_env() -> Self {
+        let mut chain = Self::new().with_provider(EnvSecrets);
+        if let Ok(path) = env::var("SWARM_SECRETS_FILE") {
+            match FileSecrets::open(&path) {
+                Ok(file) => chain = chain.with_provider(file),
+                Err(e) => tracing::error!("Secrets file {} unavailable: {:#}", path, e),
+            }
+        }
+        if env::var("SWARM_SECRETS_KEYCHAIN").is_ok_and(|v| v == "true" || v == "1") {
+            #[cfg(feature = "secrets-keychain")]
+            {
+                chain = chain.with_provider(KeychainSecrets::new());
+            }
+            #[cfg(not(feature = "secrets-keychain"))]
+            tracing::warn!("SWARM_SECRETS_KEYCHAIN is set but the secrets-keychain feature is not enabled");
+        }
+        chain
+    }
+}
+
+impl SecretsProvider for ChainedSecrets {
+    fn name(&self) -> &str {
+        "chain"
+    }
+
+    fn get(&self, key: &str) -> Result<Option<String>> {
+        for provider in &self.providers {
+            if let Some(secret) = provider.get(key)
+                .with_context(|| format!("Failed to read {} from the {} secrets provider", key, provider.name()))? {
+                return Ok(Some(secret));
+            }
+        }
+        Ok(None)
+    }
+}
+
+lazy_static! {
+    static ref GLOBAL_SECRETS: ChainedSecrets = ChainedSecrets::from_env();
+}
+
+/// The process-wide provider chain
+pub fn global() -> &'static dyn SecretsProvider {
+    &*GLOBAL_SECRETS
+}
+
+/// A secret from the global chain. Provider errors are logged and treated as missing.
+pub fn get(key: &str) -> Option<String> {
+    global().get(key).unwrap_or_else(|e| {
+        tracing::warn!("{:#}", e);
+        None
+    })
+}
+
+/// A secret from the global chain, or an error naming it
+pub fn require(key: &str) -> Result<String> {
+    global().get(key)?.ok_or_else(|| anyhow!("{} must be set", key))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Fixed(&'static str, &'static str);
+
+    impl SecretsProvider for Fixed {
+        fn name(&self) -> &str {
+            "fixed"
+        }
+
+        fn get(&self, key: &str) -> Result<Option<String>> {
+            Ok((key == self.0).then(|| self.1.to_string()))
+        }
+    }
+
+    #[test]
+    fn test_chain_prefers_earlier_providers() {
+        let chain = ChainedSecrets::new()
+            .with_provider(Fixed("OPENAI_API_KEY", "first"))
+            .with_provider(Fixed("OPENAI_API_KEY", "second"))
+            .with_provider(Fixed("GITHUB_TOKEN", "gh"));
+        assert_eq!(chain.get(OPENAI_API_KEY).unwrap().as_deref(), Some("first"));
+        assert_eq!(chain.get(GITHUB_TOKEN).unwrap().as_deref(), Some("gh"));
+        assert_eq!(chain.get(MQTT_PASSWORD).unwrap(), None);
+    }
+
+    #[test]
+    fn test_file_secrets_parse_dotenv_lines() -> Result<()> {
+        let path = env::temp_dir().join(format!("swarm-secrets-{}.env", uuid::Uuid::new_v4()));
+        std::fs::write(&path, "# provider keys\nexport OPENAI_API_KEY=\"sk-test\"\nMQTT_PASSWORD='p=w'\n\nRTK_MONGO_URI = mongodb://localhost\n")?;
+        let file = FileSecrets::open(&path)?;
+        std::fs::remove_file(&path)?;
+        assert_eq!(file.get(OPENAI_API_KEY)?.as_deref(), Some("sk-test"));
+        assert_eq!(file.get(MQTT_PASSWORD)?.as_deref(), Some("p=w"));
+        assert_eq!(file.get(MONGO_URI)?.as_deref(), Some("mongodb://localhost"));
+
+        let missing = env::temp_dir().join("swarm-secrets-missing.age");
+        assert!(FileSecrets::open(missing).is_err());
+        Ok(())
+    }
+}
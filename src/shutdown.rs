@@ -0,0 +1,221 @@
+use std::env;
+use std::future::Future;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+use anyhow::Result;
+use futures::future::BoxFuture;
+use tokio::sync::{Mutex, Notify, watch};
+
+const DEFAULT_DRAIN_TIMEOUT_SECS: u64 = 30;
+
+type Hook = Box<dyn FnOnce() -> BoxFuture<'static, Result<()>> + Send>;
+
+/// Coordinates a graceful shutdown: once triggered, new work is refused, in-flight work
+/// gets a bounded time to finish, then the registered hooks flush state and release resources.
+pub struct ShutdownCoordinator {
+    drain_timeout: Duration,
+    in_flight: AtomicUsize,
+    idle: Notify,
+    /// false -> running, true -> shutting down
+    triggered: watch::Sender<bool>,
+    finished: watch::Sender<bool>,
+    hooks: Mutex<Vec<(String, Hook)>>,
+}
+
+/// Held while a unit of work runs; shutdown waits for all guards to drop
+pub struct InFlightGuard {
+    coordinator: Arc<ShutdownCoordinator>,
+}
+
+impl Drop for InFlightGuard {
+    fn drop(&mut self) {
+        if self.coordinator.in_flight.fetch_sub(1, Ordering::SeqCst) == 1 {
+            self.coordinator.idle.notify_waiters();
+        }
+    }
+}
+
+/// What happened to in-flight work during shutdown
+#[derive(Debug, Clone, PartialEq)]
+pub struct ShutdownReport {
+    /// Work still running when the drain timeout expired
+    pub abandoned: usize,
+    pub failed_hooks: Vec<String>,
+}
+
+impl Default for ShutdownCoordinator {
+    /// Drain timeout from `SHUTDOWN_TIMEOUT_SECS`, defaulting to 30 seconds
+    fn default() -> Self {
+        let secs = env::var("SHUTDOWN_TIMEOUT_SECS")
+            .ok()
+            .and_then(|s| s.parse().ok())
+            .unwrap_or(DEFAULT_DRAIN_TIMEOUT_SECS);
+        Self::new(Duration::from_secs(secs))
+    }
+}
+
+impl ShutdownCoordinator {
+    pub fn new(drain_timeout: Duration) -> Self {
+        Self {
+            drain_timeout,
+            in_flight: AtomicUsize::new(0),
+            idle: Notify::new(),
+            triggered: watch::channel(false).0,
+            finished: watch::channel(false).0,
+            hooks: Mutex::new(Vec::new()),
+        }
+    }
+
+    pub fn is_shutting_down(&self) -> bool {
+        *self.triggered.borrow()
+    }
+
+    pub fn in_flight(&self) -> usize {
+        self.in_flight.load(Ordering::SeqCst)
+    }
+
+    /// Register work; None once shutdown has begun, in which case the work should be refused
+    pub fn begin(self: &Arc<Self>) -> Option<InFlightGuard> {
+        if self.is_shutting_down() {
+            return None;
+        }
+        self.in_flight.fetch_add(1, Ordering::SeqCst);
+        // Re-check so work can't slip in after the drain started counting
+        let guard = InFlightGuard { coordinator: self.clone() };
+        if self.is_shutting_down() {
+            return None;
+        }
+        Some(guard)
+    }
+
+    /// Run `hook` after in-flight work drains, e.g. to flush state or close a browser
+    pub async fn on_shutdown<F, Fut>(&self, name: impl Into<String>, hook: F)
+    where
+        F: FnOnce() -> Fut + Send + 'static,
+        Fut: Future<Output = Result<()>> + Send + 'static,
+    {
+        self.hooks.lock().await.push((name.into(), Box::new(move || Box::pin(hook()))));
+    }
+
+    /// Resolves once shutdown has been triggered
+    pub async fn signalled(&self) {
+        let mut triggered = self.triggered.subscribe();
+        let _ = triggered.wait_for(|t| *t).await;
+    }
+
+    /// Resolves once shutdown has drained and run its hooks
+    pub async fn finished(&self) {
+        let mut finished = self.finished.subscribe();
+        let _ = finished.wait_for(|f| *f).await;
+    }
+
+    /// Stop accepting work, wait up to the drain timeout for in-flight work, then run hooks.
+    /// Only the first call does anything; later calls wait for it to finish.
+    pub async fn shutdown(&self) -> ShutdownReport {
+        if self.triggered.send_replace(true) {
+            self.finished().await;
+            return ShutdownReport { abandoned: self.in_flight(), failed_hooks: Vec::new() };
+        }
+
+        tracing::info!("Shutting down, draining {} in-flight task(s)", self.in_flight());
+        let drained = tokio::time::timeout(self.drain_timeout, async {
+            loop {
+                let idle = self.idle.notified();
+                if self.in_flight() == 0 {
+                    break;
+                }
+                idle.await;
+            }
+        }).await;
+        let abandoned = if drained.is_ok() { 0 } else { self.in_flight() };
+        if abandoned > 0 {
+            tracing::warn!("Drain timed out after {:?} with {} task(s) still running", self.drain_timeout, abandoned);
+        }
+
+        let mut failed_hooks = Vec::new();
+        let hooks = std::mem::take(&mut *self.hooks.lock().await);
+        for (name, hook) in hooks {
+            if let Err(e) = hook().await {
+                tracing::error!("Shutdown hook '{}' failed: {}", name, e);
+                failed_hooks.push(name);
+            }
+        }
+
+        self.finished.send_replace(true);
+        tracing::info!("Shutdown complete");
+        ShutdownReport { abandoned, failed_hooks }
+    }
+
+    /// Shut down on ctrl-c or SIGTERM
+    pub fn listen_for_signals(self: &Arc<Self>) {
+        let coordinator = self.clone();
+        tokio::spawn(async move {
+            wait_for_signal().await;
+            tracing::info!("Received shutdown signal");
+            coordinator.shutdown().await;
+        });
+    }
+}
+
+#[cfg(unix)]
+async fn wait_for_signal() {
+    use tokio::signal::unix::{signal, SignalKind};
+    match signal(SignalKind::terminate()) {
+        Ok(mut terminate) => {
+            tokio::select! {
+                _ = tokio::signal::ctrl_c() => {}
+                _ = terminate.recv() => {}
+            }
+        }
+        Err(e) => {
+            tracing::warn!("Failed to listen for SIGTERM: {}", e);
+            let _ = tokio::signal::ctrl_c().await;
+        }
+    }
+}
+
+#[cfg(not(unix))]
+async fn wait_for_signal() {
+    let _ = tokio::signal::ctrl_c().await;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::anyhow;
+    use std::sync::atomic::AtomicBool;
+
+    #[tokio::test]
+    async fn test_shutdown_waits_for_in_flight_work() {
+        let coordinator = Arc::new(ShutdownCoordinator::new(Duration::from_secs(5)));
+        let flushed = Arc::new(AtomicBool::new(false));
+        let flag = flushed.clone();
+        coordinator.on_shutdown("flush", move || async move {
+            flag.store(true, Ordering::SeqCst);
+            Ok(())
+        }).await;
+        coordinator.on_shutdown("broken", || async { Err(anyhow!("disk full")) }).await;
+
+        let guard = coordinator.begin().unwrap();
+        let worker = tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            drop(guard);
+        });
+
+        let report = coordinator.shutdown().await;
+        worker.await.unwrap();
+        assert_eq!(report, ShutdownReport { abandoned: 0, failed_hooks: vec!["broken".to_string()] });
+        assert!(flushed.load(Ordering::SeqCst));
+        assert!(coordinator.begin().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_drain_is_bounded() {
+        let coordinator = Arc::new(ShutdownCoordinator::new(Duration::from_millis(20)));
+        let _stuck = coordinator.begin().unwrap();
+        let report = coordinator.shutdown().await;
+        assert_eq!(report.abandoned, 1);
+        coordinator.finished().await;
+    }
+}
@@ -0,0 +1,187 @@
+//! Voice in and out, for hands-free use. Speech is recorded from the microphone by a
+//! recorder command, transcribed by whisper.cpp, and replies are read out by a TTS command.
+//! All three are external programs, configured by environment:
+//!
+//! - `WHISPER_MODEL` (required): the ggml model file; `WHISPER_BIN` defaults to `whisper-cli`
+//! - `SPEECH_RECORD_COMMAND`: records one utterance to `{output}`, a 16 kHz mono WAV file.
+//!   Defaults to sox's `rec`, stopping after 1.5s of silence.
+//! - `TTS_COMMAND`: speaks the text it reads on stdin. Defaults to `say` on macOS and
+//!   `espeak-ng --stdin` elsewhere. Pipelines, e.g. piper into aplay, need wrapping in a script.
+
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use anyhow::{Result, anyhow};
+use tokio::io::AsyncWriteExt;
+use tokio::process::Command;
+use uuid::Uuid;
+use crate::error::SwarmError;
+
+/// Placeholder for the recording's path in `SPEECH_RECORD_COMMAND`
+const OUTPUT_PLACEHOLDER: &str = "{output}";
+
+const DEFAULT_RECORD_COMMAND: &str = "rec -q -c 1 -r 16000 -b 16 {output} silence 1 0.1 1% 1 1.5 1%";
+
+#[cfg(target_os = "macos")]
+const DEFAULT_TTS_COMMAND: &str = "say";
+#[cfg(not(target_os = "macos"))]
+const DEFAULT_TTS_COMMAND: &str = "espeak-ng --stdin";
+
+/// Said on its own, ends a voice chat
+const STOP_PHRASES: &[&str] = &["stop listening", "goodbye", "quit", "exit"];
+
+#[derive(Debug, Clone)]
+pub struct SpeechConfig {
+    pub whisper_bin: String,
+    pub whisper_model: PathBuf,
+    pub record_command: String,
+    pub tts_command: String,
+}
+
+impl SpeechConfig {
+    pub fn from_env() -> Result<Self> {
+        let whisper_model = std::env::var("WHISPER_MODEL")
+            .map_err(|_| SwarmError::Validation("WHISPER_MODEL must name a whisper.cpp model file".to_string()))?;
+        let var = |name: &str, default: &str| std::env::var(name).unwrap_or_else(|_| default.to_string());
+        Ok(Self {
+            whisper_bin: var("WHISPER_BIN", "whisper-cli"),
+            whisper_model: PathBuf::from(whisper_model),
+            record_command: var("SPEECH_RECORD_COMMAND", DEFAULT_RECORD_COMMAND),
+            tts_command: var("TTS_COMMAND", DEFAULT_TTS_COMMAND),
+        })
+    }
+}
+
+/// `template` split into a program and its arguments, with `{output}` replaced by `output`
+fn command_line(template: &str, output: Option<&Path>) -> Result<(String, Vec<String>)> {
+    let mut words = template.split_whitespace().map(|word| match output {
+        Some(path) => word.replace(OUTPUT_PLACEHOLDER, &path.to_string_lossy()),
+        None => word.to_string(),
+    });
+    let program = words.next().ok_or_else(|| SwarmError::Validation(format!("Empty command: '{}'", template)))?;
+    Ok((program, words.collect()))
+}
+
+/// The text in whisper.cpp's output, without the markers it prints for silence and noise,
+/// such as `[BLANK_AUDIO]` and `(wind blowing)`
+pub fn clean_transcript(output: &str) -> String {
+    output.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .filter(|line| !(line.starts_with('[') && line.ends_with(']')) && !(line.starts_with('(') && line.ends_with(')')))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Whether `transcript` asks to end the conversation
+pub fn is_stop_phrase(transcript: &str) -> bool {
+    let words: String = transcript.chars()
+        .filter(|c| c.is_alphanumeric() || c.is_whitespace())
+        .collect::<String>()
+        .to_lowercase();
+    let phrase = words.split_whitespace().collect::<Vec<_>>().join(" ");
+    STOP_PHRASES.contains(&phrase.as_str())
+}
+
+async fn run(program: &str, args: &[String]) -> Result<String> {
+    let output = Command::new(program).args(args).stdin(Stdio::null()).output().await
+        .map_err(|e| SwarmError::Tool(format!("Failed to run {}: {}", program, e)))?;
+    if !output.status.success() {
+        return Err(SwarmError::Tool(format!("{} failed: {}", program, String::from_utf8_lossy(&output.stderr).trim())).into());
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).to_string())
+}
+
+pub struct Speech {
+    config: SpeechConfig,
+}
+
+impl Speech {
+    pub fn new(config: SpeechConfig) -> Self {
+        Self { config }
+    }
+
+    pub fn from_env() -> Result<Self> {
+        Ok(Self::new(SpeechConfig::from_env()?))
+    }
+
+    /// Record one utterance to `path`
+    pub async fn record(&self, path: &Path) -> Result<()> {
+        let (program, args) = command_line(&self.config.record_command, Some(path))?;
+        run(&program, &args).await?;
+        Ok(())
+    }
+
+    /// The text spoken in a WAV file
+    pub async fn transcribe(&self, audio: &Path) -> Result<String> {
+        let args = [
+            "-m".to_string(), self.config.whisper_model.to_string_lossy().to_string(),
+            "-f".to_string(), audio.to_string_lossy().to_string(),
+            // Only the text: no timestamps or progress
+            "-nt".to_string(), "-np".to_string(),
+        ];
+        Ok(clean_transcript(&run(&self.config.whisper_bin, &args).await?))
+    }
+
+    /// Record and transcribe one utterance; empty if nothing was said
+    pub async fn listen(&self) -> Result<String> {
+        let path = std::env::temp_dir().join(format!("swarm-voice-{}.wav", Uuid::new_v4().simple()));
+        let transcript = match self.record(&path).await {
+            Ok(()) => self.transcribe(&path).await,
+            Err(e) => Err(e),
+        };
+        let _ = tokio::fs::remove_file(&path).await;
+        transcript
+    }
+
+    /// Read `text` out, returning once it has been spoken
+    pub async fn speak(&self, text: &str) -> Result<()> {
+        let (program, args) = command_line(&self.config.tts_command, None)?;
+        let mut child = Command::new(&program).args(&args).stdin(Stdio::piped()).stdout(Stdio::null()).spawn()
+            .map_err(|e| SwarmError::Tool(format!("Failed to run {}: {}", program, e)))?;
+        let mut stdin = child.stdin.take().ok_or_else(|| anyhow!("{} has no stdin", program))?;
+        stdin.write_all(text.as_bytes()).await?;
+        // Closing stdin tells it the text is complete
+        drop(stdin);
+        let status = child.wait().await?;
+        if !status.success() {
+            return Err(SwarmError::Tool(format!("{} exited with {}", program, status)).into());
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_commands_and_transcripts() {
+        let (program, args) = command_line("rec -q {output} trim 0 5", Some(Path::new("/tmp/a.wav"))).unwrap();
+        assert_eq!((program.as_str(), args), ("rec", vec!["-q".to_string(), "/tmp/a.wav".to_string(), "trim".to_string(), "0".to_string(), "5".to_string()]));
+        assert!(command_line("  ", None).is_err());
+
+        assert_eq!(clean_transcript(" Transfer me to the\n haiku agent.\n[BLANK_AUDIO]\n (keyboard clicking)\n"), "Transfer me to the haiku agent.");
+        assert_eq!(clean_transcript("[BLANK_AUDIO]\n"), "");
+
+        assert!(is_stop_phrase(" Goodbye! "));
+        assert!(is_stop_phrase("Stop listening."));
+        assert!(!is_stop_phrase("Say goodbye to the git agent"));
+    }
+
+    #[tokio::test]
+    #[cfg(unix)]
+    async fn test_speak_pipes_text_to_the_tts_command() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let spoken = dir.path().join("spoken.txt");
+        let speech = Speech::new(SpeechConfig {
+            whisper_bin: "whisper-cli".to_string(),
+            whisper_model: PathBuf::from("model.bin"),
+            record_command: DEFAULT_RECORD_COMMAND.to_string(),
+            tts_command: format!("tee {}", spoken.display()),
+        });
+        speech.speak("Hello from the greeter").await?;
+        assert_eq!(std::fs::read_to_string(&spoken)?, "Hello from the greeter");
+        assert!(Speech::new(SpeechConfig { tts_command: "false".to_string(), ..speech.config.clone() }).speak("x").await.is_err());
+        Ok(())
+    }
+}
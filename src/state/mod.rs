@@ -4,10 +4,11 @@ use chrono::{DateTime, Utc};
 use async_trait::async_trait;
 use mongodb::{
     bson::{doc, to_bson},
-    Client, Collection,
+    Collection,
     options::IndexOptions,
     IndexModel,
 };
+use crate::db::Database;
 use crate::types::Message;
 use anyhow::{Result, anyhow};
 use serde_json::Value;
@@ -68,9 +69,7 @@ pub struct MongoStateManager {
 }
 
 impl MongoStateManager {
-    pub async fn new(client: &Client) -> Result<Self> {
-        let db = client.database("swarmonomicon");
-
+    pub async fn new(db: &Database) -> Result<Self> {
         // Get collections
         let states = db.collection("agent_states");
         let transitions = db.collection("state_transitions");
@@ -181,6 +180,7 @@ mod tests {
     use crate::state::persistence::MongoPersistence;
     use crate::state::validation::{StateValidationConfig, StateValidatorImpl};
     use crate::state::recovery::{RecoveryConfig, StateRecoveryManager};
+    use crate::testsupport::MongoFixture;
 
     struct TestStateManager {
         persistence: MongoPersistence,
@@ -237,13 +237,7 @@ mod tests {
     }
 
     async fn create_test_manager() -> Result<TestStateManager> {
-        let client = Client::with_uri_str("mongodb://localhost:27017").await?;
-        let db = client.database("swarmonomicon_test");
-
-        // Clear test collections
-        db.collection::<PersistedState>("agent_states").drop(None).await?;
-        db.collection::<StateTransition>("state_transitions").drop(None).await?;
-        db.collection::<PersistedState>("state_checkpoints").drop(None).await?;
+        let mongo = MongoFixture::start().await?;
 
         // Create validation config
         let mut validation_config = StateValidationConfig::new();
@@ -254,9 +248,9 @@ mod tests {
         validation_config.add_transition("processing", "completed");
 
         Ok(TestStateManager {
-            persistence: MongoPersistence::new(&client).await?,
+            persistence: MongoPersistence::new(&mongo.db).await?,
             validator: StateValidatorImpl::new(validation_config),
-            recovery: StateRecoveryManager::new(&client, RecoveryConfig::default()).await?,
+            recovery: StateRecoveryManager::new(&mongo.db, RecoveryConfig::default()).await?,
         })
     }
 
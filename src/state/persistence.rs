@@ -1,7 +1,7 @@
 use std::collections::HashMap;
 use mongodb::{
     bson::{doc, to_bson},
-    Client, Collection,
+    Collection,
     options::{IndexOptions, FindOneOptions, FindOptions},
     IndexModel,
 };
@@ -9,6 +9,7 @@ use futures_util::StreamExt;
 use chrono::{DateTime, Utc};
 use anyhow::{Result, anyhow};
 use serde_json::Value;
+use crate::db::Database;
 use crate::types::Message;
 use super::{PersistedState, StateTransition, StatePersistence};
 use async_trait::async_trait;
@@ -19,9 +20,7 @@ pub struct MongoPersistence {
 }
 
 impl MongoPersistence {
-    pub async fn new(client: &Client) -> Result<Self> {
-        let db = client.database("swarmonomicon");
-        
+    pub async fn new(db: &Database) -> Result<Self> {
         // Get collections
         let states = db.collection("agent_states");
         let transitions = db.collection("state_transitions");
@@ -148,18 +147,12 @@ impl StatePersistence for MongoPersistence {
 mod tests {
     use super::*;
     use chrono::Duration;
+    use crate::testsupport::MongoFixture;
 
     #[tokio::test]
     async fn test_mongo_persistence() -> Result<()> {
-        // Connect to test database
-        let client = Client::with_uri_str("mongodb://localhost:27017").await?;
-        let db = client.database("swarmonomicon_test");
-        
-        // Clear test collections
-        db.collection::<PersistedState>("agent_states").drop(None).await?;
-        db.collection::<StateTransition>("state_transitions").drop(None).await?;
-
-        let persistence = MongoPersistence::new(&client).await?;
+        let mongo = MongoFixture::start().await?;
+        let persistence = MongoPersistence::new(&mongo.db).await?;
 
         // Test saving and loading state
         let test_state = PersistedState {
@@ -221,10 +214,6 @@ mod tests {
         ).await?;
         assert_eq!(deleted_transitions, 1); // Should delete our test transition
 
-        // Clean up
-        db.collection::<PersistedState>("agent_states").drop(None).await?;
-        db.collection::<StateTransition>("state_transitions").drop(None).await?;
-
         Ok(())
     }
 } 
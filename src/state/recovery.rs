@@ -1,7 +1,7 @@
 use std::collections::HashMap;
 use mongodb::{
     bson::{doc, to_bson},
-    Client, Collection,
+    Collection,
     options::{IndexOptions, FindOneOptions, FindOptions},
     IndexModel,
 };
@@ -9,6 +9,7 @@ use futures_util::StreamExt;
 use chrono::{DateTime, Utc, Duration};
 use anyhow::{Result, anyhow};
 use serde_json::Value;
+use crate::db::Database;
 use super::{PersistedState, StateTransition, StateRecovery};
 use async_trait::async_trait;
 
@@ -36,9 +37,7 @@ pub struct StateRecoveryManager {
 }
 
 impl StateRecoveryManager {
-    pub async fn new(client: &Client, config: RecoveryConfig) -> Result<Self> {
-        let db = client.database("swarmonomicon");
-        
+    pub async fn new(db: &Database, config: RecoveryConfig) -> Result<Self> {
         // Get collections
         let checkpoints = db.collection("state_checkpoints");
         let transitions = db.collection("state_transitions");
@@ -232,25 +231,18 @@ impl StateRecovery for StateRecoveryManager {
 mod tests {
     use super::*;
     use std::collections::HashMap;
+    use crate::testsupport::MongoFixture;
 
     #[tokio::test]
     async fn test_state_recovery() -> Result<()> {
-        // Connect to test database
-        let client = Client::with_uri_str("mongodb://localhost:27017").await?;
-        let db = client.database("swarmonomicon_test");
-        
-        // Clear test collections
-        db.collection::<PersistedState>("state_checkpoints").drop(None).await?;
-        db.collection::<StateTransition>("state_transitions").drop(None).await?;
-        db.collection::<PersistedState>("agent_states").drop(None).await?;
-
+        let mongo = MongoFixture::start().await?;
         let config = RecoveryConfig {
             max_checkpoint_age: Duration::hours(1),
             max_transitions_replay: 10,
             cleanup_older_than: Duration::hours(24),
         };
 
-        let manager = StateRecoveryManager::new(&client, config).await?;
+        let manager = StateRecoveryManager::new(&mongo.db, config).await?;
 
         // Create test state
         let test_state = PersistedState {
@@ -300,11 +292,6 @@ mod tests {
         let deleted = manager.cleanup_old_checkpoints("test_agent").await?;
         assert_eq!(deleted, 0); // No old checkpoints yet
 
-        // Clean up
-        db.collection::<PersistedState>("state_checkpoints").drop(None).await?;
-        db.collection::<StateTransition>("state_transitions").drop(None).await?;
-        db.collection::<PersistedState>("agent_states").drop(None).await?;
-
         Ok(())
     }
 } 
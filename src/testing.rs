@@ -0,0 +1,353 @@
+//! Deterministic test setup: an in-process stand-in for the MCP todo server and a harness
+//! wiring it to a `TodoTool` driven by `MockAiProvider`, so todo tests need neither a
+//! model nor MongoDB, and `EchoAgent` for exercising routing. The benchmarks use them too.
+
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use anyhow::Result;
+use axum::{Json, Router, extract::State, routing::post};
+use chrono::Utc;
+use serde::Deserialize;
+use serde_json::{Value, json};
+use tokio::sync::RwLock;
+use tokio::task::JoinHandle;
+use uuid::Uuid;
+use crate::ai::MockAiProvider;
+use crate::tools::ToolExecutor;
+use crate::tools::todo::TodoTool;
+use crate::types::{Agent, AgentConfig, Message, State as AgentState, TaskPriority, TaskStatus, TodoTask, Tool};
+
+type Todos = Arc<RwLock<Vec<TodoTask>>>;
+
+#[derive(Deserialize)]
+struct AddTodo {
+    description: String,
+    project: String,
+    priority: String,
+    target_agent: String,
+    #[serde(default)]
+    metadata: HashMap<String, Value>,
+}
+
+#[derive(Deserialize)]
+struct QueryTodos {
+    query_or_filter: Option<String>,
+    limit: Option<usize>,
+}
+
+#[derive(Deserialize)]
+struct TodoId {
+    todo_id: String,
+}
+
+#[derive(Deserialize)]
+struct UpdateTodo {
+    todo_id: String,
+    updates: HashMap<String, Value>,
+}
+
+fn not_found(todo_id: &str) -> Json<Value> {
+    Json(json!({ "success": false, "message": format!("Todo {} not found", todo_id) }))
+}
+
+async fn add_todo(State(todos): State<Todos>, Json(request): Json<AddTodo>) -> Json<Value> {
+    let priority = match request.priority.as_str() {
+        "Low" => TaskPriority::Low,
+        "High" => TaskPriority::High,
+        "Critical" => TaskPriority::Critical,
+        _ => TaskPriority::Medium,
+    };
+    let now = Utc::now().timestamp();
+    let task = TodoTask {
+        id: Uuid::new_v4().to_string(),
+        description: request.description,
+        enhanced_description: request.metadata.get("enhanced_description").and_then(Value::as_str).map(str::to_string),
+        priority,
+        project: Some(request.project),
+        source_agent: None,
+        target_agent: request.target_agent,
+        status: TaskStatus::Pending,
+        created_at: now,
+        completed_at: None,
+        due_date: None,
+        duration_minutes: None,
+        notes: None,
+        ticket: None,
+        last_modified: Some(now),
+        failure_reason: None,
+        embedding: request.metadata.get("embedding").and_then(|e| serde_json::from_value(e.clone()).ok()),
+        claim: None,
+        tags: Vec::new(),
+        failures: Vec::new(),
+        confidence: None,
+        reviewed_at: None,
+        tenant_id: None,
+        idempotency_key: None,
+        content_hash: None,
+        history: Vec::new(),
+    };
+    let todo_id = task.id.clone();
+    todos.write().await.push(task);
+    Json(json!({ "success": true, "message": "Todo created", "data": { "todo_id": todo_id } }))
+}
+
+/// Whether a stored todo matches one filter field: an equal value, or `{"$exists": bool}`
+fn matches_field(todo: &Value, field: &str, expected: &Value) -> bool {
+    // Metadata is kept on the todo itself, as in `add_todo`
+    let value = todo.get(field.strip_prefix("metadata.").unwrap_or(field)).filter(|v| !v.is_null());
+    match expected.get("$exists").and_then(Value::as_bool) {
+        Some(exists) => value.is_some() == exists,
+        None => value == Some(expected),
+    }
+}
+
+/// Filters are JSON objects matched field by field against the stored todo
+async fn query_todos(State(todos): State<Todos>, Json(request): Json<QueryTodos>) -> Json<Value> {
+    let filter: HashMap<String, Value> = match request.query_or_filter.as_deref().map(serde_json::from_str).transpose() {
+        Ok(filter) => filter.unwrap_or_default(),
+        Err(e) => return Json(json!({ "success": false, "message": format!("Invalid filter: {}", e) })),
+    };
+    let items: Vec<Value> = todos.read().await.iter()
+        .filter_map(|todo| serde_json::to_value(todo).ok())
+        .filter(|todo| filter.iter().all(|(field, expected)| matches_field(todo, field, expected)))
+        .take(request.limit.unwrap_or(usize::MAX))
+        .collect();
+    Json(json!({ "success": true, "data": { "items": items } }))
+}
+
+async fn get_todo(State(todos): State<Todos>, Json(request): Json<TodoId>) -> Json<Value> {
+    match todos.read().await.iter().find(|t| t.id == request.todo_id) {
+        Some(todo) => Json(json!({ "success": true, "data": todo })),
+        None => not_found(&request.todo_id),
+    }
+}
+
+async fn update_todo(State(todos): State<Todos>, Json(request): Json<UpdateTodo>) -> Json<Value> {
+    let mut todos = todos.write().await;
+    let Some(todo) = todos.iter_mut().find(|t| t.id == request.todo_id) else {
+        return not_found(&request.todo_id);
+    };
+    let mut value = serde_json::to_value(&*todo).unwrap_or_default();
+    for (field, update) in request.updates {
+        // Metadata such as `metadata.enhanced_description` is kept on the todo itself, as in `add_todo`
+        let field = field.strip_prefix("metadata.").unwrap_or(&field);
+        value[field] = update;
+    }
+    match serde_json::from_value(value) {
+        Ok(updated) => {
+            *todo = updated;
+            todo.last_modified = Some(Utc::now().timestamp());
+            Json(json!({ "success": true, "message": "Todo updated" }))
+        }
+        Err(e) => Json(json!({ "success": false, "message": format!("Invalid update: {}", e) })),
+    }
+}
+
+async fn mark_complete(State(todos): State<Todos>, Json(request): Json<TodoId>) -> Json<Value> {
+    let mut todos = todos.write().await;
+    let Some(todo) = todos.iter_mut().find(|t| t.id == request.todo_id) else {
+        return not_found(&request.todo_id);
+    };
+    let now = Utc::now().timestamp();
+    todo.status = TaskStatus::Completed;
+    todo.completed_at = Some(now);
+    todo.last_modified = Some(now);
+    Json(json!({ "success": true, "message": "Todo completed" }))
+}
+
+/// The MCP todo server's tool endpoints, backed by a Vec. Stops when dropped.
+pub struct InMemoryTodoServer {
+    addr: SocketAddr,
+    todos: Todos,
+    handle: JoinHandle<()>,
+}
+
+impl InMemoryTodoServer {
+    /// Serve on an ephemeral localhost port
+    pub async fn start() -> Result<Self> {
+        let todos = Todos::default();
+        let app = Router::new()
+            .route("/tools/add_todo_tool", post(add_todo))
+            .route("/tools/query_todos_tool", post(query_todos))
+            .route("/tools/get_todo_tool", post(get_todo))
+            .route("/tools/update_todo_tool", post(update_todo))
+            .route("/tools/mark_todo_complete_tool", post(mark_complete))
+            .with_state(todos.clone());
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+        let addr = listener.local_addr()?;
+        let handle = tokio::spawn(async move {
+            if let Err(e) = axum::serve(listener, app).await {
+                tracing::error!("In-memory todo server failed: {}", e);
+            }
+        });
+        Ok(Self { addr, todos, handle })
+    }
+
+    pub fn url(&self) -> String {
+        format!("http://{}", self.addr)
+    }
+
+    /// Everything stored so far, oldest first
+    pub async fn todos(&self) -> Vec<TodoTask> {
+        self.todos.read().await.clone()
+    }
+
+    /// Seed a todo directly
+    pub async fn insert(&self, todo: TodoTask) {
+        self.todos.write().await.push(todo);
+    }
+}
+
+impl Drop for InMemoryTodoServer {
+    fn drop(&mut self) {
+        self.handle.abort();
+    }
+}
+
+/// A `TodoTool` wired to an `InMemoryTodoServer` and a scripted `MockAiProvider`
+pub struct TodoHarness {
+    pub server: InMemoryTodoServer,
+    /// Shares its script with the tool's client, for inspecting requests
+    pub ai: MockAiProvider,
+    pub tool: TodoTool,
+}
+
+impl TodoHarness {
+    pub async fn start(ai: MockAiProvider) -> Result<Self> {
+        let server = InMemoryTodoServer::start().await?;
+        let tool = TodoTool::with_server_url(server.url())?.with_ai_client(ai.clone());
+        Ok(Self { server, ai, tool })
+    }
+
+    /// Run a TodoTool command, e.g. `run("add", &[("description", "Fix login")])`
+    pub async fn run(&self, command: &str, params: &[(&str, &str)]) -> Result<String> {
+        let mut params: HashMap<String, String> = params.iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect();
+        params.insert("command".to_string(), command.to_string());
+        self.tool.execute(params).await
+    }
+}
+
+/// Replies with the message it was sent, without a model or tools
+pub struct EchoAgent {
+    config: AgentConfig,
+}
+
+impl EchoAgent {
+    pub fn new(name: &str) -> Self {
+        Self {
+            config: AgentConfig {
+                name: name.to_string(),
+                public_description: "Echoes messages back".to_string(),
+                instructions: String::new(),
+                tools: Vec::new(),
+                downstream_agents: Vec::new(),
+                personality: None,
+                state_machine: None,
+                script: None,
+                max_concurrency: None,
+                middleware: None,
+                remote: None,
+            },
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl Agent for EchoAgent {
+    async fn process_message(&self, message: Message) -> Result<Message> {
+        Ok(Message::new(message.content))
+    }
+
+    async fn transfer_to(&self, _target_agent: String, message: Message) -> Result<Message> {
+        Ok(message)
+    }
+
+    async fn call_tool(&self, tool: &Tool, _params: HashMap<String, String>) -> Result<String> {
+        Err(anyhow::anyhow!("{} has no tool {}", self.config.name, tool.name))
+    }
+
+    async fn get_current_state(&self) -> Result<Option<AgentState>> {
+        Ok(None)
+    }
+
+    async fn get_config(&self) -> Result<AgentConfig> {
+        Ok(self.config.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_todo_lifecycle_without_services() -> Result<()> {
+        let ai = MockAiProvider::new()
+            .with_rule("Enhance this task", "1. Reproduce the login failure\n2. Fix session handling")
+            .with_rule("priority classifier", "high")
+            .with_rule("project classifier", "Swarmonomicon");
+        let harness = TodoHarness::start(ai).await?;
+
+        harness.run("add", &[("description", "Fix login"), ("target_agent", "git")]).await?;
+        // Enhancement happens in the background once the todo is stored
+        harness.tool.enrichment_settled().await;
+        let todos = harness.server.todos().await;
+        assert_eq!(todos.len(), 1);
+        assert_eq!(todos[0].priority, TaskPriority::High);
+        assert_eq!(todos[0].target_agent, "git");
+        assert!(todos[0].enhanced_description.as_deref().unwrap().contains("session handling"));
+        assert!(todos[0].embedding.is_some());
+
+        assert!(harness.run("list", &[]).await?.contains("Fix login (Pending)"));
+        harness.run("complete", &[("description", "Fix login")]).await?;
+        assert_eq!(harness.server.todos().await[0].status, TaskStatus::Completed);
+        assert!(harness.run("complete", &[("description", "Nothing")]).await.is_err());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_older_todos_are_embedded_once() -> Result<()> {
+        let harness = TodoHarness::start(MockAiProvider::new()).await?;
+        harness.server.insert(serde_json::from_value(json!({
+            "id": "t1", "description": "Fix login page", "priority": "Low", "target_agent": "git",
+            "status": "pending", "created_at": 0,
+        }))?).await;
+
+        assert_eq!(harness.tool.backfill_embeddings().await?, 1);
+        assert!(harness.server.todos().await[0].embedding.is_some());
+        assert_eq!(harness.tool.backfill_embeddings().await?, 0);
+
+        let similar = harness.run("similar", &[("description", "login page broken"), ("threshold", "0.1")]).await?;
+        assert!(similar.contains("Fix login page"), "{}", similar);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_update_and_reassign_keep_history() -> Result<()> {
+        let mut harness = TodoHarness::start(MockAiProvider::new()).await?;
+        harness.tool = harness.tool.with_known_agents(["git", "haiku"]);
+        // Seeded rather than added, so background enhancement can't race the edits
+        harness.server.insert(serde_json::from_value(json!({
+            "id": "t1", "description": "Fix login", "priority": "Low", "target_agent": "git",
+            "status": "pending", "created_at": 0,
+        }))?).await;
+        let id = "t1";
+
+        harness.run("update", &[("todo_id", id), ("description", "Fix login on Safari"), ("priority", "High")]).await?;
+        harness.run("reassign", &[("todo_id", id), ("target_agent", "haiku")]).await?;
+        assert!(harness.run("reassign", &[("todo_id", id), ("target_agent", "nobody")]).await.is_err());
+
+        let todo = &harness.server.todos().await[0];
+        assert_eq!(todo.description, "Fix login on Safari");
+        assert_eq!(todo.priority, TaskPriority::High);
+        assert_eq!(todo.target_agent, "haiku");
+        let fields: Vec<Vec<&str>> = todo.history.iter()
+            .map(|change| change.fields.iter().map(|f| f.field.as_str()).collect())
+            .collect();
+        assert_eq!(fields, [vec!["description", "priority"], vec!["target_agent"]]);
+        assert!(todo.history.iter().all(|change| change.actor == "todo_tool"));
+        Ok(())
+    }
+}
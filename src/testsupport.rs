@@ -0,0 +1,135 @@
+//! Services for integration tests, started in containers with testcontainers so tests don't
+//! assume MongoDB or a broker on localhost. Each test binary starts one MongoDB and gives
+//! every `MongoFixture` a database of its own, so tests can run in parallel. Set
+//! `SWARM_TEST_MONGO_URI` or `SWARM_TEST_MQTT_URL` (`host:port`) to use services you run
+//! yourself instead, e.g. where Docker isn't available.
+
+use std::env;
+use anyhow::{Context, Result};
+use chrono::Utc;
+use mongodb::Client;
+use mongodb::options::{ClientOptions, ServerAddress};
+use testcontainers_modules::mongo::Mongo;
+use testcontainers_modules::mosquitto::Mosquitto;
+use testcontainers_modules::testcontainers::ContainerAsync;
+use testcontainers_modules::testcontainers::runners::AsyncRunner;
+use tokio::sync::OnceCell;
+use uuid::Uuid;
+use crate::config::mongo::MongoConfig;
+use crate::db::Database;
+use crate::types::{TaskPriority, TaskStatus, TodoList, TodoTask};
+
+const MONGO_PORT: u16 = 27017;
+const MQTT_PORT: u16 = 1883;
+
+/// A service's address, and the container serving it unless one was configured. Statics
+/// aren't dropped, so the container lives as long as the test binary.
+struct Service<I: testcontainers_modules::testcontainers::Image> {
+    address: String,
+    _container: Option<ContainerAsync<I>>,
+}
+
+static MONGO: OnceCell<Service<Mongo>> = OnceCell::const_new();
+static MQTT: OnceCell<Service<Mosquitto>> = OnceCell::const_new();
+
+async fn mongo_uri() -> Result<&'static str> {
+    let service = MONGO.get_or_try_init(|| async {
+        if let Ok(uri) = env::var("SWARM_TEST_MONGO_URI") {
+            return Ok::<_, anyhow::Error>(Service { address: uri, _container: None });
+        }
+        let container = Mongo::default().start().await
+            .context("Could not start MongoDB; is Docker running? Set SWARM_TEST_MONGO_URI to use your own")?;
+        let address = format!(
+            "mongodb://{}:{}",
+            container.get_host().await?,
+            container.get_host_port_ipv4(MONGO_PORT).await?,
+        );
+        Ok(Service { address, _container: Some(container) })
+    }).await?;
+    Ok(&service.address)
+}
+
+/// Host and port of an MQTT broker
+pub async fn mqtt_broker() -> Result<(String, u16)> {
+    let service = MQTT.get_or_try_init(|| async {
+        if let Ok(address) = env::var("SWARM_TEST_MQTT_URL") {
+            return Ok::<_, anyhow::Error>(Service { address, _container: None });
+        }
+        let container = Mosquitto::default().start().await
+            .context("Could not start Mosquitto; is Docker running? Set SWARM_TEST_MQTT_URL to use your own")?;
+        let address = format!("{}:{}", container.get_host().await?, container.get_host_port_ipv4(MQTT_PORT).await?);
+        Ok(Service { address, _container: Some(container) })
+    }).await?;
+    let (host, port) = service.address.rsplit_once(':')
+        .with_context(|| format!("SWARM_TEST_MQTT_URL should be host:port, not {}", service.address))?;
+    Ok((host.to_string(), port.parse().with_context(|| format!("Invalid MQTT port {}", port))?))
+}
+
+/// A fresh, empty database for one test
+pub struct MongoFixture {
+    pub db: Database,
+}
+
+impl MongoFixture {
+    pub async fn start() -> Result<Self> {
+        let config = MongoConfig {
+            database: format!("swarm_test_{}", Uuid::new_v4().simple()),
+            ..MongoConfig::new(mongo_uri().await?)
+        };
+        Ok(Self { db: Database::connect(&config).await? })
+    }
+
+    pub async fn todo_list(&self) -> TodoList {
+        TodoList::from_database(&self.db).await
+    }
+
+    /// A task list already holding `tasks`
+    pub async fn seeded(&self, tasks: impl IntoIterator<Item = TodoTask>) -> Result<TodoList> {
+        let todo_list = self.todo_list().await;
+        for task in tasks {
+            todo_list.add_task(task).await?;
+        }
+        Ok(todo_list)
+    }
+}
+
+/// A task list whose client never connects, for tests that need one but don't use it
+pub fn unconnected_todo_list() -> TodoList {
+    let address = ServerAddress::Tcp { host: "localhost".to_string(), port: Some(MONGO_PORT) };
+    let client = Client::with_options(ClientOptions::builder().hosts(vec![address]).build())
+        .expect("Options without a URI to resolve are valid");
+    TodoList::unprepared(&Database::with_client(client, "swarm_test_unconnected"))
+}
+
+/// A pending, medium priority task for `target_agent`
+pub fn task(description: &str, target_agent: &str) -> TodoTask {
+    let now = Utc::now().timestamp();
+    TodoTask {
+        id: Uuid::new_v4().to_string(),
+        description: description.to_string(),
+        enhanced_description: None,
+        priority: TaskPriority::Medium,
+        project: None,
+        source_agent: Some("test".to_string()),
+        target_agent: target_agent.to_string(),
+        status: TaskStatus::Pending,
+        created_at: now,
+        completed_at: None,
+        due_date: None,
+        duration_minutes: None,
+        notes: None,
+        ticket: None,
+        last_modified: Some(now),
+        failure_reason: None,
+        embedding: None,
+        claim: None,
+        tags: Vec::new(),
+        failures: Vec::new(),
+        confidence: None,
+        reviewed_at: None,
+        tenant_id: None,
+        idempotency_key: None,
+        content_hash: None,
+        history: Vec::new(),
+    }
+}
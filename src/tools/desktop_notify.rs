@@ -0,0 +1,239 @@
+//! Native desktop notifications for people running the swarm on their workstation, raised
+//! when tasks assigned to them complete or fail. macOS uses `osascript`; elsewhere they go
+//! through notify-rust, which needs the `desktop-notify` feature. `DESKTOP_NOTIFY=true`
+//! announces every task of the `user` agent; `DESKTOP_NOTIFY_CONFIG` names a YAML or JSON
+//! file choosing whose tasks, and which priorities, per project:
+//!
+//! ```yaml
+//! agents: [user, review]
+//! min_priority: Medium
+//! projects:
+//!   swarmonomicon: { min_priority: Low }
+//!   scratch: { muted: true }
+//! ```
+
+use std::collections::HashMap;
+use std::path::Path;
+use async_trait::async_trait;
+use anyhow::{Result, anyhow};
+use futures_util::StreamExt;
+use serde::Deserialize;
+use tokio::task::JoinHandle;
+use crate::error::SwarmError;
+use crate::events;
+use crate::tools::ToolExecutor;
+use crate::types::{TaskEvent, TaskEventKind, TaskPriority};
+
+/// Title of notifications sent through the tool without one
+const DEFAULT_TITLE: &str = "Swarmonomicon";
+
+/// Overrides for one project's tasks
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct ProjectRule {
+    #[serde(default)]
+    pub muted: bool,
+    #[serde(default)]
+    pub min_priority: Option<TaskPriority>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct DesktopNotifyConfig {
+    /// Agents whose tasks are the local user's
+    #[serde(default = "default_agents")]
+    pub agents: Vec<String>,
+    /// Lowest priority announced; every priority when unset
+    #[serde(default)]
+    pub min_priority: Option<TaskPriority>,
+    #[serde(default)]
+    pub projects: HashMap<String, ProjectRule>,
+}
+
+fn default_agents() -> Vec<String> {
+    vec!["user".to_string()]
+}
+
+impl Default for DesktopNotifyConfig {
+    fn default() -> Self {
+        Self { agents: default_agents(), min_priority: None, projects: HashMap::new() }
+    }
+}
+
+impl DesktopNotifyConfig {
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let source = std::fs::read_to_string(path).map_err(|e| anyhow!("Failed to read {}: {}", path.display(), e))?;
+        // YAML is a superset of JSON
+        serde_yaml::from_str(&source).map_err(|e| anyhow!("{}: {}", path.display(), e))
+    }
+
+    /// The `DESKTOP_NOTIFY_CONFIG` file, else the defaults if `DESKTOP_NOTIFY` is set;
+    /// `None` when desktop notifications are off
+    pub fn from_env() -> Result<Option<Self>> {
+        if let Ok(path) = std::env::var("DESKTOP_NOTIFY_CONFIG") {
+            return Self::from_file(path).map(Some);
+        }
+        let enabled = std::env::var("DESKTOP_NOTIFY").ok().and_then(|value| crate::config::parse_bool(&value));
+        Ok(enabled.unwrap_or(false).then(Self::default))
+    }
+
+    /// Whether `event` finishes one of the local user's tasks that should be announced.
+    /// Tasks of unknown priority always are.
+    pub fn announces(&self, event: &TaskEvent) -> bool {
+        if !matches!(event.kind, TaskEventKind::Completed | TaskEventKind::Failed) {
+            return false;
+        }
+        if !event.target_agent.as_ref().is_some_and(|agent| self.agents.contains(agent)) {
+            return false;
+        }
+        let rule = event.project.as_ref().and_then(|project| self.projects.get(project));
+        if rule.is_some_and(|rule| rule.muted) {
+            return false;
+        }
+        match (rule.and_then(|rule| rule.min_priority.as_ref()).or(self.min_priority.as_ref()), &event.priority) {
+            (Some(min), Some(priority)) => priority >= min,
+            _ => true,
+        }
+    }
+}
+
+/// Title and body announcing a finished task
+pub fn notification_for(event: &TaskEvent) -> (String, String) {
+    let outcome = if event.kind == TaskEventKind::Failed { "Task failed" } else { "Task completed" };
+    let title = match &event.project {
+        Some(project) => format!("{} in {}", outcome, project),
+        None => outcome.to_string(),
+    };
+    let mut body = event.description.clone().unwrap_or_else(|| event.task_id.clone());
+    if let Some(reason) = event.failure_reason.as_ref().filter(|_| event.kind == TaskEventKind::Failed) {
+        body = format!("{}\n{}", body, reason);
+    }
+    (title, body)
+}
+
+/// `text` as an AppleScript string literal
+#[cfg(any(target_os = "macos", test))]
+fn applescript_string(text: &str) -> String {
+    format!("\"{}\"", text.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+#[cfg(target_os = "macos")]
+async fn show(title: &str, body: &str) -> Result<()> {
+    let script = format!("display notification {} with title {}", applescript_string(body), applescript_string(title));
+    let output = tokio::process::Command::new("osascript").arg("-e").arg(script).output().await?;
+    if !output.status.success() {
+        return Err(SwarmError::Tool(format!("osascript failed: {}", String::from_utf8_lossy(&output.stderr).trim())).into());
+    }
+    Ok(())
+}
+
+#[cfg(all(not(target_os = "macos"), feature = "desktop-notify"))]
+async fn show(title: &str, body: &str) -> Result<()> {
+    let (title, body) = (title.to_string(), body.to_string());
+    // Talking to the notification daemon blocks
+    tokio::task::spawn_blocking(move || {
+        notify_rust::Notification::new().appname(DEFAULT_TITLE).summary(&title).body(&body).show().map(|_| ())
+    }).await?.map_err(|e| SwarmError::Tool(format!("Desktop notification failed: {}", e)).into())
+}
+
+#[cfg(all(not(target_os = "macos"), not(feature = "desktop-notify")))]
+async fn show(_title: &str, _body: &str) -> Result<()> {
+    Err(SwarmError::Tool("Desktop notifications need the desktop-notify feature on this platform".to_string()).into())
+}
+
+/// Whether this build can raise notifications on this platform
+fn supported() -> bool {
+    cfg!(any(target_os = "macos", feature = "desktop-notify"))
+}
+
+#[derive(Clone)]
+pub struct DesktopNotifyTool {
+    config: DesktopNotifyConfig,
+}
+
+impl DesktopNotifyTool {
+    pub fn new(config: DesktopNotifyConfig) -> Self {
+        Self { config }
+    }
+
+    /// A tool for the configured user, or `None` if desktop notifications are off. Fails
+    /// when they're on but this build can't raise them.
+    pub fn from_env() -> Result<Option<Self>> {
+        let Some(config) = DesktopNotifyConfig::from_env()? else { return Ok(None) };
+        if !supported() {
+            return Err(SwarmError::Validation("Desktop notifications need the desktop-notify feature on this platform".to_string()).into());
+        }
+        Ok(Some(Self::new(config)))
+    }
+
+    pub async fn notify(&self, title: &str, body: &str) -> Result<()> {
+        show(title, body).await
+    }
+
+    /// Announce the local user's finished tasks as this process sees them
+    pub fn spawn_notifier(&self) -> JoinHandle<()> {
+        let tool = self.clone();
+        tokio::spawn(async move {
+            let mut task_events = Box::pin(events::task_events(events::global()));
+            while let Some(task) = task_events.next().await {
+                if !tool.config.announces(&task) {
+                    continue;
+                }
+                let (title, body) = notification_for(&task);
+                if let Err(e) = tool.notify(&title, &body).await {
+                    tracing::warn!("Failed to show desktop notification for task {}: {}", task.task_id, e);
+                }
+            }
+        })
+    }
+}
+
+#[async_trait]
+impl ToolExecutor for DesktopNotifyTool {
+    async fn execute(&self, params: HashMap<String, String>) -> Result<String> {
+        let text = params.get("text").ok_or_else(|| anyhow!("Missing text parameter"))?;
+        let title = params.get("title").map(|s| s.as_str()).unwrap_or(DEFAULT_TITLE);
+        self.notify(title, text).await?;
+        Ok("Notification shown".to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn finished(kind: TaskEventKind, agent: &str, project: Option<&str>, priority: Option<TaskPriority>) -> TaskEvent {
+        TaskEvent {
+            target_agent: Some(agent.to_string()),
+            project: project.map(str::to_string),
+            priority,
+            description: Some("Review the release notes".to_string()),
+            failure_reason: Some("no reviewer".to_string()),
+            ..TaskEvent::new(kind, "t1")
+        }
+    }
+
+    #[test]
+    fn test_only_the_users_wanted_tasks_are_announced() {
+        let config: DesktopNotifyConfig = serde_yaml::from_str(
+            "min_priority: High\nprojects:\n  web: { min_priority: Low }\n  scratch: { muted: true }\n"
+        ).unwrap();
+        assert_eq!(config.agents, ["user"]);
+
+        assert!(config.announces(&finished(TaskEventKind::Completed, "user", None, Some(TaskPriority::Critical))));
+        assert!(!config.announces(&finished(TaskEventKind::Completed, "user", None, Some(TaskPriority::Medium))));
+        assert!(config.announces(&finished(TaskEventKind::Failed, "user", None, None)));
+        assert!(config.announces(&finished(TaskEventKind::Failed, "user", Some("web"), Some(TaskPriority::Low))));
+        assert!(!config.announces(&finished(TaskEventKind::Failed, "user", Some("scratch"), Some(TaskPriority::Critical))));
+        assert!(!config.announces(&finished(TaskEventKind::Completed, "git", None, Some(TaskPriority::Critical))));
+        assert!(!config.announces(&finished(TaskEventKind::Started, "user", None, Some(TaskPriority::Critical))));
+    }
+
+    #[test]
+    fn test_notification_text() {
+        let (title, body) = notification_for(&finished(TaskEventKind::Failed, "user", Some("web"), None));
+        assert_eq!((title.as_str(), body.as_str()), ("Task failed in web", "Review the release notes\nno reviewer"));
+        let (title, body) = notification_for(&finished(TaskEventKind::Completed, "user", None, None));
+        assert_eq!((title.as_str(), body.as_str()), ("Task completed", "Review the release notes"));
+        assert_eq!(applescript_string(r#"say "hi" \o/"#), r#""say \"hi\" \\o/""#);
+    }
+}
@@ -1,8 +1,9 @@
 use std::collections::HashMap;
 use std::process::Command;
 use async_trait::async_trait;
-use crate::tools::ToolExecutor;
+use crate::tools::{ToolExecutor, ToolOutput};
 use anyhow::{Result, anyhow};
+use serde_json::json;
 
 pub struct GitTool;
 
@@ -77,36 +78,79 @@ impl GitTool {
 
         Ok(())
     }
+
+    /// Undo `commit` with a new commit
+    fn revert_commit(&self, commit: &str) -> Result<()> {
+        let output = Command::new("git")
+            .args(["revert", "--no-edit", commit])
+            .output()
+            .map_err(|e| anyhow!("Failed to revert commit: {}", e))?;
+        if !output.status.success() {
+            return Err(anyhow!("Failed to revert {}: {}", commit, String::from_utf8_lossy(&output.stderr).trim()));
+        }
+        Ok(())
+    }
 }
 
 #[async_trait]
 impl ToolExecutor for GitTool {
     async fn execute(&self, params: HashMap<String, String>) -> Result<String> {
+        Ok(match self.execute_structured(params).await? {
+            ToolOutput::Json(value) => value["message"].as_str().unwrap_or_default().to_string(),
+            output => output.to_string(),
+        })
+    }
+
+    async fn execute_structured(&self, params: HashMap<String, String>) -> Result<ToolOutput> {
         let command = params.get("command").ok_or_else(|| anyhow!("Missing command parameter"))?;
 
         match command.as_str() {
             "diff" => {
                 let diff = self.get_git_diff()?;
-                Ok(diff)
+                Ok(ToolOutput::Text(diff))
             }
             "branch" => {
                 let name = params.get("name").ok_or_else(|| anyhow!("Missing branch name"))?;
                 self.create_branch(name)?;
-                Ok(format!("Created and switched to branch: {}", name))
+                Ok(ToolOutput::Json(json!({
+                    "command": "branch",
+                    "branch": name,
+                    "message": format!("Created and switched to branch: {}", name),
+                })))
             }
             "stage" => {
                 self.stage_changes()?;
-                Ok("Changes staged successfully".to_string())
+                Ok(ToolOutput::Json(json!({
+                    "command": "stage",
+                    "message": "Changes staged successfully",
+                })))
             }
             "commit" => {
                 let message = params.get("message").ok_or_else(|| anyhow!("Missing commit message"))?;
                 self.commit_changes(message)?;
-                Ok(format!("Changes committed with message: {}", message))
+                Ok(ToolOutput::Json(json!({
+                    "command": "commit",
+                    "commit_message": message,
+                    "message": format!("Changes committed with message: {}", message),
+                })))
             }
             "merge" => {
                 let target = params.get("target").ok_or_else(|| anyhow!("Missing target branch"))?;
                 self.merge_branch(target)?;
-                Ok(format!("Merged current branch into: {}", target))
+                Ok(ToolOutput::Json(json!({
+                    "command": "merge",
+                    "target": target,
+                    "message": format!("Merged current branch into: {}", target),
+                })))
+            }
+            "revert" => {
+                let commit = params.get("commit").map(String::as_str).unwrap_or("HEAD");
+                self.revert_commit(commit)?;
+                Ok(ToolOutput::Json(json!({
+                    "command": "revert",
+                    "commit": commit,
+                    "message": format!("Reverted commit: {}", commit),
+                })))
             }
             _ => Err(anyhow!("Unknown git command")),
         }
@@ -0,0 +1,393 @@
+//! Links tasks to GitHub issues. A task's `ticket` holds its issue as `owner/repo#123`;
+//! issues are closed when their tasks finish, and open issues with a given label can be
+//! pulled in as new tasks.
+
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::str::FromStr;
+use std::time::Duration;
+use async_trait::async_trait;
+use anyhow::{Result, anyhow};
+use chrono::Utc;
+use futures_util::{StreamExt, TryStreamExt};
+use mongodb::bson::doc;
+use reqwest::{Method, RequestBuilder};
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+use tokio::task::JoinHandle;
+use uuid::Uuid;
+use crate::events;
+use crate::tools::{ToolExecutor, ToolOutput};
+use crate::types::{TaskEventKind, TaskPriority, TaskStatus, TodoList, TodoTask};
+use crate::types::tags::normalize_tags;
+
+const DEFAULT_API_URL: &str = "https://api.github.com";
+const DEFAULT_INGEST_LABEL: &str = "todo";
+/// GitHub rejects longer titles
+const MAX_TITLE_CHARS: usize = 256;
+
+/// An issue in a repository, written `owner/repo#123`
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct IssueRef {
+    pub repo: String,
+    pub number: u64,
+}
+
+impl fmt::Display for IssueRef {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}#{}", self.repo, self.number)
+    }
+}
+
+impl FromStr for IssueRef {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (repo, number) = s.trim().rsplit_once('#').ok_or_else(|| anyhow!("Not an issue reference: {}", s))?;
+        validate_repo(repo)?;
+        let number = number.parse().map_err(|_| anyhow!("Not an issue number: {}", number))?;
+        Ok(Self { repo: repo.to_string(), number })
+    }
+}
+
+fn validate_repo(repo: &str) -> Result<()> {
+    match repo.split_once('/') {
+        Some((owner, name)) if !owner.is_empty() && !name.is_empty() && !name.contains('/') => Ok(()),
+        _ => Err(anyhow!("Repository must be owner/name, got {}", repo)),
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct GitHubLabel {
+    pub name: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct GitHubIssue {
+    pub number: u64,
+    pub title: String,
+    #[serde(default)]
+    pub body: Option<String>,
+    pub html_url: String,
+    #[serde(default)]
+    pub labels: Vec<GitHubLabel>,
+    /// Set when the "issue" is a pull request
+    #[serde(default)]
+    pub pull_request: Option<Value>,
+}
+
+/// The issue `task` is filed as, with its tags as labels
+pub fn issue_request(task: &TodoTask) -> Value {
+    let title: String = task.description.lines().next().unwrap_or_default().chars().take(MAX_TITLE_CHARS).collect();
+    let mut body = task.enhanced_description.clone().unwrap_or_else(|| task.description.clone());
+    if let Some(notes) = &task.notes {
+        body.push_str("\n\n");
+        body.push_str(notes);
+    }
+    body.push_str(&format!("\n\n---\nTask `{}` for `{}`, priority {:?}", task.id, task.target_agent, task.priority));
+    json!({ "title": title, "body": body, "labels": task.tags })
+}
+
+/// Why an issue is closed when its task ends up in `status`; `None` if it stays open
+pub fn close_reason(status: &TaskStatus) -> Option<&'static str> {
+    match status {
+        TaskStatus::Completed => Some("completed"),
+        TaskStatus::Cancelled => Some("not_planned"),
+        _ => None,
+    }
+}
+
+/// A pending task for `issue`, linked to it
+pub fn task_from_issue(issue: &GitHubIssue, repo: &str, target_agent: &str) -> TodoTask {
+    let now = Utc::now().timestamp();
+    let labels: Vec<&str> = issue.labels.iter().map(|label| label.name.as_str()).collect();
+    let priority = if labels.iter().any(|l| l.eq_ignore_ascii_case("critical")) {
+        TaskPriority::Critical
+    } else if labels.iter().any(|l| l.eq_ignore_ascii_case("high priority") || l.eq_ignore_ascii_case("priority: high")) {
+        TaskPriority::High
+    } else {
+        TaskPriority::Medium
+    };
+    TodoTask {
+        id: Uuid::new_v4().to_string(),
+        description: issue.title.clone(),
+        enhanced_description: issue.body.clone().filter(|body| !body.trim().is_empty()),
+        priority,
+        project: repo.rsplit('/').next().map(str::to_string),
+        source_agent: Some("github".to_string()),
+        target_agent: target_agent.to_string(),
+        status: TaskStatus::Pending,
+        created_at: now,
+        completed_at: None,
+        due_date: None,
+        duration_minutes: None,
+        notes: Some(issue.html_url.clone()),
+        ticket: Some(IssueRef { repo: repo.to_string(), number: issue.number }.to_string()),
+        last_modified: Some(now),
+        failure_reason: None,
+        embedding: None,
+        claim: None,
+        failures: Vec::new(),
+        confidence: None,
+        reviewed_at: None,
+        tenant_id: None,
+        idempotency_key: None,
+        content_hash: None,
+        history: Vec::new(),
+        tags: normalize_tags(&labels),
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct IngestReport {
+    /// Ids of the tasks created
+    pub created: Vec<String>,
+    /// Issues that already had a task
+    pub skipped: usize,
+}
+
+#[derive(Clone)]
+pub struct GitHubIssuesTool {
+    http_client: reqwest::Client,
+    api_url: String,
+    token: Option<String>,
+    /// Used when a command isn't given a `repo`
+    default_repo: Option<String>,
+    todo_list: TodoList,
+}
+
+impl GitHubIssuesTool {
+    /// Uses `GITHUB_TOKEN`, `GITHUB_API_URL` and, as the default repository, `GITHUB_REPOSITORY`
+    pub fn new(todo_list: TodoList) -> Result<Self> {
+        let http_client = reqwest::Client::builder()
+            .timeout(Duration::from_secs(30))
+            .user_agent("swarmonomicon")
+            .build()
+            .map_err(|e| anyhow!("Failed to create HTTP client: {}", e))?;
+        Ok(Self {
+            http_client,
+            api_url: std::env::var("GITHUB_API_URL").unwrap_or_else(|_| DEFAULT_API_URL.to_string()),
+            token: crate::secrets::get(crate::secrets::GITHUB_TOKEN),
+            default_repo: std::env::var("GITHUB_REPOSITORY").ok().filter(|repo| validate_repo(repo).is_ok()),
+            todo_list,
+        })
+    }
+
+    pub fn with_api_url(mut self, api_url: impl Into<String>) -> Self {
+        self.api_url = api_url.into();
+        self
+    }
+
+    pub fn with_token(mut self, token: impl Into<String>) -> Self {
+        self.token = Some(token.into());
+        self
+    }
+
+    fn request(&self, method: Method, path: &str) -> Result<RequestBuilder> {
+        let token = self.token.as_ref().ok_or_else(|| anyhow!("GitHub issues need GITHUB_TOKEN"))?;
+        Ok(self.http_client
+            .request(method, format!("{}{}", self.api_url.trim_end_matches('/'), path))
+            .bearer_auth(token)
+            .header("Accept", "application/vnd.github+json"))
+    }
+
+    async fn send<T: serde::de::DeserializeOwned>(&self, request: RequestBuilder) -> Result<T> {
+        let response = request.send().await.map_err(|e| anyhow!("Failed to call GitHub: {}", e))?;
+        if !response.status().is_success() {
+            let status = response.status();
+            let error_text = response.text().await.unwrap_or_else(|_| "Unknown error".to_string());
+            return Err(anyhow!("GitHub returned error {}: {}", status, error_text));
+        }
+        response.json().await.map_err(|e| anyhow!("Failed to parse GitHub response: {}", e))
+    }
+
+    fn repo<'a>(&'a self, params: &'a HashMap<String, String>) -> Result<&'a str> {
+        let repo = params.get("repo").or(self.default_repo.as_ref())
+            .ok_or_else(|| anyhow!("Missing repo parameter (owner/name)"))?;
+        validate_repo(repo)?;
+        Ok(repo)
+    }
+
+    /// File `task_id` as an issue in `repo` and link it, unless it is linked already
+    pub async fn create_issue(&self, task_id: &str, repo: &str) -> Result<IssueRef> {
+        let task = self.todo_list.get_task(task_id).await?.ok_or_else(|| anyhow!("No task {}", task_id))?;
+        if let Some(existing) = task.ticket.as_deref().and_then(|ticket| ticket.parse::<IssueRef>().ok()) {
+            return Ok(existing);
+        }
+        let issue: GitHubIssue = self.send(
+            self.request(Method::POST, &format!("/repos/{}/issues", repo))?.json(&issue_request(&task))
+        ).await?;
+        let issue_ref = IssueRef { repo: repo.to_string(), number: issue.number };
+        self.todo_list.collection().update_one(
+            doc! { "id": task_id },
+            doc! { "$set": { "ticket": issue_ref.to_string(), "last_modified": Utc::now().timestamp() } },
+            None,
+        ).await?;
+        tracing::info!("Filed task {} as {}", task_id, issue_ref);
+        Ok(issue_ref)
+    }
+
+    /// Close the issue linked to `task`, if it has one and has finished
+    pub async fn close_for_task(&self, task: &TodoTask) -> Result<Option<IssueRef>> {
+        let (Some(reason), Some(ticket)) = (close_reason(&task.status), task.ticket.as_deref()) else {
+            return Ok(None);
+        };
+        let Ok(issue_ref) = ticket.parse::<IssueRef>() else { return Ok(None) };
+        let _: Value = self.send(
+            self.request(Method::PATCH, &format!("/repos/{}/issues/{}", issue_ref.repo, issue_ref.number))?
+                .json(&json!({ "state": "closed", "state_reason": reason }))
+        ).await?;
+        Ok(Some(issue_ref))
+    }
+
+    /// Close the issues of every finished task linked to `repo`, returning those closed
+    pub async fn sync_closed(&self, repo: &str) -> Result<Vec<IssueRef>> {
+        let finished = [TaskStatus::Completed, TaskStatus::Cancelled].iter()
+            .map(mongodb::bson::to_bson)
+            .collect::<Result<Vec<_>, _>>()?;
+        let filter = doc! {
+            "status": { "$in": finished },
+            "ticket": { "$regex": format!("^{}#", regex::escape(repo)) },
+        };
+        let tasks: Vec<TodoTask> = self.todo_list.collection().find(filter, None).await?.try_collect().await?;
+        let mut closed = Vec::new();
+        for task in &tasks {
+            if let Some(issue_ref) = self.close_for_task(task).await? {
+                closed.push(issue_ref);
+            }
+        }
+        Ok(closed)
+    }
+
+    /// Add a task for each open issue in `repo` labeled `label` that doesn't have one yet
+    pub async fn ingest(&self, repo: &str, label: &str, target_agent: &str) -> Result<IngestReport> {
+        let issues: Vec<GitHubIssue> = self.send(
+            self.request(Method::GET, &format!("/repos/{}/issues", repo))?
+                .query(&[("labels", label), ("state", "open"), ("per_page", "100")])
+        ).await?;
+        let issues: Vec<GitHubIssue> = issues.into_iter().filter(|issue| issue.pull_request.is_none()).collect();
+
+        let refs: Vec<String> = issues.iter()
+            .map(|issue| IssueRef { repo: repo.to_string(), number: issue.number }.to_string())
+            .collect();
+        let linked_filter = doc! { "ticket": { "$in": &refs } };
+        let mut linked: HashSet<String> = self.todo_list.collection().find(linked_filter.clone(), None).await?
+            .try_collect::<Vec<_>>().await?
+            .into_iter().filter_map(|task| task.ticket).collect();
+        linked.extend(self.todo_list.archive_collection().find(linked_filter, None).await?
+            .try_collect::<Vec<_>>().await?
+            .into_iter().filter_map(|archived| archived.task.ticket));
+
+        let mut report = IngestReport { created: Vec::new(), skipped: 0 };
+        for (issue, issue_ref) in issues.iter().zip(&refs) {
+            if linked.contains(issue_ref) {
+                report.skipped += 1;
+                continue;
+            }
+            let task = task_from_issue(issue, repo, target_agent);
+            report.created.push(task.id.clone());
+            self.todo_list.add_task(task).await?;
+        }
+        Ok(report)
+    }
+
+    /// Close linked issues as tasks complete or are cancelled in this process
+    pub fn spawn_mirror(&self) -> JoinHandle<()> {
+        let tool = self.clone();
+        tokio::spawn(async move {
+            let mut task_events = Box::pin(events::task_events(events::global()));
+            while let Some(event) = task_events.next().await {
+                if !matches!(event.kind, TaskEventKind::Completed | TaskEventKind::Cancelled) {
+                    continue;
+                }
+                let closed = match tool.todo_list.get_task(&event.task_id).await {
+                    Ok(Some(task)) => tool.close_for_task(&task).await,
+                    Ok(None) => Ok(None),
+                    Err(e) => Err(e.into()),
+                };
+                match closed {
+                    Ok(Some(issue_ref)) => tracing::info!("Closed {} for task {}", issue_ref, event.task_id),
+                    Ok(None) => {}
+                    Err(e) => tracing::warn!("Failed to close the issue for task {}: {}", event.task_id, e),
+                }
+            }
+        })
+    }
+}
+
+#[async_trait]
+impl ToolExecutor for GitHubIssuesTool {
+    async fn execute(&self, params: HashMap<String, String>) -> Result<String> {
+        let command = params.get("command").ok_or_else(|| anyhow!("Missing command parameter"))?;
+        match command.as_str() {
+            "create" => {
+                let todo_id = params.get("todo_id").ok_or_else(|| anyhow!("Missing todo_id parameter"))?;
+                let issue_ref = self.create_issue(todo_id, self.repo(&params)?).await?;
+                Ok(format!("Task {} is {}", todo_id, issue_ref))
+            }
+            "sync" => {
+                let closed = self.sync_closed(self.repo(&params)?).await?;
+                Ok(format!("Closed {} issue(s)", closed.len()))
+            }
+            "ingest" => {
+                let label = params.get("label").map(|s| s.as_str()).unwrap_or(DEFAULT_INGEST_LABEL);
+                let target_agent = params.get("target_agent").map(|s| s.as_str()).unwrap_or("user");
+                let report = self.ingest(self.repo(&params)?, label, target_agent).await?;
+                Ok(format!("Created {} task(s), {} issue(s) already had one", report.created.len(), report.skipped))
+            }
+            _ => Err(anyhow!("Unknown command: {}", command)),
+        }
+    }
+
+    async fn execute_structured(&self, params: HashMap<String, String>) -> Result<ToolOutput> {
+        match params.get("command").map(|s| s.as_str()) {
+            Some("ingest") => {
+                let label = params.get("label").map(|s| s.as_str()).unwrap_or(DEFAULT_INGEST_LABEL);
+                let target_agent = params.get("target_agent").map(|s| s.as_str()).unwrap_or("user");
+                let report = self.ingest(self.repo(&params)?, label, target_agent).await?;
+                Ok(ToolOutput::Json(serde_json::to_value(report)?))
+            }
+            _ => self.execute(params).await.map(ToolOutput::Text),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_issue_ref_round_trip() {
+        let issue_ref: IssueRef = "DanEdens/Swarmonomicon#42".parse().unwrap();
+        assert_eq!(issue_ref, IssueRef { repo: "DanEdens/Swarmonomicon".to_string(), number: 42 });
+        assert_eq!(issue_ref.to_string(), "DanEdens/Swarmonomicon#42");
+        assert!("Swarmonomicon#42".parse::<IssueRef>().is_err());
+        assert!("a/b#x".parse::<IssueRef>().is_err());
+        assert!("a/b/c#1".parse::<IssueRef>().is_err());
+    }
+
+    #[test]
+    fn test_task_from_issue_and_back() {
+        let issue: GitHubIssue = serde_json::from_value(json!({
+            "number": 7,
+            "title": "Fix the login page",
+            "body": "It 500s on submit",
+            "html_url": "https://github.com/o/web/issues/7",
+            "labels": [{ "name": "Bug" }, { "name": "critical" }],
+        })).unwrap();
+        let task = task_from_issue(&issue, "o/web", "git");
+        assert_eq!(task.ticket.as_deref(), Some("o/web#7"));
+        assert_eq!(task.project.as_deref(), Some("web"));
+        assert_eq!(task.priority, TaskPriority::Critical);
+        assert_eq!(task.tags, ["bug", "critical"]);
+
+        let request = issue_request(&task);
+        assert_eq!(request["title"], "Fix the login page");
+        assert!(request["body"].as_str().unwrap().starts_with("It 500s on submit\n\nhttps://github.com/o/web/issues/7"));
+        assert_eq!(request["labels"], json!(["bug", "critical"]));
+
+        assert_eq!(close_reason(&TaskStatus::Completed), Some("completed"));
+        assert_eq!(close_reason(&TaskStatus::Cancelled), Some("not_planned"));
+        assert_eq!(close_reason(&TaskStatus::Failed), None);
+    }
+}
@@ -0,0 +1,219 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+use async_trait::async_trait;
+use crate::tools::ToolExecutor;
+use anyhow::{Result, anyhow};
+use image::{DynamicImage, GenericImageView, Rgba, RgbaImage, imageops::FilterType};
+use serde::Serialize;
+
+const DEFAULT_DIFF_DIR: &str = "screenshots/diffs";
+const DEFAULT_TOLERANCE: u8 = 16;
+const HASH_SIZE: u32 = 8;
+
+/// Outcome of comparing a baseline image against a candidate
+#[derive(Debug, Clone, Serialize)]
+pub struct ImageDiffReport {
+    /// Fraction of pixels that match within tolerance (0.0 - 1.0)
+    pub similarity: f64,
+    /// Similarity of the perceptual hashes (0.0 - 1.0)
+    pub hash_similarity: f64,
+    pub differing_pixels: u64,
+    pub total_pixels: u64,
+    pub dimensions_match: bool,
+    pub diff_image: Option<String>,
+    /// Set when a minimum similarity was requested
+    pub passed: Option<bool>,
+}
+
+/// Compares two screenshots for visual regression checks
+pub struct ImageDiffTool {
+    output_dir: PathBuf,
+}
+
+impl ImageDiffTool {
+    pub fn new() -> Self {
+        let output_dir = std::env::var("IMAGE_DIFF_DIR")
+            .unwrap_or_else(|_| DEFAULT_DIFF_DIR.to_string());
+        Self {
+            output_dir: PathBuf::from(output_dir),
+        }
+    }
+
+    pub fn with_output_dir<P: Into<PathBuf>>(mut self, output_dir: P) -> Self {
+        self.output_dir = output_dir.into();
+        self
+    }
+
+    /// Compare two images, returning the report and the highlighted diff image
+    pub fn compare(&self, baseline: &DynamicImage, candidate: &DynamicImage, tolerance: u8) -> (ImageDiffReport, RgbaImage) {
+        let (bw, bh) = baseline.dimensions();
+        let (cw, ch) = candidate.dimensions();
+        let width = bw.max(cw);
+        let height = bh.max(ch);
+
+        let base = baseline.to_rgba8();
+        let cand = candidate.to_rgba8();
+        let mut diff = RgbaImage::new(width, height);
+        let mut differing = 0u64;
+
+        for y in 0..height {
+            for x in 0..width {
+                let base_px = if x < bw && y < bh { Some(*base.get_pixel(x, y)) } else { None };
+                let cand_px = if x < cw && y < ch { Some(*cand.get_pixel(x, y)) } else { None };
+                let matches = match (base_px, cand_px) {
+                    (Some(a), Some(b)) => pixels_match(&a, &b, tolerance),
+                    _ => false,
+                };
+
+                let out = if matches {
+                    // Faded grayscale of the baseline so changes stand out
+                    let p = base_px.unwrap_or(Rgba([0, 0, 0, 0]));
+                    let luma = (0.299 * p[0] as f64 + 0.587 * p[1] as f64 + 0.114 * p[2] as f64) as u8;
+                    let faded = 128 + luma / 2;
+                    Rgba([faded, faded, faded, 255])
+                } else {
+                    differing += 1;
+                    Rgba([255, 0, 0, 255])
+                };
+                diff.put_pixel(x, y, out);
+            }
+        }
+
+        let total = width as u64 * height as u64;
+        let similarity = if total == 0 { 1.0 } else { 1.0 - differing as f64 / total as f64 };
+        let distance = (average_hash(baseline) ^ average_hash(candidate)).count_ones();
+        let hash_similarity = 1.0 - distance as f64 / (HASH_SIZE * HASH_SIZE) as f64;
+
+        let report = ImageDiffReport {
+            similarity,
+            hash_similarity,
+            differing_pixels: differing,
+            total_pixels: total,
+            dimensions_match: bw == cw && bh == ch,
+            diff_image: None,
+            passed: None,
+        };
+        (report, diff)
+    }
+
+    fn save_diff(&self, diff: &RgbaImage) -> Result<PathBuf> {
+        std::fs::create_dir_all(&self.output_dir)
+            .map_err(|e| anyhow!("Failed to create diff directory {}: {}", self.output_dir.display(), e))?;
+        let path = self.output_dir.join(format!(
+            "diff_{}_{}.png",
+            chrono::Utc::now().format("%Y%m%d_%H%M%S"),
+            &uuid::Uuid::new_v4().to_string()[..8]
+        ));
+        diff.save(&path).map_err(|e| anyhow!("Failed to save diff image: {}", e))?;
+        Ok(path)
+    }
+}
+
+fn pixels_match(a: &Rgba<u8>, b: &Rgba<u8>, tolerance: u8) -> bool {
+    a.0.iter().zip(b.0.iter()).all(|(x, y)| x.abs_diff(*y) <= tolerance)
+}
+
+/// 64-bit average hash: downscale to 8x8 grayscale and compare each pixel to the mean
+pub fn average_hash(image: &DynamicImage) -> u64 {
+    let small = image.resize_exact(HASH_SIZE, HASH_SIZE, FilterType::Triangle).to_luma8();
+    let mean = small.pixels().map(|p| p[0] as u64).sum::<u64>() / (HASH_SIZE * HASH_SIZE) as u64;
+    small.pixels().enumerate().fold(0u64, |hash, (i, p)| {
+        if p[0] as u64 >= mean { hash | (1 << i) } else { hash }
+    })
+}
+
+#[async_trait]
+impl ToolExecutor for ImageDiffTool {
+    async fn execute(&self, params: HashMap<String, String>) -> Result<String> {
+        let baseline_path = params.get("baseline").ok_or_else(|| anyhow!("Missing baseline parameter"))?;
+        let candidate_path = params.get("candidate").ok_or_else(|| anyhow!("Missing candidate parameter"))?;
+        let tolerance = match params.get("tolerance") {
+            Some(t) => t.parse::<u8>().map_err(|_| anyhow!("Invalid tolerance: {}", t))?,
+            None => DEFAULT_TOLERANCE,
+        };
+        let min_similarity = match params.get("min_similarity") {
+            Some(s) => Some(s.parse::<f64>().map_err(|_| anyhow!("Invalid min_similarity: {}", s))?),
+            None => None,
+        };
+
+        let baseline = image::open(baseline_path)
+            .map_err(|e| anyhow!("Failed to open baseline image {}: {}", baseline_path, e))?;
+        let candidate = image::open(candidate_path)
+            .map_err(|e| anyhow!("Failed to open candidate image {}: {}", candidate_path, e))?;
+
+        let (mut report, diff) = self.compare(&baseline, &candidate, tolerance);
+        if report.differing_pixels > 0 {
+            report.diff_image = Some(self.save_diff(&diff)?.display().to_string());
+        }
+        report.passed = min_similarity.map(|min| report.similarity >= min);
+
+        Ok(serde_json::to_string(&report)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn solid(width: u32, height: u32, color: [u8; 4]) -> DynamicImage {
+        DynamicImage::ImageRgba8(RgbaImage::from_pixel(width, height, Rgba(color)))
+    }
+
+    #[test]
+    fn test_identical_images() {
+        let tool = ImageDiffTool::new();
+        let image = solid(10, 10, [10, 20, 30, 255]);
+        let (report, _) = tool.compare(&image, &image, 0);
+        assert_eq!(report.differing_pixels, 0);
+        assert_eq!(report.similarity, 1.0);
+        assert_eq!(report.hash_similarity, 1.0);
+        assert!(report.dimensions_match);
+    }
+
+    #[test]
+    fn test_partial_difference() {
+        let tool = ImageDiffTool::new();
+        let baseline = solid(10, 10, [0, 0, 0, 255]);
+        let mut changed = baseline.to_rgba8();
+        for x in 0..5 {
+            for y in 0..10 {
+                changed.put_pixel(x, y, Rgba([255, 255, 255, 255]));
+            }
+        }
+        let (report, diff) = tool.compare(&baseline, &DynamicImage::ImageRgba8(changed), 16);
+        assert_eq!(report.differing_pixels, 50);
+        assert!((report.similarity - 0.5).abs() < f64::EPSILON);
+        assert_eq!(*diff.get_pixel(0, 0), Rgba([255, 0, 0, 255]));
+    }
+
+    #[test]
+    fn test_dimension_mismatch() {
+        let tool = ImageDiffTool::new();
+        let (report, diff) = tool.compare(&solid(10, 10, [0, 0, 0, 255]), &solid(10, 5, [0, 0, 0, 255]), 0);
+        assert!(!report.dimensions_match);
+        assert_eq!(report.differing_pixels, 50);
+        assert_eq!(diff.dimensions(), (10, 10));
+    }
+
+    #[tokio::test]
+    async fn test_execute_writes_diff_image() -> Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let baseline_path = temp_dir.path().join("baseline.png");
+        let candidate_path = temp_dir.path().join("candidate.png");
+        solid(8, 8, [0, 0, 0, 255]).save(&baseline_path)?;
+        solid(8, 8, [255, 255, 255, 255]).save(&candidate_path)?;
+
+        let tool = ImageDiffTool::new().with_output_dir(temp_dir.path().join("diffs"));
+        let params = HashMap::from([
+            ("baseline".to_string(), baseline_path.display().to_string()),
+            ("candidate".to_string(), candidate_path.display().to_string()),
+            ("min_similarity".to_string(), "0.9".to_string()),
+        ]);
+
+        let result: serde_json::Value = serde_json::from_str(&tool.execute(params).await?)?;
+        assert_eq!(result["passed"], false);
+        let diff_path = result["diff_image"].as_str().expect("diff image path");
+        assert!(std::path::Path::new(diff_path).exists());
+        Ok(())
+    }
+}
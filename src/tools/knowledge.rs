@@ -0,0 +1,46 @@
+use std::collections::HashMap;
+use async_trait::async_trait;
+use anyhow::{Result, anyhow};
+use crate::knowledge::{KnowledgeBase, Passage, DEFAULT_PASSAGES};
+use crate::tools::{ToolExecutor, ToolOutput};
+
+/// Retrieves passages of a project's ingested documentation and code, for agents to ground
+/// answers in. Takes `project`, `query` and an optional `limit`.
+#[derive(Clone)]
+pub struct KnowledgeTool {
+    knowledge: KnowledgeBase,
+}
+
+impl KnowledgeTool {
+    pub fn new(knowledge: KnowledgeBase) -> Self {
+        Self { knowledge }
+    }
+
+    async fn passages(&self, params: &HashMap<String, String>) -> Result<Vec<Passage>> {
+        let project = params.get("project").ok_or_else(|| anyhow!("Missing project parameter"))?;
+        let query = params.get("query").ok_or_else(|| anyhow!("Missing query parameter"))?;
+        let limit = match params.get("limit") {
+            Some(limit) => limit.parse::<usize>().map_err(|_| anyhow!("Invalid limit parameter: {}", limit))?,
+            None => DEFAULT_PASSAGES,
+        };
+        self.knowledge.retrieve(project, query, limit).await
+    }
+}
+
+#[async_trait]
+impl ToolExecutor for KnowledgeTool {
+    async fn execute(&self, params: HashMap<String, String>) -> Result<String> {
+        let passages = self.passages(&params).await?;
+        if passages.is_empty() {
+            return Ok("No documentation matched".to_string());
+        }
+        Ok(passages.iter()
+            .map(|passage| format!("[{}]\n{}", passage.citation(), passage.text.trim()))
+            .collect::<Vec<_>>()
+            .join("\n\n"))
+    }
+
+    async fn execute_structured(&self, params: HashMap<String, String>) -> Result<ToolOutput> {
+        Ok(ToolOutput::Json(serde_json::to_value(self.passages(&params).await?)?))
+    }
+}
@@ -1,15 +1,31 @@
 use async_trait::async_trait;
-use std::collections::HashMap;
-use crate::types::Tool;
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{OnceCell, RwLock, RwLockReadGuard};
+use lazy_static::lazy_static;
+use crate::types::{AgentConfig, Tool};
 use anyhow::Result;
+use crate::error::SwarmError;
+use crate::events::{self, DomainEvent};
+use tracing::Instrument;
 
 mod git;
 mod project;
 mod object_detection;
 mod screenshot_detection;
+mod image_diff;
 pub mod todo;
+mod todo_sync;
+mod github_issues;
+mod notify;
+mod desktop_notify;
 mod goose;
+mod knowledge;
 mod gpt_batch;
+mod timeout;
+mod pipeline;
+mod output;
 
 #[cfg(feature = "yolo")]
 pub mod yolo;
@@ -18,13 +34,36 @@ pub use git::GitTool;
 pub use project::ProjectTool;
 pub use object_detection::ObjectDetectionTool;
 pub use screenshot_detection::ScreenshotDetectionTool;
+pub use image_diff::ImageDiffTool;
 pub use todo::TodoTool;
+pub use notify::{NotificationTool, NotificationConfig, NotificationKind};
+pub use desktop_notify::{DesktopNotifyTool, DesktopNotifyConfig};
+pub use github_issues::{GitHubIssuesTool, IssueRef};
+pub use todo_sync::{TodoSyncTool, SyncAction, SyncOptions, SyncReport, sync_file};
 pub use goose::GooseTool;
+pub use knowledge::KnowledgeTool;
 pub use gpt_batch::GPTBatchTool;
+pub use output::ToolOutput;
+pub use pipeline::{ToolPipeline, PipelineStep};
+pub use timeout::{CancellationToken, ToolTimeoutError, ToolCancelledError, is_timeout};
+
+const DEFAULT_TOOL_TIMEOUT_SECS: u64 = 120;
 
 #[async_trait]
 pub trait ToolExecutor: Send + Sync {
     async fn execute(&self, params: HashMap<String, String>) -> Result<String>;
+
+    /// Execute with a cancellation token. Tools doing long-running work should
+    /// override this and stop early once the token is cancelled.
+    async fn execute_cancellable(&self, params: HashMap<String, String>, _token: CancellationToken) -> Result<String> {
+        self.execute(params).await
+    }
+
+    /// Execute and return a typed result. Tools producing JSON, files or binary
+    /// data should override this; the default wraps `execute` as text.
+    async fn execute_structured(&self, params: HashMap<String, String>) -> Result<ToolOutput> {
+        self.execute(params).await.map(ToolOutput::Text)
+    }
 }
 
 pub struct AgentTransferTool {
@@ -62,31 +101,108 @@ impl OpenAITool {
 //     }
 // }
 
+#[derive(Clone)]
 pub struct ToolRegistry {
-    tools: HashMap<String, Box<dyn ToolExecutor>>,
+    tools: HashMap<String, Arc<dyn ToolExecutor>>,
+    default_timeout: Option<Duration>,
+    timeouts: HashMap<String, Duration>,
 }
 
 impl ToolRegistry {
     pub fn new() -> Self {
         Self {
             tools: HashMap::new(),
+            default_timeout: None,
+            timeouts: HashMap::new(),
         }
     }
 
+    /// Apply a timeout to every tool without a specific override
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.default_timeout = Some(timeout);
+        self
+    }
+
+    pub fn set_tool_timeout(&mut self, name: &str, timeout: Duration) {
+        self.timeouts.insert(name.to_string(), timeout);
+    }
+
+    pub fn timeout_for(&self, name: &str) -> Option<Duration> {
+        self.timeouts.get(name).copied().or(self.default_timeout)
+    }
+
     pub fn register<T: ToolExecutor + 'static>(&mut self, name: String, executor: T) {
-        self.tools.insert(name, Box::new(executor));
+        self.tools.insert(name, Arc::new(executor));
     }
 
-    pub async fn execute(&self, tool: &Tool, params: HashMap<String, String>) -> Result<String> {
-        if let Some(executor) = self.tools.get(&tool.name) {
-            executor.execute(params).await
-        } else {
-            Err(anyhow::anyhow!("Tool not found in registry"))
+    /// Register an executor that is already shared with another registry
+    pub fn register_shared(&mut self, name: String, executor: Arc<dyn ToolExecutor>) {
+        self.tools.insert(name, executor);
+    }
+
+    /// Add `other`'s tools and timeouts, keeping any already registered under the same name
+    pub fn absorb(&mut self, other: ToolRegistry) {
+        for (name, tool) in other.tools {
+            self.tools.entry(name).or_insert(tool);
         }
+        for (name, timeout) in other.timeouts {
+            self.timeouts.entry(name).or_insert(timeout);
+        }
+        self.default_timeout = self.default_timeout.or(other.default_timeout);
+    }
+
+    /// Remove a tool, returning whether it was registered
+    pub fn unregister(&mut self, name: &str) -> bool {
+        self.tools.remove(name).is_some()
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.tools.contains_key(name)
+    }
+
+    pub fn tool_names(&self) -> Vec<String> {
+        let mut names: Vec<String> = self.tools.keys().cloned().collect();
+        names.sort();
+        names
+    }
+
+    pub async fn execute(&self, tool: &Tool, params: HashMap<String, String>) -> Result<String> {
+        self.execute_with_token(tool, params, CancellationToken::new()).await
+    }
+
+    /// Execute a tool, honouring its configured timeout and an external cancellation token.
+    /// When the timeout elapses the token is cancelled so cooperative tools can clean up.
+    pub async fn execute_with_token(&self, tool: &Tool, params: HashMap<String, String>, token: CancellationToken) -> Result<String> {
+        self.execute_for(None, tool, params, token).await
+    }
+
+    /// Execute a tool and return its typed output, with the same timeout handling as `execute`
+    pub async fn execute_structured(&self, tool: &Tool, params: HashMap<String, String>) -> Result<ToolOutput> {
+        self.execute_structured_for(None, tool, params).await
+    }
+
+    /// `execute_with_token` on behalf of `agent`, which is named in the `ToolExecuted` event
+    pub(crate) async fn execute_for(&self, agent: Option<&str>, tool: &Tool, params: HashMap<String, String>, token: CancellationToken) -> Result<String> {
+        self.handle(tool)?.execute_for(agent, tool, params, token).await
+    }
+
+    pub(crate) async fn execute_structured_for(&self, agent: Option<&str>, tool: &Tool, params: HashMap<String, String>) -> Result<ToolOutput> {
+        self.handle(tool)?.execute_structured_for(agent, tool, params).await
+    }
+
+    /// `tool`'s executor and timeout, which keep working once a lock on the registry is released
+    pub(crate) fn handle(&self, tool: &Tool) -> Result<ToolHandle> {
+        let executor = self.tools.get(&tool.name)
+            .ok_or_else(|| SwarmError::tool_failure(&tool.name, "tool is not registered"))?;
+        Ok(ToolHandle { executor: executor.clone(), timeout: self.timeout_for(&tool.name) })
     }
 
     pub async fn create_default_tools() -> Result<Self> {
-        let mut registry = Self::new();
+        let timeout_secs = std::env::var("TOOL_TIMEOUT_SECS")
+            .ok()
+            .and_then(|s| s.parse().ok())
+            .unwrap_or(DEFAULT_TOOL_TIMEOUT_SECS);
+        let mut registry = Self::new().with_timeout(Duration::from_secs(timeout_secs));
 
         // Register Git tool
         registry.register("git".to_string(), GitTool::new());
@@ -96,19 +212,217 @@ impl ToolRegistry {
 
         // Register Todo tool
         let todo_tool = TodoTool::new().await?;
+        todo_tool.start_enhancement_sweep(todo::enhancement_sweep_interval());
         registry.register("todo".to_string(), todo_tool);
 
+        // Register TODO.md sync and GitHub issues tools, which need the task collection
+        if crate::secrets::get(crate::secrets::MONGO_URI).is_some() {
+            match crate::types::TodoList::shared().await {
+                Ok(todo_list) => {
+                    registry.register("github_issues".to_string(), GitHubIssuesTool::new(todo_list.clone())?);
+                    registry.register("todo_sync".to_string(), TodoSyncTool::new(todo_list));
+                }
+                Err(e) => tracing::debug!("Task collection unavailable, todo_sync and github_issues disabled: {}", e),
+            }
+        }
+
+        // Register knowledge retrieval, which needs the knowledge collection
+        match crate::knowledge::KnowledgeBase::from_env().await {
+            Ok(Some(knowledge)) => registry.register("knowledge".to_string(), KnowledgeTool::new(knowledge)),
+            Ok(None) => {}
+            Err(e) => tracing::debug!("Knowledge base unavailable, knowledge tool disabled: {}", e),
+        }
+
+        // Register notification tool when Slack or Discord channels are configured
+        match NotificationTool::from_env() {
+            Ok(Some(notify)) => registry.register("notify".to_string(), notify),
+            Ok(None) => {}
+            Err(e) => tracing::warn!("Notification tool disabled: {}", e),
+        }
+
+        // Register desktop notifications when DESKTOP_NOTIFY or DESKTOP_NOTIFY_CONFIG is set
+        match DesktopNotifyTool::from_env() {
+            Ok(Some(desktop)) => registry.register("desktop_notify".to_string(), desktop),
+            Ok(None) => {}
+            Err(e) => tracing::warn!("Desktop notification tool disabled: {}", e),
+        }
+
         // Register Goose tool
         registry.register("goose".to_string(), GooseTool::new());
 
         // Register GPT Batch tool
-        let api_key = std::env::var("OPENAI_API_KEY").unwrap_or_else(|_| "".to_string());
+        let api_key = crate::secrets::get(crate::secrets::OPENAI_API_KEY).unwrap_or_default();
         registry.register("gpt_batch".to_string(), GPTBatchTool::new(api_key));
 
+        // Register Image Diff tool
+        registry.register("image_diff".to_string(), ImageDiffTool::new());
+
         Ok(registry)
     }
 }
 
+
+/// A registered tool, taken out of its registry to run
+pub(crate) struct ToolHandle {
+    executor: Arc<dyn ToolExecutor>,
+    timeout: Option<Duration>,
+}
+
+impl ToolHandle {
+    pub(crate) async fn execute_for(&self, agent: Option<&str>, tool: &Tool, params: HashMap<String, String>, token: CancellationToken) -> Result<String> {
+        let event = params.clone();
+        let result = self.guarded(tool, token.clone(), self.executor.execute_cancellable(params, token)).await;
+        events::global().publish(DomainEvent::tool_executed(&tool.name, agent, &event, result.is_ok()));
+        result
+    }
+
+    pub(crate) async fn execute_structured_for(&self, agent: Option<&str>, tool: &Tool, params: HashMap<String, String>) -> Result<ToolOutput> {
+        let event = params.clone();
+        let result = self.guarded(tool, CancellationToken::new(), self.executor.execute_structured(params)).await;
+        events::global().publish(DomainEvent::tool_executed(&tool.name, agent, &event, result.is_ok()));
+        result
+    }
+
+    async fn guarded<T>(&self, tool: &Tool, token: CancellationToken, execution: impl std::future::Future<Output = Result<T>>) -> Result<T> {
+        crate::chaos::tool(&tool.name)?;
+        let cancelled = token.cancelled();
+        tokio::pin!(execution, cancelled);
+
+        let run = async {
+            tokio::select! {
+                result = &mut execution => result.map_err(|e| match SwarmError::find(&e) {
+                    Some(_) => e,
+                    None => {
+                        let failure = SwarmError::tool_failure(&tool.name, &e);
+                        e.context(failure)
+                    }
+                }),
+                _ = &mut cancelled => Err(ToolCancelledError { tool: tool.name.clone() }.into()),
+            }
+        }.instrument(tracing::info_span!("tool", tool = %tool.name));
+
+        match self.timeout {
+            Some(timeout) => match tokio::time::timeout(timeout, run).await {
+                Ok(result) => result,
+                Err(_) => {
+                    token.cancel();
+                    tracing::warn!("Tool '{}' timed out after {:?}", tool.name, timeout);
+                    Err(ToolTimeoutError { tool: tool.name.clone(), timeout }.into())
+                }
+            },
+            None => run.await,
+        }
+    }
+}
+
+lazy_static! {
+    pub static ref GLOBAL_TOOL_REGISTRY: Arc<RwLock<ToolRegistry>> = Arc::new(RwLock::new(ToolRegistry::new()));
+}
+
+/// Register a tool in the global registry at runtime
+pub async fn register_tool<T: ToolExecutor + 'static>(name: String, executor: T) {
+    GLOBAL_TOOL_REGISTRY.write().await.register(name, executor);
+}
+
+static DEFAULT_TOOLS_INSTALLED: OnceCell<()> = OnceCell::const_new();
+
+/// Add the default tools to the global registry, once. Tools registered at runtime under
+/// the same name are kept.
+pub async fn install_default_tools() -> Result<()> {
+    DEFAULT_TOOLS_INSTALLED.get_or_try_init(|| async {
+        let defaults = ToolRegistry::create_default_tools().await?;
+        GLOBAL_TOOL_REGISTRY.write().await.absorb(defaults);
+        Ok::<_, anyhow::Error>(())
+    }).await?;
+    Ok(())
+}
+
+/// The tools an agent is permitted to call, as declared in its `AgentConfig.tools`.
+///
+/// Lookups go through the shared registry on every call, so tools registered
+/// after the toolset was created become available without rebuilding it.
+#[derive(Clone)]
+pub struct AgentToolset {
+    agent_name: String,
+    allowed: HashSet<String>,
+    registry: Arc<RwLock<ToolRegistry>>,
+}
+
+impl AgentToolset {
+    pub fn new(config: &AgentConfig, registry: Arc<RwLock<ToolRegistry>>) -> Self {
+        Self {
+            agent_name: config.name.clone(),
+            allowed: config.tools.iter().map(|t| t.name.clone()).collect(),
+            registry,
+        }
+    }
+
+    /// Build a toolset backed by the global tool registry, which gets the default tools
+    /// on first use
+    pub fn for_agent(config: &AgentConfig) -> Self {
+        Self::new(config, GLOBAL_TOOL_REGISTRY.clone())
+    }
+
+    pub fn is_permitted(&self, name: &str) -> bool {
+        self.allowed.contains(name)
+    }
+
+    pub fn permitted_tools(&self) -> Vec<String> {
+        let mut names: Vec<String> = self.allowed.iter().cloned().collect();
+        names.sort();
+        names
+    }
+
+    /// Permitted tools that are registered
+    pub async fn available_tools(&self) -> Vec<String> {
+        let registry = match self.registry().await {
+            Ok(registry) => registry,
+            Err(e) => {
+                tracing::warn!("Default tools unavailable to agent '{}': {}", self.agent_name, e);
+                self.registry.read().await
+            }
+        };
+        self.permitted_tools().into_iter().filter(|name| registry.contains(name)).collect()
+    }
+
+    pub async fn execute(&self, tool: &Tool, params: HashMap<String, String>) -> Result<String> {
+        self.handle(tool).await?.execute_for(Some(&self.agent_name), tool, params, CancellationToken::new()).await
+    }
+
+    pub async fn execute_structured(&self, tool: &Tool, params: HashMap<String, String>) -> Result<ToolOutput> {
+        self.handle(tool).await?.execute_structured_for(Some(&self.agent_name), tool, params).await
+    }
+
+    /// Take `tool` out of the registry, so the registry isn't locked while it runs
+    async fn handle(&self, tool: &Tool) -> Result<ToolHandle> {
+        let registry = self.registry().await?;
+        self.check_access(&registry, tool)?;
+        registry.handle(tool)
+    }
+
+    /// The backing registry, with the default tools installed first if it's the global one
+    async fn registry(&self) -> Result<RwLockReadGuard<'_, ToolRegistry>> {
+        if Arc::ptr_eq(&self.registry, &GLOBAL_TOOL_REGISTRY) {
+            install_default_tools().await?;
+        }
+        Ok(self.registry.read().await)
+    }
+
+    fn check_access(&self, registry: &ToolRegistry, tool: &Tool) -> Result<()> {
+        if !self.is_permitted(&tool.name) {
+            return Err(SwarmError::tool_failure(
+                &tool.name, format!("agent '{}' is not permitted to use it", self.agent_name)
+            ).into());
+        }
+        if !registry.contains(&tool.name) {
+            return Err(SwarmError::tool_failure(
+                &tool.name, format!("requested by agent '{}' but not registered", self.agent_name)
+            ).into());
+        }
+        Ok(())
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -136,4 +450,124 @@ mod tests {
         let result = registry.execute(&tool, HashMap::new()).await.unwrap();
         assert_eq!(result, "mock result");
     }
+
+    #[tokio::test]
+    async fn test_structured_execution() {
+        let mut registry = ToolRegistry::new();
+        registry.register("mock".to_string(), MockTool);
+        registry.register("git".to_string(), GitTool::new());
+
+        // Tools without a structured implementation fall back to text
+        let output = registry.execute_structured(&tool("mock"), HashMap::new()).await.unwrap();
+        assert_eq!(output, ToolOutput::Text("mock result".to_string()));
+
+        let err = registry.execute_structured(&tool("git"), HashMap::from([
+            ("command".to_string(), "rebase".to_string()),
+        ])).await.unwrap_err();
+        assert!(err.to_string().contains("Unknown git command"));
+        assert!(matches!(SwarmError::find(&err), Some(SwarmError::ToolFailure { tool, .. }) if tool == "git"));
+    }
+
+    struct SlowTool;
+
+    #[async_trait]
+    impl ToolExecutor for SlowTool {
+        async fn execute(&self, _params: HashMap<String, String>) -> Result<String> {
+            tokio::time::sleep(Duration::from_secs(60)).await;
+            Ok("too late".to_string())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_tool_timeout_and_cancellation() {
+        let mut registry = ToolRegistry::new().with_timeout(Duration::from_secs(30));
+        registry.register("slow".to_string(), SlowTool);
+        registry.set_tool_timeout("slow", Duration::from_millis(20));
+        assert_eq!(registry.timeout_for("slow"), Some(Duration::from_millis(20)));
+        assert_eq!(registry.timeout_for("other"), Some(Duration::from_secs(30)));
+
+        let err = registry.execute(&tool("slow"), HashMap::new()).await.unwrap_err();
+        assert!(is_timeout(&err));
+        assert!(err.to_string().contains("timed out"));
+
+        let token = CancellationToken::new();
+        token.cancel();
+        let err = registry.execute_with_token(&tool("slow"), HashMap::new(), token).await.unwrap_err();
+        assert!(!is_timeout(&err));
+        assert!(err.to_string().contains("cancelled"));
+    }
+
+    fn tool(name: &str) -> Tool {
+        Tool {
+            name: name.to_string(),
+            description: format!("{} tool", name),
+            parameters: HashMap::new(),
+        }
+    }
+
+    fn agent_config(tools: Vec<Tool>) -> AgentConfig {
+        AgentConfig {
+            name: "tester".to_string(),
+            public_description: "Test agent".to_string(),
+            instructions: "Test".to_string(),
+            tools,
+            downstream_agents: vec![],
+            personality: None,
+            state_machine: None,
+            script: None,
+            max_concurrency: None,
+            middleware: None,
+            remote: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_agent_toolset_scoping() {
+        let mut registry = ToolRegistry::new();
+        registry.register("mock".to_string(), MockTool);
+        registry.register("other".to_string(), MockTool);
+        let registry = Arc::new(RwLock::new(registry));
+
+        let toolset = AgentToolset::new(&agent_config(vec![tool("mock"), tool("late")]), registry.clone());
+        assert_eq!(toolset.execute(&tool("mock"), HashMap::new()).await.unwrap(), "mock result");
+
+        let denied = toolset.execute(&tool("other"), HashMap::new()).await.unwrap_err();
+        assert!(denied.to_string().contains("not permitted"));
+
+        let missing = toolset.execute(&tool("late"), HashMap::new()).await.unwrap_err();
+        assert!(missing.to_string().contains("not registered"));
+
+        // Tools registered at runtime become visible to existing toolsets
+        registry.write().await.register("late".to_string(), MockTool);
+        assert_eq!(toolset.execute(&tool("late"), HashMap::new()).await.unwrap(), "mock result");
+
+        assert!(registry.write().await.unregister("late"));
+        assert!(toolset.execute(&tool("late"), HashMap::new()).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_running_tools_leave_the_registry_unlocked() {
+        let mut registry = ToolRegistry::new();
+        registry.register("slow".to_string(), SlowTool);
+        let registry = Arc::new(RwLock::new(registry));
+        let toolset = AgentToolset::new(&agent_config(vec![tool("slow")]), registry.clone());
+
+        let running = tokio::spawn(async move { toolset.execute(&tool("slow"), HashMap::new()).await });
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        let writer = tokio::time::timeout(Duration::from_secs(1), registry.write()).await;
+        assert!(writer.is_ok(), "registering a tool waited for a running one");
+        running.abort();
+    }
+
+    #[tokio::test]
+    async fn test_global_toolsets_see_runtime_and_default_tools() {
+        register_tool("runtime_mock".to_string(), MockTool).await;
+        register_tool("image_diff".to_string(), MockTool).await;
+        let toolset = AgentToolset::for_agent(&agent_config(vec![tool("runtime_mock"), tool("image_diff"), tool("goose")]));
+
+        assert_eq!(toolset.execute(&tool("runtime_mock"), HashMap::new()).await.unwrap(), "mock result");
+        // Defaults are installed alongside, without replacing tools registered first
+        assert_eq!(toolset.available_tools().await, ["goose", "image_diff", "runtime_mock"]);
+        assert_eq!(toolset.execute(&tool("image_diff"), HashMap::new()).await.unwrap(), "mock result");
+    }
 }
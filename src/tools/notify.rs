@@ -0,0 +1,343 @@
+//! Announcements to Slack and Discord incoming webhooks. Channels and per-project routing
+//! come from the file at `NOTIFY_CONFIG` (YAML or JSON); without one, `SLACK_WEBHOOK_URL`
+//! and `DISCORD_WEBHOOK_URL` are used as default channels.
+//!
+//! ```yaml
+//! channels:
+//!   team: { kind: slack, webhook_secret: SLACK_TEAM_WEBHOOK }
+//!   ops: { kind: discord, webhook_url: "https://discord.com/api/webhooks/..." }
+//! routes:
+//!   swarmonomicon: [team]
+//! default: [ops]
+//! templates:
+//!   failed: ":x: {agent} gave up on {task}: {reason}"
+//! ```
+
+use std::collections::{BTreeMap, HashMap};
+use std::path::Path;
+use std::time::Duration;
+use async_trait::async_trait;
+use anyhow::{Result, anyhow};
+use futures_util::StreamExt;
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+use tokio::task::JoinHandle;
+use crate::events::{self, DomainEvent};
+use crate::tools::ToolExecutor;
+use crate::types::TaskEventKind;
+
+/// Discord rejects longer messages
+const DISCORD_MAX_CHARS: usize = 2000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ChannelKind {
+    Slack,
+    Discord,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Channel {
+    pub kind: ChannelKind,
+    /// The webhook, or leave unset and name the secret holding it in `webhook_secret`
+    #[serde(default)]
+    pub webhook_url: Option<String>,
+    #[serde(default)]
+    pub webhook_secret: Option<String>,
+}
+
+impl Channel {
+    fn webhook(&self) -> Result<String> {
+        if let Some(url) = &self.webhook_url {
+            return Ok(url.clone());
+        }
+        let secret = self.webhook_secret.as_deref().ok_or_else(|| anyhow!("Channel has no webhook_url or webhook_secret"))?;
+        crate::secrets::require(secret)
+    }
+
+    /// The webhook request body for `text`
+    pub fn payload(&self, text: &str) -> Value {
+        match self.kind {
+            ChannelKind::Slack => json!({ "text": text }),
+            ChannelKind::Discord => {
+                let content: String = text.chars().take(DISCORD_MAX_CHARS).collect();
+                json!({ "content": content })
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NotificationKind {
+    Completed,
+    Failed,
+    Transferred,
+    /// A task's worker stopped sending heartbeats
+    Stuck,
+    /// Free text from the caller
+    Message,
+}
+
+impl std::str::FromStr for NotificationKind {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        serde_json::from_value(json!(s.to_lowercase())).map_err(|_| anyhow!("Unknown notification kind: {}", s))
+    }
+}
+
+impl NotificationKind {
+    fn default_template(&self) -> &'static str {
+        match self {
+            NotificationKind::Completed => "✅ {agent} completed: {task}",
+            NotificationKind::Failed => "❌ {agent} failed: {task} ({reason})",
+            NotificationKind::Transferred => "↪️ {from} handed over to {to}",
+            NotificationKind::Stuck => "⚠️ Task {task_id} is stuck: no heartbeat from {worker}",
+            NotificationKind::Message => "{text}",
+        }
+    }
+}
+
+/// `template` with each `{name}` replaced by its value in `vars`; unknown names are left as-is
+pub fn render(template: &str, vars: &HashMap<String, String>) -> String {
+    let mut output = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(open) = rest.find('{') {
+        output.push_str(&rest[..open]);
+        let after = &rest[open + 1..];
+        match after.find('}').map(|close| (&after[..close], close)) {
+            Some((name, close)) if vars.contains_key(name) => {
+                output.push_str(&vars[name]);
+                rest = &after[close + 1..];
+            }
+            _ => {
+                output.push('{');
+                rest = after;
+            }
+        }
+    }
+    output.push_str(rest);
+    output
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct NotificationConfig {
+    #[serde(default)]
+    pub channels: HashMap<String, Channel>,
+    /// Channels to notify for each project
+    #[serde(default)]
+    pub routes: HashMap<String, Vec<String>>,
+    /// Channels for tasks without a routed project
+    #[serde(default)]
+    pub default: Vec<String>,
+    #[serde(default)]
+    pub templates: BTreeMap<NotificationKind, String>,
+}
+
+impl NotificationConfig {
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let source = std::fs::read_to_string(path).map_err(|e| anyhow!("Failed to read {}: {}", path.display(), e))?;
+        // YAML is a superset of JSON
+        serde_yaml::from_str(&source).map_err(|e| anyhow!("{}: {}", path.display(), e))
+    }
+
+    /// The `NOTIFY_CONFIG` file, else the Slack and Discord webhook secrets; `None` if there
+    /// are no channels
+    pub fn from_env() -> Result<Option<Self>> {
+        let config = match std::env::var("NOTIFY_CONFIG") {
+            Ok(path) => Self::from_file(path)?,
+            Err(_) => {
+                let mut config = Self::default();
+                for (name, kind, secret) in [
+                    ("slack", ChannelKind::Slack, crate::secrets::SLACK_WEBHOOK_URL),
+                    ("discord", ChannelKind::Discord, crate::secrets::DISCORD_WEBHOOK_URL),
+                ] {
+                    if crate::secrets::get(secret).is_some() {
+                        config.channels.insert(name.to_string(), Channel { kind, webhook_url: None, webhook_secret: Some(secret.to_string()) });
+                        config.default.push(name.to_string());
+                    }
+                }
+                config
+            }
+        };
+        Ok((!config.channels.is_empty()).then_some(config))
+    }
+
+    /// The channels a notification about `project` goes to
+    pub fn channels_for(&self, project: Option<&str>) -> &[String] {
+        project.and_then(|project| self.routes.get(project)).unwrap_or(&self.default)
+    }
+
+    pub fn template(&self, kind: NotificationKind) -> &str {
+        self.templates.get(&kind).map(|t| t.as_str()).unwrap_or(kind.default_template())
+    }
+}
+
+#[derive(Clone)]
+pub struct NotificationTool {
+    http_client: reqwest::Client,
+    config: NotificationConfig,
+}
+
+impl NotificationTool {
+    pub fn new(config: NotificationConfig) -> Result<Self> {
+        let http_client = reqwest::Client::builder()
+            .timeout(Duration::from_secs(10))
+            .build()
+            .map_err(|e| anyhow!("Failed to create HTTP client: {}", e))?;
+        Ok(Self { http_client, config })
+    }
+
+    /// A tool for the configured channels, or `None` if none are configured
+    pub fn from_env() -> Result<Option<Self>> {
+        NotificationConfig::from_env()?.map(Self::new).transpose()
+    }
+
+    async fn post(&self, name: &str, text: &str) -> Result<()> {
+        let channel = self.config.channels.get(name).ok_or_else(|| anyhow!("Unknown channel: {}", name))?;
+        let response = self.http_client
+            .post(channel.webhook()?)
+            .json(&channel.payload(text))
+            .send()
+            .await
+            .map_err(|e| anyhow!("Failed to notify {}: {}", name, e))?;
+        if !response.status().is_success() {
+            let status = response.status();
+            let error_text = response.text().await.unwrap_or_else(|_| "Unknown error".to_string());
+            return Err(anyhow!("Channel {} returned error {}: {}", name, status, error_text));
+        }
+        Ok(())
+    }
+
+    /// Render `kind`'s template with `vars` and post it to `channel`, or to the channels
+    /// routed for the `project` var. Returns the channels notified.
+    pub async fn notify(&self, kind: NotificationKind, vars: &HashMap<String, String>, channel: Option<&str>) -> Result<Vec<String>> {
+        let text = render(self.config.template(kind), vars);
+        let channels = match channel {
+            Some(channel) => vec![channel.to_string()],
+            None => self.config.channels_for(vars.get("project").map(|s| s.as_str())).to_vec(),
+        };
+        for name in &channels {
+            self.post(name, &text).await?;
+        }
+        Ok(channels)
+    }
+
+    /// Announce task completions, failures and transfers between agents in this process
+    pub fn spawn_notifier(&self) -> JoinHandle<()> {
+        let tool = self.clone();
+        tokio::spawn(async move {
+            let mut domain_events = events::global().subscribe();
+            while let Some(event) = domain_events.next().await {
+                let Some((kind, vars)) = notification_for(&event) else { continue };
+                if let Err(e) = tool.notify(kind, &vars, None).await {
+                    tracing::warn!("Failed to send {} notification: {}", event.name(), e);
+                }
+            }
+        })
+    }
+}
+
+/// The notification an event calls for, with its template vars
+pub fn notification_for(event: &DomainEvent) -> Option<(NotificationKind, HashMap<String, String>)> {
+    let mut vars = HashMap::new();
+    let kind = match event {
+        DomainEvent::Task(task) => {
+            let kind = match task.kind {
+                TaskEventKind::Completed => NotificationKind::Completed,
+                TaskEventKind::Failed => NotificationKind::Failed,
+                _ => return None,
+            };
+            vars.insert("task_id".to_string(), task.task_id.clone());
+            vars.insert("task".to_string(), task.description.clone().unwrap_or_else(|| task.task_id.clone()));
+            vars.insert("agent".to_string(), task.target_agent.clone().unwrap_or_else(|| "an agent".to_string()));
+            vars.insert("reason".to_string(), task.failure_reason.clone().unwrap_or_else(|| "no reason given".to_string()));
+            if let Some(project) = &task.project {
+                vars.insert("project".to_string(), project.clone());
+            }
+            kind
+        }
+        DomainEvent::MessageRouted { from: Some(from), to, .. } => {
+            vars.insert("from".to_string(), from.clone());
+            vars.insert("to".to_string(), to.clone());
+            NotificationKind::Transferred
+        }
+        DomainEvent::TaskStuck { task_id, worker_id, .. } => {
+            vars.insert("task_id".to_string(), task_id.clone());
+            vars.insert("worker".to_string(), worker_id.clone().unwrap_or_else(|| "its worker".to_string()));
+            NotificationKind::Stuck
+        }
+        _ => return None,
+    };
+    Some((kind, vars))
+}
+
+#[async_trait]
+impl ToolExecutor for NotificationTool {
+    async fn execute(&self, params: HashMap<String, String>) -> Result<String> {
+        let kind: NotificationKind = params.get("kind").map(|s| s.as_str()).unwrap_or("message").parse()?;
+        if kind == NotificationKind::Message && !params.contains_key("text") {
+            return Err(anyhow!("Missing text parameter"));
+        }
+        let channel = params.get("channel").map(|s| s.as_str());
+        let channels = self.notify(kind, &params, channel).await?;
+        Ok(format!("Notified {}", channels.join(", ")))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::TaskEvent;
+
+    #[test]
+    fn test_render_and_payloads() {
+        let vars = HashMap::from([("agent".to_string(), "git".to_string()), ("task".to_string(), "Ship {it}".to_string())]);
+        assert_eq!(render("{agent} did {task} {unknown} {", &vars), "git did Ship {it} {unknown} {");
+
+        let slack = Channel { kind: ChannelKind::Slack, webhook_url: Some("u".to_string()), webhook_secret: None };
+        assert_eq!(slack.payload("hi"), json!({ "text": "hi" }));
+        let discord = Channel { kind: ChannelKind::Discord, ..slack };
+        let long = "x".repeat(3000);
+        assert_eq!(discord.payload(&long)["content"].as_str().unwrap().len(), DISCORD_MAX_CHARS);
+    }
+
+    #[test]
+    fn test_routing_and_events() {
+        let config: NotificationConfig = serde_yaml::from_str(
+            "channels:\n  team: { kind: slack, webhook_url: a }\n  ops: { kind: discord, webhook_url: b }\n\
+             routes:\n  web: [team, ops]\ndefault: [ops]\ntemplates:\n  failed: 'nope {task}'\n"
+        ).unwrap();
+        assert_eq!(config.channels_for(Some("web")), ["team", "ops"]);
+        assert_eq!(config.channels_for(Some("other")), ["ops"]);
+        assert_eq!(config.channels_for(None), ["ops"]);
+        assert_eq!(config.template(NotificationKind::Failed), "nope {task}");
+        assert_eq!(config.template(NotificationKind::Completed), "✅ {agent} completed: {task}");
+
+        let event = DomainEvent::Task(TaskEvent {
+            kind: TaskEventKind::Failed,
+            task_id: "t1".to_string(),
+            description: Some("Deploy".to_string()),
+            target_agent: Some("git".to_string()),
+            project: Some("web".to_string()),
+            priority: None,
+            failure_reason: Some("timeout".to_string()),
+            tenant_id: None,
+            timestamp: 0,
+        });
+        let (kind, vars) = notification_for(&event).unwrap();
+        assert_eq!(kind, NotificationKind::Failed);
+        assert_eq!(render(config.template(kind), &vars), "nope Deploy");
+        assert_eq!(vars["project"], "web");
+
+        let (kind, vars) = notification_for(&DomainEvent::message_routed(Some("greeter"), "git")).unwrap();
+        assert_eq!(kind, NotificationKind::Transferred);
+        assert_eq!(render(config.template(kind), &vars), "↪️ greeter handed over to git");
+        assert!(notification_for(&DomainEvent::message_routed(None, "git")).is_none());
+
+        let (kind, vars) = notification_for(&DomainEvent::task_stuck("t2", Some("worker-1"), Some(0))).unwrap();
+        assert_eq!(render(config.template(kind), &vars), "⚠️ Task t2 is stuck: no heartbeat from worker-1");
+    }
+}
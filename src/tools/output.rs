@@ -0,0 +1,91 @@
+use std::fmt;
+use std::path::PathBuf;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// Typed result of a tool call, so callers don't have to re-parse flat strings
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", content = "value", rename_all = "snake_case")]
+pub enum ToolOutput {
+    Text(String),
+    Json(Value),
+    /// A file the tool wrote to disk
+    File(PathBuf),
+    Binary(Vec<u8>),
+}
+
+impl ToolOutput {
+    /// Wrap a response body as JSON when it parses, otherwise as text
+    pub fn from_response(body: String) -> Self {
+        match serde_json::from_str::<Value>(&body) {
+            Ok(value) if value.is_object() || value.is_array() => ToolOutput::Json(value),
+            _ => ToolOutput::Text(body),
+        }
+    }
+
+    pub fn as_text(&self) -> Option<&str> {
+        match self {
+            ToolOutput::Text(text) => Some(text),
+            _ => None,
+        }
+    }
+
+    pub fn as_json(&self) -> Option<&Value> {
+        match self {
+            ToolOutput::Json(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    pub fn as_file(&self) -> Option<&PathBuf> {
+        match self {
+            ToolOutput::File(path) => Some(path),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for ToolOutput {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ToolOutput::Text(text) => write!(f, "{}", text),
+            ToolOutput::Json(value) => write!(f, "{}", value),
+            ToolOutput::File(path) => write!(f, "{}", path.display()),
+            ToolOutput::Binary(bytes) => write!(f, "<{} bytes of binary data>", bytes.len()),
+        }
+    }
+}
+
+impl From<String> for ToolOutput {
+    fn from(text: String) -> Self {
+        ToolOutput::Text(text)
+    }
+}
+
+impl From<Value> for ToolOutput {
+    fn from(value: Value) -> Self {
+        ToolOutput::Json(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_tool_output_conversions() {
+        assert_eq!(ToolOutput::from_response("done".to_string()), ToolOutput::Text("done".to_string()));
+        assert_eq!(ToolOutput::from_response("42".to_string()), ToolOutput::Text("42".to_string()));
+        assert_eq!(
+            ToolOutput::from_response(r#"{"success": true}"#.to_string()),
+            ToolOutput::Json(json!({"success": true}))
+        );
+
+        assert_eq!(ToolOutput::Json(json!({"a": 1})).to_string(), r#"{"a":1}"#);
+        assert_eq!(ToolOutput::Binary(vec![0; 3]).to_string(), "<3 bytes of binary data>");
+
+        let serialized = serde_json::to_value(ToolOutput::File(PathBuf::from("out.png"))).unwrap();
+        assert_eq!(serialized, json!({"type": "file", "value": "out.png"}));
+    }
+}
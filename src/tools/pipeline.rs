@@ -0,0 +1,189 @@
+use std::collections::{HashMap, HashSet};
+use anyhow::{Result, anyhow};
+use futures::future::join_all;
+use crate::tools::ToolRegistry;
+use crate::types::{MessageMetadata, ToolCall};
+
+/// A single tool call in a pipeline, identified by `id` so other steps can depend on it
+#[derive(Debug, Clone)]
+pub struct PipelineStep {
+    pub id: String,
+    pub call: ToolCall,
+    pub depends_on: Vec<String>,
+}
+
+/// Runs a set of tool calls, executing calls whose dependencies are satisfied concurrently.
+///
+/// Parameters may reference earlier results with `{{step_id}}`, which is replaced by
+/// that step's output before the call runs.
+#[derive(Debug, Clone, Default)]
+pub struct ToolPipeline {
+    steps: Vec<PipelineStep>,
+}
+
+impl ToolPipeline {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add_step(mut self, id: &str, call: ToolCall, depends_on: Vec<String>) -> Self {
+        self.steps.push(PipelineStep {
+            id: id.to_string(),
+            call,
+            depends_on,
+        });
+        self
+    }
+
+    pub fn steps(&self) -> &[PipelineStep] {
+        &self.steps
+    }
+
+    /// Group steps into batches that can run concurrently, in dependency order
+    pub fn execution_order(&self) -> Result<Vec<Vec<String>>> {
+        let ids: HashSet<&str> = self.steps.iter().map(|s| s.id.as_str()).collect();
+        if ids.len() != self.steps.len() {
+            return Err(anyhow!("Pipeline contains duplicate step ids"));
+        }
+        for step in &self.steps {
+            if let Some(missing) = step.depends_on.iter().find(|d| !ids.contains(d.as_str())) {
+                return Err(anyhow!("Step '{}' depends on unknown step '{}'", step.id, missing));
+            }
+        }
+
+        let mut done: HashSet<String> = HashSet::new();
+        let mut batches = Vec::new();
+        while done.len() < self.steps.len() {
+            let ready: Vec<String> = self.steps.iter()
+                .filter(|s| !done.contains(&s.id))
+                .filter(|s| s.depends_on.iter().all(|d| done.contains(d)))
+                .map(|s| s.id.clone())
+                .collect();
+            if ready.is_empty() {
+                return Err(anyhow!("Pipeline contains a dependency cycle"));
+            }
+            done.extend(ready.iter().cloned());
+            batches.push(ready);
+        }
+        Ok(batches)
+    }
+
+    /// Execute the pipeline, returning each step's result keyed by step id
+    pub async fn execute(&self, registry: &ToolRegistry) -> Result<HashMap<String, String>> {
+        let mut results: HashMap<String, String> = HashMap::new();
+
+        for batch in self.execution_order()? {
+            let calls = batch.iter().map(|id| {
+                let step = self.steps.iter().find(|s| &s.id == id).expect("step exists");
+                let params = substitute_results(&step.call.parameters, &results);
+                async move {
+                    let result = registry.execute(&step.call.tool, params).await
+                        .map_err(|e| anyhow!("Step '{}' ({}) failed: {}", step.id, step.call.tool.name, e));
+                    (step.id.clone(), result)
+                }
+            });
+
+            for (id, result) in join_all(calls).await {
+                results.insert(id, result?);
+            }
+        }
+
+        Ok(results)
+    }
+
+    /// Execute the pipeline and merge the results into a message's tool_results
+    pub async fn execute_into(&self, registry: &ToolRegistry, metadata: &mut MessageMetadata) -> Result<()> {
+        let results = self.execute(registry).await?;
+        metadata.tool_results.get_or_insert_with(HashMap::new).extend(results);
+        Ok(())
+    }
+}
+
+fn substitute_results(params: &HashMap<String, String>, results: &HashMap<String, String>) -> HashMap<String, String> {
+    params.iter()
+        .map(|(key, value)| {
+            let value = results.iter().fold(value.clone(), |acc, (id, result)| {
+                acc.replace(&format!("{{{{{}}}}}", id), result)
+            });
+            (key.clone(), value)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
+    use async_trait::async_trait;
+    use crate::tools::ToolExecutor;
+    use crate::types::Tool;
+
+    struct EchoTool {
+        running: Arc<AtomicUsize>,
+        max_running: Arc<AtomicUsize>,
+    }
+
+    #[async_trait]
+    impl ToolExecutor for EchoTool {
+        async fn execute(&self, params: HashMap<String, String>) -> Result<String> {
+            let now = self.running.fetch_add(1, Ordering::SeqCst) + 1;
+            self.max_running.fetch_max(now, Ordering::SeqCst);
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            self.running.fetch_sub(1, Ordering::SeqCst);
+            Ok(params.get("text").cloned().unwrap_or_default())
+        }
+    }
+
+    fn call(text: &str) -> ToolCall {
+        ToolCall {
+            tool: Tool {
+                name: "echo".to_string(),
+                description: "Echo".to_string(),
+                parameters: HashMap::new(),
+            },
+            parameters: HashMap::from([("text".to_string(), text.to_string())]),
+            result: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_pipeline_runs_independent_steps_concurrently() -> Result<()> {
+        let max_running = Arc::new(AtomicUsize::new(0));
+        let mut registry = ToolRegistry::new();
+        registry.register("echo".to_string(), EchoTool {
+            running: Arc::new(AtomicUsize::new(0)),
+            max_running: max_running.clone(),
+        });
+
+        let pipeline = ToolPipeline::new()
+            .add_step("a", call("alpha"), vec![])
+            .add_step("b", call("beta"), vec![])
+            .add_step("c", call("{{a}}+{{b}}"), vec!["a".to_string(), "b".to_string()]);
+
+        assert_eq!(pipeline.execution_order()?, vec![
+            vec!["a".to_string(), "b".to_string()],
+            vec!["c".to_string()],
+        ]);
+
+        let mut metadata = MessageMetadata::new("tester".to_string());
+        pipeline.execute_into(&registry, &mut metadata).await?;
+
+        let results = metadata.tool_results.unwrap();
+        assert_eq!(results["c"], "alpha+beta");
+        assert_eq!(max_running.load(Ordering::SeqCst), 2);
+        Ok(())
+    }
+
+    #[test]
+    fn test_pipeline_rejects_invalid_dependencies() {
+        let cyclic = ToolPipeline::new()
+            .add_step("a", call("x"), vec!["b".to_string()])
+            .add_step("b", call("y"), vec!["a".to_string()]);
+        assert!(cyclic.execution_order().unwrap_err().to_string().contains("cycle"));
+
+        let unknown = ToolPipeline::new().add_step("a", call("x"), vec!["missing".to_string()]);
+        assert!(unknown.execution_order().unwrap_err().to_string().contains("unknown step"));
+    }
+}
@@ -67,6 +67,18 @@ setup(
         Ok(())
     }
 
+    fn remove_project(&self, project_type: &str, name: &str) -> Result<String> {
+        if name.is_empty() || name.contains(['/', '\\']) || name == ".." {
+            return Err(anyhow!("Invalid project name: {}", name));
+        }
+        let project_dir = Path::new("projects").join(project_type).join(name);
+        if !project_dir.exists() {
+            return Err(anyhow!("Project directory {} doesn't exist", project_dir.display()));
+        }
+        fs::remove_dir_all(&project_dir).map_err(|e| anyhow!("Failed to remove project directory: {}", e))?;
+        Ok(format!("Project {} removed from {}", name, project_dir.display()))
+    }
+
     fn create_readme(
         &self,
         name: &str,
@@ -131,6 +143,12 @@ impl ToolExecutor for ProjectTool {
     async fn execute(&self, params: HashMap<String, String>) -> Result<String> {
         let project_type = params.get("type").ok_or_else(|| anyhow!("Missing project type"))?;
         let name = params.get("name").ok_or_else(|| anyhow!("Missing project name"))?;
+
+        // `action: remove` deletes a project created earlier, e.g. to undo a failed workflow
+        if params.get("action").is_some_and(|action| action == "remove") {
+            return self.remove_project(project_type, name);
+        }
+
         let description = params.get("description").ok_or_else(|| anyhow!("Missing project description"))?;
 
         // Validate project type
@@ -1,31 +1,190 @@
 use std::collections::HashMap;
+use std::io::Cursor;
+use std::sync::Arc;
 use async_trait::async_trait;
-use crate::tools::ToolExecutor;
+use crate::tools::{ToolExecutor, ToolOutput};
 use anyhow::{Result, anyhow};
 use image::{DynamicImage, GenericImageView};
 use screenshots::Screen;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::fs;
 use std::io;
 use std::error::Error;
-use crate::types::Tool;
+use tokio::process::Command as TokioCommand;
+use crate::ai::{AiProvider, FallbackAiClient};
+use crate::types::{Attachment, Tool};
 
-pub struct ScreenshotDetectionTool;
+const DEFAULT_SCREENSHOT_DIR: &str = "screenshots";
+
+const VISION_PROMPT: &str = "You are looking at a screenshot taken during an automation task. \
+    Answer the question about it briefly and precisely, naming any visible text that matters.";
+
+/// What part of the desktop a capture should cover
+#[derive(Debug, Clone, PartialEq)]
+pub enum CaptureTarget {
+    /// A whole display, selected by index into `Screen::all()` (defaults to the first)
+    Display(Option<usize>),
+    /// A rectangle relative to the top-left corner of the selected display
+    Region {
+        display: Option<usize>,
+        x: i32,
+        y: i32,
+        width: u32,
+        height: u32,
+    },
+    /// The first window whose title matches the given name
+    Window(String),
+}
+
+impl CaptureTarget {
+    /// Build a capture target from tool parameters, validating them up front
+    pub fn from_params(params: &HashMap<String, String>) -> Result<Self> {
+        let display = match params.get("display") {
+            Some(value) => Some(value.parse::<usize>()
+                .map_err(|_| anyhow!("Invalid display index: {}", value))?),
+            None => None,
+        };
+
+        let mode = params.get("mode").map(|s| s.as_str()).unwrap_or("screen");
+        match mode {
+            "screen" | "display" => Ok(CaptureTarget::Display(display)),
+            "region" => {
+                let x = parse_param::<i32>(params, "x")?;
+                let y = parse_param::<i32>(params, "y")?;
+                let width = parse_param::<u32>(params, "width")?;
+                let height = parse_param::<u32>(params, "height")?;
+                if x < 0 || y < 0 {
+                    return Err(anyhow!("Region origin must not be negative"));
+                }
+                if width == 0 || height == 0 {
+                    return Err(anyhow!("Region width and height must be greater than zero"));
+                }
+                Ok(CaptureTarget::Region { display, x, y, width, height })
+            }
+            "window" => {
+                let name = params.get("window")
+                    .map(|s| s.trim())
+                    .filter(|s| !s.is_empty())
+                    .ok_or_else(|| anyhow!("Missing window parameter"))?;
+                Ok(CaptureTarget::Window(name.to_string()))
+            }
+            other => Err(anyhow!("Unknown capture mode '{}'. Use 'screen', 'region' or 'window'", other)),
+        }
+    }
+}
+
+fn parse_param<T: std::str::FromStr>(params: &HashMap<String, String>, name: &str) -> Result<T> {
+    let value = params.get(name).ok_or_else(|| anyhow!("Missing {} parameter", name))?;
+    value.trim().parse::<T>().map_err(|_| anyhow!("Invalid {} parameter: {}", name, value))
+}
+
+pub struct ScreenshotDetectionTool {
+    output_dir: PathBuf,
+    /// Answers questions about captures
+    vision: Arc<dyn AiProvider + Send + Sync>,
+}
 
 impl ScreenshotDetectionTool {
     pub fn new() -> Self {
-        Self
+        let output_dir = std::env::var("SCREENSHOT_DIR")
+            .unwrap_or_else(|_| DEFAULT_SCREENSHOT_DIR.to_string());
+        Self {
+            output_dir: PathBuf::from(output_dir),
+            vision: Arc::new(FallbackAiClient::default_chain()),
+        }
     }
 
-    pub async fn capture_screen(&self) -> Result<DynamicImage> {
+    pub fn with_ai_client<T: AiProvider + Send + Sync + 'static>(mut self, client: T) -> Self {
+        self.vision = Arc::new(client);
+        self
+    }
+
+    pub fn with_output_dir<P: Into<PathBuf>>(mut self, output_dir: P) -> Self {
+        self.output_dir = output_dir.into();
+        self
+    }
+
+    fn select_screen(display: Option<usize>) -> Result<Screen> {
         let screens = Screen::all().map_err(|e| anyhow!("Failed to get screens: {}", e))?;
-        if let Some(screen) = screens.first() {
-            let image = screen.capture().map_err(|e| anyhow!("Failed to capture screen: {}", e))?;
-            let dynamic_image = DynamicImage::from(image);
-            Ok(dynamic_image)
-        } else {
-            Err(anyhow!("No screens found"))
-        }
+        let index = display.unwrap_or(0);
+        screens.get(index).copied().ok_or_else(|| {
+            if screens.is_empty() {
+                anyhow!("No screens found")
+            } else {
+                anyhow!("Display {} not found ({} available)", index, screens.len())
+            }
+        })
+    }
+
+    pub async fn capture_screen(&self) -> Result<DynamicImage> {
+        self.capture(&CaptureTarget::Display(None)).await
+    }
+
+    pub async fn capture(&self, target: &CaptureTarget) -> Result<DynamicImage> {
+        let image = match target {
+            CaptureTarget::Display(display) => {
+                let screen = Self::select_screen(*display)?;
+                screen.capture().map_err(|e| anyhow!("Failed to capture screen: {}", e))?
+            }
+            CaptureTarget::Region { display, x, y, width, height } => {
+                let screen = Self::select_screen(*display)?;
+                screen.capture_area(*x, *y, *width, *height)
+                    .map_err(|e| anyhow!("Failed to capture region: {}", e))?
+            }
+            CaptureTarget::Window(name) => {
+                let (x, y, width, height) = find_window_geometry(name).await?;
+                let screen = Screen::from_point(x, y)
+                    .map_err(|e| anyhow!("Failed to find screen for window '{}': {}", name, e))?;
+                let info = screen.display_info;
+                screen.capture_area(x - info.x, y - info.y, width, height)
+                    .map_err(|e| anyhow!("Failed to capture window '{}': {}", name, e))?
+            }
+        };
+        Ok(DynamicImage::from(image))
+    }
+
+    /// Save a captured image as a timestamped PNG in the output directory
+    pub fn save_image(&self, image: &DynamicImage) -> Result<PathBuf> {
+        fs::create_dir_all(&self.output_dir)
+            .map_err(|e| anyhow!("Failed to create screenshot directory {}: {}", self.output_dir.display(), e))?;
+        let file_name = format!(
+            "screenshot_{}_{}.png",
+            chrono::Utc::now().format("%Y%m%d_%H%M%S"),
+            &uuid::Uuid::new_v4().to_string()[..8]
+        );
+        let path = self.output_dir.join(file_name);
+        image.save(&path).map_err(|e| anyhow!("Failed to save screenshot: {}", e))?;
+        Ok(path)
+    }
+
+    /// Ask a multimodal model `question` about a captured image
+    pub async fn ask(&self, image: &DynamicImage, question: &str) -> Result<String> {
+        let mut png = Vec::new();
+        image.write_to(&mut Cursor::new(&mut png), image::ImageOutputFormat::Png)
+            .map_err(|e| anyhow!("Failed to encode screenshot: {}", e))?;
+        let screenshot = Attachment::Image { data: png, mime_type: "image/png".to_string() };
+        let messages = vec![HashMap::from([
+            ("role".to_string(), "user".to_string()),
+            ("content".to_string(), question.to_string()),
+        ])];
+        self.vision.chat_with_images(VISION_PROMPT, messages, &[screenshot]).await
+    }
+
+    /// Capture the requested target, run detection, answer the question if one was asked
+    /// and optionally save the image
+    async fn capture_and_detect(&self, params: &HashMap<String, String>) -> Result<Capture> {
+        let target = CaptureTarget::from_params(params)?;
+        let save = params.get("save").map(|s| s == "true").unwrap_or(false);
+        let question = params.get("question").map(|s| s.trim()).filter(|s| !s.is_empty());
+
+        let screenshot = self.capture(&target).await?;
+        let objects = self.detect_objects(&screenshot).await?;
+        let answer = match question {
+            Some(question) => Some(self.ask(&screenshot, question).await?),
+            None => None,
+        };
+        let saved = if save { Some(self.save_image(&screenshot)?) } else { None };
+        Ok(Capture { objects, answer, saved })
     }
 
     pub async fn detect_objects(&self, _image: &DynamicImage) -> Result<Vec<String>> {
@@ -34,11 +193,136 @@ impl ScreenshotDetectionTool {
     }
 }
 
+/// What one call of the tool found
+struct Capture {
+    objects: Vec<String>,
+    answer: Option<String>,
+    saved: Option<PathBuf>,
+}
+
+/// Look up a window's absolute geometry (x, y, width, height) by title
+#[cfg(target_os = "linux")]
+async fn find_window_geometry(name: &str) -> Result<(i32, i32, u32, u32)> {
+    let search = TokioCommand::new("xdotool")
+        .args(["search", "--onlyvisible", "--name", name])
+        .output()
+        .await
+        .map_err(|e| anyhow!("Failed to execute xdotool (is it installed?): {}", e))?;
+    let window_id = String::from_utf8_lossy(&search.stdout)
+        .lines()
+        .next()
+        .map(|s| s.trim().to_string())
+        .filter(|s| !s.is_empty())
+        .ok_or_else(|| anyhow!("Window '{}' not found", name))?;
+
+    let geometry = TokioCommand::new("xdotool")
+        .args(["getwindowgeometry", "--shell", &window_id])
+        .output()
+        .await
+        .map_err(|e| anyhow!("Failed to get window geometry: {}", e))?;
+    if !geometry.status.success() {
+        return Err(anyhow!("Failed to get window geometry: {}", String::from_utf8_lossy(&geometry.stderr)));
+    }
+
+    let values: HashMap<String, String> = String::from_utf8_lossy(&geometry.stdout)
+        .lines()
+        .filter_map(|line| line.split_once('='))
+        .map(|(k, v)| (k.trim().to_string(), v.trim().to_string()))
+        .collect();
+    let x = parse_param::<i32>(&values, "X")?;
+    let y = parse_param::<i32>(&values, "Y")?;
+    let width = parse_param::<u32>(&values, "WIDTH")?;
+    let height = parse_param::<u32>(&values, "HEIGHT")?;
+    Ok((x, y, width, height))
+}
+
+#[cfg(not(target_os = "linux"))]
+async fn find_window_geometry(name: &str) -> Result<(i32, i32, u32, u32)> {
+    Err(anyhow!("Window capture is not supported on this platform (requested '{}')", name))
+}
+
 #[async_trait::async_trait]
 impl ToolExecutor for ScreenshotDetectionTool {
-    async fn execute(&self, _params: HashMap<String, String>) -> Result<String> {
-        let screenshot = self.capture_screen().await?;
-        let analysis_result = self.detect_objects(&screenshot).await?;
-        Ok(analysis_result.join(", "))
+    async fn execute(&self, params: HashMap<String, String>) -> Result<String> {
+        let Capture { objects, answer, saved } = self.capture_and_detect(&params).await?;
+
+        let mut result = answer.unwrap_or_else(|| objects.join(", "));
+        if let Some(path) = saved {
+            result.push_str(&format!("\nScreenshot saved to: {}", path.display()));
+        }
+        Ok(result)
+    }
+
+    async fn execute_structured(&self, params: HashMap<String, String>) -> Result<ToolOutput> {
+        let Capture { objects, answer, saved } = self.capture_and_detect(&params).await?;
+        Ok(ToolOutput::Json(serde_json::json!({
+            "objects": objects,
+            "answer": answer,
+            "file": saved.map(|path| path.display().to_string()),
+        })))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn params(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+        pairs.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect()
+    }
+
+    #[test]
+    fn test_capture_target_parsing() {
+        assert_eq!(CaptureTarget::from_params(&params(&[])).unwrap(), CaptureTarget::Display(None));
+        assert_eq!(
+            CaptureTarget::from_params(&params(&[("display", "1")])).unwrap(),
+            CaptureTarget::Display(Some(1))
+        );
+        assert_eq!(
+            CaptureTarget::from_params(&params(&[
+                ("mode", "region"), ("x", "10"), ("y", "20"), ("width", "300"), ("height", "200"),
+            ])).unwrap(),
+            CaptureTarget::Region { display: None, x: 10, y: 20, width: 300, height: 200 }
+        );
+        assert_eq!(
+            CaptureTarget::from_params(&params(&[("mode", "window"), ("window", "Firefox")])).unwrap(),
+            CaptureTarget::Window("Firefox".to_string())
+        );
+    }
+
+    #[test]
+    fn test_capture_target_validation() {
+        assert!(CaptureTarget::from_params(&params(&[("display", "first")])).is_err());
+        assert!(CaptureTarget::from_params(&params(&[("mode", "region"), ("x", "0"), ("y", "0")])).is_err());
+        assert!(CaptureTarget::from_params(&params(&[
+            ("mode", "region"), ("x", "0"), ("y", "0"), ("width", "0"), ("height", "10"),
+        ])).is_err());
+        assert!(CaptureTarget::from_params(&params(&[("mode", "window"), ("window", " ")])).is_err());
+        assert!(CaptureTarget::from_params(&params(&[("mode", "fullscreen")])).is_err());
+    }
+
+    #[test]
+    fn test_save_image() -> Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let tool = ScreenshotDetectionTool::new().with_output_dir(temp_dir.path().join("shots"));
+        let image = DynamicImage::new_rgba8(4, 4);
+
+        let path = tool.save_image(&image)?;
+        assert!(path.exists());
+        assert!(path.starts_with(temp_dir.path().join("shots")));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_questions_are_asked_with_the_capture() -> Result<()> {
+        let vision = crate::ai::MockAiProvider::new().with_default("A terminal showing a failed build");
+        let tool = ScreenshotDetectionTool::new().with_ai_client(vision.clone());
+
+        let answer = tool.ask(&DynamicImage::new_rgba8(4, 4), "What is on this screen?").await?;
+        assert_eq!(answer, "A terminal showing a failed build");
+        let request = &vision.requests()[0];
+        assert_eq!(request.last_message(), "What is on this screen?");
+        assert_eq!(request.images[0].mime_type(), Some("image/png"));
+        Ok(())
     }
 }
@@ -0,0 +1,85 @@
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+use tokio::sync::Notify;
+
+/// Cooperative cancellation signal shared between the caller and a running tool.
+///
+/// Tools that support cancellation should check `is_cancelled()` between steps or
+/// `select!` on `cancelled()` around long awaits.
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken {
+    cancelled: Arc<AtomicBool>,
+    notify: Arc<Notify>,
+}
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::SeqCst);
+        self.notify.notify_waiters();
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::SeqCst)
+    }
+
+    /// Whether `other` is a clone of this token
+    pub fn same_as(&self, other: &CancellationToken) -> bool {
+        Arc::ptr_eq(&self.cancelled, &other.cancelled)
+    }
+
+    /// Resolves once `cancel()` has been called
+    pub async fn cancelled(&self) {
+        loop {
+            let notified = self.notify.notified();
+            if self.is_cancelled() {
+                return;
+            }
+            notified.await;
+        }
+    }
+}
+
+#[derive(Debug, Clone, thiserror::Error)]
+#[error("Tool '{tool}' timed out after {} seconds", timeout.as_secs_f64())]
+pub struct ToolTimeoutError {
+    pub tool: String,
+    pub timeout: Duration,
+}
+
+#[derive(Debug, Clone, thiserror::Error)]
+#[error("Tool '{tool}' was cancelled")]
+pub struct ToolCancelledError {
+    pub tool: String,
+}
+
+/// Returns true if the error (or anything it wraps) is a tool timeout
+pub fn is_timeout(err: &anyhow::Error) -> bool {
+    err.chain().any(|cause| cause.is::<ToolTimeoutError>())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_cancellation_token() {
+        let token = CancellationToken::new();
+        assert!(!token.is_cancelled());
+
+        let waiter = {
+            let token = token.clone();
+            tokio::spawn(async move { token.cancelled().await })
+        };
+        token.cancel();
+        tokio::time::timeout(Duration::from_secs(1), waiter).await.unwrap().unwrap();
+        assert!(token.is_cancelled());
+
+        // Already-cancelled tokens resolve immediately
+        tokio::time::timeout(Duration::from_millis(10), token.cancelled()).await.unwrap();
+    }
+}
@@ -1,21 +1,83 @@
-use std::collections::HashMap;
-use std::sync::Arc;
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, OnceLock};
 use async_trait::async_trait;
 use chrono::Utc;
 use reqwest;
 use std::time::Duration;
 use futures_util::StreamExt;
-use crate::tools::ToolExecutor;
-use crate::types::{TodoTask, TaskPriority, TaskStatus, projects};
+use crate::tools::{ToolExecutor, ToolOutput};
+use crate::types::{TodoList, TodoTask, TaskPriority, TaskStatus, projects, tags};
+use crate::types::dead_letter::DeadLetterEdit;
+use crate::types::archive::ArchivePolicy;
+use crate::types::search::SearchHit;
+use crate::types::projects::ProjectRegistry;
+use crate::types::idempotency;
+use crate::types::dedup;
+use crate::types::edits::TaskEdit;
+use crate::types::history::ChangeKind;
+use crate::correlation;
+use crate::db::Database;
+use crate::ingest::{Stage, StageConfig, StageMetrics};
+use crate::types::reporting::{self, ProjectStats};
 use anyhow::{Result, anyhow};
 use serde_json::Value;
 use uuid::Uuid;
 use regex::Regex;
-use crate::ai::{AiProvider, DefaultAiClient, LocalAiClient};
+use crate::ai::{AiProvider, BudgetedAiClient, DefaultAiClient, Enhancement, FallbackAiClient, LocalAiClient, ProjectClassifier, RateLimit, RateLimitedAiClient, cassette_from_env};
 use serde::{Serialize, Deserialize};
 // use langgraph::{Graph, Node};
 
+const DEFAULT_SIMILAR_LIMIT: usize = 5;
+const DEFAULT_SIMILARITY_THRESHOLD: f32 = 0.5;
+const DEFAULT_ENHANCEMENT_SWEEP_SECS: u64 = 600;
+
 // MCP Server request/response structures
+/// A todo to add, as it arrives over MQTT or from an agent
+#[derive(Debug, Clone, Default)]
+pub struct TodoRequest {
+    pub description: String,
+    pub context: Option<String>,
+    pub target_agent: String,
+    pub project: Option<String>,
+    pub tags: Vec<String>,
+    pub allow_duplicate: bool,
+    /// Set by producers that may resend the same todo; see `types::idempotency`
+    pub idempotency_key: Option<String>,
+}
+
+/// A todo checked for duplicates and given keyword priority, project and tags, ready for
+/// the MCP server. The model enhances it once it is stored.
+#[derive(Debug)]
+pub struct PreparedTodo {
+    request: McpAddTodoRequest,
+    /// The caller's project, which enhancement keeps
+    project_requested: bool,
+    /// The caller's tags, which come before suggested ones
+    tags: Vec<String>,
+    embedded: bool,
+    idempotency_key: Option<String>,
+    /// Unset when duplicates are allowed; see `types::dedup`
+    content_hash: Option<String>,
+}
+
+/// A stored todo waiting for the model
+#[derive(Debug, Clone)]
+struct Enrichment {
+    todo_id: String,
+    description: String,
+    project_requested: bool,
+    tags: Vec<String>,
+    embedded: bool,
+    /// The request that stored it, so the model calls are logged under its correlation id
+    context: correlation::Context,
+}
+
+impl PreparedTodo {
+    pub fn description(&self) -> &str {
+        &self.request.description
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 struct McpAddTodoRequest {
     description: String,
@@ -45,6 +107,9 @@ struct McpQueryRequest {
     limit: Option<i32>,
 }
 
+/// How many todos one query to the MCP server returns at most
+const QUERY_LIMIT: i32 = 100;
+
 #[derive(Debug, Serialize, Deserialize)]
 struct McpMarkCompleteRequest {
     todo_id: String,
@@ -89,13 +154,54 @@ pub struct TodoTool {
     http_client: reqwest::Client,
     mcp_server_url: String,
     ai_client: Arc<Box<dyn AiProvider + Send + Sync>>,
+    /// Refuse to add todos at least this similar to an existing one
+    duplicate_threshold: Option<f32>,
+    /// Live project definitions; classification falls back to the built-in list without it
+    project_registry: Option<Arc<ProjectRegistry>>,
+    project_classifier: Arc<ProjectClassifier>,
+    /// Direct access to the task collection, for the search, cancel, archive and dead-letter commands
+    todo_list: Option<TodoList>,
+    /// Enhances stored todos in the background; its workers start with the first todo stored
+    enrichment: Arc<OnceLock<Arc<Stage<Enrichment>>>>,
+    /// Set once the first similarity search has started embedding older todos
+    embedding_backfill: Arc<OnceLock<()>>,
+    /// Agents todos can be reassigned to; the built-in agents when unset
+    known_agents: Option<HashSet<String>>,
 }
 
 impl TodoTool {
     pub async fn new() -> Result<Self> {
         let mcp_server_url = std::env::var("MCP_SERVER_URL")
             .unwrap_or_else(|_| "http://localhost:8000".to_string());
+        let tool = Self::with_server_url(mcp_server_url)?;
 
+        if crate::secrets::get(crate::secrets::MONGO_URI).is_none() {
+            return Ok(tool);
+        }
+        match Database::shared().await {
+            Ok(db) => Ok(tool.with_database(&db).await),
+            Err(e) => {
+                tracing::warn!("Using built-in projects; search, cancel, archive and dead-letter commands disabled: {}", e);
+                Ok(tool)
+            }
+        }
+    }
+
+    /// Classify against the projects in `db` and run the commands that need the task collection
+    pub async fn with_database(mut self, db: &Database) -> Self {
+        let todo_list = TodoList::from_database(db).await;
+        // Classify with the corrections reviewers have made
+        if let Err(e) = todo_list.load_examples(self.project_classifier.examples()).await {
+            tracing::debug!("Classifying without corrected examples: {}", e);
+        }
+        self.project_registry = Some(Arc::new(ProjectRegistry::from_database(db)));
+        self.todo_list = Some(todo_list);
+        self
+    }
+
+    /// A tool talking to the MCP server at `mcp_server_url`, classifying against the
+    /// built-in project list
+    pub fn with_server_url(mcp_server_url: impl Into<String>) -> Result<Self> {
         let http_client = reqwest::Client::builder()
             .timeout(Duration::from_secs(30))
             .build()
@@ -103,11 +209,167 @@ impl TodoTool {
 
         Ok(Self {
             http_client,
-            mcp_server_url,
-            ai_client: Arc::new(Box::new(DefaultAiClient::new())),
+            mcp_server_url: mcp_server_url.into(),
+            // Bulk intake enhances every todo, so keep it from swamping the model,
+            // and keep accepting todos when the model is down
+            ai_client: Arc::new(cassette_from_env(RateLimitedAiClient::new(
+                BudgetedAiClient::new("todo", FallbackAiClient::default_chain()),
+                RateLimit::from_env("AI"),
+            ))),
+            duplicate_threshold: std::env::var("TODO_DUPLICATE_THRESHOLD").ok().and_then(|s| s.parse().ok()),
+            project_registry: None,
+            project_classifier: Arc::new(ProjectClassifier::default()),
+            todo_list: None,
+            enrichment: Arc::new(OnceLock::new()),
+            embedding_backfill: Arc::new(OnceLock::new()),
+            known_agents: None,
         })
     }
 
+    pub fn with_todo_list(mut self, todo_list: TodoList) -> Self {
+        self.todo_list = Some(todo_list);
+        self
+    }
+
+    /// Limit reassignment to `agents`, e.g. the ones registered with the server
+    pub fn with_known_agents<I, S>(mut self, agents: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.known_agents = Some(agents.into_iter().map(Into::into).collect());
+        self
+    }
+
+    fn agent_exists(&self, name: &str) -> bool {
+        match &self.known_agents {
+            Some(agents) => agents.contains(name),
+            None => crate::agents::default_agents().iter().any(|config| config.name == name),
+        }
+    }
+
+    /// Apply `edit` to a todo on the MCP server, keeping the change in its history
+    async fn edit(&self, params: &HashMap<String, String>, edit: TaskEdit) -> Result<String> {
+        let id = params.get("todo_id").ok_or_else(|| anyhow!("Missing todo_id"))?;
+        edit.validate(|name| self.agent_exists(name))?;
+        let mut todo = self.call_mcp_get_todo(id).await?;
+        let Some(change) = edit.apply(&mut todo, "todo_tool") else {
+            return Ok(format!("Todo {} unchanged", id));
+        };
+        let mut updates = HashMap::new();
+        for field in &change.fields {
+            let value = match field.field.as_str() {
+                "description" => Value::from(todo.description.clone()),
+                "priority" => Value::from(mcp_priority(&todo.priority)),
+                "project" => Value::from(Self::normalize_project_name(todo.project.as_deref().unwrap_or_default())),
+                _ => Value::from(todo.target_agent.clone()),
+            };
+            updates.insert(field.field.clone(), value);
+        }
+        updates.insert("history".to_string(), serde_json::to_value(&todo.history)?);
+        updates.insert("updated_at".to_string(), Value::from(change.at));
+        self.call_mcp_update_todo(id, updates).await?;
+        Ok(match change.kind {
+            ChangeKind::Reassigned => format!("Reassigned todo {} to {}", id, todo.target_agent),
+            ChangeKind::Edited => format!("Updated todo {}: {}", id, todo.description),
+            _ => format!("Updated todo {}", id),
+        })
+    }
+
+    fn task_store(&self) -> Result<&TodoList> {
+        self.todo_list.as_ref().ok_or_else(|| anyhow!("This command needs MongoDB (RTK_MONGO_URI)"))
+    }
+
+    async fn search(&self, query: &str, params: &HashMap<String, String>) -> Result<Vec<SearchHit>> {
+        let limit = match params.get("limit") {
+            Some(limit) => limit.parse().map_err(|_| anyhow!("Invalid limit: {}", limit))?,
+            None => 10,
+        };
+        let tags = params.get("tags").map(|list| tags::parse_tags(list)).unwrap_or_default();
+        Ok(self.task_store()?.search(query, &tags, limit).await?)
+    }
+
+    /// Archive finished todos now rather than waiting for the background job
+    async fn archive_now(&self, params: &HashMap<String, String>) -> Result<String> {
+        let policy = match params.get("older_than_days") {
+            Some(days) => ArchivePolicy { after_days: days.parse().map_err(|_| anyhow!("Invalid older_than_days: {}", days))? },
+            None => ArchivePolicy::from_env().unwrap_or_default(),
+        };
+        let archived = self.task_store()?.archive_finished(policy.cutoff(Utc::now().timestamp())).await?;
+        Ok(format!("Archived {} todos finished more than {} days ago", archived, policy.after_days))
+    }
+
+    async fn list_archived(&self) -> Result<String> {
+        let archived = self.task_store()?.archived_tasks(Some(50)).await?;
+        let mut output = String::from("Archived todos:\n");
+        for entry in archived {
+            output.push_str(&format!("- {} ({:?})\n", entry.task.description, entry.task.status));
+        }
+        Ok(output)
+    }
+
+    async fn cancel(&self, params: &HashMap<String, String>) -> Result<String> {
+        let id = params.get("todo_id").ok_or_else(|| anyhow!("Missing todo_id"))?;
+        match self.task_store()?.cancel_task(id, "todo_tool").await? {
+            Some(task) => Ok(format!("Cancelled todo {}: {}", task.id, task.description)),
+            None => Err(anyhow!("No unfinished todo with id {}", id)),
+        }
+    }
+
+    async fn list_dead_letters(&self) -> Result<String> {
+        let dead_letters = self.task_store()?.dead_letters(50).await?;
+        if dead_letters.is_empty() {
+            return Ok("No dead-lettered todos.".to_string());
+        }
+        let mut output = String::from("Dead-lettered todos:\n");
+        for dead_letter in dead_letters {
+            let task = &dead_letter.task;
+            output.push_str(&format!(
+                "- {} [{}] {} ({} attempts, last: {})\n",
+                task.id,
+                task.target_agent,
+                task.description,
+                task.failures.len(),
+                task.failure_reason.as_deref().unwrap_or("unknown"),
+            ));
+        }
+        Ok(output)
+    }
+
+    /// Requeue a dead-lettered todo, applying any edits in `params`
+    async fn requeue(&self, params: &HashMap<String, String>) -> Result<String> {
+        let id = params.get("todo_id").ok_or_else(|| anyhow!("Missing todo_id"))?;
+        let edit = DeadLetterEdit {
+            description: params.get("description").cloned(),
+            priority: priority_param(params)?,
+            target_agent: params.get("target_agent").cloned(),
+            notes: params.get("notes").cloned(),
+        };
+        let task = self.task_store()?.requeue_dead_letter(id, edit, "todo_tool").await?
+            .ok_or_else(|| anyhow!("No dead-lettered todo with id {}", id))?;
+        Ok(format!("Requeued todo {} for {}", task.id, task.target_agent))
+    }
+
+    pub fn with_project_registry(mut self, registry: Arc<ProjectRegistry>) -> Self {
+        self.project_registry = Some(registry);
+        self
+    }
+
+    /// Load the active projects from the registry into the classifier
+    async fn refresh_projects(&self) {
+        let Some(registry) = &self.project_registry else { return };
+        match registry.classification_projects().await {
+            Ok(projects) if !projects.is_empty() => self.project_classifier.update_projects(projects).await,
+            Ok(_) => tracing::debug!("Project registry is empty, keeping current projects"),
+            Err(e) => tracing::warn!("Failed to load projects from registry: {}", e),
+        }
+    }
+
+    pub fn with_duplicate_threshold(mut self, threshold: f32) -> Self {
+        self.duplicate_threshold = Some(threshold);
+        self
+    }
+
     pub fn with_ai_client<T: AiProvider + Send + Sync + 'static>(mut self, client: T) -> Self {
         self.ai_client = Arc::new(Box::new(client));
         self
@@ -188,7 +450,7 @@ impl TodoTool {
         let request_body = McpQueryRequest {
             query_or_filter: filter,
             fields_or_projection: None,
-            limit: Some(100),
+            limit: Some(QUERY_LIMIT),
         };
 
         let response = self.http_client
@@ -217,7 +479,11 @@ impl TodoTool {
                 if let Some(data) = mcp_response.get("data") {
                     // Parse the todos from the response data
                     if let Some(items) = data.get("items") {
-                        let todos: Vec<TodoTask> = serde_json::from_value(items.clone())
+                        let mut items = items.clone();
+                        if let Some(items) = items.as_array_mut() {
+                            items.iter_mut().for_each(lift_metadata);
+                        }
+                        let todos: Vec<TodoTask> = serde_json::from_value(items)
                             .unwrap_or_else(|_| Vec::new());
                         Ok(todos)
                     } else {
@@ -363,72 +629,326 @@ impl TodoTool {
     }
 
     async fn predict_project(&self, description: &str) -> Result<String> {
-        let (_, _, project) = crate::ai::enhance_todo_description(
-            description,
-            self.ai_client.as_ref().as_ref()
-        ).await?;
-
-        Ok(project)
+        self.refresh_projects().await;
+        crate::ai::predict_project_with(description, self.ai_client.as_ref().as_ref(), &self.project_classifier).await
     }
 
-    async fn enhance_with_ai(&self, description: &str) -> Result<(String, TaskPriority, String)> {
+    /// The enhanced description, priority, project, suggested tags and confidence for a todo
+    async fn enhance_with_ai(&self, description: &str) -> Result<Enhancement> {
         tracing::debug!("Enhancing todo description with AI: {}", description);
 
         // Use the shared enhancement function
-        crate::ai::enhance_todo_description(description, self.ai_client.as_ref().as_ref()).await
+        self.refresh_projects().await;
+        crate::ai::enhance_task_with(description, self.ai_client.as_ref().as_ref(), &self.project_classifier).await
     }
 
-    async fn add_todo(&self, description: &str, context: Option<&str>, target_agent: &str, project: Option<&str>) -> Result<String> {
-        tracing::debug!("Adding new todo - Description: {}, Context: {:?}, Target Agent: {}, Project: {:?}", description, context, target_agent, project);
+    /// Rank existing todos by similarity to the query embedding. Only todos with a stored
+    /// embedding are ranked; the first search starts embedding the rest in the background.
+    async fn find_similar_to(&self, query: &[f32], min_similarity: f32, limit: usize) -> Result<Vec<(TodoTask, f32)>> {
+        self.start_embedding_backfill();
+        let candidates = self.call_mcp_query_todos(Some(embedding_filter(true))).await?
+            .into_iter()
+            .filter_map(|todo| todo.embedding.clone().map(|embedding| (todo, embedding)))
+            .collect();
+        Ok(crate::ai::rank_by_similarity(query, candidates, min_similarity, limit))
+    }
 
-        // Try to enhance the description with AI, fallback to original if enhancement fails
-        tracing::debug!("Attempting AI enhancement..");
-        let (enhanced_description, priority, predicted_project) = match self.enhance_with_ai(description).await {
-            Ok((desc, prio, proj)) => {
-                tracing::debug!("AI enhancement successful!");
-                (desc, prio, proj)
-            },
-            Err(e) => {
-                tracing::warn!("Failed to enhance todo with AI: {}", e);
-                tracing::debug!("Using original description with medium priority");
-                (description.to_string(), TaskPriority::Medium, projects::get_default_project().to_string())
+    /// Embed older todos in the background, once per tool
+    fn start_embedding_backfill(&self) {
+        self.embedding_backfill.get_or_init(|| {
+            let tool = self.clone();
+            tokio::spawn(async move {
+                match tool.backfill_embeddings().await {
+                    Ok(0) => {}
+                    Ok(count) => tracing::info!("Embedded {} todo(s) stored without an embedding", count),
+                    Err(e) => tracing::warn!("Stopped embedding todos stored without an embedding: {}", e),
+                }
+            });
+        });
+    }
+
+    /// Embed todos stored without an embedding, e.g. by other clients or while the model
+    /// was down, returning how many were embedded
+    pub async fn backfill_embeddings(&self) -> Result<usize> {
+        let mut embedded = 0;
+        loop {
+            let todos = self.call_mcp_query_todos(Some(embedding_filter(false))).await?;
+            let mut progressed = false;
+            for todo in todos.iter().filter(|todo| todo.embedding.is_none()) {
+                match self.ai_client.embed(&todo.description).await {
+                    Ok(embedding) => {
+                        let updates = HashMap::from([("metadata.embedding".to_string(), serde_json::to_value(embedding)?)]);
+                        self.call_mcp_update_todo(&todo.id, updates).await?;
+                        embedded += 1;
+                        progressed = true;
+                    }
+                    Err(e) => tracing::debug!("Left todo {} without an embedding: {}", todo.id, e),
+                }
+            }
+            // A short page was the last one; without progress the next would be the same
+            if !progressed || todos.len() < QUERY_LIMIT as usize {
+                return Ok(embedded);
             }
+        }
+    }
+
+    /// The todos known to the MCP server, optionally for one project
+    async fn report_todos(&self, project: Option<&str>) -> Result<Vec<TodoTask>> {
+        let filter = project.map(|p| serde_json::json!({ "project": p }).to_string());
+        self.call_mcp_query_todos(filter).await
+    }
+
+    /// Per-project statistics over the todos known to the MCP server
+    async fn project_report(&self, project: Option<&str>) -> Result<Vec<ProjectStats>> {
+        Ok(reporting::stats_from_tasks(&self.report_todos(project).await?))
+    }
+
+    async fn find_similar(&self, description: &str, min_similarity: f32, limit: usize) -> Result<Vec<(TodoTask, f32)>> {
+        let query = self.ai_client.embed(description).await
+            .map_err(|e| anyhow!("Failed to embed todo description: {}", e))?;
+        self.find_similar_to(&query, min_similarity, limit).await
+    }
+
+    async fn add_todo(&self, request: TodoRequest) -> Result<String> {
+        let prepared = self.prepare_todo(request).await?;
+        self.store_todo(prepared).await
+    }
+
+    /// Refuse near-duplicates and give a todo keyword priority, project and tags, leaving the
+    /// model's enhancement for after it is stored. Only the duplicate check waits on the model,
+    /// for an embedding, and only with a duplicate threshold set.
+    pub async fn prepare_todo(&self, request: TodoRequest) -> Result<PreparedTodo> {
+        let TodoRequest { description, context, target_agent, project, tags, allow_duplicate, idempotency_key } = request;
+        let idempotency_key = match idempotency_key {
+            Some(key) => idempotency::normalize_key(&key)?,
+            None => None,
         };
+        tracing::debug!("Adding new todo - Description: {}, Context: {:?}, Target Agent: {}, Project: {:?}", description, context, target_agent, project);
 
-        // Use the provided project if available, otherwise use the predicted one
-        let final_project = project.map(|p| p.to_string()).unwrap_or(predicted_project);
-        let normalized_project = Self::normalize_project_name(&final_project);
-
-        // Convert priority to string for MCP call
-        let priority_str = match priority {
-            TaskPriority::Low => "Low",
-            TaskPriority::Medium => "Medium",
-            TaskPriority::High => "High",
-            TaskPriority::Critical => "High", // Map Critical to High for MCP
-            TaskPriority::Inital => "Medium", // Map Inital to Medium for MCP
+        let mut embedding = None;
+        if let (Some(threshold), false) = (self.duplicate_threshold, allow_duplicate) {
+            match self.ai_client.embed(&description).await {
+                Ok(query) => {
+                    if let Some((existing, score)) = self.find_similar_to(&query, threshold, 1).await?.into_iter().next() {
+                        return Err(anyhow!(
+                            "Refusing to add near-duplicate of existing todo '{}' (similarity {:.2})",
+                            existing.description, score
+                        ));
+                    }
+                    embedding = Some(query);
+                }
+                Err(e) => tracing::debug!("Adding todo without a duplicate check: {}", e),
+            }
+        }
+
+        let content_hash = (!allow_duplicate).then(|| dedup::content_hash(&description, project.as_deref(), &target_agent));
+        let project_requested = project.is_some();
+        let project = match project {
+            Some(project) => project,
+            None => {
+                self.refresh_projects().await;
+                crate::ai::offline::project(&description, &self.project_classifier.projects().await)
+                    .unwrap_or_else(|| projects::get_default_project().to_string())
+            }
         };
+        // Tags the caller gave come first, so they survive the cap
+        let keyword_tags = crate::ai::offline::tags(&description);
+        let all_tags = tags::normalize_tags(tags.iter().chain(&keyword_tags));
 
         // Create metadata with source information
         let mut metadata = HashMap::new();
         metadata.insert("source".to_string(), serde_json::Value::String("swarmonomicon_agent".to_string()));
         metadata.insert("created_via".to_string(), serde_json::Value::String("swarmonomicon_todo_tool".to_string()));
         if let Some(ctx) = context {
-            metadata.insert("context".to_string(), serde_json::Value::String(ctx.to_string()));
+            metadata.insert("context".to_string(), serde_json::Value::String(ctx));
+        }
+        metadata.insert("enhancement".to_string(), serde_json::json!("unenhanced"));
+        // So a later sweep knows to keep the project when it enhances the todo
+        if project_requested {
+            metadata.insert("project_requested".to_string(), serde_json::json!(true));
+        }
+        if let Some(key) = &idempotency_key {
+            metadata.insert("idempotency_key".to_string(), serde_json::json!(key));
+        }
+        if let Some(hash) = &content_hash {
+            metadata.insert("content_hash".to_string(), serde_json::json!(hash));
+        }
+        let embedded = embedding.is_some();
+        if let Some(embedding) = embedding {
+            metadata.insert("embedding".to_string(), serde_json::to_value(embedding)?);
+        }
+        if !all_tags.is_empty() {
+            metadata.insert("tags".to_string(), serde_json::to_value(&all_tags)?);
         }
-        metadata.insert("enhanced_description".to_string(), serde_json::Value::String(enhanced_description));
 
+        Ok(PreparedTodo {
+            request: McpAddTodoRequest {
+                priority: mcp_priority(&crate::ai::offline::priority(&description)).to_string(),
+                description,
+                project: Self::normalize_project_name(&project),
+                target_agent,
+                metadata: Some(metadata),
+            },
+            project_requested,
+            tags: tags::normalize_tags(tags),
+            embedded,
+            idempotency_key,
+            content_hash,
+        })
+    }
+
+    /// Add a prepared todo through the MCP server, queueing it for enhancement. A todo whose
+    /// idempotency key the server already has, or identical to one added within the dedup
+    /// window, isn't added again; the response names the original instead.
+    pub async fn store_todo(&self, prepared: PreparedTodo) -> Result<String> {
+        let PreparedTodo { request, project_requested, tags, embedded, idempotency_key, content_hash } = prepared;
+        // The MCP server has no unique index to lean on, so these lookups narrow the window
+        // for duplicates rather than closing it
+        let mut filters = Vec::new();
+        if let Some(key) = &idempotency_key {
+            filters.push(serde_json::json!({ "metadata.idempotency_key": key }));
+        }
+        if let (Some(hash), Some(window)) = (&content_hash, dedup::window_from_env()) {
+            let since = Utc::now().timestamp() - window;
+            filters.push(serde_json::json!({ "metadata.content_hash": hash, "created_at": { "$gte": since } }));
+        }
+        for filter in filters {
+            if let Some(existing) = self.call_mcp_query_todos(Some(filter.to_string())).await?.into_iter().next() {
+                tracing::info!("Todo '{}' already added as {}", existing.description, existing.id);
+                return Ok(serde_json::json!({
+                    "success": true,
+                    "message": "Todo already added",
+                    "data": { "todo_id": existing.id, "duplicate": true },
+                }).to_string());
+            }
+        }
         tracing::debug!("Calling MCP server to add todo");
-        self.call_mcp_add_todo(
-            description.to_string(),
-            normalized_project,
-            priority_str.to_string(),
-            target_agent.to_string(),
-            Some(metadata)
-        ).await
+        let McpAddTodoRequest { description, project, priority, target_agent, metadata } = request;
+        let response = self.call_mcp_add_todo(description.clone(), project, priority, target_agent, metadata).await?;
+
+        match created_todo_id(&response) {
+            Some(todo_id) => {
+                let job = Enrichment { todo_id, description, project_requested, tags, embedded, context: correlation::Context::capture() };
+                match self.enrichment().push(job).await {
+                    Ok(Some(dropped)) => tracing::warn!("Enhancement queue full, todo {} stays unenhanced", dropped.todo_id),
+                    Ok(None) => {}
+                    Err(e) => tracing::warn!("Todo stored but not queued for enhancement: {}", e),
+                }
+            }
+            None => tracing::warn!("No todo id in the MCP response, todo '{}' stays unenhanced", description),
+        }
+        Ok(response)
     }
 
-    async fn list_todos(&self) -> Result<String> {
-        let todos = self.call_mcp_query_todos(None).await?;
+    /// The background enhancement queue, starting its workers on first use
+    fn enrichment(&self) -> &Arc<Stage<Enrichment>> {
+        self.enrichment.get_or_init(|| {
+            let config = StageConfig::from_env("enrich", StageConfig::default()).unwrap_or_else(|e| {
+                tracing::warn!("Ignoring invalid enrichment settings: {}", e);
+                StageConfig::default()
+            });
+            let stage = Arc::new(Stage::<Enrichment>::new("enrich", config));
+            for _ in 0..config.workers {
+                let (tool, stage) = (self.clone(), stage.clone());
+                tokio::spawn(async move {
+                    while let Some(job) = stage.pop().await {
+                        let result = job.context.clone().run(tool.enrich(&job)).await;
+                        if let Err(e) = &result {
+                            tracing::warn!("Todo {} keeps its keyword enhancement: {}", job.todo_id, e);
+                        }
+                        stage.record(result.is_ok());
+                    }
+                });
+            }
+            stage
+        })
+    }
+
+    /// Queue todos still marked `unenhanced` that were created before `created_before`, e.g.
+    /// by a process that stopped before enhancing them, returning how many were queued. At
+    /// most one page of each kind is queued per call; the rest wait for the next sweep.
+    pub async fn requeue_unenhanced(&self, created_before: i64) -> Result<usize> {
+        let mut queued = 0;
+        for project_requested in [true, false] {
+            let requested = if project_requested { serde_json::json!(true) } else { serde_json::json!({ "$ne": true }) };
+            let filter = serde_json::json!({
+                "metadata.enhancement": "unenhanced",
+                "metadata.project_requested": requested,
+                "created_at": { "$lt": created_before },
+            });
+            for todo in self.call_mcp_query_todos(Some(filter.to_string())).await? {
+                let job = Enrichment {
+                    todo_id: todo.id,
+                    description: todo.description,
+                    project_requested,
+                    tags: todo.tags,
+                    embedded: todo.embedding.is_some(),
+                    context: correlation::Context::capture(),
+                };
+                match self.enrichment().push(job).await {
+                    Ok(Some(dropped)) => tracing::debug!("Enhancement queue full, todo {} waits for the next sweep", dropped.todo_id),
+                    Ok(None) => queued += 1,
+                    Err(e) => return Err(anyhow!("Could not queue unenhanced todos: {}", e)),
+                }
+            }
+        }
+        Ok(queued)
+    }
+
+    /// Queue unenhanced todos now and then every `interval`. Todos younger than `interval`
+    /// are left to the queue they went into when they were stored. Only the first call in a
+    /// process starts a sweep.
+    pub fn start_enhancement_sweep(&self, interval: Duration) {
+        static STARTED: OnceLock<()> = OnceLock::new();
+        STARTED.get_or_init(|| {
+            let tool = self.clone();
+            tokio::spawn(async move {
+                let mut created_before = Utc::now().timestamp();
+                let mut ticker = tokio::time::interval(interval);
+                loop {
+                    ticker.tick().await;
+                    match tool.requeue_unenhanced(created_before).await {
+                        Ok(0) => {}
+                        Ok(count) => tracing::info!("Queued {} todo(s) left unenhanced for enhancement", count),
+                        Err(e) => tracing::debug!("Could not sweep unenhanced todos: {}", e),
+                    }
+                    created_before = Utc::now().timestamp() - interval.as_secs() as i64;
+                }
+            });
+        });
+    }
+
+    /// Wait until every todo queued so far has been enhanced or given up on
+    pub async fn enrichment_settled(&self) {
+        if let Some(stage) = self.enrichment.get() {
+            stage.settled().await;
+        }
+    }
+
+    /// The background enhancement queue's depth and counts, once a todo has been stored
+    pub fn enrichment_metrics(&self) -> Option<StageMetrics> {
+        self.enrichment.get().map(|stage| stage.metrics())
+    }
+
+    /// Enhance a stored todo with the model and update it on the MCP server
+    async fn enrich(&self, job: &Enrichment) -> Result<String> {
+        let enhancement = self.enhance_with_ai(&job.description).await?;
+        let mut updates = enhancement_updates(enhancement, job);
+        if !job.embedded {
+            match self.ai_client.embed(&job.description).await {
+                Ok(embedding) => {
+                    updates.insert("metadata.embedding".to_string(), serde_json::to_value(embedding)?);
+                }
+                Err(e) => tracing::debug!("Enhanced todo {} without an embedding: {}", job.todo_id, e),
+            }
+        }
+        self.call_mcp_update_todo(&job.todo_id, updates).await
+    }
+
+    /// Todos that carry all of `wanted_tags`
+    async fn list_todos(&self, wanted_tags: &[String]) -> Result<String> {
+        let mut todos = self.call_mcp_query_todos(None).await?;
+        todos.retain(|todo| tags::has_all(&todo.tags, wanted_tags));
 
         if todos.is_empty() {
             return Ok("No todos found.".to_string());
@@ -436,7 +956,11 @@ impl TodoTool {
 
         let mut output = String::from("Current todos:\n");
         for todo in todos {
-            output.push_str(&format!("- {} ({:?})\n", todo.description, todo.status));
+            output.push_str(&format!("- {} ({:?})", todo.description, todo.status));
+            if !todo.tags.is_empty() {
+                output.push_str(&format!(" [{}]", todo.tags.join(", ")));
+            }
+            output.push('\n');
         }
 
         Ok(output)
@@ -468,6 +992,89 @@ impl TodoTool {
     }
 }
 
+/// How often to sweep for unenhanced todos, from `TODO_ENHANCEMENT_SWEEP_SECS`
+pub fn enhancement_sweep_interval() -> Duration {
+    let secs = std::env::var("TODO_ENHANCEMENT_SWEEP_SECS").ok()
+        .and_then(|s| s.parse().ok())
+        .filter(|&secs| secs > 0)
+        .unwrap_or(DEFAULT_ENHANCEMENT_SWEEP_SECS);
+    Duration::from_secs(secs)
+}
+
+/// The MCP server's name for `priority`, which has no critical or initial levels
+fn mcp_priority(priority: &TaskPriority) -> &'static str {
+    match priority {
+        TaskPriority::Low => "Low",
+        TaskPriority::Medium => "Medium",
+        TaskPriority::High | TaskPriority::Critical => "High",
+        TaskPriority::Inital => "Medium",
+    }
+}
+
+/// The id the MCP server gave a todo it added
+fn created_todo_id(response: &str) -> Option<String> {
+    let response: Value = serde_json::from_str(response).ok()?;
+    ["/data/todo_id", "/data/id", "/todo_id", "/id"].iter()
+        .find_map(|pointer| response.pointer(pointer).and_then(Value::as_str))
+        .map(str::to_string)
+}
+
+/// The fields enhancement sets on a stored todo, keeping the caller's project and tags
+fn enhancement_updates(enhancement: Enhancement, job: &Enrichment) -> HashMap<String, Value> {
+    let Enhancement { enhanced_description, priority, project, tags: suggested_tags, confidence } = enhancement;
+    let mut updates = HashMap::new();
+    updates.insert("priority".to_string(), Value::from(mcp_priority(&priority)));
+    if !job.project_requested {
+        updates.insert("project".to_string(), Value::from(TodoTool::normalize_project_name(&project)));
+    }
+    let tags = tags::normalize_tags(job.tags.iter().chain(&suggested_tags));
+    updates.insert("metadata.tags".to_string(), serde_json::json!(tags));
+    updates.insert("metadata.enhanced_description".to_string(), Value::from(enhanced_description));
+    updates.insert("metadata.confidence".to_string(), serde_json::json!(confidence));
+    updates.insert("metadata.enhancement".to_string(), Value::from("enhanced"));
+    updates.insert("updated_at".to_string(), Value::from(Utc::now().timestamp()));
+    updates
+}
+
+fn priority_param(params: &HashMap<String, String>) -> Result<Option<TaskPriority>> {
+    match params.get("priority") {
+        Some(p) => Ok(Some(serde_json::from_value(Value::String(p.clone()))
+            .map_err(|_| anyhow!("Invalid priority: {}", p))?)),
+        None => Ok(None),
+    }
+}
+
+fn similarity_params(params: &HashMap<String, String>) -> Result<(f32, usize)> {
+    let threshold = match params.get("threshold") {
+        Some(t) => t.parse::<f32>().map_err(|_| anyhow!("Invalid threshold: {}", t))?,
+        None => DEFAULT_SIMILARITY_THRESHOLD,
+    };
+    let limit = match params.get("limit") {
+        Some(l) => l.parse::<usize>().map_err(|_| anyhow!("Invalid limit: {}", l))?,
+        None => DEFAULT_SIMILAR_LIMIT,
+    };
+    Ok((threshold, limit))
+}
+
+/// The MCP server keeps tags and embeddings in a todo's metadata, where `add_todo` puts them
+fn lift_metadata(item: &mut Value) {
+    for field in ["tags", "embedding"] {
+        if item.get(field).is_some() {
+            continue;
+        }
+        if let Some(value) = item.pointer(&format!("/metadata/{}", field)).cloned() {
+            if let Some(item) = item.as_object_mut() {
+                item.insert(field.to_string(), value);
+            }
+        }
+    }
+}
+
+/// A query for todos with or without a stored embedding
+fn embedding_filter(exists: bool) -> String {
+    serde_json::json!({ "metadata.embedding": { "$exists": exists } }).to_string()
+}
+
 #[async_trait]
 impl ToolExecutor for TodoTool {
     async fn execute(&self, params: HashMap<String, String>) -> Result<String> {
@@ -481,12 +1088,50 @@ impl ToolExecutor for TodoTool {
                 let default_agent = "user".to_string();
                 let target_agent = params.get("target_agent").unwrap_or(&default_agent);
                 let project = params.get("project").map(|s| s.as_str());
+                let allow_duplicate = params.get("allow_duplicate").map(|s| s == "true").unwrap_or(false);
+                let tags = params.get("tags").map(|list| tags::parse_tags(list)).unwrap_or_default();
+                let idempotency_key = params.get("idempotency_key").cloned();
                 tracing::debug!("Adding todo - Description: {}, Context: {:?}, Target Agent: {}, Project: {:?}", description, context, target_agent, project);
-                self.add_todo(description, context, target_agent, project).await
+                self.add_todo(TodoRequest {
+                    description: description.to_string(),
+                    context: context.map(str::to_string),
+                    target_agent: target_agent.to_string(),
+                    project: project.map(str::to_string),
+                    tags,
+                    allow_duplicate,
+                    idempotency_key,
+                }).await
+            }
+            "report" => {
+                let todos = self.report_todos(params.get("project").map(|s| s.as_str())).await?;
+                let mut output = reporting::format_report(&reporting::stats_from_tasks(&todos));
+                let tag_stats = reporting::tag_stats_from_tasks(&todos);
+                if !tag_stats.is_empty() {
+                    output.push_str(&reporting::format_tag_report(&tag_stats));
+                }
+                Ok(output)
+            }
+            "similar" => {
+                let description = params.get("description").ok_or_else(|| anyhow!("Missing todo description"))?;
+                let (threshold, limit) = similarity_params(&params)?;
+                let similar = self.find_similar(description, threshold, limit).await?;
+                if similar.is_empty() {
+                    return Ok("No similar todos found.".to_string());
+                }
+                let mut output = String::from("Similar todos:\n");
+                for (todo, score) in similar {
+                    output.push_str(&format!("- {} ({:?}, similarity {:.2})\n", todo.description, todo.status, score));
+                }
+                Ok(output)
             }
             "list" => {
                 tracing::debug!("Listing todos");
-                self.list_todos().await
+                let wanted_tags = params.get("tags").map(|list| tags::parse_tags(list)).unwrap_or_default();
+                let mut output = self.list_todos(&wanted_tags).await?;
+                if params.get("include_archived").map(|s| s == "true").unwrap_or(false) {
+                    output.push_str(&self.list_archived().await?);
+                }
+                Ok(output)
             }
             "complete" => {
                 let description = params.get("description").ok_or_else(|| anyhow!("Missing todo description"))?;
@@ -498,18 +1143,87 @@ impl ToolExecutor for TodoTool {
                 tracing::debug!("Marking todo as failed: {}", description);
                 self.update_todo_status(description, TaskStatus::Failed).await
             }
+            "search" => {
+                let query = params.get("query").ok_or_else(|| anyhow!("Missing search query"))?;
+                let hits = self.search(query, &params).await?;
+                if hits.is_empty() {
+                    return Ok(format!("No todos match \"{}\".", query));
+                }
+                let mut output = format!("Todos matching \"{}\":\n", query);
+                for hit in hits {
+                    let text = hit.highlights.get("description")
+                        .or_else(|| hit.highlights.values().next())
+                        .unwrap_or(&hit.task.description);
+                    output.push_str(&format!("- {} ({:?}, {}, score {:.2})\n", text, hit.task.status, hit.task.id, hit.score));
+                }
+                Ok(output)
+            }
+            "cancel" => self.cancel(&params).await,
+            "archive" => self.archive_now(&params).await,
+            "dead_letters" => self.list_dead_letters().await,
+            "requeue" => self.requeue(&params).await,
+            "update" => {
+                let edit = TaskEdit {
+                    description: params.get("description").cloned(),
+                    priority: priority_param(&params)?,
+                    project: params.get("project").cloned(),
+                    target_agent: params.get("target_agent").cloned(),
+                };
+                self.edit(&params, edit).await
+            }
+            "reassign" => {
+                let target_agent = params.get("target_agent").ok_or_else(|| anyhow!("Missing target_agent"))?;
+                self.edit(&params, TaskEdit::reassign(target_agent.clone())).await
+            }
             _ => {
                 tracing::error!("Unknown todo command: {}", command);
                 Err(anyhow!("Unknown todo command"))
             },
         }
     }
+
+    async fn execute_structured(&self, params: HashMap<String, String>) -> Result<ToolOutput> {
+        match params.get("command").map(|s| s.as_str()) {
+            Some("list") => {
+                let todos = self.call_mcp_query_todos(None).await?;
+                Ok(ToolOutput::Json(serde_json::to_value(todos)?))
+            }
+            Some("report") => {
+                let stats = self.project_report(params.get("project").map(|s| s.as_str())).await?;
+                Ok(ToolOutput::Json(serde_json::to_value(stats)?))
+            }
+            Some("similar") => {
+                let description = params.get("description").ok_or_else(|| anyhow!("Missing todo description"))?;
+                let (threshold, limit) = similarity_params(&params)?;
+                let similar: Vec<Value> = self.find_similar(description, threshold, limit).await?
+                    .into_iter()
+                    .map(|(mut todo, score)| {
+                        todo.embedding = None;
+                        serde_json::json!({ "todo": todo, "similarity": score })
+                    })
+                    .collect();
+                Ok(ToolOutput::Json(Value::Array(similar)))
+            }
+            Some("search") => {
+                let query = params.get("query").ok_or_else(|| anyhow!("Missing search query"))?;
+                let hits = self.search(query, &params).await?;
+                Ok(ToolOutput::Json(serde_json::to_value(hits)?))
+            }
+            Some("dead_letters") => {
+                let dead_letters = self.task_store()?.dead_letters(50).await?;
+                Ok(ToolOutput::Json(serde_json::to_value(dead_letters)?))
+            }
+            // MCP responses are JSON bodies; keep them typed rather than flattened
+            _ => self.execute(params).await.map(ToolOutput::from_response),
+        }
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::ai::DefaultAiClient;
+    use crate::ai::{DefaultAiClient, MockAiProvider};
+    use crate::testsupport::{self, MongoFixture};
 
     #[tokio::test]
     async fn test_todo_operations() -> Result<()> {
@@ -557,6 +1271,21 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_similarity_params() {
+        let defaults = similarity_params(&HashMap::new()).unwrap();
+        assert_eq!(defaults, (DEFAULT_SIMILARITY_THRESHOLD, DEFAULT_SIMILAR_LIMIT));
+
+        let params = HashMap::from([
+            ("threshold".to_string(), "0.9".to_string()),
+            ("limit".to_string(), "2".to_string()),
+        ]);
+        assert_eq!(similarity_params(&params).unwrap(), (0.9, 2));
+
+        let invalid = HashMap::from([("threshold".to_string(), "high".to_string())]);
+        assert!(similarity_params(&invalid).is_err());
+    }
+
     #[tokio::test]
     async fn test_ai_enhancement() -> Result<()> {
         // Test AI enhancement functionality
@@ -585,7 +1314,7 @@ mod tests {
 
         for (description, valid_priorities, expected_keywords) in test_cases {
             match tool.enhance_with_ai(description).await {
-                Ok((enhanced, priority, project)) => {
+                Ok(Enhancement { enhanced_description: enhanced, priority, project, .. }) => {
                     println!("\nTesting enhancement for: {}", description);
                     println!("Enhanced description: {}", enhanced);
                     println!("Assigned priority: {:?}", priority);
@@ -637,6 +1366,92 @@ mod tests {
         Ok(())
     }
 
+    #[tokio::test]
+    async fn test_ai_enhancement_with_mock() -> Result<()> {
+        let ai = MockAiProvider::new()
+            .with_rule("Enhance this task", "## Steps\n1. Audit the login handler for the injection\n2. Add regression tests")
+            .with_rule("priority classifier", "critical")
+            .with_rule("project classifier", "Swarmonomicon")
+            .with_rule("task labeler", "Security, bug, Auth");
+        let tool = TodoTool::with_server_url("http://localhost:0")?.with_ai_client(ai.clone());
+
+        let Enhancement { enhanced_description: enhanced, priority, project, tags, .. } = tool.enhance_with_ai("fix critical security vulnerability in login").await?;
+        assert!(enhanced.contains("regression tests"));
+        assert_eq!(tags, ["security", "bug", "auth"]);
+        assert_eq!(priority, TaskPriority::Critical);
+        assert!(!project.is_empty());
+        assert!(ai.requests()[0].last_message().contains("fix critical security vulnerability"));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_ai_enhancement_structured_in_one_call() -> Result<()> {
+        let ai = MockAiProvider::new().with_response(
+            r#"{"enhanced_description": "Steps:\n1. Rotate the leaked keys", "priority": "critical", "project": "swarmonomicon", "tags": ["Security", "infra"], "confidence": 0.9}"#,
+        );
+        let tool = TodoTool::with_server_url("http://localhost:0")?.with_ai_client(ai.clone());
+
+        let Enhancement { enhanced_description: enhanced, priority, project, tags, confidence } = tool.enhance_with_ai("rotate leaked API keys").await?;
+        assert!(enhanced.contains("Rotate the leaked keys"));
+        assert_eq!(priority, TaskPriority::Critical);
+        assert_eq!(project, "swarmonomicon");
+        assert_eq!(tags, ["security", "infra"]);
+        assert_eq!(confidence, 0.9);
+        assert_eq!(ai.call_count(), 1);
+        assert!(ai.requests()[0].system_prompt.contains("\"critical\""));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_prepare_todo_leaves_enhancement_to_the_background() -> Result<()> {
+        let ai = MockAiProvider::new().with_response("should not be asked");
+        let tool = TodoTool::with_server_url("http://localhost:0")?.with_ai_client(ai.clone());
+
+        let prepared = tool.prepare_todo(TodoRequest {
+            description: "Fix broken login after the security update".to_string(),
+            target_agent: "worker".to_string(),
+            tags: vec!["Urgent".to_string()],
+            ..Default::default()
+        }).await?;
+        assert_eq!(ai.call_count(), 0);
+        assert_eq!(prepared.request.priority, "High");
+        let metadata = prepared.request.metadata.as_ref().unwrap();
+        assert_eq!(metadata["enhancement"], "unenhanced");
+        assert!(!metadata.contains_key("project_requested"));
+        assert_eq!(metadata["tags"], serde_json::json!(["urgent", "security", "bug"]));
+        assert!(!prepared.project_requested);
+
+        let job = Enrichment {
+            todo_id: "t1".to_string(),
+            description: prepared.description().to_string(),
+            project_requested: true,
+            tags: prepared.tags,
+            embedded: false,
+            context: correlation::Context::capture(),
+        };
+        let enhancement = Enhancement {
+            enhanced_description: "Steps".to_string(),
+            priority: TaskPriority::Critical,
+            project: "Swarmonomicon".to_string(),
+            tags: vec!["auth".to_string()],
+            confidence: 0.8,
+        };
+        let updates = enhancement_updates(enhancement, &job);
+        assert_eq!(updates["priority"], "High");
+        assert!(!updates.contains_key("project"));
+        assert_eq!(updates["metadata.tags"], serde_json::json!(["urgent", "auth"]));
+        assert_eq!(updates["metadata.enhancement"], "enhanced");
+        Ok(())
+    }
+
+    #[test]
+    fn test_created_todo_id() {
+        assert_eq!(created_todo_id(r#"{"success": true, "data": {"todo_id": "abc"}}"#).as_deref(), Some("abc"));
+        assert_eq!(created_todo_id(r#"{"id": "def"}"#).as_deref(), Some("def"));
+        assert_eq!(created_todo_id(r#"{"success": true, "message": "added"}"#), None);
+        assert_eq!(created_todo_id("not json"), None);
+    }
+
     #[tokio::test]
     async fn test_project_field() -> Result<()> {
         let ai_client = DefaultAiClient::new();
@@ -732,7 +1547,8 @@ mod tests {
         // Test adding a todo without specifying a project
         let description = "Update the Swarmonomicon API documentation with new endpoints";
 
-        match tool.add_todo(description, None, "test_agent", None).await {
+        let request = TodoRequest { description: description.to_string(), target_agent: "test_agent".to_string(), ..Default::default() };
+        match tool.add_todo(request).await {
             Ok(result) => {
                 tracing::info!("Add todo with project prediction test passed: {}", result);
                 assert!(result.contains("todo") || result.contains("success"));
@@ -744,6 +1560,25 @@ mod tests {
 
         Ok(())
     }
+
+    #[tokio::test]
+    async fn test_cancel_against_mongodb() -> Result<()> {
+        let mongo = MongoFixture::start().await?;
+        let task = testsupport::task("Write the changelog", "user");
+        mongo.seeded([task.clone()]).await?;
+        let tool = TodoTool::with_server_url("http://localhost:0")?.with_database(&mongo.db).await;
+        let run = |params: &[(&str, &str)]| {
+            tool.execute(params.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect())
+        };
+
+        let output = run(&[("command", "cancel"), ("todo_id", &task.id)]).await?;
+        assert_eq!(output, format!("Cancelled todo {}: Write the changelog", task.id));
+        let stored = mongo.todo_list().await.get_task(&task.id).await?.unwrap();
+        assert_eq!(stored.status, TaskStatus::Cancelled);
+        assert!(run(&[("command", "cancel"), ("todo_id", &task.id)]).await.is_err(), "already cancelled");
+        assert_eq!(run(&[("command", "dead_letters")]).await?, "No dead-lettered todos.");
+        Ok(())
+    }
 }
 
 // // Example structure (actual implementation would depend on the Rust LangGraph API)
@@ -0,0 +1,328 @@
+//! Keeps a `TODO.md`-style checklist and the task queue in step. Each unchecked item without
+//! a task gets one, linked by a `<!-- task:ID -->` marker at the end of the line. Ticking an
+//! item completes its task, and a task completed elsewhere ticks its item.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use async_trait::async_trait;
+use anyhow::{Result, anyhow};
+use chrono::Utc;
+use serde::Serialize;
+use uuid::Uuid;
+use crate::tools::{ToolExecutor, ToolOutput};
+use crate::types::{TaskPriority, TaskStatus, TodoList, TodoTask};
+
+const DEFAULT_FILE: &str = "TODO.md";
+const MARKER_PREFIX: &str = "<!-- task:";
+const MARKER_SUFFIX: &str = " -->";
+
+/// One `- [ ] text` line of a checklist
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChecklistItem {
+    /// Zero-based line number
+    pub line: usize,
+    pub checked: bool,
+    pub text: String,
+    /// The task this item is linked to, from its marker
+    pub task_id: Option<String>,
+    /// The nearest heading above the item
+    pub section: Option<String>,
+}
+
+/// Checklist items in `content`, skipping fenced code blocks
+pub fn parse_checklist(content: &str) -> Vec<ChecklistItem> {
+    let mut items = Vec::new();
+    let mut section = None;
+    let mut in_code = false;
+    for (line, raw) in content.lines().enumerate() {
+        let trimmed = raw.trim_start();
+        if trimmed.starts_with("```") || trimmed.starts_with("~~~") {
+            in_code = !in_code;
+            continue;
+        }
+        if in_code {
+            continue;
+        }
+        if trimmed.starts_with('#') {
+            let heading = trimmed.trim_start_matches('#').trim();
+            section = (!heading.is_empty()).then(|| heading.to_string());
+            continue;
+        }
+        let Some((checked, rest)) = split_checkbox(trimmed) else { continue };
+        let (text, task_id) = split_marker(rest);
+        if text.is_empty() {
+            continue;
+        }
+        items.push(ChecklistItem { line, checked, text: text.to_string(), task_id, section: section.clone() });
+    }
+    items
+}
+
+/// Whether a list line is ticked, and the text after its box
+fn split_checkbox(line: &str) -> Option<(bool, &str)> {
+    let rest = line.strip_prefix(['-', '*', '+'])?.strip_prefix(' ')?.trim_start();
+    let checked = match rest.get(..3)? {
+        "[ ]" => false,
+        "[x]" | "[X]" => true,
+        _ => return None,
+    };
+    Some((checked, rest[3..].trim()))
+}
+
+fn split_marker(text: &str) -> (&str, Option<String>) {
+    let Some(start) = text.rfind(MARKER_PREFIX) else { return (text, None) };
+    let id = text[start + MARKER_PREFIX.len()..].trim_end().strip_suffix(MARKER_SUFFIX.trim()).map(str::trim);
+    match id {
+        Some(id) if !id.is_empty() => (text[..start].trim_end(), Some(id.to_string())),
+        _ => (text, None),
+    }
+}
+
+/// `line` ticked and linked to `task_id`, keeping its indentation and bullet
+fn rewrite_line(line: &str, checked: bool, task_id: &str) -> String {
+    let (body, cr) = match line.strip_suffix('\r') {
+        Some(body) => (body, "\r"),
+        None => (line, ""),
+    };
+    let open = body.find('[').expect("checklist line has a box");
+    let (text, _) = split_marker(body[open + 3..].trim());
+    let mark = if checked { 'x' } else { ' ' };
+    format!("{}[{}] {} {}{}{}{}", &body[..open], mark, text, MARKER_PREFIX, task_id, MARKER_SUFFIX, cr)
+}
+
+/// What a sync does with one item
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum SyncAction {
+    /// An unchecked item with no task yet
+    Create { line: usize, text: String },
+    /// Ticked in the file but not finished in the queue
+    Complete { line: usize, task_id: String },
+    /// Finished in the queue but not ticked in the file
+    Tick { line: usize, task_id: String },
+    /// Linked to a task that no longer exists, e.g. after archiving
+    Missing { line: usize, task_id: String },
+}
+
+/// The actions that bring `items` and the queue in step, given the status of each linked task
+pub fn plan_sync(items: &[ChecklistItem], statuses: &HashMap<String, TaskStatus>) -> Vec<SyncAction> {
+    items.iter().filter_map(|item| {
+        let line = item.line;
+        let Some(task_id) = item.task_id.clone() else {
+            return (!item.checked).then(|| SyncAction::Create { line, text: item.text.clone() });
+        };
+        match (statuses.get(&task_id), item.checked) {
+            (None, _) => Some(SyncAction::Missing { line, task_id }),
+            (Some(TaskStatus::Completed), false) => Some(SyncAction::Tick { line, task_id }),
+            (Some(TaskStatus::Completed), true) => None,
+            (Some(_), true) => Some(SyncAction::Complete { line, task_id }),
+            (Some(_), false) => None,
+        }
+    }).collect()
+}
+
+#[derive(Debug, Clone)]
+pub struct SyncOptions {
+    /// Agent new tasks are assigned to
+    pub target_agent: String,
+    /// Project new tasks belong to
+    pub project: Option<String>,
+    /// Report the actions without touching the file or the queue
+    pub dry_run: bool,
+}
+
+impl Default for SyncOptions {
+    fn default() -> Self {
+        Self { target_agent: "user".to_string(), project: None, dry_run: false }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SyncReport {
+    pub file: PathBuf,
+    pub actions: Vec<SyncAction>,
+    pub dry_run: bool,
+}
+
+impl SyncReport {
+    fn count(&self, matches: fn(&SyncAction) -> bool) -> usize {
+        self.actions.iter().filter(|action| matches(action)).count()
+    }
+
+    pub fn summary(&self) -> String {
+        format!(
+            "{}{}: {} created, {} completed, {} ticked, {} missing",
+            if self.dry_run { "(dry run) " } else { "" },
+            self.file.display(),
+            self.count(|a| matches!(a, SyncAction::Create { .. })),
+            self.count(|a| matches!(a, SyncAction::Complete { .. })),
+            self.count(|a| matches!(a, SyncAction::Tick { .. })),
+            self.count(|a| matches!(a, SyncAction::Missing { .. })),
+        )
+    }
+}
+
+/// `path` itself, or the `TODO.md` in it if it is a directory
+pub fn checklist_path(path: &Path) -> PathBuf {
+    if path.is_dir() { path.join(DEFAULT_FILE) } else { path.to_path_buf() }
+}
+
+fn new_task(item: &ChecklistItem, file: &Path, options: &SyncOptions) -> TodoTask {
+    let now = Utc::now().timestamp();
+    let source = match &item.section {
+        Some(section) => format!("{}:{} ({})", file.display(), item.line + 1, section),
+        None => format!("{}:{}", file.display(), item.line + 1),
+    };
+    TodoTask {
+        id: Uuid::new_v4().to_string(),
+        description: item.text.clone(),
+        enhanced_description: None,
+        priority: TaskPriority::Medium,
+        project: options.project.clone(),
+        source_agent: Some("todo_sync".to_string()),
+        target_agent: options.target_agent.clone(),
+        status: TaskStatus::Pending,
+        created_at: now,
+        completed_at: None,
+        due_date: None,
+        duration_minutes: None,
+        notes: Some(format!("From {}", source)),
+        ticket: None,
+        last_modified: Some(now),
+        failure_reason: None,
+        embedding: None,
+        claim: None,
+        failures: Vec::new(),
+        confidence: None,
+        reviewed_at: None,
+        tenant_id: None,
+        idempotency_key: None,
+        content_hash: None,
+        history: Vec::new(),
+        tags: Vec::new(),
+    }
+}
+
+/// Sync the checklist at `path` (a file, or a directory holding `TODO.md`) with the queue
+pub async fn sync_file(todos: &TodoList, path: &Path, options: &SyncOptions) -> Result<SyncReport> {
+    let file = checklist_path(path);
+    let content = tokio::fs::read_to_string(&file).await
+        .map_err(|e| anyhow!("Failed to read {}: {}", file.display(), e))?;
+    let items = parse_checklist(&content);
+
+    let mut statuses = HashMap::new();
+    for task_id in items.iter().filter_map(|item| item.task_id.as_ref()) {
+        if let Some(task) = todos.get_task(task_id).await? {
+            statuses.insert(task.id, task.status);
+        }
+    }
+    let actions = plan_sync(&items, &statuses);
+    let report = SyncReport { file: file.clone(), actions, dry_run: options.dry_run };
+    if options.dry_run || report.actions.is_empty() {
+        return Ok(report);
+    }
+
+    let mut lines: Vec<String> = content.split('\n').map(str::to_string).collect();
+    for action in &report.actions {
+        match action {
+            SyncAction::Create { line, .. } => {
+                let item = items.iter().find(|item| item.line == *line).expect("planned from these items");
+                let task = new_task(item, &file, options);
+                lines[*line] = rewrite_line(&lines[*line], false, &task.id);
+                todos.add_task(task).await?;
+            }
+            SyncAction::Complete { task_id, .. } => todos.mark_task_completed(task_id).await?,
+            SyncAction::Tick { line, task_id } => lines[*line] = rewrite_line(&lines[*line], true, task_id),
+            SyncAction::Missing { line, task_id } => {
+                tracing::warn!("{}:{} links to unknown task {}", file.display(), line + 1, task_id);
+            }
+        }
+    }
+    tokio::fs::write(&file, lines.join("\n")).await
+        .map_err(|e| anyhow!("Failed to write {}: {}", file.display(), e))?;
+    Ok(report)
+}
+
+pub struct TodoSyncTool {
+    todo_list: TodoList,
+}
+
+impl TodoSyncTool {
+    pub fn new(todo_list: TodoList) -> Self {
+        Self { todo_list }
+    }
+
+    async fn sync(&self, params: &HashMap<String, String>) -> Result<SyncReport> {
+        let path = params.get("path").map(PathBuf::from).unwrap_or_else(|| PathBuf::from(DEFAULT_FILE));
+        let options = SyncOptions {
+            target_agent: params.get("target_agent").cloned().unwrap_or_else(|| SyncOptions::default().target_agent),
+            project: params.get("project").cloned(),
+            dry_run: params.get("dry_run").is_some_and(|s| s == "true"),
+        };
+        sync_file(&self.todo_list, &path, &options).await
+    }
+}
+
+#[async_trait]
+impl ToolExecutor for TodoSyncTool {
+    async fn execute(&self, params: HashMap<String, String>) -> Result<String> {
+        Ok(self.sync(&params).await?.summary())
+    }
+
+    async fn execute_structured(&self, params: HashMap<String, String>) -> Result<ToolOutput> {
+        Ok(ToolOutput::Json(serde_json::to_value(self.sync(&params).await?)?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CHECKLIST: &str = "# Backlog\n\
+        - [ ] Write the docs\n\
+        * [x] Ship it <!-- task:abc -->\n\
+        ```\n\
+        - [ ] not a task\n\
+        ```\n\
+        ## Later\n\
+        \x20 - [ ] Refactor <!-- task:def -->\r\n\
+        - [X] Done before syncing\n\
+        - [ ] Gone <!-- task:old -->\n\
+        - plain bullet\n";
+
+    #[test]
+    fn test_parse_checklist() {
+        let items = parse_checklist(CHECKLIST);
+        let lines: Vec<_> = items.iter().map(|item| (item.line, item.checked, item.text.as_str(), item.task_id.as_deref())).collect();
+        assert_eq!(lines, [
+            (1, false, "Write the docs", None),
+            (2, true, "Ship it", Some("abc")),
+            (7, false, "Refactor", Some("def")),
+            (8, true, "Done before syncing", None),
+            (9, false, "Gone", Some("old")),
+        ]);
+        assert_eq!(items[0].section.as_deref(), Some("Backlog"));
+        assert_eq!(items[2].section.as_deref(), Some("Later"));
+    }
+
+    #[test]
+    fn test_plan_and_rewrite() {
+        let items = parse_checklist(CHECKLIST);
+        let statuses = HashMap::from([
+            ("abc".to_string(), TaskStatus::InProgress),
+            ("def".to_string(), TaskStatus::Completed),
+        ]);
+        assert_eq!(plan_sync(&items, &statuses), [
+            SyncAction::Create { line: 1, text: "Write the docs".to_string() },
+            SyncAction::Complete { line: 2, task_id: "abc".to_string() },
+            SyncAction::Tick { line: 7, task_id: "def".to_string() },
+            SyncAction::Missing { line: 9, task_id: "old".to_string() },
+        ]);
+
+        assert_eq!(rewrite_line("- [ ] Write the docs", false, "new"), "- [ ] Write the docs <!-- task:new -->");
+        assert_eq!(
+            rewrite_line("  - [ ] Refactor <!-- task:def -->\r", true, "def"),
+            "  - [x] Refactor <!-- task:def -->\r"
+        );
+    }
+}
@@ -0,0 +1,205 @@
+//! Builds `AgentConfig`s without spelling out every field. Unset fields take their usual
+//! defaults, and `build` checks the result before an agent is created from it.
+
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
+use anyhow::Result;
+use crate::agents::Personality;
+use crate::error::SwarmError;
+use super::{AgentConfig, RemoteAgentConfig, StateMachine, Tool};
+
+impl AgentConfig {
+    pub fn builder(name: impl Into<String>) -> AgentConfigBuilder {
+        AgentConfigBuilder::new(name)
+    }
+}
+
+impl Tool {
+    pub fn new(name: impl Into<String>, description: impl Into<String>) -> Self {
+        Self { name: name.into(), description: description.into(), parameters: HashMap::new() }
+    }
+
+    pub fn with_parameter(mut self, name: impl Into<String>, description: impl Into<String>) -> Self {
+        self.parameters.insert(name.into(), description.into());
+        self
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct AgentConfigBuilder {
+    config: AgentConfig,
+    state_machine_path: Option<PathBuf>,
+    known_agents: Option<HashSet<String>>,
+}
+
+impl AgentConfigBuilder {
+    pub fn new(name: impl Into<String>) -> Self {
+        let name = name.into();
+        Self {
+            config: AgentConfig {
+                public_description: format!("The {} agent.", name),
+                name,
+                instructions: String::new(),
+                tools: Vec::new(),
+                downstream_agents: Vec::new(),
+                personality: None,
+                state_machine: None,
+                script: None,
+                max_concurrency: None,
+                middleware: None,
+                remote: None,
+            },
+            state_machine_path: None,
+            known_agents: None,
+        }
+    }
+
+    pub fn description(mut self, description: impl Into<String>) -> Self {
+        self.config.public_description = description.into();
+        self
+    }
+
+    pub fn instructions(mut self, instructions: impl Into<String>) -> Self {
+        self.config.instructions = instructions.into();
+        self
+    }
+
+    pub fn with_tool(mut self, tool: Tool) -> Self {
+        self.config.tools.push(tool);
+        self
+    }
+
+    /// An agent this one may transfer conversations to
+    pub fn with_downstream(mut self, agent: impl Into<String>) -> Self {
+        self.config.downstream_agents.push(agent.into());
+        self
+    }
+
+    /// A personality as plain text describing its style
+    pub fn with_personality(mut self, personality: impl Into<String>) -> Self {
+        self.config.personality = Some(personality.into());
+        self
+    }
+
+    /// A structured personality; see `agents::personality`
+    pub fn with_personality_json(mut self, personality: serde_json::Value) -> Self {
+        self.config.personality = Some(personality.to_string());
+        self
+    }
+
+    pub fn with_state_machine(mut self, state_machine: StateMachine) -> Self {
+        self.config.state_machine = Some(state_machine);
+        self.state_machine_path = None;
+        self
+    }
+
+    /// A state machine loaded, and validated, from a YAML file when the config is built
+    pub fn with_state_machine_yaml(mut self, path: impl Into<PathBuf>) -> Self {
+        self.state_machine_path = Some(path.into());
+        self.config.state_machine = None;
+        self
+    }
+
+    pub fn with_script(mut self, path: impl Into<String>) -> Self {
+        self.config.script = Some(path.into());
+        self
+    }
+
+    pub fn with_max_concurrency(mut self, limit: usize) -> Self {
+        self.config.max_concurrency = Some(limit);
+        self
+    }
+
+    /// Middleware by name, outermost first; see `agents::middleware`
+    pub fn with_middleware<S: AsRef<str>>(mut self, names: &[S]) -> Self {
+        self.config.middleware = Some(names.iter().map(|name| name.as_ref().to_string()).collect());
+        self
+    }
+
+    /// Run the agent on another Swarmonomicon server instead of in this process
+    pub fn with_remote(mut self, remote: RemoteAgentConfig) -> Self {
+        self.config.remote = Some(remote);
+        self
+    }
+
+    /// Agents that exist, so `build` can check the downstream agents are among them
+    pub fn known_agents<I, S>(mut self, agents: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.known_agents = Some(agents.into_iter().map(Into::into).collect());
+        self
+    }
+
+    pub fn build(self) -> Result<AgentConfig> {
+        let mut config = self.config;
+        let invalid = |message: String| -> anyhow::Error { SwarmError::Validation(message).into() };
+        if config.name.trim().is_empty() {
+            return Err(invalid("Agent name must not be empty".to_string()));
+        }
+        for agent in &config.downstream_agents {
+            if agent.trim().is_empty() || *agent == config.name {
+                return Err(invalid(format!("Agent {} has an invalid downstream agent '{}'", config.name, agent)));
+            }
+            if self.known_agents.as_ref().is_some_and(|known| !known.contains(agent)) {
+                return Err(invalid(format!("Agent {} names unknown downstream agent '{}'", config.name, agent)));
+            }
+        }
+        if let Some(personality) = &config.personality {
+            Personality::parse(personality)
+                .map_err(|e| invalid(format!("Agent {}: {}", config.name, e)))?;
+        }
+        if config.max_concurrency == Some(0) {
+            return Err(invalid(format!("Agent {} needs a max_concurrency of at least 1", config.name)));
+        }
+        if let Some(remote) = &config.remote {
+            if !remote.url.starts_with("http://") && !remote.url.starts_with("https://") {
+                return Err(invalid(format!("Agent {} has a remote URL that isn't http(s): '{}'", config.name, remote.url)));
+            }
+        }
+        if let Some(path) = self.state_machine_path {
+            config.state_machine = Some(StateMachine::from_yaml_file(&path)?);
+        }
+        Ok(config)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_defaults_and_helpers() {
+        let config = AgentConfig::builder("greeter")
+            .instructions("Greet users")
+            .with_tool(Tool::new("lookup", "Find a user").with_parameter("name", "Who to find"))
+            .with_downstream("haiku")
+            .with_personality_json(json!({ "style": "friendly", "traits": ["warm"] }))
+            .with_max_concurrency(2)
+            .known_agents(["greeter", "haiku"])
+            .build()
+            .unwrap();
+        assert_eq!(config.public_description, "The greeter agent.");
+        assert_eq!(config.tools[0].parameters["name"], "Who to find");
+        assert_eq!(config.downstream_agents, ["haiku"]);
+        assert!(config.personality.unwrap().contains("\"traits\":[\"warm\"]"));
+        assert_eq!(config.max_concurrency, Some(2));
+        assert!(config.middleware.is_none());
+    }
+
+    #[test]
+    fn test_invalid_configs_are_rejected() {
+        let is_validation = |result: Result<AgentConfig>| matches!(SwarmError::find(&result.unwrap_err()), Some(SwarmError::Validation(_)));
+        assert!(is_validation(AgentConfig::builder(" ").build()));
+        assert!(is_validation(AgentConfig::builder("greeter").with_downstream("greeter").build()));
+        assert!(is_validation(AgentConfig::builder("greeter").with_downstream("haiku").known_agents(["git"]).build()));
+        assert!(is_validation(AgentConfig::builder("greeter").with_personality("{ broken").build()));
+        assert!(is_validation(AgentConfig::builder("greeter").with_max_concurrency(0).build()));
+        assert!(is_validation(AgentConfig::builder("browser").with_remote(RemoteAgentConfig::new("gpu-box:3000")).build()));
+        // Without a list of known agents any downstream name goes
+        assert!(AgentConfig::builder("greeter").with_downstream("haiku").build().is_ok());
+        assert!(AgentConfig::builder("greeter").with_state_machine_yaml("/nonexistent/machine.yaml").build().is_err());
+    }
+}
@@ -0,0 +1,206 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use chrono::Utc;
+use mongodb::{Collection, IndexModel};
+use mongodb::bson::doc;
+use mongodb::options::IndexOptions;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tokio::sync::RwLock;
+use utoipa::ToSchema;
+use uuid::Uuid;
+use crate::db::Database;
+use crate::error::SwarmError;
+
+const KEY_PREFIX: &str = "swarm_";
+
+/// What an API key may do. `Admin` implies every other scope.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "kebab-case")]
+pub enum Scope {
+    ReadTasks,
+    WriteTasks,
+    Chat,
+    Admin,
+}
+
+/// A stored key. Only the SHA-256 of the secret is kept; keys are random, so a slow
+/// password hash would add nothing.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct ApiKey {
+    pub id: String,
+    pub name: String,
+    #[serde(skip_serializing_if = "String::is_empty", default)]
+    pub key_hash: String,
+    pub scopes: Vec<Scope>,
+    pub created_at: i64,
+    #[serde(default)]
+    pub revoked: bool,
+    /// The only tenant the key may act for; see `tenants`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tenant: Option<String>,
+}
+
+impl ApiKey {
+    /// A new key and the secret to hand to its holder, which is not stored
+    pub fn generate(name: impl Into<String>, scopes: Vec<Scope>) -> (Self, String) {
+        let secret = format!("{}{}{}", KEY_PREFIX, Uuid::new_v4().simple(), Uuid::new_v4().simple());
+        let key = Self {
+            id: Uuid::new_v4().to_string(),
+            name: name.into(),
+            key_hash: hash_key(&secret),
+            scopes,
+            created_at: Utc::now().timestamp(),
+            revoked: false,
+            tenant: None,
+        };
+        (key, secret)
+    }
+
+    pub fn with_tenant(mut self, tenant: impl Into<String>) -> Self {
+        self.tenant = Some(tenant.into());
+        self
+    }
+
+    pub fn allows(&self, scope: Scope) -> bool {
+        !self.revoked && (self.scopes.contains(&scope) || self.scopes.contains(&Scope::Admin))
+    }
+
+    /// This key with the hash removed, for listing
+    pub fn redacted(&self) -> Self {
+        Self { key_hash: String::new(), ..self.clone() }
+    }
+}
+
+pub fn hash_key(secret: &str) -> String {
+    hex::encode(Sha256::digest(secret.as_bytes()))
+}
+
+/// Storage backend for API keys
+#[async_trait]
+pub trait ApiKeyStore: Send + Sync {
+    async fn find_by_hash(&self, key_hash: &str) -> Result<Option<ApiKey>>;
+    async fn insert(&self, key: &ApiKey) -> Result<()>;
+    async fn list(&self) -> Result<Vec<ApiKey>>;
+    /// Returns whether a key with this id existed
+    async fn revoke(&self, id: &str) -> Result<bool>;
+}
+
+/// Keys stored in the `api_keys` collection
+pub struct MongoApiKeyStore {
+    collection: Collection<ApiKey>,
+}
+
+impl MongoApiKeyStore {
+    pub async fn new() -> Result<Self> {
+        Self::from_database(&Database::shared().await?).await
+    }
+
+    pub async fn from_database(db: &Database) -> Result<Self> {
+        let collection: Collection<ApiKey> = db.collection("api_keys");
+        let index = IndexModel::builder()
+            .keys(doc! { "key_hash": 1 })
+            .options(Some(IndexOptions::builder().unique(true).build()))
+            .build();
+        collection.create_index(index, None).await?;
+        Ok(Self { collection })
+    }
+}
+
+#[async_trait]
+impl ApiKeyStore for MongoApiKeyStore {
+    async fn find_by_hash(&self, key_hash: &str) -> Result<Option<ApiKey>> {
+        Ok(self.collection.find_one(doc! { "key_hash": key_hash }, None).await.map_err(SwarmError::from)?)
+    }
+
+    async fn insert(&self, key: &ApiKey) -> Result<()> {
+        self.collection.insert_one(key, None).await.map_err(SwarmError::from)?;
+        Ok(())
+    }
+
+    async fn list(&self) -> Result<Vec<ApiKey>> {
+        use futures_util::TryStreamExt;
+        let cursor = self.collection.find(None, None).await.map_err(SwarmError::from)?;
+        Ok(cursor.try_collect().await.map_err(SwarmError::from)?)
+    }
+
+    async fn revoke(&self, id: &str) -> Result<bool> {
+        let result = self.collection.update_one(doc! { "id": id }, doc! { "$set": { "revoked": true } }, None).await
+            .map_err(SwarmError::from)?;
+        Ok(result.matched_count > 0)
+    }
+}
+
+/// Keys held in memory, for tests and single-process setups
+#[derive(Default)]
+pub struct InMemoryApiKeyStore {
+    keys: RwLock<Vec<ApiKey>>,
+}
+
+impl InMemoryApiKeyStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[async_trait]
+impl ApiKeyStore for InMemoryApiKeyStore {
+    async fn find_by_hash(&self, key_hash: &str) -> Result<Option<ApiKey>> {
+        Ok(self.keys.read().await.iter().find(|k| k.key_hash == key_hash).cloned())
+    }
+
+    async fn insert(&self, key: &ApiKey) -> Result<()> {
+        self.keys.write().await.push(key.clone());
+        Ok(())
+    }
+
+    async fn list(&self) -> Result<Vec<ApiKey>> {
+        Ok(self.keys.read().await.clone())
+    }
+
+    async fn revoke(&self, id: &str) -> Result<bool> {
+        let mut keys = self.keys.write().await;
+        let key = keys.iter_mut().find(|k| k.id == id);
+        let found = key.is_some();
+        if let Some(key) = key {
+            key.revoked = true;
+        }
+        Ok(found)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_keys_are_found_by_secret_hash() -> Result<()> {
+        let store = InMemoryApiKeyStore::new();
+        let (key, secret) = ApiKey::generate("dashboard", vec![Scope::ReadTasks]);
+        assert!(secret.starts_with(KEY_PREFIX));
+        assert_ne!(key.key_hash, secret);
+        store.insert(&key).await?;
+
+        let found = store.find_by_hash(&hash_key(&secret)).await?.unwrap();
+        assert_eq!(found.name, "dashboard");
+        assert!(store.find_by_hash(&hash_key("swarm_guess")).await?.is_none());
+        assert!(store.list().await?.iter().all(|k| k.redacted().key_hash.is_empty()));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_scopes_and_revocation() -> Result<()> {
+        let (reader, _) = ApiKey::generate("reader", vec![Scope::ReadTasks]);
+        assert!(reader.allows(Scope::ReadTasks));
+        assert!(!reader.allows(Scope::WriteTasks));
+        let (admin, _) = ApiKey::generate("admin", vec![Scope::Admin]);
+        assert!(admin.allows(Scope::Chat));
+
+        let store = InMemoryApiKeyStore::new();
+        store.insert(&admin).await?;
+        assert!(store.revoke(&admin.id).await?);
+        assert!(!store.revoke("missing").await?);
+        assert!(!store.find_by_hash(&admin.key_hash).await?.unwrap().allows(Scope::Chat));
+        Ok(())
+    }
+}
@@ -0,0 +1,168 @@
+//! Finished tasks are moved out of the `todos` collection once they are older than
+//! `TODO_ARCHIVE_AFTER_DAYS`, into `archived_tasks`. This keeps the collection workers poll
+//! small; archived tasks can still be listed by asking for them explicitly.
+
+use std::env;
+use std::time::Duration;
+use chrono::Utc;
+use futures_util::TryStreamExt;
+use mongodb::bson::{self, Document, doc};
+use mongodb::error::Error as MongoError;
+use mongodb::options::{FindOptions, ReplaceOptions};
+use serde::{Deserialize, Serialize};
+use tokio::task::JoinHandle;
+use super::todo::{TaskStatus, TodoList, TodoTask};
+
+const DEFAULT_ARCHIVE_AFTER_DAYS: u64 = 30;
+const ARCHIVE_INTERVAL: Duration = Duration::from_secs(3600);
+const ARCHIVE_BATCH: i64 = 500;
+const SECS_PER_DAY: i64 = 24 * 60 * 60;
+
+/// Statuses a task can be archived in
+pub const FINISHED: [TaskStatus; 3] = [TaskStatus::Completed, TaskStatus::Failed, TaskStatus::Cancelled];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ArchivePolicy {
+    /// Days after finishing that a task is archived
+    pub after_days: u64,
+}
+
+impl Default for ArchivePolicy {
+    fn default() -> Self {
+        Self { after_days: DEFAULT_ARCHIVE_AFTER_DAYS }
+    }
+}
+
+impl ArchivePolicy {
+    /// `TODO_ARCHIVE_AFTER_DAYS`, defaulting to 30; `None` if set to 0 to turn archiving off
+    pub fn from_env() -> Option<Self> {
+        let policy = env::var("TODO_ARCHIVE_AFTER_DAYS").ok()
+            .and_then(|days| days.parse().ok())
+            .map(|after_days| Self { after_days })
+            .unwrap_or_default();
+        (policy.after_days > 0).then_some(policy)
+    }
+
+    /// Tasks that finished before this timestamp are due for archiving
+    pub fn cutoff(&self, now: i64) -> i64 {
+        now - self.after_days as i64 * SECS_PER_DAY
+    }
+}
+
+/// Finished tasks that finished before `cutoff`. Tasks failed by older code have no
+/// `completed_at`, so their last change is used instead.
+pub fn archivable_filter(cutoff: i64) -> Result<Document, bson::ser::Error> {
+    let finished = FINISHED.iter().map(bson::to_bson).collect::<Result<Vec<_>, _>>()?;
+    Ok(doc! {
+        "status": { "$in": finished },
+        "$or": [
+            { "completed_at": { "$lt": cutoff } },
+            { "completed_at": null, "last_modified": { "$lt": cutoff } },
+        ],
+    })
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ArchivedTask {
+    #[serde(flatten)]
+    pub task: TodoTask,
+    pub archived_at: i64,
+}
+
+impl TodoList {
+    /// Move tasks that finished before `cutoff` to the archive, returning how many moved
+    pub async fn archive_finished(&self, cutoff: i64) -> Result<u64, MongoError> {
+        let filter = self.scope(archivable_filter(cutoff)?);
+        let mut archived = 0;
+        loop {
+            let options = FindOptions::builder().limit(ARCHIVE_BATCH).build();
+            let batch: Vec<TodoTask> = self.collection().find(filter.clone(), options).await?.try_collect().await?;
+            if batch.is_empty() {
+                break;
+            }
+            let archived_at = Utc::now().timestamp();
+            let mut ids = Vec::with_capacity(batch.len());
+            for task in batch {
+                // Upsert so a run interrupted between copying and deleting can be repeated
+                let upsert = ReplaceOptions::builder().upsert(true).build();
+                let id = task.id.clone();
+                self.archive_collection()
+                    .replace_one(doc! { "id": &id }, ArchivedTask { task, archived_at }, upsert)
+                    .await?;
+                ids.push(id);
+            }
+            let mut delete = filter.clone();
+            delete.insert("id", doc! { "$in": ids });
+            archived += self.collection().delete_many(delete, None).await?.deleted_count;
+        }
+        if archived > 0 {
+            tracing::info!("Archived {} finished tasks", archived);
+        }
+        Ok(archived)
+    }
+
+    /// Archived tasks, most recently finished first
+    pub async fn archived_tasks(&self, limit: Option<usize>) -> Result<Vec<ArchivedTask>, MongoError> {
+        let options = FindOptions::builder()
+            .sort(doc! { "completed_at": -1 })
+            .limit(limit.map(|n| n as i64))
+            .projection(doc! { "embedding": 0 })
+            .build();
+        self.archive_collection().find(self.scope(doc! {}), options).await?.try_collect().await
+    }
+
+    /// Live tasks carrying all of `tags`, followed by archived ones if `include_archived` is set
+    pub async fn list_tasks(&self, include_archived: bool, tags: &[String]) -> Result<Vec<TodoTask>, MongoError> {
+        let mut filter = self.scope(doc! {});
+        if !tags.is_empty() {
+            filter.insert("tags", doc! { "$all": tags });
+        }
+        let mut tasks: Vec<TodoTask> = self.collection().find(filter.clone(), None).await?.try_collect().await?;
+        if include_archived {
+            let archived: Vec<ArchivedTask> = self.archive_collection().find(filter, None).await?.try_collect().await?;
+            tasks.extend(archived.into_iter().map(|archived| archived.task));
+        }
+        Ok(tasks)
+    }
+
+    /// Archive according to `policy` now and then every hour, until the handle is aborted
+    pub fn spawn_archiver(&self, policy: ArchivePolicy) -> JoinHandle<()> {
+        let todo_list = self.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(ARCHIVE_INTERVAL);
+            loop {
+                interval.tick().await;
+                if let Err(e) = todo_list.archive_finished(policy.cutoff(Utc::now().timestamp())).await {
+                    tracing::warn!("Failed to archive finished tasks: {}", e);
+                }
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_policy_cutoff() {
+        let policy = ArchivePolicy { after_days: 2 };
+        assert_eq!(policy.cutoff(1_000_000), 1_000_000 - 2 * SECS_PER_DAY);
+        assert_eq!(ArchivePolicy::default().after_days, 30);
+    }
+
+    #[test]
+    fn test_archivable_filter_only_matches_finished_tasks() {
+        let filter = archivable_filter(500).unwrap();
+        let statuses = filter.get_document("status").unwrap().get_array("$in").unwrap();
+        let statuses: Vec<_> = statuses.iter().map(|s| s.as_str().unwrap()).collect();
+        assert_eq!(statuses, ["completed", "failed", "cancelled"]);
+
+        let branches = filter.get_array("$or").unwrap();
+        let by_completion = branches[0].as_document().unwrap();
+        assert_eq!(by_completion.get_document("completed_at").unwrap().get_i64("$lt").unwrap(), 500);
+        let legacy = branches[1].as_document().unwrap();
+        assert!(legacy.get("completed_at").unwrap().as_null().is_some());
+        assert_eq!(legacy.get_document("last_modified").unwrap().get_i64("$lt").unwrap(), 500);
+    }
+}
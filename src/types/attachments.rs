@@ -0,0 +1,168 @@
+use std::path::{Path, PathBuf};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use utoipa::ToSchema;
+use crate::tools::ToolOutput;
+use super::Message;
+
+/// Non-text content carried alongside a message's text
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Attachment {
+    /// A file on the local filesystem
+    File {
+        #[schema(value_type = String)]
+        path: PathBuf,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        mime_type: Option<String>,
+    },
+    /// Inline image bytes, base64-encoded on the wire
+    Image {
+        #[serde(with = "base64_bytes")]
+        #[schema(value_type = String, format = Byte)]
+        data: Vec<u8>,
+        mime_type: String,
+    },
+    /// Structured data
+    Json { value: Value },
+}
+
+impl Attachment {
+    pub fn file(path: impl Into<PathBuf>) -> Self {
+        let path = path.into();
+        let mime_type = mime_from_extension(&path).map(|m| m.to_string());
+        Attachment::File { path, mime_type }
+    }
+
+    /// Wrap image bytes, detecting the format from its signature.
+    /// Returns None if the bytes are not a recognised image format.
+    pub fn image(data: Vec<u8>) -> Option<Self> {
+        let mime_type = sniff_image(&data)?.to_string();
+        Some(Attachment::Image { data, mime_type })
+    }
+
+    /// Whether the content travels with the attachment rather than naming a local file.
+    /// Attachments from outside the process must be inline.
+    pub fn is_inline(&self) -> bool {
+        !matches!(self, Attachment::File { .. })
+    }
+
+    pub fn mime_type(&self) -> Option<&str> {
+        match self {
+            Attachment::File { mime_type, .. } => mime_type.as_deref(),
+            Attachment::Image { mime_type, .. } => Some(mime_type),
+            Attachment::Json { .. } => Some("application/json"),
+        }
+    }
+
+    /// Attachment for a tool result, if it carries more than text
+    pub fn from_tool_output(output: &ToolOutput) -> Option<Self> {
+        match output {
+            ToolOutput::Text(_) => None,
+            ToolOutput::Json(value) => Some(Attachment::Json { value: value.clone() }),
+            ToolOutput::File(path) => Some(Attachment::file(path.clone())),
+            ToolOutput::Binary(data) => Attachment::image(data.clone()),
+        }
+    }
+}
+
+fn mime_from_extension(path: &Path) -> Option<&'static str> {
+    let extension = path.extension()?.to_str()?.to_lowercase();
+    Some(match extension.as_str() {
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        "webp" => "image/webp",
+        "json" => "application/json",
+        "txt" | "log" => "text/plain",
+        "md" => "text/markdown",
+        "pdf" => "application/pdf",
+        _ => return None,
+    })
+}
+
+pub(crate) fn sniff_image(data: &[u8]) -> Option<&'static str> {
+    if data.starts_with(b"\x89PNG\r\n\x1a\n") {
+        Some("image/png")
+    } else if data.starts_with(&[0xFF, 0xD8, 0xFF]) {
+        Some("image/jpeg")
+    } else if data.starts_with(b"GIF87a") || data.starts_with(b"GIF89a") {
+        Some("image/gif")
+    } else if data.len() >= 12 && &data[0..4] == b"RIFF" && &data[8..12] == b"WEBP" {
+        Some("image/webp")
+    } else {
+        None
+    }
+}
+
+mod base64_bytes {
+    use base64::{engine::general_purpose::STANDARD, Engine};
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(data: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&STANDARD.encode(data))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+        let encoded = String::deserialize(deserializer)?;
+        STANDARD.decode(encoded).map_err(serde::de::Error::custom)
+    }
+}
+
+impl Message {
+    pub fn with_attachment(mut self, attachment: Attachment) -> Self {
+        self.attachments.push(attachment);
+        self
+    }
+
+    pub fn with_attachments(mut self, attachments: Vec<Attachment>) -> Self {
+        self.attachments.extend(attachments);
+        self
+    }
+
+    /// Attach a tool's structured output; plain text results are left in the content
+    pub fn with_tool_output(self, output: &ToolOutput) -> Self {
+        match Attachment::from_tool_output(output) {
+            Some(attachment) => self.with_attachment(attachment),
+            None => self,
+        }
+    }
+
+    pub fn images(&self) -> impl Iterator<Item = &Attachment> {
+        self.attachments.iter().filter(|a| a.mime_type().map_or(false, |m| m.starts_with("image/")))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PNG_HEADER: &[u8] = b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR";
+
+    #[test]
+    fn test_attachments_round_trip() {
+        let message = Message::new("Found 2 objects".to_string())
+            .with_attachment(Attachment::image(PNG_HEADER.to_vec()).unwrap())
+            .with_attachment(Attachment::file("/tmp/screenshot.png"))
+            .with_tool_output(&ToolOutput::Json(serde_json::json!({ "objects": 2 })));
+
+        let json = serde_json::to_value(&message).unwrap();
+        assert_eq!(json["attachments"][0]["type"], "image");
+        assert_eq!(json["attachments"][0]["data"], "iVBORw0KGgoAAAANSUhEUg==");
+        assert_eq!(json["attachments"][1]["mime_type"], "image/png");
+
+        let decoded: Message = serde_json::from_value(json).unwrap();
+        assert_eq!(decoded.attachments, message.attachments);
+        assert_eq!(decoded.images().count(), 2);
+    }
+
+    #[test]
+    fn test_plain_messages_omit_attachments() {
+        let json = serde_json::to_value(Message::new("hi".to_string())).unwrap();
+        assert!(json.get("attachments").is_none());
+
+        let decoded: Message = serde_json::from_str(r#"{"content": "hi", "metadata": null, "role": null, "timestamp": null}"#).unwrap();
+        assert!(decoded.attachments.is_empty());
+        assert!(Attachment::image(b"not an image".to_vec()).is_none());
+    }
+}
@@ -0,0 +1,129 @@
+//! Cancelling tasks. `TodoList::cancel_task` marks the task cancelled and signals the
+//! `CancellationToken` of whoever is running it. A run in this process stops straight away;
+//! a worker in another process notices when its next lease renewal finds the claim gone.
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+use chrono::Utc;
+use mongodb::bson::{self, doc};
+use mongodb::error::Error as MongoError;
+use mongodb::options::{FindOneAndUpdateOptions, ReturnDocument};
+use crate::events::{self, DomainEvent};
+use crate::tools::CancellationToken;
+use super::task_events::{TaskEvent, TaskEventKind};
+use super::history::{ChangeKind, TaskChange};
+use super::todo::{TaskStatus, TodoList, TodoTask};
+
+#[derive(Debug, Clone, thiserror::Error)]
+#[error("Task {task_id} was cancelled")]
+pub struct TaskCancelledError {
+    pub task_id: String,
+}
+
+/// Returns true if the error (or anything it wraps) is a task cancellation
+pub fn is_cancelled(err: &anyhow::Error) -> bool {
+    err.chain().any(|cause| cause.is::<TaskCancelledError>())
+}
+
+/// Statuses a task can still be cancelled from
+pub const CANCELLABLE: [TaskStatus; 4] = [TaskStatus::Initial, TaskStatus::Pending, TaskStatus::InProgress, TaskStatus::Review];
+
+lazy_static::lazy_static! {
+    static ref RUNNING: Mutex<HashMap<String, CancellationToken>> = Mutex::new(HashMap::new());
+}
+
+/// A task being processed in this process. Dropping it unregisters the task.
+pub struct RunningTask {
+    task_id: String,
+    token: CancellationToken,
+}
+
+impl RunningTask {
+    pub fn start(task_id: &str) -> Self {
+        let token = CancellationToken::new();
+        RUNNING.lock().unwrap().insert(task_id.to_string(), token.clone());
+        Self { task_id: task_id.to_string(), token }
+    }
+
+    pub fn token(&self) -> CancellationToken {
+        self.token.clone()
+    }
+}
+
+impl Drop for RunningTask {
+    fn drop(&mut self) {
+        let mut running = RUNNING.lock().unwrap();
+        // A later run of the same task may have replaced this one
+        if running.get(&self.task_id).is_some_and(|token| token.same_as(&self.token)) {
+            running.remove(&self.task_id);
+        }
+    }
+}
+
+/// Signal a task running in this process; returns false if it isn't running here
+pub fn cancel_running(task_id: &str) -> bool {
+    match RUNNING.lock().unwrap().get(task_id) {
+        Some(token) => {
+            token.cancel();
+            true
+        }
+        None => false,
+    }
+}
+
+impl TodoList {
+    /// Cancel a task that hasn't finished on behalf of `actor`, stopping it if it is running.
+    /// Returns `None` if there is no such task or it already completed, failed or was cancelled.
+    pub async fn cancel_task(&self, task_id: &str, actor: &str) -> Result<Option<TodoTask>, MongoError> {
+        let cancellable = CANCELLABLE.iter().map(bson::to_bson).collect::<Result<Vec<_>, _>>()?;
+        let now = Utc::now().timestamp();
+        let update = doc! {
+            "$set": { "status": bson::to_bson(&TaskStatus::Cancelled)?, "completed_at": now, "last_modified": now },
+            "$unset": { "claim": "" },
+            "$push": TaskChange::status(ChangeKind::Status, actor, None, &TaskStatus::Cancelled).push()?,
+        };
+        let options = FindOneAndUpdateOptions::builder().return_document(ReturnDocument::After).build();
+        let filter = self.scope(doc! { "id": task_id, "status": { "$in": cancellable } });
+        let Some(task) = self.collection().find_one_and_update(filter, update, options).await? else {
+            return Ok(None);
+        };
+
+        if cancel_running(task_id) {
+            tracing::info!("Cancelled running task {}", task_id);
+        } else {
+            tracing::info!("Cancelled task {}", task_id);
+        }
+        events::global().publish(DomainEvent::Task(TaskEvent::for_task(TaskEventKind::Cancelled, &task)));
+        Ok(Some(task))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cancel_signals_the_running_token() {
+        let run = RunningTask::start("cancel-me");
+        let token = run.token();
+        assert!(!cancel_running("someone-else"));
+        assert!(cancel_running("cancel-me"));
+        assert!(token.is_cancelled());
+
+        drop(run);
+        assert!(!cancel_running("cancel-me"), "finished tasks are unregistered");
+    }
+
+    #[test]
+    fn test_a_stale_run_does_not_unregister_its_replacement() {
+        let first = RunningTask::start("rerun");
+        let second = RunningTask::start("rerun");
+        drop(first);
+        assert!(cancel_running("rerun"));
+        assert!(second.token().is_cancelled());
+
+        let err = anyhow::Error::new(TaskCancelledError { task_id: "rerun".to_string() }).context("processing");
+        assert!(is_cancelled(&err));
+        assert_eq!(bson::to_bson(&TaskStatus::Cancelled).unwrap(), bson::Bson::String("cancelled".to_string()));
+    }
+}
@@ -0,0 +1,145 @@
+//! What agents advertise they can do. `Agent::capabilities` describes an agent's commands,
+//! the input formats it accepts and roughly what a request costs; the registry collects them
+//! into a catalog that `GET /api/agents` exposes and `ai::router` routes conversations with.
+
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use super::AgentConfig;
+
+/// Rough cost of a request, so callers can prefer cheaper agents
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum CostHint {
+    /// No model calls
+    Free,
+    #[default]
+    Low,
+    Medium,
+    High,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct Capability {
+    pub name: String,
+    pub description: String,
+    /// Commands the agent understands, e.g. `commit`
+    #[serde(default)]
+    pub commands: Vec<String>,
+    /// Input formats accepted, e.g. `text` or `json`
+    #[serde(default)]
+    pub input_formats: Vec<String>,
+    /// Words that suggest a message is for this capability
+    #[serde(default)]
+    pub keywords: Vec<String>,
+    #[serde(default)]
+    pub cost: CostHint,
+}
+
+impl Capability {
+    pub fn new(name: impl Into<String>, description: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            description: description.into(),
+            commands: Vec::new(),
+            input_formats: vec!["text".to_string()],
+            keywords: Vec::new(),
+            cost: CostHint::default(),
+        }
+    }
+
+    /// The capability an agent has by default: its description, with its tools as commands
+    pub fn from_config(config: &AgentConfig) -> Self {
+        Self::new(config.name.clone(), config.public_description.clone())
+            .with_commands(config.tools.iter().map(|tool| tool.name.clone()))
+    }
+
+    pub fn with_commands<I: IntoIterator<Item = S>, S: Into<String>>(mut self, commands: I) -> Self {
+        self.commands.extend(commands.into_iter().map(Into::into));
+        self
+    }
+
+    pub fn with_input_formats<I: IntoIterator<Item = S>, S: Into<String>>(mut self, formats: I) -> Self {
+        self.input_formats = formats.into_iter().map(Into::into).collect();
+        self
+    }
+
+    pub fn with_keywords<I: IntoIterator<Item = S>, S: Into<String>>(mut self, keywords: I) -> Self {
+        self.keywords.extend(keywords.into_iter().map(Into::into));
+        self
+    }
+
+    pub fn with_cost(mut self, cost: CostHint) -> Self {
+        self.cost = cost;
+        self
+    }
+
+    /// How many of the capability's commands and keywords appear as words in `content`
+    pub fn matches(&self, content: &str) -> usize {
+        let words: Vec<String> = content
+            .split(|c: char| !c.is_alphanumeric() && c != '-')
+            .filter(|word| !word.is_empty())
+            .map(str::to_lowercase)
+            .collect();
+        self.commands.iter().chain(&self.keywords)
+            .filter(|term| words.iter().any(|word| word == &term.to_lowercase()))
+            .count()
+    }
+}
+
+/// An agent's entry in the capability catalog
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct AgentCapabilities {
+    pub agent: String,
+    pub capabilities: Vec<Capability>,
+}
+
+impl AgentCapabilities {
+    /// The agent's best match for `content`; 0 when nothing matches
+    pub fn matches(&self, content: &str) -> usize {
+        self.capabilities.iter().map(|capability| capability.matches(content)).max().unwrap_or(0)
+    }
+
+    pub fn supports_command(&self, command: &str) -> bool {
+        self.capabilities.iter().any(|c| c.commands.iter().any(|known| known.eq_ignore_ascii_case(command)))
+    }
+
+    pub fn accepts_format(&self, format: &str) -> bool {
+        self.capabilities.iter().any(|c| c.input_formats.iter().any(|known| known.eq_ignore_ascii_case(format)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::Tool;
+
+    #[test]
+    fn test_default_capability_comes_from_the_config() {
+        let config = AgentConfig::builder("search")
+            .description("Finds things")
+            .with_tool(Tool::new("lookup", "Find a record"))
+            .build()
+            .unwrap();
+        let capability = Capability::from_config(&config);
+        assert_eq!(capability.description, "Finds things");
+        assert_eq!(capability.commands, ["lookup"]);
+        assert_eq!(capability.input_formats, ["text"]);
+        assert_eq!(capability.cost, CostHint::Low);
+        assert_eq!(serde_json::to_value(CostHint::Free).unwrap(), "free");
+    }
+
+    #[test]
+    fn test_matching_counts_whole_words() {
+        let git = AgentCapabilities {
+            agent: "git".to_string(),
+            capabilities: vec![Capability::new("git", "Version control")
+                .with_commands(["commit", "branch"])
+                .with_keywords(["repository"])],
+        };
+        assert_eq!(git.matches("Please commit this to the repository"), 2);
+        assert_eq!(git.matches("commitment issues"), 0);
+        assert!(git.supports_command("COMMIT"));
+        assert!(git.accepts_format("text"));
+        assert!(!git.accepts_format("json"));
+    }
+}
@@ -0,0 +1,195 @@
+//! Claims let several processes share one task collection without processing a task
+//! twice. A worker claims a task atomically and holds it for a lease, which it renews
+//! while working; if the worker dies the lease runs out and another worker can take over.
+//! Each renewal is also a heartbeat, which the reaper (see `reaper`) watches for.
+
+use std::env;
+use std::time::Duration;
+use chrono::Utc;
+use mongodb::bson::{self, Document, doc};
+use serde::{Deserialize, Serialize};
+use tokio::task::JoinHandle;
+use uuid::Uuid;
+use crate::tools::CancellationToken;
+use super::todo::{TaskStatus, TodoList};
+
+const DEFAULT_LEASE_SECS: u64 = 300;
+
+/// Which worker holds a task, and until when
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TaskClaim {
+    pub worker_id: String,
+    /// Unix timestamp after which other workers may reclaim the task
+    pub lease_expires_at: i64,
+    /// When the worker last showed it is alive; unset for claims made by older workers
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub heartbeat_at: Option<i64>,
+}
+
+impl TaskClaim {
+    pub fn is_expired(&self, now: i64) -> bool {
+        self.lease_expires_at <= now
+    }
+}
+
+/// This process's identity and how long its claims last
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ClaimConfig {
+    pub worker_id: String,
+    pub lease: Duration,
+}
+
+impl Default for ClaimConfig {
+    fn default() -> Self {
+        let host = env::var("HOSTNAME").unwrap_or_else(|_| "worker".to_string());
+        let suffix = Uuid::new_v4().simple().to_string();
+        Self {
+            // The suffix keeps containers that share a hostname and pid apart
+            worker_id: format!("{}-{}-{}", host, std::process::id(), &suffix[..8]),
+            lease: Duration::from_secs(DEFAULT_LEASE_SECS),
+        }
+    }
+}
+
+impl ClaimConfig {
+    /// `TODO_WORKER_ID` and `TODO_LEASE_SECS`, defaulting to a generated id and five minutes
+    pub fn from_env() -> Self {
+        let mut config = Self::default();
+        if let Ok(worker_id) = env::var("TODO_WORKER_ID") {
+            config.worker_id = worker_id;
+        }
+        if let Some(secs) = env::var("TODO_LEASE_SECS").ok().and_then(|s| s.parse().ok()).filter(|&s: &u64| s > 0) {
+            config.lease = Duration::from_secs(secs);
+        }
+        config
+    }
+
+    pub fn claim_until(&self, now: i64) -> TaskClaim {
+        TaskClaim {
+            worker_id: self.worker_id.clone(),
+            lease_expires_at: now + self.lease.as_secs() as i64,
+            heartbeat_at: Some(now),
+        }
+    }
+}
+
+/// Tasks a worker may claim at `now`: pending ones, and in-progress ones whose lease ran out.
+/// In-progress tasks left without a claim are returned to pending by the reaper.
+pub fn claimable_filter(now: i64) -> Result<Document, bson::ser::Error> {
+    Ok(doc! {
+        "$or": [
+            { "status": bson::to_bson(&TaskStatus::Pending)? },
+            {
+                "status": bson::to_bson(&TaskStatus::InProgress)?,
+                "claim.lease_expires_at": { "$lte": now },
+            },
+        ]
+    })
+}
+
+/// Renews a task's lease in the background until dropped. If the claim is lost, e.g.
+/// because the task was cancelled, `cancel` is signalled.
+pub struct LeaseRenewal {
+    handle: JoinHandle<()>,
+}
+
+impl LeaseRenewal {
+    pub(crate) fn start(todo_list: TodoList, task_id: String, lease: Duration, cancel: CancellationToken) -> Self {
+        let handle = tokio::spawn(async move {
+            let mut interval = tokio::time::interval((lease / 3).max(Duration::from_secs(1)));
+            interval.tick().await;
+            loop {
+                interval.tick().await;
+                match todo_list.renew_lease(&task_id).await {
+                    Ok(true) => {}
+                    Ok(false) => {
+                        tracing::warn!("Lost the claim on task {}; it was cancelled or another worker took it over", task_id);
+                        cancel.cancel();
+                        return;
+                    }
+                    Err(e) => tracing::warn!("Failed to renew the lease on task {}: {}", task_id, e),
+                }
+            }
+        });
+        Self { handle }
+    }
+}
+
+impl Drop for LeaseRenewal {
+    fn drop(&mut self) {
+        self.handle.abort();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testsupport::{self, MongoFixture};
+
+    #[test]
+    fn test_claims_expire_after_the_lease() {
+        let config = ClaimConfig { worker_id: "a".to_string(), lease: Duration::from_secs(60) };
+        let claim = config.claim_until(1_000);
+        assert_eq!(claim, TaskClaim { worker_id: "a".to_string(), lease_expires_at: 1_060, heartbeat_at: Some(1_000) });
+        assert!(!claim.is_expired(1_059));
+        assert!(claim.is_expired(1_060));
+
+        let generated = ClaimConfig::default();
+        assert_ne!(generated.worker_id, ClaimConfig::default().worker_id, "ids are unique per process");
+    }
+
+    #[test]
+    fn test_claimable_filter_includes_expired_leases() {
+        let filter = claimable_filter(500).unwrap();
+        let branches = filter.get_array("$or").unwrap();
+        assert_eq!(branches[0].as_document().unwrap().get_str("status").unwrap(), "pending");
+        let expired = branches[1].as_document().unwrap();
+        assert_eq!(expired.get_str("status").unwrap(), "in_progress");
+        assert_eq!(expired.get_document("claim.lease_expires_at").unwrap().get_i64("$lte").unwrap(), 500);
+        assert_eq!(branches.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_only_the_claiming_worker_finishes_a_task() -> anyhow::Result<()> {
+        let mongo = MongoFixture::start().await?;
+        let first = mongo.seeded([testsupport::task("Contested", "worker")]).await?;
+        let task = first.get_next_task().await?.expect("a task to claim");
+
+        let second = mongo.todo_list().await;
+        second.mark_task_failed_with_reason(&task.id, "not mine").await?;
+        assert_eq!(first.get_task(&task.id).await?.unwrap().status, TaskStatus::InProgress);
+
+        first.mark_task_completed(&task.id).await?;
+        assert_eq!(first.get_task(&task.id).await?.unwrap().status, TaskStatus::Completed);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_workers_claim_each_task_once() -> anyhow::Result<()> {
+        let mongo = MongoFixture::start().await?;
+        mongo.seeded((0..20).map(|i| testsupport::task(&format!("Task {}", i), "worker"))).await?;
+
+        // Each task list has a worker id of its own
+        let mut workers = Vec::new();
+        for _ in 0..4 {
+            let todo_list = mongo.todo_list().await;
+            workers.push(tokio::spawn(async move {
+                let mut claimed = Vec::new();
+                while let Some(task) = todo_list.get_next_task().await? {
+                    assert_eq!(task.claim.as_ref().map(|claim| claim.worker_id.as_str()), Some(todo_list.worker_id()));
+                    claimed.push(task.id);
+                }
+                Ok::<_, mongodb::error::Error>(claimed)
+            }));
+        }
+        let mut claimed = Vec::new();
+        for worker in workers {
+            claimed.extend(worker.await??);
+        }
+        let handed_out = claimed.len();
+        claimed.sort();
+        claimed.dedup();
+        assert_eq!((handed_out, claimed.len()), (20, 20));
+        Ok(())
+    }
+}
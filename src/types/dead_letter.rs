@@ -0,0 +1,259 @@
+//! Failed tasks are retried up to `TODO_MAX_ATTEMPTS` times. After that they move to the
+//! `dead_letter` collection with their failure history, where they can be inspected,
+//! edited and requeued, possibly to a different agent.
+
+use std::env;
+use chrono::Utc;
+use mongodb::bson::{self, doc};
+use mongodb::error::Error as MongoError;
+use mongodb::options::FindOptions;
+use futures_util::TryStreamExt;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use crate::events::{self, DomainEvent};
+use super::task_events::{TaskEvent, TaskEventKind};
+use super::history::{ChangeKind, TaskChange};
+use super::todo::{TaskFailure, TaskPriority, TaskStatus, TodoList, TodoTask};
+
+const DEFAULT_MAX_ATTEMPTS: u32 = 3;
+
+/// Attempts before a task is dead-lettered, from `TODO_MAX_ATTEMPTS`
+pub fn max_attempts_from_env() -> u32 {
+    env::var("TODO_MAX_ATTEMPTS").ok()
+        .and_then(|n| n.parse().ok())
+        .filter(|&n| n > 0)
+        .unwrap_or(DEFAULT_MAX_ATTEMPTS)
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DeadLetter {
+    /// The task as it was when it gave up, including its failure history
+    #[serde(flatten)]
+    pub task: TodoTask,
+    pub dead_lettered_at: i64,
+}
+
+/// Changes to make to a dead-lettered task before retrying it
+#[derive(Debug, Clone, Default, Deserialize, ToSchema)]
+pub struct DeadLetterEdit {
+    pub description: Option<String>,
+    pub priority: Option<TaskPriority>,
+    pub target_agent: Option<String>,
+    pub notes: Option<String>,
+}
+
+impl DeadLetterEdit {
+    fn is_empty(&self) -> bool {
+        self.description.is_none() && self.priority.is_none() && self.target_agent.is_none() && self.notes.is_none()
+    }
+
+    fn apply(self, task: &mut TodoTask) {
+        if let Some(description) = self.description {
+            // A stale enhancement would override the new description
+            task.enhanced_description = None;
+            task.description = description;
+        }
+        if let Some(priority) = self.priority {
+            task.priority = priority;
+        }
+        if let Some(target_agent) = self.target_agent {
+            task.target_agent = target_agent;
+        }
+        if let Some(notes) = self.notes {
+            task.notes = Some(notes);
+        }
+    }
+}
+
+/// What happened to a failed attempt
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FailureOutcome {
+    /// Back in the queue for another attempt
+    Retrying { attempt: u32 },
+    DeadLettered,
+    /// The task no longer exists, e.g. it was deleted while running
+    Missing,
+}
+
+impl DeadLetter {
+    /// The task to put back in the queue: edited, pending and with a fresh set of attempts.
+    /// Earlier failures are kept in the notes so the context isn't lost.
+    pub fn into_requeued(self, edit: DeadLetterEdit) -> TodoTask {
+        let mut task = self.task;
+        if !task.failures.is_empty() {
+            let history = task.failures.iter()
+                .map(|f| format!("- {}", f.reason))
+                .collect::<Vec<_>>()
+                .join("\n");
+            let previous = task.notes.take().map(|n| format!("{}\n\n", n)).unwrap_or_default();
+            task.notes = Some(format!("{}Failed {} times before requeueing:\n{}", previous, task.failures.len(), history));
+        }
+        edit.apply(&mut task);
+        task.status = TaskStatus::Pending;
+        task.failures.clear();
+        task.failure_reason = None;
+        task.claim = None;
+        task.completed_at = None;
+        task.last_modified = Some(Utc::now().timestamp());
+        task
+    }
+}
+
+impl TodoList {
+    /// Record a failed attempt. The task is retried until it has failed `max_attempts`
+    /// times, then moved to the dead-letter collection.
+    pub async fn fail_attempt(&self, task_id: &str, reason: &str) -> Result<FailureOutcome, MongoError> {
+        crate::chaos::mongo()?;
+        let failure = TaskFailure {
+            reason: reason.to_string(),
+            failed_at: Utc::now().timestamp(),
+            worker_id: Some(self.worker_id().to_string()),
+        };
+        let update = doc! {
+            "$push": { "failures": bson::to_bson(&failure)? },
+            "$set": { "failure_reason": reason, "last_modified": failure.failed_at },
+            "$unset": { "claim": "" },
+        };
+        let options = mongodb::options::FindOneAndUpdateOptions::builder()
+            .return_document(mongodb::options::ReturnDocument::After)
+            .build();
+        let Some(task) = self.collection().find_one_and_update(self.scope(doc! { "id": task_id }), update, options).await? else {
+            return Ok(FailureOutcome::Missing);
+        };
+
+        let attempts = task.failures.len() as u32;
+        if attempts < self.max_attempts() {
+            let change = TaskChange::status(ChangeKind::Retried, self.worker_id(), Some(&task.status), &TaskStatus::Pending)
+                .with_reason(reason);
+            let pending = doc! { "$set": { "status": bson::to_bson(&TaskStatus::Pending)? }, "$push": change.push()? };
+            self.collection().update_one(self.scope(doc! { "id": task_id }), pending, None).await?;
+            tracing::info!("Task {} failed attempt {} of {}, retrying: {}", task_id, attempts, self.max_attempts(), reason);
+            return Ok(FailureOutcome::Retrying { attempt: attempts });
+        }
+
+        let mut task = task;
+        task.history.push(TaskChange::status(ChangeKind::DeadLettered, self.worker_id(), Some(&task.status), &TaskStatus::Failed)
+            .with_reason(reason));
+        let dead_letter = DeadLetter {
+            task: TodoTask { status: TaskStatus::Failed, ..task },
+            dead_lettered_at: Utc::now().timestamp(),
+        };
+        self.dead_letter_collection().insert_one(&dead_letter, None).await?;
+        self.collection().delete_one(self.scope(doc! { "id": task_id }), None).await?;
+        tracing::warn!("Task {} dead-lettered after {} attempts: {}", task_id, attempts, reason);
+        events::global().publish(DomainEvent::Task(
+            TaskEvent::for_task(TaskEventKind::Failed, &dead_letter.task).with_failure_reason(reason),
+        ));
+        Ok(FailureOutcome::DeadLettered)
+    }
+
+    /// Dead-lettered tasks, most recent first
+    pub async fn dead_letters(&self, limit: usize) -> Result<Vec<DeadLetter>, MongoError> {
+        let options = FindOptions::builder()
+            .sort(doc! { "dead_lettered_at": -1 })
+            .limit(limit as i64)
+            .projection(doc! { "embedding": 0 })
+            .build();
+        self.dead_letter_collection().find(self.scope(doc! {}), options).await?.try_collect().await
+    }
+
+    pub async fn get_dead_letter(&self, task_id: &str) -> Result<Option<DeadLetter>, MongoError> {
+        self.dead_letter_collection().find_one(self.scope(doc! { "id": task_id }), None).await
+    }
+
+    /// Edit a dead-lettered task in place, returning the result
+    pub async fn edit_dead_letter(&self, task_id: &str, edit: DeadLetterEdit) -> Result<Option<DeadLetter>, MongoError> {
+        let Some(mut dead_letter) = self.get_dead_letter(task_id).await? else {
+            return Ok(None);
+        };
+        if edit.is_empty() {
+            return Ok(Some(dead_letter));
+        }
+        edit.apply(&mut dead_letter.task);
+        dead_letter.task.last_modified = Some(Utc::now().timestamp());
+        self.dead_letter_collection().replace_one(self.scope(doc! { "id": task_id }), &dead_letter, None).await?;
+        Ok(Some(dead_letter))
+    }
+
+    /// Move a dead-lettered task back into the queue on behalf of `actor`, applying `edit` first
+    pub async fn requeue_dead_letter(&self, task_id: &str, edit: DeadLetterEdit, actor: &str) -> Result<Option<TodoTask>, MongoError> {
+        let Some(dead_letter) = self.get_dead_letter(task_id).await? else {
+            return Ok(None);
+        };
+        let mut task = dead_letter.into_requeued(edit);
+        task.history.push(TaskChange::status(ChangeKind::Requeued, actor, Some(&TaskStatus::Failed), &TaskStatus::Pending));
+        self.add_task(task.clone()).await?;
+        self.dead_letter_collection().delete_one(self.scope(doc! { "id": task_id }), None).await?;
+        Ok(Some(task))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dead_letter(failures: &[&str]) -> DeadLetter {
+        DeadLetter {
+            task: TodoTask {
+                id: "t1".to_string(),
+                description: "Open the dashboard".to_string(),
+                enhanced_description: Some("Open the dashboard in the browser".to_string()),
+                priority: TaskPriority::Low,
+                project: None,
+                source_agent: None,
+                target_agent: "browser".to_string(),
+                status: TaskStatus::Failed,
+                created_at: 0,
+                completed_at: None,
+                due_date: None,
+                duration_minutes: None,
+                notes: None,
+                ticket: None,
+                last_modified: None,
+                failure_reason: failures.last().map(|r| r.to_string()),
+                embedding: None,
+                claim: None,
+                tags: Vec::new(),
+                failures: failures.iter()
+                    .map(|reason| TaskFailure { reason: reason.to_string(), failed_at: 0, worker_id: None })
+                    .collect(),
+                confidence: None,
+                reviewed_at: None,
+                tenant_id: None,
+                idempotency_key: None,
+                content_hash: None,
+                history: Vec::new(),
+            },
+            dead_lettered_at: 0,
+        }
+    }
+
+    #[test]
+    fn test_requeue_resets_attempts_and_keeps_history() {
+        let task = dead_letter(&["browser crashed", "Timed out"]).into_requeued(DeadLetterEdit::default());
+        assert_eq!(task.status, TaskStatus::Pending);
+        assert!(task.failures.is_empty() && task.failure_reason.is_none());
+        let notes = task.notes.unwrap();
+        assert!(notes.contains("Failed 2 times") && notes.contains("- browser crashed"), "{}", notes);
+        assert_eq!(task.target_agent, "browser");
+    }
+
+    #[test]
+    fn test_requeue_applies_edits() {
+        let edit = DeadLetterEdit {
+            description: Some("Open the dashboard with curl".to_string()),
+            priority: Some(TaskPriority::High),
+            target_agent: Some("git".to_string()),
+            notes: None,
+        };
+        let task = dead_letter(&[]).into_requeued(edit);
+        assert_eq!((task.target_agent.as_str(), task.priority), ("git", TaskPriority::High));
+        assert_eq!(task.description, "Open the dashboard with curl");
+        assert_eq!(task.enhanced_description, None);
+        assert_eq!(task.notes, None);
+
+        let serialized = bson::to_document(&dead_letter(&["boom"])).unwrap();
+        assert_eq!(serialized.get_str("id").unwrap(), "t1", "the task is stored flat so it can be found by id");
+        assert_eq!(serialized.get_array("failures").unwrap().len(), 1);
+    }
+}
@@ -0,0 +1,87 @@
+//! Tasks repeated by accident, such as a webhook firing twice or a form submitted again, are
+//! spotted by a hash of their description, project and target agent. A task matching one
+//! added within `TODO_DEDUP_WINDOW_SECS` (default 600, `0` turns it off) isn't added; the
+//! earlier task is returned instead. `allow_duplicate` adds it anyway, for tasks meant to
+//! repeat. This replaces the unique index on description that older deployments have,
+//! which refused a repeated task however long ago the first one was; it is dropped on startup.
+
+use std::env;
+use futures_util::TryStreamExt;
+use mongodb::IndexModel;
+use mongodb::bson::doc;
+use mongodb::error::Error as MongoError;
+use mongodb::options::{FindOneOptions, IndexOptions};
+use sha2::{Digest, Sha256};
+use super::todo::{TodoList, TodoTask};
+
+const DEFAULT_WINDOW_SECS: i64 = 600;
+const INDEX_NAME: &str = "tenant_content_hash";
+
+/// The hash duplicates share. Case and runs of whitespace don't make a task different.
+pub fn content_hash(description: &str, project: Option<&str>, target_agent: &str) -> String {
+    let normalize = |text: &str| text.split_whitespace().collect::<Vec<_>>().join(" ").to_lowercase();
+    let mut hasher = Sha256::new();
+    for part in [description, project.unwrap_or(""), target_agent] {
+        hasher.update(normalize(part).as_bytes());
+        // Keeps "a b" + "c" apart from "a" + "b c"
+        hasher.update([0u8]);
+    }
+    hex::encode(hasher.finalize())
+}
+
+/// `TODO_DEDUP_WINDOW_SECS`, or `None` when set to 0
+pub fn window_from_env() -> Option<i64> {
+    let window = env::var("TODO_DEDUP_WINDOW_SECS").ok()
+        .and_then(|secs| secs.parse().ok())
+        .unwrap_or(DEFAULT_WINDOW_SECS);
+    (window > 0).then_some(window)
+}
+
+impl TodoList {
+    /// Create the index duplicate lookups use; a no-op if it already exists
+    pub async fn ensure_content_hash_index(&self) -> Result<(), MongoError> {
+        let index = IndexModel::builder()
+            .keys(doc! { "tenant_id": 1, "content_hash": 1, "created_at": -1 })
+            .options(Some(IndexOptions::builder().name(INDEX_NAME.to_string()).build()))
+            .build();
+        self.collection().create_index(index, None).await?;
+        Ok(())
+    }
+
+    /// Drop a unique index on description alone, returning whether there was one
+    pub async fn drop_unique_description_index(&self) -> Result<bool, MongoError> {
+        let indexes: Vec<IndexModel> = self.collection().list_indexes(None).await?.try_collect().await?;
+        for index in indexes {
+            let on_description = index.keys.len() == 1 && index.keys.contains_key("description");
+            let Some(options) = index.options.filter(|options| options.unique == Some(true)) else { continue };
+            if let (true, Some(name)) = (on_description, options.name) {
+                self.collection().drop_index(&name, None).await?;
+                tracing::info!("Dropped the unique index {} on task descriptions", name);
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+
+    /// The latest task with `content_hash` created at or after `since`
+    pub async fn find_recent_duplicate(&self, content_hash: &str, since: i64) -> Result<Option<TodoTask>, MongoError> {
+        let filter = self.scope(doc! { "content_hash": content_hash, "created_at": { "$gte": since } });
+        let options = FindOneOptions::builder().sort(doc! { "created_at": -1 }).build();
+        self.collection().find_one(filter, options).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_content_hash() {
+        let hash = content_hash("Fix the  login page", Some("web"), "worker");
+        assert_eq!(hash, content_hash("fix the login page ", Some("Web"), "worker"));
+        assert_ne!(hash, content_hash("Fix the login page", Some("api"), "worker"));
+        assert_ne!(hash, content_hash("Fix the login page", Some("web"), "reviewer"));
+        assert_ne!(content_hash("a b", Some("c"), "x"), content_hash("a", Some("b c"), "x"));
+        assert_eq!(content_hash("Task", None, "x"), content_hash("Task", Some(""), "x"));
+    }
+}
@@ -0,0 +1,165 @@
+//! Traits behind `#[derive(Agent)]`. The derive writes the boilerplate `Agent` methods and
+//! leaves the agent to implement `MessageHandler`:
+//!
+//! ```ignore
+//! #[derive(Agent)]
+//! pub struct EchoAgent {
+//!     config: AgentConfig,
+//!     state_manager: AgentStateManager,
+//! }
+//!
+//! #[async_trait]
+//! impl MessageHandler for EchoAgent {
+//!     async fn handle_message(&self, message: Message) -> Result<Message> {
+//!         Ok(Message::new(message.content))
+//!     }
+//! }
+//! ```
+
+use async_trait::async_trait;
+use std::sync::Arc;
+use anyhow::Result;
+use super::{AgentStateManager, Message, State, StateMachine};
+
+pub use swarmonomicon_derive::Agent;
+
+/// What a derived agent does with a message; `process_message` calls it
+#[async_trait]
+pub trait MessageHandler: Send + Sync {
+    async fn handle_message(&self, message: Message) -> Result<Message>;
+}
+
+/// A field a derived agent reads its current state and state machine from
+#[async_trait]
+pub trait StateSource: Send + Sync {
+    async fn current_state(&self) -> Option<State>;
+    async fn state_machine(&self) -> Option<StateMachine>;
+}
+
+#[async_trait]
+impl StateSource for AgentStateManager {
+    async fn current_state(&self) -> Option<State> {
+        self.get_current_state().cloned()
+    }
+
+    async fn state_machine(&self) -> Option<StateMachine> {
+        AgentStateManager::state_machine(self).cloned()
+    }
+}
+
+#[async_trait]
+impl<T: StateSource> StateSource for tokio::sync::RwLock<T> {
+    async fn current_state(&self) -> Option<State> {
+        self.read().await.current_state().await
+    }
+
+    async fn state_machine(&self) -> Option<StateMachine> {
+        self.read().await.state_machine().await
+    }
+}
+
+#[async_trait]
+impl<T: StateSource> StateSource for tokio::sync::Mutex<T> {
+    async fn current_state(&self) -> Option<State> {
+        self.lock().await.current_state().await
+    }
+
+    async fn state_machine(&self) -> Option<StateMachine> {
+        self.lock().await.state_machine().await
+    }
+}
+
+#[async_trait]
+impl StateSource for std::sync::Mutex<AgentStateManager> {
+    async fn current_state(&self) -> Option<State> {
+        self.lock().unwrap().get_current_state().cloned()
+    }
+
+    async fn state_machine(&self) -> Option<StateMachine> {
+        self.lock().unwrap().state_machine().cloned()
+    }
+}
+
+#[async_trait]
+impl<T: StateSource + ?Sized> StateSource for Arc<T> {
+    async fn current_state(&self) -> Option<State> {
+        (**self).current_state().await
+    }
+
+    async fn state_machine(&self) -> Option<StateMachine> {
+        (**self).state_machine().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use tokio::sync::RwLock;
+    use crate::error::SwarmError;
+    use crate::types::{Agent, AgentConfig, Tool};
+
+    #[derive(Agent)]
+    struct EchoAgent {
+        config: AgentConfig,
+        state_manager: Arc<RwLock<AgentStateManager>>,
+    }
+
+    #[async_trait]
+    impl MessageHandler for EchoAgent {
+        async fn handle_message(&self, message: Message) -> Result<Message> {
+            Ok(Message::new(format!("echo: {}", message.content)))
+        }
+    }
+
+    #[derive(Agent)]
+    #[agent(call_tool = "run_tool")]
+    struct ToolAgent {
+        #[agent(config)]
+        settings: AgentConfig,
+    }
+
+    impl ToolAgent {
+        async fn run_tool(&self, tool: &Tool, params: HashMap<String, String>) -> Result<String> {
+            Ok(format!("{}({})", tool.name, params.len()))
+        }
+    }
+
+    #[async_trait]
+    impl MessageHandler for ToolAgent {
+        async fn handle_message(&self, message: Message) -> Result<Message> {
+            Ok(message)
+        }
+    }
+
+    fn state_machine() -> StateMachine {
+        serde_yaml::from_str("initial_state: idle\nstates:\n  idle:\n    name: idle\n    data: null\n    prompt: Waiting\n    transitions: null\n    validation: null\n").unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_derived_agent_fills_in_the_boilerplate() {
+        let config = AgentConfig::builder("echo").with_state_machine(state_machine()).build().unwrap();
+        let agent = EchoAgent {
+            state_manager: Arc::new(RwLock::new(AgentStateManager::new(config.state_machine.clone()))),
+            config,
+        };
+        assert_eq!(agent.process_message(Message::new("hi".to_string())).await.unwrap().content, "echo: hi");
+        assert_eq!(agent.get_config().await.unwrap().name, "echo");
+        assert_eq!(agent.get_current_state().await.unwrap().unwrap().name, "idle");
+        assert_eq!(agent.get_state_machine().await.unwrap().unwrap().initial_state, "idle");
+        let message = agent.transfer_to("haiku".to_string(), Message::new("hi".to_string())).await.unwrap();
+        assert_eq!(message.content, "hi");
+        let err = agent.call_tool(&Tool::new("lookup", "Find"), HashMap::new()).await.unwrap_err();
+        assert!(matches!(SwarmError::find(&err), Some(SwarmError::ToolFailure { .. })));
+        assert!(err.to_string().contains("EchoAgent does not support tool calls"));
+    }
+
+    #[tokio::test]
+    async fn test_marked_fields_and_overrides() {
+        let agent = ToolAgent { settings: AgentConfig::builder("tools").build().unwrap() };
+        assert_eq!(agent.get_config().await.unwrap().name, "tools");
+        assert!(agent.get_current_state().await.unwrap().is_none());
+        let params = HashMap::from([("q".to_string(), "x".to_string())]);
+        assert_eq!(agent.call_tool(&Tool::new("lookup", "Find"), params).await.unwrap(), "lookup(1)");
+    }
+}
@@ -0,0 +1,223 @@
+//! What happened to tasks over a window of time, and a narrative summary of it written by
+//! the AI provider, for the digest agent and `/api/digest`.
+
+use std::collections::{BTreeMap, HashMap};
+use mongodb::bson::doc;
+use mongodb::error::Error as MongoError;
+use futures_util::TryStreamExt;
+use serde::Serialize;
+use utoipa::ToSchema;
+use crate::ai::AiProvider;
+use super::todo::{TodoList, TodoTask, TaskStatus};
+
+const UNASSIGNED_PROJECT: &str = "unassigned";
+
+const DIGEST_PROMPT: &str = "You write a short daily digest of a team's task activity. \
+For each project, summarize in two or three sentences what was finished, what failed and why, \
+and what new work arrived. Lead with the project's name. Plain text, no preamble.";
+
+/// Tasks created, completed or failed between `since` and `until` (Unix seconds)
+#[derive(Debug, Clone, Default)]
+pub struct Activity {
+    pub since: i64,
+    pub until: i64,
+    pub created: Vec<TodoTask>,
+    pub completed: Vec<TodoTask>,
+    pub failed: Vec<TodoTask>,
+}
+
+impl Activity {
+    /// Sort `tasks` into the window; a task created and finished inside it counts for both
+    pub fn from_tasks(tasks: impl IntoIterator<Item = TodoTask>, since: i64, until: i64) -> Self {
+        let within = |at: Option<i64>| at.is_some_and(|at| at >= since && at < until);
+        let mut activity = Self { since, until, ..Default::default() };
+        for task in tasks {
+            let finished = match task.status {
+                TaskStatus::Completed if within(task.completed_at) => Some(&mut activity.completed),
+                TaskStatus::Failed if within(task.last_modified) => Some(&mut activity.failed),
+                _ => None,
+            };
+            match (within(Some(task.created_at)), finished) {
+                (true, Some(list)) => {
+                    list.push(task.clone());
+                    activity.created.push(task);
+                }
+                (false, Some(list)) => list.push(task),
+                (true, None) => activity.created.push(task),
+                (false, None) => {}
+            }
+        }
+        activity
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.created.is_empty() && self.completed.is_empty() && self.failed.is_empty()
+    }
+}
+
+impl TodoList {
+    /// Tasks created, completed or failed between `since` and `until`
+    pub async fn activity(&self, since: i64, until: i64) -> Result<Activity, MongoError> {
+        let window = doc! { "$gte": since, "$lt": until };
+        let filter = self.scope(doc! {
+            "$or": [
+                { "created_at": window.clone() },
+                { "completed_at": window.clone() },
+                { "last_modified": window },
+            ]
+        });
+        let tasks: Vec<TodoTask> = self.collection().find(filter, None).await?.try_collect().await?;
+        Ok(Activity::from_tasks(tasks, since, until))
+    }
+}
+
+/// The activity as plain text grouped by project, used as the prompt and as the digest
+/// when the AI provider is unavailable
+pub fn format_activity(activity: &Activity) -> String {
+    if activity.is_empty() {
+        return "No task activity.".to_string();
+    }
+    let mut by_project: BTreeMap<&str, [Vec<&TodoTask>; 3]> = BTreeMap::new();
+    for (index, tasks) in [&activity.completed, &activity.failed, &activity.created].into_iter().enumerate() {
+        for task in tasks {
+            by_project.entry(task.project.as_deref().unwrap_or(UNASSIGNED_PROJECT)).or_default()[index].push(task);
+        }
+    }
+    let mut output = String::new();
+    for (project, groups) in by_project {
+        output.push_str(&format!("{}\n", project));
+        for (label, tasks) in ["Completed", "Failed", "Created"].into_iter().zip(groups) {
+            if tasks.is_empty() {
+                continue;
+            }
+            output.push_str(&format!("  {} ({}):\n", label, tasks.len()));
+            for task in tasks {
+                match task.failure_reason.as_deref().filter(|_| label == "Failed") {
+                    Some(reason) => output.push_str(&format!("  - {} ({}): {}\n", task.description, task.target_agent, reason)),
+                    None => output.push_str(&format!("  - {} ({})\n", task.description, task.target_agent)),
+                }
+            }
+        }
+    }
+    output
+}
+
+/// A summary of one window of activity
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct Digest {
+    pub since: i64,
+    pub until: i64,
+    pub created: usize,
+    pub completed: usize,
+    pub failed: usize,
+    pub summary: String,
+}
+
+/// Ask `ai_client` to narrate `activity`, falling back to the plain listing if it fails
+pub async fn summarize(activity: &Activity, ai_client: &dyn AiProvider) -> Digest {
+    let listing = format_activity(activity);
+    let summary = if activity.is_empty() {
+        listing
+    } else {
+        let messages = vec![HashMap::from([
+            ("role".to_string(), "user".to_string()),
+            ("content".to_string(), listing.clone()),
+        ])];
+        match ai_client.chat(DIGEST_PROMPT, messages).await {
+            Ok(summary) if !summary.trim().is_empty() => summary.trim().to_string(),
+            Ok(_) => listing,
+            Err(e) => {
+                tracing::warn!("Digest summary failed, sending the task list instead: {}", e);
+                listing
+            }
+        }
+    };
+    Digest {
+        since: activity.since,
+        until: activity.until,
+        created: activity.created.len(),
+        completed: activity.completed.len(),
+        failed: activity.failed.len(),
+        summary,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::{Result, anyhow};
+    use crate::types::TaskPriority;
+
+    fn task(project: Option<&str>, description: &str, status: TaskStatus, created_at: i64, finished_at: Option<i64>) -> TodoTask {
+        TodoTask {
+            id: description.to_string(),
+            description: description.to_string(),
+            enhanced_description: None,
+            priority: TaskPriority::Medium,
+            project: project.map(str::to_string),
+            source_agent: None,
+            target_agent: "git".to_string(),
+            completed_at: finished_at.filter(|_| status == TaskStatus::Completed),
+            last_modified: finished_at,
+            failure_reason: (status == TaskStatus::Failed).then(|| "timed out".to_string()),
+            status,
+            created_at,
+            due_date: None,
+            duration_minutes: None,
+            notes: None,
+            ticket: None,
+            embedding: None,
+            claim: None,
+            failures: Vec::new(),
+            confidence: None,
+            reviewed_at: None,
+            tenant_id: None,
+            idempotency_key: None,
+            content_hash: None,
+            history: Vec::new(),
+            tags: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_activity_sorts_tasks_into_the_window() {
+        let tasks = vec![
+            task(Some("swarm"), "ship", TaskStatus::Completed, 50, Some(150)),
+            task(Some("swarm"), "lint", TaskStatus::Failed, 120, Some(130)),
+            task(None, "triage", TaskStatus::Pending, 110, None),
+            task(Some("web"), "old", TaskStatus::Completed, 10, Some(20)),
+        ];
+        let activity = Activity::from_tasks(tasks, 100, 200);
+        let ids = |tasks: &[TodoTask]| tasks.iter().map(|t| t.id.clone()).collect::<Vec<_>>();
+        assert_eq!(ids(&activity.completed), ["ship"]);
+        assert_eq!(ids(&activity.failed), ["lint"]);
+        assert_eq!(ids(&activity.created), ["lint", "triage"]);
+        assert_eq!(
+            format_activity(&activity),
+            "swarm\n  Completed (1):\n  - ship (git)\n  Failed (1):\n  - lint (git): timed out\n  Created (1):\n  - lint (git)\n\
+             unassigned\n  Created (1):\n  - triage (git)\n"
+        );
+        assert_eq!(format_activity(&Activity::default()), "No task activity.");
+    }
+
+    struct Narrator(Option<&'static str>);
+
+    #[async_trait::async_trait]
+    impl AiProvider for Narrator {
+        async fn chat(&self, system_prompt: &str, messages: Vec<HashMap<String, String>>) -> Result<String> {
+            assert_eq!(system_prompt, DIGEST_PROMPT);
+            assert!(messages[0]["content"].contains("- ship (git)"));
+            self.0.map(str::to_string).ok_or_else(|| anyhow!("offline"))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_summarize_falls_back_to_the_listing() {
+        let activity = Activity::from_tasks(vec![task(Some("swarm"), "ship", TaskStatus::Completed, 150, Some(160))], 100, 200);
+        let digest = summarize(&activity, &Narrator(Some(" swarm shipped. \n"))).await;
+        assert_eq!((digest.created, digest.completed, digest.failed), (1, 1, 0));
+        assert_eq!(digest.summary, "swarm shipped.");
+        let fallback = summarize(&activity, &Narrator(None)).await;
+        assert_eq!(fallback.summary, format_activity(&activity));
+    }
+}
@@ -0,0 +1,214 @@
+//! Changing a task after it was added: its description, priority, project or agent. Each
+//! edit that changes something is kept in the task's history. Reassigning a task that is
+//! in progress puts it back in the queue for its new agent; the old worker loses its claim.
+
+use mongodb::bson::{self, doc};
+use mongodb::error::Error as MongoError;
+use serde::Deserialize;
+use utoipa::ToSchema;
+use crate::error::SwarmError;
+use super::dedup;
+use super::history::{self, ChangeKind, TaskChange};
+use super::todo::{TaskPriority, TaskStatus, TodoList, TodoTask};
+
+/// The fields to change; unset ones are kept
+#[derive(Debug, Clone, Default, Deserialize, ToSchema)]
+pub struct TaskEdit {
+    pub description: Option<String>,
+    pub priority: Option<TaskPriority>,
+    pub project: Option<String>,
+    pub target_agent: Option<String>,
+}
+
+impl TaskEdit {
+    pub fn reassign(target_agent: impl Into<String>) -> Self {
+        Self { target_agent: Some(target_agent.into()), ..Self::default() }
+    }
+
+    /// Refuse a blank description, or an agent `agent_exists` doesn't know
+    pub fn validate(&self, agent_exists: impl Fn(&str) -> bool) -> Result<(), SwarmError> {
+        if self.description.as_deref().is_some_and(|d| d.trim().is_empty()) {
+            return Err(SwarmError::Validation("A task's description can't be blank".to_string()));
+        }
+        match &self.target_agent {
+            Some(agent) if !agent_exists(agent) => Err(SwarmError::Validation(format!("No agent named {}", agent))),
+            _ => Ok(()),
+        }
+    }
+
+    /// Apply to `task`, adding to its history; the entry for what changed, if anything did
+    pub fn apply(self, task: &mut TodoTask, actor: &str) -> Option<TaskChange> {
+        let reassigned = self.target_agent.as_ref().is_some_and(|agent| *agent != task.target_agent);
+        let mut change = TaskChange::new(if reassigned { ChangeKind::Reassigned } else { ChangeKind::Edited }, actor);
+        if let Some(description) = self.description.map(|d| d.trim().to_string()) {
+            change = change.with_field("description", Some(task.description.clone()), Some(description.clone()));
+            if description != task.description {
+                // Both describe the old text
+                task.enhanced_description = None;
+                task.embedding = None;
+                task.description = description;
+            }
+        }
+        if let Some(priority) = self.priority {
+            change = change.with_field("priority", Some(format!("{:?}", task.priority)), Some(format!("{:?}", priority)));
+            task.priority = priority;
+        }
+        if let Some(project) = self.project {
+            change = change.with_field("project", task.project.clone(), Some(project.clone()));
+            task.project = Some(project);
+        }
+        if let Some(target_agent) = self.target_agent {
+            change = change.with_field("target_agent", Some(task.target_agent.clone()), Some(target_agent.clone()));
+            task.target_agent = target_agent;
+        }
+        if reassigned && task.status == TaskStatus::InProgress {
+            change = change
+                .with_field("status", Some(history::status_name(&task.status)), Some(history::status_name(&TaskStatus::Pending)))
+                .with_reason("Reassigned while in progress");
+            task.status = TaskStatus::Pending;
+            task.claim = None;
+        }
+        if change.fields.is_empty() {
+            return None;
+        }
+        if task.content_hash.is_some() {
+            task.content_hash = Some(dedup::content_hash(&task.description, task.project.as_deref(), &task.target_agent));
+        }
+        task.last_modified = Some(change.at);
+        task.history.push(change.clone());
+        Some(change)
+    }
+}
+
+impl TodoList {
+    /// Apply `edit` to a task on behalf of `actor`. Returns the task, or `None` if it
+    /// doesn't exist; a task the edit leaves as it was isn't written. The write only lands
+    /// if nobody changed the task since it was read; otherwise the edit is applied again.
+    pub async fn edit_task(&self, task_id: &str, edit: TaskEdit, actor: &str) -> Result<Option<TodoTask>, MongoError> {
+        loop {
+            let Some(mut task) = self.get_task(task_id).await? else {
+                return Ok(None);
+            };
+            let read_at = task.last_modified;
+            let Some(change) = edit.clone().apply(&mut task, actor) else {
+                return Ok(Some(task));
+            };
+            let mut set = doc! {
+                "description": &task.description,
+                "enhanced_description": bson::to_bson(&task.enhanced_description)?,
+                "priority": bson::to_bson(&task.priority)?,
+                "project": bson::to_bson(&task.project)?,
+                "target_agent": &task.target_agent,
+                "status": bson::to_bson(&task.status)?,
+                "last_modified": change.at,
+            };
+            if let Some(hash) = &task.content_hash {
+                set.insert("content_hash", hash);
+            }
+            let mut unset = doc! {};
+            if task.embedding.is_none() {
+                unset.insert("embedding", "");
+            }
+            if task.claim.is_none() {
+                unset.insert("claim", "");
+            }
+            let mut update = doc! { "$set": set, "$push": change.push()? };
+            if !unset.is_empty() {
+                update.insert("$unset", unset);
+            }
+            // A missing last_modified matches null
+            let filter = self.scope(doc! { "id": task_id, "last_modified": bson::to_bson(&read_at)? });
+            if self.collection().update_one(filter, update, None).await?.matched_count == 0 {
+                tracing::debug!("Task {} changed while being edited, editing it again", task_id);
+                continue;
+            }
+            tracing::info!("Task {} {:?} by {}", task_id, change.kind, actor);
+            return Ok(Some(task));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn task() -> TodoTask {
+        TodoTask {
+            id: "t1".to_string(),
+            description: "Fix login redirect".to_string(),
+            enhanced_description: Some("Fix the redirect after login".to_string()),
+            priority: TaskPriority::Low,
+            project: Some("web".to_string()),
+            source_agent: None,
+            target_agent: "worker".to_string(),
+            status: TaskStatus::Pending,
+            created_at: 100,
+            completed_at: None,
+            due_date: None,
+            duration_minutes: None,
+            notes: None,
+            ticket: None,
+            last_modified: Some(100),
+            failure_reason: None,
+            embedding: Some(vec![0.1, 0.2]),
+            claim: None,
+            tags: Vec::new(),
+            failures: Vec::new(),
+            confidence: None,
+            reviewed_at: None,
+            tenant_id: None,
+            idempotency_key: None,
+            content_hash: None,
+            history: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_apply_records_what_changed() {
+        let mut task = task();
+        let edit = TaskEdit {
+            description: Some(" Fix login redirect on Safari ".to_string()),
+            priority: Some(TaskPriority::Low),
+            project: None,
+            target_agent: Some("git".to_string()),
+        };
+        let change = edit.apply(&mut task, "todo_tool").unwrap();
+
+        assert_eq!(change.kind, ChangeKind::Reassigned);
+        let fields: Vec<&str> = change.fields.iter().map(|f| f.field.as_str()).collect();
+        assert_eq!(fields, ["description", "target_agent"]);
+        assert_eq!(task.description, "Fix login redirect on Safari");
+        assert_eq!(task.enhanced_description, None);
+        assert_eq!(task.embedding, None);
+        assert_eq!(task.history, vec![change]);
+
+        assert!(TaskEdit::reassign("git").apply(&mut task, "todo_tool").is_none());
+        assert_eq!(task.history.len(), 1);
+    }
+
+    #[test]
+    fn test_reassigning_a_task_in_progress_requeues_it() {
+        let mut task = task();
+        task.status = TaskStatus::InProgress;
+        task.claim = Some(crate::types::claims::ClaimConfig::default().claim_until(100));
+        let change = TaskEdit::reassign("git").apply(&mut task, "todo_tool").unwrap();
+
+        let fields: Vec<&str> = change.fields.iter().map(|f| f.field.as_str()).collect();
+        assert_eq!(fields, ["target_agent", "status"]);
+        assert_eq!(task.status, TaskStatus::Pending);
+        assert!(task.claim.is_none());
+
+        let mut pending = self::task();
+        TaskEdit { priority: Some(TaskPriority::High), ..TaskEdit::default() }.apply(&mut pending, "todo_tool").unwrap();
+        assert_eq!(pending.status, TaskStatus::Pending);
+    }
+
+    #[test]
+    fn test_validate() {
+        let known = |agent: &str| agent == "git";
+        assert!(TaskEdit::reassign("git").validate(known).is_ok());
+        assert!(matches!(TaskEdit::reassign("nobody").validate(known), Err(SwarmError::Validation(_))));
+        let blank = TaskEdit { description: Some("  ".to_string()), ..TaskEdit::default() };
+        assert!(matches!(blank.validate(known), Err(SwarmError::Validation(_))));
+    }
+}
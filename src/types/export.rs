@@ -0,0 +1,374 @@
+//! Export tasks to JSON Lines or CSV and import them back, e.g. to back up a queue or move it
+//! to another deployment. JSON Lines keeps every field; CSV drops embeddings, claims and
+//! failure history so it stays readable in a spreadsheet.
+
+use std::collections::{HashMap, HashSet};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::path::Path;
+use std::str::FromStr;
+use anyhow::{Result, anyhow};
+use futures_util::TryStreamExt;
+use mongodb::bson::{self, Document, doc};
+use mongodb::options::FindOptions;
+use serde::{Deserialize, Serialize};
+use super::archive::ArchivedTask;
+use super::dedup;
+use super::tags::parse_tags;
+use super::todo::{TaskPriority, TaskStatus, TodoList, TodoTask};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    JsonLines,
+    Csv,
+}
+
+impl FromStr for ExportFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "jsonl" | "json" | "ndjson" => Ok(ExportFormat::JsonLines),
+            "csv" => Ok(ExportFormat::Csv),
+            other => Err(anyhow!("Unknown format '{}'; expected jsonl or csv", other)),
+        }
+    }
+}
+
+impl ExportFormat {
+    /// The format a file's extension implies, defaulting to JSON Lines
+    pub fn for_path(path: &Path) -> Self {
+        path.extension()
+            .and_then(|ext| ext.to_str())
+            .and_then(|ext| ext.parse().ok())
+            .unwrap_or(ExportFormat::JsonLines)
+    }
+}
+
+/// Which tasks to export; empty fields match everything
+#[derive(Debug, Clone, Default)]
+pub struct ExportFilter {
+    pub target_agent: Option<String>,
+    pub project: Option<String>,
+    pub status: Option<TaskStatus>,
+    /// Tasks must have all of these
+    pub tags: Vec<String>,
+    pub include_archived: bool,
+}
+
+impl ExportFilter {
+    pub fn to_document(&self) -> Result<Document> {
+        let mut filter = Document::new();
+        if let Some(agent) = &self.target_agent {
+            filter.insert("target_agent", agent);
+        }
+        if let Some(project) = &self.project {
+            filter.insert("project", project);
+        }
+        if let Some(status) = &self.status {
+            filter.insert("status", bson::to_bson(status)?);
+        }
+        if !self.tags.is_empty() {
+            filter.insert("tags", doc! { "$all": &self.tags });
+        }
+        Ok(filter)
+    }
+}
+
+/// One CSV row. Optional fields are empty cells, tags are comma-separated in one cell.
+#[derive(Debug, Serialize, Deserialize)]
+struct CsvTask {
+    id: String,
+    description: String,
+    enhanced_description: Option<String>,
+    priority: TaskPriority,
+    project: Option<String>,
+    source_agent: Option<String>,
+    target_agent: String,
+    status: TaskStatus,
+    created_at: i64,
+    completed_at: Option<i64>,
+    due_date: Option<String>,
+    duration_minutes: Option<i32>,
+    notes: Option<String>,
+    ticket: Option<String>,
+    last_modified: Option<i64>,
+    failure_reason: Option<String>,
+    #[serde(default)]
+    tags: String,
+}
+
+impl From<&TodoTask> for CsvTask {
+    fn from(task: &TodoTask) -> Self {
+        Self {
+            id: task.id.clone(),
+            description: task.description.clone(),
+            enhanced_description: task.enhanced_description.clone(),
+            priority: task.priority.clone(),
+            project: task.project.clone(),
+            source_agent: task.source_agent.clone(),
+            target_agent: task.target_agent.clone(),
+            status: task.status.clone(),
+            created_at: task.created_at,
+            completed_at: task.completed_at,
+            due_date: task.due_date.clone(),
+            duration_minutes: task.duration_minutes,
+            notes: task.notes.clone(),
+            ticket: task.ticket.clone(),
+            last_modified: task.last_modified,
+            failure_reason: task.failure_reason.clone(),
+            tags: task.tags.join(","),
+        }
+    }
+}
+
+impl From<CsvTask> for TodoTask {
+    fn from(row: CsvTask) -> Self {
+        Self {
+            id: row.id,
+            description: row.description,
+            enhanced_description: row.enhanced_description,
+            priority: row.priority,
+            project: row.project,
+            source_agent: row.source_agent,
+            target_agent: row.target_agent,
+            status: row.status,
+            created_at: row.created_at,
+            completed_at: row.completed_at,
+            due_date: row.due_date,
+            duration_minutes: row.duration_minutes,
+            notes: row.notes,
+            ticket: row.ticket,
+            last_modified: row.last_modified,
+            failure_reason: row.failure_reason,
+            embedding: None,
+            claim: None,
+            failures: Vec::new(),
+            confidence: None,
+            reviewed_at: None,
+            tenant_id: None,
+            idempotency_key: None,
+            content_hash: None,
+            history: Vec::new(),
+            tags: parse_tags(&row.tags),
+        }
+    }
+}
+
+/// Write `tasks` in `format`, returning how many were written
+pub fn write_tasks(tasks: &[TodoTask], format: ExportFormat, writer: impl Write) -> Result<usize> {
+    match format {
+        ExportFormat::JsonLines => {
+            let mut writer = writer;
+            for task in tasks {
+                serde_json::to_writer(&mut writer, task)?;
+                writer.write_all(b"\n")?;
+            }
+            writer.flush()?;
+        }
+        ExportFormat::Csv => {
+            let mut writer = csv::Writer::from_writer(writer);
+            for task in tasks {
+                writer.serialize(CsvTask::from(task))?;
+            }
+            writer.flush()?;
+        }
+    }
+    Ok(tasks.len())
+}
+
+/// Read tasks written by `write_tasks`. Line and row numbers in errors are 1-based.
+pub fn read_tasks(format: ExportFormat, reader: impl Read) -> Result<Vec<TodoTask>> {
+    match format {
+        ExportFormat::JsonLines => BufReader::new(reader).lines()
+            .enumerate()
+            .filter(|(_, line)| line.as_ref().map_or(true, |l| !l.trim().is_empty()))
+            .map(|(n, line)| {
+                serde_json::from_str(&line?).map_err(|e| anyhow!("Line {}: {}", n + 1, e))
+            })
+            .collect(),
+        ExportFormat::Csv => csv::Reader::from_reader(reader).deserialize::<CsvTask>()
+            .enumerate()
+            .map(|(n, row)| row.map(TodoTask::from).map_err(|e| anyhow!("Row {}: {}", n + 1, e)))
+            .collect(),
+    }
+}
+
+/// What an import did
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct ImportReport {
+    pub imported: usize,
+    /// Tasks whose id was already present, or that repeat a task added within the
+    /// duplicate window
+    pub duplicates: usize,
+}
+
+/// Split `incoming` into tasks to import and the number of duplicates. A task is a
+/// duplicate if its id is taken or, with a `window`, if a task with its content hash was
+/// added within `window` seconds of it; `added` holds when existing tasks with each hash
+/// were added, and earlier tasks in the same import count too. Imported tasks get their
+/// hash so later adds are checked against them.
+fn dedupe(incoming: Vec<TodoTask>, mut ids: HashSet<String>, mut added: HashMap<String, Vec<i64>>, window: Option<i64>) -> (Vec<TodoTask>, usize) {
+    let mut fresh = Vec::new();
+    let mut duplicates = 0;
+    for mut task in incoming {
+        if ids.contains(&task.id) {
+            duplicates += 1;
+            continue;
+        }
+        if let Some(window) = window {
+            let hash = dedup::content_hash(&task.description, task.project.as_deref(), &task.target_agent);
+            let times = added.entry(hash.clone()).or_default();
+            if times.iter().any(|&at| (at - task.created_at).abs() <= window) {
+                duplicates += 1;
+                continue;
+            }
+            times.push(task.created_at);
+            task.content_hash = Some(hash);
+        }
+        ids.insert(task.id.clone());
+        fresh.push(task);
+    }
+    (fresh, duplicates)
+}
+
+/// Claims belong to the workers of the deployment the task came from, and imports into a
+/// tenant's list belong to that tenant
+fn prepare_for_import(mut task: TodoTask, tenant: Option<&str>) -> TodoTask {
+    task.claim = None;
+    if let Some(tenant) = tenant {
+        task.tenant_id = Some(tenant.to_string());
+    }
+    if task.status == TaskStatus::InProgress {
+        task.status = TaskStatus::Pending;
+    }
+    task
+}
+
+impl TodoList {
+    /// Tasks matching `filter`, oldest first
+    pub async fn export_tasks(&self, filter: &ExportFilter) -> Result<Vec<TodoTask>> {
+        let query = self.scope(filter.to_document()?);
+        let options = FindOptions::builder().sort(doc! { "created_at": 1 }).build();
+        let mut tasks: Vec<TodoTask> = self.collection().find(query.clone(), options.clone()).await?.try_collect().await?;
+        if filter.include_archived {
+            let archived: Vec<ArchivedTask> = self.archive_collection().find(query, options).await?.try_collect().await?;
+            tasks.extend(archived.into_iter().map(|archived| archived.task));
+            tasks.sort_by_key(|task| task.created_at);
+        }
+        Ok(tasks)
+    }
+
+    /// Add `tasks`, skipping any whose id is present and, unless `allow_duplicate`, any
+    /// that repeat a task added within the list's duplicate window, as adding them one by
+    /// one would. With `dry_run` nothing is written but the report says what would happen.
+    pub async fn import_tasks(&self, tasks: Vec<TodoTask>, allow_duplicate: bool, dry_run: bool) -> Result<ImportReport> {
+        let window = if allow_duplicate { None } else { self.dedup_window() };
+        let projection = FindOptions::builder().projection(doc! { "id": 1, "content_hash": 1, "created_at": 1 }).build();
+        let mut ids = HashSet::new();
+        let mut added: HashMap<String, Vec<i64>> = HashMap::new();
+        let mut existing = self.collection().clone_with_type::<Document>().find(self.scope(doc! {}), projection.clone()).await?;
+        while let Some(task) = existing.try_next().await? {
+            ids.insert(task.get_str("id").unwrap_or_default().to_string());
+            if let (Some(_), Ok(hash), Ok(created_at)) = (window, task.get_str("content_hash"), task.get_i64("created_at")) {
+                added.entry(hash.to_string()).or_default().push(created_at);
+            }
+        }
+        let mut archived = self.archive_collection().clone_with_type::<Document>().find(self.scope(doc! {}), projection).await?;
+        while let Some(task) = archived.try_next().await? {
+            ids.insert(task.get_str("id").unwrap_or_default().to_string());
+        }
+
+        let (fresh, duplicates) = dedupe(tasks, ids, added, window);
+        let report = ImportReport { imported: fresh.len(), duplicates };
+        if !dry_run && !fresh.is_empty() {
+            self.collection().insert_many(fresh.into_iter().map(|task| prepare_for_import(task, self.tenant())), None).await?;
+        }
+        Ok(report)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn task(id: &str, description: &str) -> TodoTask {
+        TodoTask {
+            id: id.to_string(),
+            description: description.to_string(),
+            enhanced_description: Some("Steps:\n1. \"quote\", comma".to_string()),
+            priority: TaskPriority::High,
+            project: Some("swarm".to_string()),
+            source_agent: None,
+            target_agent: "git".to_string(),
+            status: TaskStatus::InProgress,
+            created_at: 100,
+            completed_at: None,
+            due_date: None,
+            duration_minutes: Some(15),
+            notes: None,
+            ticket: None,
+            last_modified: Some(120),
+            failure_reason: None,
+            embedding: Some(vec![0.5, 0.25]),
+            claim: None,
+            tags: vec!["bug".to_string(), "api".to_string()],
+            failures: Vec::new(),
+            confidence: None,
+            reviewed_at: None,
+            tenant_id: None,
+            idempotency_key: None,
+            content_hash: None,
+            history: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_round_trips_through_both_formats() {
+        let tasks = vec![task("a", "Fix the login"), task("b", "Write docs")];
+
+        let mut jsonl = Vec::new();
+        assert_eq!(write_tasks(&tasks, ExportFormat::JsonLines, &mut jsonl).unwrap(), 2);
+        let read = read_tasks(ExportFormat::JsonLines, jsonl.as_slice()).unwrap();
+        assert_eq!(serde_json::to_value(&read).unwrap(), serde_json::to_value(&tasks).unwrap());
+
+        let mut csv = Vec::new();
+        write_tasks(&tasks, ExportFormat::Csv, &mut csv).unwrap();
+        let read = read_tasks(ExportFormat::Csv, csv.as_slice()).unwrap();
+        assert_eq!(read.len(), 2);
+        assert_eq!(read[0].enhanced_description, tasks[0].enhanced_description);
+        assert_eq!((read[0].status.clone(), read[0].priority.clone()), (TaskStatus::InProgress, TaskPriority::High));
+        assert_eq!(read[1].tags, ["bug", "api"]);
+        assert_eq!((read[1].source_agent.clone(), read[1].embedding.clone()), (None, None), "CSV leaves out embeddings");
+
+        assert_eq!(ExportFormat::for_path(Path::new("backup.CSV")), ExportFormat::Csv);
+        assert_eq!(ExportFormat::for_path(Path::new("backup")), ExportFormat::JsonLines);
+        assert!(read_tasks(ExportFormat::JsonLines, "{}\n".as_bytes()).unwrap_err().to_string().starts_with("Line 1"));
+    }
+
+    #[test]
+    fn test_dedupe_by_id_and_content_hash() {
+        let existing_ids = HashSet::from(["a".to_string()]);
+        let existing = task("x", "Write docs");
+        let hash = |task: &TodoTask| dedup::content_hash(&task.description, task.project.as_deref(), &task.target_agent);
+        let added = HashMap::from([(hash(&existing), vec![existing.created_at])]);
+        let mut later = task("e", "Fix the login");
+        later.created_at += 601;
+        let incoming = vec![
+            task("a", "Something new"),
+            task("b", "  Write  Docs "),
+            task("c", "Fix the login"),
+            task("d", "fix the login"),
+            later,
+        ];
+        let (fresh, duplicates) = dedupe(incoming.clone(), existing_ids.clone(), added.clone(), Some(600));
+        assert_eq!(fresh.iter().map(|t| t.id.as_str()).collect::<Vec<_>>(), ["c", "e"]);
+        assert_eq!(duplicates, 3);
+        assert_eq!(fresh[0].content_hash, Some(hash(&fresh[0])));
+
+        let (fresh, duplicates) = dedupe(incoming, existing_ids, added, None);
+        assert_eq!((fresh.len(), duplicates), (4, 1), "without a window only ids are compared");
+
+        let imported = prepare_for_import(fresh.into_iter().next().unwrap(), None);
+        assert_eq!(imported.status, TaskStatus::Pending);
+    }
+}
@@ -0,0 +1,205 @@
+//! Handing a task to another agent and getting its answer back. Processors store the
+//! response `Message` of each task they complete in the `task_results` collection; a
+//! `Handoff` waits for the task to finish, woken by task events in this process and polling
+//! for tasks finished by others such as the todo worker. Chaining handoffs composes agents,
+//! e.g. greeter → project-init → git, each step starting from the last one's reply.
+
+use std::time::Duration;
+use chrono::Utc;
+use futures_util::StreamExt;
+use mongodb::bson::doc;
+use mongodb::error::Error as MongoError;
+use mongodb::options::ReplaceOptions;
+use serde::{Deserialize, Serialize};
+use anyhow::{Result, anyhow};
+use crate::events;
+use super::Message;
+use super::todo::{TodoList, TodoTask, TaskStatus};
+
+const POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+/// The response an agent gave to a task it completed
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TaskResult {
+    pub task_id: String,
+    /// The agent that processed the task
+    pub agent: String,
+    pub response: Message,
+    pub completed_at: i64,
+}
+
+/// How a handed-off task ended
+#[derive(Debug, Clone)]
+pub enum HandoffOutcome {
+    Completed(Message),
+    /// Gave up for good, after its retries
+    Failed(String),
+    Cancelled,
+}
+
+impl HandoffOutcome {
+    /// The response of a completed task, or an error describing why there is none
+    pub fn into_response(self) -> Result<Message> {
+        match self {
+            HandoffOutcome::Completed(response) => Ok(response),
+            HandoffOutcome::Failed(reason) => Err(anyhow!("Handed-off task failed: {}", reason)),
+            HandoffOutcome::Cancelled => Err(anyhow!("Handed-off task was cancelled")),
+        }
+    }
+}
+
+/// The outcome for a task in `task`'s state, or `None` while it is unfinished. A completed
+/// task whose processor didn't store a response completes with an empty message.
+fn outcome_of(task: &TodoTask, result: Option<TaskResult>) -> Option<HandoffOutcome> {
+    match (&task.status, result) {
+        (_, Some(result)) => Some(HandoffOutcome::Completed(result.response)),
+        (TaskStatus::Completed, None) => Some(HandoffOutcome::Completed(Message::new(String::new()))),
+        (TaskStatus::Failed, None) => Some(HandoffOutcome::Failed(task.failure_reason.clone().unwrap_or_else(|| "unknown".to_string()))),
+        (TaskStatus::Cancelled, None) => Some(HandoffOutcome::Cancelled),
+        _ => None,
+    }
+}
+
+impl TodoList {
+    /// Store `response` as the task's result, then mark it completed
+    pub async fn complete_with_response(&self, task_id: &str, agent: &str, response: &Message) -> Result<(), MongoError> {
+        let result = TaskResult {
+            task_id: task_id.to_string(),
+            agent: agent.to_string(),
+            response: response.clone(),
+            completed_at: Utc::now().timestamp(),
+        };
+        let upsert = ReplaceOptions::builder().upsert(true).build();
+        self.results_collection().replace_one(doc! { "task_id": task_id }, &result, upsert).await?;
+        self.mark_task_completed(task_id).await
+    }
+
+    /// The stored response to a completed task
+    pub async fn task_result(&self, task_id: &str) -> Result<Option<TaskResult>, MongoError> {
+        self.results_collection().find_one(doc! { "task_id": task_id }, None).await
+    }
+
+    /// Queue `task` and return a handle for its outcome
+    pub async fn hand_off(&self, task: TodoTask) -> Result<Handoff, MongoError> {
+        let handoff = Handoff { task_id: task.id.clone(), target_agent: task.target_agent.clone(), todo_list: self.clone() };
+        self.add_task(task).await?;
+        Ok(handoff)
+    }
+}
+
+/// A task queued for another agent, whose outcome can be awaited
+#[derive(Debug, Clone)]
+pub struct Handoff {
+    pub task_id: String,
+    pub target_agent: String,
+    todo_list: TodoList,
+}
+
+impl Handoff {
+    /// The outcome if the task has finished
+    pub async fn outcome(&self) -> Result<Option<HandoffOutcome>> {
+        let result = self.todo_list.task_result(&self.task_id).await?;
+        if let Some(task) = self.todo_list.get_task(&self.task_id).await? {
+            return Ok(outcome_of(&task, result));
+        }
+        if let Some(result) = result {
+            return Ok(Some(HandoffOutcome::Completed(result.response)));
+        }
+        // Dead-lettered tasks leave the queue
+        match self.todo_list.get_dead_letter(&self.task_id).await? {
+            Some(dead) => Ok(outcome_of(&dead.task, None)),
+            None => Err(anyhow!("Task {} no longer exists", self.task_id)),
+        }
+    }
+
+    /// Wait up to `timeout` for the task to finish
+    pub async fn wait(&self, timeout: Duration) -> Result<HandoffOutcome> {
+        // Subscribe first so an event published while checking isn't missed
+        let mut events = events::task_events(events::global())
+            .filter(|event| futures::future::ready(event.task_id == self.task_id))
+            .boxed();
+        let deadline = tokio::time::Instant::now() + timeout;
+        loop {
+            if let Some(outcome) = self.outcome().await? {
+                return Ok(outcome);
+            }
+            tokio::select! {
+                _ = events.next() => {}
+                _ = tokio::time::sleep(POLL_INTERVAL) => {}
+                _ = tokio::time::sleep_until(deadline) => {
+                    return Err(anyhow!("Timed out after {:?} waiting for {} to finish task {}", timeout, self.target_agent, self.task_id));
+                }
+            }
+        }
+    }
+
+    /// Wait for the task and return the agent's response, failing if the task did
+    pub async fn response(&self, timeout: Duration) -> Result<Message> {
+        self.wait(timeout).await?.into_response()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::TaskPriority;
+
+    fn task(status: TaskStatus) -> TodoTask {
+        TodoTask {
+            id: "t1".to_string(),
+            description: "Scaffold the service".to_string(),
+            enhanced_description: None,
+            priority: TaskPriority::Medium,
+            project: None,
+            source_agent: Some("greeter".to_string()),
+            target_agent: "project-init".to_string(),
+            status,
+            created_at: 0,
+            completed_at: None,
+            due_date: None,
+            duration_minutes: None,
+            notes: None,
+            ticket: None,
+            last_modified: None,
+            failure_reason: Some("no template".to_string()),
+            embedding: None,
+            claim: None,
+            failures: Vec::new(),
+            confidence: None,
+            reviewed_at: None,
+            tenant_id: None,
+            idempotency_key: None,
+            content_hash: None,
+            history: Vec::new(),
+            tags: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_outcome_follows_task_status() {
+        assert!(outcome_of(&task(TaskStatus::Pending), None).is_none());
+        assert!(outcome_of(&task(TaskStatus::InProgress), None).is_none());
+        let result = TaskResult {
+            task_id: "t1".to_string(),
+            agent: "project-init".to_string(),
+            response: Message::new("Created ./service".to_string()),
+            completed_at: 0,
+        };
+        // The result is stored just before the status changes
+        let Some(HandoffOutcome::Completed(response)) = outcome_of(&task(TaskStatus::InProgress), Some(result)) else {
+            panic!("expected a completed outcome");
+        };
+        assert_eq!(response.content, "Created ./service");
+        assert!(matches!(outcome_of(&task(TaskStatus::Failed), None), Some(HandoffOutcome::Failed(reason)) if reason == "no template"));
+        assert!(matches!(outcome_of(&task(TaskStatus::Cancelled), None), Some(HandoffOutcome::Cancelled)));
+    }
+
+    #[test]
+    fn test_outcome_into_response() {
+        let response = HandoffOutcome::Completed(Message::new("done".to_string())).into_response().unwrap();
+        assert_eq!(response.content, "done");
+        let error = HandoffOutcome::Failed("timed out".to_string()).into_response().err().unwrap();
+        assert_eq!(error.to_string(), "Handed-off task failed: timed out");
+        assert!(HandoffOutcome::Cancelled.into_response().is_err());
+    }
+}
@@ -0,0 +1,105 @@
+//! Each task keeps a list of the changes made to it after it was added: status changes,
+//! retries, edits and reassignments, with who or what made them. It is the first place to
+//! look when a task is stuck or ended up somewhere unexpected (`GET /tasks/{id}/history`).
+
+use chrono::Utc;
+use mongodb::bson::{self, Document, doc};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use super::todo::TaskStatus;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ChangeKind {
+    /// Description, priority or project changed
+    Edited,
+    /// Moved to another agent
+    Reassigned,
+    /// Claimed, released, completed, failed or cancelled
+    Status,
+    /// A failed attempt, back in the queue for another
+    Retried,
+    /// Failed its last attempt and moved to the dead-letter collection
+    DeadLettered,
+    /// Taken out of the dead-letter collection for another round of attempts
+    Requeued,
+}
+
+/// A field's value before and after a change; unset values are `None`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct FieldChange {
+    pub field: String,
+    pub from: Option<String>,
+    pub to: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct TaskChange {
+    pub kind: ChangeKind,
+    pub at: i64,
+    /// Who made the change: a worker id, `key:<name>` for an API key, `todo_tool` and so on
+    pub actor: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub fields: Vec<FieldChange>,
+    /// Why, e.g. the error that failed an attempt
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+}
+
+impl TaskChange {
+    pub fn new(kind: ChangeKind, actor: impl Into<String>) -> Self {
+        Self { kind, at: Utc::now().timestamp(), actor: actor.into(), fields: Vec::new(), reason: None }
+    }
+
+    /// A `kind` change moving a task from `from` to `to`; `from` is `None` when the update
+    /// didn't read the task first
+    pub fn status(kind: ChangeKind, actor: impl Into<String>, from: Option<&TaskStatus>, to: &TaskStatus) -> Self {
+        Self::new(kind, actor).with_field("status", from.map(status_name), Some(status_name(to)))
+    }
+
+    pub fn with_reason(mut self, reason: impl Into<String>) -> Self {
+        self.reason = Some(reason.into());
+        self
+    }
+
+    /// The `$push` adding this change to a task's history in an update
+    pub fn push(&self) -> Result<Document, bson::ser::Error> {
+        Ok(doc! { "history": bson::to_bson(self)? })
+    }
+
+    /// Record `field` going from `from` to `to`, unless they are the same
+    pub fn with_field(mut self, field: &str, from: Option<String>, to: Option<String>) -> Self {
+        if from != to {
+            self.fields.push(FieldChange { field: field.to_string(), from, to });
+        }
+        self
+    }
+}
+
+/// The stored name of `status`, e.g. `in_progress`
+pub(crate) fn status_name(status: &TaskStatus) -> String {
+    match bson::to_bson(status) {
+        Ok(bson::Bson::String(name)) => name,
+        _ => format!("{:?}", status),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_status_change() {
+        let change = TaskChange::status(ChangeKind::Retried, "worker-1", Some(&TaskStatus::InProgress), &TaskStatus::Pending)
+            .with_reason("timed out");
+        assert_eq!(change.fields, vec![FieldChange {
+            field: "status".to_string(),
+            from: Some("in_progress".to_string()),
+            to: Some("pending".to_string()),
+        }]);
+        let pushed = change.push().unwrap();
+        let entry = pushed.get_document("history").unwrap();
+        assert_eq!(entry.get_str("kind").unwrap(), "retried");
+        assert_eq!(entry.get_str("reason").unwrap(), "timed out");
+    }
+}
@@ -0,0 +1,111 @@
+//! Producers that may retry a task they already sent, such as an HTTP client after a timeout
+//! or an MQTT publisher resending after a lost acknowledgement, give it an idempotency key
+//! (the `Idempotency-Key` header, or `idempotency_key` in an MQTT payload). The first task
+//! with a key is added; later ones with the same key get the original task back. A unique
+//! index on the tenant and key makes this hold across processes.
+
+use mongodb::IndexModel;
+use mongodb::bson::doc;
+use mongodb::error::Error as MongoError;
+use mongodb::options::IndexOptions;
+use crate::error::SwarmError;
+use super::offline_queue;
+use super::todo::{TodoList, TodoTask};
+
+/// The HTTP header carrying a task's key
+pub const HEADER: &str = "idempotency-key";
+const INDEX_NAME: &str = "tenant_idempotency_key";
+const MAX_KEY_LEN: usize = 255;
+
+/// `key` trimmed, or `None` if blank. Fails if it is too long to index.
+pub fn normalize_key(key: &str) -> Result<Option<String>, SwarmError> {
+    let key = key.trim();
+    if key.len() > MAX_KEY_LEN {
+        return Err(SwarmError::Validation(format!("Idempotency keys are at most {} bytes", MAX_KEY_LEN)));
+    }
+    Ok((!key.is_empty()).then(|| key.to_string()))
+}
+
+/// Whether adding a task stored it or found the one added earlier with its key
+#[derive(Debug, Clone)]
+pub enum Insertion {
+    Created(TodoTask),
+    Existing(TodoTask),
+}
+
+impl Insertion {
+    pub fn task(&self) -> &TodoTask {
+        match self {
+            Insertion::Created(task) | Insertion::Existing(task) => task,
+        }
+    }
+
+    pub fn into_task(self) -> TodoTask {
+        match self {
+            Insertion::Created(task) | Insertion::Existing(task) => task,
+        }
+    }
+
+    pub fn is_created(&self) -> bool {
+        matches!(self, Insertion::Created(_))
+    }
+}
+
+impl TodoList {
+    /// Create the unique index on tenant and key; a no-op if it already exists
+    pub async fn ensure_idempotency_index(&self) -> Result<(), MongoError> {
+        let index = IndexModel::builder()
+            .keys(doc! { "tenant_id": 1, "idempotency_key": 1 })
+            .options(Some(IndexOptions::builder()
+                .name(INDEX_NAME.to_string())
+                .unique(true)
+                // Tasks without a key don't take part
+                .partial_filter_expression(doc! { "idempotency_key": { "$exists": true } })
+                .build()))
+            .build();
+        self.collection().create_index(index, None).await?;
+        Ok(())
+    }
+
+    pub async fn find_by_idempotency_key(&self, key: &str) -> Result<Option<TodoTask>, MongoError> {
+        self.collection().find_one(self.scope(doc! { "idempotency_key": key }), None).await
+    }
+
+    /// Add `task` unless a task with its idempotency key exists, or an identical one was
+    /// added within the dedup window, returning that one instead
+    pub async fn add_task_once(&self, task: TodoTask) -> Result<Insertion, MongoError> {
+        if let (Some(hash), Some(window)) = (&task.content_hash, self.dedup_window()) {
+            if let Some(existing) = self.find_recent_duplicate(hash, task.created_at - window).await? {
+                return Ok(Insertion::Existing(existing));
+            }
+        }
+        let Some(key) = task.idempotency_key.clone() else {
+            self.add_task(task.clone()).await?;
+            return Ok(Insertion::Created(task));
+        };
+        if let Some(existing) = self.find_by_idempotency_key(&key).await? {
+            return Ok(Insertion::Existing(existing));
+        }
+        match self.add_task(task.clone()).await {
+            Ok(()) => Ok(Insertion::Created(task)),
+            // Another producer added it between the lookup and the insert
+            Err(e) if offline_queue::is_duplicate(&e) => match self.find_by_idempotency_key(&key).await? {
+                Some(existing) => Ok(Insertion::Existing(existing)),
+                None => Err(e),
+            },
+            Err(e) => Err(e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normalize_key() {
+        assert_eq!(normalize_key("  order-42 ").unwrap().as_deref(), Some("order-42"));
+        assert_eq!(normalize_key("   ").unwrap(), None);
+        assert!(matches!(normalize_key(&"k".repeat(MAX_KEY_LEN + 1)), Err(SwarmError::Validation(_))));
+    }
+}
@@ -9,13 +9,58 @@ use crate::agents::{AgentRegistry, AgentWrapper};
 use std::sync::Arc;
 use anyhow::{Result, anyhow};
 use std::error::Error as StdError;
+use utoipa::ToSchema;
 
 // Declare the modules that actually exist in the src/types directory
 pub mod todo;
 pub mod projects;
+pub mod reporting;
+pub mod transitions;
+pub mod sessions;
+pub mod state_store;
+pub mod state_definitions;
+pub mod state_export;
+pub mod state_hierarchy;
+pub mod attachments;
+pub mod task_events;
+pub mod api_keys;
+pub mod scheduling;
+pub mod claims;
+pub mod dead_letter;
+pub mod offline_queue;
+pub mod idempotency;
+pub mod dedup;
+pub mod history;
+pub mod edits;
+pub mod cancellation;
+pub mod archive;
+pub mod reaper;
+pub mod search;
+pub mod tags;
+pub mod export;
+pub mod snapshot;
+pub mod webhooks;
+pub mod digest;
+pub mod handoff;
+pub mod review;
+pub mod tenants;
+pub mod agent_config;
+pub mod derive;
+pub mod capabilities;
+#[cfg(test)]
+mod state_properties;
 
 // Re-export the types from the todo module that are used elsewhere
 pub use todo::{TodoList, TodoProcessor, TodoTask, TaskPriority, TaskStatus};
+pub use transitions::{Transition, Guard, TransitionAction};
+pub use attachments::Attachment;
+pub use task_events::{TaskEvent, TaskEventKind};
+pub use handoff::{Handoff, HandoffOutcome, TaskResult};
+pub use sessions::SessionState;
+pub use state_store::{StateStore, MongoStateStore, InMemoryStateStore};
+pub use agent_config::AgentConfigBuilder;
+pub use derive::{Agent, MessageHandler, StateSource};
+pub use capabilities::{AgentCapabilities, Capability, CostHint};
 
 // The rest of the file remains the same to avoid breaking other dependencies
 // (All the existing type definitions)
@@ -32,7 +77,7 @@ pub struct ToolParameter {
     pub items: Option<Box<ToolParameter>>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
 pub struct Tool {
     pub name: String,
     pub description: String,
@@ -42,12 +87,53 @@ pub struct Tool {
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AgentConfig {
     pub name: String,
+    #[serde(default)]
     pub public_description: String,
+    #[serde(default)]
     pub instructions: String,
+    #[serde(default)]
     pub tools: Vec<Tool>,
+    #[serde(default)]
     pub downstream_agents: Vec<String>,
     pub personality: Option<String>,
+    /// Inline definition, or a path to a YAML file when deserialized
+    #[serde(default, deserialize_with = "state_definitions::deserialize_state_machine")]
     pub state_machine: Option<StateMachine>,
+    /// Path to a Rhai script that handles messages in place of Rust code
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub script: Option<String>,
+    /// Most messages or tasks the agent handles at once; others queue. Unlimited when unset.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_concurrency: Option<usize>,
+    /// Middleware run around each message, outermost first; see `agents::middleware`.
+    /// `logging`, `metrics` and `personality` when unset.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub middleware: Option<Vec<String>>,
+    /// Another Swarmonomicon server that runs the agent; see `agents::remote`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub remote: Option<RemoteAgentConfig>,
+}
+
+/// Where a remote agent runs and how to reach it
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RemoteAgentConfig {
+    /// Base URL of the other server's API, e.g. `http://gpu-box:3000`
+    pub url: String,
+    /// The agent's name on that server; this agent's name when unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub agent: Option<String>,
+    /// Secret holding an API key for that server, looked up like the other secrets
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub api_key_secret: Option<String>,
+    /// Seconds to wait for a reply; 120 when unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timeout_secs: Option<u64>,
+}
+
+impl RemoteAgentConfig {
+    pub fn new(url: impl Into<String>) -> Self {
+        Self { url: url.into(), agent: None, api_key_secret: None, timeout_secs: None }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -64,12 +150,15 @@ pub struct TranscriptItem {
     pub is_hidden: bool,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
 pub struct Message {
     pub content: String,
     pub metadata: Option<MessageMetadata>,
     pub role: Option<String>,
     pub timestamp: Option<i64>,
+    /// Files, images and structured data sent alongside the text
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub attachments: Vec<Attachment>,
 }
 
 impl Message {
@@ -79,6 +168,7 @@ impl Message {
             metadata: None,
             role: Some("assistant".to_string()),
             timestamp: Some(chrono::Utc::now().timestamp()),
+            attachments: Vec::new(),
         }
     }
 
@@ -104,7 +194,7 @@ impl fmt::Display for Message {
     }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
 pub struct MessageMetadata {
     pub agent: String,
     pub state: Option<String>,
@@ -172,6 +262,49 @@ pub struct State {
     pub prompt: Option<String>,
     pub transitions: Option<HashMap<String, String>>,
     pub validation: Option<Vec<String>>,
+    /// Transitions with guards and actions, checked before the plain `transitions` map
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub guarded_transitions: Option<Vec<Transition>>,
+    /// Enclosing state; its transitions apply when this state has no match for an event
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub parent: Option<String>,
+    /// Child entered when a transition targets this state
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub initial_child: Option<String>,
+    /// Prompt emitted when the state is entered
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub on_enter: Option<String>,
+    /// Prompt emitted when the state is left
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub on_exit: Option<String>,
+}
+
+/// A state's validation pattern, the first `validation` entry, compiled when the machine
+/// is loaded. Input that doesn't match is refused with the second entry, or a generic
+/// message; an invalid pattern matches nothing.
+#[derive(Debug, Clone)]
+struct InputRule {
+    regex: Option<regex::Regex>,
+    message: String,
+}
+
+impl InputRule {
+    fn for_state(state: &State) -> Option<Self> {
+        let validation = state.validation.as_ref()?;
+        let pattern = validation.first()?;
+        let regex = regex::Regex::new(pattern)
+            .map_err(|e| tracing::warn!("Invalid validation pattern '{}' in state '{}': {}", pattern, state.name, e))
+            .ok();
+        let message = validation.get(1).cloned().unwrap_or_else(|| format!("Input doesn't match {}", pattern));
+        Some(Self { regex, message })
+    }
+
+    fn check(&self, input: &str) -> std::result::Result<(), String> {
+        match &self.regex {
+            Some(regex) if regex.is_match(input) => Ok(()),
+            _ => Err(self.message.clone()),
+        }
+    }
 }
 
 impl FromStr for State {
@@ -184,6 +317,11 @@ impl FromStr for State {
             prompt: None,
             transitions: None,
             validation: None,
+            guarded_transitions: None,
+            parent: None,
+            initial_child: None,
+            on_enter: None,
+            on_exit: None,
         })
     }
 }
@@ -202,10 +340,25 @@ pub trait Agent: Send + Sync {
     async fn get_current_state(&self) -> Result<Option<State>>;
     async fn get_config(&self) -> Result<AgentConfig>;
 
+    /// The conversational flow this agent runs, if any
+    async fn get_state_machine(&self) -> Result<Option<StateMachine>> {
+        Ok(self.get_config().await?.state_machine)
+    }
+
+    /// What the agent can do, for discovery and routing; by default its description and tools
+    async fn capabilities(&self) -> Result<Vec<Capability>> {
+        Ok(vec![Capability::from_config(&self.get_config().await?)])
+    }
+
     fn get_todo_list(&self) -> Option<&TodoList> {
         None
     }
 
+    /// Flush buffered state and release resources before the process exits
+    async fn shutdown(&self) -> Result<()> {
+        Ok(())
+    }
+
     async fn delegate_task(&self, task: TodoTask, registry: &AgentRegistry) -> Result<()> {
         if let Some(target_agent) = registry.get(&task.target_agent) {
             let todo_list = <AgentWrapper as TodoProcessor>::get_todo_list(target_agent);
@@ -215,33 +368,114 @@ pub trait Agent: Send + Sync {
             Err(anyhow!("Target agent '{}' not found", task.target_agent).into())
         }
     }
+
+    /// Queue `task` for its target agent and return a handle for that agent's response
+    async fn hand_off(&self, mut task: TodoTask, registry: &AgentRegistry) -> Result<Handoff> {
+        let target_agent = registry.get(&task.target_agent)
+            .ok_or_else(|| anyhow!("Target agent '{}' not found", task.target_agent))?;
+        if task.source_agent.is_none() {
+            task.source_agent = Some(self.get_config().await?.name);
+        }
+        let todo_list = <AgentWrapper as TodoProcessor>::get_todo_list(target_agent);
+        Ok(todo_list.hand_off(task).await?)
+    }
 }
 
 // Implement a basic agent state manager
 pub struct AgentStateManager {
     current_state: Option<String>,
     state_machine: Option<StateMachine>,
+    context: HashMap<String, String>,
+    /// Validation patterns by state name
+    input_rules: HashMap<String, InputRule>,
+}
+
+/// The state reached by a transition and the tool calls its actions requested
+#[derive(Debug, Clone)]
+pub struct TransitionOutcome {
+    pub state: State,
+    pub tool_calls: Vec<ToolCall>,
+    /// `on_exit` prompts of the states left, then `on_enter` prompts of the states entered
+    pub prompts: Vec<String>,
 }
 
 impl AgentStateManager {
     pub fn new(state_machine: Option<StateMachine>) -> Self {
-        let current_state = state_machine.as_ref().map(|sm| sm.initial_state.clone());
+        let current_state = state_machine.as_ref().map(|sm| sm.entry_state(&sm.initial_state));
+        let input_rules = state_machine.iter()
+            .flat_map(|sm| sm.states.iter())
+            .filter_map(|(name, state)| Some((name.clone(), InputRule::for_state(state)?)))
+            .collect();
         Self {
             current_state,
             state_machine,
+            context: HashMap::new(),
+            input_rules,
         }
     }
 
     pub fn transition(&mut self, event: &str) -> Option<&State> {
-        if let (Some(state_machine), Some(current_state)) = (&self.state_machine, &self.current_state) {
-            if let Some(current) = state_machine.states.get(current_state) {
-                if let Some(next_state) = current.transitions.as_ref().and_then(|transitions| transitions.get(event)) {
-                    self.current_state = Some(next_state.clone());
-                    return state_machine.states.get(next_state);
-                }
-            }
+        let next_state = self.transition_on(event, None)?.state.name;
+        self.state_machine.as_ref().and_then(|sm| sm.states.get(&next_state))
+    }
+
+    /// Fire `event` against the current state. Guarded transitions for the event are
+    /// tried in order and the first whose guards all pass wins, running its actions;
+    /// otherwise the plain `transitions` map is used. States without a match defer to
+    /// their parent, and targets with an `initial_child` are entered at that child. A
+    /// message failing the current state's validation pattern fires nothing.
+    pub fn transition_on(&mut self, event: &str, message: Option<&Message>) -> Option<TransitionOutcome> {
+        let state_machine = self.state_machine.as_ref()?;
+        let current_name = self.current_state.as_ref()?;
+        if let (Some(message), Some(rule)) = (message, self.input_rules.get(current_name)) {
+            rule.check(&message.content).ok()?;
         }
-        None
+
+        let (target, actions) = state_machine.lineage(current_name).into_iter().find_map(|state| {
+            let guarded = state.guarded_transitions.iter().flatten()
+                .filter(|t| t.event == event)
+                .find(|t| t.guards.iter().all(|guard| guard.evaluate(message, &self.context)));
+            match guarded {
+                Some(transition) => Some((transition.target.clone(), transition.actions.clone())),
+                None => state.transitions.as_ref()?.get(event).map(|target| (target.clone(), Vec::new())),
+            }
+        })?;
+        let next_state = state_machine.entry_state(&target);
+        let state = state_machine.states.get(&next_state)?.clone();
+        let prompts = state_machine.transition_prompts(current_name, &next_state);
+
+        let tool_calls = actions.iter()
+            .filter_map(|action| action.apply(message, &mut self.context))
+            .collect();
+        self.current_state = Some(next_state);
+        Some(TransitionOutcome { state, tool_calls, prompts })
+    }
+
+    /// Values set by `set_context` transition actions
+    pub fn context(&self) -> &HashMap<String, String> {
+        &self.context
+    }
+
+    pub fn set_context(&mut self, key: String, value: String) {
+        self.context.insert(key, value);
+    }
+
+    pub(crate) fn replace_context(&mut self, context: HashMap<String, String>) {
+        self.context = context;
+    }
+
+    pub(crate) fn set_current_state(&mut self, state: String) {
+        self.current_state = Some(state);
+    }
+
+    pub fn state_machine(&self) -> Option<&StateMachine> {
+        self.state_machine.as_ref()
+    }
+
+    /// Return to the initial state with an empty context
+    pub fn reset(&mut self) {
+        self.current_state = self.state_machine.as_ref().map(|sm| sm.entry_state(&sm.initial_state));
+        self.context.clear();
     }
 
     pub fn get_current_state(&self) -> Option<&State> {
@@ -255,17 +489,25 @@ impl AgentStateManager {
     pub fn get_current_state_name(&self) -> Option<&str> {
         self.current_state.as_deref()
     }
+
+    /// Check `input` against the current state's validation pattern, for telling the user
+    /// why their message didn't move the conversation on
+    pub fn validate_input(&self, input: &str) -> std::result::Result<(), String> {
+        let rule = self.current_state.as_ref().and_then(|current| self.input_rules.get(current));
+        rule.map_or(Ok(()), |rule| rule.check(input))
+    }
 }
 
 // More types will be added as needed
 #[allow(dead_code)]
 pub struct Unimplemented;
 
-#[derive(Debug, Clone, Serialize)]
+#[derive(Debug, Clone, Serialize, ToSchema)]
 pub struct AgentInfo {
     pub name: String,
     pub description: String,
     pub instructions: String,
     pub tools: Vec<Tool>,
     pub downstream_agents: Vec<String>,
+    pub capabilities: Vec<Capability>,
 }
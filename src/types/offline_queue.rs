@@ -0,0 +1,424 @@
+//! Tasks added while MongoDB can't be reached are appended to a file, one JSON task per
+//! line, instead of being lost. A background reconciler inserts them once the database is
+//! back, and the file survives restarts in between. `SWARM_OFFLINE_QUEUE` sets the file
+//! (default `./swarmonomicon-offline.jsonl`), or `off` to fail such adds as before, and
+//! `SWARM_OFFLINE_FLUSH_SECS` how often the reconciler tries (default 15). Replays insert
+//! by task id, so a task stored before a crash cut a flush short isn't added twice. A task
+//! the database refuses `SWARM_OFFLINE_MAX_ATTEMPTS` times (default 5) is dead-lettered.
+
+use std::env;
+use std::future::Future;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, OnceLock};
+use std::time::Duration;
+use anyhow::Result;
+use chrono::Utc;
+use mongodb::Collection;
+use mongodb::bson::{self, doc};
+use mongodb::error::{Error as MongoError, ErrorKind};
+use mongodb::options::UpdateOptions;
+use serde::{Deserialize, Serialize};
+use tokio::fs;
+use tokio::io::AsyncWriteExt;
+use tokio::sync::{Mutex, OnceCell};
+use crate::events::{self, DomainEvent};
+use super::dead_letter::DeadLetter;
+use super::task_events::{TaskEvent, TaskEventKind};
+use super::todo::{TaskFailure, TodoTask};
+
+const DEFAULT_PATH: &str = "./swarmonomicon-offline.jsonl";
+const DEFAULT_FLUSH_SECS: u64 = 15;
+const DEFAULT_MAX_ATTEMPTS: u32 = 5;
+
+/// What became of a queued task on a flush
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Delivery {
+    Stored,
+    /// Already in the database, e.g. inserted before a crash cut the flush short
+    Duplicate,
+    /// The database is still unreachable; stop and try the rest later
+    Unreachable,
+    /// Refused for another reason; kept for the next flush until it runs out of attempts
+    Failed,
+}
+
+impl Delivery {
+    pub fn of(result: &Result<(), MongoError>) -> Self {
+        match result {
+            Ok(()) => Delivery::Stored,
+            Err(e) => Self::of_error(e),
+        }
+    }
+
+    pub fn of_error(err: &MongoError) -> Self {
+        if is_unreachable(err) {
+            Delivery::Unreachable
+        } else if is_duplicate(err) {
+            Delivery::Duplicate
+        } else {
+            Delivery::Failed
+        }
+    }
+}
+
+/// Errors meaning the server couldn't be reached, as opposed to it refusing the write
+pub fn is_unreachable(err: &MongoError) -> bool {
+    matches!(
+        *err.kind,
+        ErrorKind::ServerSelection { .. } | ErrorKind::Io(_) | ErrorKind::ConnectionPoolCleared { .. }
+    )
+}
+
+/// Errors from inserting a document whose unique key is already taken
+pub fn is_duplicate(err: &MongoError) -> bool {
+    matches!(
+        &*err.kind,
+        ErrorKind::Write(mongodb::error::WriteFailure::WriteError(e)) if e.code == 11000
+    )
+}
+
+/// Refusals before a queued task is dead-lettered, from `SWARM_OFFLINE_MAX_ATTEMPTS`
+fn max_attempts_from_env() -> u32 {
+    env::var("SWARM_OFFLINE_MAX_ATTEMPTS").ok()
+        .and_then(|n| n.parse().ok())
+        .unwrap_or(DEFAULT_MAX_ATTEMPTS)
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct FlushReport {
+    pub stored: usize,
+    pub remaining: usize,
+    /// Refused too many times and moved to the dead letters
+    pub dead_lettered: usize,
+}
+
+/// A line of the queue file: the task, plus how often the database refused it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Queued {
+    #[serde(flatten)]
+    task: TodoTask,
+    #[serde(default, skip_serializing_if = "is_zero")]
+    refused: u32,
+}
+
+fn is_zero(n: &u32) -> bool {
+    *n == 0
+}
+
+#[derive(Debug)]
+pub struct OfflineQueue {
+    path: PathBuf,
+    /// Serializes appends and flushes, which rewrite the file
+    lock: Mutex<()>,
+    reconciler: OnceCell<()>,
+    max_attempts: u32,
+}
+
+impl OfflineQueue {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into(), lock: Mutex::new(()), reconciler: OnceCell::new(), max_attempts: DEFAULT_MAX_ATTEMPTS }
+    }
+
+    /// Dead-letter a task once the database has refused it this many times
+    pub fn with_max_attempts(mut self, max_attempts: u32) -> Self {
+        self.max_attempts = max_attempts.max(1);
+        self
+    }
+
+    /// The process's queue, or `None` when `SWARM_OFFLINE_QUEUE=off`. Every task list in
+    /// the process shares it, so only one reconciler rewrites the file.
+    pub fn shared() -> Option<Arc<Self>> {
+        static SHARED: OnceLock<Option<Arc<OfflineQueue>>> = OnceLock::new();
+        SHARED.get_or_init(|| {
+            let path = match env::var("SWARM_OFFLINE_QUEUE") {
+                Ok(path) if path == "off" => return None,
+                Ok(path) if !path.is_empty() => path,
+                _ => DEFAULT_PATH.to_string(),
+            };
+            Some(Arc::new(Self::new(path).with_max_attempts(max_attempts_from_env())))
+        }).clone()
+    }
+
+    /// Whether adds are queued while the database is unreachable, rather than failing
+    pub fn enabled() -> bool {
+        Self::shared().is_some()
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Append `task`, synced to disk before returning
+    pub async fn push(&self, task: &TodoTask) -> Result<()> {
+        let _guard = self.lock.lock().await;
+        let mut line = serde_json::to_vec(&Queued { task: task.clone(), refused: 0 })?;
+        line.push(b'\n');
+        let mut file = fs::OpenOptions::new().create(true).append(true).open(&self.path).await?;
+        file.write_all(&line).await?;
+        file.sync_data().await?;
+        Ok(())
+    }
+
+    /// The queued tasks, oldest first. Lines that don't parse are skipped and logged.
+    pub async fn tasks(&self) -> Result<Vec<TodoTask>> {
+        let _guard = self.lock.lock().await;
+        Ok(self.read().await?.into_iter().map(|queued| queued.task).collect())
+    }
+
+    async fn read(&self) -> Result<Vec<Queued>> {
+        let contents = match fs::read_to_string(&self.path).await {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e.into()),
+        };
+        Ok(contents.lines()
+            .filter(|line| !line.trim().is_empty())
+            .filter_map(|line| match serde_json::from_str(line) {
+                Ok(task) => Some(task),
+                Err(e) => {
+                    tracing::warn!("Dropping unreadable line in {}: {}", self.path.display(), e);
+                    None
+                }
+            })
+            .collect())
+    }
+
+    /// Hand each queued task to `insert`, keeping the ones it couldn't deliver. Stops at the
+    /// first unreachable result, so an outage doesn't cost one timeout per task. A task
+    /// refused `max_attempts` times goes to `dead_letter` instead of being kept.
+    pub async fn flush_with<F, Fut, D, DFut>(&self, mut insert: F, mut dead_letter: D) -> Result<FlushReport>
+    where
+        F: FnMut(TodoTask) -> Fut,
+        Fut: Future<Output = Delivery>,
+        D: FnMut(TodoTask) -> DFut,
+        DFut: Future<Output = Delivery>,
+    {
+        let _guard = self.lock.lock().await;
+        let tasks = self.read().await?;
+        if tasks.is_empty() {
+            return Ok(FlushReport::default());
+        }
+
+        let mut report = FlushReport::default();
+        let mut kept = Vec::new();
+        let mut tasks = tasks.into_iter();
+        while let Some(mut queued) = tasks.next() {
+            match insert(queued.task.clone()).await {
+                Delivery::Stored => report.stored += 1,
+                Delivery::Duplicate => {}
+                Delivery::Failed => {
+                    queued.refused += 1;
+                    if queued.refused < self.max_attempts {
+                        kept.push(queued);
+                        continue;
+                    }
+                    let mut task = queued.task.clone();
+                    task.failures.push(TaskFailure {
+                        reason: format!("MongoDB refused the task {} times while replaying the offline queue", queued.refused),
+                        failed_at: Utc::now().timestamp(),
+                        worker_id: None,
+                    });
+                    match dead_letter(task).await {
+                        Delivery::Stored | Delivery::Duplicate => report.dead_lettered += 1,
+                        Delivery::Failed | Delivery::Unreachable => kept.push(queued),
+                    }
+                }
+                Delivery::Unreachable => {
+                    kept.push(queued);
+                    kept.extend(tasks.by_ref());
+                }
+            }
+        }
+        report.remaining = kept.len();
+        self.rewrite(&kept).await?;
+        Ok(report)
+    }
+
+    /// Replace the file with `tasks`, through a temporary file so a crash keeps the old one
+    async fn rewrite(&self, tasks: &[Queued]) -> Result<()> {
+        if tasks.is_empty() {
+            return match fs::remove_file(&self.path).await {
+                Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+                _ => Ok(()),
+            };
+        }
+        let mut contents = Vec::new();
+        for queued in tasks {
+            contents.extend(serde_json::to_vec(queued)?);
+            contents.push(b'\n');
+        }
+        let temp = self.path.with_extension("jsonl.tmp");
+        let mut file = fs::File::create(&temp).await?;
+        file.write_all(&contents).await?;
+        file.sync_data().await?;
+        fs::rename(&temp, &self.path).await?;
+        Ok(())
+    }
+
+    /// Insert the queued tasks into `collection` unless a task with the same id is already
+    /// there, announcing each as created. Tasks refused too often go to `dead_letters`.
+    pub async fn flush(&self, collection: &Collection<TodoTask>, dead_letters: &Collection<DeadLetter>) -> Result<FlushReport> {
+        self.flush_with(|task| async move {
+            let document = match bson::to_document(&task) {
+                Ok(document) => document,
+                Err(e) => {
+                    tracing::warn!("Could not encode queued task {}: {}", task.id, e);
+                    return Delivery::Failed;
+                }
+            };
+            let options = UpdateOptions::builder().upsert(true).build();
+            let delivery = match collection.update_one(doc! { "id": &task.id }, doc! { "$setOnInsert": document }, options).await {
+                Ok(result) if result.upserted_id.is_some() => Delivery::Stored,
+                Ok(_) => Delivery::Duplicate,
+                Err(e) => {
+                    let delivery = Delivery::of_error(&e);
+                    if delivery == Delivery::Failed {
+                        tracing::warn!("MongoDB refused queued task {}: {}", task.id, e);
+                    }
+                    delivery
+                }
+            };
+            if delivery == Delivery::Stored {
+                events::global().publish(DomainEvent::Task(TaskEvent::for_task(TaskEventKind::Created, &task)));
+            }
+            delivery
+        }, |task| async move {
+            tracing::error!("Dead-lettering queued task {}, which MongoDB keeps refusing", task.id);
+            let letter = DeadLetter { task, dead_lettered_at: Utc::now().timestamp() };
+            Delivery::of(&dead_letters.insert_one(letter, None).await.map(|_| ()))
+        }).await
+    }
+
+    /// Flush into `collection` every `SWARM_OFFLINE_FLUSH_SECS`. Only the first call starts one.
+    pub async fn start_reconciler(self: &Arc<Self>, collection: Collection<TodoTask>, dead_letters: Collection<DeadLetter>) {
+        let queue = self.clone();
+        self.reconciler.get_or_init(|| async move {
+            let interval = env::var("SWARM_OFFLINE_FLUSH_SECS").ok()
+                .and_then(|secs| secs.parse().ok())
+                .filter(|&secs| secs > 0)
+                .unwrap_or(DEFAULT_FLUSH_SECS);
+            tokio::spawn(async move {
+                let mut ticker = tokio::time::interval(Duration::from_secs(interval));
+                loop {
+                    ticker.tick().await;
+                    match queue.flush(&collection, &dead_letters).await {
+                        Ok(FlushReport { stored: 0, dead_lettered: 0, .. }) => {}
+                        Ok(report) => tracing::info!(
+                            "Stored {} tasks queued while MongoDB was unreachable, {} still queued, {} dead-lettered",
+                            report.stored, report.remaining, report.dead_lettered
+                        ),
+                        Err(e) => tracing::warn!("Could not flush the offline task queue {}: {}", queue.path.display(), e),
+                    }
+                }
+            });
+        }).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{TaskPriority, TaskStatus};
+
+    fn queue() -> OfflineQueue {
+        OfflineQueue::new(env::temp_dir().join(format!("swarm-offline-{}.jsonl", uuid::Uuid::new_v4())))
+    }
+
+    fn task(description: &str) -> TodoTask {
+        TodoTask {
+            id: uuid::Uuid::new_v4().to_string(),
+            description: description.to_string(),
+            enhanced_description: None,
+            priority: TaskPriority::Medium,
+            project: None,
+            source_agent: Some("mqtt".to_string()),
+            target_agent: "worker".to_string(),
+            status: TaskStatus::Pending,
+            created_at: 100,
+            completed_at: None,
+            due_date: None,
+            duration_minutes: None,
+            notes: None,
+            ticket: None,
+            last_modified: Some(100),
+            failure_reason: None,
+            embedding: None,
+            claim: None,
+            tags: Vec::new(),
+            failures: Vec::new(),
+            confidence: None,
+            reviewed_at: None,
+            tenant_id: None,
+            idempotency_key: None,
+            content_hash: None,
+            history: Vec::new(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_queued_tasks_survive_until_flushed() -> Result<()> {
+        let queue = queue();
+        for description in ["first", "second", "third"] {
+            queue.push(&task(description)).await?;
+        }
+        // A new handle on the same file, as after a restart
+        let reopened = OfflineQueue::new(queue.path());
+        let queued: Vec<String> = reopened.tasks().await?.into_iter().map(|task| task.description).collect();
+        assert_eq!(queued, ["first", "second", "third"]);
+
+        let report = reopened.flush_with(|_| async { Delivery::Stored }, |_| async { Delivery::Stored }).await?;
+        assert_eq!(report, FlushReport { stored: 3, remaining: 0, dead_lettered: 0 });
+        assert!(reopened.tasks().await?.is_empty());
+        assert!(!queue.path().exists());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_flush_keeps_what_it_could_not_deliver() -> Result<()> {
+        let queue = queue();
+        for description in ["stored", "refused", "duplicate", "outage", "after outage"] {
+            queue.push(&task(description)).await?;
+        }
+        let mut attempted = Vec::new();
+        let report = queue.flush_with(|task| {
+            attempted.push(task.description.clone());
+            async move {
+                match task.description.as_str() {
+                    "refused" => Delivery::Failed,
+                    "duplicate" => Delivery::Duplicate,
+                    "outage" => Delivery::Unreachable,
+                    _ => Delivery::Stored,
+                }
+            }
+        }, |_| async { Delivery::Stored }).await?;
+
+        assert_eq!(attempted, ["stored", "refused", "duplicate", "outage"]);
+        assert_eq!(report, FlushReport { stored: 1, remaining: 3, dead_lettered: 0 });
+        let kept: Vec<String> = queue.tasks().await?.into_iter().map(|task| task.description).collect();
+        assert_eq!(kept, ["refused", "outage", "after outage"]);
+        fs::remove_file(queue.path()).await?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_tasks_refused_too_often_are_dead_lettered() -> Result<()> {
+        let queue = queue().with_max_attempts(2);
+        queue.push(&task("too large")).await?;
+        let refuse = |_| async { Delivery::Failed };
+
+        let report = queue.flush_with(refuse, |_| async { Delivery::Stored }).await?;
+        assert_eq!(report, FlushReport { stored: 0, remaining: 1, dead_lettered: 0 });
+        // The count of refusals survives a restart
+        let reopened = OfflineQueue::new(queue.path()).with_max_attempts(2);
+        let mut dead = Vec::new();
+        let report = reopened.flush_with(refuse, |task| {
+            dead.push(task);
+            async { Delivery::Stored }
+        }).await?;
+        assert_eq!(report, FlushReport { stored: 0, remaining: 0, dead_lettered: 1 });
+        assert_eq!(dead[0].description, "too large");
+        assert_eq!(dead[0].failures.len(), 1);
+        assert!(!queue.path().exists());
+        Ok(())
+    }
+}
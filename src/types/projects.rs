@@ -1,6 +1,18 @@
 use std::path::{Path, PathBuf};
 use std::collections::HashMap;
 use lazy_static::lazy_static;
+use anyhow::{Result, anyhow};
+use chrono::Utc;
+use futures_util::TryStreamExt;
+use mongodb::Collection;
+use mongodb::bson::{doc, Document};
+use mongodb::options::{FindOptions, ReplaceOptions};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use crate::ai::KNOWN_PROJECTS;
+use crate::db::Database;
+use crate::error::SwarmError;
+use super::tenants;
 
 /// Represents a project in the system
 #[derive(Debug, Clone)]
@@ -382,3 +394,190 @@ pub fn get_project_descriptions_text() -> String {
 - phoenix: Regression test dashboard and control system
 - Tinker: Rust-based tinkering and experimental project"#.to_string()
 } 
+
+/// A project definition stored in the `projects` collection
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct ProjectDefinition {
+    pub name: String,
+    pub description: String,
+    pub path: Option<String>,
+    pub parent: Option<String>,
+    #[serde(default)]
+    pub archived: bool,
+    pub created_at: i64,
+    pub last_modified: Option<i64>,
+    /// The tenant that defined the project; shared by every tenant when unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tenant_id: Option<String>,
+}
+
+impl ProjectDefinition {
+    pub fn new(name: &str, description: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            description: description.to_string(),
+            path: None,
+            parent: None,
+            archived: false,
+            created_at: Utc::now().timestamp(),
+            last_modified: None,
+            tenant_id: None,
+        }
+    }
+}
+
+/// Fields to change on an existing project; `None` leaves a field untouched
+#[derive(Debug, Clone, Default, Deserialize, ToSchema)]
+pub struct ProjectUpdate {
+    pub description: Option<String>,
+    pub path: Option<String>,
+    pub parent: Option<String>,
+}
+
+impl ProjectUpdate {
+    fn to_document(&self) -> Document {
+        let mut set = doc! { "last_modified": Utc::now().timestamp() };
+        if let Some(description) = &self.description {
+            set.insert("description", description);
+        }
+        if let Some(path) = &self.path {
+            set.insert("path", path);
+        }
+        if let Some(parent) = &self.parent {
+            set.insert("parent", parent);
+        }
+        doc! { "$set": set }
+    }
+}
+
+/// Project definitions backed by MongoDB, seeded with the known projects on first use.
+/// The seeded projects are shared; a registry scoped to a tenant also sees the tenant's
+/// own projects, and only changes those.
+#[derive(Debug, Clone)]
+pub struct ProjectRegistry {
+    collection: Collection<ProjectDefinition>,
+    tenant: Option<String>,
+}
+
+impl ProjectRegistry {
+    pub async fn new() -> Result<Self> {
+        let registry = Self::connect().await?;
+        registry.seed_defaults().await?;
+        Ok(registry)
+    }
+
+    /// The registry in the shared database, without seeding it
+    pub async fn connect() -> Result<Self> {
+        Ok(Self::from_database(&Database::shared().await?))
+    }
+
+    pub fn from_database(db: &Database) -> Self {
+        Self {
+            collection: db.collection("projects"),
+            tenant: None,
+        }
+    }
+
+    /// The same registry as seen by `tenant`; `None` sees every tenant's projects
+    pub fn for_tenant(&self, tenant: Option<&str>) -> Self {
+        Self { tenant: tenant.map(str::to_string), ..self.clone() }
+    }
+
+    /// `filter` limited to projects this registry may change
+    fn own(&self, filter: Document) -> Document {
+        tenants::scope_filter(self.tenant.as_deref(), filter)
+    }
+
+    /// Insert the built-in project list if the collection is empty
+    pub async fn seed_defaults(&self) -> Result<()> {
+        if self.collection.count_documents(None, None).await.map_err(SwarmError::from)? > 0 {
+            return Ok(());
+        }
+        let defaults: Vec<ProjectDefinition> = KNOWN_PROJECTS.iter()
+            .map(|(name, description)| ProjectDefinition::new(name, description))
+            .collect();
+        self.collection.insert_many(defaults, None).await.map_err(SwarmError::from)?;
+        Ok(())
+    }
+
+    pub async fn add_project(&self, mut project: ProjectDefinition) -> Result<ProjectDefinition> {
+        if let Some(tenant) = &self.tenant {
+            project.tenant_id = Some(tenant.clone());
+        }
+        if self.get_project(&project.name).await?.is_some() {
+            return Err(SwarmError::Validation(format!("Project '{}' already exists", project.name)).into());
+        }
+        self.collection.insert_one(&project, None).await.map_err(SwarmError::from)?;
+        Ok(project)
+    }
+
+    pub async fn get_project(&self, name: &str) -> Result<Option<ProjectDefinition>> {
+        Ok(self.collection.find_one(tenants::shared_filter(self.tenant.as_deref(), doc! { "name": name }), None).await.map_err(SwarmError::from)?)
+    }
+
+    pub async fn update_project(&self, name: &str, update: ProjectUpdate) -> Result<ProjectDefinition> {
+        let result = self.collection.update_one(self.own(doc! { "name": name }), update.to_document(), None).await.map_err(SwarmError::from)?;
+        if result.matched_count == 0 {
+            return Err(anyhow!("Project '{}' not found", name));
+        }
+        self.get_project(name).await?
+            .ok_or_else(|| anyhow!("Project '{}' not found", name))
+    }
+
+    /// Hide a project from classification without deleting it
+    pub async fn archive_project(&self, name: &str) -> Result<()> {
+        let update = doc! { "$set": { "archived": true, "last_modified": Utc::now().timestamp() } };
+        let result = self.collection.update_one(self.own(doc! { "name": name }), update, None).await.map_err(SwarmError::from)?;
+        if result.matched_count == 0 {
+            return Err(anyhow!("Project '{}' not found", name));
+        }
+        Ok(())
+    }
+
+    pub async fn list_projects(&self, include_archived: bool) -> Result<Vec<ProjectDefinition>> {
+        let filter = if include_archived { doc! {} } else { doc! { "archived": { "$ne": true } } };
+        let filter = tenants::shared_filter(self.tenant.as_deref(), filter);
+        let options = FindOptions::builder().sort(doc! { "name": 1 }).build();
+        let mut cursor = self.collection.find(filter, options).await.map_err(SwarmError::from)?;
+        let mut projects = Vec::new();
+        while let Some(project) = cursor.try_next().await? {
+            projects.push(project);
+        }
+        Ok(projects)
+    }
+
+    /// Store `project` as given, replacing the one with its name and tenant, e.g. when
+    /// restoring a snapshot
+    pub async fn put_project(&self, project: &ProjectDefinition) -> Result<()> {
+        let filter = doc! { "name": &project.name, "tenant_id": project.tenant_id.as_deref() };
+        let options = ReplaceOptions::builder().upsert(true).build();
+        self.collection.replace_one(filter, project, options).await.map_err(SwarmError::from)?;
+        Ok(())
+    }
+
+    /// Active projects as (name, description) pairs for classification
+    pub async fn classification_projects(&self) -> Result<Vec<(String, String)>> {
+        Ok(self.list_projects(false).await?
+            .into_iter()
+            .map(|p| (p.name, p.description))
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_project_update_document() {
+        let update = ProjectUpdate {
+            description: Some("New description".to_string()),
+            ..Default::default()
+        };
+        let document = update.to_document();
+        let set = document.get_document("$set").unwrap();
+        assert_eq!(set.get_str("description").unwrap(), "New description");
+        assert!(set.contains_key("last_modified"));
+        assert!(!set.contains_key("path"));
+    }
+}
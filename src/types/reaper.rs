@@ -0,0 +1,186 @@
+//! Workers send a heartbeat with every lease renewal. A task still in progress with no
+//! heartbeat for `TODO_STUCK_AFTER_SECS` (default 900, `0` turns the reaper off) is taken
+//! from its worker and counted as a failed attempt, so it is retried or dead-lettered like
+//! any other failure, and a `task_stuck` event is published for alerting. Claims made by
+//! older workers carry no heartbeat, and tasks they started carry no claim at all; their
+//! last change is used instead.
+
+use std::env;
+use std::time::Duration;
+use chrono::Utc;
+use futures_util::TryStreamExt;
+use mongodb::bson::{self, Document, doc};
+use mongodb::error::Error as MongoError;
+use tokio::task::JoinHandle;
+use crate::events::{self, DomainEvent};
+use super::todo::{TaskStatus, TodoList, TodoTask};
+
+const DEFAULT_STUCK_AFTER_SECS: u64 = 900;
+const MIN_REAP_INTERVAL: Duration = Duration::from_secs(10);
+/// How in-progress tasks were stored before claims
+const LEGACY_IN_PROGRESS: &str = "InProgress";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StuckTaskPolicy {
+    /// How long a task may go without a heartbeat
+    pub deadline: Duration,
+}
+
+impl Default for StuckTaskPolicy {
+    fn default() -> Self {
+        Self { deadline: Duration::from_secs(DEFAULT_STUCK_AFTER_SECS) }
+    }
+}
+
+impl StuckTaskPolicy {
+    /// `TODO_STUCK_AFTER_SECS`, defaulting to 15 minutes; `None` if set to 0 to turn the reaper off
+    pub fn from_env() -> Option<Self> {
+        let policy = env::var("TODO_STUCK_AFTER_SECS").ok()
+            .and_then(|secs| secs.parse().ok())
+            .map(|secs| Self { deadline: Duration::from_secs(secs) })
+            .unwrap_or_default();
+        (!policy.deadline.is_zero()).then_some(policy)
+    }
+
+    /// Tasks whose last heartbeat is before this timestamp are stuck
+    pub fn cutoff(&self, now: i64) -> i64 {
+        now - self.deadline.as_secs() as i64
+    }
+
+    /// How often the reaper looks, a third of the deadline but at least ten seconds
+    pub fn interval(&self) -> Duration {
+        (self.deadline / 3).max(MIN_REAP_INTERVAL)
+    }
+}
+
+/// In-progress tasks with no heartbeat since `cutoff`
+pub fn stuck_filter(cutoff: i64) -> Result<Document, bson::ser::Error> {
+    Ok(doc! {
+        "status": { "$in": [bson::to_bson(&TaskStatus::InProgress)?, LEGACY_IN_PROGRESS] },
+        "$or": [
+            { "claim.heartbeat_at": { "$lt": cutoff } },
+            { "claim.heartbeat_at": null, "last_modified": { "$lt": cutoff } },
+        ],
+    })
+}
+
+impl TodoList {
+    /// Take stuck tasks from their workers and record a failed attempt for each, returning
+    /// the tasks reaped
+    pub async fn reap_stuck_tasks(&self, policy: StuckTaskPolicy) -> Result<Vec<TodoTask>, MongoError> {
+        let now = Utc::now().timestamp();
+        let filter = self.scope(stuck_filter(policy.cutoff(now))?);
+        let stuck: Vec<TodoTask> = self.collection().find(filter.clone(), None).await?.try_collect().await?;
+        let mut reaped = Vec::with_capacity(stuck.len());
+        for task in stuck {
+            // Only if it is still stuck, so a worker that renewed in the meantime keeps it.
+            // Touching it means another reaper no longer finds it stuck, claim or not.
+            let mut still_stuck = filter.clone();
+            still_stuck.insert("id", &task.id);
+            let release = doc! { "$unset": { "claim": "" }, "$set": { "last_modified": now } };
+            let released = self.collection()
+                .update_one(still_stuck, release, None)
+                .await?
+                .modified_count > 0;
+            if !released {
+                continue;
+            }
+            let worker_id = task.claim.as_ref().map(|claim| claim.worker_id.clone());
+            let last_heartbeat = task.claim.as_ref().and_then(|claim| claim.heartbeat_at).or(task.last_modified);
+            let reason = format!(
+                "No heartbeat from {} for over {}s",
+                worker_id.as_deref().unwrap_or("its worker"),
+                policy.deadline.as_secs(),
+            );
+            tracing::warn!("Task {} is stuck: {}", task.id, reason);
+            self.fail_attempt(&task.id, &reason).await?;
+            events::global().publish(DomainEvent::task_stuck(&task.id, worker_id.as_deref(), last_heartbeat));
+            reaped.push(task);
+        }
+        Ok(reaped)
+    }
+
+    /// Reap according to `policy` now and then every `policy.interval()`, until the handle is aborted
+    pub fn spawn_reaper(&self, policy: StuckTaskPolicy) -> JoinHandle<()> {
+        let todo_list = self.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(policy.interval());
+            loop {
+                interval.tick().await;
+                if let Err(e) = todo_list.reap_stuck_tasks(policy).await {
+                    tracing::warn!("Failed to reap stuck tasks: {}", e);
+                }
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testsupport::{self, MongoFixture};
+    use crate::types::claims::TaskClaim;
+
+    #[test]
+    fn test_policy() {
+        let policy = StuckTaskPolicy { deadline: Duration::from_secs(90) };
+        assert_eq!(policy.cutoff(1_000), 910);
+        assert_eq!(policy.interval(), Duration::from_secs(30));
+        assert_eq!(StuckTaskPolicy { deadline: Duration::from_secs(12) }.interval(), MIN_REAP_INTERVAL);
+        assert_eq!(StuckTaskPolicy::default().deadline, Duration::from_secs(900));
+    }
+
+    #[test]
+    fn test_stuck_filter_falls_back_to_last_modified() {
+        let filter = stuck_filter(500).unwrap();
+        let statuses = filter.get_document("status").unwrap().get_array("$in").unwrap();
+        assert_eq!(statuses.iter().filter_map(|s| s.as_str()).collect::<Vec<_>>(), ["in_progress", LEGACY_IN_PROGRESS]);
+        let branches = filter.get_array("$or").unwrap();
+        let by_heartbeat = branches[0].as_document().unwrap();
+        assert_eq!(by_heartbeat.get_document("claim.heartbeat_at").unwrap().get_i64("$lt").unwrap(), 500);
+        let legacy = branches[1].as_document().unwrap();
+        assert!(legacy.get("claim.heartbeat_at").unwrap().as_null().is_some());
+        assert_eq!(legacy.get_document("last_modified").unwrap().get_i64("$lt").unwrap(), 500);
+    }
+
+    #[tokio::test]
+    async fn test_tasks_without_a_recent_heartbeat_are_retried() -> anyhow::Result<()> {
+        let mongo = MongoFixture::start().await?;
+        let now = Utc::now().timestamp();
+        let in_progress = |description: &str, heartbeat_at: i64| TodoTask {
+            status: TaskStatus::InProgress,
+            claim: Some(TaskClaim { worker_id: "gone".to_string(), lease_expires_at: now + 3600, heartbeat_at: Some(heartbeat_at) }),
+            ..testsupport::task(description, "worker")
+        };
+        let stuck = in_progress("Hangs", now - 3600);
+        let alive = in_progress("Working", now);
+        let todo_list = mongo.seeded([stuck.clone(), alive.clone()]).await?;
+
+        let reaped = todo_list.reap_stuck_tasks(StuckTaskPolicy { deadline: Duration::from_secs(60) }).await?;
+        assert_eq!(reaped.iter().map(|task| task.id.as_str()).collect::<Vec<_>>(), [stuck.id.as_str()]);
+        let retried = todo_list.get_task(&stuck.id).await?.unwrap();
+        assert_eq!(retried.status, TaskStatus::Pending);
+        assert!(retried.claim.is_none());
+        assert_eq!(retried.failures.len(), 1);
+        assert_eq!(todo_list.get_task(&alive.id).await?.unwrap().claim, alive.claim);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_in_progress_tasks_without_a_claim_are_retried_after_the_deadline() -> anyhow::Result<()> {
+        let mongo = MongoFixture::start().await?;
+        let now = Utc::now().timestamp();
+        let left = TodoTask { last_modified: Some(now - 3600), ..testsupport::task("Left behind", "worker") };
+        let recent = TodoTask { last_modified: Some(now), ..testsupport::task("Just started", "worker") };
+        let todo_list = mongo.seeded([left.clone(), recent.clone()]).await?;
+        // As stored by workers from before claims
+        todo_list.collection().update_many(doc! {}, doc! { "$set": { "status": LEGACY_IN_PROGRESS } }, None).await?;
+        assert!(todo_list.get_next_task().await?.is_none(), "claimless tasks aren't claimable by lease");
+
+        let reaped = todo_list.reap_stuck_tasks(StuckTaskPolicy { deadline: Duration::from_secs(60) }).await?;
+        assert_eq!(reaped.iter().map(|task| task.id.as_str()).collect::<Vec<_>>(), [left.id.as_str()]);
+        assert_eq!(todo_list.get_task(&left.id).await?.unwrap().status, TaskStatus::Pending);
+        assert_eq!(todo_list.get_next_task().await?.map(|task| task.id), Some(left.id));
+        Ok(())
+    }
+}
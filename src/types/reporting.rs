@@ -0,0 +1,508 @@
+use std::collections::{BTreeMap, HashMap};
+use mongodb::bson::{doc, Bson, Document};
+use mongodb::error::Error as MongoError;
+use futures_util::TryStreamExt;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use super::tenants;
+use super::todo::{TodoList, TodoTask, TaskStatus};
+
+const UNASSIGNED_PROJECT: &str = "unassigned";
+
+/// Aggregated task counts and completion metrics for one project
+#[derive(Debug, Clone, Default, PartialEq, Serialize, ToSchema)]
+pub struct ProjectStats {
+    pub project: String,
+    pub total: u64,
+    pub by_status: BTreeMap<String, u64>,
+    pub by_priority: BTreeMap<String, u64>,
+    pub completed: u64,
+    pub failed: u64,
+    /// Fraction of tasks that are completed (0.0 - 1.0)
+    pub completion_rate: f64,
+    /// Mean minutes from creation to completion, over completed tasks with both timestamps
+    pub average_completion_minutes: Option<f64>,
+}
+
+/// One group of tasks sharing project, status and priority
+#[derive(Debug, Clone)]
+struct StatsRow {
+    project: String,
+    status: String,
+    priority: String,
+    count: u64,
+    timed_completions: u64,
+    completion_seconds: i64,
+}
+
+impl StatsRow {
+    fn from_task(task: &TodoTask) -> Self {
+        let completion = match (&task.status, task.completed_at) {
+            (TaskStatus::Completed, Some(completed_at)) => Some(completed_at - task.created_at),
+            _ => None,
+        };
+        Self {
+            project: task.project.clone().unwrap_or_else(|| UNASSIGNED_PROJECT.to_string()),
+            status: enum_label(&task.status),
+            priority: enum_label(&task.priority),
+            count: 1,
+            timed_completions: completion.is_some() as u64,
+            completion_seconds: completion.unwrap_or(0),
+        }
+    }
+
+    fn from_document(row: &Document) -> Option<Self> {
+        let id = row.get_document("_id").ok()?;
+        Some(Self {
+            project: id.get_str("project").unwrap_or(UNASSIGNED_PROJECT).to_string(),
+            status: id.get_str("status").unwrap_or("unknown").to_string(),
+            priority: id.get_str("priority").unwrap_or("unknown").to_string(),
+            count: bson_to_i64(row.get("count")) as u64,
+            timed_completions: bson_to_i64(row.get("timed_completions")) as u64,
+            completion_seconds: bson_to_i64(row.get("completion_seconds")),
+        })
+    }
+}
+
+fn enum_label<T: Serialize>(value: &T) -> String {
+    serde_json::to_value(value).ok()
+        .and_then(|v| v.as_str().map(|s| s.to_string()))
+        .unwrap_or_else(|| "unknown".to_string())
+}
+
+fn bson_to_i64(value: Option<&Bson>) -> i64 {
+    match value {
+        Some(Bson::Int32(v)) => *v as i64,
+        Some(Bson::Int64(v)) => *v,
+        Some(Bson::Double(v)) => *v as i64,
+        _ => 0,
+    }
+}
+
+/// Fold grouped rows into per-project stats, sorted by project name
+fn fold_rows(rows: impl IntoIterator<Item = StatsRow>) -> Vec<ProjectStats> {
+    let mut projects: BTreeMap<String, (ProjectStats, u64, i64)> = BTreeMap::new();
+    for row in rows {
+        let (stats, timed, seconds) = projects.entry(row.project.clone())
+            .or_insert_with(|| (ProjectStats { project: row.project.clone(), ..Default::default() }, 0, 0));
+        stats.total += row.count;
+        *stats.by_status.entry(row.status.clone()).or_insert(0) += row.count;
+        *stats.by_priority.entry(row.priority.clone()).or_insert(0) += row.count;
+        match row.status.as_str() {
+            "completed" => stats.completed += row.count,
+            "failed" => stats.failed += row.count,
+            _ => {}
+        }
+        *timed += row.timed_completions;
+        *seconds += row.completion_seconds;
+    }
+
+    projects.into_values()
+        .map(|(mut stats, timed, seconds)| {
+            stats.completion_rate = if stats.total == 0 { 0.0 } else { stats.completed as f64 / stats.total as f64 };
+            stats.average_completion_minutes = (timed > 0).then(|| seconds as f64 / timed as f64 / 60.0);
+            stats
+        })
+        .collect()
+}
+
+/// Compute stats from tasks already in memory, e.g. results fetched from the MCP server
+pub fn stats_from_tasks(tasks: &[TodoTask]) -> Vec<ProjectStats> {
+    fold_rows(tasks.iter().map(StatsRow::from_task))
+}
+
+impl TodoList {
+    /// Aggregate task statistics per project, optionally for a single project
+    pub async fn project_stats(&self, project: Option<&str>) -> Result<Vec<ProjectStats>, MongoError> {
+        let mut pipeline: Vec<_> = tenants::scope_stage(self.tenant()).into_iter().collect();
+        if let Some(project) = project {
+            pipeline.push(doc! { "$match": { "project": project } });
+        }
+
+        // Older documents stored completed_at as a BSON date; normalise to seconds
+        let completed_seconds = doc! {
+            "$cond": [
+                { "$eq": [{ "$type": "$completed_at" }, "date"] },
+                { "$divide": [{ "$toLong": "$completed_at" }, 1000] },
+                "$completed_at"
+            ]
+        };
+        let timed = doc! {
+            "$and": [
+                { "$eq": ["$status", "completed"] },
+                { "$ne": [{ "$ifNull": ["$completed_at", Bson::Null] }, Bson::Null] }
+            ]
+        };
+        pipeline.push(doc! {
+            "$group": {
+                "_id": {
+                    "project": { "$ifNull": ["$project", UNASSIGNED_PROJECT] },
+                    "status": "$status",
+                    "priority": "$priority",
+                },
+                "count": { "$sum": 1 },
+                "timed_completions": { "$sum": { "$cond": [timed.clone(), 1, 0] } },
+                "completion_seconds": {
+                    "$sum": { "$cond": [timed, { "$subtract": [completed_seconds, "$created_at"] }, 0] }
+                },
+            }
+        });
+
+        let mut cursor = self.collection().aggregate(pipeline, None).await?;
+        let mut rows = Vec::new();
+        while let Some(row) = cursor.try_next().await? {
+            if let Some(row) = StatsRow::from_document(&row) {
+                rows.push(row);
+            }
+        }
+        Ok(fold_rows(rows))
+    }
+}
+
+/// Task counts for one tag, across projects
+#[derive(Debug, Clone, Default, PartialEq, Serialize, ToSchema)]
+pub struct TagStats {
+    pub tag: String,
+    pub total: u64,
+    pub completed: u64,
+    pub failed: u64,
+    /// Fraction of tagged tasks that are completed (0.0 - 1.0)
+    pub completion_rate: f64,
+}
+
+/// Fold (tag, status, count) rows into per-tag stats, most used first
+fn fold_tag_rows(rows: impl IntoIterator<Item = (String, String, u64)>) -> Vec<TagStats> {
+    let mut tags: BTreeMap<String, TagStats> = BTreeMap::new();
+    for (tag, status, count) in rows {
+        let stats = tags.entry(tag.clone()).or_insert_with(|| TagStats { tag, ..Default::default() });
+        stats.total += count;
+        match status.as_str() {
+            "completed" => stats.completed += count,
+            "failed" => stats.failed += count,
+            _ => {}
+        }
+    }
+    let mut stats: Vec<TagStats> = tags.into_values()
+        .map(|mut stats| {
+            stats.completion_rate = if stats.total == 0 { 0.0 } else { stats.completed as f64 / stats.total as f64 };
+            stats
+        })
+        .collect();
+    // Stable, so ties stay in tag order
+    stats.sort_by(|a, b| b.total.cmp(&a.total));
+    stats
+}
+
+/// Tag stats from tasks already in memory
+pub fn tag_stats_from_tasks(tasks: &[TodoTask]) -> Vec<TagStats> {
+    fold_tag_rows(tasks.iter().flat_map(|task| {
+        let status = enum_label(&task.status);
+        task.tags.iter().map(move |tag| (tag.clone(), status.clone(), 1))
+    }))
+}
+
+impl TodoList {
+    /// Aggregate task counts per tag, optionally for a single project
+    pub async fn tag_stats(&self, project: Option<&str>) -> Result<Vec<TagStats>, MongoError> {
+        let mut pipeline: Vec<_> = tenants::scope_stage(self.tenant()).into_iter().collect();
+        if let Some(project) = project {
+            pipeline.push(doc! { "$match": { "project": project } });
+        }
+        pipeline.push(doc! { "$unwind": "$tags" });
+        pipeline.push(doc! {
+            "$group": {
+                "_id": { "tag": "$tags", "status": "$status" },
+                "count": { "$sum": 1 },
+            }
+        });
+
+        let mut cursor = self.collection().aggregate(pipeline, None).await?;
+        let mut rows = Vec::new();
+        while let Some(row) = cursor.try_next().await? {
+            let Ok(id) = row.get_document("_id") else { continue };
+            let Ok(tag) = id.get_str("tag") else { continue };
+            let status = id.get_str("status").unwrap_or("unknown").to_string();
+            rows.push((tag.to_string(), status, bson_to_i64(row.get("count")) as u64));
+        }
+        Ok(fold_tag_rows(rows))
+    }
+}
+
+/// Tasks waiting for one agent and being worked on for it
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct QueueDepth {
+    pub agent: String,
+    pub pending: u64,
+    pub in_progress: u64,
+}
+
+/// A worker holding claims, as the task collection shows it
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct WorkerStatus {
+    pub worker_id: String,
+    /// In-progress tasks it has claimed
+    pub tasks: u64,
+    /// Its latest heartbeat on any of them; unset for claims made by older workers
+    pub last_heartbeat: Option<i64>,
+}
+
+impl TodoList {
+    /// Pending and in-progress task counts per agent, by agent name
+    pub async fn queue_depths(&self) -> Result<Vec<QueueDepth>, MongoError> {
+        let pipeline = vec![
+            doc! { "$match": self.scope(doc! { "status": { "$in": ["pending", "in_progress"] } }) },
+            doc! { "$group": { "_id": { "agent": "$target_agent", "status": "$status" }, "count": { "$sum": 1 } } },
+        ];
+        let mut cursor = self.collection().aggregate(pipeline, None).await?;
+        let mut depths: BTreeMap<String, QueueDepth> = BTreeMap::new();
+        while let Some(row) = cursor.try_next().await? {
+            let Ok(id) = row.get_document("_id") else { continue };
+            let Ok(agent) = id.get_str("agent") else { continue };
+            let depth = depths.entry(agent.to_string())
+                .or_insert_with(|| QueueDepth { agent: agent.to_string(), ..Default::default() });
+            let count = bson_to_i64(row.get("count")) as u64;
+            match id.get_str("status") {
+                Ok("pending") => depth.pending += count,
+                _ => depth.in_progress += count,
+            }
+        }
+        Ok(depths.into_values().collect())
+    }
+
+    /// Workers with claimed in-progress tasks, most recently heard from first
+    pub async fn worker_statuses(&self) -> Result<Vec<WorkerStatus>, MongoError> {
+        let pipeline = vec![
+            doc! { "$match": self.scope(doc! { "status": "in_progress", "claim.worker_id": { "$exists": true } }) },
+            doc! {
+                "$group": {
+                    "_id": "$claim.worker_id",
+                    "tasks": { "$sum": 1 },
+                    "last_heartbeat": { "$max": "$claim.heartbeat_at" },
+                }
+            },
+            doc! { "$sort": { "last_heartbeat": -1, "_id": 1 } },
+        ];
+        let mut cursor = self.collection().aggregate(pipeline, None).await?;
+        let mut workers = Vec::new();
+        while let Some(row) = cursor.try_next().await? {
+            let Ok(worker_id) = row.get_str("_id") else { continue };
+            workers.push(WorkerStatus {
+                worker_id: worker_id.to_string(),
+                tasks: bson_to_i64(row.get("tasks")) as u64,
+                last_heartbeat: match row.get("last_heartbeat") {
+                    None | Some(Bson::Null) => None,
+                    heartbeat => Some(bson_to_i64(heartbeat)),
+                },
+            });
+        }
+        Ok(workers)
+    }
+
+    /// A cheap round trip to the task collection, e.g. to measure storage latency
+    pub async fn ping(&self) -> Result<(), MongoError> {
+        self.collection().estimated_document_count(None).await?;
+        Ok(())
+    }
+}
+
+/// Render tag stats as a short plain-text report
+pub fn format_tag_report(stats: &[TagStats]) -> String {
+    let mut output = String::from("Tags:\n");
+    for tag in stats {
+        output.push_str(&format!(
+            "- {}: {} total, {} completed ({:.0}%), {} failed\n",
+            tag.tag, tag.total, tag.completed, tag.completion_rate * 100.0, tag.failed
+        ));
+    }
+    output
+}
+
+/// Render stats as a short plain-text report
+pub fn format_report(stats: &[ProjectStats]) -> String {
+    if stats.is_empty() {
+        return "No todos found.".to_string();
+    }
+    let mut output = String::from("Todo report:\n");
+    for project in stats {
+        output.push_str(&format!(
+            "- {}: {} total, {} completed ({:.0}%), {} failed",
+            project.project, project.total, project.completed, project.completion_rate * 100.0, project.failed
+        ));
+        if let Some(minutes) = project.average_completion_minutes {
+            output.push_str(&format!(", avg {:.1} min to complete", minutes));
+        }
+        output.push('\n');
+    }
+    output
+}
+
+/// Unfinished tasks grouped by project, highest priority and oldest first, listing at most
+/// `per_project` of each
+pub fn format_open_summary(tasks: &[TodoTask], per_project: usize) -> String {
+    let mut by_project: BTreeMap<&str, Vec<&TodoTask>> = BTreeMap::new();
+    for task in tasks.iter().filter(|t| matches!(t.status, TaskStatus::Initial | TaskStatus::Pending | TaskStatus::InProgress)) {
+        by_project.entry(task.project.as_deref().unwrap_or(UNASSIGNED_PROJECT)).or_default().push(task);
+    }
+    if by_project.is_empty() {
+        return "No open todos.".to_string();
+    }
+    let mut output = String::from("Open todos:\n");
+    for (project, mut open) in by_project {
+        open.sort_by(|a, b| b.priority.cmp(&a.priority).then(a.created_at.cmp(&b.created_at)));
+        output.push_str(&format!("{} ({} open)\n", project, open.len()));
+        for task in open.iter().take(per_project) {
+            output.push_str(&format!("  - [{:?}] {} ({})\n", task.priority, task.description, task.target_agent));
+        }
+        if open.len() > per_project {
+            output.push_str(&format!("  - …and {} more\n", open.len() - per_project));
+        }
+    }
+    output
+}
+
+/// Stats for a project name, or an empty entry if it has no tasks
+pub fn stats_for(stats: Vec<ProjectStats>, project: &str) -> ProjectStats {
+    let mut by_name: HashMap<String, ProjectStats> = stats.into_iter()
+        .map(|s| (s.project.clone(), s))
+        .collect();
+    by_name.remove(project).unwrap_or_else(|| ProjectStats {
+        project: project.to_string(),
+        ..Default::default()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::TaskPriority;
+
+    fn task(project: &str, status: TaskStatus, priority: TaskPriority, minutes: Option<i64>) -> TodoTask {
+        TodoTask {
+            id: uuid::Uuid::new_v4().to_string(),
+            description: "task".to_string(),
+            enhanced_description: None,
+            priority,
+            project: Some(project.to_string()),
+            source_agent: None,
+            target_agent: "user".to_string(),
+            status,
+            created_at: 1_000,
+            completed_at: minutes.map(|m| 1_000 + m * 60),
+            due_date: None,
+            duration_minutes: None,
+            notes: None,
+            ticket: None,
+            last_modified: None,
+            failure_reason: None,
+            embedding: None,
+            claim: None,
+            tags: Vec::new(),
+            failures: Vec::new(),
+            confidence: None,
+            reviewed_at: None,
+            tenant_id: None,
+            idempotency_key: None,
+            content_hash: None,
+            history: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_stats_from_tasks() {
+        let tasks = vec![
+            task("swarm", TaskStatus::Completed, TaskPriority::High, Some(10)),
+            task("swarm", TaskStatus::Completed, TaskPriority::Low, Some(30)),
+            task("swarm", TaskStatus::Failed, TaskPriority::High, None),
+            task("swarm", TaskStatus::Pending, TaskPriority::Medium, None),
+            task("docs", TaskStatus::Pending, TaskPriority::Low, None),
+        ];
+
+        let stats = stats_from_tasks(&tasks);
+        assert_eq!(stats.len(), 2);
+
+        let swarm = stats_for(stats, "swarm");
+        assert_eq!(swarm.total, 4);
+        assert_eq!(swarm.completed, 2);
+        assert_eq!(swarm.failed, 1);
+        assert_eq!(swarm.by_status["pending"], 1);
+        assert_eq!(swarm.by_priority["High"], 2);
+        assert!((swarm.completion_rate - 0.5).abs() < f64::EPSILON);
+        assert_eq!(swarm.average_completion_minutes, Some(20.0));
+    }
+
+    #[test]
+    fn test_stats_row_from_document() {
+        let row = doc! {
+            "_id": { "project": "swarm", "status": "completed", "priority": "High" },
+            "count": 3,
+            "timed_completions": 2i64,
+            "completion_seconds": 1200.0,
+        };
+        let stats = fold_rows(StatsRow::from_document(&row));
+        assert_eq!(stats[0].total, 3);
+        assert_eq!(stats[0].average_completion_minutes, Some(10.0));
+        assert!(format_report(&stats).contains("swarm: 3 total, 3 completed (100%)"));
+    }
+
+    #[test]
+    fn test_tag_stats_from_tasks() {
+        let tagged = |status, tags: &[&str]| TodoTask {
+            tags: tags.iter().map(|t| t.to_string()).collect(),
+            ..task("swarm", status, TaskPriority::Medium, None)
+        };
+        let tasks = vec![
+            tagged(TaskStatus::Completed, &["bug", "api"]),
+            tagged(TaskStatus::Failed, &["bug"]),
+            tagged(TaskStatus::Pending, &["bug", "docs"]),
+            tagged(TaskStatus::Completed, &[]),
+        ];
+
+        let stats = tag_stats_from_tasks(&tasks);
+        assert_eq!(stats.iter().map(|s| s.tag.as_str()).collect::<Vec<_>>(), ["bug", "api", "docs"]);
+        assert_eq!((stats[0].total, stats[0].completed, stats[0].failed), (3, 1, 1));
+        assert!((stats[1].completion_rate - 1.0).abs() < f64::EPSILON);
+        assert!(format_tag_report(&stats).contains("- bug: 3 total, 1 completed (33%), 1 failed"));
+    }
+
+    #[test]
+    fn test_open_summary_lists_unfinished_by_priority() {
+        let named = |project, status, priority, description: &str| TodoTask {
+            description: description.to_string(),
+            ..task(project, status, priority, None)
+        };
+        let tasks = vec![
+            named("swarm", TaskStatus::Pending, TaskPriority::Low, "tidy"),
+            named("swarm", TaskStatus::InProgress, TaskPriority::Critical, "outage"),
+            named("swarm", TaskStatus::Pending, TaskPriority::Medium, "docs"),
+            named("swarm", TaskStatus::Completed, TaskPriority::High, "done"),
+            named("web", TaskStatus::Completed, TaskPriority::High, "shipped"),
+        ];
+        assert_eq!(
+            format_open_summary(&tasks, 2),
+            "Open todos:\nswarm (3 open)\n  - [Critical] outage (user)\n  - [Medium] docs (user)\n  - …and 1 more\n"
+        );
+        assert_eq!(format_open_summary(&tasks[3..], 2), "No open todos.");
+    }
+
+    #[tokio::test]
+    async fn test_queue_depths_and_workers() -> anyhow::Result<()> {
+        let mongo = crate::testsupport::MongoFixture::start().await?;
+        let todos = mongo.seeded([
+            crate::testsupport::task("Fix the login page", "git"),
+            crate::testsupport::task("Write a haiku", "haiku"),
+            crate::testsupport::task("Another haiku", "haiku"),
+        ]).await?;
+        let claimed = todos.for_agent("git").get_next_task().await?.expect("a git task");
+
+        let depths = todos.queue_depths().await?;
+        assert_eq!(depths, [
+            QueueDepth { agent: "git".to_string(), pending: 0, in_progress: 1 },
+            QueueDepth { agent: "haiku".to_string(), pending: 2, in_progress: 0 },
+        ]);
+        let workers = todos.worker_statuses().await?;
+        assert_eq!(workers.len(), 1);
+        assert_eq!((workers[0].worker_id.as_str(), workers[0].tasks), (todos.worker_id(), 1));
+        assert_eq!(workers[0].last_heartbeat, claimed.claim.and_then(|claim| claim.heartbeat_at));
+        Ok(())
+    }
+}
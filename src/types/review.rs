@@ -0,0 +1,205 @@
+//! Human review of AI enhancements. Tasks whose enhancement confidence is under
+//! `REVIEW_CONFIDENCE_THRESHOLD` wait in a review queue until someone approves or corrects
+//! their priority and project. Corrections are kept in `enhancement_corrections`, and
+//! project corrections become examples for the project classifier (see `ai::few_shot`).
+
+use std::env;
+use chrono::Utc;
+use mongodb::bson::{self, doc};
+use mongodb::error::Error as MongoError;
+use mongodb::options::FindOptions;
+use futures_util::TryStreamExt;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use crate::ai::{FewShotExample, FewShotExamples};
+use super::todo::{TaskPriority, TodoList, TodoTask};
+
+const DEFAULT_THRESHOLD: f32 = 0.6;
+/// Most recent corrections loaded as classifier examples
+const MAX_EXAMPLES: i64 = 200;
+
+/// Confidence under which enhancements need review, from `REVIEW_CONFIDENCE_THRESHOLD`
+pub fn threshold_from_env() -> f32 {
+    env::var("REVIEW_CONFIDENCE_THRESHOLD").ok()
+        .and_then(|t| t.parse().ok())
+        .filter(|t: &f32| (0.0..=1.0).contains(t))
+        .unwrap_or(DEFAULT_THRESHOLD)
+}
+
+/// Whether a task is waiting for review at `threshold`
+pub fn needs_review(task: &TodoTask, threshold: f32) -> bool {
+    task.reviewed_at.is_none() && task.confidence.is_some_and(|confidence| confidence < threshold)
+}
+
+/// The priority and project a reviewer says a task should have; unset fields were right
+#[derive(Debug, Clone, Default, Deserialize, ToSchema)]
+pub struct Correction {
+    pub priority: Option<TaskPriority>,
+    pub project: Option<String>,
+}
+
+/// A reviewer's fix to an enhancement, kept as an example for later enhancements
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct EnhancementCorrection {
+    pub task_id: String,
+    pub description: String,
+    pub original_priority: TaskPriority,
+    pub corrected_priority: TaskPriority,
+    pub original_project: Option<String>,
+    pub corrected_project: Option<String>,
+    pub corrected_at: i64,
+}
+
+impl Correction {
+    /// Apply to `task`, marking it reviewed; the record of what changed, if anything did
+    pub fn apply(self, task: &mut TodoTask) -> Option<EnhancementCorrection> {
+        let now = Utc::now().timestamp();
+        let original_priority = task.priority.clone();
+        let original_project = task.project.clone();
+        if let Some(priority) = self.priority {
+            task.priority = priority;
+        }
+        if let Some(project) = self.project {
+            task.project = Some(project);
+        }
+        task.reviewed_at = Some(now);
+        task.last_modified = Some(now);
+        let changed = task.priority != original_priority || task.project != original_project;
+        changed.then(|| EnhancementCorrection {
+            task_id: task.id.clone(),
+            description: task.description.clone(),
+            original_priority,
+            corrected_priority: task.priority.clone(),
+            original_project,
+            corrected_project: task.project.clone(),
+            corrected_at: now,
+        })
+    }
+}
+
+impl TodoList {
+    /// Unreviewed tasks enhanced with confidence under `threshold`, least confident first
+    pub async fn review_queue(&self, threshold: f32, limit: i64) -> Result<Vec<TodoTask>, MongoError> {
+        let filter = self.scope(doc! {
+            "confidence": { "$lt": threshold as f64 },
+            "reviewed_at": { "$exists": false },
+        });
+        let options = FindOptions::builder()
+            .sort(doc! { "confidence": 1, "created_at": 1 })
+            .limit(limit)
+            .build();
+        self.collection().find(filter, options).await?.try_collect().await
+    }
+
+    /// Mark a task's enhancement reviewed, applying `correction`. Returns the updated task,
+    /// or `None` if it doesn't exist.
+    pub async fn review(&self, task_id: &str, correction: Correction) -> Result<Option<TodoTask>, MongoError> {
+        let Some(mut task) = self.collection().find_one(self.scope(doc! { "id": task_id }), None).await? else {
+            return Ok(None);
+        };
+        let record = correction.apply(&mut task);
+        let update = doc! { "$set": {
+            "priority": bson::to_bson(&task.priority)?,
+            "project": bson::to_bson(&task.project)?,
+            "reviewed_at": task.reviewed_at,
+            "last_modified": task.last_modified,
+        } };
+        self.collection().update_one(self.scope(doc! { "id": task_id }), update, None).await?;
+        if let Some(record) = record {
+            tracing::info!("Task {} enhancement corrected: {:?} -> {:?}", task_id, record.original_project, record.corrected_project);
+            self.corrections_collection().insert_one(&record, None).await?;
+            if let Some(example) = FewShotExample::from_correction(record) {
+                self.project_classifier().examples().add(example).await;
+            }
+        }
+        Ok(Some(task))
+    }
+
+    /// Stored corrections, newest first
+    pub async fn enhancement_corrections(&self, limit: i64) -> Result<Vec<EnhancementCorrection>, MongoError> {
+        let options = FindOptions::builder().sort(doc! { "corrected_at": -1 }).limit(limit).build();
+        self.corrections_collection().find(None, options).await?.try_collect().await
+    }
+
+    /// Replace `examples` with the latest project corrections, one per description
+    pub async fn load_examples(&self, examples: &FewShotExamples) -> Result<usize, MongoError> {
+        let mut loaded: Vec<FewShotExample> = Vec::new();
+        for example in self.enhancement_corrections(MAX_EXAMPLES).await?.into_iter().filter_map(FewShotExample::from_correction) {
+            if !loaded.iter().any(|seen| seen.description == example.description) {
+                loaded.push(example);
+            }
+        }
+        let count = loaded.len();
+        // Oldest first, as `FewShotExamples::add` keeps them
+        loaded.reverse();
+        examples.replace(loaded).await;
+        Ok(count)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::TaskStatus;
+
+    fn task(confidence: Option<f32>) -> TodoTask {
+        TodoTask {
+            id: "t1".to_string(),
+            description: "Fix login redirect".to_string(),
+            enhanced_description: None,
+            priority: TaskPriority::Low,
+            project: Some("madness_interactive".to_string()),
+            source_agent: None,
+            target_agent: "user".to_string(),
+            status: TaskStatus::Pending,
+            created_at: 0,
+            completed_at: None,
+            due_date: None,
+            duration_minutes: None,
+            notes: None,
+            ticket: None,
+            last_modified: None,
+            embedding: None,
+            failure_reason: None,
+            claim: None,
+            failures: Vec::new(),
+            tags: Vec::new(),
+            confidence,
+            reviewed_at: None,
+            tenant_id: None,
+            idempotency_key: None,
+            content_hash: None,
+            history: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_needs_review_below_threshold_until_reviewed() {
+        assert!(needs_review(&task(Some(0.3)), 0.6));
+        assert!(!needs_review(&task(Some(0.8)), 0.6));
+        assert!(!needs_review(&task(None), 0.6));
+
+        let mut reviewed = task(Some(0.3));
+        assert_eq!(Correction::default().apply(&mut reviewed), None);
+        assert!(reviewed.reviewed_at.is_some());
+        assert!(!needs_review(&reviewed, 0.6));
+    }
+
+    #[test]
+    fn test_correction_records_what_changed() {
+        let mut corrected = task(Some(0.2));
+        let correction = Correction { priority: Some(TaskPriority::High), project: Some("swarmonomicon".to_string()) };
+        let record = correction.apply(&mut corrected).unwrap();
+        assert_eq!(corrected.priority, TaskPriority::High);
+        assert_eq!(corrected.project.as_deref(), Some("swarmonomicon"));
+        assert_eq!(record.original_priority, TaskPriority::Low);
+        assert_eq!(record.original_project.as_deref(), Some("madness_interactive"));
+        assert_eq!(record.corrected_project.as_deref(), Some("swarmonomicon"));
+        assert_eq!(record.description, "Fix login redirect");
+
+        // Restating the same values isn't a correction
+        let mut same = task(Some(0.2));
+        let correction = Correction { priority: Some(TaskPriority::Low), project: None };
+        assert_eq!(correction.apply(&mut same), None);
+    }
+}
@@ -0,0 +1,206 @@
+//! The order pending tasks are handed out in: highest priority first, oldest first within
+//! a priority. Tasks gain a priority level for every `aging_interval` they wait, so a
+//! steady stream of urgent work can't starve low-priority tasks forever. MongoDB does the
+//! ordering, so claiming a task doesn't load the whole queue.
+
+use std::cmp::Reverse;
+use std::env;
+use mongodb::IndexModel;
+use mongodb::bson::{self, Bson, Document, doc};
+use mongodb::error::Error as MongoError;
+use mongodb::options::IndexOptions;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use super::todo::{TaskPriority, TodoList, TodoTask};
+
+const DEFAULT_AGING_SECS: i64 = 3600;
+const INDEX_NAME: &str = "queue";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SchedulingPolicy {
+    /// Seconds of waiting per priority level gained; zero disables aging
+    pub aging_interval_secs: i64,
+}
+
+impl Default for SchedulingPolicy {
+    fn default() -> Self {
+        Self { aging_interval_secs: DEFAULT_AGING_SECS }
+    }
+}
+
+impl SchedulingPolicy {
+    /// Aging from `TODO_PRIORITY_AGING_SECS`, defaulting to an hour per level
+    pub fn from_env() -> Self {
+        env::var("TODO_PRIORITY_AGING_SECS").ok()
+            .and_then(|secs| secs.parse().ok())
+            .map(|aging_interval_secs| Self { aging_interval_secs })
+            .unwrap_or_default()
+    }
+
+    /// The priority the task is scheduled at, after aging, as of `now`
+    pub fn effective_priority(&self, task: &TodoTask, now: i64) -> TaskPriority {
+        let waited = (now - task.created_at).max(0);
+        let boost = if self.aging_interval_secs > 0 { waited / self.aging_interval_secs } else { 0 };
+        priority_at(rank(&task.priority).saturating_add(boost))
+    }
+
+    /// Sort `tasks` into the order they should be processed
+    pub fn order(&self, tasks: &mut [TodoTask], now: i64) {
+        tasks.sort_by_cached_key(|task| {
+            (Reverse(self.effective_priority(task, now)), task.created_at, task.id.clone())
+        });
+    }
+
+    /// Aggregation stages that sort tasks as `order` does, so the database does the sorting.
+    /// They leave the effective priority's rank in a `schedule_rank` field.
+    pub fn sort_stages(&self, now: i64) -> Result<Vec<Document>, bson::ser::Error> {
+        let priorities = [TaskPriority::Inital, TaskPriority::Low, TaskPriority::Medium, TaskPriority::High, TaskPriority::Critical];
+        let mut branches = Vec::new();
+        for priority in &priorities {
+            branches.push(doc! { "case": { "$eq": ["$priority", bson::to_bson(priority)?] }, "then": rank(priority) });
+        }
+        let boost: Bson = if self.aging_interval_secs > 0 {
+            let waited = doc! { "$max": [{ "$subtract": [now, "$created_at"] }, 0_i64] };
+            doc! { "$floor": { "$divide": [waited, self.aging_interval_secs] } }.into()
+        } else {
+            Bson::Int64(0)
+        };
+        let base = doc! { "$switch": { "branches": branches, "default": 0_i64 } };
+        Ok(vec![
+            doc! { "$addFields": { "schedule_rank": { "$min": [{ "$add": [base, boost] }, rank(&TaskPriority::Critical)] } } },
+            doc! { "$sort": { "schedule_rank": -1, "created_at": 1, "id": 1 } },
+        ])
+    }
+}
+
+fn rank(priority: &TaskPriority) -> i64 {
+    match priority {
+        TaskPriority::Inital => 0,
+        TaskPriority::Low => 1,
+        TaskPriority::Medium => 2,
+        TaskPriority::High => 3,
+        TaskPriority::Critical => 4,
+    }
+}
+
+fn priority_at(rank: i64) -> TaskPriority {
+    match rank {
+        i64::MIN..=0 => TaskPriority::Inital,
+        1 => TaskPriority::Low,
+        2 => TaskPriority::Medium,
+        3 => TaskPriority::High,
+        _ => TaskPriority::Critical,
+    }
+}
+
+/// Where a pending task stands in the queue
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct QueuePosition {
+    pub task_id: String,
+    /// 1 means the task is next
+    pub position: usize,
+    pub queue_length: usize,
+    /// The task's priority after aging
+    pub effective_priority: TaskPriority,
+}
+
+impl TodoList {
+    /// Create the index queue lookups use; a no-op if it already exists
+    pub async fn ensure_queue_index(&self) -> Result<(), MongoError> {
+        let index = IndexModel::builder()
+            .keys(doc! { "status": 1, "target_agent": 1, "created_at": 1 })
+            .options(Some(IndexOptions::builder().name(INDEX_NAME.to_string()).build()))
+            .build();
+        self.collection().create_index(index, None).await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::TaskStatus;
+
+    fn task(id: &str, priority: TaskPriority, created_at: i64) -> TodoTask {
+        TodoTask {
+            id: id.to_string(),
+            description: id.to_string(),
+            enhanced_description: None,
+            priority,
+            project: None,
+            source_agent: None,
+            target_agent: "git".to_string(),
+            status: TaskStatus::Pending,
+            created_at,
+            completed_at: None,
+            due_date: None,
+            duration_minutes: None,
+            notes: None,
+            ticket: None,
+            last_modified: None,
+            failure_reason: None,
+            embedding: None,
+            claim: None,
+            tags: Vec::new(),
+            failures: Vec::new(),
+            confidence: None,
+            reviewed_at: None,
+            tenant_id: None,
+            idempotency_key: None,
+            content_hash: None,
+            history: Vec::new(),
+        }
+    }
+
+    fn ids(tasks: &[TodoTask]) -> Vec<&str> {
+        tasks.iter().map(|t| t.id.as_str()).collect()
+    }
+
+    #[test]
+    fn test_orders_by_priority_then_age() {
+        let policy = SchedulingPolicy { aging_interval_secs: 0 };
+        let mut tasks = vec![
+            task("low", TaskPriority::Low, 100),
+            task("high-new", TaskPriority::High, 300),
+            task("critical", TaskPriority::Critical, 400),
+            task("high-old", TaskPriority::High, 200),
+            task("medium", TaskPriority::Medium, 0),
+        ];
+        policy.order(&mut tasks, 1_000_000);
+        assert_eq!(ids(&tasks), ["critical", "high-old", "high-new", "medium", "low"]);
+    }
+
+    #[test]
+    fn test_waiting_tasks_age_into_higher_priority() {
+        let policy = SchedulingPolicy { aging_interval_secs: 60 };
+        let now = 10_000;
+        let starved = task("starved", TaskPriority::Low, now - 125);
+        assert_eq!(policy.effective_priority(&starved, now), TaskPriority::High);
+        assert_eq!(policy.effective_priority(&task("ancient", TaskPriority::Inital, 0), now), TaskPriority::Critical);
+
+        let mut tasks = vec![task("fresh-high", TaskPriority::High, now), starved];
+        policy.order(&mut tasks, now);
+        assert_eq!(ids(&tasks), ["starved", "fresh-high"], "older wins at equal effective priority");
+    }
+
+    #[tokio::test]
+    async fn test_the_database_orders_tasks_like_the_policy() -> anyhow::Result<()> {
+        let mongo = crate::testsupport::MongoFixture::start().await?;
+        let now = chrono::Utc::now().timestamp();
+        let mut tasks = vec![
+            task("low", TaskPriority::Low, now - 10),
+            task("starved", TaskPriority::Low, now - 7_300),
+            task("high-new", TaskPriority::High, now - 5),
+            task("high-old", TaskPriority::High, now - 50),
+            task("critical", TaskPriority::Critical, now),
+            task("initial", TaskPriority::Inital, now - 20),
+        ];
+        let todo_list = mongo.seeded(tasks.clone()).await?.with_scheduling(SchedulingPolicy { aging_interval_secs: 3600 });
+
+        SchedulingPolicy { aging_interval_secs: 3600 }.order(&mut tasks, now);
+        let peeked = todo_list.peek(None, 4).await?;
+        assert_eq!(ids(&peeked), ids(&tasks[..4]));
+        assert_eq!(ids(&tasks[..3]), ["critical", "starved", "high-old"]);
+        Ok(())
+    }
+}
@@ -0,0 +1,171 @@
+//! Full-text search over tasks, backed by a MongoDB text index on `description`,
+//! `enhanced_description` and `project`. Results come back most relevant first, with the
+//! matching words in each field marked `**like this**`.
+
+use std::collections::BTreeMap;
+use futures_util::TryStreamExt;
+use mongodb::bson::doc;
+use mongodb::error::Error as MongoError;
+use mongodb::options::{FindOptions, IndexOptions};
+use mongodb::IndexModel;
+use serde::{Deserialize, Serialize};
+use super::todo::{TodoList, TodoTask};
+
+pub const TEXT_INDEX_NAME: &str = "task_text";
+/// Longest highlighted snippet; longer fields are cut down around the first match
+const SNIPPET_CHARS: usize = 200;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct ScoredTask {
+    #[serde(flatten)]
+    task: TodoTask,
+    score: f64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SearchHit {
+    pub task: TodoTask,
+    /// MongoDB's text score; higher is more relevant
+    pub score: f64,
+    /// Highlighted text of each field that matched, by field name
+    pub highlights: BTreeMap<String, String>,
+}
+
+/// The words a text search matches on, skipping negated terms like `-draft`
+pub fn search_terms(query: &str) -> Vec<String> {
+    query.split_whitespace()
+        .filter(|term| !term.starts_with('-'))
+        .flat_map(|term| term.split(|c: char| !c.is_alphanumeric()))
+        .filter(|word| !word.is_empty())
+        .map(stem)
+        .collect()
+}
+
+/// A rough stem, so "deploying" highlights for "deploy" as MongoDB's stemmer would match it
+fn stem(word: &str) -> String {
+    let word = word.to_lowercase();
+    for suffix in ["ing", "ed", "es", "s"] {
+        if let Some(stripped) = word.strip_suffix(suffix) {
+            if stripped.chars().count() >= 3 {
+                return stripped.to_string();
+            }
+        }
+    }
+    word
+}
+
+/// `text` with each word matching one of `terms` wrapped in `**`, or `None` if nothing
+/// matched. Long text is trimmed to a snippet around the first match.
+pub fn highlight(text: &str, terms: &[String]) -> Option<String> {
+    let mut output = String::with_capacity(text.len() + 16);
+    let mut first_match = None;
+    let mut rest = text;
+    while !rest.is_empty() {
+        let word_len = rest.find(|c: char| !c.is_alphanumeric()).unwrap_or(rest.len());
+        if word_len == 0 {
+            let separator = rest.chars().next().unwrap();
+            output.push(separator);
+            rest = &rest[separator.len_utf8()..];
+            continue;
+        }
+        let word = &rest[..word_len];
+        if terms.contains(&stem(word)) {
+            first_match.get_or_insert(output.len());
+            output.push_str("**");
+            output.push_str(word);
+            output.push_str("**");
+        } else {
+            output.push_str(word);
+        }
+        rest = &rest[word_len..];
+    }
+    first_match.map(|at| snippet(&output, at))
+}
+
+fn snippet(text: &str, first_match: usize) -> String {
+    if text.chars().count() <= SNIPPET_CHARS {
+        return text.to_string();
+    }
+    let lead = SNIPPET_CHARS / 4;
+    let start = text[..first_match].char_indices().rev().nth(lead).map(|(i, _)| i).unwrap_or(0);
+    let body: String = text[start..].chars().take(SNIPPET_CHARS).collect();
+    let prefix = if start > 0 { "…" } else { "" };
+    let suffix = if start + body.len() < text.len() { "…" } else { "" };
+    format!("{}{}{}", prefix, body, suffix)
+}
+
+impl SearchHit {
+    fn new(scored: ScoredTask, terms: &[String]) -> Self {
+        let task = scored.task;
+        let fields = [
+            ("description", Some(&task.description)),
+            ("enhanced_description", task.enhanced_description.as_ref()),
+            ("project", task.project.as_ref()),
+        ];
+        let highlights = fields.into_iter()
+            .filter_map(|(name, text)| Some((name.to_string(), highlight(text?, terms)?)))
+            .collect();
+        Self { task, score: scored.score, highlights }
+    }
+}
+
+impl TodoList {
+    /// Create the text index searches use; a no-op if it already exists
+    pub async fn ensure_text_index(&self) -> Result<(), MongoError> {
+        let index = IndexModel::builder()
+            .keys(doc! { "description": "text", "enhanced_description": "text", "project": "text" })
+            .options(Some(IndexOptions::builder()
+                .name(TEXT_INDEX_NAME.to_string())
+                .weights(doc! { "description": 10, "enhanced_description": 5, "project": 2 })
+                .build()))
+            .build();
+        self.collection().create_index(index, None).await?;
+        Ok(())
+    }
+
+    /// Tasks matching `query` and carrying all of `tags`, most relevant first. The query uses
+    /// MongoDB text search syntax: words match any, `"quoted phrases"` must appear, `-word` excludes.
+    pub async fn search(&self, query: &str, tags: &[String], limit: usize) -> Result<Vec<SearchHit>, MongoError> {
+        let options = FindOptions::builder()
+            .projection(doc! { "score": { "$meta": "textScore" }, "embedding": 0 })
+            .sort(doc! { "score": { "$meta": "textScore" } })
+            .limit(limit as i64)
+            .build();
+        let mut filter = self.scope(doc! { "$text": { "$search": query } });
+        if !tags.is_empty() {
+            filter.insert("tags", doc! { "$all": tags });
+        }
+        let scored: Vec<ScoredTask> = self.collection().clone_with_type::<ScoredTask>()
+            .find(filter, options).await?
+            .try_collect().await?;
+        let terms = search_terms(query);
+        Ok(scored.into_iter().map(|hit| SearchHit::new(hit, &terms)).collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_search_terms_skip_negations_and_stem() {
+        assert_eq!(search_terms(r#"Deploying "staging server" -draft"#), ["deploy", "stag", "server"]);
+        assert_eq!(search_terms("bus fixes"), ["bus", "fix"]);
+    }
+
+    #[test]
+    fn test_highlight_marks_matching_words() {
+        let terms = search_terms("deploy server");
+        assert_eq!(
+            highlight("Deployed the API server, then re-deploy.", &terms).unwrap(),
+            "**Deployed** the API **server**, then re-**deploy**."
+        );
+        assert_eq!(highlight("Nothing relevant", &terms), None);
+
+        let long = format!("{} server {}", "word ".repeat(100), "tail ".repeat(100));
+        let snippet = highlight(&long, &terms).unwrap();
+        assert!(snippet.starts_with('…') && snippet.ends_with('…'), "{}", snippet);
+        assert!(snippet.contains("**server**"));
+        assert!(snippet.chars().count() <= SNIPPET_CHARS + 2);
+    }
+}
@@ -0,0 +1,198 @@
+use std::collections::HashMap;
+use anyhow::Result;
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use super::{AgentStateManager, Message, MessageMetadata};
+use super::state_store::{self, StateStore};
+
+/// Metadata context key carrying the conversation's session id
+pub const SESSION_ID_KEY: &str = "session_id";
+/// Stands in for the agent name in a session's routing record, whose `current_state` is
+/// the agent handling the conversation; see `load_route`
+pub const ROUTING_AGENT: &str = "_routing";
+
+/// Where an agent's state machine was for one conversation
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SessionState {
+    pub agent: String,
+    pub session_id: String,
+    pub current_state: String,
+    #[serde(default)]
+    pub context: HashMap<String, String>,
+    pub updated_at: i64,
+}
+
+/// Session id from the message metadata context, if the client sent one. Sessions of
+/// messages sent for a tenant are namespaced as `tenant/session`, so tenants can't resume
+/// each other's conversations.
+pub fn message_session_id(message: &Message) -> Option<String> {
+    let session = message.metadata.as_ref()
+        .and_then(|m| m.context.as_ref())
+        .and_then(|c| c.get(SESSION_ID_KEY).cloned())?;
+    Some(session_key(super::tenants::message_tenant(message).as_deref(), &session))
+}
+
+/// The id `session` is kept under for `tenant`
+pub fn session_key(tenant: Option<&str>, session: &str) -> String {
+    match tenant {
+        Some(tenant) => format!("{}/{}", tenant, session),
+        None => session.to_string(),
+    }
+}
+
+/// `message` marked as part of session `session_id`, with `agent` as its sender if it had none
+pub fn tag_session(mut message: Message, agent: &str, session_id: &str) -> Message {
+    let metadata = message.metadata.get_or_insert_with(|| MessageMetadata::new(agent.to_string()));
+    metadata.context.get_or_insert_with(HashMap::new).insert(SESSION_ID_KEY.to_string(), session_id.to_string());
+    message
+}
+
+/// The agent handling the conversation in `session_id`, if it has been routed
+pub async fn load_route(store: &dyn StateStore, session_id: &str) -> Result<Option<String>> {
+    Ok(load_session(store, ROUTING_AGENT, session_id).await?.map(|route| route.current_state))
+}
+
+/// Route the rest of the conversation in `session_id` to `agent`
+pub async fn save_route(store: &dyn StateStore, session_id: &str, agent: &str) -> Result<()> {
+    save_session(store, &SessionState {
+        agent: ROUTING_AGENT.to_string(),
+        session_id: session_id.to_string(),
+        current_state: agent.to_string(),
+        context: HashMap::new(),
+        updated_at: Utc::now().timestamp(),
+    }).await
+}
+
+/// The session's saved position, kept in the state store under (agent, session_id)
+pub async fn load_session(store: &dyn StateStore, agent: &str, session_id: &str) -> Result<Option<SessionState>> {
+    state_store::load(store, agent, session_id).await
+}
+
+pub async fn save_session(store: &dyn StateStore, session: &SessionState) -> Result<()> {
+    state_store::save(store, &session.agent, &session.session_id, session).await
+}
+
+impl AgentStateManager {
+    /// Capture the current position for persisting
+    pub fn snapshot(&self, agent: &str, session_id: &str) -> Option<SessionState> {
+        Some(SessionState {
+            agent: agent.to_string(),
+            session_id: session_id.to_string(),
+            current_state: self.get_current_state_name()?.to_string(),
+            context: self.context().clone(),
+            updated_at: Utc::now().timestamp(),
+        })
+    }
+
+    /// Restore a persisted position. Returns false, leaving the manager untouched,
+    /// if the saved state no longer exists in the state machine.
+    pub fn restore(&mut self, session: &SessionState) -> bool {
+        let known = self.state_machine()
+            .map(|sm| sm.states.contains_key(&session.current_state))
+            .unwrap_or(false);
+        if known {
+            self.set_current_state(session.current_state.clone());
+            self.replace_context(session.context.clone());
+        }
+        known
+    }
+
+    /// Load the session's position from the store, starting from the initial state
+    /// for new sessions. Returns whether a saved position was restored.
+    pub async fn resume(&mut self, store: &dyn StateStore, agent: &str, session_id: &str) -> Result<bool> {
+        match load_session(store, agent, session_id).await? {
+            Some(session) if self.restore(&session) => Ok(true),
+            Some(session) => {
+                tracing::warn!("Discarding session {} for {}: unknown state '{}'", session_id, agent, session.current_state);
+                self.reset();
+                Ok(false)
+            }
+            None => {
+                self.reset();
+                Ok(false)
+            }
+        }
+    }
+
+    pub async fn persist(&self, store: &dyn StateStore, agent: &str, session_id: &str) -> Result<()> {
+        match self.snapshot(agent, session_id) {
+            Some(session) => save_session(store, &session).await,
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{State, StateMachine};
+    use crate::types::state_store::InMemoryStateStore;
+
+    fn machine() -> StateMachine {
+        let state = |name: &str, next: &str| State {
+            name: name.to_string(),
+            data: None,
+            prompt: None,
+            transitions: Some(HashMap::from([("next".to_string(), next.to_string())])),
+            validation: None,
+            guarded_transitions: None,
+            parent: None,
+            initial_child: None,
+            on_enter: None,
+            on_exit: None,
+        };
+        StateMachine {
+            states: HashMap::from([
+                ("start".to_string(), state("start", "middle")),
+                ("middle".to_string(), state("middle", "start")),
+            ]),
+            initial_state: "start".to_string(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_resume_session_after_restart() -> Result<()> {
+        let store = InMemoryStateStore::new();
+
+        let mut manager = AgentStateManager::new(Some(machine()));
+        manager.transition("next");
+        manager.set_context("topic".to_string(), "rust".to_string());
+        manager.persist(&store, "haiku", "abc").await?;
+
+        // A fresh manager, as after a process restart
+        let mut restarted = AgentStateManager::new(Some(machine()));
+        assert!(restarted.resume(&store, "haiku", "abc").await?);
+        assert_eq!(restarted.get_current_state_name(), Some("middle"));
+        assert_eq!(restarted.context()["topic"], "rust");
+
+        // Other sessions start from the initial state
+        assert!(!restarted.resume(&store, "haiku", "other").await?);
+        assert_eq!(restarted.get_current_state_name(), Some("start"));
+        assert!(restarted.context().is_empty());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_sessions_route_independently() -> Result<()> {
+        let store = InMemoryStateStore::new();
+        save_route(&store, "alice", "haiku").await?;
+        save_route(&store, "bob", "git").await?;
+        save_route(&store, "alice", "project").await?;
+        assert_eq!(load_route(&store, "alice").await?.as_deref(), Some("project"));
+        assert_eq!(load_route(&store, "bob").await?.as_deref(), Some("git"));
+        assert_eq!(load_route(&store, "carol").await?, None);
+
+        let message = tag_session(Message::new("hi".to_string()), "user", "alice");
+        assert_eq!(message_session_id(&message).as_deref(), Some("alice"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_restore_rejects_unknown_state() {
+        let mut manager = AgentStateManager::new(Some(machine()));
+        let mut session = manager.snapshot("haiku", "abc").unwrap();
+        session.current_state = "removed".to_string();
+        assert!(!manager.restore(&session));
+        assert_eq!(manager.get_current_state_name(), Some("start"));
+    }
+}
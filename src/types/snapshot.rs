@@ -0,0 +1,178 @@
+//! The whole operational state in one file: tasks (queued, archived and dead-lettered),
+//! agent state and sessions, and the project registry. Snapshots back up a deployment,
+//! move it to another database or state store backend, and capture the state a bug
+//! report needs. Each file records its format version; newer versions are refused.
+
+use std::io::{Read, Write};
+use anyhow::Result;
+use chrono::Utc;
+use futures_util::TryStreamExt;
+use mongodb::bson::doc;
+use mongodb::options::ReplaceOptions;
+use serde::{Deserialize, Serialize};
+use crate::error::SwarmError;
+use super::archive::ArchivedTask;
+use super::dead_letter::DeadLetter;
+use super::projects::{ProjectDefinition, ProjectRegistry};
+use super::state_store::{StateEntry, StateStore};
+use super::todo::{TodoList, TodoTask};
+
+/// The format written; files from older versions still read
+pub const SNAPSHOT_VERSION: u32 = 1;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Snapshot {
+    pub version: u32,
+    pub created_at: i64,
+    pub tasks: Vec<TodoTask>,
+    #[serde(default)]
+    pub archived_tasks: Vec<ArchivedTask>,
+    #[serde(default)]
+    pub dead_letters: Vec<DeadLetter>,
+    /// Agent state and conversation sessions from the state store
+    #[serde(default)]
+    pub state: Vec<StateEntry>,
+    #[serde(default)]
+    pub projects: Vec<ProjectDefinition>,
+}
+
+/// How many of each thing a restore wrote
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct RestoreReport {
+    pub tasks: usize,
+    pub archived_tasks: usize,
+    pub dead_letters: usize,
+    pub state: usize,
+    pub projects: usize,
+}
+
+impl RestoreReport {
+    pub fn summary(&self) -> String {
+        format!(
+            "{} task(s), {} archived, {} dead-lettered, {} state entr{}, {} project(s)",
+            self.tasks,
+            self.archived_tasks,
+            self.dead_letters,
+            self.state,
+            if self.state == 1 { "y" } else { "ies" },
+            self.projects,
+        )
+    }
+}
+
+impl Snapshot {
+    /// Everything in `todos` (all tenants, if it isn't scoped to one), and in `state` and
+    /// `projects` when given
+    pub async fn capture(todos: &TodoList, state: Option<&dyn StateStore>, projects: Option<&ProjectRegistry>) -> Result<Self> {
+        let all = todos.scope(doc! {});
+        Ok(Self {
+            version: SNAPSHOT_VERSION,
+            created_at: Utc::now().timestamp(),
+            tasks: todos.collection().find(all.clone(), None).await?.try_collect().await?,
+            archived_tasks: todos.archive_collection().find(all.clone(), None).await?.try_collect().await?,
+            dead_letters: todos.dead_letter_collection().find(all, None).await?.try_collect().await?,
+            state: match state {
+                Some(store) => store.entries().await?,
+                None => Vec::new(),
+            },
+            projects: match projects {
+                Some(registry) => registry.list_projects(true).await?,
+                None => Vec::new(),
+            },
+        })
+    }
+
+    pub fn write(&self, writer: impl Write) -> Result<()> {
+        serde_json::to_writer(writer, self)?;
+        Ok(())
+    }
+
+    /// Read a snapshot, refusing ones written by a newer version
+    pub fn read(reader: impl Read) -> Result<Self> {
+        let snapshot: Self = serde_json::from_reader(reader)?;
+        if snapshot.version > SNAPSHOT_VERSION {
+            return Err(SwarmError::Validation(format!(
+                "Snapshot version {} is newer than this build supports ({})",
+                snapshot.version, SNAPSHOT_VERSION,
+            )).into());
+        }
+        Ok(snapshot)
+    }
+
+    /// Write the snapshot's contents, replacing tasks with the same id, state for the same
+    /// agent and session, and projects with the same name. Nothing else is removed. State
+    /// and projects are skipped without somewhere to put them.
+    pub async fn restore(&self, todos: &TodoList, state: Option<&dyn StateStore>, projects: Option<&ProjectRegistry>) -> Result<RestoreReport> {
+        let upsert = ReplaceOptions::builder().upsert(true).build();
+        let mut report = RestoreReport::default();
+        for task in &self.tasks {
+            todos.collection().replace_one(todos.scope(doc! { "id": &task.id }), task, upsert.clone()).await?;
+            report.tasks += 1;
+        }
+        for archived in &self.archived_tasks {
+            todos.archive_collection().replace_one(todos.scope(doc! { "id": &archived.task.id }), archived, upsert.clone()).await?;
+            report.archived_tasks += 1;
+        }
+        for dead_letter in &self.dead_letters {
+            todos.dead_letter_collection().replace_one(todos.scope(doc! { "id": &dead_letter.task.id }), dead_letter, upsert.clone()).await?;
+            report.dead_letters += 1;
+        }
+        if let Some(store) = state {
+            for entry in &self.state {
+                store.put(&entry.agent, &entry.session_id, entry.value.clone()).await?;
+                report.state += 1;
+            }
+        }
+        if let Some(registry) = projects {
+            for project in &self.projects {
+                registry.put_project(project).await?;
+                report.projects += 1;
+            }
+        }
+        Ok(report)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testsupport::{self, MongoFixture};
+    use crate::types::state_store::InMemoryStateStore;
+
+    #[test]
+    fn test_newer_snapshots_are_refused() {
+        let read = |version: u32| Snapshot::read(format!(r#"{{"version": {}, "created_at": 0, "tasks": []}}"#, version).as_bytes());
+        let snapshot = read(SNAPSHOT_VERSION).unwrap();
+        assert!(snapshot.dead_letters.is_empty() && snapshot.state.is_empty());
+        let err = read(SNAPSHOT_VERSION + 1).unwrap_err();
+        assert!(matches!(SwarmError::find(&err), Some(SwarmError::Validation(_))), "{}", err);
+    }
+
+    #[tokio::test]
+    async fn test_restoring_into_another_database_reproduces_the_state() -> Result<()> {
+        let source = MongoFixture::start().await?;
+        let todos = source.seeded([testsupport::task("Fix the login page", "git")]).await?;
+        let projects = ProjectRegistry::from_database(&source.db);
+        projects.add_project(ProjectDefinition::new("swarm", "Agents")).await?;
+        let state = InMemoryStateStore::new();
+        state.put("haiku", "alice", serde_json::json!({ "topic": "autumn" })).await?;
+
+        let mut file = Vec::new();
+        Snapshot::capture(&todos, Some(&state), Some(&projects)).await?.write(&mut file)?;
+        let snapshot = Snapshot::read(file.as_slice())?;
+
+        let target = MongoFixture::start().await?;
+        let (todos, state, projects) = (target.todo_list().await, InMemoryStateStore::new(), ProjectRegistry::from_database(&target.db));
+        let report = snapshot.restore(&todos, Some(&state), Some(&projects)).await?;
+        assert_eq!(report, RestoreReport { tasks: 1, state: 1, projects: 1, ..Default::default() });
+        // Restoring again replaces rather than duplicates
+        snapshot.restore(&todos, Some(&state), Some(&projects)).await?;
+
+        let again = Snapshot::capture(&todos, Some(&state), Some(&projects)).await?;
+        let descriptions = |snapshot: &Snapshot| snapshot.tasks.iter().map(|task| (task.id.clone(), task.description.clone())).collect::<Vec<_>>();
+        assert_eq!(descriptions(&again), descriptions(&snapshot));
+        assert_eq!(again.state, snapshot.state);
+        assert_eq!(again.projects, snapshot.projects);
+        Ok(())
+    }
+}
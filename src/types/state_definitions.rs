@@ -0,0 +1,249 @@
+use std::fs;
+use std::path::Path;
+use anyhow::{Result, anyhow};
+use regex::Regex;
+use serde::{Deserialize, Deserializer};
+use super::{Guard, StateMachine};
+
+/// A problem found while validating a state machine definition
+#[derive(Debug, Clone, PartialEq)]
+pub struct DefinitionError {
+    pub line: Option<usize>,
+    pub message: String,
+}
+
+impl StateMachine {
+    /// Load and validate a YAML state machine definition
+    pub fn from_yaml_file(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let source = fs::read_to_string(path)
+            .map_err(|e| anyhow!("Failed to read state machine {}: {}", path.display(), e))?;
+        Self::from_yaml_str(&source, &path.display().to_string())
+    }
+
+    /// Parse and validate YAML source; `origin` names the source in error messages
+    pub fn from_yaml_str(source: &str, origin: &str) -> Result<Self> {
+        let machine: StateMachine = serde_yaml::from_str(source).map_err(|e| match e.location() {
+            Some(location) => anyhow!("{}:{}:{}: {}", origin, location.line(), location.column(), e),
+            None => anyhow!("{}: {}", origin, e),
+        })?;
+
+        let errors = machine.validate_with_source(Some(source));
+        if errors.is_empty() {
+            return Ok(machine);
+        }
+        let details: Vec<String> = errors.iter()
+            .map(|error| match error.line {
+                Some(line) => format!("{}:{}: {}", origin, line, error.message),
+                None => format!("{}: {}", origin, error.message),
+            })
+            .collect();
+        Err(anyhow!("Invalid state machine definition:\n{}", details.join("\n")))
+    }
+
+    /// Check that the initial state, transition targets, parents and initial children
+    /// exist, that parents don't form cycles, and that all patterns compile. The first `validation` entry of a state is its pattern.
+    pub fn validate(&self) -> Vec<DefinitionError> {
+        self.validate_with_source(None)
+    }
+
+    fn validate_with_source(&self, source: Option<&str>) -> Vec<DefinitionError> {
+        let locate = |state: Option<&str>, needle: &str| source.and_then(|s| find_line(s, state, needle));
+        let mut errors = Vec::new();
+
+        if !self.states.contains_key(&self.initial_state) {
+            errors.push(DefinitionError {
+                line: locate(None, "initial_state:"),
+                message: format!("initial state '{}' is not defined", self.initial_state),
+            });
+        }
+
+        let mut names: Vec<&String> = self.states.keys().collect();
+        names.sort();
+        for key in names {
+            let state = &self.states[key];
+            if state.name != *key {
+                errors.push(DefinitionError {
+                    line: locate(Some(key), "name:"),
+                    message: format!("state '{}' is named '{}'", key, state.name),
+                });
+            }
+
+            if let Some(parent) = &state.parent {
+                if !self.states.contains_key(parent) {
+                    errors.push(DefinitionError {
+                        line: locate(Some(key), "parent:"),
+                        message: format!("state '{}' has undefined parent '{}'", key, parent),
+                    });
+                } else if self.lineage(key).last().and_then(|s| s.parent.as_ref()).map_or(false, |p| self.states.contains_key(p)) {
+                    errors.push(DefinitionError {
+                        line: locate(Some(key), "parent:"),
+                        message: format!("state '{}' is part of a parent cycle", key),
+                    });
+                }
+            }
+            if let Some(child) = &state.initial_child {
+                if self.states.get(child).and_then(|c| c.parent.as_ref()) != Some(key) {
+                    errors.push(DefinitionError {
+                        line: locate(Some(key), "initial_child:"),
+                        message: format!("state '{}' has initial child '{}', which is not one of its children", key, child),
+                    });
+                }
+            }
+
+            let mut targets: Vec<(&String, &String)> = state.transitions.iter().flatten().collect();
+            targets.sort();
+            let guarded = state.guarded_transitions.iter().flatten().map(|t| (&t.event, &t.target));
+            for (event, target) in targets.into_iter().chain(guarded) {
+                if !self.states.contains_key(target) {
+                    errors.push(DefinitionError {
+                        line: locate(Some(key), target),
+                        message: format!("state '{}' transitions on '{}' to undefined state '{}'", key, event, target),
+                    });
+                }
+            }
+
+            let guard_patterns = state.guarded_transitions.iter().flatten()
+                .flat_map(|t| t.guards.iter())
+                .filter_map(|guard| match guard {
+                    Guard::MessageMatches { pattern } => Some(pattern),
+                    _ => None,
+                });
+            let validation_pattern = state.validation.as_ref().and_then(|v| v.first());
+            for pattern in validation_pattern.into_iter().chain(guard_patterns) {
+                if let Err(e) = Regex::new(pattern) {
+                    errors.push(DefinitionError {
+                        line: locate(Some(key), pattern),
+                        message: format!("state '{}' has an invalid pattern '{}': {}", key, pattern, e),
+                    });
+                }
+            }
+        }
+        errors
+    }
+}
+
+/// 1-based line of the first `needle` inside the given state's block (or anywhere if no state)
+fn find_line(source: &str, state: Option<&str>, needle: &str) -> Option<usize> {
+    let lines: Vec<&str> = source.lines().collect();
+    let start = match state {
+        Some(state) => lines.iter().position(|line| {
+            line.split_once(':')
+                .map(|(key, rest)| key.trim().trim_matches(['"', '\'']) == state && rest.trim().is_empty())
+                .unwrap_or(false)
+        })?,
+        None => 0,
+    };
+    let indent = |line: &str| line.len() - line.trim_start().len();
+    let block_indent = indent(lines[start]);
+    lines.iter()
+        .enumerate()
+        .skip(start)
+        .take_while(|(i, line)| *i == start || line.trim().is_empty() || indent(line) > block_indent || state.is_none())
+        .find(|(_, line)| line.contains(needle))
+        .map(|(i, _)| i + 1)
+}
+
+/// Accepts an inline state machine or a path to a YAML definition.
+/// Relative paths are resolved against the working directory.
+pub fn deserialize_state_machine<'de, D>(deserializer: D) -> std::result::Result<Option<StateMachine>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Definition {
+        File(String),
+        Inline(StateMachine),
+    }
+
+    match Option::<Definition>::deserialize(deserializer)? {
+        None => Ok(None),
+        Some(Definition::File(path)) => StateMachine::from_yaml_file(&path)
+            .map(Some)
+            .map_err(|e| serde::de::Error::custom(format!("{:#}", e))),
+        Some(Definition::Inline(machine)) => {
+            let errors = machine.validate();
+            if errors.is_empty() {
+                Ok(Some(machine))
+            } else {
+                let details: Vec<String> = errors.into_iter().map(|e| e.message).collect();
+                Err(serde::de::Error::custom(format!("Invalid state machine: {}", details.join("; "))))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::AgentConfig;
+
+    const HAIKU_YAML: &str = r#"initial_state: awaiting_topic
+states:
+  awaiting_topic:
+    name: awaiting_topic
+    prompt: What shall we write about?
+    transitions:
+      topic_received: generating
+  generating:
+    name: generating
+    transitions:
+      haiku_generated: complete
+  complete:
+    name: complete
+    validation:
+      - "^(yes|no)$"
+      - Please respond with 'yes' or 'no'.
+    transitions:
+      yes: awaiting_topic
+"#;
+
+    #[test]
+    fn test_load_yaml_definition() -> Result<()> {
+        let machine = StateMachine::from_yaml_str(HAIKU_YAML, "haiku.yaml")?;
+        assert_eq!(machine.initial_state, "awaiting_topic");
+        assert_eq!(machine.states["complete"].transitions.as_ref().unwrap()["yes"], "awaiting_topic");
+        Ok(())
+    }
+
+    #[test]
+    fn test_validation_errors_report_lines() {
+        let broken = HAIKU_YAML
+            .replace("haiku_generated: complete", "haiku_generated: finished")
+            .replace("^(yes|no)$", "^(yes|no$");
+        let error = StateMachine::from_yaml_str(&broken, "haiku.yaml").unwrap_err().to_string();
+        assert!(error.contains("haiku.yaml:11: state 'generating' transitions on 'haiku_generated' to undefined state 'finished'"), "{}", error);
+        assert!(error.contains("haiku.yaml:15: state 'complete' has an invalid pattern"), "{}", error);
+
+        let error = StateMachine::from_yaml_str("initial_state: [", "bad.yaml").unwrap_err().to_string();
+        assert!(error.starts_with("bad.yaml:1:"), "{}", error);
+    }
+
+    #[test]
+    fn test_validate_nested_states() {
+        let nested = format!("{}  wizard:\n    name: wizard\n    initial_child: complete\n    parent: ghost\n", HAIKU_YAML);
+        let error = StateMachine::from_yaml_str(&nested, "haiku.yaml").unwrap_err().to_string();
+        assert!(error.contains("haiku.yaml:22: state 'wizard' has undefined parent 'ghost'"), "{}", error);
+        assert!(error.contains("haiku.yaml:21: state 'wizard' has initial child 'complete'"), "{}", error);
+    }
+
+    #[test]
+    fn test_agent_config_references_yaml_file() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("haiku.yaml");
+        fs::write(&path, HAIKU_YAML)?;
+
+        let config: AgentConfig = serde_json::from_value(serde_json::json!({
+            "name": "haiku",
+            "public_description": "Haiku agent",
+            "instructions": "Write haikus",
+            "tools": [],
+            "downstream_agents": [],
+            "personality": null,
+            "state_machine": path.to_string_lossy(),
+        }))?;
+        assert_eq!(config.state_machine.unwrap().states.len(), 3);
+        Ok(())
+    }
+}
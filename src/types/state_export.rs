@@ -0,0 +1,190 @@
+use std::fmt::Write;
+use super::StateMachine;
+
+/// (from, to, label, guarded) for every transition, in a stable order
+fn edges(machine: &StateMachine) -> Vec<(String, String, String, bool)> {
+    let mut edges = Vec::new();
+    for name in machine.state_names() {
+        let state = &machine.states[name];
+        let mut plain: Vec<(&String, &String)> = state.transitions.iter().flatten().collect();
+        plain.sort();
+        for (event, target) in plain {
+            edges.push((name.clone(), target.clone(), event.clone(), false));
+        }
+        for transition in state.guarded_transitions.iter().flatten() {
+            edges.push((name.clone(), transition.target.clone(), transition.event.clone(), true));
+        }
+    }
+    edges
+}
+
+/// Write a state's node, wrapping it and its children in a cluster if it has any
+fn write_dot_state(dot: &mut String, machine: &StateMachine, name: &str, depth: usize) {
+    let indent = "    ".repeat(depth);
+    let state = &machine.states[name];
+    let mut label = escape(name);
+    if let Some(prompt) = &state.prompt {
+        label.push_str(&format!("\\n{}", escape(prompt)));
+    }
+    let children = machine.children(name);
+    if children.is_empty() {
+        writeln!(dot, "{}\"{}\" [label=\"{}\"];", indent, escape(name), label).unwrap();
+        return;
+    }
+    writeln!(dot, "{}subgraph \"cluster_{}\" {{", indent, escape(name)).unwrap();
+    writeln!(dot, "{}    label=\"{}\";", indent, escape(name)).unwrap();
+    writeln!(dot, "{}    \"{}\" [label=\"{}\"];", indent, escape(name), label).unwrap();
+    for child in children {
+        write_dot_state(dot, machine, child, depth + 1);
+    }
+    writeln!(dot, "{}}}", indent).unwrap();
+}
+
+/// Write a state, nesting its children in a composite state block
+fn write_mermaid_state(mermaid: &mut String, machine: &StateMachine, name: &str, depth: usize) {
+    let indent = "    ".repeat(depth);
+    let id = mermaid_id(name);
+    if id != name {
+        writeln!(mermaid, "{}state \"{}\" as {}", indent, name, id).unwrap();
+    }
+    let children = machine.children(name);
+    if children.is_empty() {
+        return;
+    }
+    writeln!(mermaid, "{}state {} {{", indent, id).unwrap();
+    if let Some(initial) = &machine.states[name].initial_child {
+        writeln!(mermaid, "{}    [*] --> {}", indent, mermaid_id(initial)).unwrap();
+    }
+    for child in children {
+        write_mermaid_state(mermaid, machine, child, depth + 1);
+    }
+    writeln!(mermaid, "{}}}", indent).unwrap();
+}
+
+/// States without a (known) parent
+fn top_level(machine: &StateMachine) -> Vec<&String> {
+    machine.state_names().into_iter()
+        .filter(|name| machine.states[*name].parent.as_ref().map_or(true, |p| !machine.states.contains_key(p)))
+        .collect()
+}
+
+fn escape(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+/// Mermaid ids may not contain spaces or punctuation
+fn mermaid_id(name: &str) -> String {
+    name.chars().map(|c| if c.is_ascii_alphanumeric() || c == '_' { c } else { '_' }).collect()
+}
+
+impl StateMachine {
+    /// State names sorted alphabetically, so exports are deterministic
+    pub fn state_names(&self) -> Vec<&String> {
+        let mut names: Vec<&String> = self.states.keys().collect();
+        names.sort();
+        names
+    }
+
+    /// Render as a Graphviz DOT digraph. Guarded transitions are drawn dashed and
+    /// nested states are grouped in clusters.
+    pub fn to_dot(&self, graph_name: &str) -> String {
+        let mut dot = String::new();
+        writeln!(dot, "digraph \"{}\" {{", escape(graph_name)).unwrap();
+        writeln!(dot, "    rankdir=LR;").unwrap();
+        writeln!(dot, "    node [shape=box, style=rounded];").unwrap();
+        writeln!(dot, "    \"__start\" [shape=point];").unwrap();
+        for name in top_level(self) {
+            write_dot_state(&mut dot, self, name, 1);
+        }
+        writeln!(dot, "    \"__start\" -> \"{}\";", escape(&self.initial_state)).unwrap();
+        for (from, to, event, guarded) in edges(self) {
+            let style = if guarded { ", style=dashed" } else { "" };
+            writeln!(dot, "    \"{}\" -> \"{}\" [label=\"{}\"{}];", escape(&from), escape(&to), escape(&event), style).unwrap();
+        }
+        dot.push_str("}\n");
+        dot
+    }
+
+    /// Render as a Mermaid `stateDiagram-v2`
+    pub fn to_mermaid(&self) -> String {
+        let mut mermaid = String::from("stateDiagram-v2\n");
+        for name in top_level(self) {
+            write_mermaid_state(&mut mermaid, self, name, 1);
+        }
+        writeln!(mermaid, "    [*] --> {}", mermaid_id(&self.initial_state)).unwrap();
+        for (from, to, event, guarded) in edges(self) {
+            let label = if guarded { format!("{} [guarded]", event) } else { event };
+            writeln!(mermaid, "    {} --> {}: {}", mermaid_id(&from), mermaid_id(&to), label).unwrap();
+        }
+        mermaid
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use crate::types::{State, Transition};
+    use super::*;
+
+    fn machine() -> StateMachine {
+        let state = |name: &str, transitions: &[(&str, &str)]| State {
+            name: name.to_string(),
+            data: None,
+            prompt: None,
+            transitions: Some(transitions.iter().map(|(e, t)| (e.to_string(), t.to_string())).collect()),
+            validation: None,
+            guarded_transitions: None,
+            parent: None,
+            initial_child: None,
+            on_enter: None,
+            on_exit: None,
+        };
+        let mut complete = state("complete", &[("no", "goodbye")]);
+        complete.guarded_transitions = Some(vec![Transition {
+            event: "reply".to_string(),
+            target: "awaiting topic".to_string(),
+            guards: vec![],
+            actions: vec![],
+        }]);
+        StateMachine {
+            states: HashMap::from([
+                ("awaiting topic".to_string(), state("awaiting topic", &[("topic_received", "complete")])),
+                ("complete".to_string(), complete),
+                ("goodbye".to_string(), state("goodbye", &[])),
+            ]),
+            initial_state: "awaiting topic".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_to_dot() {
+        let dot = machine().to_dot("haiku");
+        assert!(dot.starts_with("digraph \"haiku\" {"));
+        assert!(dot.contains("\"__start\" -> \"awaiting topic\";"));
+        assert!(dot.contains("\"awaiting topic\" -> \"complete\" [label=\"topic_received\"];"));
+        assert!(dot.contains("\"complete\" -> \"awaiting topic\" [label=\"reply\", style=dashed];"));
+    }
+
+    #[test]
+    fn test_to_mermaid() {
+        let mermaid = machine().to_mermaid();
+        assert!(mermaid.contains("state \"awaiting topic\" as awaiting_topic"));
+        assert!(mermaid.contains("[*] --> awaiting_topic"));
+        assert!(mermaid.contains("complete --> goodbye: no"));
+        assert!(mermaid.contains("complete --> awaiting_topic: reply [guarded]"));
+    }
+
+    #[test]
+    fn test_nested_states_are_grouped() {
+        let mut machine = machine();
+        machine.states.get_mut("complete").unwrap().initial_child = Some("goodbye".to_string());
+        machine.states.get_mut("goodbye").unwrap().parent = Some("complete".to_string());
+
+        let dot = machine.to_dot("haiku");
+        assert!(dot.contains("    subgraph \"cluster_complete\" {\n        label=\"complete\";"));
+        assert!(dot.contains("        \"goodbye\" [label=\"goodbye\"];"));
+
+        let mermaid = machine.to_mermaid();
+        assert!(mermaid.contains("    state complete {\n        [*] --> goodbye\n    }"));
+    }
+}
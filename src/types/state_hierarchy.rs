@@ -0,0 +1,136 @@
+use super::{AgentStateManager, State, StateMachine};
+
+impl StateMachine {
+    /// The state followed by its ancestors, innermost first. Stops at unknown
+    /// parents and at cycles rather than looping.
+    pub fn lineage(&self, name: &str) -> Vec<&State> {
+        let mut lineage: Vec<&State> = Vec::new();
+        let mut next = self.states.get(name);
+        while let Some(state) = next {
+            if lineage.iter().any(|s| s.name == state.name) {
+                break;
+            }
+            lineage.push(state);
+            next = state.parent.as_ref().and_then(|parent| self.states.get(parent));
+        }
+        lineage
+    }
+
+    /// The leaf actually entered when targeting `name`, following `initial_child`
+    pub fn entry_state(&self, name: &str) -> String {
+        let mut current = name.to_string();
+        for _ in 0..self.states.len() {
+            match self.states.get(&current).and_then(|s| s.initial_child.as_ref()) {
+                Some(child) if self.states.contains_key(child) => current = child.clone(),
+                _ => break,
+            }
+        }
+        current
+    }
+
+    /// Direct children of a state, sorted by name
+    pub fn children(&self, name: &str) -> Vec<&String> {
+        self.state_names().into_iter()
+            .filter(|child| self.states[*child].parent.as_deref() == Some(name))
+            .collect()
+    }
+
+    /// Exit prompts from `from` up to the closest common ancestor, then enter prompts
+    /// from below that ancestor down to `to`
+    pub fn transition_prompts(&self, from: &str, to: &str) -> Vec<String> {
+        let exited = self.lineage(from);
+        let entered = self.lineage(to);
+        let common = exited.iter()
+            .find(|state| entered.iter().any(|s| s.name == state.name))
+            .map(|state| state.name.clone());
+        let below_common = |state: &&&State| Some(&state.name) != common.as_ref();
+
+        let mut prompts: Vec<String> = exited.iter()
+            .take_while(below_common)
+            .filter_map(|state| state.on_exit.clone())
+            .collect();
+        let entering: Vec<&&State> = entered.iter().take_while(below_common).collect();
+        prompts.extend(entering.into_iter().rev().filter_map(|state| state.on_enter.clone()));
+        prompts
+    }
+}
+
+impl AgentStateManager {
+    /// Whether the current state is `name` or nested inside it
+    pub fn is_in(&self, name: &str) -> bool {
+        match (self.state_machine(), self.get_current_state_name()) {
+            (Some(machine), Some(current)) => machine.lineage(current).iter().any(|s| s.name == name),
+            _ => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use super::*;
+
+    fn state(name: &str, parent: Option<&str>, transitions: &[(&str, &str)]) -> State {
+        State {
+            name: name.to_string(),
+            data: None,
+            prompt: None,
+            transitions: Some(transitions.iter().map(|(e, t)| (e.to_string(), t.to_string())).collect()),
+            validation: None,
+            guarded_transitions: None,
+            parent: parent.map(|p| p.to_string()),
+            initial_child: None,
+            on_enter: Some(format!("enter {}", name)),
+            on_exit: Some(format!("exit {}", name)),
+        }
+    }
+
+    /// gather (name -> language) -> scaffold -> done, with cancel inherited from the wizard
+    fn wizard() -> StateMachine {
+        let mut wizard = state("wizard", None, &[("cancel", "cancelled")]);
+        wizard.initial_child = Some("gather".to_string());
+        let mut gather = state("gather", Some("wizard"), &[("gathered", "scaffold")]);
+        gather.initial_child = Some("ask_name".to_string());
+        let states = vec![
+            wizard,
+            gather,
+            state("ask_name", Some("gather"), &[("answered", "ask_language")]),
+            state("ask_language", Some("gather"), &[("answered", "scaffold")]),
+            state("scaffold", Some("wizard"), &[("done", "cancelled")]),
+            state("cancelled", None, &[]),
+        ];
+        StateMachine {
+            states: states.into_iter().map(|s| (s.name.clone(), s)).collect::<HashMap<_, _>>(),
+            initial_state: "wizard".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_nested_states_inherit_transitions() {
+        let mut manager = AgentStateManager::new(Some(wizard()));
+        assert_eq!(manager.get_current_state_name(), Some("ask_name"));
+        assert!(manager.is_in("gather") && manager.is_in("wizard"));
+
+        let outcome = manager.transition_on("answered", None).unwrap();
+        assert_eq!(outcome.state.name, "ask_language");
+        assert_eq!(outcome.prompts, vec!["exit ask_name", "enter ask_language"]);
+
+        let outcome = manager.transition_on("answered", None).unwrap();
+        assert_eq!(outcome.state.name, "scaffold");
+        assert_eq!(outcome.prompts, vec!["exit ask_language", "exit gather", "enter scaffold"]);
+
+        // Inherited from the wizard parent
+        let outcome = manager.transition_on("cancel", None).unwrap();
+        assert_eq!(outcome.state.name, "cancelled");
+        assert_eq!(outcome.prompts, vec!["exit scaffold", "exit wizard", "enter cancelled"]);
+        assert!(!manager.is_in("wizard"));
+    }
+
+    #[test]
+    fn test_lineage_stops_at_cycles() {
+        let mut machine = wizard();
+        machine.states.get_mut("wizard").unwrap().parent = Some("ask_name".to_string());
+        let names: Vec<&str> = machine.lineage("ask_name").iter().map(|s| s.name.as_str()).collect();
+        assert_eq!(names, vec!["ask_name", "gather", "wizard"]);
+    }
+}
@@ -0,0 +1,133 @@
+//! Property tests for the state machine engine: generated machines and event sequences
+//! checked against the invariants `AgentStateManager::transition_on` keeps.
+
+use std::collections::HashMap;
+use proptest::prelude::*;
+use regex::Regex;
+use super::{AgentStateManager, Message, State, StateMachine};
+use super::transitions::{Guard, Transition};
+
+const EVENTS: [&str; 4] = ["next", "back", "reply", "cancel"];
+/// Used for both validation and guards; all compile
+const PATTERNS: [&str; 4] = ["^yes$", "(?i)^y", "^[a-z]+$", "[0-9]"];
+const INPUTS: [&str; 6] = ["yes", "Yes please", "no", "abc", "42", ""];
+
+fn name(i: usize) -> String {
+    format!("s{}", i)
+}
+
+/// Usually one of the `count` states, sometimes one that isn't defined
+fn target(count: usize) -> impl Strategy<Value = String> {
+    prop_oneof![
+        4 => (0..count).prop_map(name),
+        1 => Just("undefined".to_string()),
+    ]
+}
+
+fn state(i: usize, count: usize) -> impl Strategy<Value = State> {
+    let event = || prop::sample::select(EVENTS.to_vec()).prop_map(String::from);
+    let pattern = || prop::sample::select(PATTERNS.to_vec());
+    let transitions = prop::collection::hash_map(event(), target(count), 0..3);
+    let guarded = prop::collection::vec((event(), target(count), pattern()), 0..2);
+    // Parents come before their children, so there are no cycles
+    let parent = match i {
+        0 => Just(None).boxed(),
+        _ => prop::option::weighted(0.4, (0..i).prop_map(name)).boxed(),
+    };
+    (transitions, guarded, prop::option::of(pattern()), parent).prop_map(move |(transitions, guarded, validation, parent)| State {
+        name: name(i),
+        data: None,
+        prompt: None,
+        transitions: Some(transitions),
+        validation: validation.map(|pattern| vec![pattern.to_string(), format!("{} needs {}", name(i), pattern)]),
+        guarded_transitions: Some(guarded.into_iter().map(|(event, target, pattern)| Transition {
+            event,
+            target,
+            guards: vec![Guard::MessageMatches { pattern: pattern.to_string() }],
+            actions: Vec::new(),
+        }).collect()),
+        parent,
+        initial_child: None,
+        on_enter: None,
+        on_exit: None,
+    })
+}
+
+/// One to six states, nested at random, with some parents entered at their first child
+fn machine() -> impl Strategy<Value = StateMachine> {
+    (1..7usize)
+        .prop_flat_map(|count| ((0..count).map(|i| state(i, count)).collect::<Vec<_>>(), 0..count, any::<bool>()))
+        .prop_map(|(states, initial, with_children)| {
+            let mut states: HashMap<String, State> = states.into_iter().map(|s| (s.name.clone(), s)).collect();
+            if with_children {
+                let mut names: Vec<String> = states.keys().cloned().collect();
+                names.sort();
+                for child in names.iter().rev() {
+                    if let Some(parent) = states[child].parent.clone() {
+                        states.get_mut(&parent).unwrap().initial_child = Some(child.clone());
+                    }
+                }
+            }
+            StateMachine { states, initial_state: name(initial) }
+        })
+}
+
+fn steps() -> impl Strategy<Value = Vec<(&'static str, Option<&'static str>)>> {
+    let step = (prop::sample::select(EVENTS.to_vec()), prop::option::of(prop::sample::select(INPUTS.to_vec())));
+    prop::collection::vec(step, 0..24)
+}
+
+/// Whether no transition can leave `name`, counting those inherited from its parents
+fn is_terminal(machine: &StateMachine, name: &str) -> bool {
+    machine.lineage(name).iter().all(|state| {
+        state.transitions.iter().flatten().next().is_none() && state.guarded_transitions.iter().flatten().next().is_none()
+    })
+}
+
+fn passes_validation(state: &State, input: &str) -> bool {
+    match state.validation.as_ref().and_then(|v| v.first()) {
+        Some(pattern) => Regex::new(pattern).unwrap().is_match(input),
+        None => true,
+    }
+}
+
+proptest! {
+    #[test]
+    fn test_transitions_keep_invariants((machine, steps) in (machine(), steps())) {
+        let mut manager = AgentStateManager::new(Some(machine.clone()));
+        prop_assert!(manager.get_current_state().is_some());
+
+        for (event, input) in steps {
+            let message = input.map(|input| Message::new(input.to_string()));
+            let before = manager.get_current_state_name().unwrap().to_string();
+            let terminal = is_terminal(&machine, &before);
+            let valid = message.as_ref().map_or(true, |m| passes_validation(&machine.states[&before], &m.content));
+
+            match manager.transition_on(event, message.as_ref()) {
+                Some(outcome) => {
+                    prop_assert!(machine.states.contains_key(&outcome.state.name), "entered undefined state {}", outcome.state.name);
+                    prop_assert_eq!(manager.get_current_state_name(), Some(outcome.state.name.as_str()));
+                    prop_assert!(valid, "{:?} left {} despite failing its validation", input, before);
+                    prop_assert!(!terminal, "left terminal state {}", before);
+                    // Targets are entered at their innermost initial child
+                    prop_assert_eq!(machine.entry_state(&outcome.state.name), outcome.state.name.clone());
+                }
+                None => prop_assert_eq!(manager.get_current_state_name(), Some(before.as_str())),
+            }
+            prop_assert!(manager.get_current_state().is_some());
+        }
+    }
+
+    #[test]
+    fn test_failed_validation_reports_the_states_message(machine in machine(), input in prop::sample::select(INPUTS.to_vec())) {
+        let manager = AgentStateManager::new(Some(machine.clone()));
+        let state = manager.get_current_state().unwrap();
+        match manager.validate_input(input) {
+            Ok(()) => prop_assert!(passes_validation(state, input)),
+            Err(message) => {
+                prop_assert!(!passes_validation(state, input));
+                prop_assert_eq!(Some(&message), state.validation.as_ref().and_then(|v| v.get(1)));
+            }
+        }
+    }
+}
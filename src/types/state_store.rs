@@ -0,0 +1,270 @@
+//! Where agents keep state between messages and restarts: one JSON value per agent and
+//! session. `from_env` picks the backend, MongoDB (`agent_state` collection) or an
+//! embedded sled database with the `sled-store` feature.
+
+use std::collections::HashMap;
+use std::env;
+use std::sync::Arc;
+use anyhow::Result;
+use async_trait::async_trait;
+use chrono::Utc;
+use futures_util::TryStreamExt;
+use mongodb::{Collection, IndexModel};
+use mongodb::bson::doc;
+use mongodb::options::{IndexOptions, ReplaceOptions};
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
+use serde_json::Value;
+use tokio::sync::{OnceCell, RwLock};
+use crate::db::Database;
+use crate::error::SwarmError;
+
+/// Storage backend for per-agent, per-session state
+#[async_trait]
+pub trait StateStore: Send + Sync {
+    async fn get(&self, agent: &str, session_id: &str) -> Result<Option<Value>>;
+    async fn put(&self, agent: &str, session_id: &str, value: Value) -> Result<()>;
+    async fn remove(&self, agent: &str, session_id: &str) -> Result<()>;
+    /// Everything stored, for snapshots and moving between backends
+    async fn entries(&self) -> Result<Vec<StateEntry>>;
+}
+
+/// One agent's state for one session
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StateEntry {
+    pub agent: String,
+    pub session_id: String,
+    pub value: Value,
+}
+
+/// The state saved for `agent` in `session_id`, if any
+pub async fn load<T: DeserializeOwned>(store: &dyn StateStore, agent: &str, session_id: &str) -> Result<Option<T>> {
+    match store.get(agent, session_id).await? {
+        Some(value) => Ok(Some(serde_json::from_value(value)?)),
+        None => Ok(None),
+    }
+}
+
+pub async fn save<T: Serialize>(store: &dyn StateStore, agent: &str, session_id: &str, state: &T) -> Result<()> {
+    store.put(agent, session_id, serde_json::to_value(state)?).await
+}
+
+static SHARED: OnceCell<Option<Arc<dyn StateStore>>> = OnceCell::const_new();
+
+/// The store selected by `STATE_STORE` (`mongo`, `sled` or `memory`), shared by the whole
+/// process. Without it, MongoDB when it is configured. `None` means state is kept in memory.
+pub async fn from_env() -> Result<Option<Arc<dyn StateStore>>> {
+    SHARED.get_or_try_init(|| async {
+        let backend = env::var("STATE_STORE").ok()
+            .or_else(|| crate::secrets::get(crate::secrets::MONGO_URI).map(|_| "mongo".to_string()));
+        let store: Option<Arc<dyn StateStore>> = match backend.as_deref() {
+            None | Some("memory") => None,
+            Some("mongo") => Some(Arc::new(MongoStateStore::new().await?)),
+            #[cfg(feature = "sled-store")]
+            Some("sled") => Some(Arc::new(SledStateStore::from_env()?)),
+            Some(other) => return Err(SwarmError::Validation(format!(
+                "Unknown STATE_STORE '{}' (expected mongo, memory{})",
+                other,
+                if cfg!(feature = "sled-store") { " or sled" } else { "; sled needs the sled-store feature" },
+            )).into()),
+        };
+        Ok(store)
+    }).await.cloned()
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct StateRecord {
+    agent: String,
+    session_id: String,
+    value: Value,
+    updated_at: i64,
+}
+
+/// State stored in the `agent_state` collection, one document per (agent, session_id)
+pub struct MongoStateStore {
+    collection: Collection<StateRecord>,
+}
+
+impl MongoStateStore {
+    pub async fn new() -> Result<Self> {
+        Self::from_database(&Database::shared().await?).await
+    }
+
+    pub async fn from_database(db: &Database) -> Result<Self> {
+        let collection: Collection<StateRecord> = db.collection("agent_state");
+        let index = IndexModel::builder()
+            .keys(doc! { "agent": 1, "session_id": 1 })
+            .options(Some(IndexOptions::builder().unique(true).build()))
+            .build();
+        collection.create_index(index, None).await?;
+        Ok(Self { collection })
+    }
+}
+
+#[async_trait]
+impl StateStore for MongoStateStore {
+    async fn get(&self, agent: &str, session_id: &str) -> Result<Option<Value>> {
+        let record = self.collection.find_one(doc! { "agent": agent, "session_id": session_id }, None).await.map_err(SwarmError::from)?;
+        Ok(record.map(|record| record.value))
+    }
+
+    async fn put(&self, agent: &str, session_id: &str, value: Value) -> Result<()> {
+        let record = StateRecord {
+            agent: agent.to_string(),
+            session_id: session_id.to_string(),
+            value,
+            updated_at: Utc::now().timestamp(),
+        };
+        let filter = doc! { "agent": agent, "session_id": session_id };
+        let options = ReplaceOptions::builder().upsert(true).build();
+        self.collection.replace_one(filter, &record, options).await.map_err(SwarmError::from)?;
+        Ok(())
+    }
+
+    async fn remove(&self, agent: &str, session_id: &str) -> Result<()> {
+        self.collection.delete_one(doc! { "agent": agent, "session_id": session_id }, None).await.map_err(SwarmError::from)?;
+        Ok(())
+    }
+
+    async fn entries(&self) -> Result<Vec<StateEntry>> {
+        let records: Vec<StateRecord> = self.collection.find(None, None).await.map_err(SwarmError::from)?
+            .try_collect().await.map_err(SwarmError::from)?;
+        Ok(records.into_iter()
+            .map(|record| StateEntry { agent: record.agent, session_id: record.session_id, value: record.value })
+            .collect())
+    }
+}
+
+/// State in an embedded sled database, for single-server deployments without MongoDB
+#[cfg(feature = "sled-store")]
+pub struct SledStateStore {
+    db: sled::Db,
+}
+
+#[cfg(feature = "sled-store")]
+impl SledStateStore {
+    pub fn open(path: impl AsRef<std::path::Path>) -> Result<Self> {
+        Ok(Self { db: sled::open(path)? })
+    }
+
+    /// The database at `SWARM_STATE_DB`, or `./swarmonomicon-state`
+    pub fn from_env() -> Result<Self> {
+        Self::open(env::var("SWARM_STATE_DB").unwrap_or_else(|_| "swarmonomicon-state".to_string()))
+    }
+
+    fn key(agent: &str, session_id: &str) -> Vec<u8> {
+        [agent.as_bytes(), b"\0", session_id.as_bytes()].concat()
+    }
+}
+
+#[cfg(feature = "sled-store")]
+#[async_trait]
+impl StateStore for SledStateStore {
+    async fn get(&self, agent: &str, session_id: &str) -> Result<Option<Value>> {
+        match self.db.get(Self::key(agent, session_id))? {
+            Some(bytes) => Ok(Some(serde_json::from_slice(&bytes)?)),
+            None => Ok(None),
+        }
+    }
+
+    async fn put(&self, agent: &str, session_id: &str, value: Value) -> Result<()> {
+        self.db.insert(Self::key(agent, session_id), serde_json::to_vec(&value)?)?;
+        self.db.flush_async().await?;
+        Ok(())
+    }
+
+    async fn remove(&self, agent: &str, session_id: &str) -> Result<()> {
+        self.db.remove(Self::key(agent, session_id))?;
+        self.db.flush_async().await?;
+        Ok(())
+    }
+
+    async fn entries(&self) -> Result<Vec<StateEntry>> {
+        let mut entries = Vec::new();
+        for item in self.db.iter() {
+            let (key, bytes) = item?;
+            let key = String::from_utf8_lossy(&key);
+            let Some((agent, session_id)) = key.split_once('\0') else { continue };
+            entries.push(StateEntry {
+                agent: agent.to_string(),
+                session_id: session_id.to_string(),
+                value: serde_json::from_slice(&bytes)?,
+            });
+        }
+        Ok(entries)
+    }
+}
+
+/// Process-local store, for tests and deployments without a database
+#[derive(Default)]
+pub struct InMemoryStateStore {
+    values: RwLock<HashMap<(String, String), Value>>,
+}
+
+impl InMemoryStateStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[async_trait]
+impl StateStore for InMemoryStateStore {
+    async fn get(&self, agent: &str, session_id: &str) -> Result<Option<Value>> {
+        Ok(self.values.read().await.get(&(agent.to_string(), session_id.to_string())).cloned())
+    }
+
+    async fn put(&self, agent: &str, session_id: &str, value: Value) -> Result<()> {
+        self.values.write().await.insert((agent.to_string(), session_id.to_string()), value);
+        Ok(())
+    }
+
+    async fn remove(&self, agent: &str, session_id: &str) -> Result<()> {
+        self.values.write().await.remove(&(agent.to_string(), session_id.to_string()));
+        Ok(())
+    }
+
+    async fn entries(&self) -> Result<Vec<StateEntry>> {
+        Ok(self.values.read().await.iter()
+            .map(|((agent, session_id), value)| StateEntry { agent: agent.clone(), session_id: session_id.clone(), value: value.clone() })
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Counter {
+        count: u32,
+    }
+
+    async fn round_trip(store: &dyn StateStore) -> Result<()> {
+        save(store, "counter", "alice", &Counter { count: 3 }).await?;
+        save(store, "counter", "bob", &Counter { count: 5 }).await?;
+        assert_eq!(load::<Counter>(store, "counter", "alice").await?, Some(Counter { count: 3 }));
+        assert_eq!(load::<Counter>(store, "other", "alice").await?, None);
+
+        store.remove("counter", "alice").await?;
+        assert_eq!(load::<Counter>(store, "counter", "alice").await?, None);
+        assert_eq!(load::<Counter>(store, "counter", "bob").await?, Some(Counter { count: 5 }));
+        let entry = StateEntry { agent: "counter".to_string(), session_id: "bob".to_string(), value: serde_json::json!({ "count": 5 }) };
+        assert_eq!(store.entries().await?, vec![entry]);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_in_memory_state_is_kept_per_agent_and_session() -> Result<()> {
+        round_trip(&InMemoryStateStore::new()).await
+    }
+
+    #[cfg(feature = "sled-store")]
+    #[tokio::test]
+    async fn test_sled_state_survives_reopening() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        round_trip(&SledStateStore::open(dir.path())?).await?;
+
+        let reopened = SledStateStore::open(dir.path())?;
+        assert_eq!(load::<Counter>(&reopened, "counter", "bob").await?, Some(Counter { count: 5 }));
+        Ok(())
+    }
+}
@@ -0,0 +1,67 @@
+//! Task tags are short lowercase labels, e.g. `bug`, `frontend` or `tech-debt`. Everything
+//! that stores or filters by tags goes through `normalize_tag`, so `Front End` and
+//! `front-end` are the same tag.
+
+/// Most tags kept on one task
+pub const MAX_TAGS: usize = 8;
+const MAX_TAG_CHARS: usize = 32;
+
+/// Lowercase, with runs of spaces and punctuation collapsed to `-`; `None` if nothing is left
+pub fn normalize_tag(tag: &str) -> Option<String> {
+    let mut normalized = String::new();
+    for c in tag.trim().trim_start_matches('#').chars() {
+        if c.is_alphanumeric() {
+            normalized.extend(c.to_lowercase());
+        } else if !normalized.is_empty() && !normalized.ends_with('-') {
+            normalized.push('-');
+        }
+    }
+    let normalized: String = normalized.trim_end_matches('-').chars().take(MAX_TAG_CHARS).collect();
+    (!normalized.is_empty()).then_some(normalized)
+}
+
+/// Normalized and deduplicated, in first-seen order, at most `MAX_TAGS`
+pub fn normalize_tags<S: AsRef<str>>(tags: impl IntoIterator<Item = S>) -> Vec<String> {
+    let mut normalized: Vec<String> = Vec::new();
+    for tag in tags {
+        if let Some(tag) = normalize_tag(tag.as_ref()) {
+            if !normalized.contains(&tag) {
+                normalized.push(tag);
+            }
+        }
+    }
+    normalized.truncate(MAX_TAGS);
+    normalized
+}
+
+/// Tags from a comma- or newline-separated list, as query parameters and models give them
+pub fn parse_tags(list: &str) -> Vec<String> {
+    normalize_tags(list.split([',', '\n']))
+}
+
+/// Whether `task_tags` has every one of `wanted`
+pub fn has_all(task_tags: &[String], wanted: &[String]) -> bool {
+    wanted.iter().all(|tag| task_tags.contains(tag))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normalize_tag() {
+        assert_eq!(normalize_tag("  Front End "), Some("front-end".to_string()));
+        assert_eq!(normalize_tag("#Tech_Debt!"), Some("tech-debt".to_string()));
+        assert_eq!(normalize_tag(" -- "), None);
+        assert_eq!(normalize_tag(&"x".repeat(100)).unwrap().len(), MAX_TAG_CHARS);
+    }
+
+    #[test]
+    fn test_parse_tags_dedupes_and_caps() {
+        assert_eq!(parse_tags("bug, Bug ,frontend,,\nsecurity"), ["bug", "frontend", "security"]);
+        let many = (0..20).map(|i| format!("t{}", i)).collect::<Vec<_>>().join(",");
+        assert_eq!(parse_tags(&many).len(), MAX_TAGS);
+        assert!(has_all(&parse_tags("bug,ui"), &parse_tags("UI")));
+        assert!(!has_all(&parse_tags("bug"), &parse_tags("bug,ui")));
+    }
+}
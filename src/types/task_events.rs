@@ -0,0 +1,162 @@
+//! Task lifecycle events. `TodoList` publishes them to the event bus as it changes tasks;
+//! `watch_changes` derives the same events from a MongoDB change stream, which also sees
+//! tasks changed by other processes such as the todo worker.
+
+use futures_util::{Stream, StreamExt};
+use mongodb::bson::{Document, doc};
+use mongodb::change_stream::event::{ChangeStreamEvent, OperationType};
+use mongodb::error::Error as MongoError;
+use mongodb::options::{ChangeStreamOptions, FullDocumentType};
+use serde::{Deserialize, Serialize};
+use super::{TaskPriority, TodoTask};
+use super::TodoList;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TaskEventKind {
+    Created,
+    Started,
+    Completed,
+    Failed,
+    Cancelled,
+}
+
+impl TaskEventKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TaskEventKind::Created => "created",
+            TaskEventKind::Started => "started",
+            TaskEventKind::Completed => "completed",
+            TaskEventKind::Failed => "failed",
+            TaskEventKind::Cancelled => "cancelled",
+        }
+    }
+
+    /// The event a status update represents. Status values are stored in mixed case.
+    pub fn for_status(status: &str) -> Option<Self> {
+        match status.to_ascii_lowercase().as_str() {
+            "inprogress" | "in_progress" => Some(TaskEventKind::Started),
+            "completed" => Some(TaskEventKind::Completed),
+            "failed" => Some(TaskEventKind::Failed),
+            "cancelled" => Some(TaskEventKind::Cancelled),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TaskEvent {
+    pub kind: TaskEventKind,
+    pub task_id: String,
+    /// Task details, when the source of the event had them
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub target_agent: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub project: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub priority: Option<TaskPriority>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub failure_reason: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tenant_id: Option<String>,
+    pub timestamp: i64,
+}
+
+impl TaskEvent {
+    pub fn new(kind: TaskEventKind, task_id: impl Into<String>) -> Self {
+        Self {
+            kind,
+            task_id: task_id.into(),
+            description: None,
+            target_agent: None,
+            project: None,
+            priority: None,
+            failure_reason: None,
+            tenant_id: None,
+            timestamp: chrono::Utc::now().timestamp(),
+        }
+    }
+
+    pub fn for_task(kind: TaskEventKind, task: &TodoTask) -> Self {
+        Self {
+            description: Some(task.description.clone()),
+            target_agent: Some(task.target_agent.clone()),
+            project: task.project.clone(),
+            priority: Some(task.priority.clone()),
+            failure_reason: task.failure_reason.clone(),
+            tenant_id: task.tenant_id.clone(),
+            ..Self::new(kind, task.id.clone())
+        }
+    }
+
+    pub fn with_failure_reason(mut self, reason: impl Into<String>) -> Self {
+        self.failure_reason = Some(reason.into());
+        self
+    }
+
+    /// The event for a raw change stream notification, if it is a lifecycle change
+    fn from_change(change: ChangeStreamEvent<Document>) -> Option<Self> {
+        let kind = match change.operation_type {
+            OperationType::Insert => TaskEventKind::Created,
+            OperationType::Update => {
+                let fields = change.update_description?.updated_fields;
+                TaskEventKind::for_status(fields.get_str("status").ok()?)?
+            }
+            _ => return None,
+        };
+        let task = change.full_document?;
+        let text = |field: &str| task.get_str(field).ok().map(str::to_string);
+        Some(Self {
+            description: text("description"),
+            target_agent: text("target_agent"),
+            project: text("project"),
+            priority: task.get("priority").cloned().and_then(|priority| mongodb::bson::from_bson(priority).ok()),
+            failure_reason: text("failure_reason"),
+            tenant_id: text("tenant_id"),
+            ..Self::new(kind, text("id")?)
+        })
+    }
+}
+
+impl TodoList {
+    /// Lifecycle events from the collection's change stream. Fails unless MongoDB runs as a
+    /// replica set, which change streams require.
+    pub async fn watch_changes(&self) -> Result<impl Stream<Item = TaskEvent>, MongoError> {
+        let options = ChangeStreamOptions::builder().full_document(Some(FullDocumentType::UpdateLookup)).build();
+        let mut matching = doc! { "operationType": { "$in": ["insert", "update"] } };
+        if let Some(tenant) = self.tenant() {
+            matching.insert("fullDocument.tenant_id", tenant);
+        }
+        let pipeline = [doc! { "$match": matching }];
+        let changes = self.collection().clone_with_type::<Document>().watch(pipeline, options).await?;
+        Ok(changes.filter_map(|change| async move {
+            match change {
+                Ok(change) => TaskEvent::from_change(change),
+                Err(e) => {
+                    tracing::warn!("Task change stream error: {}", e);
+                    None
+                }
+            }
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_status_maps_to_event_kind() {
+        assert_eq!(TaskEventKind::for_status("InProgress"), Some(TaskEventKind::Started));
+        assert_eq!(TaskEventKind::for_status("completed"), Some(TaskEventKind::Completed));
+        assert_eq!(TaskEventKind::for_status("failed"), Some(TaskEventKind::Failed));
+        assert_eq!(TaskEventKind::for_status("cancelled"), Some(TaskEventKind::Cancelled));
+        assert_eq!(TaskEventKind::for_status("pending"), None);
+
+        let json = serde_json::to_value(TaskEvent::new(TaskEventKind::Started, "t2")).unwrap();
+        assert_eq!((json["kind"].as_str(), json["task_id"].as_str()), (Some("started"), Some("t2")));
+        assert!(json.get("description").is_none());
+    }
+}
@@ -0,0 +1,145 @@
+//! Lets one deployment serve several teams. Tasks carry a `tenant_id`, and a `TodoList`
+//! or `ProjectRegistry` scoped to a tenant (`for_tenant`) adds it to every query, so a team
+//! only ever reads and changes its own queue, projects and stats. Unscoped lists see every
+//! tenant, as before.
+//!
+//! API keys can be bound to a tenant; other callers pick one with the `X-Tenant-Id` header.
+//! `TENANTS_CONFIG` names a YAML or JSON file listing the tenants and the agents each sees:
+//!
+//! ```yaml
+//! tenants:
+//!   platform:
+//!     agents: [git, project]
+//!   research: {}   # sees every agent
+//! ```
+
+use std::collections::HashMap;
+use std::path::Path;
+use anyhow::{Result, anyhow, bail};
+use mongodb::bson::{Bson, Document, doc};
+use serde::Deserialize;
+use super::{Message, MessageMetadata};
+
+/// Field tasks and projects record their tenant in
+pub const TENANT_FIELD: &str = "tenant_id";
+/// Metadata context key carrying the tenant a message was sent for
+pub const TENANT_ID_KEY: &str = "tenant_id";
+const MAX_TENANT_ID_LEN: usize = 64;
+
+/// Tenant ids are short and safe to embed in keys: letters, digits, `-` and `_`
+pub fn validate_tenant_id(tenant: &str) -> Result<()> {
+    if tenant.is_empty() || tenant.len() > MAX_TENANT_ID_LEN {
+        bail!("Tenant ids must be 1 to {} characters", MAX_TENANT_ID_LEN);
+    }
+    if !tenant.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_') {
+        bail!("Invalid tenant id '{}': use letters, digits, '-' and '_'", tenant);
+    }
+    Ok(())
+}
+
+/// `filter` restricted to one tenant's documents; unchanged without a tenant
+pub fn scope_filter(tenant: Option<&str>, mut filter: Document) -> Document {
+    if let Some(tenant) = tenant {
+        filter.insert(TENANT_FIELD, tenant);
+    }
+    filter
+}
+
+/// `filter` restricted to documents shared by all tenants plus the tenant's own
+pub fn shared_filter(tenant: Option<&str>, mut filter: Document) -> Document {
+    if let Some(tenant) = tenant {
+        filter.insert(TENANT_FIELD, doc! { "$in": [Bson::Null, tenant] });
+    }
+    filter
+}
+
+/// A `$match` stage scoping an aggregation to one tenant, if there is one
+pub fn scope_stage(tenant: Option<&str>) -> Option<Document> {
+    tenant.map(|tenant| doc! { "$match": { TENANT_FIELD: tenant } })
+}
+
+/// The tenant a message was sent for, if any
+pub fn message_tenant(message: &Message) -> Option<String> {
+    message.metadata.as_ref()
+        .and_then(|m| m.context.as_ref())
+        .and_then(|c| c.get(TENANT_ID_KEY).cloned())
+}
+
+/// `message` marked as sent to `agent` for `tenant`
+pub fn tag_message(mut message: Message, agent: &str, tenant: &str) -> Message {
+    let metadata = message.metadata.get_or_insert_with(|| MessageMetadata::new(agent.to_string()));
+    metadata.context.get_or_insert_with(HashMap::new).insert(TENANT_ID_KEY.to_string(), tenant.to_string());
+    message
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct TenantConfig {
+    #[serde(default)]
+    pub tenants: HashMap<String, TenantSettings>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct TenantSettings {
+    /// Agents the tenant sees; all of them when empty
+    #[serde(default)]
+    pub agents: Vec<String>,
+}
+
+impl TenantConfig {
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let source = std::fs::read_to_string(path).map_err(|e| anyhow!("Failed to read {}: {}", path.display(), e))?;
+        let config: Self = serde_yaml::from_str(&source).map_err(|e| anyhow!("{}: {}", path.display(), e))?;
+        for tenant in config.tenants.keys() {
+            validate_tenant_id(tenant)?;
+        }
+        Ok(config)
+    }
+
+    /// The tenants in `TENANTS_CONFIG`, or `None` if it isn't set
+    pub fn from_env() -> Result<Option<Self>> {
+        std::env::var("TENANTS_CONFIG").ok().map(Self::from_file).transpose()
+    }
+
+    pub fn is_known(&self, tenant: &str) -> bool {
+        self.tenants.contains_key(tenant)
+    }
+
+    /// Whether `tenant` sees `agent` in the registry; unscoped callers see every agent
+    pub fn sees_agent(&self, tenant: Option<&str>, agent: &str) -> bool {
+        let Some(settings) = tenant.and_then(|tenant| self.tenants.get(tenant)) else {
+            return tenant.is_none();
+        };
+        settings.agents.is_empty() || settings.agents.iter().any(|a| a == agent)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_filters_are_scoped_to_the_tenant() {
+        let filter = scope_filter(Some("platform"), doc! { "id": "t1" });
+        assert_eq!(filter, doc! { "id": "t1", "tenant_id": "platform" });
+        assert_eq!(scope_filter(None, doc! { "id": "t1" }), doc! { "id": "t1" });
+        let shared = shared_filter(Some("platform"), doc! {});
+        assert_eq!(shared, doc! { "tenant_id": { "$in": [Bson::Null, "platform"] } });
+        assert!(scope_stage(None).is_none());
+
+        assert!(validate_tenant_id("team-a_1").is_ok());
+        assert!(validate_tenant_id("").is_err());
+        assert!(validate_tenant_id("a/b").is_err());
+    }
+
+    #[test]
+    fn test_tenants_see_their_agents() {
+        let config: TenantConfig = serde_yaml::from_str("tenants: { platform: { agents: [git] }, research: {} }").unwrap();
+        assert!(config.sees_agent(Some("platform"), "git"));
+        assert!(!config.sees_agent(Some("platform"), "haiku"));
+        assert!(config.sees_agent(Some("research"), "haiku"));
+        assert!(!config.sees_agent(Some("unknown"), "git"));
+        assert!(config.sees_agent(None, "haiku"));
+        assert!(config.is_known("research"));
+    }
+}
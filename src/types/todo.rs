@@ -1,18 +1,56 @@
 use std::collections::VecDeque;
 use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
 use tokio::sync::RwLock;
 use std::sync::Arc;
 use super::Message;
-use mongodb::{Client, Collection, Database};
-use mongodb::bson::{doc, DateTime};
+use mongodb::Collection;
+use mongodb::bson::{Document, doc};
 use mongodb::error::Error as MongoError;
 use futures_util::TryStreamExt;
-use std::env;
 use uuid::Uuid;
 use std::collections::HashMap;
 use chrono::{Utc};
-use crate::ai::AiProvider;
+use crate::ai::{AiProvider, ProjectClassifier};
+use crate::shutdown::ShutdownCoordinator;
 use crate::types::projects::{get_default_project};
+use crate::events::{self, DomainEvent};
+use crate::types::task_events::{TaskEvent, TaskEventKind};
+use crate::types::scheduling::{QueuePosition, SchedulingPolicy};
+use crate::types::claims::{self, ClaimConfig, LeaseRenewal, TaskClaim};
+use crate::types::dead_letter::{self, DeadLetter};
+use crate::types::cancellation::RunningTask;
+use crate::types::archive::ArchivedTask;
+use crate::types::handoff::TaskResult;
+use crate::types::review::EnhancementCorrection;
+use crate::types::tenants;
+use crate::types::offline_queue::{self, OfflineQueue};
+use crate::types::dedup;
+use crate::types::history::{ChangeKind, TaskChange};
+use crate::chaos;
+use crate::tools::CancellationToken;
+use crate::db::Database;
+
+/// How often a task list started without the database checks whether it is back
+const RECONNECT_SECS: u64 = 15;
+
+/// How many of the next tasks a worker fetches to try claiming
+const CLAIM_CANDIDATES: usize = 16;
+
+/// What a producer gives for a new task; `TodoList::prepare_task` fills in the rest
+#[derive(Debug, Clone)]
+pub struct NewTask {
+    pub description: String,
+    pub priority: TaskPriority,
+    pub source_agent: Option<String>,
+    pub target_agent: String,
+    pub project: Option<String>,
+    pub tags: Vec<String>,
+    /// Set by producers that may retry; see `idempotency`
+    pub idempotency_key: Option<String>,
+    /// Add the task even if an identical one was added recently; see `dedup`
+    pub allow_duplicate: bool,
+}
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct TodoTask {
@@ -31,9 +69,51 @@ pub struct TodoTask {
     pub notes: Option<String>,
     pub ticket: Option<String>,
     pub last_modified: Option<i64>,
+    pub failure_reason: Option<String>,
+    /// Vector embedding of the description, used for similarity search
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub embedding: Option<Vec<f32>>,
+    /// The worker processing the task, while it is in progress
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub claim: Option<TaskClaim>,
+    /// Earlier failed attempts, oldest first
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub failures: Vec<TaskFailure>,
+    /// Lowercase labels such as `bug` or `frontend`; see `tags::normalize_tag`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    /// How sure AI enhancement was of the priority and project, from 0 to 1; unset when
+    /// the task wasn't enhanced
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub confidence: Option<f32>,
+    /// When someone approved or corrected the enhancement; see `review`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reviewed_at: Option<i64>,
+    /// The team the task belongs to; see `tenants`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tenant_id: Option<String>,
+    /// The producer's key for this task; a second task with the same key isn't added
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub idempotency_key: Option<String>,
+    /// Hash of the description, project and agent as given, matching accidental repeats;
+    /// unset for tasks added with `allow_duplicate`. See `dedup`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub content_hash: Option<String>,
+    /// Changes made after the task was added, oldest first; see `history`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub history: Vec<TaskChange>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct TaskFailure {
+    pub reason: String,
+    pub failed_at: i64,
+    /// The worker that made the attempt
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub worker_id: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord, ToSchema)]
 pub enum TaskPriority {
     #[serde(rename = "Inital")]
     Inital,
@@ -47,96 +127,446 @@ pub enum TaskPriority {
     Critical,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, ToSchema)]
 pub enum TaskStatus {
     #[serde(rename = "initial")]
     Initial,
     #[serde(rename = "pending")]
     Pending,
+    /// Claimed by a worker
+    #[serde(rename = "in_progress", alias = "InProgress")]
+    InProgress,
     #[serde(rename = "review")]
     Review,
     #[serde(rename = "completed")]
     Completed,
     #[serde(rename = "failed")]
     Failed,
+    /// Stopped on request before it finished
+    #[serde(rename = "cancelled")]
+    Cancelled,
 }
 
+/// Tasks in MongoDB. Every agent's list is a view of the one collection, restricted to
+/// the tasks for that agent (see `for_agent`), so there is no copy to fall out of step.
 #[derive(Debug, Clone)]
 pub struct TodoList {
     collection: Collection<TodoTask>,
+    dead_letters: Collection<DeadLetter>,
+    archive: Collection<ArchivedTask>,
+    results: Collection<TaskResult>,
+    corrections: Collection<EnhancementCorrection>,
+    /// Learns from review corrections; see `review::load_examples`
+    classifier: Arc<ProjectClassifier>,
+    scheduling: SchedulingPolicy,
+    claims: ClaimConfig,
+    max_attempts: u32,
+    /// Restricts every query to one tenant's tasks; all tenants when unset
+    tenant: Option<String>,
+    /// Restricts every query to the tasks for one agent; all agents when unset
+    agent: Option<String>,
+    /// Where added tasks wait while the database is unreachable
+    offline: Option<Arc<OfflineQueue>>,
+    /// Seconds within which an identical task isn't added again; never when unset
+    dedup_window: Option<i64>,
 }
 
 impl TodoList {
-    pub async fn new() -> Result<Self, MongoError> {
-        let uri = env::var("RTK_MONGO_URI")
-            .expect("RTK_MONGO_URI must be set");
-        let db_name = env::var("RTK_MONGO_DB")
-            .unwrap_or_else(|_| "swarmonomicon".to_string());
+    /// The task list in the shared database
+    pub async fn new() -> anyhow::Result<Self> {
+        Ok(Self::from_database(&Database::shared().await?).await)
+    }
+
+    /// The process-wide task list, created on first use. Agents' lists are views of it.
+    pub async fn shared() -> anyhow::Result<Self> {
+        static SHARED: tokio::sync::OnceCell<TodoList> = tokio::sync::OnceCell::const_new();
+        SHARED.get_or_try_init(Self::new).await.cloned()
+    }
+
+    /// The task list in `db`, without index setup or the offline queue's reconciler, so
+    /// nothing talks to the database until the list is used
+    pub(crate) fn unprepared(db: &Database) -> Self {
+        Self {
+            collection: db.collection("todos"),
+            dead_letters: db.collection("dead_letter"),
+            archive: db.collection("archived_tasks"),
+            results: db.collection("task_results"),
+            corrections: db.collection("enhancement_corrections"),
+            classifier: Arc::new(ProjectClassifier::default()),
+            scheduling: SchedulingPolicy::from_env(),
+            claims: ClaimConfig::from_env(),
+            max_attempts: dead_letter::max_attempts_from_env(),
+            tenant: None,
+            agent: None,
+            offline: OfflineQueue::shared(),
+            dedup_window: dedup::window_from_env(),
+        }
+    }
+
+    pub async fn from_database(db: &Database) -> Self {
+        let todo_list = Self::unprepared(db);
+        if let Some(queue) = &todo_list.offline {
+            queue.start_reconciler(todo_list.collection.clone(), todo_list.dead_letters.clone()).await;
+            // Started while the database is down: adds are queued, and the indexes wait for it
+            if let Err(e) = db.ping().await {
+                tracing::warn!("MongoDB is unreachable, queueing new tasks until it is back: {}", e);
+                let (pending, db) = (todo_list.clone(), db.clone());
+                tokio::spawn(async move {
+                    while db.ping().await.is_err() {
+                        tokio::time::sleep(std::time::Duration::from_secs(RECONNECT_SECS)).await;
+                    }
+                    pending.ensure_indexes().await;
+                });
+                return todo_list;
+            }
+        }
+        todo_list.ensure_indexes().await;
+        todo_list
+    }
+
+    /// Create the indexes the task list relies on and load enhancement corrections
+    async fn ensure_indexes(&self) {
+        // Search is the only thing that needs it, so don't fail without it
+        if let Err(e) = self.ensure_text_index().await {
+            tracing::warn!("Could not create the task text index, search will fail: {}", e);
+        }
+        if let Err(e) = self.ensure_idempotency_index().await {
+            tracing::warn!("Could not create the idempotency key index, retried tasks may be added twice: {}", e);
+        }
+        if let Err(e) = self.ensure_queue_index().await {
+            tracing::warn!("Could not create the queue index, claiming tasks will be slow: {}", e);
+        }
+        if let Err(e) = self.ensure_content_hash_index().await {
+            tracing::warn!("Could not create the content hash index, duplicate checks will be slow: {}", e);
+        }
+        if let Err(e) = self.drop_unique_description_index().await {
+            tracing::warn!("Could not drop the unique description index, repeated tasks will be refused: {}", e);
+        }
+        if let Err(e) = self.load_examples(self.project_classifier().examples()).await {
+            tracing::warn!("Could not load enhancement corrections, classifying without examples: {}", e);
+        }
+    }
+
+    pub fn with_scheduling(mut self, scheduling: SchedulingPolicy) -> Self {
+        self.scheduling = scheduling;
+        self
+    }
+
+    pub fn with_claims(mut self, claims: ClaimConfig) -> Self {
+        self.claims = claims;
+        self
+    }
+
+    /// Don't add a task identical to one added within `secs`; `None` turns this off
+    pub fn with_dedup_window(mut self, secs: Option<i64>) -> Self {
+        self.dedup_window = secs.filter(|&secs| secs > 0);
+        self
+    }
 
-        let client = Client::with_uri_str(&uri).await?;
-        let db = client.database(&db_name);
-        let collection = db.collection("todos");
+    pub fn dedup_window(&self) -> Option<i64> {
+        self.dedup_window
+    }
+
+    /// Attempts a task gets before it is dead-lettered
+    pub fn with_max_attempts(mut self, max_attempts: u32) -> Self {
+        self.max_attempts = max_attempts.max(1);
+        self
+    }
+
+    /// The same list, seeing and creating only `tenant`'s tasks. `None` sees every tenant.
+    pub fn for_tenant(&self, tenant: Option<&str>) -> Self {
+        Self { tenant: tenant.map(str::to_string), ..self.clone() }
+    }
 
-        Ok(Self { collection })
+    pub fn tenant(&self) -> Option<&str> {
+        self.tenant.as_deref()
     }
 
-    pub async fn add_task(&self, task: TodoTask) -> Result<(), MongoError> {
+    /// The same list, seeing only the tasks for `agent`. Tasks added through it are stored
+    /// for whichever agent they name, so agents can still hand work to each other.
+    pub fn for_agent(&self, agent: &str) -> Self {
+        Self { agent: Some(agent.to_string()), ..self.clone() }
+    }
+
+    pub fn agent(&self) -> Option<&str> {
+        self.agent.as_deref()
+    }
+
+    /// `filter` limited to this list's tenant and agent. Every query on the task collections
+    /// goes through this, which is what keeps tenants and agents' queues apart.
+    pub(crate) fn scope(&self, filter: Document) -> Document {
+        let mut filter = tenants::scope_filter(self.tenant(), filter);
+        if let Some(agent) = &self.agent {
+            filter.insert("target_agent", agent);
+        }
+        filter
+    }
+
+    pub fn max_attempts(&self) -> u32 {
+        self.max_attempts
+    }
+
+    /// The id this list claims tasks under
+    pub fn worker_id(&self) -> &str {
+        &self.claims.worker_id
+    }
+
+    pub(crate) fn collection(&self) -> &Collection<TodoTask> {
+        &self.collection
+    }
+
+    pub(crate) fn dead_letter_collection(&self) -> &Collection<DeadLetter> {
+        &self.dead_letters
+    }
+
+    pub(crate) fn archive_collection(&self) -> &Collection<ArchivedTask> {
+        &self.archive
+    }
+
+    pub(crate) fn results_collection(&self) -> &Collection<TaskResult> {
+        &self.results
+    }
+
+    pub(crate) fn corrections_collection(&self) -> &Collection<EnhancementCorrection> {
+        &self.corrections
+    }
+
+    /// The classifier enhancement files tasks with, including corrected examples
+    pub fn project_classifier(&self) -> &ProjectClassifier {
+        &self.classifier
+    }
+
+    pub async fn add_task(&self, mut task: TodoTask) -> Result<(), MongoError> {
+        if let Some(tenant) = &self.tenant {
+            task.tenant_id = Some(tenant.clone());
+        }
         if let Some(enhanced) = &task.enhanced_description {
             println!("Inserting enhanced description with length: {}", enhanced.len());
         }
-        self.collection.insert_one(task, None).await?;
+        let event = TaskEvent::for_task(TaskEventKind::Created, &task);
+        let inserted = match chaos::mongo() {
+            Ok(()) => self.collection.insert_one(&task, None).await.map(|_| ()),
+            Err(e) => Err(e),
+        };
+        if let Err(e) = inserted {
+            let Some(queue) = self.offline.as_ref().filter(|_| offline_queue::is_unreachable(&e)) else {
+                return Err(e);
+            };
+            if let Err(queue_error) = queue.push(&task).await {
+                tracing::error!("Could not queue task {} while MongoDB is unreachable: {}", task.id, queue_error);
+                return Err(e);
+            }
+            // Announced as created once the reconciler stores it
+            tracing::warn!("MongoDB unreachable, queued task {} in {}: {}", task.id, queue.path().display(), e);
+            return Ok(());
+        }
+        events::global().publish(DomainEvent::Task(event));
         Ok(())
     }
 
+    /// Stages matching the claimable tasks, optionally for one agent, in the order they
+    /// will be handed out
+    fn queue_pipeline(&self, target_agent: Option<&str>, now: i64) -> Result<Vec<Document>, MongoError> {
+        let mut filter = self.scope(claims::claimable_filter(now)?);
+        if let Some(agent) = target_agent {
+            filter.insert("target_agent", agent);
+        }
+        let mut pipeline = vec![doc! { "$match": filter }];
+        pipeline.extend(self.scheduling.sort_stages(now)?);
+        Ok(pipeline)
+    }
+
+    /// The first `limit` claimable tasks in the order they will be handed out, optionally
+    /// for one agent
+    async fn queued_tasks(&self, target_agent: Option<&str>, limit: usize) -> Result<Vec<TodoTask>, MongoError> {
+        let mut pipeline = self.queue_pipeline(target_agent, Utc::now().timestamp())?;
+        pipeline.push(doc! { "$limit": limit as i64 });
+        pipeline.push(doc! { "$project": { "embedding": 0, "schedule_rank": 0 } });
+        self.collection.aggregate(pipeline, None).await?.with_type::<TodoTask>().try_collect().await
+    }
+
+    /// Claim the task that is next by priority (after aging), then age. Tasks whose
+    /// lease has run out are claimable again.
     pub async fn get_next_task(&self) -> Result<Option<TodoTask>, MongoError> {
-        let filter = doc! {
-            "status": "Pending"
-        };
+        chaos::mongo()?;
+        loop {
+            let candidates = self.queued_tasks(None, CLAIM_CANDIDATES).await?;
+            let last_page = candidates.len() < CLAIM_CANDIDATES;
+            for candidate in candidates {
+                if let Some(task) = self.claim(&candidate).await? {
+                    return Ok(Some(task));
+                }
+            }
+            // Other workers took every candidate; the next ones are further down the queue
+            if last_page {
+                return Ok(None);
+            }
+        }
+    }
+
+    /// Claim `candidate` unless another worker got to it first
+    async fn claim(&self, candidate: &TodoTask) -> Result<Option<TodoTask>, MongoError> {
+        let now = Utc::now().timestamp();
+        let mut filter = self.scope(claims::claimable_filter(now)?);
+        filter.insert("id", &candidate.id);
+        let mut change = TaskChange::status(ChangeKind::Status, self.worker_id(), Some(&candidate.status), &TaskStatus::InProgress);
+        if candidate.status == TaskStatus::InProgress {
+            change = change.with_reason(match candidate.claim {
+                Some(_) => "The previous worker's lease ran out",
+                None => "Left in progress without a claim",
+            });
+        }
         let update = doc! {
             "$set": {
-                "status": "InProgress"
-            }
+                "status": mongodb::bson::to_bson(&TaskStatus::InProgress)?,
+                "claim": mongodb::bson::to_bson(&self.claims.claim_until(now))?,
+                "last_modified": now,
+            },
+            "$push": change.push()?,
         };
         let options = mongodb::options::FindOneAndUpdateOptions::builder()
-            .sort(doc! { "priority": -1, "created_at": 1 })
+            .return_document(mongodb::options::ReturnDocument::After)
             .build();
+        let task = self.collection.find_one_and_update(filter, update, options).await?;
+        if let Some(task) = &task {
+            events::global().publish(DomainEvent::Task(TaskEvent::for_task(TaskEventKind::Started, task)));
+        }
+        Ok(task)
+    }
 
-        Ok(self.collection
-            .find_one_and_update(filter, update, options)
-            .await?)
+    /// Extend the lease on a task this worker holds, recording a heartbeat. Returns false
+    /// if the claim was lost, e.g. because the lease ran out and another worker took the task.
+    pub async fn renew_lease(&self, task_id: &str) -> Result<bool, MongoError> {
+        chaos::mongo()?;
+        let now = Utc::now().timestamp();
+        let filter = self.scope(doc! {
+            "id": task_id,
+            "status": mongodb::bson::to_bson(&TaskStatus::InProgress)?,
+            "claim.worker_id": &self.claims.worker_id,
+        });
+        let update = doc! { "$set": {
+            "claim.lease_expires_at": self.claims.claim_until(now).lease_expires_at,
+            "claim.heartbeat_at": now,
+        } };
+        Ok(self.collection.update_one(filter, update, None).await?.matched_count > 0)
     }
 
-    pub async fn mark_task_completed(&self, task_id: &str) -> Result<(), MongoError> {
-        let filter = doc! {
-            "id": task_id
+    /// Keep renewing the lease on `task_id` until the returned guard is dropped, signalling
+    /// `cancel` if the claim is lost
+    pub fn keep_lease(&self, task_id: &str, cancel: CancellationToken) -> LeaseRenewal {
+        LeaseRenewal::start(self.clone(), task_id.to_string(), self.claims.lease, cancel)
+    }
+
+    /// Give a claimed task back to the queue, e.g. when shutting down before processing it
+    pub async fn release_task(&self, task_id: &str) -> Result<bool, MongoError> {
+        let filter = self.scope(doc! { "id": task_id, "claim.worker_id": &self.claims.worker_id });
+        let change = TaskChange::status(ChangeKind::Status, self.worker_id(), Some(&TaskStatus::InProgress), &TaskStatus::Pending)
+            .with_reason("Released unprocessed");
+        let update = doc! {
+            "$set": { "status": mongodb::bson::to_bson(&TaskStatus::Pending)?, "last_modified": Utc::now().timestamp() },
+            "$unset": { "claim": "" },
+            "$push": change.push()?,
         };
+        Ok(self.collection.update_one(filter, update, None).await?.modified_count > 0)
+    }
+
+    /// The next `limit` tasks that would be handed out, without claiming them
+    pub async fn peek(&self, target_agent: Option<&str>, limit: usize) -> Result<Vec<TodoTask>, MongoError> {
+        self.queued_tasks(target_agent, limit).await
+    }
+
+    /// Where a pending task stands among the pending tasks this list sees, or `None` if it
+    /// isn't pending
+    pub async fn position(&self, task_id: &str) -> Result<Option<QueuePosition>, MongoError> {
+        let now = Utc::now().timestamp();
+        let mut pipeline = self.queue_pipeline(None, now)?;
+        pipeline.push(doc! { "$project": { "_id": 0, "id": 1 } });
+        let queue: Vec<Document> = self.collection.aggregate(pipeline, None).await?.try_collect().await?;
+        let Some(index) = queue.iter().position(|entry| entry.get_str("id") == Ok(task_id)) else {
+            return Ok(None);
+        };
+        let Some(task) = self.get_task(task_id).await? else {
+            return Ok(None);
+        };
+        Ok(Some(QueuePosition {
+            task_id: task_id.to_string(),
+            position: index + 1,
+            queue_length: queue.len(),
+            effective_priority: self.scheduling.effective_priority(&task, now),
+        }))
+    }
+
+    /// `task_id`, unless another worker has claimed it. Finishing a task this worker doesn't
+    /// hold, e.g. from the CLI, is fine as long as nobody is working on it.
+    fn unclaimed_or_held(&self, task_id: &str) -> Document {
+        self.scope(doc! {
+            "id": task_id,
+            "$or": [{ "claim": { "$exists": false } }, { "claim.worker_id": &self.claims.worker_id }],
+        })
+    }
+
+    pub async fn mark_task_completed(&self, task_id: &str) -> Result<(), MongoError> {
+        chaos::mongo()?;
+        let filter = self.unclaimed_or_held(task_id);
         let update = doc! {
             "$set": {
                 "status": "completed",
-                "completed_at": DateTime::now(),
+                "completed_at": Utc::now().timestamp(),
                 "last_modified": Utc::now().timestamp()
-            }
+            },
+            "$unset": { "claim": "" },
+            "$push": TaskChange::status(ChangeKind::Status, self.worker_id(), None, &TaskStatus::Completed).push()?,
         };
-        self.collection.update_one(filter, update, None).await?;
+        if self.collection.update_one(filter, update, None).await?.matched_count == 0 {
+            tracing::warn!("Not completing task {}: it is missing or claimed by another worker", task_id);
+            return Ok(());
+        }
+        events::global().publish(DomainEvent::Task(TaskEvent::new(TaskEventKind::Completed, task_id)));
         Ok(())
     }
 
     pub async fn mark_task_failed(&self, task_id: &str) -> Result<(), MongoError> {
-        let filter = doc! {
-            "id": task_id
+        chaos::mongo()?;
+        let filter = self.unclaimed_or_held(task_id);
+        let update = doc! {
+            "$set": {
+                "status": "failed",
+                "last_modified": Utc::now().timestamp()
+            },
+            "$unset": { "claim": "" },
+            "$push": TaskChange::status(ChangeKind::Status, self.worker_id(), None, &TaskStatus::Failed).push()?,
         };
+        if self.collection.update_one(filter, update, None).await?.matched_count == 0 {
+            tracing::warn!("Not failing task {}: it is missing or claimed by another worker", task_id);
+            return Ok(());
+        }
+        events::global().publish(DomainEvent::Task(TaskEvent::new(TaskEventKind::Failed, task_id)));
+        Ok(())
+    }
+
+    /// Mark a task as failed and record why, e.g. a tool timeout
+    pub async fn mark_task_failed_with_reason(&self, task_id: &str, reason: &str) -> Result<(), MongoError> {
+        chaos::mongo()?;
+        let filter = self.unclaimed_or_held(task_id);
         let update = doc! {
             "$set": {
                 "status": "failed",
+                "failure_reason": reason,
                 "last_modified": Utc::now().timestamp()
-            }
+            },
+            "$unset": { "claim": "" },
+            "$push": TaskChange::status(ChangeKind::Status, self.worker_id(), None, &TaskStatus::Failed).with_reason(reason).push()?,
         };
-        self.collection.update_one(filter, update, None).await?;
+        if self.collection.update_one(filter, update, None).await?.matched_count == 0 {
+            tracing::warn!("Not failing task {}: it is missing or claimed by another worker", task_id);
+            return Ok(());
+        }
+        events::global().publish(DomainEvent::Task(TaskEvent::new(TaskEventKind::Failed, task_id).with_failure_reason(reason)));
         Ok(())
     }
 
     pub async fn get_all_tasks(&self) -> Result<Vec<TodoTask>, MongoError> {
-        let mut cursor = self.collection.find(None, None).await?;
+        let mut cursor = self.collection.find(self.scope(doc! {}), None).await?;
         let mut tasks = Vec::new();
         while let Some(task) = cursor.try_next().await? {
             tasks.push(task);
@@ -145,18 +575,47 @@ impl TodoList {
     }
 
     pub async fn get_task(&self, task_id: &str) -> Result<Option<TodoTask>, MongoError> {
-        let filter = doc! {
-            "id": task_id
-        };
+        let filter = self.scope(doc! { "id": task_id });
         Ok(self.collection.find_one(filter, None).await?)
     }
 
+    /// Add a line to the task's notes, e.g. the outcome of work done on its behalf
+    pub async fn append_note(&self, task_id: &str, note: &str) -> Result<bool, MongoError> {
+        let update = vec![doc! {
+            "$set": {
+                "notes": {
+                    "$cond": [
+                        { "$gt": [{ "$strLenCP": { "$ifNull": ["$notes", ""] } }, 0] },
+                        { "$concat": ["$notes", "\n", note] },
+                        note
+                    ]
+                },
+                "last_modified": Utc::now().timestamp()
+            }
+        }];
+        let result = self.collection.update_one(self.scope(doc! { "id": task_id }), update, None).await?;
+        Ok(result.matched_count > 0)
+    }
+
+    /// Find stored tasks whose embeddings are most similar to the given one
+    pub async fn find_similar(&self, embedding: &[f32], min_similarity: f32, limit: usize) -> Result<Vec<(TodoTask, f32)>, MongoError> {
+        let filter = self.scope(doc! { "embedding": { "$exists": true } });
+        let mut cursor = self.collection.find(filter, None).await?;
+        let mut candidates = Vec::new();
+        while let Some(task) = cursor.try_next().await? {
+            if let Some(task_embedding) = task.embedding.clone() {
+                candidates.push((task, task_embedding));
+            }
+        }
+        Ok(crate::ai::rank_by_similarity(embedding, candidates, min_similarity, limit))
+    }
+
     pub async fn is_empty(&self) -> Result<bool, MongoError> {
-        Ok(self.collection.count_documents(None, None).await? == 0)
+        Ok(self.collection.count_documents(self.scope(doc! {}), None).await? == 0)
     }
 
     pub async fn len(&self) -> Result<u64, MongoError> {
-        Ok(self.collection.count_documents(None, None).await?)
+        Ok(self.collection.count_documents(self.scope(doc! {}), None).await?)
     }
 
     pub async fn create_task_with_enhancement(
@@ -166,8 +625,19 @@ impl TodoList {
         source_agent: Option<String>,
         target_agent: String,
         project: Option<String>,
+        tags: Vec<String>,
         ai_client: Option<&dyn AiProvider>,
     ) -> Result<TodoTask, MongoError> {
+        let new = NewTask { description, priority, source_agent, target_agent, project, tags, idempotency_key: None, allow_duplicate: false };
+        let task = self.prepare_task(new, ai_client).await;
+        self.add_task(task.clone()).await?;
+        Ok(task)
+    }
+
+    /// The task `new` describes, enhanced when there's an AI client, without storing it
+    pub async fn prepare_task(&self, new: NewTask, ai_client: Option<&dyn AiProvider>) -> TodoTask {
+        let NewTask { description, priority, source_agent, target_agent, project, tags, idempotency_key, allow_duplicate } = new;
+        let content_hash = (!allow_duplicate).then(|| dedup::content_hash(&description, project.as_deref(), &target_agent));
         let mut task = TodoTask {
             id: Uuid::new_v4().to_string(),
             description: description.clone(),
@@ -184,42 +654,68 @@ impl TodoList {
             notes: None,
             ticket: None,
             last_modified: Some(Utc::now().timestamp()),
+            failure_reason: None,
+            embedding: None,
+            claim: None,
+            tags: super::tags::normalize_tags(tags),
+            failures: Vec::new(),
+            confidence: None,
+            reviewed_at: None,
+            tenant_id: self.tenant.clone(),
+            idempotency_key,
+            content_hash,
+            history: Vec::new(),
         };
 
         // Only attempt AI enhancement if a client is provided
         if let Some(ai_client) = ai_client {
-            // Use the shared enhancement logic from our AI module
-            if let Ok((enhanced_desc, predicted_priority, predicted_project)) = 
-                crate::ai::enhance_todo_description(&description, ai_client).await {
-                
-                task.enhanced_description = Some(enhanced_desc);
-                
-                // Only override priority if none was explicitly set or if predicted is higher
-                if task.priority < predicted_priority {
-                    task.priority = predicted_priority;
-                }
-                
-                // Only use predicted project if none was provided
-                if task.project.is_none() {
-                    task.project = Some(predicted_project);
+            // Use the shared enhancement logic from our AI module, or keyword rules without a model
+            let enhancement = match crate::ai::enhance_task_with(&description, ai_client, self.project_classifier()).await {
+                Ok(enhancement) => enhancement,
+                Err(e) => {
+                    tracing::debug!("AI enhancement failed ({}), enhancing offline", e);
+                    crate::ai::offline::enhance(&description, ai_client, self.project_classifier()).await
                 }
+            };
+
+            // Offline enhancement leaves the description as it was
+            if enhancement.enhanced_description != description {
+                task.enhanced_description = Some(enhancement.enhanced_description);
             }
+
+            // Only override priority if none was explicitly set or if predicted is higher
+            if task.priority < enhancement.priority {
+                task.priority = enhancement.priority;
+            }
+
+            // Only use predicted project if none was provided
+            if task.project.is_none() {
+                task.project = Some(enhancement.project);
+            }
+            task.tags = super::tags::normalize_tags(task.tags.iter().chain(&enhancement.tags));
+            task.confidence = Some(enhancement.confidence);
         }
         
+        if let Some(ai_client) = ai_client {
+            match ai_client.embed(&description).await {
+                Ok(embedding) => task.embedding = Some(embedding),
+                Err(e) => tracing::debug!("Skipping task embedding: {}", e),
+            }
+        }
+
         // Ensure we have a default project if none was set
         if task.project.is_none() {
             task.project = Some(get_default_project().to_string());
         }
-
-        self.add_task(task.clone()).await?;
-        Ok(task)
+        task
     }
 }
 
 #[async_trait::async_trait]
 pub trait TodoProcessor: Send + Sync {
-    /// Process a single task from the todo list
-    async fn process_task(&self, task: TodoTask) -> super::Result<Message>;
+    /// Process a single task from the todo list. Long-running processors should stop
+    /// early, returning a `TaskCancelledError`, once `cancel` is signalled.
+    async fn process_task(&self, task: TodoTask, cancel: CancellationToken) -> super::Result<Message>;
 
     /// Get the interval at which this processor should check for new tasks
     fn get_check_interval(&self) -> std::time::Duration;
@@ -229,18 +725,45 @@ pub trait TodoProcessor: Send + Sync {
 
     /// Start the task processing loop
     async fn start_processing(&self) -> super::Result<()> {
+        self.process_until_shutdown(&Arc::new(ShutdownCoordinator::default())).await
+    }
+
+    /// Process tasks until `shutdown` is triggered. Each task runs under an in-flight guard,
+    /// so the coordinator lets it finish before flushing and exiting.
+    async fn process_until_shutdown(&self, shutdown: &Arc<ShutdownCoordinator>) -> super::Result<()> {
         loop {
+            let Some(guard) = shutdown.begin() else {
+                return Ok(());
+            };
             if let Some(task) = self.get_todo_list().get_next_task().await? {
-                match self.process_task(task.clone()).await {
-                    Ok(_) => {
-                        self.get_todo_list().mark_task_completed(&task.id).await?;
+                let run = RunningTask::start(&task.id);
+                let lease = self.get_todo_list().keep_lease(&task.id, run.token());
+                let result = self.process_task(task.clone(), run.token()).await;
+                drop(lease);
+                match result {
+                    // Whoever cancelled it has already updated the task
+                    _ if run.token().is_cancelled() => {
+                        tracing::info!("Task {} was cancelled", task.id);
+                    }
+                    Ok(response) => {
+                        self.get_todo_list().complete_with_response(&task.id, &task.target_agent, &response).await?;
                     }
-                    Err(_) => {
-                        self.get_todo_list().mark_task_failed(&task.id).await?;
+                    Err(e) => {
+                        let reason = if crate::tools::is_timeout(&e) {
+                            format!("Timed out: {}", e)
+                        } else {
+                            e.to_string()
+                        };
+                        self.get_todo_list().fail_attempt(&task.id, &reason).await?;
                     }
                 }
             }
-            tokio::time::sleep(self.get_check_interval()).await;
+            drop(guard);
+
+            tokio::select! {
+                _ = tokio::time::sleep(self.get_check_interval()) => {}
+                _ = shutdown.signalled() => return Ok(()),
+            }
         }
     }
 }
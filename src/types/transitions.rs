@@ -0,0 +1,177 @@
+use std::collections::HashMap;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use super::{Message, Tool, ToolCall};
+
+/// A transition that only fires when all of its guards pass, running its actions when it does
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Transition {
+    pub event: String,
+    pub target: String,
+    #[serde(default)]
+    pub guards: Vec<Guard>,
+    #[serde(default)]
+    pub actions: Vec<TransitionAction>,
+}
+
+/// Condition checked against the incoming message and the state manager's context
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Guard {
+    /// Regex matched against the message content
+    MessageMatches { pattern: String },
+    /// Message metadata field (`agent`, `state`, or a metadata context key) equals a value
+    MetadataEquals { key: String, value: String },
+    /// State manager context key equals a value
+    ContextEquals { key: String, value: String },
+    /// State manager context key is set
+    ContextPresent { key: String },
+}
+
+/// Side effect applied when a guarded transition fires
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum TransitionAction {
+    /// Store a value in the state manager's context
+    SetContext { key: String, value: String },
+    /// Ask the agent to call a tool
+    CallTool {
+        tool: String,
+        #[serde(default)]
+        parameters: HashMap<String, String>,
+    },
+}
+
+impl Guard {
+    pub fn evaluate(&self, message: Option<&Message>, context: &HashMap<String, String>) -> bool {
+        match self {
+            Guard::MessageMatches { pattern } => match (message, Regex::new(pattern)) {
+                (Some(message), Ok(regex)) => regex.is_match(&message.content),
+                (_, Err(e)) => {
+                    tracing::warn!("Invalid guard pattern '{}': {}", pattern, e);
+                    false
+                }
+                (None, _) => false,
+            },
+            Guard::MetadataEquals { key, value } => {
+                let metadata = message.and_then(|m| m.metadata.as_ref());
+                let actual = metadata.and_then(|m| match key.as_str() {
+                    "agent" => Some(m.agent.clone()),
+                    "state" => m.state.clone(),
+                    _ => m.context.as_ref().and_then(|c| c.get(key).cloned()),
+                });
+                actual.as_deref() == Some(value.as_str())
+            }
+            Guard::ContextEquals { key, value } => context.get(key) == Some(value),
+            Guard::ContextPresent { key } => context.contains_key(key),
+        }
+    }
+}
+
+/// Replace `{{message}}` and `{{<context key>}}` placeholders
+pub fn render_template(template: &str, message: Option<&Message>, context: &HashMap<String, String>) -> String {
+    let mut rendered = template.replace("{{message}}", message.map(|m| m.content.as_str()).unwrap_or(""));
+    for (key, value) in context {
+        rendered = rendered.replace(&format!("{{{{{}}}}}", key), value);
+    }
+    rendered
+}
+
+impl TransitionAction {
+    /// Apply the action to the context; tool calls are returned for the agent to execute
+    pub fn apply(&self, message: Option<&Message>, context: &mut HashMap<String, String>) -> Option<ToolCall> {
+        match self {
+            TransitionAction::SetContext { key, value } => {
+                let value = render_template(value, message, context);
+                context.insert(key.clone(), value);
+                None
+            }
+            TransitionAction::CallTool { tool, parameters } => Some(ToolCall {
+                tool: Tool {
+                    name: tool.clone(),
+                    description: String::new(),
+                    parameters: HashMap::new(),
+                },
+                parameters: parameters.iter()
+                    .map(|(k, v)| (k.clone(), render_template(v, message, context)))
+                    .collect(),
+                result: None,
+            }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{AgentStateManager, MessageMetadata, State, StateMachine};
+
+    fn state(name: &str, transitions: &[(&str, &str)], guarded: Option<Vec<Transition>>) -> State {
+        State {
+            name: name.to_string(),
+            data: None,
+            prompt: None,
+            transitions: Some(transitions.iter().map(|(e, t)| (e.to_string(), t.to_string())).collect()),
+            validation: None,
+            guarded_transitions: guarded,
+            parent: None,
+            initial_child: None,
+            on_enter: None,
+            on_exit: None,
+        }
+    }
+
+    fn machine() -> StateMachine {
+        let guarded = vec![Transition {
+            event: "reply".to_string(),
+            target: "confirmed".to_string(),
+            guards: vec![Guard::MessageMatches { pattern: "(?i)^yes".to_string() }],
+            actions: vec![
+                TransitionAction::SetContext { key: "answer".to_string(), value: "{{message}}".to_string() },
+                TransitionAction::CallTool {
+                    tool: "todo".to_string(),
+                    parameters: HashMap::from([("description".to_string(), "Confirmed: {{answer}}".to_string())]),
+                },
+            ],
+        }];
+        StateMachine {
+            states: HashMap::from([
+                ("asking".to_string(), state("asking", &[("reply", "asking")], Some(guarded))),
+                ("confirmed".to_string(), state("confirmed", &[], None)),
+            ]),
+            initial_state: "asking".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_guarded_transition_runs_actions() {
+        let mut manager = AgentStateManager::new(Some(machine()));
+
+        let outcome = manager.transition_on("reply", Some(&Message::new("maybe".to_string()))).unwrap();
+        assert_eq!(outcome.state.name, "asking");
+        assert!(outcome.tool_calls.is_empty());
+
+        let outcome = manager.transition_on("reply", Some(&Message::new("Yes please".to_string()))).unwrap();
+        assert_eq!(outcome.state.name, "confirmed");
+        assert_eq!(manager.context()["answer"], "Yes please");
+        assert_eq!(outcome.tool_calls[0].tool.name, "todo");
+        assert_eq!(outcome.tool_calls[0].parameters["description"], "Confirmed: Yes please");
+    }
+
+    #[test]
+    fn test_metadata_and_context_guards() {
+        let mut message = Message::new("hi".to_string());
+        let mut metadata = MessageMetadata::new("greeter".to_string());
+        metadata.context = Some(HashMap::from([("channel".to_string(), "mqtt".to_string())]));
+        message.metadata = Some(metadata);
+        let mut context = HashMap::new();
+
+        assert!(Guard::MetadataEquals { key: "agent".to_string(), value: "greeter".to_string() }.evaluate(Some(&message), &context));
+        assert!(Guard::MetadataEquals { key: "channel".to_string(), value: "mqtt".to_string() }.evaluate(Some(&message), &context));
+        assert!(!Guard::ContextPresent { key: "user".to_string() }.evaluate(Some(&message), &context));
+
+        context.insert("user".to_string(), "dan".to_string());
+        assert!(Guard::ContextEquals { key: "user".to_string(), value: "dan".to_string() }.evaluate(None, &context));
+        assert!(!Guard::MessageMatches { pattern: "[".to_string() }.evaluate(Some(&message), &context));
+    }
+}
@@ -0,0 +1,309 @@
+//! Rules turning JSON posted to `/hooks/{name}` into a task or an agent message, so CI
+//! systems and monitoring tools can feed the swarm. Rules come from the file at
+//! `HOOKS_CONFIG` (YAML or JSON):
+//!
+//! ```yaml
+//! hooks:
+//!   ci:
+//!     when:
+//!       - { path: "$.build.status", equals: failed }
+//!     task:
+//!       description: "Fix {{ $.build.name }} on {{ $.build.branch }}"
+//!       target_agent: git
+//!       priority: high
+//!       tags: "ci, {{ $.build.labels }}"
+//!   alerts:
+//!     message:
+//!       agent: greeter
+//!       content: "{{ $.alerts[0].annotations.summary }}"
+//! ```
+//!
+//! Paths are a JSONPath subset: `$`, `.field`, `['field']` and `[index]`. `{{ path }}`
+//! inserts the value at `path`; strings go in as-is, arrays of strings comma-separated,
+//! anything else as JSON, and missing values as nothing.
+
+use std::collections::HashMap;
+use std::path::Path;
+use anyhow::{Result, anyhow, bail};
+use serde::Deserialize;
+use serde_json::Value;
+use super::tags::parse_tags;
+use super::todo::TaskPriority;
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct HookConfig {
+    #[serde(default)]
+    pub hooks: HashMap<String, HookRule>,
+}
+
+impl HookConfig {
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let source = std::fs::read_to_string(path).map_err(|e| anyhow!("Failed to read {}: {}", path.display(), e))?;
+        // YAML is a superset of JSON
+        let config: Self = serde_yaml::from_str(&source).map_err(|e| anyhow!("{}: {}", path.display(), e))?;
+        for (name, rule) in &config.hooks {
+            rule.validate().map_err(|e| anyhow!("Hook {}: {}", name, e))?;
+        }
+        Ok(config)
+    }
+
+    /// The rules in `HOOKS_CONFIG`, or `None` if it isn't set
+    pub fn from_env() -> Result<Option<Self>> {
+        std::env::var("HOOKS_CONFIG").ok().map(Self::from_file).transpose()
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct HookRule {
+    /// Conditions that must all hold; other payloads are ignored
+    #[serde(default)]
+    pub when: Vec<Condition>,
+    #[serde(flatten)]
+    pub action: HookAction,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HookAction {
+    Task(TaskTemplate),
+    Message(MessageTemplate),
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Condition {
+    pub path: String,
+    /// The value must equal this; without it, the value only has to be present
+    #[serde(default)]
+    pub equals: Option<Value>,
+}
+
+impl Condition {
+    pub fn holds(&self, payload: &Value) -> bool {
+        match (select(payload, &self.path), &self.equals) {
+            (Some(value), Some(expected)) => value == expected || value.as_str().is_some_and(|s| Some(s) == expected.as_str()),
+            (Some(value), None) => !value.is_null(),
+            (None, _) => false,
+        }
+    }
+}
+
+/// Each field is a template
+#[derive(Debug, Clone, Deserialize)]
+pub struct TaskTemplate {
+    pub description: String,
+    pub target_agent: String,
+    /// low, medium, high or critical; medium when unset or empty
+    #[serde(default)]
+    pub priority: Option<String>,
+    #[serde(default)]
+    pub project: Option<String>,
+    /// Comma-separated
+    #[serde(default)]
+    pub tags: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct MessageTemplate {
+    pub agent: String,
+    pub content: String,
+}
+
+/// A rule applied to a payload
+#[derive(Debug, Clone, PartialEq)]
+pub enum HookOutput {
+    Task {
+        description: String,
+        target_agent: String,
+        priority: TaskPriority,
+        project: Option<String>,
+        tags: Vec<String>,
+    },
+    Message { agent: String, content: String },
+}
+
+impl HookRule {
+    /// Check every template's paths parse, so mistakes show up at startup
+    pub fn validate(&self) -> Result<()> {
+        for condition in &self.when {
+            parse_path(&condition.path)?;
+        }
+        let templates: Vec<&str> = match &self.action {
+            HookAction::Task(task) => [Some(&task.description), Some(&task.target_agent), task.priority.as_ref(), task.project.as_ref(), task.tags.as_ref()]
+                .into_iter().flatten().map(String::as_str).collect(),
+            HookAction::Message(message) => vec![&message.agent, &message.content],
+        };
+        templates.into_iter().try_for_each(|template| render(template, &Value::Null).map(|_| ()))
+    }
+
+    /// What `payload` turns into, or `None` if the rule's conditions don't hold
+    pub fn apply(&self, payload: &Value) -> Result<Option<HookOutput>> {
+        if !self.when.iter().all(|condition| condition.holds(payload)) {
+            return Ok(None);
+        }
+        let optional = |template: &Option<String>| -> Result<Option<String>> {
+            Ok(template.as_deref().map(|t| render(t, payload)).transpose()?.filter(|s| !s.trim().is_empty()))
+        };
+        let output = match &self.action {
+            HookAction::Task(task) => HookOutput::Task {
+                description: required(render(&task.description, payload)?, "description")?,
+                target_agent: required(render(&task.target_agent, payload)?, "target_agent")?,
+                priority: optional(&task.priority)?.as_deref().map(parse_priority).transpose()?.unwrap_or(TaskPriority::Medium),
+                project: optional(&task.project)?,
+                tags: optional(&task.tags)?.as_deref().map(parse_tags).unwrap_or_default(),
+            },
+            HookAction::Message(message) => HookOutput::Message {
+                agent: required(render(&message.agent, payload)?, "agent")?,
+                content: required(render(&message.content, payload)?, "content")?,
+            },
+        };
+        Ok(Some(output))
+    }
+}
+
+fn required(value: String, field: &str) -> Result<String> {
+    let value = value.trim();
+    if value.is_empty() {
+        bail!("Payload left {} empty", field);
+    }
+    Ok(value.to_string())
+}
+
+fn parse_priority(priority: &str) -> Result<TaskPriority> {
+    match priority.trim().to_lowercase().as_str() {
+        "low" => Ok(TaskPriority::Low),
+        "medium" => Ok(TaskPriority::Medium),
+        "high" => Ok(TaskPriority::High),
+        "critical" => Ok(TaskPriority::Critical),
+        other => Err(anyhow!("Unknown priority '{}'", other)),
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Segment {
+    Field(String),
+    Index(usize),
+}
+
+fn parse_path(path: &str) -> Result<Vec<Segment>> {
+    let mut rest = path.trim().strip_prefix('$').ok_or_else(|| anyhow!("Path must start with $: {}", path))?;
+    let mut segments = Vec::new();
+    while !rest.is_empty() {
+        if let Some(after) = rest.strip_prefix('.') {
+            let end = after.find(['.', '[']).unwrap_or(after.len());
+            if end == 0 {
+                bail!("Empty field in path {}", path);
+            }
+            segments.push(Segment::Field(after[..end].to_string()));
+            rest = &after[end..];
+        } else if let Some(after) = rest.strip_prefix('[') {
+            let end = after.find(']').ok_or_else(|| anyhow!("Unclosed [ in path {}", path))?;
+            let inner = after[..end].trim();
+            let quoted = inner.strip_prefix('\'').and_then(|s| s.strip_suffix('\''))
+                .or_else(|| inner.strip_prefix('"').and_then(|s| s.strip_suffix('"')));
+            segments.push(match quoted {
+                Some(field) => Segment::Field(field.to_string()),
+                None => Segment::Index(inner.parse().map_err(|_| anyhow!("Bad index [{}] in path {}", inner, path))?),
+            });
+            rest = &after[end + 1..];
+        } else {
+            bail!("Unexpected '{}' in path {}", rest, path);
+        }
+    }
+    Ok(segments)
+}
+
+/// The value at `path` in `payload`; `None` if the path is invalid or leads nowhere
+pub fn select<'a>(payload: &'a Value, path: &str) -> Option<&'a Value> {
+    parse_path(path).ok()?.iter().try_fold(payload, |value, segment| match segment {
+        Segment::Field(field) => value.get(field),
+        Segment::Index(index) => value.get(index),
+    })
+}
+
+fn to_text(value: Option<&Value>) -> String {
+    match value {
+        None | Some(Value::Null) => String::new(),
+        Some(Value::String(s)) => s.clone(),
+        Some(Value::Array(items)) if items.iter().all(Value::is_string) => {
+            items.iter().filter_map(Value::as_str).collect::<Vec<_>>().join(", ")
+        }
+        Some(other) => other.to_string(),
+    }
+}
+
+/// `template` with each `{{ path }}` replaced by the value at that path
+pub fn render(template: &str, payload: &Value) -> Result<String> {
+    let mut output = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(open) = rest.find("{{") {
+        output.push_str(&rest[..open]);
+        let after = &rest[open + 2..];
+        let close = after.find("}}").ok_or_else(|| anyhow!("Unclosed {{{{ in template {}", template))?;
+        let path = after[..close].trim();
+        parse_path(path)?;
+        output.push_str(&to_text(select(payload, path)));
+        rest = &after[close + 2..];
+    }
+    output.push_str(rest);
+    Ok(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_select_and_render() {
+        let payload = json!({ "build": { "name": "api", "labels": ["flaky", "Backend"], "steps": [{ "id": 3 }] }, "a b": true });
+        assert_eq!(select(&payload, "$.build.name"), Some(&json!("api")));
+        assert_eq!(select(&payload, "$.build.steps[0].id"), Some(&json!(3)));
+        assert_eq!(select(&payload, "$['a b']"), Some(&json!(true)));
+        assert_eq!(select(&payload, "$.build.missing"), None);
+        assert_eq!(
+            render("{{ $.build.name }}: {{$.build.labels}} #{{ $.build.steps[0].id }}{{ $.nope }}", &payload).unwrap(),
+            "api: flaky, Backend #3"
+        );
+        assert!(render("{{ build.name }}", &payload).is_err());
+        assert!(render("{{ $.build", &payload).is_err());
+    }
+
+    #[test]
+    fn test_rules_apply_to_matching_payloads() {
+        let config: HookConfig = serde_yaml::from_str(r#"
+hooks:
+  ci:
+    when:
+      - { path: "$.status", equals: failed }
+    task:
+      description: "Fix {{ $.job }}"
+      target_agent: git
+      priority: "{{ $.severity }}"
+      tags: "ci, {{ $.labels }}"
+  alerts:
+    message:
+      agent: greeter
+      content: "{{ $.summary }}"
+"#).unwrap();
+        let ci = &config.hooks["ci"];
+        ci.validate().unwrap();
+        let failed = json!({ "status": "failed", "job": "lint", "severity": "High", "labels": ["Flaky"] });
+        assert_eq!(ci.apply(&failed).unwrap(), Some(HookOutput::Task {
+            description: "Fix lint".to_string(),
+            target_agent: "git".to_string(),
+            priority: TaskPriority::High,
+            project: None,
+            tags: vec!["ci".to_string(), "flaky".to_string()],
+        }));
+        assert_eq!(ci.apply(&json!({ "status": "passed", "job": "lint" })).unwrap(), None);
+        assert!(ci.apply(&json!({ "status": "failed", "job": "lint", "severity": "urgent" })).is_err());
+
+        let alerts = &config.hooks["alerts"];
+        assert_eq!(alerts.apply(&json!({ "summary": "Disk full" })).unwrap(), Some(HookOutput::Message {
+            agent: "greeter".to_string(),
+            content: "Disk full".to_string(),
+        }));
+        assert!(alerts.apply(&json!({})).is_err());
+    }
+}
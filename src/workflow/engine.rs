@@ -0,0 +1,467 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+use anyhow::{Result, anyhow};
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use tokio::sync::RwLock;
+use utoipa::ToSchema;
+use uuid::Uuid;
+use crate::agents::AgentRegistry;
+use crate::tools::ToolRegistry;
+use crate::types::{Agent, Message, Tool, TodoList, tenants};
+use super::{Step, StepAction, WorkflowConfig, WorkflowDefinition, render};
+use super::store::{InMemoryWorkflowRunStore, MongoWorkflowRunStore, WorkflowRunStore};
+
+/// Steps a run may take before it is assumed to be looping
+const DEFAULT_MAX_STEPS: usize = 100;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum RunStatus {
+    Running,
+    Completed,
+    Failed,
+}
+
+/// One attempt at a step
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct StepRecord {
+    pub step: String,
+    /// 1 for the first try
+    pub attempt: u32,
+    pub success: bool,
+    /// The output, or the error if the attempt failed
+    pub output: String,
+    pub finished_at: i64,
+}
+
+/// A workflow's execution state, saved after every step
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct WorkflowRun {
+    pub id: String,
+    pub workflow: String,
+    pub input: String,
+    pub status: RunStatus,
+    /// The step to run next while running; the step it stopped at after failing
+    pub current_step: Option<String>,
+    /// The latest output of each step that succeeded
+    #[serde(default)]
+    pub outputs: HashMap<String, String>,
+    #[serde(default)]
+    pub history: Vec<StepRecord>,
+    #[serde(default)]
+    pub error: Option<String>,
+    /// The task the run works on, where the outcome of compensation is noted
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub task_id: Option<String>,
+    /// Compensating actions run after the run failed, newest step first
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub compensations: Vec<StepRecord>,
+    /// The tenant that started the run; its agent steps and task note act for it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tenant_id: Option<String>,
+    pub started_at: i64,
+    pub updated_at: i64,
+}
+
+impl WorkflowRun {
+    /// Whether `tenant` may see the run; unscoped callers see every run
+    pub fn belongs_to(&self, tenant: Option<&str>) -> bool {
+        tenant.map_or(true, |tenant| self.tenant_id.as_deref() == Some(tenant))
+    }
+
+    /// Values for step templates
+    fn variables(&self) -> HashMap<String, String> {
+        let mut vars: HashMap<String, String> = self.outputs.iter()
+            .map(|(step, output)| (format!("steps.{}", step), output.clone()))
+            .collect();
+        vars.insert("input".to_string(), self.input.clone());
+        for (name, success) in [("last", true), ("error", false)] {
+            if let Some(record) = self.history.iter().rev().find(|record| record.success == success) {
+                vars.insert(name.to_string(), record.output.clone());
+            }
+        }
+        vars
+    }
+
+    fn fail(&mut self, error: String) {
+        self.status = RunStatus::Failed;
+        self.error = Some(error);
+    }
+
+    /// Steps that succeeded, each once, most recently succeeded first
+    fn completed_steps(&self) -> Vec<&str> {
+        let mut steps: Vec<&str> = Vec::new();
+        for record in self.history.iter().rev().filter(|record| record.success) {
+            if !steps.contains(&record.step.as_str()) {
+                steps.push(&record.step);
+            }
+        }
+        steps
+    }
+
+    /// What happened, for the notes of the task the run was for
+    fn compensation_note(&self) -> String {
+        let undone: Vec<String> = self.compensations.iter()
+            .map(|record| match record.success {
+                true => format!("{} undone", record.step),
+                false => format!("{} not undone ({})", record.step, record.output.trim()),
+            })
+            .collect();
+        format!(
+            "Workflow {} run {} failed: {}. Compensation: {}.",
+            self.workflow, self.id, self.error.as_deref().unwrap_or("unknown error"),
+            if undone.is_empty() { "nothing to undo".to_string() } else { undone.join(", ") }
+        )
+    }
+}
+
+pub struct WorkflowEngine {
+    config: WorkflowConfig,
+    store: Arc<dyn WorkflowRunStore>,
+    agents: Option<Arc<RwLock<AgentRegistry>>>,
+    tools: Option<Arc<ToolRegistry>>,
+    todos: Option<TodoList>,
+    max_steps: usize,
+}
+
+impl WorkflowEngine {
+    pub fn new(config: WorkflowConfig, store: Arc<dyn WorkflowRunStore>) -> Self {
+        Self { config, store, agents: None, tools: None, todos: None, max_steps: DEFAULT_MAX_STEPS }
+    }
+
+    /// The workflows in `WORKFLOWS_CONFIG`, with runs kept in MongoDB when it is configured,
+    /// or `None` if it isn't set
+    pub async fn from_env() -> Result<Option<Self>> {
+        let Some(config) = WorkflowConfig::from_env()? else {
+            return Ok(None);
+        };
+        if crate::secrets::get(crate::secrets::MONGO_URI).is_none() {
+            return Ok(Some(Self::new(config, Arc::new(InMemoryWorkflowRunStore::new()))));
+        }
+        let engine = Self::new(config, Arc::new(MongoWorkflowRunStore::new().await?))
+            .with_todos(TodoList::shared().await?);
+        Ok(Some(engine))
+    }
+
+    pub fn with_agents(mut self, agents: Arc<RwLock<AgentRegistry>>) -> Self {
+        self.agents = Some(agents);
+        self
+    }
+
+    pub fn with_tools(mut self, tools: Arc<ToolRegistry>) -> Self {
+        self.tools = Some(tools);
+        self
+    }
+
+    /// Where compensation outcomes are noted on runs' tasks
+    pub fn with_todos(mut self, todos: TodoList) -> Self {
+        self.todos = Some(todos);
+        self
+    }
+
+    pub fn with_max_steps(mut self, max_steps: usize) -> Self {
+        self.max_steps = max_steps;
+        self
+    }
+
+    pub fn config(&self) -> &WorkflowConfig {
+        &self.config
+    }
+
+    pub fn store(&self) -> &Arc<dyn WorkflowRunStore> {
+        &self.store
+    }
+
+    fn workflow(&self, name: &str) -> Result<&WorkflowDefinition> {
+        self.config.get(name).ok_or_else(|| anyhow!("No workflow {}", name))
+    }
+
+    /// Record a new run of `name`, ready for `run`
+    pub async fn start(&self, name: &str, input: &str) -> Result<WorkflowRun> {
+        self.start_for_task(name, input, None).await
+    }
+
+    /// Record a new run of `name` working on the task `task_id`
+    pub async fn start_for_task(&self, name: &str, input: &str, task_id: Option<String>) -> Result<WorkflowRun> {
+        self.start_for_tenant(name, input, task_id, None).await
+    }
+
+    /// Record a new run of `name` for `tenant`, working on the tenant's task `task_id`
+    pub async fn start_for_tenant(&self, name: &str, input: &str, task_id: Option<String>, tenant: Option<&str>) -> Result<WorkflowRun> {
+        let workflow = self.workflow(name)?;
+        let now = Utc::now().timestamp();
+        let run = WorkflowRun {
+            id: Uuid::new_v4().to_string(),
+            workflow: name.to_string(),
+            input: input.to_string(),
+            status: RunStatus::Running,
+            current_step: workflow.first_step().map(str::to_string),
+            outputs: HashMap::new(),
+            history: Vec::new(),
+            error: None,
+            task_id,
+            compensations: Vec::new(),
+            tenant_id: tenant.map(str::to_string),
+            started_at: now,
+            updated_at: now,
+        };
+        self.store.save(&run).await?;
+        Ok(run)
+    }
+
+    /// Continue a stored run from its current step; a failed run retries the step it failed at
+    pub async fn resume(&self, id: &str) -> Result<WorkflowRun> {
+        let mut run = self.store.load(id, None).await?.ok_or_else(|| anyhow!("No workflow run {}", id))?;
+        if !run.compensations.is_empty() {
+            return Err(anyhow!("Run {} was compensated after failing; start a new run", id));
+        }
+        if run.status == RunStatus::Failed && run.current_step.is_some() {
+            run.status = RunStatus::Running;
+            run.error = None;
+        }
+        self.run(run).await
+    }
+
+    /// Run steps until the run completes or fails, saving it after each
+    pub async fn run(&self, mut run: WorkflowRun) -> Result<WorkflowRun> {
+        let workflow = self.workflow(&run.workflow)?;
+        let mut steps_taken = 0;
+        while run.status == RunStatus::Running {
+            let Some(name) = run.current_step.clone() else {
+                run.status = RunStatus::Completed;
+                break;
+            };
+            let Some(step) = workflow.step(&name) else {
+                run.fail(format!("No step {}", name));
+                break;
+            };
+            steps_taken += 1;
+            if steps_taken > self.max_steps {
+                run.fail(format!("Stopped after {} steps; check the workflow for loops", self.max_steps));
+                break;
+            }
+            match self.attempt(step, &mut run).await {
+                Ok(output) => {
+                    run.current_step = workflow.next_step(step, &output);
+                    run.outputs.insert(step.name.clone(), output);
+                }
+                Err(error) => match &step.on_failure {
+                    Some(target) => run.current_step = (target != super::END).then(|| target.clone()),
+                    None => run.fail(format!("Step {} failed: {}", step.name, error)),
+                },
+            }
+            run.updated_at = Utc::now().timestamp();
+            self.store.save(&run).await?;
+        }
+        if run.status == RunStatus::Failed {
+            self.compensate(workflow, &mut run).await;
+        }
+        run.updated_at = Utc::now().timestamp();
+        self.store.save(&run).await?;
+        Ok(run)
+    }
+
+    /// Start `name` and run it to the end
+    pub async fn run_workflow(&self, name: &str, input: &str) -> Result<WorkflowRun> {
+        let run = self.start(name, input).await?;
+        self.run(run).await
+    }
+
+    /// Undo the steps that succeeded, newest first, and note the outcome on the run's task.
+    /// Every compensation is tried once; one failing doesn't stop the rest.
+    async fn compensate(&self, workflow: &WorkflowDefinition, run: &mut WorkflowRun) {
+        let steps: Vec<&Step> = run.completed_steps().into_iter().filter_map(|name| workflow.step(name)).collect();
+        for step in steps {
+            let Some(action) = &step.compensate else { continue };
+            let result = self.perform(action, &run.variables(), run.tenant_id.as_deref()).await;
+            if let Err(e) = &result {
+                tracing::warn!("Workflow {} could not undo step {}: {}", run.workflow, step.name, e);
+            }
+            run.compensations.push(StepRecord {
+                step: step.name.clone(),
+                attempt: 1,
+                success: result.is_ok(),
+                output: result.unwrap_or_else(|e| e.to_string()),
+                finished_at: Utc::now().timestamp(),
+            });
+        }
+        if let (Some(task_id), Some(todos)) = (&run.task_id, &self.todos) {
+            if let Err(e) = todos.for_tenant(run.tenant_id.as_deref()).append_note(task_id, &run.compensation_note()).await {
+                tracing::warn!("Failed to note the outcome of workflow run {} on task {}: {}", run.id, task_id, e);
+            }
+        }
+    }
+
+    /// Try `step` up to `retries + 1` times, recording each attempt
+    async fn attempt(&self, step: &Step, run: &mut WorkflowRun) -> Result<String> {
+        let mut attempt = 0;
+        loop {
+            attempt += 1;
+            let result = self.perform(&step.action, &run.variables(), run.tenant_id.as_deref()).await;
+            run.history.push(StepRecord {
+                step: step.name.clone(),
+                attempt,
+                success: result.is_ok(),
+                output: match &result {
+                    Ok(output) => output.clone(),
+                    Err(e) => e.to_string(),
+                },
+                finished_at: Utc::now().timestamp(),
+            });
+            match result {
+                Ok(output) => return Ok(output),
+                Err(e) if attempt > step.retries => return Err(e),
+                Err(e) => {
+                    tracing::info!("Workflow {} step {} attempt {} failed, retrying: {}", run.workflow, step.name, attempt, e);
+                    tokio::time::sleep(Duration::from_secs(step.retry_delay_secs)).await;
+                }
+            }
+        }
+    }
+
+    async fn perform(&self, action: &StepAction, vars: &HashMap<String, String>, tenant: Option<&str>) -> Result<String> {
+        match action {
+            StepAction::Agent { name, message } => {
+                let agents = self.agents.as_ref().ok_or_else(|| anyhow!("No agents to message"))?;
+                let name = render(name, vars)?;
+                // Clone the agent out so the registry isn't locked while it works
+                let agent = agents.read().await.get(&name).cloned().ok_or_else(|| anyhow!("No agent {}", name))?;
+                let mut message = Message::new(render(message, vars)?);
+                if let Some(tenant) = tenant {
+                    message = tenants::tag_message(message, &name, tenant);
+                }
+                Ok(agent.process_message(message).await?.content)
+            }
+            StepAction::Tool { name, params } => {
+                let tools = self.tools.as_ref().ok_or_else(|| anyhow!("No tools to run"))?;
+                let params = params.iter()
+                    .map(|(key, value)| Ok((key.clone(), render(value, vars)?)))
+                    .collect::<Result<HashMap<_, _>>>()?;
+                let tool = Tool { name: render(name, vars)?, description: String::new(), parameters: HashMap::new() };
+                tools.execute(&tool, params).await
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicU32, Ordering};
+    use async_trait::async_trait;
+    use crate::tools::ToolExecutor;
+
+    /// Fails until it has been called `failures` times, then echoes `text`
+    struct Flaky {
+        failures: u32,
+        calls: AtomicU32,
+    }
+
+    #[async_trait]
+    impl ToolExecutor for Flaky {
+        async fn execute(&self, params: HashMap<String, String>) -> Result<String> {
+            if self.calls.fetch_add(1, Ordering::SeqCst) < self.failures {
+                return Err(anyhow!("not yet"));
+            }
+            Ok(params.get("text").cloned().unwrap_or_default())
+        }
+    }
+
+    fn engine(yaml: &str, failures: u32) -> WorkflowEngine {
+        let config: WorkflowConfig = serde_yaml::from_str(yaml).unwrap();
+        config.validate().unwrap();
+        let mut tools = ToolRegistry::new();
+        tools.register("echo".to_string(), Flaky { failures: 0, calls: AtomicU32::new(0) });
+        tools.register("flaky".to_string(), Flaky { failures, calls: AtomicU32::new(0) });
+        WorkflowEngine::new(config, Arc::new(InMemoryWorkflowRunStore::new())).with_tools(Arc::new(tools))
+    }
+
+    const PIPELINE: &str = r#"
+workflows:
+  build:
+    steps:
+      - name: fetch
+        tool: { name: flaky, params: { text: "{{ input }} ok" } }
+        retries: 1
+        on_failure: recover
+      - name: check
+        tool: { name: echo, params: { text: "checked {{ steps.fetch }}" } }
+        next: [{ goto: end }]
+      - name: recover
+        tool: { name: echo, params: { text: "recovered from {{ error }}" } }
+"#;
+
+    #[tokio::test]
+    async fn test_run_retries_and_branches() {
+        let engine = engine(PIPELINE, 1);
+        let run = engine.run_workflow("build", "api").await.unwrap();
+        assert_eq!(run.status, RunStatus::Completed);
+        assert_eq!(run.outputs["check"], "checked api ok");
+        assert!(!run.outputs.contains_key("recover"));
+        let attempts: Vec<(&str, u32, bool)> = run.history.iter().map(|r| (r.step.as_str(), r.attempt, r.success)).collect();
+        assert_eq!(attempts, [("fetch", 1, false), ("fetch", 2, true), ("check", 1, true)]);
+
+        let stored = engine.store().load(&run.id, None).await.unwrap().unwrap();
+        assert_eq!(stored.status, RunStatus::Completed);
+        assert_eq!(stored.history.len(), 3);
+
+        let exhausted = self::engine(PIPELINE, 5).run_workflow("build", "api").await.unwrap();
+        assert_eq!(exhausted.status, RunStatus::Completed);
+        assert_eq!(exhausted.outputs["recover"], "recovered from Tool 'flaky' failed: not yet");
+    }
+
+    #[tokio::test]
+    async fn test_failed_runs_stop_and_resume() {
+        let engine = engine(r#"
+workflows:
+  once:
+    steps:
+      - name: fetch
+        tool: { name: flaky, params: { text: done } }
+      - name: loop
+        tool: { name: echo }
+        next: [{ goto: loop }]
+"#, 1).with_max_steps(4);
+        let failed = engine.run_workflow("once", "").await.unwrap();
+        assert_eq!(failed.status, RunStatus::Failed);
+        assert_eq!(failed.current_step.as_deref(), Some("fetch"));
+        assert_eq!(failed.error.as_deref(), Some("Step fetch failed: Tool 'flaky' failed: not yet"));
+
+        // The tool succeeds the second time; the loop then runs into the step limit
+        let resumed = engine.resume(&failed.id).await.unwrap();
+        assert_eq!(resumed.outputs["fetch"], "done");
+        assert_eq!(resumed.status, RunStatus::Failed);
+        assert!(resumed.error.unwrap().starts_with("Stopped after 4 steps"));
+        assert!(engine.start("missing", "").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_failed_runs_compensate_newest_first() {
+        let engine = engine(r#"
+workflows:
+  release:
+    steps:
+      - name: scaffold
+        tool: { name: echo, params: { text: "{{ input }}" } }
+        compensate: { tool: { name: echo, params: { text: "removed {{ steps.scaffold }}" } } }
+      - name: commit
+        tool: { name: echo, params: { text: committed } }
+        compensate: { tool: { name: flaky } }
+      - name: push
+        tool: { name: flaky }
+"#, 5);
+        let run = engine.start_for_task("release", "svc", Some("t1".to_string())).await.unwrap();
+        let run = engine.run(run).await.unwrap();
+        assert_eq!(run.status, RunStatus::Failed);
+        let undone: Vec<(&str, bool, &str)> = run.compensations.iter().map(|r| (r.step.as_str(), r.success, r.output.as_str())).collect();
+        assert_eq!(undone, [("commit", false, "Tool 'flaky' failed: not yet"), ("scaffold", true, "removed svc")]);
+        assert_eq!(
+            run.compensation_note(),
+            format!("Workflow release run {} failed: Step push failed: Tool 'flaky' failed: not yet. \
+                Compensation: commit not undone (Tool 'flaky' failed: not yet), scaffold undone.", run.id)
+        );
+        assert!(engine.resume(&run.id).await.is_err());
+    }
+}
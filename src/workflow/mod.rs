@@ -0,0 +1,299 @@
+//! Multi-step pipelines across agents and tools. Workflows come from the file at
+//! `WORKFLOWS_CONFIG` (YAML or JSON):
+//!
+//! ```yaml
+//! workflows:
+//!   new-service:
+//!     description: Scaffold a project, commit and push it
+//!     steps:
+//!       - name: scaffold
+//!         tool: { name: project, params: { type: rust, name: "{{ input }}", description: "{{ input }}" } }
+//!         retries: 2
+//!         next:
+//!           - { when: { contains: error }, goto: report }
+//!         compensate: { tool: { name: project, params: { action: remove, type: rust, name: "{{ input }}" } } }
+//!       - name: commit
+//!         tool: { name: git, params: { command: commit, message: "Scaffold {{ input }}" } }
+//!         compensate: { tool: { name: git, params: { command: revert } } }
+//!       - name: push
+//!         agent: { name: git, message: "Push the current branch" }
+//!         next: [{ goto: end }]
+//!       - name: report
+//!         agent: { name: greeter, message: "Scaffolding failed: {{ steps.scaffold }}" }
+//! ```
+//!
+//! Steps run in order unless a branch in `next` matches the step's output; `goto: end` stops.
+//! A step that fails every attempt goes to `on_failure`, or fails the run. A failed run then
+//! undoes the steps that succeeded, newest first, by running their `compensate` actions, and
+//! notes the outcome on the task the run was started for. Templates take
+//! `{{ input }}`, `{{ last }}` (the previous step's output), `{{ error }}` (the last failure)
+//! and `{{ steps.<name> }}`.
+
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+use anyhow::{Result, anyhow, bail};
+use regex::Regex;
+use serde::Deserialize;
+
+mod engine;
+mod store;
+
+pub use engine::{RunStatus, StepRecord, WorkflowEngine, WorkflowRun};
+pub use store::{InMemoryWorkflowRunStore, MongoWorkflowRunStore, WorkflowRunStore};
+
+/// The `goto` target that ends a run
+pub const END: &str = "end";
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct WorkflowConfig {
+    #[serde(default)]
+    pub workflows: HashMap<String, WorkflowDefinition>,
+}
+
+impl WorkflowConfig {
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let source = std::fs::read_to_string(path).map_err(|e| anyhow!("Failed to read {}: {}", path.display(), e))?;
+        // YAML is a superset of JSON
+        let config: Self = serde_yaml::from_str(&source).map_err(|e| anyhow!("{}: {}", path.display(), e))?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// The workflows in `WORKFLOWS_CONFIG`, or `None` if it isn't set
+    pub fn from_env() -> Result<Option<Self>> {
+        std::env::var("WORKFLOWS_CONFIG").ok().map(Self::from_file).transpose()
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        for (name, workflow) in &self.workflows {
+            workflow.validate().map_err(|e| anyhow!("Workflow {}: {}", name, e))?;
+        }
+        Ok(())
+    }
+
+    pub fn get(&self, name: &str) -> Option<&WorkflowDefinition> {
+        self.workflows.get(name)
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct WorkflowDefinition {
+    #[serde(default)]
+    pub description: Option<String>,
+    pub steps: Vec<Step>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Step {
+    pub name: String,
+    #[serde(flatten)]
+    pub action: StepAction,
+    /// Attempts after the first before the step fails
+    #[serde(default)]
+    pub retries: u32,
+    #[serde(default)]
+    pub retry_delay_secs: u64,
+    /// The first branch whose condition holds picks the next step; otherwise the next in order
+    #[serde(default)]
+    pub next: Vec<Branch>,
+    /// Where to go once every attempt has failed; the run fails when unset
+    #[serde(default)]
+    pub on_failure: Option<String>,
+    /// Undoes the step if the run fails later, e.g. deleting a directory it created
+    #[serde(default, with = "serde_yaml::with::singleton_map")]
+    pub compensate: Option<StepAction>,
+}
+
+/// Each string is a template
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StepAction {
+    /// Send `message` to the agent `name`; the output is its reply
+    Agent { name: String, message: String },
+    /// Run a registered tool; the output is its result
+    Tool {
+        name: String,
+        #[serde(default)]
+        params: HashMap<String, String>,
+    },
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Branch {
+    /// Always taken when unset
+    #[serde(default)]
+    pub when: Option<Condition>,
+    pub goto: String,
+}
+
+/// Tests on a step's output; every one given must hold
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct Condition {
+    /// Case-insensitive substring
+    #[serde(default)]
+    pub contains: Option<String>,
+    /// Regular expression
+    #[serde(default)]
+    pub matches: Option<String>,
+    /// The whole output, ignoring surrounding whitespace
+    #[serde(default)]
+    pub equals: Option<String>,
+}
+
+impl StepAction {
+    fn templates(&self) -> Vec<&String> {
+        match self {
+            StepAction::Agent { name, message } => vec![name, message],
+            StepAction::Tool { name, params } => std::iter::once(name).chain(params.values()).collect(),
+        }
+    }
+}
+
+impl Condition {
+    pub fn holds(&self, output: &str) -> bool {
+        let contains = self.contains.as_ref().map_or(true, |needle| output.to_lowercase().contains(&needle.to_lowercase()));
+        let matches = self.matches.as_ref().map_or(true, |pattern| Regex::new(pattern).is_ok_and(|re| re.is_match(output)));
+        let equals = self.equals.as_ref().map_or(true, |expected| output.trim() == expected.trim());
+        contains && matches && equals
+    }
+}
+
+impl WorkflowDefinition {
+    pub fn step(&self, name: &str) -> Option<&Step> {
+        self.steps.iter().find(|step| step.name == name)
+    }
+
+    pub fn first_step(&self) -> Option<&str> {
+        self.steps.first().map(|step| step.name.as_str())
+    }
+
+    /// The step after `step` given its output, or `None` at the end
+    pub fn next_step(&self, step: &Step, output: &str) -> Option<String> {
+        let target = match step.next.iter().find(|branch| branch.when.as_ref().map_or(true, |c| c.holds(output))) {
+            Some(branch) => branch.goto.clone(),
+            None => {
+                let index = self.steps.iter().position(|s| s.name == step.name)?;
+                self.steps.get(index + 1)?.name.clone()
+            }
+        };
+        (target != END).then_some(target)
+    }
+
+    /// Check step names, targets, patterns and templates, so mistakes show up at startup
+    pub fn validate(&self) -> Result<()> {
+        if self.steps.is_empty() {
+            bail!("No steps");
+        }
+        let mut names = HashSet::new();
+        for step in &self.steps {
+            if step.name == END || !names.insert(step.name.as_str()) {
+                bail!("Step name '{}' is reserved or used twice", step.name);
+            }
+        }
+        let known = |target: &str| target == END || names.contains(target);
+        for step in &self.steps {
+            let targets = step.next.iter().map(|branch| &branch.goto).chain(&step.on_failure);
+            if let Some(target) = targets.into_iter().find(|target| !known(target)) {
+                bail!("Step {} goes to unknown step '{}'", step.name, target);
+            }
+            if let Some(pattern) = step.next.iter().filter_map(|b| b.when.as_ref()?.matches.as_ref()).find(|p| Regex::new(p).is_err()) {
+                bail!("Step {} has an invalid pattern '{}'", step.name, pattern);
+            }
+            for template in std::iter::once(&step.action).chain(&step.compensate).flat_map(StepAction::templates) {
+                for variable in variables(template)? {
+                    let valid = match variable.strip_prefix("steps.") {
+                        Some(name) => names.contains(name),
+                        None => matches!(variable, "input" | "last" | "error"),
+                    };
+                    if !valid {
+                        bail!("Step {} uses unknown variable '{}'", step.name, variable);
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The variables `{{ name }}` in `template`
+fn variables(template: &str) -> Result<Vec<&str>> {
+    let mut found = Vec::new();
+    let mut rest = template;
+    while let Some(open) = rest.find("{{") {
+        let after = &rest[open + 2..];
+        let close = after.find("}}").ok_or_else(|| anyhow!("Unclosed {{{{ in template {}", template))?;
+        found.push(after[..close].trim());
+        rest = &after[close + 2..];
+    }
+    Ok(found)
+}
+
+/// `template` with each `{{ name }}` replaced from `vars`; unknown names become empty
+pub fn render(template: &str, vars: &HashMap<String, String>) -> Result<String> {
+    let mut output = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(open) = rest.find("{{") {
+        output.push_str(&rest[..open]);
+        let after = &rest[open + 2..];
+        let close = after.find("}}").ok_or_else(|| anyhow!("Unclosed {{{{ in template {}", template))?;
+        output.push_str(vars.get(after[..close].trim()).map(String::as_str).unwrap_or_default());
+        rest = &after[close + 2..];
+    }
+    output.push_str(rest);
+    Ok(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONFIG: &str = r#"
+workflows:
+  new-service:
+    steps:
+      - name: scaffold
+        agent: { name: project-init, message: "Create {{ input }}" }
+        retries: 2
+        next:
+          - { when: { contains: ERROR }, goto: report }
+          - { when: { matches: "^skip" }, goto: end }
+      - name: commit
+        tool: { name: git, params: { message: "Add {{ steps.scaffold }}" } }
+        on_failure: report
+      - name: report
+        agent: { name: greeter, message: "{{ error }}" }
+"#;
+
+    #[test]
+    fn test_branches_pick_the_next_step() {
+        let config: WorkflowConfig = serde_yaml::from_str(CONFIG).unwrap();
+        config.validate().unwrap();
+        let workflow = config.get("new-service").unwrap();
+        let scaffold = workflow.step("scaffold").unwrap();
+        assert!(matches!(&scaffold.action, StepAction::Agent { name, .. } if name == "project-init"));
+        assert_eq!(scaffold.retries, 2);
+        assert_eq!(workflow.next_step(scaffold, "Error: no template").as_deref(), Some("report"));
+        assert_eq!(workflow.next_step(scaffold, "skipped"), None);
+        assert_eq!(workflow.next_step(scaffold, "created ./svc").as_deref(), Some("commit"));
+        assert_eq!(workflow.next_step(workflow.step("report").unwrap(), "ok"), None);
+
+        let vars = HashMap::from([("steps.scaffold".to_string(), "svc".to_string())]);
+        assert_eq!(render("Add {{ steps.scaffold }}{{ last }}", &vars).unwrap(), "Add svc");
+    }
+
+    #[test]
+    fn test_validate_rejects_broken_workflows() {
+        let check = |yaml: &str| serde_yaml::from_str::<WorkflowConfig>(yaml).unwrap().validate().err().map(|e| e.to_string());
+        assert_eq!(
+            check("workflows: { w: { steps: [{ name: a, tool: { name: t }, next: [{ goto: b }] }] } }").as_deref(),
+            Some("Workflow w: Step a goes to unknown step 'b'")
+        );
+        assert_eq!(
+            check("workflows: { w: { steps: [{ name: a, tool: { name: t, params: { x: '{{ steps.b }}' } } }] } }").as_deref(),
+            Some("Workflow w: Step a uses unknown variable 'steps.b'")
+        );
+        assert!(check("workflows: { w: { steps: [{ name: a, tool: { name: t } }, { name: a, tool: { name: t } }] } }").is_some());
+        assert!(check("workflows: { w: { steps: [] } }").is_some());
+    }
+}
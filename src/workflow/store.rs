@@ -0,0 +1,100 @@
+use std::collections::HashMap;
+use anyhow::Result;
+use async_trait::async_trait;
+use futures_util::TryStreamExt;
+use mongodb::{Collection, IndexModel};
+use mongodb::bson::doc;
+use mongodb::options::{FindOptions, IndexOptions, ReplaceOptions};
+use tokio::sync::RwLock;
+use super::engine::WorkflowRun;
+use crate::db::Database;
+use crate::error::SwarmError;
+use crate::types::tenants;
+
+/// Storage for workflow runs, saved after every step so they can be inspected and resumed
+#[async_trait]
+pub trait WorkflowRunStore: Send + Sync {
+    async fn save(&self, run: &WorkflowRun) -> Result<()>;
+    /// The run, if it belongs to `tenant`; any run without one
+    async fn load(&self, id: &str, tenant: Option<&str>) -> Result<Option<WorkflowRun>>;
+    /// Most recent first, optionally for one workflow, and only `tenant`'s runs when given
+    async fn list(&self, tenant: Option<&str>, workflow: Option<&str>, limit: usize) -> Result<Vec<WorkflowRun>>;
+}
+
+/// Runs stored in the `workflow_runs` collection
+pub struct MongoWorkflowRunStore {
+    collection: Collection<WorkflowRun>,
+}
+
+impl MongoWorkflowRunStore {
+    pub async fn new() -> Result<Self> {
+        Self::from_database(&Database::shared().await?).await
+    }
+
+    pub async fn from_database(db: &Database) -> Result<Self> {
+        let collection: Collection<WorkflowRun> = db.collection("workflow_runs");
+        let index = IndexModel::builder()
+            .keys(doc! { "id": 1 })
+            .options(Some(IndexOptions::builder().unique(true).build()))
+            .build();
+        collection.create_index(index, None).await?;
+        Ok(Self { collection })
+    }
+}
+
+#[async_trait]
+impl WorkflowRunStore for MongoWorkflowRunStore {
+    async fn save(&self, run: &WorkflowRun) -> Result<()> {
+        let options = ReplaceOptions::builder().upsert(true).build();
+        self.collection.replace_one(doc! { "id": &run.id }, run, options).await.map_err(SwarmError::from)?;
+        Ok(())
+    }
+
+    async fn load(&self, id: &str, tenant: Option<&str>) -> Result<Option<WorkflowRun>> {
+        let filter = tenants::scope_filter(tenant, doc! { "id": id });
+        Ok(self.collection.find_one(filter, None).await.map_err(SwarmError::from)?)
+    }
+
+    async fn list(&self, tenant: Option<&str>, workflow: Option<&str>, limit: usize) -> Result<Vec<WorkflowRun>> {
+        let mut filter = tenants::scope_filter(tenant, doc! {});
+        if let Some(workflow) = workflow {
+            filter.insert("workflow", workflow);
+        }
+        let options = FindOptions::builder().sort(doc! { "started_at": -1 }).limit(limit as i64).build();
+        Ok(self.collection.find(filter, options).await.map_err(SwarmError::from)?.try_collect().await.map_err(SwarmError::from)?)
+    }
+}
+
+/// Process-local store, for tests and deployments without MongoDB
+#[derive(Default)]
+pub struct InMemoryWorkflowRunStore {
+    runs: RwLock<HashMap<String, WorkflowRun>>,
+}
+
+impl InMemoryWorkflowRunStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[async_trait]
+impl WorkflowRunStore for InMemoryWorkflowRunStore {
+    async fn save(&self, run: &WorkflowRun) -> Result<()> {
+        self.runs.write().await.insert(run.id.clone(), run.clone());
+        Ok(())
+    }
+
+    async fn load(&self, id: &str, tenant: Option<&str>) -> Result<Option<WorkflowRun>> {
+        Ok(self.runs.read().await.get(id).filter(|run| run.belongs_to(tenant)).cloned())
+    }
+
+    async fn list(&self, tenant: Option<&str>, workflow: Option<&str>, limit: usize) -> Result<Vec<WorkflowRun>> {
+        let mut runs: Vec<WorkflowRun> = self.runs.read().await.values()
+            .filter(|run| run.belongs_to(tenant) && workflow.map_or(true, |w| run.workflow == w))
+            .cloned()
+            .collect();
+        runs.sort_by(|a, b| b.started_at.cmp(&a.started_at));
+        runs.truncate(limit);
+        Ok(runs)
+    }
+}
@@ -0,0 +1,147 @@
+//! `#[derive(Agent)]` writes the `Agent` methods most agents copy from one another:
+//!
+//! - `get_config` clones the `config` field (or the field marked `#[agent(config)]`)
+//! - `get_current_state` and `get_state_machine` read the `state_manager` field (or the
+//!   field marked `#[agent(state)]`) through `StateSource`; without one the agent has no state
+//! - `transfer_to` hands the message back unchanged and `call_tool` fails with a tool error
+//! - `process_message` calls the agent's `MessageHandler::handle_message`
+//!
+//! `#[agent(transfer_to = "method", call_tool = "method")]` on the struct delegates either
+//! to an async method of the agent instead.
+
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::{format_ident, quote};
+use syn::{Data, DeriveInput, Fields, Ident, LitStr, parse_macro_input, spanned::Spanned};
+
+#[proc_macro_derive(Agent, attributes(agent))]
+pub fn derive_agent(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand(input).unwrap_or_else(syn::Error::into_compile_error).into()
+}
+
+#[derive(Default)]
+struct Overrides {
+    transfer_to: Option<Ident>,
+    call_tool: Option<Ident>,
+}
+
+fn expand(input: DeriveInput) -> syn::Result<TokenStream2> {
+    let name = &input.ident;
+    let fields = match &input.data {
+        Data::Struct(data) => match &data.fields {
+            Fields::Named(fields) => &fields.named,
+            _ => return Err(syn::Error::new(input.span(), "derive(Agent) needs a struct with named fields")),
+        },
+        _ => return Err(syn::Error::new(input.span(), "derive(Agent) only supports structs")),
+    };
+
+    let mut overrides = Overrides::default();
+    for attr in input.attrs.iter().filter(|attr| attr.path().is_ident("agent")) {
+        attr.parse_nested_meta(|meta| {
+            let method = |meta: &syn::meta::ParseNestedMeta| -> syn::Result<Ident> {
+                let value: LitStr = meta.value()?.parse()?;
+                Ok(format_ident!("{}", value.value(), span = value.span()))
+            };
+            if meta.path.is_ident("transfer_to") {
+                overrides.transfer_to = Some(method(&meta)?);
+            } else if meta.path.is_ident("call_tool") {
+                overrides.call_tool = Some(method(&meta)?);
+            } else {
+                return Err(meta.error("expected `transfer_to` or `call_tool`"));
+            }
+            Ok(())
+        })?;
+    }
+
+    let mut config = None;
+    let mut state = None;
+    for field in fields {
+        let ident = field.ident.clone().expect("named field");
+        let mut marked = None;
+        for attr in field.attrs.iter().filter(|attr| attr.path().is_ident("agent")) {
+            attr.parse_nested_meta(|meta| {
+                if meta.path.is_ident("config") || meta.path.is_ident("state") {
+                    marked = meta.path.get_ident().map(ToString::to_string);
+                    Ok(())
+                } else {
+                    Err(meta.error("expected `config` or `state`"))
+                }
+            })?;
+        }
+        match marked.as_deref() {
+            Some("config") => config = Some(ident),
+            Some("state") => state = Some(ident),
+            _ if ident == "config" => { config.get_or_insert(ident); }
+            _ if ident == "state_manager" => { state.get_or_insert(ident); }
+            _ => {}
+        }
+    }
+    let config = config.ok_or_else(|| syn::Error::new(
+        input.ident.span(),
+        "derive(Agent) needs a `config: AgentConfig` field or one marked #[agent(config)]",
+    ))?;
+
+    let types = quote!(::swarmonomicon::types);
+    let result = quote!(::swarmonomicon::__private::anyhow::Result);
+
+    let transfer_to = match &overrides.transfer_to {
+        Some(method) => quote!(self.#method(target_agent, message).await),
+        None => quote! {
+            let _ = target_agent;
+            Ok(message)
+        },
+    };
+    let unsupported = LitStr::new(&format!("{} does not support tool calls", name), name.span());
+    let call_tool = match &overrides.call_tool {
+        Some(method) => quote!(self.#method(tool, params).await),
+        None => quote! {
+            let _ = params;
+            Err(::swarmonomicon::error::SwarmError::tool_failure(&tool.name, #unsupported).into())
+        },
+    };
+    let state_methods = match &state {
+        Some(field) => quote! {
+            async fn get_current_state(&self) -> #result<Option<#types::State>> {
+                Ok(#types::StateSource::current_state(&self.#field).await)
+            }
+
+            async fn get_state_machine(&self) -> #result<Option<#types::StateMachine>> {
+                Ok(#types::StateSource::state_machine(&self.#field).await)
+            }
+        },
+        None => quote! {
+            async fn get_current_state(&self) -> #result<Option<#types::State>> {
+                Ok(None)
+            }
+        },
+    };
+
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    Ok(quote! {
+        #[::swarmonomicon::__private::async_trait]
+        impl #impl_generics #types::Agent for #name #ty_generics #where_clause {
+            async fn process_message(&self, message: #types::Message) -> #result<#types::Message> {
+                <Self as #types::MessageHandler>::handle_message(self, message).await
+            }
+
+            async fn transfer_to(&self, target_agent: String, message: #types::Message) -> #result<#types::Message> {
+                #transfer_to
+            }
+
+            async fn call_tool(
+                &self,
+                tool: &#types::Tool,
+                params: ::std::collections::HashMap<String, String>,
+            ) -> #result<String> {
+                #call_tool
+            }
+
+            #state_methods
+
+            async fn get_config(&self) -> #result<#types::AgentConfig> {
+                Ok(::core::clone::Clone::clone(&self.#config))
+            }
+        }
+    })
+}